@@ -0,0 +1,51 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use crab_db::buffer_pool::eviction::policy::ReplacerPolicy;
+
+// Policies to compare, as a comma-separated list in CRAB_DB_REPLACERS (e.g. "lru-k:2,sieve");
+// every policy when it is not set.
+fn policies() -> Vec<ReplacerPolicy> {
+    match std::env::var("CRAB_DB_REPLACERS") {
+        Ok(names) => names.split(',').map(|name| name.trim().parse().unwrap()).collect(),
+        Err(_) => vec![
+            ReplacerPolicy::lru_k(2),
+            ReplacerPolicy::ConcurrentLruK { k: 2, sample_size: 8 },
+            ReplacerPolicy::Lru,
+            ReplacerPolicy::Clock,
+            ReplacerPolicy::Sieve,
+            ReplacerPolicy::Arc,
+            ReplacerPolicy::TwoQ,
+            ReplacerPolicy::TinyLfu,
+        ],
+    }
+}
+
+// Fills a replacer with `pool_size` evictable frames, then measures one eviction followed by
+// the access that brings a page back into the freed frame, the steady state of a full pool.
+fn bench_evict(c: &mut Criterion) {
+    let mut group = c.benchmark_group("evict");
+    for policy in policies() {
+        for pool_size in [1_000usize, 10_000, 100_000] {
+            let replacer = policy.build(pool_size);
+            for frame_id in 0..pool_size {
+                replacer.record_access(frame_id).unwrap();
+                if frame_id % 2 == 0 {
+                    replacer.record_access(frame_id).unwrap();
+                }
+                replacer.set_evictable(frame_id, true).unwrap();
+            }
+
+            group.bench_with_input(BenchmarkId::new(policy.to_string(), pool_size), &replacer, |b, replacer| {
+                b.iter(|| {
+                    let frame_id = replacer.evict().unwrap().frame_id().unwrap();
+                    replacer.record_access(frame_id).unwrap();
+                    replacer.set_evictable(frame_id, true).unwrap();
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_evict);
+criterion_main!(benches);
@@ -0,0 +1,26 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/crab_db.proto");
+        // Use the bundled protoc rather than requiring one to be installed.
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().expect("no bundled protoc for this platform"));
+        tonic_prost_build::compile_protos("proto/crab_db.proto").expect("cannot compile proto/crab_db.proto");
+    }
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        // Only the C API module is read, so the rest of the crate needs no cbindgen annotations.
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").expect("cargo sets CARGO_MANIFEST_DIR");
+        let config = cbindgen::Config { usize_is_size_t: true, ..Default::default() };
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src(format!("{crate_dir}/src/ffi.rs"))
+            .with_language(cbindgen::Language::C)
+            .with_include_guard("CRABDB_H")
+            .with_autogen_warning("/* Generated from src/ffi.rs by the build; do not edit. */")
+            .generate()
+            .expect("cannot generate the C header from src/ffi.rs")
+            .write_to_file(format!("{crate_dir}/include/crabdb.h"));
+    }
+}
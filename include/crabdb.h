@@ -0,0 +1,236 @@
+#ifndef CRABDB_H
+#define CRABDB_H
+
+/* Generated from src/ffi.rs by the build; do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The call succeeded.
+ */
+#define CRABDB_OK 0
+
+/**
+ * The statement was malformed or refers to something that does not exist.
+ */
+#define CRABDB_INVALID_INPUT 1
+
+/**
+ * The transaction lost a write conflict; running it again may succeed.
+ */
+#define CRABDB_CONFLICT 2
+
+/**
+ * The transaction was chosen as a deadlock victim; running it again may succeed.
+ */
+#define CRABDB_DEADLOCK 3
+
+/**
+ * The transaction was already aborted.
+ */
+#define CRABDB_TXN_ABORTED 4
+
+/**
+ * The buffer pool ran out of frames.
+ */
+#define CRABDB_RESOURCE_EXHAUSTED 5
+
+/**
+ * Reading or writing a file failed.
+ */
+#define CRABDB_IO 6
+
+/**
+ * The database files are damaged.
+ */
+#define CRABDB_CORRUPTION 7
+
+/**
+ * A bug in the engine.
+ */
+#define CRABDB_INTERNAL 8
+
+/**
+ * The transaction waited too long for a lock; running it again may succeed.
+ */
+#define CRABDB_LOCK_TIMEOUT 9
+
+/**
+ * A page, row or encryption key the database looked for is not there.
+ */
+#define CRABDB_NOT_FOUND 10
+
+/**
+ * A null pointer, or a string that is not UTF-8, was passed in.
+ */
+#define CRABDB_MISUSE 21
+
+/**
+ * `crabdb_rows_next` moved to the next row.
+ */
+#define CRABDB_ROW 100
+
+/**
+ * `crabdb_rows_next` went past the last row.
+ */
+#define CRABDB_DONE 101
+
+/**
+ * Types `crabdb_value_type` reports.
+ */
+#define CRABDB_TYPE_NULL 0
+
+#define CRABDB_TYPE_BOOLEAN 1
+
+#define CRABDB_TYPE_INT32 2
+
+#define CRABDB_TYPE_INT64 3
+
+#define CRABDB_TYPE_FLOAT64 4
+
+#define CRABDB_TYPE_VARCHAR 5
+
+/**
+ * Microseconds since the Unix epoch.
+ */
+#define CRABDB_TYPE_TIMESTAMP 6
+
+/**
+ * Read as text, e.g. "[1, 2.5]".
+ */
+#define CRABDB_TYPE_VECTOR 7
+
+/**
+ * Read as text, e.g. "POINT(1 2)" or "BOX(0 0, 3 4)".
+ */
+#define CRABDB_TYPE_GEOMETRY 8
+
+/**
+ * An open database.
+ */
+typedef struct CrabDbHandle CrabDbHandle;
+
+/**
+ * The rows a query returns, read one at a time with `crabdb_rows_next`. A query runs as
+ * its rows are read.
+ */
+typedef struct CrabDbRows CrabDbRows;
+
+/**
+ * The message of the last call on this thread that failed. Valid until the next failure on
+ * the thread.
+ */
+const char *crabdb_errmsg(void);
+
+/**
+ * Opens the database at `path`, creating it if it does not exist, and stores the handle in
+ * `*db_out`, or NULL on failure.
+ *
+ * # Safety
+ * `path` must be a NUL-terminated string and `db_out` a valid pointer.
+ */
+int crabdb_open(const char *path, struct CrabDbHandle **db_out);
+
+/**
+ * Runs the statements in `sql`, each in a transaction of its own, and stores in `*count_out`
+ * (if it is not NULL) how many rows the last one changed, or returned if it is a query.
+ *
+ * # Safety
+ * `db` must come from `crabdb_open` and `sql` be a NUL-terminated string.
+ */
+int crabdb_exec(struct CrabDbHandle *db, const char *sql, uint64_t *count_out);
+
+/**
+ * Runs the statements in `sql`, each in a transaction of its own, and stores the rows of
+ * the last one in `*rows_out`, or NULL on failure. Free them with `crabdb_rows_free`.
+ *
+ * # Safety
+ * `db` must come from `crabdb_open`, `sql` be a NUL-terminated string and `rows_out` a
+ * valid pointer.
+ */
+int crabdb_query(struct CrabDbHandle *db, const char *sql, struct CrabDbRows **rows_out);
+
+/**
+ * Closes the database, flushing everything it holds. `db` is freed even if this fails.
+ *
+ * # Safety
+ * `db` must come from `crabdb_open` and not be used again; NULL is ignored.
+ */
+int crabdb_close(struct CrabDbHandle *db);
+
+/**
+ * How many columns the rows have.
+ *
+ * # Safety
+ * `rows` must come from `crabdb_query`.
+ */
+size_t crabdb_rows_column_count(const struct CrabDbRows *rows);
+
+/**
+ * The name of column `column`, or NULL if there is no such column. Valid until the rows are
+ * freed.
+ *
+ * # Safety
+ * `rows` must come from `crabdb_query`.
+ */
+const char *crabdb_rows_column_name(const struct CrabDbRows *rows, size_t column);
+
+/**
+ * Moves to the next row: returns CRABDB_ROW if there is one, CRABDB_DONE once the rows are
+ * exhausted, or an error code if the query failed, after which there are no more rows.
+ *
+ * # Safety
+ * `rows` must come from `crabdb_query`.
+ */
+int crabdb_rows_next(struct CrabDbRows *rows);
+
+/**
+ * The type of column `column` of the current row, CRABDB_TYPE_NULL when the value is NULL
+ * or there is no such value.
+ *
+ * # Safety
+ * `rows` must come from `crabdb_query`.
+ */
+int crabdb_value_type(const struct CrabDbRows *rows, size_t column);
+
+/**
+ * Column `column` of the current row as an integer: booleans are 0 or 1, and anything that
+ * is not an integer, boolean or timestamp is 0.
+ *
+ * # Safety
+ * `rows` must come from `crabdb_query`.
+ */
+int64_t crabdb_value_int64(const struct CrabDbRows *rows, size_t column);
+
+/**
+ * Column `column` of the current row as a double; 0 unless it is a number.
+ *
+ * # Safety
+ * `rows` must come from `crabdb_query`.
+ */
+double crabdb_value_double(const struct CrabDbRows *rows, size_t column);
+
+/**
+ * Column `column` of the current row as NUL-terminated text, values other than VARCHAR
+ * written out as SQL shows them, or NULL when the value is NULL. Stores the length without
+ * the terminator in `*len_out` if it is not NULL; a VARCHAR may hold NULs of its own. Valid
+ * until the next call to `crabdb_rows_next`.
+ *
+ * # Safety
+ * `rows` must come from `crabdb_query`.
+ */
+const char *crabdb_value_text(struct CrabDbRows *rows, size_t column, size_t *len_out);
+
+/**
+ * Frees rows from `crabdb_query`; NULL is ignored.
+ *
+ * # Safety
+ * `rows` must come from `crabdb_query` and not be used again.
+ */
+void crabdb_rows_free(struct CrabDbRows *rows);
+
+#endif  /* CRABDB_H */
@@ -0,0 +1,342 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::db::crab_db::CrabDb;
+use crate::kv::kv_store::KvStore;
+use crate::metrics::latency_histogram::{LatencyHistogram, LatencyHistogramSnapshot};
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::workload::{KeyChooser, Operation, OperationChooser, Rng, Workload};
+
+// Where the records live: the table, or key-value store, named so.
+pub const BENCH_TABLE: &str = "usertable";
+// Records inserted per transaction while loading.
+const LOAD_BATCH: u64 = 1000;
+
+// Which API of the database a benchmark drives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BenchTarget {
+    // A key-value store, each operation a transaction of its own.
+    Kv,
+    // A table keyed by a BIGINT primary key, each operation a SQL statement.
+    Sql,
+}
+
+impl fmt::Display for BenchTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BenchTarget::Kv => "kv",
+            BenchTarget::Sql => "sql",
+        })
+    }
+}
+
+impl FromStr for BenchTarget {
+    type Err = CrabDBError;
+
+    fn from_str(s: &str) -> CrabDbResult<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "kv" => Ok(BenchTarget::Kv),
+            "sql" => Ok(BenchTarget::Sql),
+            _ => Err(CrabDBError::InvalidInput(format!("Unknown benchmark target {s}; use kv or sql"))),
+        }
+    }
+}
+
+// How one kind of operation fared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OperationReport {
+    pub operation: Operation,
+    // Latency of every operation run, failed ones included.
+    pub latency: LatencyHistogramSnapshot,
+    // Operations that returned an error, such as a write conflict with another thread.
+    pub failed: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchReport {
+    pub target: BenchTarget,
+    pub records_loaded: u64,
+    pub load_time: Duration,
+    pub run_time: Duration,
+    // The operations of the mix that ran.
+    pub operations: Vec<OperationReport>,
+    // Page fetches the buffer pool served while running, and those it had to read in.
+    pub pool_hits: u64,
+    pub pool_misses: u64,
+    pub evictions: u64,
+}
+
+impl BenchReport {
+    pub fn operation_count(&self) -> u64 {
+        self.operations.iter().map(|report| report.latency.count()).sum()
+    }
+
+    pub fn failed(&self) -> u64 {
+        self.operations.iter().map(|report| report.failed).sum()
+    }
+
+    // Operations per second while running.
+    pub fn throughput(&self) -> f64 {
+        self.operation_count() as f64 / self.run_time.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+
+    // Fraction of page fetches while running served from the buffer pool; 1 if there were none.
+    pub fn hit_ratio(&self) -> f64 {
+        let fetches = self.pool_hits + self.pool_misses;
+        if fetches == 0 { 1.0 } else { self.pool_hits as f64 / fetches as f64 }
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "[{}] loaded {} records in {:.2?}", self.target, self.records_loaded, self.load_time)?;
+        writeln!(
+            f,
+            "[{}] {} operations in {:.2?}: {:.0} ops/s, {} failed",
+            self.target, self.operation_count(), self.run_time, self.throughput(), self.failed()
+        )?;
+        writeln!(
+            f,
+            "[{}] buffer pool: {:.2}% hits, {} misses, {} evictions",
+            self.target, self.hit_ratio() * 100.0, self.pool_misses, self.evictions
+        )?;
+        for report in &self.operations {
+            let latency = &report.latency;
+            writeln!(
+                f,
+                "[{}] {:<17} {:>9} ops  p50 {:>9.1?}  p95 {:>9.1?}  p99 {:>9.1?}  p99.9 {:>9.1?}  max {:>9.1?}  {} failed",
+                self.target, report.operation.to_string(), latency.count(), latency.percentile(50.0),
+                latency.percentile(95.0), latency.percentile(99.0), latency.percentile(99.9), latency.max(), report.failed
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Loads the records of `workload` into `db`, then runs its operations and reports how they
+// went. The table or key-value store BENCH_TABLE is created for it, so `db` must not have a
+// table of that name, nor a key-value store holding other records.
+pub fn run_workload(db: &CrabDb, workload: &Workload, target: BenchTarget) -> CrabDbResult<BenchReport> {
+    workload.validate()?;
+    let driver = Driver::new(db, target)?;
+    let mut rng = Rng::new(workload.seed);
+    let started = Instant::now();
+    let mut loaded = 0;
+    while loaded < workload.record_count {
+        let batch = LOAD_BATCH.min(workload.record_count - loaded);
+        let records: Vec<_> = (loaded..loaded + batch).map(|id| (id, value(&mut rng, workload.value_size))).collect();
+        driver.load(&records)?;
+        loaded += batch;
+    }
+    let load_time = started.elapsed();
+
+    let operations = OperationChooser::new(workload);
+    let keys = KeyChooser::new(workload.distribution, workload.record_count);
+    let stats: Vec<_> = workload.shares().into_iter()
+        .filter(|&(_, share)| share > 0.0)
+        .map(|(operation, _)| (operation, LatencyHistogram::default(), AtomicU64::new(0)))
+        .collect();
+    // Ids of the records inserted so far, and the next to insert.
+    let next_id = AtomicU64::new(workload.record_count);
+    let before = db.metrics_snapshot().buffer_pool;
+    let started = Instant::now();
+    std::thread::scope(|scope| {
+        for thread in 0..workload.threads as u64 {
+            let count = workload.operation_count / workload.threads as u64
+                + (thread < workload.operation_count % workload.threads as u64) as u64;
+            let (driver, operations, keys, stats, next_id) = (&driver, &operations, &keys, &stats, &next_id);
+            let mut rng = Rng::new(workload.seed.wrapping_add(thread + 1));
+            scope.spawn(move || {
+                for _ in 0..count {
+                    let operation = operations.next(&mut rng);
+                    let id = match operation {
+                        Operation::Insert => next_id.fetch_add(1, Ordering::Relaxed),
+                        _ => keys.next(&mut rng, next_id.load(Ordering::Relaxed)),
+                    };
+                    let value = value(&mut rng, workload.value_size);
+                    let scan_length = 1 + rng.below(workload.max_scan_length as u64) as usize;
+                    let started = Instant::now();
+                    let result = match operation {
+                        Operation::Read => driver.read(id),
+                        Operation::Update => driver.update(id, &value),
+                        Operation::Insert => driver.insert(id, &value),
+                        Operation::Scan => driver.scan(id, scan_length),
+                        Operation::ReadModifyWrite => driver.read_modify_write(id, &value),
+                    };
+                    let (_, latency, failed) = stats.iter().find(|(op, _, _)| *op == operation).unwrap();
+                    latency.record(started.elapsed());
+                    if result.is_err() {
+                        failed.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            });
+        }
+    });
+    let run_time = started.elapsed();
+    let after = db.metrics_snapshot().buffer_pool;
+    Ok(BenchReport {
+        target,
+        records_loaded: workload.record_count,
+        load_time,
+        run_time,
+        operations: stats.into_iter()
+            .map(|(operation, latency, failed)| OperationReport { operation, latency: latency.snapshot(), failed: failed.into_inner() })
+            .collect(),
+        pool_hits: after.hits - before.hits,
+        pool_misses: after.misses - before.misses,
+        evictions: after.evictions - before.evictions,
+    })
+}
+
+// Random letters and digits, which need no quoting in SQL.
+fn value(rng: &mut Rng, size: usize) -> String {
+    const CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+    (0..size).map(|_| CHARS[rng.below(CHARS.len() as u64) as usize] as char).collect()
+}
+
+// Keys sort in the order of their ids, so scans read consecutive records.
+fn kv_key(id: u64) -> Vec<u8> {
+    format!("user{id:020}").into_bytes()
+}
+
+// Runs each operation against one API of the database.
+enum Driver<'a> {
+    Kv(&'a CrabDb, KvStore),
+    Sql(&'a CrabDb),
+}
+
+impl<'a> Driver<'a> {
+    fn new(db: &'a CrabDb, target: BenchTarget) -> CrabDbResult<Self> {
+        match target {
+            BenchTarget::Kv => Ok(Driver::Kv(db, db.kv_store(BENCH_TABLE)?)),
+            BenchTarget::Sql => {
+                db.execute(&format!("CREATE TABLE {BENCH_TABLE} (ycsb_key BIGINT PRIMARY KEY, field0 VARCHAR)"))?;
+                Ok(Driver::Sql(db))
+            },
+        }
+    }
+
+    fn load(&self, records: &[(u64, String)]) -> CrabDbResult<()> {
+        match self {
+            Driver::Kv(db, store) => {
+                let txn = db.begin_transaction();
+                for (id, value) in records {
+                    store.put(txn.txn(), &kv_key(*id), value.as_bytes())?;
+                }
+                txn.commit()
+            },
+            Driver::Sql(db) => {
+                let values: Vec<_> = records.iter().map(|(id, value)| format!("({id}, '{value}')")).collect();
+                db.execute(&format!("INSERT INTO {BENCH_TABLE} VALUES {}", values.join(", "))).map(|_| ())
+            },
+        }
+    }
+
+    fn read(&self, id: u64) -> CrabDbResult<()> {
+        match self {
+            Driver::Kv(db, store) => {
+                let txn = db.begin_transaction();
+                store.get(txn.txn(), &kv_key(id))?;
+                txn.commit()
+            },
+            Driver::Sql(db) => db.query(&format!("SELECT * FROM {BENCH_TABLE} WHERE ycsb_key = {id}"))?.fetch_all().map(|_| ()),
+        }
+    }
+
+    fn update(&self, id: u64, value: &str) -> CrabDbResult<()> {
+        match self {
+            Driver::Kv(db, store) => {
+                let txn = db.begin_transaction();
+                store.put(txn.txn(), &kv_key(id), value.as_bytes())?;
+                txn.commit()
+            },
+            Driver::Sql(db) => db.execute(&format!("UPDATE {BENCH_TABLE} SET field0 = '{value}' WHERE ycsb_key = {id}")).map(|_| ()),
+        }
+    }
+
+    fn insert(&self, id: u64, value: &str) -> CrabDbResult<()> {
+        match self {
+            Driver::Kv(..) => self.update(id, value),
+            Driver::Sql(db) => db.execute(&format!("INSERT INTO {BENCH_TABLE} VALUES ({id}, '{value}')")).map(|_| ()),
+        }
+    }
+
+    fn scan(&self, id: u64, length: usize) -> CrabDbResult<()> {
+        match self {
+            Driver::Kv(db, store) => {
+                let txn = db.begin_transaction();
+                for entry in store.scan(txn.txn(), kv_key(id)..)?.take(length) {
+                    entry?;
+                }
+                txn.commit()
+            },
+            Driver::Sql(db) => {
+                let sql = format!("SELECT * FROM {BENCH_TABLE} WHERE ycsb_key >= {id} ORDER BY ycsb_key LIMIT {length}");
+                db.query(&sql)?.fetch_all().map(|_| ())
+            },
+        }
+    }
+
+    fn read_modify_write(&self, id: u64, value: &str) -> CrabDbResult<()> {
+        match self {
+            Driver::Kv(db, store) => {
+                let txn = db.begin_transaction();
+                store.get(txn.txn(), &kv_key(id))?;
+                store.put(txn.txn(), &kv_key(id), value.as_bytes())?;
+                txn.commit()
+            },
+            Driver::Sql(db) => {
+                let txn = db.begin_transaction();
+                txn.query(&format!("SELECT * FROM {BENCH_TABLE} WHERE ycsb_key = {id}"))?.fetch_all()?;
+                txn.execute(&format!("UPDATE {BENCH_TABLE} SET field0 = '{value}' WHERE ycsb_key = {id}"))?;
+                txn.commit()
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bench::workload::{KeyDistribution, Operation, Workload};
+    use crate::db::crab_db::CrabDb;
+    use crate::db::options::CrabDbOptions;
+    use super::{run_workload, BenchTarget};
+
+    #[test]
+    pub fn test_run_workload_against_kv_and_sql() {
+        let options = CrabDbOptions::default().vacuum_interval(None).flush_interval(None).warmup(false).pool_size(64);
+        for target in [BenchTarget::Kv, BenchTarget::Sql] {
+            let db = CrabDb::open_in_memory(options.clone()).unwrap();
+            let workload = Workload::default()
+                .record_count(500)
+                .operation_count(400)
+                .mix(0.4, 0.2, 0.1, 0.1, 0.2)
+                .max_scan_length(10)
+                .threads(2);
+            let report = run_workload(&db, &workload, target).unwrap();
+            assert_eq!(400, report.operation_count());
+            assert_eq!(5, report.operations.len());
+            assert!(report.pool_hits > 0 && report.hit_ratio() <= 1.0);
+            assert!(report.to_string().contains("READ-MODIFY-WRITE"));
+
+            // Every insert added a record, and none went missing.
+            let inserted = report.operations.iter().find(|report| report.operation == Operation::Insert).unwrap();
+            let expected = 500 + inserted.latency.count() - inserted.failed;
+            match target {
+                BenchTarget::Kv => {
+                    let txn = db.begin_transaction();
+                    let store = db.kv_store("usertable").unwrap();
+                    assert_eq!(expected as usize, store.scan::<&[u8]>(txn.txn(), ..).unwrap().count());
+                },
+                BenchTarget::Sql => assert_eq!(expected, db.execute("SELECT * FROM usertable").unwrap()),
+            }
+        }
+
+        let db = CrabDb::open_in_memory(options).unwrap();
+        let workload = Workload::ycsb("c").unwrap().record_count(100).distribution(KeyDistribution::Zipfian { theta: 1.5 });
+        assert!(run_workload(&db, &workload, BenchTarget::Kv).is_err());
+    }
+}
@@ -0,0 +1,381 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::types::{CrabDBError, CrabDbResult};
+
+// What a benchmark asks of the database, in the manner of YCSB: a load phase inserting
+// `record_count` records, then `operation_count` operations drawn from the mix below, spread
+// over `threads`. Start from one of the YCSB core workloads and change what you need:
+//   Workload::ycsb("a")?.record_count(100_000).distribution(KeyDistribution::Uniform)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Workload {
+    pub record_count: u64,
+    pub operation_count: u64,
+    // Shares of the operations, which need not add up to one; they are weighed against
+    // each other.
+    pub read_proportion: f64,
+    pub update_proportion: f64,
+    pub insert_proportion: f64,
+    pub scan_proportion: f64,
+    pub read_modify_write_proportion: f64,
+    // Scans read from 1 to this many records, evenly spread.
+    pub max_scan_length: usize,
+    // Which records reads, updates and scans go to.
+    pub distribution: KeyDistribution,
+    // Bytes in each record's value.
+    pub value_size: usize,
+    pub threads: usize,
+    // Runs with the same seed draw the same operations on the same keys.
+    pub seed: u64,
+}
+
+impl Default for Workload {
+    fn default() -> Self {
+        Workload {
+            record_count: 10_000,
+            operation_count: 100_000,
+            read_proportion: 0.5,
+            update_proportion: 0.5,
+            insert_proportion: 0.0,
+            scan_proportion: 0.0,
+            read_modify_write_proportion: 0.0,
+            max_scan_length: 100,
+            distribution: KeyDistribution::Zipfian { theta: ZIPFIAN_THETA },
+            value_size: 100,
+            threads: 1,
+            seed: 0,
+        }
+    }
+}
+
+impl Workload {
+    // YCSB core workload "a" to "f", at the default sizes:
+    //   a: 50% reads, 50% updates        b: 95% reads, 5% updates
+    //   c: reads only                    d: 95% reads of the latest records, 5% inserts
+    //   e: 95% short scans, 5% inserts   f: 50% reads, 50% read-modify-writes
+    pub fn ycsb(name: &str) -> CrabDbResult<Self> {
+        let workload = Workload { read_proportion: 0.0, update_proportion: 0.0, ..Workload::default() };
+        Ok(match name.to_ascii_lowercase().as_str() {
+            "a" => Workload { read_proportion: 0.5, update_proportion: 0.5, ..workload },
+            "b" => Workload { read_proportion: 0.95, update_proportion: 0.05, ..workload },
+            "c" => Workload { read_proportion: 1.0, ..workload },
+            "d" => Workload {
+                read_proportion: 0.95,
+                insert_proportion: 0.05,
+                distribution: KeyDistribution::Latest { theta: ZIPFIAN_THETA },
+                ..workload
+            },
+            "e" => Workload { scan_proportion: 0.95, insert_proportion: 0.05, ..workload },
+            "f" => Workload { read_proportion: 0.5, read_modify_write_proportion: 0.5, ..workload },
+            _ => return Err(CrabDBError::InvalidInput(format!("Unknown YCSB workload {name}; use a to f"))),
+        })
+    }
+
+    pub fn record_count(mut self, record_count: u64) -> Self {
+        self.record_count = record_count;
+        self
+    }
+
+    pub fn operation_count(mut self, operation_count: u64) -> Self {
+        self.operation_count = operation_count;
+        self
+    }
+
+    // Sets the share of each operation: reads, updates, inserts, scans and
+    // read-modify-writes.
+    pub fn mix(mut self, read: f64, update: f64, insert: f64, scan: f64, read_modify_write: f64) -> Self {
+        self.read_proportion = read;
+        self.update_proportion = update;
+        self.insert_proportion = insert;
+        self.scan_proportion = scan;
+        self.read_modify_write_proportion = read_modify_write;
+        self
+    }
+
+    pub fn max_scan_length(mut self, max_scan_length: usize) -> Self {
+        self.max_scan_length = max_scan_length;
+        self
+    }
+
+    pub fn distribution(mut self, distribution: KeyDistribution) -> Self {
+        self.distribution = distribution;
+        self
+    }
+
+    pub fn value_size(mut self, value_size: usize) -> Self {
+        self.value_size = value_size;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn validate(&self) -> CrabDbResult<()> {
+        let invalid = |message: String| Err(CrabDBError::InvalidInput(message));
+        if self.record_count == 0 {
+            return invalid("A workload needs at least one record".to_string());
+        }
+        if self.threads == 0 {
+            return invalid("A workload needs at least one thread".to_string());
+        }
+        if self.max_scan_length == 0 {
+            return invalid("Scans need to read at least one record".to_string());
+        }
+        let shares = self.shares();
+        if shares.iter().any(|&(_, share)| !(share >= 0.0 && share.is_finite())) || shares.iter().all(|&(_, share)| share == 0.0) {
+            return invalid("Operation shares must not be negative, and at least one must be above zero".to_string());
+        }
+        self.distribution.validate()
+    }
+
+    pub(crate) fn shares(&self) -> [(Operation, f64); 5] {
+        [
+            (Operation::Read, self.read_proportion),
+            (Operation::Update, self.update_proportion),
+            (Operation::Insert, self.insert_proportion),
+            (Operation::Scan, self.scan_proportion),
+            (Operation::ReadModifyWrite, self.read_modify_write_proportion),
+        ]
+    }
+}
+
+// The skew YCSB gives zipfian workloads: a few records take most of the operations.
+pub const ZIPFIAN_THETA: f64 = 0.99;
+
+// How the records an operation goes to are picked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyDistribution {
+    // Every record as likely as any other.
+    Uniform,
+    // Records ranked by a zipfian distribution with skew `theta`, from 0 to 1 exclusive,
+    // the hot ones scattered over the key space rather than bunched at its start.
+    Zipfian { theta: f64 },
+    // Zipfian over how recently records were inserted, the newest the hottest.
+    Latest { theta: f64 },
+}
+
+impl KeyDistribution {
+    fn validate(&self) -> CrabDbResult<()> {
+        match *self {
+            KeyDistribution::Zipfian { theta } | KeyDistribution::Latest { theta } if !(theta > 0.0 && theta < 1.0) => {
+                Err(CrabDBError::InvalidInput(format!("Zipfian skew {theta} is not between 0 and 1")))
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for KeyDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyDistribution::Uniform => write!(f, "uniform"),
+            KeyDistribution::Zipfian { theta } => write!(f, "zipfian:{theta}"),
+            KeyDistribution::Latest { theta } => write!(f, "latest:{theta}"),
+        }
+    }
+}
+
+impl FromStr for KeyDistribution {
+    type Err = CrabDBError;
+
+    // "uniform", "zipfian" or "latest", the last two with an optional skew: "zipfian:0.8".
+    fn from_str(s: &str) -> CrabDbResult<Self> {
+        let invalid = || CrabDBError::InvalidInput(format!("Unknown key distribution {s}"));
+        let (name, theta) = match s.split_once(':') {
+            Some((name, theta)) => (name, theta.parse::<f64>().map_err(|_| invalid())?),
+            None => (s, ZIPFIAN_THETA),
+        };
+        match name.to_ascii_lowercase().as_str() {
+            "uniform" if !s.contains(':') => Ok(KeyDistribution::Uniform),
+            "zipfian" => Ok(KeyDistribution::Zipfian { theta }),
+            "latest" => Ok(KeyDistribution::Latest { theta }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Read,
+    Update,
+    Insert,
+    Scan,
+    ReadModifyWrite,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Operation::Read => "READ",
+            Operation::Update => "UPDATE",
+            Operation::Insert => "INSERT",
+            Operation::Scan => "SCAN",
+            Operation::ReadModifyWrite => "READ-MODIFY-WRITE",
+        })
+    }
+}
+
+// SplitMix64: small, fast and plenty random for picking operations and keys.
+#[derive(Debug, Clone)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, 1).
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Uniform in [0, bound).
+    pub(crate) fn below(&mut self, bound: u64) -> u64 {
+        ((self.next_u64() as u128 * bound as u128) >> 64) as u64
+    }
+}
+
+// Ranks from 0 to `items` - 1 drawn from a zipfian distribution, rank 0 the likeliest, by the
+// method of Gray et al., "Quickly Generating Billion-Record Synthetic Databases", as YCSB
+// does. Setting it up takes time linear in `items`; drawing a rank is constant.
+#[derive(Debug, Clone)]
+pub(crate) struct Zipfian {
+    items: u64,
+    theta: f64,
+    zeta_n: f64,
+    alpha: f64,
+    eta: f64,
+}
+
+impl Zipfian {
+    pub(crate) fn new(items: u64, theta: f64) -> Self {
+        let zeta = |n: u64| (1..=n).map(|i| 1.0 / (i as f64).powf(theta)).sum::<f64>();
+        let (zeta_2, zeta_n) = (zeta(2), zeta(items));
+        let eta = (1.0 - (2.0 / items as f64).powf(1.0 - theta)) / (1.0 - zeta_2 / zeta_n);
+        Zipfian { items, theta, zeta_n, alpha: 1.0 / (1.0 - theta), eta }
+    }
+
+    pub(crate) fn next(&self, rng: &mut Rng) -> u64 {
+        let u = rng.next_f64();
+        let uz = u * self.zeta_n;
+        if uz < 1.0 {
+            return 0;
+        }
+        if uz < 1.0 + 0.5f64.powf(self.theta) {
+            return 1.min(self.items - 1);
+        }
+        ((self.items as f64 * (self.eta * u - self.eta + 1.0).powf(self.alpha)) as u64).min(self.items - 1)
+    }
+}
+
+// Picks the record each operation goes to out of the first `records` inserted.
+#[derive(Debug, Clone)]
+pub(crate) enum KeyChooser {
+    Uniform,
+    // Ranks are drawn over the records loaded; those inserted while running share them.
+    Zipfian(Zipfian),
+    Latest(Zipfian),
+}
+
+impl KeyChooser {
+    pub(crate) fn new(distribution: KeyDistribution, record_count: u64) -> Self {
+        match distribution {
+            KeyDistribution::Uniform => KeyChooser::Uniform,
+            KeyDistribution::Zipfian { theta } => KeyChooser::Zipfian(Zipfian::new(record_count, theta)),
+            KeyDistribution::Latest { theta } => KeyChooser::Latest(Zipfian::new(record_count, theta)),
+        }
+    }
+
+    pub(crate) fn next(&self, rng: &mut Rng, records: u64) -> u64 {
+        match self {
+            KeyChooser::Uniform => rng.below(records),
+            // Hashing the rank keeps the hot records from all sharing a few pages.
+            KeyChooser::Zipfian(zipfian) => fnv_1a(zipfian.next(rng)) % records,
+            KeyChooser::Latest(zipfian) => records - 1 - zipfian.next(rng).min(records - 1),
+        }
+    }
+}
+
+fn fnv_1a(value: u64) -> u64 {
+    value.to_le_bytes().iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3))
+}
+
+// Draws operations in proportion to their shares.
+#[derive(Debug, Clone)]
+pub(crate) struct OperationChooser {
+    // Each operation with the running total of shares up to and including it.
+    cumulative: Vec<(Operation, f64)>,
+}
+
+impl OperationChooser {
+    pub(crate) fn new(workload: &Workload) -> Self {
+        let mut total = 0.0;
+        let cumulative = workload.shares().into_iter()
+            .filter(|&(_, share)| share > 0.0)
+            .map(|(operation, share)| {
+                total += share;
+                (operation, total)
+            })
+            .collect();
+        OperationChooser { cumulative }
+    }
+
+    pub(crate) fn next(&self, rng: &mut Rng) -> Operation {
+        let total = self.cumulative.last().unwrap().1;
+        let point = rng.next_f64() * total;
+        self.cumulative.iter().find(|&&(_, upto)| point < upto).unwrap_or(self.cumulative.last().unwrap()).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyChooser, KeyDistribution, Operation, OperationChooser, Rng, Workload, Zipfian};
+
+    #[test]
+    pub fn test_workload_draws_keys_and_operations() {
+        let mut rng = Rng::new(7);
+        // Rank 0 of a zipfian with YCSB's skew takes a few percent of the draws, and the
+        // top tenth of the ranks most of them.
+        let zipfian = Zipfian::new(1000, 0.99);
+        let ranks: Vec<_> = (0..100_000).map(|_| zipfian.next(&mut rng)).collect();
+        assert!(ranks.iter().all(|&rank| rank < 1000));
+        let first = ranks.iter().filter(|&&rank| rank == 0).count();
+        assert!((5_000..20_000).contains(&first), "{first}");
+        assert!(ranks.iter().filter(|&&rank| rank < 100).count() > 60_000);
+
+        // Latest favours the newest records, and uniform none.
+        let latest = KeyChooser::new(KeyDistribution::Latest { theta: 0.99 }, 1000);
+        assert!((0..1000).filter(|_| latest.next(&mut rng, 2000) >= 1900).count() > 600);
+        let uniform = KeyChooser::new(KeyDistribution::Uniform, 1000);
+        let mut counts = [0; 10];
+        for _ in 0..10_000 {
+            counts[uniform.next(&mut rng, 10) as usize] += 1;
+        }
+        assert!(counts.iter().all(|&count| (800..1200).contains(&count)), "{counts:?}");
+
+        let workload = Workload::ycsb("e").unwrap();
+        let chooser = OperationChooser::new(&workload);
+        let scans = (0..10_000).filter(|_| chooser.next(&mut rng) == Operation::Scan).count();
+        assert!((9_300..9_700).contains(&scans), "{scans}");
+        assert!(Workload::ycsb("g").is_err());
+        assert!(Workload::default().mix(0.0, 0.0, 0.0, 0.0, 0.0).validate().is_err());
+        assert_eq!(KeyDistribution::Zipfian { theta: 0.8 }, "zipfian:0.8".parse().unwrap());
+        assert!("zipfian:1".parse::<KeyDistribution>().unwrap().validate().is_err());
+        assert!("uniform:0.5".parse::<KeyDistribution>().is_err());
+    }
+}
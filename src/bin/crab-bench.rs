@@ -0,0 +1,116 @@
+// Runs YCSB-style workloads against crab-db and reports throughput, latency percentiles and
+// the buffer pool's hit ratio, once per eviction policy given, each on a fresh database:
+//   crab-bench --workload a --target kv --records 100000 --operations 1000000 --policies lru-k:2,sieve
+// wasm builds have no threads or clock to time with; those leave this an empty binary.
+#![cfg_attr(target_family = "wasm", allow(dead_code, unused_imports))]
+
+use crab_db::buffer_pool::eviction::policy::ReplacerPolicy;
+use crab_db::types::{CrabDBError, CrabDbResult};
+use crab_db::wal::log_manager::SyncMode;
+use crab_db::CrabDbOptions;
+
+const USAGE: &str = "\
+Usage: crab-bench [OPTIONS]
+  --workload NAME         YCSB core workload, a to f (default a)
+  --target kv|sql         the API to drive (default kv)
+  --records N             records loaded before running
+  --operations N          operations run
+  --threads N             threads running them
+  --distribution D        uniform, zipfian[:THETA] or latest[:THETA]
+  --value-size N          bytes per value
+  --scan-length N         most records a scan reads
+  --seed N                seed of the keys and operations drawn
+  --pool-size N           frames in the buffer pool
+  --policies P,P,...      eviction policies to compare, e.g. lru-k:2,clock,sieve
+  --path FILE             database file, removed before each run; in memory if unset
+  --sync full|off         whether commits sync the log, for --path
+";
+
+#[cfg(target_family = "wasm")]
+fn main() {}
+
+#[cfg(not(target_family = "wasm"))]
+fn main() {
+    if let Err(e) = run(std::env::args().skip(1).collect()) {
+        eprintln!("crab-bench: {e}\n\n{USAGE}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn run(args: Vec<String>) -> CrabDbResult<()> {
+    use crab_db::bench::runner::{run_workload, BenchTarget};
+    use crab_db::bench::workload::Workload;
+    use crab_db::CrabDb;
+
+    let invalid = |message: String| CrabDBError::InvalidInput(message);
+    let mut workload = Workload::ycsb("a")?;
+    let mut target = BenchTarget::Kv;
+    let mut options = CrabDbOptions::default().vacuum_interval(None).warmup(false);
+    let mut policies = vec![options.eviction_policy];
+    let mut path = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--help" || arg == "-h" {
+            print!("{USAGE}");
+            return Ok(());
+        }
+        let value = args.next().ok_or_else(|| invalid(format!("{arg} needs a value")))?;
+        let number = || value.parse::<u64>().map_err(|_| invalid(format!("{arg} takes a number, not {value}")));
+        match arg.as_str() {
+            // The other options apply to the preset, wherever they come.
+            "--workload" => workload = Workload { seed: workload.seed, ..Workload::ycsb(&value)? },
+            "--target" => target = value.parse()?,
+            "--records" => workload.record_count = number()?,
+            "--operations" => workload.operation_count = number()?,
+            "--threads" => workload.threads = number()? as usize,
+            "--distribution" => workload.distribution = value.parse()?,
+            "--value-size" => workload.value_size = number()? as usize,
+            "--scan-length" => workload.max_scan_length = number()? as usize,
+            "--seed" => workload.seed = number()?,
+            "--pool-size" => options.pool_size = number()? as usize,
+            "--policies" => policies = value.split(',').map(|name| name.trim().parse()).collect::<CrabDbResult<Vec<ReplacerPolicy>>>()?,
+            "--path" => path = Some(value),
+            "--sync" => options.sync_mode = match value.as_str() {
+                "full" => SyncMode::Full,
+                "off" => SyncMode::Off,
+                _ => return Err(invalid(format!("Unknown sync mode {value}; use full or off"))),
+            },
+            _ => return Err(invalid(format!("Unknown option {arg}"))),
+        }
+    }
+    workload.validate()?;
+
+    println!(
+        "crab-bench {}: {} records, {} operations on {} thread(s), {} keys, {} frames",
+        env!("CARGO_PKG_VERSION"), workload.record_count, workload.operation_count, workload.threads,
+        workload.distribution, options.pool_size
+    );
+    for policy in policies {
+        let options = options.clone().eviction_policy(policy);
+        let db = match &path {
+            Some(path) => {
+                remove_database(path);
+                CrabDb::open(path, options)?
+            },
+            None => CrabDb::open_in_memory(options)?,
+        };
+        let report = run_workload(&db, &workload, target)?;
+        db.close()?;
+        println!("\n{policy}");
+        print!("{report}");
+    }
+    if let Some(path) = &path {
+        remove_database(path);
+    }
+    Ok(())
+}
+
+// Removes the database at `path` and its log and warmup files, so each run starts afresh.
+#[cfg(not(target_family = "wasm"))]
+fn remove_database(path: &str) {
+    for file in [path.to_string(), format!("{path}.wal"), format!("{path}.warmup")] {
+        let file = std::path::Path::new(&file);
+        let _ = if file.is_dir() { std::fs::remove_dir_all(file) } else { std::fs::remove_file(file) };
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+use crate::buffer_pool::common::FrameId;
+
+// How many frames a large scan reads through, as many as Postgres gives one (256KB).
+pub const SCAN_RING_SIZE: usize = 32;
+
+// A small private ring of frames that a large sequential scan reads its pages into, like
+// Postgres's buffer access strategies. Once the ring is full each page the scan misses on
+// reuses the frame of the oldest page in it, so the scan recycles a few frames instead of
+// evicting the working set. A page someone else pinned meanwhile has left the ring for good.
+pub struct BufferAccessStrategy {
+    ring_size: usize,
+    ring: Mutex<VecDeque<FrameId>>,
+}
+
+impl BufferAccessStrategy {
+    pub fn new(ring_size: usize) -> Self {
+        let ring_size = ring_size.max(1);
+        BufferAccessStrategy { ring_size, ring: Mutex::new(VecDeque::with_capacity(ring_size)) }
+    }
+
+    // The strategy for scanning `num_pages` pages through `bpm`: a ring when the scan is
+    // bigger than a quarter of the pool, none when it is small enough to keep. The ring
+    // takes at most an eighth of the pool.
+    pub fn for_scan(bpm: &BufferPoolManager, num_pages: usize) -> Option<Self> {
+        let pool_size = bpm.pool_size();
+        (num_pages > pool_size / 4).then(|| Self::new(SCAN_RING_SIZE.min(pool_size / 8)))
+    }
+
+    pub fn ring_size(&self) -> usize {
+        self.ring_size
+    }
+
+    // The frame to read the next page into, once the ring is full.
+    pub(crate) fn next_victim(&self) -> Option<FrameId> {
+        let mut ring = self.ring.lock().unwrap();
+        if ring.len() < self.ring_size {
+            return None;
+        }
+        ring.pop_front()
+    }
+
+    // The replacer may hand out a frame already in the ring; it moves to the back.
+    pub(crate) fn push(&self, frame_id: FrameId) {
+        let mut ring = self.ring.lock().unwrap();
+        ring.retain(|&ring_frame_id| ring_frame_id != frame_id);
+        ring.push_back(frame_id);
+    }
+}
@@ -0,0 +1,79 @@
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+
+// Writes dirty pages back on a background thread, so eviction rarely has to wait for a write
+// of its own. Every `interval` it checks the pool, and once at least `dirty_ratio` of the
+// frames are dirty it writes back every unpinned one, oldest page LSN first.
+pub struct BackgroundWriter {
+    worker: Option<(Sender<()>, JoinHandle<()>)>,
+}
+
+impl BackgroundWriter {
+    pub fn new(bpm: Arc<BufferPoolManager>, interval: Duration, dirty_ratio: f64) -> Self {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let worker = std::thread::Builder::new()
+            .name("crab-db-bgwriter".into())
+            .spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    if bpm.dirty_ratio() >= dirty_ratio {
+                        // A failed write is retried on the next tick.
+                        let _ = bpm.flush_lagging_pages();
+                    }
+                }
+            })
+            .expect("failed to spawn background writer");
+        BackgroundWriter { worker: Some((stop, worker)) }
+    }
+}
+
+impl Drop for BackgroundWriter {
+    fn drop(&mut self) {
+        if let Some((stop, worker)) = self.worker.take() {
+            drop(stop);
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use super::BackgroundWriter;
+
+    #[test]
+    pub fn test_background_writer_flushes_once_dirty_ratio_is_reached() {
+        let bpm = Arc::new(BufferPoolManager::new(4, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(4, 2))));
+        let writer = BackgroundWriter::new(bpm.clone(), Duration::from_millis(5), 0.5);
+
+        // one dirty frame out of four stays below the trigger
+        bpm.new_page().unwrap();
+        assert!(bpm.unpin_page(0, true).is_ok());
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(0, bpm.metrics_snapshot().dirty_writes);
+
+        // a second one, still pinned, sets it off, but only the unpinned page is written back
+        bpm.new_page().unwrap();
+        bpm.fetch_page(1).unwrap();
+        assert!(bpm.unpin_page(1, true).is_ok());
+        let started = Instant::now();
+        while bpm.metrics_snapshot().dirty_writes == 0 && started.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        drop(writer);
+        assert_eq!(1, bpm.metrics_snapshot().dirty_writes);
+        assert_eq!(0.25, bpm.dirty_ratio());
+
+        assert!(bpm.unpin_page(1, false).is_ok());
+        assert_eq!(1, bpm.flush_lagging_pages().unwrap());
+        assert_eq!(0.0, bpm.dirty_ratio());
+    }
+}
@@ -0,0 +1,1807 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::buffer_pool::access_strategy::BufferAccessStrategy;
+use crate::buffer_pool::{common::FrameId, eviction::replacer::{AccessType, Replacer, ReplacerDebugState}, page::{LatchWait, Page}};
+use crate::buffer_pool::frame_table::FrameTable;
+use crate::buffer_pool::page_guard::{ReadPageGuard, WritePageGuard};
+use crate::buffer_pool::shared_buffer_pool::{EvictionRank, SharedBufferPool};
+use crate::metrics::buffer_pool_metrics::{BufferPoolMetrics, BufferPoolMetricsSnapshot};
+use crate::platform::Stopwatch;
+use crate::storage::common::{PageId, INVALID_PAGE_ID, PAGE_HEADER_SIZE, PAGE_LSN_OFFSET, PAGE_SIZE};
+use crate::storage::disk::disk_manager::DiskManager;
+use crate::storage::disk::disk_scheduler::{DiskCompletion, DiskScheduler, PageBuffer};
+use crate::storage::free_space_map::FreeSpaceMap;
+use crate::types::{CrabDBError, CrabDbResult};
+use crate::wal::common::{Lsn, INVALID_LSN, SYSTEM_TXN_ID};
+use crate::wal::log_manager::LogManager;
+use crate::wal::log_record::LogRecordBody;
+
+pub struct BufferPoolManager {
+    // The most frames the pool holds; in a shared pool, its quota of the shared frames.
+    pool_size: AtomicUsize,
+    pages: Arc<FrameTable>,
+    // The pool whose frames these are, if they are not the manager's own.
+    share: Option<PoolShare>,
+    disk_scheduler: Arc<DiskScheduler>,
+    replacer: Arc<dyn Replacer>,
+    log_manager: Option<Arc<LogManager>>,
+    state: Mutex<BufferPoolState>,
+    // Signalled whenever a page read started by `fetch_frame` is done.
+    loaded: Condvar,
+    metrics: BufferPoolMetrics,
+    eviction_listeners: RwLock<Vec<EvictionListener>>,
+    // Where deallocated pages are kept for reuse, once the catalog has opened it.
+    free_space_map: OnceLock<FreeSpaceMap>,
+    // While above zero, every dirty page written back is logged whole first; see
+    // `start_full_page_writes`. Write-backs hold the read side for as long as they run.
+    full_page_writes: RwLock<usize>,
+}
+
+// A page that left the buffer pool to make room, as eviction listeners see it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvictedPage {
+    pub frame_id: FrameId,
+    pub page_id: PageId,
+    // Whether the page had to be written back on its way out.
+    pub dirty: bool,
+}
+
+type EvictionListener = Arc<dyn Fn(&EvictedPage) + Send + Sync>;
+
+struct PoolShare {
+    pool: Arc<SharedBufferPool>,
+    priority: u8,
+}
+
+struct BufferPoolState {
+    page_table: HashMap<PageId, FrameId>,
+    // Frames of the manager's own holding no page; a shared pool keeps its own list.
+    free_list: VecDeque<FrameId>,
+    // Reads `prefetch` started for pages that are not resident, oldest first.
+    prefetched: HashMap<PageId, DiskCompletion<PageBuffer>>,
+    prefetch_order: VecDeque<PageId>,
+    // Pages pinned for good with `pin_permanently`.
+    permanent: HashSet<PageId>,
+    // Pages in the page table whose frames are still being read into, without the lock;
+    // fetches of them wait on `loaded` until the read is done.
+    loading: HashSet<PageId>,
+}
+
+impl BufferPoolManager {
+    pub fn new(pool_size: usize, disk_manager: Arc<dyn DiskManager>, replacer: Arc<dyn Replacer>) -> Self {
+        Self::with_disk_scheduler(pool_size, Arc::new(DiskScheduler::new(disk_manager, 1)), replacer)
+    }
+
+    pub fn with_disk_scheduler(pool_size: usize, disk_scheduler: Arc<DiskScheduler>, replacer: Arc<dyn Replacer>) -> Self {
+        Self::with_log_manager(pool_size, disk_scheduler, replacer, None)
+    }
+
+    // With a log manager, a dirty page is only written once the log is flushed up to its
+    // page LSN.
+    pub fn with_log_manager(
+        pool_size: usize,
+        disk_scheduler: Arc<DiskScheduler>,
+        replacer: Arc<dyn Replacer>,
+        log_manager: Option<Arc<LogManager>>,
+    ) -> Self {
+        Self::build(pool_size, Arc::new(FrameTable::new(pool_size)), None, disk_scheduler, replacer, log_manager)
+    }
+
+    // A manager holding up to `quota` of the frames of `pool`; see `SharedBufferPool`. Pages
+    // of a higher `priority` are evicted for other managers later. The replacer must have
+    // room for every frame of the pool, since any of them can end up here.
+    pub fn in_shared_pool(
+        pool: &Arc<SharedBufferPool>,
+        quota: usize,
+        priority: u8,
+        disk_scheduler: Arc<DiskScheduler>,
+        replacer: Arc<dyn Replacer>,
+        log_manager: Option<Arc<LogManager>>,
+    ) -> Arc<Self> {
+        let share = PoolShare { pool: pool.clone(), priority };
+        let quota = quota.min(pool.capacity());
+        let bpm = Arc::new(Self::build(quota, pool.frames().clone(), Some(share), disk_scheduler, replacer, log_manager));
+        pool.join(&bpm);
+        bpm
+    }
+
+    fn build(
+        pool_size: usize,
+        pages: Arc<FrameTable>,
+        share: Option<PoolShare>,
+        disk_scheduler: Arc<DiskScheduler>,
+        replacer: Arc<dyn Replacer>,
+        log_manager: Option<Arc<LogManager>>,
+    ) -> Self {
+        let free_list = if share.is_some() { VecDeque::new() } else { (0..pool_size).collect() };
+        BufferPoolManager {
+            pool_size: AtomicUsize::new(pool_size),
+            pages,
+            share,
+            disk_scheduler,
+            replacer,
+            log_manager,
+            state: Mutex::new(BufferPoolState {
+                page_table: HashMap::new(),
+                free_list,
+                prefetched: HashMap::new(),
+                prefetch_order: VecDeque::new(),
+                permanent: HashSet::new(),
+                loading: HashSet::new(),
+            }),
+            loaded: Condvar::new(),
+            metrics: BufferPoolMetrics::default(),
+            eviction_listeners: RwLock::new(Vec::new()),
+            free_space_map: OnceLock::new(),
+            full_page_writes: RwLock::new(0),
+        }
+    }
+
+    pub fn pool_size(&self) -> usize {
+        self.pool_size.load(Ordering::Acquire)
+    }
+
+    pub fn disk_scheduler(&self) -> &Arc<DiskScheduler> {
+        &self.disk_scheduler
+    }
+
+    pub(crate) fn log_manager(&self) -> Option<&Arc<LogManager>> {
+        self.log_manager.as_ref()
+    }
+
+    pub fn free_space_map(&self) -> Option<&FreeSpaceMap> {
+        self.free_space_map.get()
+    }
+
+    // From here on `new_page` reuses the pages `free_space_map` holds and `delete_page` puts
+    // pages on it.
+    pub fn set_free_space_map(&self, free_space_map: FreeSpaceMap) -> CrabDbResult<()> {
+        self.free_space_map.set(free_space_map)
+            .map_err(|_| CrabDBError::InvalidInput("The buffer pool already has a free space map".into()))
+    }
+
+    // Frames holding one of the pool's pages.
+    pub fn frames_held(&self) -> usize {
+        self.state.lock().unwrap().page_table.len()
+    }
+
+    // Page reads and writes slower than `threshold` are counted, and reported as tracing
+    // events; none are if None.
+    pub fn set_slow_io_threshold(&self, threshold: Option<Duration>) {
+        self.metrics.set_slow_io_threshold(threshold);
+    }
+
+    pub fn metrics_snapshot(&self) -> BufferPoolMetricsSnapshot {
+        let (free_frames, permanent_frames) = {
+            let state: MutexGuard<BufferPoolState> = self.state.lock().unwrap();
+            (state.free_list.len(), state.permanent.len())
+        };
+        let free_frames = self.share.as_ref().map_or(free_frames, |share| share.pool.free_frames());
+        let evictable_frames = self.replacer.size().map_or(0, |size| size.num_evictable_frames());
+        self.metrics.snapshot(self.pool_size(), free_frames, evictable_frames, permanent_frames)
+    }
+
+    // Returns a pinned, zeroed page. Callers must hand it back with `unpin_page`.
+    pub fn new_page(&self) -> CrabDbResult<Arc<Page>> {
+        Ok(self.pages[self.new_frame()?].clone())
+    }
+
+    // Returns the page pinned, reading it from disk if it is not resident.
+    pub fn fetch_page(&self, page_id: PageId) -> CrabDbResult<Arc<Page>> {
+        self.fetch_page_with_access(page_id, AccessType::Lookup)
+    }
+
+    // `fetch_page` telling the replacer what the page is for, e.g. that a scan reads it.
+    pub fn fetch_page_with_access(&self, page_id: PageId, access_type: AccessType) -> CrabDbResult<Arc<Page>> {
+        Ok(self.pages[self.fetch_frame(page_id, access_type, None)?].clone())
+    }
+
+    // `fetch_page` for a large scan: on a miss the page is read into a frame of the
+    // strategy's ring rather than one the replacer picks.
+    pub fn fetch_page_with_strategy(&self, page_id: PageId, strategy: &BufferAccessStrategy) -> CrabDbResult<Arc<Page>> {
+        Ok(self.pages[self.fetch_frame(page_id, AccessType::Scan, Some(strategy))?].clone())
+    }
+
+    // Fetches several pages at once, all pinned. Their reads are started together, so they
+    // overlap instead of waiting for one another, and the replacer hears of the pages that
+    // are resident in one batch. On error, the pages already fetched are unpinned again.
+    pub fn fetch_pages(&self, page_ids: &[PageId]) -> CrabDbResult<Vec<Arc<Page>>> {
+        self.prefetch(page_ids)?;
+        let mut pages = self.pin_resident(page_ids)?;
+        for i in 0..page_ids.len() {
+            if pages[i].is_some() {
+                continue;
+            }
+            match self.fetch_page(page_ids[i]) {
+                Ok(page) => pages[i] = Some(page),
+                Err(e) => {
+                    for page in pages.into_iter().flatten() {
+                        self.unpin_page(page.page_id(), false)?;
+                    }
+                    return Err(e);
+                },
+            }
+        }
+        Ok(pages.into_iter().map(|page| page.unwrap()).collect())
+    }
+
+    // Pins those of `page_ids` that are resident and records their accesses with one call
+    // to the replacer. The accesses are recorded before anything is pinned, and the pins
+    // are handed back if the replacer fails, so an error leaves no page pinned.
+    fn pin_resident(&self, page_ids: &[PageId]) -> CrabDbResult<Vec<Option<Arc<Page>>>> {
+        let state: MutexGuard<BufferPoolState> = self.state.lock().unwrap();
+        let frames: Vec<Option<FrameId>> = page_ids.iter().map(|page_id| {
+            // A page still being read in is left to `fetch_page`, which waits for it.
+            if state.loading.contains(page_id) {
+                return None;
+            }
+            state.page_table.get(page_id).copied()
+        }).collect();
+        let frame_ids: Vec<FrameId> = frames.iter().flatten().copied().collect();
+        self.replacer.record_accesses(&frame_ids)?;
+        for (pinned, &frame_id) in frame_ids.iter().enumerate() {
+            self.pages[frame_id].pin();
+            if let Err(e) = self.replacer.set_evictable(frame_id, false) {
+                for &frame_id in &frame_ids[..=pinned] {
+                    if self.pages[frame_id].unpin() == 0 {
+                        let _ = self.replacer.set_evictable(frame_id, true);
+                    }
+                }
+                return Err(e);
+            }
+        }
+        for _ in &frame_ids {
+            self.metrics.record_pin();
+            self.metrics.record_hit();
+        }
+        Ok(frames.into_iter().map(|frame_id| frame_id.map(|frame_id| self.pages[frame_id].clone())).collect())
+    }
+
+    // A hint that the pages will be fetched soon: starts reading the ones that are not
+    // resident, without taking a frame or a pin. A fetch then waits for the read already
+    // under way. At most `pool_size` reads are kept; the oldest make way for new ones.
+    pub fn prefetch(&self, page_ids: &[PageId]) -> CrabDbResult<()> {
+        let mut state: MutexGuard<BufferPoolState> = self.state.lock().unwrap();
+        for &page_id in page_ids {
+            if page_id == INVALID_PAGE_ID || state.page_table.contains_key(&page_id) || state.prefetched.contains_key(&page_id) {
+                continue;
+            }
+            while state.prefetched.len() >= self.pool_size() {
+                let oldest = state.prefetch_order.pop_front().unwrap();
+                state.prefetched.remove(&oldest);
+            }
+            let completion = self.disk_scheduler.schedule_read(page_id)?;
+            state.prefetched.insert(page_id, completion);
+            state.prefetch_order.push_back(page_id);
+        }
+        Ok(())
+    }
+
+    // Resident pages from hottest to coldest, as the replacer ranks them; pages it does not
+    // rank follow in page id order.
+    pub fn hot_pages(&self) -> Vec<PageId> {
+        let hottest_frames = self.replacer.hottest_frames();
+        let state: MutexGuard<BufferPoolState> = self.state.lock().unwrap();
+        let mut resident: HashMap<FrameId, PageId> = state.page_table.iter().map(|(&page_id, &frame_id)| (frame_id, page_id)).collect();
+        drop(state);
+        let mut pages: Vec<_> = hottest_frames.into_iter().filter_map(|frame_id| resident.remove(&frame_id)).collect();
+        let mut unranked: Vec<_> = resident.into_values().collect();
+        unranked.sort_unstable();
+        pages.extend(unranked);
+        pages
+    }
+
+    // The replacer's view of every frame, with the page each one holds.
+    pub fn replacer_debug_state(&self) -> ReplacerDebugState {
+        let mut debug_state = self.replacer.debug_state();
+        let state: MutexGuard<BufferPoolState> = self.state.lock().unwrap();
+        let resident: HashMap<FrameId, PageId> = state.page_table.iter().map(|(&page_id, &frame_id)| (frame_id, page_id)).collect();
+        for frame in &mut debug_state.frames {
+            frame.page_id = resident.get(&frame.frame_id).copied();
+        }
+        debug_state
+    }
+
+    // Threads blocked on the latch of a page in the pool, longest first.
+    pub fn latch_waits(&self) -> Vec<LatchWait> {
+        let frame_ids: Vec<FrameId> = self.state.lock().unwrap().page_table.values().copied().collect();
+        let mut waits: Vec<_> = frame_ids.into_iter().flat_map(|frame_id| self.pages[frame_id].latch_waits()).collect();
+        waits.sort_by_key(|wait| std::cmp::Reverse(wait.waited));
+        waits
+    }
+
+    // Reads `page_ids`, hottest first as `hot_pages` lists them, into the pool, up to as many
+    // as it has frames. The pages come in coldest first and evict what the replacer picks,
+    // so they push out what was there before they push out each other. Pages that cannot
+    // be read, e.g. because they were deallocated since, are skipped: the list is only a
+    // hint. Returns how many pages were read in.
+    pub fn warm_up(&self, page_ids: &[PageId]) -> CrabDbResult<usize> {
+        // A page past the end of the file would only cost a frame to find that out.
+        let num_pages = self.disk_scheduler.disk_manager().num_pages();
+        let page_ids: Vec<_> = page_ids.iter().copied().filter(|&page_id| page_id < num_pages).take(self.pool_size()).collect();
+        self.prefetch(&page_ids)?;
+        let mut loaded = 0;
+        for &page_id in page_ids.iter().rev() {
+            if self.state.lock().unwrap().page_table.contains_key(&page_id) {
+                continue;
+            }
+            match self.fetch_frame(page_id, AccessType::Lookup, None) {
+                Ok(_) => {
+                    self.unpin_page(page_id, false)?;
+                    loaded += 1;
+                },
+                Err(CrabDBError::BufferPoolFull) => break,
+                Err(_) => (),
+            }
+        }
+        Ok(loaded)
+    }
+
+    // Like `new_page`, but the page comes back write-latched and is unpinned when the guard
+    // is dropped.
+    pub fn new_page_write(&self) -> CrabDbResult<WritePageGuard<'_>> {
+        Ok(WritePageGuard::new(self, &self.pages[self.new_frame()?]))
+    }
+
+    pub fn fetch_page_read(&self, page_id: PageId) -> CrabDbResult<ReadPageGuard<'_>> {
+        self.fetch_page_read_with_access(page_id, AccessType::Lookup)
+    }
+
+    pub fn fetch_page_read_with_access(&self, page_id: PageId, access_type: AccessType) -> CrabDbResult<ReadPageGuard<'_>> {
+        Ok(ReadPageGuard::new(self, &self.pages[self.fetch_frame(page_id, access_type, None)?]))
+    }
+
+    pub fn fetch_page_write(&self, page_id: PageId) -> CrabDbResult<WritePageGuard<'_>> {
+        Ok(WritePageGuard::new(self, &self.pages[self.fetch_frame(page_id, AccessType::Lookup, None)?]))
+    }
+
+    fn new_frame(&self) -> CrabDbResult<FrameId> {
+        if let Some(free_space_map) = self.free_space_map.get() {
+            if let Some(page_id) = free_space_map.take_free_page(self)? {
+                let frame_id = self.new_frame_with_id(page_id);
+                if frame_id.is_err() {
+                    free_space_map.mark_free(self, page_id)?;
+                }
+                return frame_id;
+            }
+        }
+        let mut state: MutexGuard<BufferPoolState> = self.state.lock().unwrap();
+        let frame_id = self.acquire_frame(&mut state)?;
+        let page_id = match self.disk_scheduler.disk_manager().allocate_page() {
+            Ok(page_id) => page_id,
+            Err(e) => {
+                self.free_frame(&mut state, frame_id);
+                return Err(e);
+            }
+        };
+        self.install_new_page(&mut state, frame_id, page_id)
+    }
+
+    // `new_page` and `new_page_write` for a page id the caller has already allocated, so it
+    // can pick the pool a page lands in.
+    pub(crate) fn new_page_with_id(&self, page_id: PageId) -> CrabDbResult<Arc<Page>> {
+        Ok(self.pages[self.new_frame_with_id(page_id)?].clone())
+    }
+
+    pub(crate) fn new_page_write_with_id(&self, page_id: PageId) -> CrabDbResult<WritePageGuard<'_>> {
+        Ok(WritePageGuard::new(self, &self.pages[self.new_frame_with_id(page_id)?]))
+    }
+
+    fn new_frame_with_id(&self, page_id: PageId) -> CrabDbResult<FrameId> {
+        let mut state: MutexGuard<BufferPoolState> = self.state.lock().unwrap();
+        let frame_id = self.acquire_frame(&mut state)?;
+        self.install_new_page(&mut state, frame_id, page_id)
+    }
+
+    fn install_new_page(&self, state: &mut BufferPoolState, frame_id: FrameId, page_id: PageId) -> CrabDbResult<FrameId> {
+        // A read prefetched before the id was last deallocated is stale.
+        Self::take_prefetched(state, page_id);
+        let page = &self.pages[frame_id];
+        page.reset(page_id);
+        state.page_table.insert(page_id, frame_id);
+        self.pin_frame(frame_id, AccessType::Lookup)?;
+        self.metrics.record_new_page();
+        Ok(frame_id)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self, strategy), err))]
+    fn fetch_frame(&self, page_id: PageId, access_type: AccessType, strategy: Option<&BufferAccessStrategy>) -> CrabDbResult<FrameId> {
+        if page_id == INVALID_PAGE_ID {
+            return Err(CrabDBError::InvalidInput("Cannot fetch an invalid page id".into()));
+        }
+        // If the read fails, the page leaves the page table and this fetch reads it itself.
+        let mut state = self.wait_for_read(self.state.lock().unwrap(), page_id);
+        if let Some(&frame_id) = state.page_table.get(&page_id) {
+            self.pin_frame(frame_id, access_type)?;
+            self.metrics.record_hit();
+            trace_event!(tracing::Level::TRACE, frame_id, "buffer pool hit");
+            return Ok(frame_id);
+        }
+
+        self.metrics.record_miss();
+        let frame_id = match strategy {
+            Some(strategy) => self.acquire_ring_frame(&mut state, strategy)?,
+            None => self.acquire_frame(&mut state)?,
+        };
+        trace_event!(tracing::Level::TRACE, frame_id, "buffer pool miss");
+        // The frame holds the page from here on, so other fetches of it find the read under
+        // way. The pin keeps the frame out of rings, resizes and deletes, and the replacer
+        // does not hear of the frame until the read is done.
+        let page = &self.pages[frame_id];
+        page.reset(page_id);
+        page.pin();
+        state.page_table.insert(page_id, frame_id);
+        state.loading.insert(page_id);
+        let started = Stopwatch::start();
+        let read = match Self::take_prefetched(&mut state, page_id) {
+            Some(completion) => Ok(completion),
+            None => self.disk_scheduler.schedule_read(page_id),
+        };
+        drop(state);
+        let read = read.and_then(|completion| completion.wait());
+        let latency = started.elapsed();
+        if self.metrics.record_disk_read(latency) {
+            trace_event!(tracing::Level::WARN, page_id, micros = latency.as_micros() as u64, "slow page read");
+        }
+        if let Ok(data) = &read {
+            page.write().copy_from_slice(data.as_slice());
+            page.set_page_lsn(u64::from_le_bytes(data[PAGE_LSN_OFFSET..PAGE_HEADER_SIZE].try_into().unwrap()));
+            page.set_rec_lsn(INVALID_LSN);
+        }
+
+        let mut state: MutexGuard<BufferPoolState> = self.state.lock().unwrap();
+        state.loading.remove(&page_id);
+        self.loaded.notify_all();
+        page.unpin();
+        if let Err(e) = read {
+            state.page_table.remove(&page_id);
+            page.reset(INVALID_PAGE_ID);
+            self.free_frame(&mut state, frame_id);
+            return Err(e);
+        }
+        self.pin_frame(frame_id, access_type)?;
+        Ok(frame_id)
+    }
+
+    // Waits until no read of the page started by `fetch_frame` is under way.
+    fn wait_for_read<'a>(&self, mut state: MutexGuard<'a, BufferPoolState>, page_id: PageId) -> MutexGuard<'a, BufferPoolState> {
+        while state.loading.contains(&page_id) {
+            state = self.loaded.wait(state).unwrap();
+        }
+        state
+    }
+
+    // Keeps the page resident for good, for pages nearly every operation reads, such as the
+    // catalog's first page. The page holds a pin of its own that `unpin_page` never hands
+    // back, so it is never evictable; only `unpin_permanently` releases it.
+    pub fn pin_permanently(&self, page_id: PageId) -> CrabDbResult<()> {
+        let frame_id = self.fetch_frame(page_id, AccessType::Lookup, None)?;
+        let mut state: MutexGuard<BufferPoolState> = self.state.lock().unwrap();
+        if !state.permanent.insert(page_id) {
+            // Already pinned for good; the pin just taken is one too many.
+            self.pages[frame_id].unpin();
+        }
+        Ok(())
+    }
+
+    pub fn unpin_permanently(&self, page_id: PageId) -> CrabDbResult<()> {
+        if !self.state.lock().unwrap().permanent.remove(&page_id) {
+            return Err(CrabDBError::InvalidInput(format!("Page {page_id} is not pinned permanently")));
+        }
+        self.unpin_page(page_id, false)
+    }
+
+    // A page unpinned dirty counts as changed without a log record; see `Page::is_unlogged`.
+    pub fn unpin_page(&self, page_id: PageId, is_dirty: bool) -> CrabDbResult<()> {
+        self.unpin(page_id, is_dirty, is_dirty)
+    }
+
+    pub(crate) fn unpin(&self, page_id: PageId, is_dirty: bool, unlogged: bool) -> CrabDbResult<()> {
+        let state: MutexGuard<BufferPoolState> = self.state.lock().unwrap();
+        let frame_id = match state.page_table.get(&page_id) {
+            Some(&frame_id) => frame_id,
+            None => return Err(CrabDBError::NotFound(format!("Page {page_id} is not in the buffer pool"))),
+        };
+        let page = &self.pages[frame_id];
+        // The pin `pin_permanently` took is not for callers to hand back.
+        let permanent_pins = usize::from(state.permanent.contains(&page_id));
+        if page.pin_count() <= permanent_pins {
+            return Err(CrabDBError::InvalidInput(format!("Page {page_id} is not pinned")));
+        }
+        if is_dirty {
+            page.set_dirty(true);
+        }
+        if unlogged {
+            page.set_unlogged(true);
+        }
+        // The replacer only sees a frame as a victim once nobody holds a pin on it.
+        if page.unpin() == 0 {
+            self.replacer.set_evictable(frame_id, true)?;
+        }
+        Ok(())
+    }
+
+    // Runs `f` on page `page_id` with its latch held for writing and, if the pool has a log,
+    // has `log` log what `f` did, given its result and the page before and after; the page
+    // takes the LSN `log` returns, which is returned along with the result. If `log` returns
+    // None, or `f` fails, the page is left unlogged. A page that already is gets logged whole
+    // first, so the new record applies on top of what the log holds of the page.
+    pub(crate) fn write_page_logged<R>(
+        &self,
+        page_id: PageId,
+        f: impl FnOnce(&mut [u8; PAGE_SIZE]) -> CrabDbResult<R>,
+        log: impl FnOnce(&Arc<LogManager>, &R, &[u8; PAGE_SIZE], &[u8; PAGE_SIZE]) -> CrabDbResult<Option<Lsn>>,
+    ) -> CrabDbResult<(R, Lsn)> {
+        let mut guard = self.fetch_page_write(page_id)?;
+        let Some(log_manager) = &self.log_manager else {
+            return Ok((f(&mut guard)?, INVALID_LSN));
+        };
+        if guard.page().is_unlogged() {
+            let lsn = log_manager.append_buffered(SYSTEM_TXN_ID, INVALID_LSN, LogRecordBody::Compensation {
+                page_id,
+                offset: PAGE_HEADER_SIZE as u16,
+                after: guard[PAGE_HEADER_SIZE..].to_vec(),
+                undo_next_lsn: INVALID_LSN,
+            })?;
+            log_manager.append_buffered(SYSTEM_TXN_ID, lsn, LogRecordBody::Commit { commit_ts: 0 })?;
+            guard.page().set_page_lsn(lsn);
+            guard.page().set_unlogged(false);
+        }
+        let before = Box::new(*guard);
+        let result = match f(&mut guard) {
+            Ok(result) => result,
+            Err(e) => {
+                if *before != *guard {
+                    guard.page().set_unlogged(true);
+                }
+                return Err(e);
+            },
+        };
+        if *before == *guard {
+            guard.set_unchanged();
+            return Ok((result, INVALID_LSN));
+        }
+        match log(log_manager, &result, &before, &guard)? {
+            Some(lsn) => {
+                guard.page().set_page_lsn(lsn);
+                Ok((result, lsn))
+            },
+            None => {
+                guard.page().set_unlogged(true);
+                Ok((result, INVALID_LSN))
+            },
+        }
+    }
+
+    // Logs every page of the pool changed without a log record whole, through `log`, which
+    // returns the LSN the page takes.
+    pub(crate) fn log_unlogged_pages(&self, mut log: impl FnMut(PageId, &[u8]) -> CrabDbResult<Lsn>) -> CrabDbResult<()> {
+        let unlogged: Vec<PageId> = {
+            let state: MutexGuard<BufferPoolState> = self.state.lock().unwrap();
+            state.page_table.iter()
+                .filter(|(_, &frame_id)| self.pages[frame_id].is_unlogged())
+                .map(|(&page_id, _)| page_id)
+                .collect()
+        };
+        for page_id in unlogged {
+            let page = self.fetch_page_read(page_id)?;
+            if page.page().is_unlogged() {
+                let lsn = log(page_id, &page[PAGE_HEADER_SIZE..])?;
+                page.page().set_page_lsn(lsn);
+                page.page().set_unlogged(false);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush_page(&self, page_id: PageId) -> CrabDbResult<()> {
+        let state = self.wait_for_read(self.state.lock().unwrap(), page_id);
+        let frame_id = match state.page_table.get(&page_id) {
+            Some(&frame_id) => frame_id,
+            None => return Err(CrabDBError::NotFound(format!("Page {page_id} is not in the buffer pool"))),
+        };
+        self.write_back(frame_id)
+    }
+
+    // Consecutive pages go out in one write; see `write_runs`. Pages nobody has pinned are
+    // written with the page table held, so none is evicted meanwhile. The others may be
+    // latched by whoever pinned them, who may be fetching a page in turn, so the dirty ones
+    // are pinned again and written once the page table is let go.
+    pub fn flush_all_pages(&self) -> CrabDbResult<()> {
+        let (mut written, pinned) = {
+            let state: MutexGuard<BufferPoolState> = self.state.lock().unwrap();
+            let (idle, pinned): (Vec<_>, Vec<_>) = state.page_table.iter()
+                .map(|(&page_id, &frame_id)| (page_id, frame_id))
+                .filter(|&(_, frame_id)| self.pages[frame_id].pin_count() == 0 || self.pages[frame_id].is_dirty())
+                .partition(|&(_, frame_id)| self.pages[frame_id].pin_count() == 0);
+            for &(_, frame_id) in &pinned {
+                self.pages[frame_id].pin();
+            }
+            (write_runs(idle).iter().try_for_each(|run| self.write_back_run(run)), pinned)
+        };
+        if written.is_ok() {
+            written = write_runs(pinned.clone()).iter().try_for_each(|run| self.write_back_run(run));
+        }
+        for (page_id, _) in pinned {
+            self.unpin_page(page_id, false)?;
+        }
+        written
+    }
+
+    // Writes back the dirty pages nobody has pinned, oldest page LSN first, so eviction finds
+    // clean victims; returns how many it wrote. Consecutive pages go out in one write; see
+    // `runs_by_lsn`. Each run is looked at again just before, so fetches carry on in between.
+    pub fn flush_lagging_pages(&self) -> CrabDbResult<usize> {
+        let lagging = {
+            let state: MutexGuard<BufferPoolState> = self.state.lock().unwrap();
+            state.page_table.iter()
+                .filter(|(_, &frame_id)| self.is_lagging(frame_id))
+                .map(|(&page_id, &frame_id)| (self.pages[frame_id].page_lsn(), page_id, frame_id))
+                .collect()
+        };
+        let mut flushed = 0;
+        for run in runs_by_lsn(lagging) {
+            let state: MutexGuard<BufferPoolState> = self.state.lock().unwrap();
+            let still_lagging = run.into_iter()
+                .map(|frame_id| (self.pages[frame_id].page_id(), frame_id))
+                .filter(|&(page_id, frame_id)| state.page_table.get(&page_id) == Some(&frame_id) && self.is_lagging(frame_id))
+                .collect();
+            // A page that has gone from the middle of a run splits it.
+            for run in write_runs(still_lagging) {
+                self.write_back_run(&run)?;
+                flushed += run.len();
+            }
+        }
+        Ok(flushed)
+    }
+
+    fn is_lagging(&self, frame_id: FrameId) -> bool {
+        let page = &self.pages[frame_id];
+        page.is_dirty() && page.pin_count() == 0
+    }
+
+    // The pages changed by a log record since they were last written back, with the LSN of
+    // the first such record: the dirty page table of a checkpoint. Each page is latched in
+    // turn, so a change whose record is logged but whose page LSN is not set yet is waited
+    // for rather than missed.
+    pub(crate) fn dirty_page_table(&self) -> Vec<(PageId, Lsn)> {
+        let frame_ids: Vec<FrameId> = self.state.lock().unwrap().page_table.values().copied().collect();
+        let mut dirty_pages: Vec<_> = frame_ids.into_iter().filter_map(|frame_id| {
+            let page = &self.pages[frame_id];
+            let _latch = page.read();
+            let (page_id, rec_lsn) = (page.page_id(), page.rec_lsn());
+            (page_id != INVALID_PAGE_ID && rec_lsn != INVALID_LSN).then_some((page_id, rec_lsn))
+        }).collect();
+        dirty_pages.sort_unstable();
+        dirty_pages
+    }
+
+    // Fraction of the frames holding a page changed since it was last written.
+    pub fn dirty_ratio(&self) -> f64 {
+        let state: MutexGuard<BufferPoolState> = self.state.lock().unwrap();
+        let dirty = state.page_table.values().filter(|&&frame_id| self.pages[frame_id].is_dirty()).count();
+        dirty as f64 / self.pool_size() as f64
+    }
+
+    pub fn delete_page(&self, page_id: PageId) -> CrabDbResult<()> {
+        {
+            let mut state: MutexGuard<BufferPoolState> = self.state.lock().unwrap();
+            if let Some(&frame_id) = state.page_table.get(&page_id) {
+                let page = &self.pages[frame_id];
+                if page.pin_count() > 0 {
+                    return Err(CrabDBError::PagePinned(page_id));
+                }
+                self.replacer.remove(frame_id)?;
+                state.page_table.remove(&page_id);
+                page.reset(INVALID_PAGE_ID);
+                self.free_frame(&mut state, frame_id);
+            }
+            Self::take_prefetched(&mut state, page_id);
+        }
+        self.disk_scheduler.disk_manager().deallocate_page(page_id)?;
+        // The free space map fetches its own pages, so the latch has to be released first.
+        match self.free_space_map.get() {
+            Some(free_space_map) => free_space_map.mark_free(self, page_id),
+            None => Ok(()),
+        }
+    }
+
+    // Calls `listener` for every page evicted from here on, once it has been written back and
+    // before its frame is reused, so structures kept about resident pages can follow along.
+    // Listeners run under the buffer pool latch and must not call back into the pool.
+    pub fn on_evict(&self, listener: impl Fn(&EvictedPage) + Send + Sync + 'static) {
+        self.eviction_listeners.write().unwrap().push(Arc::new(listener));
+    }
+
+    fn notify_evicted(&self, evicted: EvictedPage) {
+        for listener in self.eviction_listeners.read().unwrap().iter() {
+            listener(&evicted);
+        }
+    }
+
+    // Changes the number of frames. Growing adds free frames; shrinking retires the frames
+    // from `pool_size` up, writing back their dirty pages first, and fails without changing
+    // anything if one of them is pinned. A retired frame's memory is kept for when the pool
+    // grows again. In a shared pool this changes the quota instead, evicting pages until the
+    // manager holds no more than it, and fails if too many of them are pinned.
+    pub fn resize(&self, pool_size: usize) -> CrabDbResult<()> {
+        if pool_size == 0 {
+            return Err(CrabDBError::InvalidInput("The buffer pool needs at least one frame".to_string()));
+        }
+        let mut state: MutexGuard<BufferPoolState> = self.state.lock().unwrap();
+        if let Some(share) = &self.share {
+            let quota = pool_size.min(share.pool.capacity());
+            while state.page_table.len() > quota {
+                let Some(frame_id) = self.replacer.evict()?.frame_id() else {
+                    return Err(CrabDBError::InvalidInput(format!("Too many pages are pinned to hold only {quota} frames")));
+                };
+                self.evict_frame(&mut state, frame_id)?;
+                self.free_frame(&mut state, frame_id);
+            }
+            self.pool_size.store(quota, Ordering::Release);
+            return Ok(());
+        }
+        let current_size = self.pool_size();
+        if pool_size >= current_size {
+            self.replacer.set_replacer_size(pool_size)?;
+            self.pages.grow(pool_size);
+            state.free_list.extend(current_size..pool_size);
+            self.pool_size.store(pool_size, Ordering::Release);
+            return Ok(());
+        }
+
+        let resident: Vec<FrameId> = (pool_size..current_size)
+            .filter(|&frame_id| state.page_table.get(&self.pages[frame_id].page_id()) == Some(&frame_id))
+            .collect();
+        if let Some(&frame_id) = resident.iter().find(|&&frame_id| self.pages[frame_id].pin_count() > 0) {
+            let page_id = self.pages[frame_id].page_id();
+            return Err(CrabDBError::PagePinned(page_id));
+        }
+        let dirty: Vec<bool> = resident.iter().map(|&frame_id| self.pages[frame_id].is_dirty()).collect();
+        for (&frame_id, &dirty) in resident.iter().zip(&dirty) {
+            if dirty {
+                self.write_back(frame_id)?;
+            }
+        }
+        for (&frame_id, &dirty) in resident.iter().zip(&dirty) {
+            let page = &self.pages[frame_id];
+            let page_id = page.page_id();
+            self.replacer.remove(frame_id)?;
+            state.page_table.remove(&page_id);
+            page.reset(INVALID_PAGE_ID);
+            self.metrics.record_eviction();
+            self.notify_evicted(EvictedPage { frame_id, page_id, dirty });
+        }
+        state.free_list.retain(|&frame_id| frame_id < pool_size);
+        self.pool_size.store(pool_size, Ordering::Release);
+        // A replacer that cannot shrink just keeps room for frames that no longer exist.
+        let _ = self.replacer.set_replacer_size(pool_size);
+        Ok(())
+    }
+
+    // Hands out a frame to hold a new page, preferring the free list and falling back to
+    // evicting a victim chosen by the replacer. Dirty victims are written back first.
+    // In a shared pool, a manager under its quota takes a free frame of the pool, or else
+    // one it reclaims from a manager that goes before it; see `SharedBufferPool`.
+    fn acquire_frame(&self, state: &mut BufferPoolState) -> CrabDbResult<FrameId> {
+        let under_quota = state.page_table.len() < self.pool_size();
+        match &self.share {
+            Some(share) if under_quota => {
+                if let Some(frame_id) = share.pool.take_free_frame() {
+                    return Ok(frame_id);
+                }
+                let rank = EvictionRank { priority: share.priority, frames_held: state.page_table.len() };
+                if let Some(frame_id) = share.pool.reclaim_frame(self, Some(rank))? {
+                    return Ok(frame_id);
+                }
+            },
+            Some(_) => (),
+            None => {
+                if let Some(frame_id) = state.free_list.pop_front() {
+                    return Ok(frame_id);
+                }
+            },
+        }
+
+        let frame_id = match self.replacer.evict()?.frame_id() {
+            Some(frame_id) => frame_id,
+            None => return match &self.share {
+                Some(share) if under_quota => share.pool.reclaim_frame(self, None)?.ok_or(CrabDBError::BufferPoolFull),
+                _ => Err(CrabDBError::BufferPoolFull),
+            },
+        };
+        self.evict_frame(state, frame_id)?;
+        Ok(frame_id)
+    }
+
+    // Puts a frame that no longer holds a page back where free frames are kept.
+    fn free_frame(&self, state: &mut BufferPoolState, frame_id: FrameId) {
+        match &self.share {
+            Some(share) => share.pool.return_frames([frame_id]),
+            None => state.free_list.push_back(frame_id),
+        }
+    }
+
+    // Where the manager stands among those sharing its pool, unless it is busy.
+    pub(crate) fn eviction_rank(&self) -> Option<EvictionRank> {
+        let share = self.share.as_ref()?;
+        let state = self.state.try_lock().ok()?;
+        Some(EvictionRank { priority: share.priority, frames_held: state.page_table.len() })
+    }
+
+    // Evicts a page for another manager sharing the pool and gives up its frame; None if
+    // there is nothing to evict or the manager is busy.
+    pub(crate) fn release_frame(&self) -> CrabDbResult<Option<FrameId>> {
+        let Ok(mut state) = self.state.try_lock() else {
+            return Ok(None);
+        };
+        let Some(frame_id) = self.replacer.evict()?.frame_id() else {
+            return Ok(None);
+        };
+        self.evict_frame(&mut state, frame_id)?;
+        Ok(Some(frame_id))
+    }
+
+    // Takes the frame of the oldest page in the strategy's ring when the ring is full and
+    // nobody has pinned that page since; otherwise acquires a frame as usual and adds it to
+    // the ring.
+    fn acquire_ring_frame(&self, state: &mut BufferPoolState, strategy: &BufferAccessStrategy) -> CrabDbResult<FrameId> {
+        if let Some(frame_id) = strategy.next_victim() {
+            if self.share.is_some() || frame_id < self.pool_size() {
+                let page = &self.pages[frame_id];
+                let page_id = page.page_id();
+                if state.page_table.get(&page_id) == Some(&frame_id) && page.pin_count() == 0 && !state.permanent.contains(&page_id) {
+                    self.replacer.remove(frame_id)?;
+                    self.evict_frame(state, frame_id)?;
+                    strategy.push(frame_id);
+                    return Ok(frame_id);
+                }
+            }
+        }
+        let frame_id = self.acquire_frame(state)?;
+        strategy.push(frame_id);
+        Ok(frame_id)
+    }
+
+    // Empties a frame the replacer has let go of, writing its page back if it is dirty. If
+    // the write fails, the page stays in its frame, still dirty, and goes back to the
+    // replacer as evictable, so the pool does not lose the frame.
+    fn evict_frame(&self, state: &mut BufferPoolState, frame_id: FrameId) -> CrabDbResult<()> {
+        let victim = &self.pages[frame_id];
+        let evicted = EvictedPage { frame_id, page_id: victim.page_id(), dirty: victim.is_dirty() };
+        trace_event!(tracing::Level::DEBUG, frame_id, page_id = evicted.page_id, dirty = evicted.dirty, "evicting page");
+        if evicted.dirty {
+            if let Err(e) = self.write_back(frame_id) {
+                self.replacer.record_page_access(frame_id, evicted.page_id)?;
+                self.replacer.set_evictable(frame_id, true)?;
+                return Err(e);
+            }
+        }
+        self.metrics.record_eviction();
+        state.page_table.remove(&evicted.page_id);
+        self.notify_evicted(evicted);
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self), fields(page_id = self.pages[frame_id].page_id()), err))]
+    fn write_back(&self, frame_id: FrameId) -> CrabDbResult<()> {
+        self.write_back_run(&[frame_id])
+    }
+
+    // Writes back the pages in `frame_ids`, which are consecutive, with one write.
+    fn write_back_run(&self, frame_ids: &[FrameId]) -> CrabDbResult<()> {
+        let Some(&first_frame_id) = frame_ids.first() else {
+            return Ok(());
+        };
+        let full_page_writes = self.full_page_writes.read().unwrap();
+        let mut images = Vec::with_capacity(frame_ids.len());
+        let mut image_lsns = Vec::with_capacity(frame_ids.len());
+        for &frame_id in frame_ids {
+            let (image, page_lsn) = self.page_image(frame_id, *full_page_writes > 0)?;
+            images.push(image);
+            image_lsns.push(page_lsn);
+        }
+        let flush_lsn = image_lsns.iter().copied().max().unwrap_or(INVALID_LSN);
+        if let Some(log_manager) = &self.log_manager {
+            log_manager.flush(flush_lsn)?;
+        }
+        let first_page_id = self.pages[first_frame_id].page_id();
+        let started = Stopwatch::start();
+        let written = match images.len() {
+            1 => self.disk_scheduler.schedule_write(first_page_id, images.pop().unwrap()),
+            _ => self.disk_scheduler.schedule_writes(first_page_id, images),
+        };
+        written?.wait()?;
+        let latency = started.elapsed();
+        if self.metrics.record_disk_write(frame_ids.len(), latency) {
+            trace_event!(tracing::Level::WARN, page_id = first_page_id, pages = frame_ids.len(), micros = latency.as_micros() as u64, "slow page write");
+        }
+        for (&frame_id, &image_lsn) in frame_ids.iter().zip(&image_lsns) {
+            let page = &self.pages[frame_id];
+            page.set_dirty(false);
+            // Changes logged since the image was taken are not on disk yet. Whoever logs one
+            // sets the page LSN under the latch, so it is settled while the latch is held.
+            let _latch = page.read();
+            page.set_rec_lsn(if page.page_lsn() > image_lsn { image_lsn + 1 } else { INVALID_LSN });
+        }
+        Ok(())
+    }
+
+    // A copy of the page in `frame_id` as it goes to disk, and the LSN the log has to be
+    // flushed to before it does. With full page writes, a dirty page is logged whole first.
+    fn page_image(&self, frame_id: FrameId, full_page_writes: bool) -> CrabDbResult<(PageBuffer, Lsn)> {
+        let page = &self.pages[frame_id];
+        let mut data = Box::new(*page.read());
+        let dirty = page.is_dirty();
+        let mut page_lsn = page.page_lsn();
+        if let Some(log_manager) = &self.log_manager {
+            if full_page_writes && dirty {
+                // A compensation record is redone and never undone, which is what a page
+                // image needs.
+                page_lsn = log_manager.append(SYSTEM_TXN_ID, INVALID_LSN, LogRecordBody::Compensation {
+                    page_id: page.page_id(),
+                    offset: PAGE_HEADER_SIZE as u16,
+                    after: data[PAGE_HEADER_SIZE..].to_vec(),
+                    undo_next_lsn: INVALID_LSN,
+                })?;
+                log_manager.append(SYSTEM_TXN_ID, page_lsn, LogRecordBody::Commit { commit_ts: 0 })?;
+                if page.page_lsn() < page_lsn {
+                    page.set_page_lsn(page_lsn);
+                }
+            }
+        }
+        data[PAGE_LSN_OFFSET..PAGE_HEADER_SIZE].copy_from_slice(&page_lsn.to_le_bytes());
+        if dirty {
+            self.metrics.record_dirty_write();
+        }
+        Ok((data, page_lsn))
+    }
+
+    // With full page writes on, the log holds every change made to the database file, so a
+    // copy of the file taken meanwhile is brought up to date by redoing the log. They stay
+    // on until every start is matched by a stop. Returns once write-backs that started
+    // before are done. Needs a log manager.
+    pub(crate) fn start_full_page_writes(&self) -> CrabDbResult<()> {
+        if self.log_manager.is_none() {
+            return Err(CrabDBError::InvalidInput("Full page writes need a log manager".to_string()));
+        }
+        *self.full_page_writes.write().unwrap() += 1;
+        Ok(())
+    }
+
+    pub(crate) fn stop_full_page_writes(&self) {
+        let mut full_page_writes = self.full_page_writes.write().unwrap();
+        *full_page_writes = full_page_writes.saturating_sub(1);
+    }
+
+    fn take_prefetched(state: &mut BufferPoolState, page_id: PageId) -> Option<DiskCompletion<PageBuffer>> {
+        let completion = state.prefetched.remove(&page_id)?;
+        state.prefetch_order.retain(|&prefetched| prefetched != page_id);
+        Some(completion)
+    }
+
+    fn pin_frame(&self, frame_id: FrameId, access_type: AccessType) -> CrabDbResult<()> {
+        let page = &self.pages[frame_id];
+        page.pin();
+        self.metrics.record_pin();
+        self.replacer.record_access_with_type(frame_id, page.page_id(), access_type)?;
+        self.replacer.set_evictable(frame_id, false)?;
+        Ok(())
+    }
+}
+
+// The most pages written back in one write: 256 KiB of 4 KiB pages.
+const MAX_WRITE_RUN: usize = 64;
+
+// Groups pages into runs of consecutive page ids, in page order, of at most MAX_WRITE_RUN
+// pages each, and returns the frames of each run.
+fn write_runs(mut pages: Vec<(PageId, FrameId)>) -> Vec<Vec<FrameId>> {
+    pages.sort_unstable();
+    let mut runs: Vec<Vec<FrameId>> = Vec::new();
+    let mut next_page_id = INVALID_PAGE_ID;
+    for (page_id, frame_id) in pages {
+        match runs.last_mut() {
+            Some(run) if page_id == next_page_id && run.len() < MAX_WRITE_RUN => run.push(frame_id),
+            _ => runs.push(vec![frame_id]),
+        }
+        next_page_id = page_id.wrapping_add(1);
+    }
+    runs
+}
+
+// Groups pages into runs of consecutive page ids like `write_runs`, but picks them oldest
+// page LSN first: each run grows around the oldest page not yet in one, taking its dirty
+// neighbours along, and the runs come out in the order of their oldest pages.
+fn runs_by_lsn(mut pages: Vec<(Lsn, PageId, FrameId)>) -> Vec<Vec<FrameId>> {
+    pages.sort_unstable();
+    let mut frames: HashMap<PageId, FrameId> = pages.iter().map(|&(_, page_id, frame_id)| (page_id, frame_id)).collect();
+    let mut runs = Vec::new();
+    for (_, page_id, _) in pages {
+        if !frames.contains_key(&page_id) {
+            continue;
+        }
+        let (mut first, mut last) = (page_id, page_id);
+        while first > 0 && frames.contains_key(&(first - 1)) && last - first + 1 < MAX_WRITE_RUN as PageId {
+            first -= 1;
+        }
+        while frames.contains_key(&(last + 1)) && last - first + 1 < MAX_WRITE_RUN as PageId {
+            last += 1;
+        }
+        runs.push((first..=last).map(|page_id| frames.remove(&page_id).unwrap()).collect());
+    }
+    runs
+}
+
+// Frames of a shared pool go back to it with the manager.
+impl Drop for BufferPoolManager {
+    fn drop(&mut self) {
+        if let Some(share) = &self.share {
+            let state = self.state.get_mut().unwrap();
+            share.pool.return_frames(state.page_table.drain().map(|(_, frame_id)| frame_id));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use tempfile::TempDir;
+
+    use crate::buffer_pool::access_strategy::BufferAccessStrategy;
+    use crate::buffer_pool::common::FrameId;
+    use crate::buffer_pool::eviction::lru::lru_replacer::LRUReplacer;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::buffer_pool::eviction::replacer::{responses::*, Replacer};
+    use crate::storage::common::{PageId, PAGE_HEADER_SIZE, PAGE_SIZE};
+    use crate::storage::disk::{disk_manager::DiskManager, file_disk_manager::FileDiskManager, memory_disk_manager::MemoryDiskManager};
+    use crate::storage::disk::disk_scheduler::DiskScheduler;
+    use crate::storage::disk::faulty_disk_manager::{Fault, FaultyDiskManager};
+    use crate::types::{CrabDBError, CrabDbResult};
+    use crate::wal::common::INVALID_LSN;
+    use crate::wal::log_manager::LogManager;
+    use crate::wal::log_record::LogRecordBody;
+
+    use super::{runs_by_lsn, BufferPoolManager, EvictedPage};
+
+    fn buffer_pool(dir: &TempDir, pool_size: usize) -> BufferPoolManager {
+        let disk_manager = Arc::new(FileDiskManager::new(dir.path().join("test.db")).unwrap());
+        BufferPoolManager::new(pool_size, disk_manager, Arc::new(LRUKReplacer::new(pool_size, 2)))
+    }
+
+    #[test]
+    pub fn test_bpm_new_page_until_full() {
+        let dir = TempDir::new().unwrap();
+        let bpm = buffer_pool(&dir, 3);
+
+        for expected_page_id in 0..3 {
+            let page = bpm.new_page().unwrap();
+            assert_eq!(expected_page_id, page.page_id());
+            assert_eq!(1, page.pin_count());
+        }
+
+        // every frame is pinned, so nothing can be evicted
+        assert!(matches!(bpm.new_page(), Err(CrabDBError::BufferPoolFull)));
+
+        assert!(bpm.unpin_page(1, false).is_ok());
+        assert_eq!(3, bpm.new_page().unwrap().page_id());
+    }
+
+    #[test]
+    pub fn test_bpm_evicted_page_is_written_back() {
+        let dir = TempDir::new().unwrap();
+        let bpm = buffer_pool(&dir, 2);
+
+        let page = bpm.new_page().unwrap();
+        page.write()[PAGE_HEADER_SIZE..PAGE_HEADER_SIZE + 5].copy_from_slice(b"crabs");
+        assert!(bpm.unpin_page(0, true).is_ok());
+
+        // push page 0 out of the pool
+        for page_id in 1..3 {
+            bpm.new_page().unwrap();
+            assert!(bpm.unpin_page(page_id, false).is_ok());
+        }
+
+        let page = bpm.fetch_page(0).unwrap();
+        assert_eq!(b"crabs", &page.read()[PAGE_HEADER_SIZE..PAGE_HEADER_SIZE + 5]);
+        assert!(!page.is_dirty());
+    }
+
+    #[test]
+    pub fn test_bpm_keeps_victim_after_failed_write_back() {
+        let disk_manager = Arc::new(FaultyDiskManager::new(Arc::new(MemoryDiskManager::new())));
+        let bpm = BufferPoolManager::new(1, disk_manager.clone(), Arc::new(LRUKReplacer::new(1, 2)));
+
+        let page = bpm.new_page().unwrap();
+        page.write()[PAGE_HEADER_SIZE..PAGE_HEADER_SIZE + 5].copy_from_slice(b"crabs");
+        assert!(bpm.unpin_page(0, true).is_ok());
+
+        // writing the victim back fails, so it stays resident and dirty
+        disk_manager.inject(disk_manager.writes() + 1, Fault::PartialWrite { bytes: 16 });
+        assert!(matches!(bpm.new_page(), Err(CrabDBError::Io { .. })));
+        let page = bpm.fetch_page(0).unwrap();
+        assert!(page.is_dirty());
+        assert_eq!(b"crabs", &page.read()[PAGE_HEADER_SIZE..PAGE_HEADER_SIZE + 5]);
+        assert!(bpm.unpin_page(0, false).is_ok());
+
+        // the pool still has its one frame to evict once writes succeed again
+        let page_id = bpm.new_page().unwrap().page_id();
+        assert!(bpm.unpin_page(page_id, false).is_ok());
+        let page = bpm.fetch_page(0).unwrap();
+        assert_eq!(b"crabs", &page.read()[PAGE_HEADER_SIZE..PAGE_HEADER_SIZE + 5]);
+    }
+
+    #[test]
+    pub fn test_bpm_fetch_resident_page_pins_again() {
+        let dir = TempDir::new().unwrap();
+        let bpm = buffer_pool(&dir, 2);
+
+        bpm.new_page().unwrap();
+        assert_eq!(2, bpm.fetch_page(0).unwrap().pin_count());
+        assert!(bpm.unpin_page(0, false).is_ok());
+        assert!(bpm.unpin_page(0, false).is_ok());
+        assert_eq!(
+            "Page 0 is not pinned",
+            bpm.unpin_page(0, false).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    pub fn test_bpm_unpin_missing_page() {
+        let dir = TempDir::new().unwrap();
+        let bpm = buffer_pool(&dir, 2);
+
+        assert_eq!(
+            "Page 7 is not in the buffer pool",
+            bpm.unpin_page(7, false).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    pub fn test_bpm_delete_page() {
+        let dir = TempDir::new().unwrap();
+        let bpm = buffer_pool(&dir, 1);
+
+        bpm.new_page().unwrap();
+        assert!(matches!(bpm.delete_page(0), Err(CrabDBError::PagePinned(0))));
+        assert!(bpm.unpin_page(0, false).is_ok());
+        assert!(bpm.delete_page(0).is_ok());
+
+        // the frame went back to the free list
+        assert_eq!(1, bpm.new_page().unwrap().page_id());
+        assert_eq!(
+            "Page 42 has not been allocated",
+            bpm.delete_page(42).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    pub fn test_bpm_flush_all_pages() {
+        let dir = TempDir::new().unwrap();
+        let bpm = buffer_pool(&dir, 2);
+
+        bpm.new_page().unwrap().write()[PAGE_HEADER_SIZE] = 7;
+        assert!(bpm.unpin_page(0, true).is_ok());
+        assert!(bpm.flush_all_pages().is_ok());
+
+        let disk_manager = FileDiskManager::new(dir.path().join("test.db")).unwrap();
+        let mut buf = [0u8; PAGE_SIZE];
+        disk_manager.read_page(0, &mut buf).unwrap();
+        assert_eq!(7, buf[PAGE_HEADER_SIZE]);
+    }
+
+    #[test]
+    pub fn test_bpm_flush_all_pages_lets_fetches_past_latched_pages() {
+        let dir = TempDir::new().unwrap();
+        let bpm = buffer_pool(&dir, 4);
+        for page_id in 0..2 {
+            bpm.new_page().unwrap();
+            assert!(bpm.unpin_page(page_id, true).is_ok());
+        }
+        let page = bpm.fetch_page(0).unwrap();
+        let mut latched = page.write();
+        latched[PAGE_HEADER_SIZE] = 7;
+        std::thread::scope(|scope| {
+            let flush = scope.spawn(|| bpm.flush_all_pages());
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            // The flush waits for page 0 without holding up fetches of other pages.
+            assert!(bpm.fetch_page(1).is_ok() && bpm.unpin_page(1, false).is_ok());
+            drop(latched);
+            assert!(flush.join().unwrap().is_ok());
+        });
+        assert_eq!(1, page.pin_count());
+
+        let disk_manager = FileDiskManager::new(dir.path().join("test.db")).unwrap();
+        let mut buf = [0u8; PAGE_SIZE];
+        disk_manager.read_page(0, &mut buf).unwrap();
+        assert_eq!(7, buf[PAGE_HEADER_SIZE]);
+    }
+
+    #[test]
+    pub fn test_bpm_lists_threads_waiting_for_latches() {
+        let bpm = Arc::new(BufferPoolManager::new(4, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(4, 2))));
+        let page_id = bpm.new_page().unwrap().page_id();
+        bpm.unpin_page(page_id, false).unwrap();
+        let page = bpm.fetch_page(page_id).unwrap();
+        let latched = page.write();
+        assert!(bpm.latch_waits().is_empty());
+
+        let reader = {
+            let bpm = bpm.clone();
+            std::thread::Builder::new().name("latch-reader".into()).spawn(move || {
+                bpm.fetch_page_read(page_id).unwrap()[PAGE_HEADER_SIZE]
+            }).unwrap()
+        };
+        while bpm.latch_waits().is_empty() {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let waits = bpm.latch_waits();
+        assert_eq!((page_id, "latch-reader", false), (waits[0].page_id, waits[0].thread.as_str(), waits[0].exclusive));
+        drop(latched);
+        bpm.unpin_page(page_id, false).unwrap();
+        assert_eq!(0, reader.join().unwrap());
+        assert!(bpm.latch_waits().is_empty());
+    }
+
+    #[test]
+    pub fn test_bpm_coalesces_writes_of_consecutive_pages() {
+        let dir = TempDir::new().unwrap();
+        let bpm = buffer_pool(&dir, 8);
+        for page_id in 0..8u8 {
+            bpm.new_page().unwrap().write()[PAGE_HEADER_SIZE] = page_id;
+            assert!(bpm.unpin_page(page_id as u64, true).is_ok());
+        }
+        assert!(bpm.flush_all_pages().is_ok());
+        let metrics = bpm.metrics_snapshot();
+        assert_eq!((8, 1, 1), (metrics.disk_writes, metrics.coalesced_writes, metrics.disk_write_latency.count()));
+
+        // Pages 1 and 2 go out together, 5 on its own, and pinned 6 not at all.
+        for page_id in [1, 2, 5, 6] {
+            bpm.fetch_page(page_id).unwrap().write()[PAGE_HEADER_SIZE] += 10;
+        }
+        for page_id in [1, 2, 5] {
+            assert!(bpm.unpin_page(page_id, true).is_ok());
+        }
+        assert_eq!(3, bpm.flush_lagging_pages().unwrap());
+        let metrics = bpm.metrics_snapshot();
+        assert_eq!((11, 2, 3), (metrics.disk_writes, metrics.coalesced_writes, metrics.disk_write_latency.count()));
+        assert_eq!(0, bpm.flush_lagging_pages().unwrap());
+
+        let disk_manager = FileDiskManager::new(dir.path().join("test.db")).unwrap();
+        let mut buf = [0u8; PAGE_SIZE];
+        for (page_id, expected) in [(0, 0), (1, 11), (2, 12), (5, 15), (6, 6), (7, 7)] {
+            disk_manager.read_page(page_id, &mut buf).unwrap();
+            assert_eq!(expected, buf[PAGE_HEADER_SIZE], "page {page_id}");
+        }
+    }
+
+    #[test]
+    pub fn test_lagging_runs_go_out_oldest_lsn_first() {
+        // (page LSN, page id, frame id): page 5 is the oldest, so its run with 6 goes first,
+        // then 2's with 1 and 3, while 9 is not next to any of them
+        let pages = vec![(30, 1, 11), (40, 9, 19), (10, 5, 15), (20, 2, 12), (50, 6, 16), (60, 3, 13)];
+        assert_eq!(vec![vec![15, 16], vec![11, 12, 13], vec![19]], runs_by_lsn(pages));
+
+        // a run stops at MAX_WRITE_RUN pages, counted around the oldest page
+        let pages = (0..100).map(|page_id| (100 - page_id, page_id, page_id as usize)).collect();
+        let runs = runs_by_lsn(pages);
+        assert_eq!((36..100).collect::<Vec<_>>(), runs[0]);
+        assert_eq!((0..36).collect::<Vec<_>>(), runs[1]);
+    }
+
+    #[test]
+    pub fn test_bpm_over_memory_disk_manager() {
+        let disk_manager = Arc::new(MemoryDiskManager::new());
+        let bpm = BufferPoolManager::new(1, disk_manager.clone(), Arc::new(LRUKReplacer::new(1, 2)));
+
+        bpm.new_page().unwrap().write()[PAGE_HEADER_SIZE] = 42;
+        assert!(bpm.unpin_page(0, true).is_ok());
+        bpm.new_page().unwrap();
+        assert!(bpm.unpin_page(1, false).is_ok());
+
+        let mut buf = [0u8; PAGE_SIZE];
+        disk_manager.read_page(0, &mut buf).unwrap();
+        assert_eq!(42, buf[PAGE_HEADER_SIZE]);
+        assert_eq!(42, bpm.fetch_page(0).unwrap().read()[PAGE_HEADER_SIZE]);
+    }
+
+    // Holds each read back until the test lets it through, and counts the reads.
+    struct GatedDiskManager {
+        inner: MemoryDiskManager,
+        reads: AtomicUsize,
+        started: Mutex<mpsc::Sender<PageId>>,
+        release: Mutex<mpsc::Receiver<()>>,
+    }
+
+    impl DiskManager for GatedDiskManager {
+        fn read_page(&self, page_id: PageId, buf: &mut [u8]) -> CrabDbResult<()> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            self.started.lock().unwrap().send(page_id).unwrap();
+            self.release.lock().unwrap().recv().unwrap();
+            self.inner.read_page(page_id, buf)
+        }
+
+        fn write_page(&self, page_id: PageId, buf: &[u8]) -> CrabDbResult<()> {
+            self.inner.write_page(page_id, buf)
+        }
+
+        fn allocate_page(&self) -> CrabDbResult<PageId> {
+            self.inner.allocate_page()
+        }
+
+        fn deallocate_page(&self, page_id: PageId) -> CrabDbResult<()> {
+            self.inner.deallocate_page(page_id)
+        }
+
+        fn num_pages(&self) -> u64 {
+            self.inner.num_pages()
+        }
+    }
+
+    #[test]
+    pub fn test_bpm_reads_pages_in_without_the_lock_and_fetches_wait_on_the_frame() {
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel();
+        let inner = MemoryDiskManager::new();
+        let mut buf = [0u8; PAGE_SIZE];
+        buf[PAGE_HEADER_SIZE] = 42;
+        inner.write_page(inner.allocate_page().unwrap(), &buf).unwrap();
+        let disk_manager = Arc::new(GatedDiskManager {
+            inner,
+            reads: AtomicUsize::new(0),
+            started: Mutex::new(started_tx),
+            release: Mutex::new(release_rx),
+        });
+        let bpm = Arc::new(BufferPoolManager::new(2, disk_manager.clone(), Arc::new(LRUKReplacer::new(2, 2))));
+
+        let fetch = |bpm: &Arc<BufferPoolManager>| {
+            let bpm = bpm.clone();
+            std::thread::spawn(move || bpm.fetch_page(0).unwrap())
+        };
+        let first = fetch(&bpm);
+        assert_eq!(0, started_rx.recv().unwrap());
+        // The read is under way; the pool goes on serving everything else meanwhile.
+        assert!(bpm.flush_all_pages().is_ok());
+        assert!(bpm.flush_page(1).is_err());
+        let second = fetch(&bpm);
+
+        // Enough for a second read too, so that one fails the test instead of hanging it.
+        release_tx.send(()).unwrap();
+        release_tx.send(()).unwrap();
+        let (first, second) = (first.join().unwrap(), second.join().unwrap());
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(2, first.pin_count());
+        assert_eq!(42, first.read()[PAGE_HEADER_SIZE]);
+        assert_eq!(1, disk_manager.reads.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    pub fn test_bpm_pinned_page_is_never_evicted() {
+        let dir = TempDir::new().unwrap();
+        let bpm = buffer_pool(&dir, 2);
+
+        let pinned = bpm.new_page().unwrap();
+        pinned.write()[PAGE_HEADER_SIZE] = 1;
+        for page_id in 1..5 {
+            bpm.new_page().unwrap();
+            assert!(bpm.unpin_page(page_id, false).is_ok());
+        }
+
+        // page 0 kept its frame the whole time
+        assert_eq!(0, pinned.page_id());
+        assert_eq!(1, pinned.pin_count());
+        assert!(Arc::ptr_eq(&pinned, &bpm.fetch_page(0).unwrap()));
+        assert_eq!(2, pinned.pin_count());
+    }
+
+    #[test]
+    pub fn test_bpm_shared_across_threads() {
+        let dir = TempDir::new().unwrap();
+        let bpm = Arc::new(buffer_pool(&dir, 4));
+        for _ in 0..8 {
+            let page = bpm.new_page().unwrap();
+            page.write()[PAGE_HEADER_SIZE] = page.page_id() as u8;
+            assert!(bpm.unpin_page(page.page_id(), true).is_ok());
+        }
+
+        let handles: Vec<_> = (0..4).map(|thread| {
+            let bpm = bpm.clone();
+            std::thread::spawn(move || {
+                for i in 0..100u64 {
+                    let page_id = (i + thread) % 8;
+                    let page = bpm.fetch_page(page_id).unwrap();
+                    assert_eq!(page_id as u8, page.read()[PAGE_HEADER_SIZE]);
+                    bpm.unpin_page(page_id, false).unwrap();
+                }
+            })
+        }).collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    pub fn test_bpm_flushes_log_before_dirty_page() {
+        let dir = TempDir::new().unwrap();
+        let disk_manager = Arc::new(FileDiskManager::new(dir.path().join("test.db")).unwrap());
+        let log_manager = Arc::new(LogManager::new(dir.path().join("test.log")).unwrap());
+        let bpm = BufferPoolManager::with_log_manager(
+            1,
+            Arc::new(DiskScheduler::new(disk_manager, 1)),
+            Arc::new(LRUKReplacer::new(1, 2)),
+            Some(log_manager.clone()),
+        );
+
+        let page = bpm.new_page().unwrap();
+        let lsn = log_manager.append(1, INVALID_LSN, LogRecordBody::Update {
+            page_id: 0,
+            offset: PAGE_HEADER_SIZE as u16,
+            before: vec![0],
+            after: vec![9],
+        }).unwrap();
+        page.write()[PAGE_HEADER_SIZE] = 9;
+        page.set_page_lsn(lsn);
+        assert!(bpm.unpin_page(0, true).is_ok());
+        assert_eq!(INVALID_LSN, log_manager.flushed_lsn());
+
+        // evicting page 0 forces its log record out first
+        bpm.new_page().unwrap();
+        assert_eq!(lsn, log_manager.flushed_lsn());
+        assert!(bpm.unpin_page(1, false).is_ok());
+        assert_eq!(lsn, bpm.fetch_page(0).unwrap().page_lsn());
+
+        // with full page writes, a dirty page is logged whole on its way to disk
+        assert!(bpm.start_full_page_writes().is_ok());
+        bpm.fetch_page(0).unwrap().write()[PAGE_HEADER_SIZE + 1] = 7;
+        assert!(bpm.unpin_page(0, true).is_ok());
+        bpm.flush_page(0).unwrap();
+        bpm.flush_page(0).unwrap();
+        let records = log_manager.records().unwrap();
+        assert_eq!(3, records.len());
+        assert!(matches!(&records[1].body, LogRecordBody::Compensation { page_id: 0, after, .. } if after[1] == 7));
+        assert_eq!(LogRecordBody::Commit { commit_ts: 0 }, records[2].body);
+        assert_eq!(records[1].lsn, bpm.fetch_page(0).unwrap().page_lsn());
+        bpm.stop_full_page_writes();
+        assert!(BufferPoolManager::new(1, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(1, 2)))
+            .start_full_page_writes().is_err());
+    }
+
+    #[test]
+    pub fn test_bpm_resize_grows_and_shrinks() {
+        let dir = TempDir::new().unwrap();
+        let bpm = buffer_pool(&dir, 2);
+        for page_id in 0..2 {
+            bpm.new_page().unwrap().write()[PAGE_HEADER_SIZE] = page_id as u8 + 1;
+        }
+        assert!(matches!(bpm.new_page(), Err(CrabDBError::BufferPoolFull)));
+
+        // growing past the first segment of frames hands out the new ones
+        assert!(bpm.resize(100).is_ok());
+        assert_eq!(100, bpm.pool_size());
+        for _ in 2..100 {
+            bpm.new_page().unwrap();
+        }
+        assert!(matches!(bpm.new_page(), Err(CrabDBError::BufferPoolFull)));
+        for page_id in 2..100 {
+            assert!(bpm.unpin_page(page_id, false).is_ok());
+        }
+
+        // frame 1 holds pinned page 1, so the pool cannot shrink below 2 yet
+        assert!(bpm.resize(1).is_err());
+        assert_eq!(100, bpm.pool_size());
+        assert!(bpm.unpin_page(1, true).is_ok());
+        assert!(bpm.resize(1).is_ok());
+        assert_eq!(1, bpm.metrics_snapshot().pool_size);
+        assert!(matches!(bpm.resize(0), Err(CrabDBError::InvalidInput(_))));
+
+        // page 1 was written back when its frame was retired; page 0 is still pinned
+        assert!(matches!(bpm.fetch_page(1), Err(CrabDBError::BufferPoolFull)));
+        assert!(bpm.unpin_page(0, false).is_ok());
+        assert_eq!(2, bpm.fetch_page(1).unwrap().read()[PAGE_HEADER_SIZE]);
+        assert!(bpm.unpin_page(1, false).is_ok());
+    }
+
+    #[test]
+    pub fn test_bpm_warm_up_reloads_hot_pages() {
+        let dir = TempDir::new().unwrap();
+        let hot_pages = {
+            let bpm = buffer_pool(&dir, 4);
+            for page_id in 0..4 {
+                bpm.new_page().unwrap();
+                assert!(bpm.unpin_page(page_id, true).is_ok());
+            }
+            // 2 and 0 reach K accesses; of the rest, 3 was used last
+            for page_id in [2, 0] {
+                bpm.fetch_page(page_id).unwrap();
+                assert!(bpm.unpin_page(page_id, false).is_ok());
+            }
+            assert!(bpm.flush_all_pages().is_ok());
+            bpm.hot_pages()
+        };
+        assert_eq!(vec![2, 0, 3, 1], hot_pages);
+
+        // a smaller pool takes the hottest pages that fit, ranks them as before and lets them
+        // push out the pages it held
+        let bpm = buffer_pool(&dir, 3);
+        bpm.fetch_page(1).unwrap();
+        assert!(bpm.unpin_page(1, false).is_ok());
+        assert_eq!(3, bpm.warm_up(&hot_pages).unwrap());
+        assert_eq!(vec![2, 0, 3], bpm.hot_pages());
+        assert_eq!(0, bpm.warm_up(&[3, 7]).unwrap());
+    }
+
+    #[test]
+    pub fn test_bpm_replacer_debug_state_names_pages() {
+        let dir = TempDir::new().unwrap();
+        let bpm = buffer_pool(&dir, 2);
+        for page_id in 0..2 {
+            bpm.new_page().unwrap();
+            assert!(bpm.unpin_page(page_id, false).is_ok());
+        }
+        bpm.fetch_page(1).unwrap();
+
+        let frames = bpm.replacer_debug_state().frames;
+        assert_eq!(vec![Some(0), Some(1)], frames.iter().map(|frame| frame.page_id).collect::<Vec<_>>());
+        assert_eq!(vec![true, false], frames.iter().map(|frame| frame.evictable).collect::<Vec<_>>());
+        assert_eq!(vec![None, Some(2)], frames.iter().map(|frame| frame.k_distance).collect::<Vec<_>>());
+    }
+
+    #[test]
+    pub fn test_bpm_fetch_pages_and_prefetch() {
+        let dir = TempDir::new().unwrap();
+        let bpm = buffer_pool(&dir, 2);
+        for page_id in 0..4 {
+            bpm.new_page().unwrap().write()[PAGE_HEADER_SIZE] = page_id as u8 + 1;
+            assert!(bpm.unpin_page(page_id, true).is_ok());
+        }
+
+        // prefetching takes neither a frame nor a pin; the fetch picks up the read
+        assert!(bpm.prefetch(&[0, 1, 3]).is_ok());
+        let metrics = bpm.metrics_snapshot();
+        assert_eq!((0, 2, 4), (metrics.free_frames, metrics.evictable_frames, metrics.pins));
+        let pages = bpm.fetch_pages(&[1, 0]).unwrap();
+        assert_eq!(vec![2, 1], pages.iter().map(|page| page.read()[PAGE_HEADER_SIZE]).collect::<Vec<_>>());
+        assert_eq!(2, bpm.metrics_snapshot().disk_reads);
+
+        // resident pages are pinned again without touching the disk
+        for page_id in [0, 1] {
+            assert!(bpm.unpin_page(page_id, false).is_ok());
+        }
+        let hits = bpm.metrics_snapshot().hits;
+        assert_eq!(2, bpm.fetch_pages(&[0, 1]).unwrap().len());
+        let metrics = bpm.metrics_snapshot();
+        assert_eq!((hits + 2, 2, 0), (metrics.hits, metrics.disk_reads, metrics.evictable_frames));
+
+        // the pool cannot hold a third page, so the first two are handed back
+        for page_id in [0, 1] {
+            assert!(bpm.unpin_page(page_id, false).is_ok());
+        }
+        assert!(matches!(bpm.fetch_pages(&[2, 3, 0]), Err(CrabDBError::BufferPoolFull)));
+        assert_eq!(2, bpm.metrics_snapshot().evictable_frames);
+        assert_eq!(4, bpm.fetch_page(3).unwrap().read()[PAGE_HEADER_SIZE]);
+    }
+
+    // An LRU-K replacer whose batched accesses fail while `fail` is set.
+    struct FailingReplacer {
+        inner: LRUKReplacer,
+        fail: AtomicBool,
+    }
+
+    impl Replacer for FailingReplacer {
+        fn evict(&self) -> CrabDbResult<EvictionResponse> {
+            self.inner.evict()
+        }
+
+        fn record_access(&self, frame_id: FrameId) -> CrabDbResult<RecordAccessResponse> {
+            self.inner.record_access(frame_id)
+        }
+
+        fn record_accesses(&self, frame_ids: &[FrameId]) -> CrabDbResult<RecordAccessResponse> {
+            if self.fail.load(Ordering::SeqCst) {
+                return Err(CrabDBError::new("injected replacer failure".to_string()));
+            }
+            self.inner.record_accesses(frame_ids)
+        }
+
+        fn remove(&self, frame_id: FrameId) -> CrabDbResult<RemoveResponse> {
+            self.inner.remove(frame_id)
+        }
+
+        fn set_evictable(&self, frame_id: FrameId, set_evictable: bool) -> CrabDbResult<SetEvictableResponse> {
+            self.inner.set_evictable(frame_id, set_evictable)
+        }
+
+        fn size(&self) -> CrabDbResult<ReplacerSizeResponse> {
+            self.inner.size()
+        }
+    }
+
+    #[test]
+    pub fn test_bpm_fetch_pages_leaves_nothing_pinned_when_the_replacer_fails() {
+        let replacer = Arc::new(FailingReplacer { inner: LRUKReplacer::new(2, 2), fail: AtomicBool::new(false) });
+        let bpm = BufferPoolManager::new(2, Arc::new(MemoryDiskManager::new()), replacer.clone());
+        for page_id in 0..2 {
+            bpm.new_page().unwrap();
+            assert!(bpm.unpin_page(page_id, false).is_ok());
+        }
+
+        replacer.fail.store(true, Ordering::SeqCst);
+        assert!(bpm.fetch_pages(&[0, 1]).is_err());
+        let metrics = bpm.metrics_snapshot();
+        assert_eq!((2, 2), (metrics.pins, metrics.evictable_frames));
+
+        // a pinned page could not be deleted
+        replacer.fail.store(false, Ordering::SeqCst);
+        for page_id in 0..2 {
+            assert!(bpm.delete_page(page_id).is_ok());
+        }
+    }
+
+    #[test]
+    pub fn test_bpm_scan_strategy_keeps_the_working_set() {
+        let dir = TempDir::new().unwrap();
+        let disk_manager = Arc::new(FileDiskManager::new(dir.path().join("test.db")).unwrap());
+        let bpm = BufferPoolManager::new(8, disk_manager, Arc::new(LRUReplacer::new(8)));
+        for page_id in 0..12 {
+            bpm.new_page().unwrap();
+            assert!(bpm.unpin_page(page_id, true).is_ok());
+        }
+        for page_id in 0..4 {
+            bpm.fetch_page(page_id).unwrap();
+            assert!(bpm.unpin_page(page_id, false).is_ok());
+        }
+
+        // under plain LRU the scan would evict every page of the working set
+        let strategy = BufferAccessStrategy::new(2);
+        for page_id in 4..12 {
+            bpm.fetch_page_with_strategy(page_id, &strategy).unwrap();
+            assert!(bpm.unpin_page(page_id, false).is_ok());
+        }
+        let metrics = bpm.metrics_snapshot();
+        for page_id in 0..4 {
+            bpm.fetch_page(page_id).unwrap();
+            assert!(bpm.unpin_page(page_id, false).is_ok());
+        }
+        assert_eq!(metrics.hits + 4, bpm.metrics_snapshot().hits);
+
+        // a page still pinned leaves the ring instead of having its frame reused
+        let page = bpm.fetch_page_with_strategy(4, &strategy).unwrap();
+        for page_id in [5, 6] {
+            bpm.fetch_page_with_strategy(page_id, &strategy).unwrap();
+            assert!(bpm.unpin_page(page_id, false).is_ok());
+        }
+        assert_eq!((4, 1), (page.page_id(), page.pin_count()));
+        assert!(bpm.unpin_page(4, false).is_ok());
+    }
+
+    #[test]
+    pub fn test_bpm_eviction_listeners_see_evicted_pages() {
+        let dir = TempDir::new().unwrap();
+        let bpm = buffer_pool(&dir, 2);
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        {
+            let evicted = evicted.clone();
+            bpm.on_evict(move |page| evicted.lock().unwrap().push(*page));
+        }
+
+        for page_id in 0..3 {
+            bpm.new_page().unwrap();
+            assert!(bpm.unpin_page(page_id, page_id == 0).is_ok());
+        }
+        assert!(bpm.resize(1).is_ok());
+        // deleting a page is not an eviction
+        assert!(bpm.delete_page(2).is_ok());
+        assert_eq!(
+            vec![EvictedPage { frame_id: 0, page_id: 0, dirty: true }, EvictedPage { frame_id: 1, page_id: 1, dirty: false }],
+            *evicted.lock().unwrap(),
+        );
+    }
+
+    #[test]
+    pub fn test_bpm_permanently_pinned_page_is_never_evicted() {
+        let dir = TempDir::new().unwrap();
+        let bpm = buffer_pool(&dir, 2);
+        bpm.new_page().unwrap();
+        assert!(bpm.unpin_page(0, true).is_ok());
+        assert!(bpm.pin_permanently(0).is_ok());
+        assert!(bpm.pin_permanently(0).is_ok());
+        // the permanent pin is not the caller's to hand back
+        assert!(bpm.unpin_page(0, false).is_err());
+        let metrics = bpm.metrics_snapshot();
+        assert_eq!((1, 0), (metrics.permanent_frames, metrics.evictable_frames));
+
+        // pages come and go through the one frame left
+        for page_id in 1..4 {
+            bpm.new_page().unwrap();
+            assert!(bpm.unpin_page(page_id, false).is_ok());
+        }
+        assert_eq!(0, bpm.fetch_page(0).unwrap().page_id());
+        assert!(bpm.unpin_page(0, false).is_ok());
+        assert!(bpm.delete_page(0).is_err());
+
+        assert!(bpm.unpin_permanently(0).is_ok());
+        assert!(bpm.unpin_permanently(0).is_err());
+        assert_eq!((0, 2), (bpm.metrics_snapshot().permanent_frames, bpm.metrics_snapshot().evictable_frames));
+        assert!(bpm.delete_page(0).is_ok());
+    }
+
+    #[test]
+    pub fn test_bpm_metrics_count_hits_misses_and_evictions() {
+        let dir = TempDir::new().unwrap();
+        let bpm = buffer_pool(&dir, 2);
+
+        bpm.new_page().unwrap();
+        assert!(bpm.unpin_page(0, true).is_ok());
+        bpm.new_page().unwrap();
+        assert!(bpm.unpin_page(1, false).is_ok());
+        // a hit on page 1, then page 0 is evicted, written back and read in again by a miss
+        bpm.fetch_page(1).unwrap();
+        assert!(bpm.unpin_page(1, false).is_ok());
+        bpm.new_page().unwrap();
+        assert!(bpm.unpin_page(2, false).is_ok());
+        bpm.fetch_page(0).unwrap();
+
+        let metrics = bpm.metrics_snapshot();
+        assert_eq!((1, 1, 3), (metrics.hits, metrics.misses, metrics.new_pages));
+        assert_eq!((2, 1), (metrics.evictions, metrics.dirty_writes));
+        assert_eq!((1, 1), (metrics.disk_reads, metrics.disk_writes));
+        assert_eq!(5, metrics.pins);
+        assert_eq!((2, 0, 1), (metrics.pool_size, metrics.free_frames, metrics.evictable_frames));
+        assert_eq!(0.5, metrics.hit_ratio());
+        assert_eq!((1, 1), (metrics.disk_read_latency.count(), metrics.disk_write_latency.count()));
+        assert_eq!(metrics.disk_read_latency.max(), metrics.disk_read_latency.percentile(50.0));
+        assert_eq!((0, 0), (metrics.slow_disk_reads, metrics.slow_disk_writes));
+
+        // With no threshold to speak of, every read is slow.
+        bpm.set_slow_io_threshold(Some(Duration::ZERO));
+        assert!(bpm.unpin_page(0, false).is_ok());
+        bpm.fetch_page(2).unwrap();
+        let metrics = bpm.metrics_snapshot();
+        assert_eq!((2, 1, 0), (metrics.disk_read_latency.count(), metrics.slow_disk_reads, metrics.slow_disk_writes));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    pub fn test_bpm_traces_fetches_and_evictions() {
+        use std::sync::Mutex;
+
+        struct Captured(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Captured {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let dir = TempDir::new().unwrap();
+        let bpm = buffer_pool(&dir, 1);
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let writer = output.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::TRACE)
+            .with_writer(move || Captured(writer.clone()))
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            bpm.new_page().unwrap();
+            assert!(bpm.unpin_page(0, true).is_ok());
+            bpm.new_page().unwrap();
+            assert!(bpm.unpin_page(1, false).is_ok());
+            bpm.fetch_page(0).unwrap();
+        });
+
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("evicting page frame_id=0 page_id=0 dirty=true"), "{output}");
+        assert!(output.contains("evicting page frame_id=0 page_id=1 dirty=false"), "{output}");
+        let miss = output.lines().find(|line| line.contains("buffer pool miss frame_id=0")).unwrap();
+        assert!(miss.contains("fetch_frame{page_id=0 access_type=Lookup}"), "{miss}");
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::buffer_pool::{common::FrameId, eviction::replacer::Replacer};
+use crate::buffer_pool::eviction::ghost_list::GhostList;
+use crate::buffer_pool::eviction::lru_k::common::Timestamp;
+use crate::storage::common::PageId;
+use crate::types::{CrabDBError, CrabDbResult};
+use crate::buffer_pool::eviction::replacer::responses::*;
+
+// Adaptive Replacement Cache. Resident frames live in T1 (seen once recently) or T2 (seen at
+// least twice); B1/B2 remember the pages recently evicted from each. A hit in a ghost list
+// shifts the target size of T1 towards whichever side would have kept the page.
+pub struct ARCReplacer {
+    replacer_size: AtomicUsize,
+    state: RwLock<ARCReplacerState>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArcList {
+    Recent,
+    Frequent,
+}
+
+#[derive(Debug)]
+struct ArcEntry {
+    list: ArcList,
+    position: Timestamp,
+    page_id: Option<PageId>,
+    is_evictable: bool,
+}
+
+#[derive(Debug)]
+pub struct ARCReplacerState {
+    current_timestamp: Timestamp,
+    target_recent_size: usize,
+    num_evictable: usize,
+    entries: HashMap<FrameId, ArcEntry>,
+    recent: BTreeMap<Timestamp, FrameId>,
+    frequent: BTreeMap<Timestamp, FrameId>,
+    recent_ghost: GhostList,
+    frequent_ghost: GhostList,
+}
+
+impl ARCReplacerState {
+    fn list_mut(&mut self, list: ArcList) -> &mut BTreeMap<Timestamp, FrameId> {
+        match list {
+            ArcList::Recent => &mut self.recent,
+            ArcList::Frequent => &mut self.frequent,
+        }
+    }
+
+    fn next_timestamp(&mut self) -> Timestamp {
+        let timestamp = self.current_timestamp;
+        self.current_timestamp += 1;
+        timestamp
+    }
+
+    fn find_victim(&self, list: ArcList) -> Option<FrameId> {
+        let frames = match list {
+            ArcList::Recent => &self.recent,
+            ArcList::Frequent => &self.frequent,
+        };
+        frames.values()
+            .find(|frame_id| self.entries[*frame_id].is_evictable)
+            .copied()
+    }
+}
+
+impl ARCReplacer {
+    pub fn new(replacer_size: usize) -> Self {
+        ARCReplacer {
+            replacer_size: AtomicUsize::new(replacer_size),
+            state: RwLock::new(ARCReplacerState {
+                current_timestamp: 1,
+                target_recent_size: 0,
+                num_evictable: 0,
+                entries: HashMap::new(),
+                recent: BTreeMap::new(),
+                frequent: BTreeMap::new(),
+                recent_ghost: GhostList::default(),
+                frequent_ghost: GhostList::default(),
+            })
+        }
+    }
+
+    pub fn target_recent_size(&self) -> usize {
+        self.state.read().unwrap().target_recent_size
+    }
+
+    fn access(&self, frame_id: FrameId, page_id: Option<PageId>) -> CrabDbResult<RecordAccessResponse> {
+        let mut arc_state: RwLockWriteGuard<ARCReplacerState> = self.state.write().unwrap();
+        let timestamp = arc_state.next_timestamp();
+
+        // Resident hit: promote to the MRU end of T2.
+        if let Some(entry) = arc_state.entries.get_mut(&frame_id) {
+            let (old_list, old_position) = (entry.list, entry.position);
+            entry.list = ArcList::Frequent;
+            entry.position = timestamp;
+            if page_id.is_some() {
+                entry.page_id = page_id;
+            }
+            arc_state.list_mut(old_list).remove(&old_position);
+            arc_state.frequent.insert(timestamp, frame_id);
+            return Ok(RecordAccessResponse {  });
+        }
+
+        if arc_state.entries.len() >= self.replacer_size.load(Ordering::Relaxed) {
+            return Err(CrabDBError::FrameOutOfRange(frame_id));
+        }
+
+        let capacity = self.replacer_size.load(Ordering::Relaxed);
+        let recent_ghost_len = arc_state.recent_ghost.len();
+        let frequent_ghost_len = arc_state.frequent_ghost.len();
+        let list = match page_id {
+            Some(page_id) if arc_state.recent_ghost.remove(page_id) => {
+                let delta = (frequent_ghost_len / recent_ghost_len).max(1);
+                arc_state.target_recent_size = (arc_state.target_recent_size + delta).min(capacity);
+                ArcList::Frequent
+            },
+            Some(page_id) if arc_state.frequent_ghost.remove(page_id) => {
+                let delta = (recent_ghost_len / frequent_ghost_len).max(1);
+                arc_state.target_recent_size = arc_state.target_recent_size.saturating_sub(delta);
+                ArcList::Frequent
+            },
+            _ => {
+                let total = arc_state.entries.len() + recent_ghost_len + frequent_ghost_len;
+                if arc_state.recent.len() + recent_ghost_len >= capacity {
+                    arc_state.recent_ghost.pop_oldest();
+                } else if total >= 2 * capacity {
+                    arc_state.frequent_ghost.pop_oldest();
+                }
+                ArcList::Recent
+            },
+        };
+
+        arc_state.list_mut(list).insert(timestamp, frame_id);
+        arc_state.entries.insert(frame_id, ArcEntry {
+            list,
+            position: timestamp,
+            page_id,
+            is_evictable: false,
+        });
+        Ok(RecordAccessResponse {  })
+    }
+}
+
+impl Replacer for ARCReplacer {
+
+    fn record_access(&self, frame_id: FrameId) -> CrabDbResult<RecordAccessResponse> {
+        self.access(frame_id, None)
+    }
+
+    fn record_page_access(&self, frame_id: FrameId, page_id: PageId) -> CrabDbResult<RecordAccessResponse> {
+        self.access(frame_id, Some(page_id))
+    }
+
+    fn evict(&self) -> CrabDbResult<EvictionResponse> {
+        let mut arc_state: RwLockWriteGuard<ARCReplacerState> = self.state.write().unwrap();
+        let (preferred, fallback) = if arc_state.recent.len() >= arc_state.target_recent_size {
+            (ArcList::Recent, ArcList::Frequent)
+        } else {
+            (ArcList::Frequent, ArcList::Recent)
+        };
+        let victim = arc_state.find_victim(preferred).or_else(|| arc_state.find_victim(fallback));
+
+        if let Some(frame_id) = victim {
+            let entry = arc_state.entries.remove(&frame_id).expect("victim must have an entry");
+            arc_state.list_mut(entry.list).remove(&entry.position);
+            arc_state.num_evictable -= 1;
+            if let Some(page_id) = entry.page_id {
+                let timestamp = arc_state.next_timestamp();
+                match entry.list {
+                    ArcList::Recent => arc_state.recent_ghost.push(page_id, timestamp),
+                    ArcList::Frequent => arc_state.frequent_ghost.push(page_id, timestamp),
+                }
+            }
+        }
+        Ok(EvictionResponse::new(victim))
+    }
+
+    fn remove(&self, frame_id: FrameId) -> CrabDbResult<RemoveResponse> {
+        let mut arc_state: RwLockWriteGuard<ARCReplacerState> = self.state.write().unwrap();
+        match arc_state.entries.get(&frame_id) {
+            Some(entry) if entry.is_evictable => {
+                let (list, position) = (entry.list, entry.position);
+                arc_state.list_mut(list).remove(&position);
+                arc_state.entries.remove(&frame_id);
+                arc_state.num_evictable -= 1;
+            },
+            Some(_) => return Err(CrabDBError::NotEvictable(frame_id)),
+            None => return Err(CrabDBError::FrameNotFound(frame_id))
+        }
+        Ok(RemoveResponse {})
+    }
+
+    fn set_evictable(&self, frame_id: FrameId, set_evictable: bool) -> CrabDbResult<SetEvictableResponse> {
+        let mut arc_state: RwLockWriteGuard<ARCReplacerState> = self.state.write().unwrap();
+        let entry = match arc_state.entries.get_mut(&frame_id) {
+            Some(entry) => entry,
+            None => return Err(CrabDBError::FrameNotFound(frame_id)),
+        };
+        if entry.is_evictable != set_evictable {
+            entry.is_evictable = set_evictable;
+            if set_evictable {
+                arc_state.num_evictable += 1;
+            } else {
+                arc_state.num_evictable -= 1;
+            }
+        }
+        Ok(SetEvictableResponse {  })
+    }
+
+    fn set_replacer_size(&self, replacer_size: usize) -> CrabDbResult<ResizeResponse> {
+        let mut arc_state: RwLockWriteGuard<ARCReplacerState> = self.state.write().unwrap();
+        self.replacer_size.store(replacer_size, Ordering::Relaxed);
+        // The target and the ghost lists are bounded by the capacity, so a smaller cache
+        // forgets the oldest ghosts instead of trimming them one access at a time.
+        arc_state.target_recent_size = arc_state.target_recent_size.min(replacer_size);
+        while arc_state.recent_ghost.len() > replacer_size {
+            arc_state.recent_ghost.pop_oldest();
+        }
+        while arc_state.frequent_ghost.len() > replacer_size {
+            arc_state.frequent_ghost.pop_oldest();
+        }
+        Ok(ResizeResponse {})
+    }
+
+    fn size(&self) -> CrabDbResult<ReplacerSizeResponse> {
+        let arc_state: RwLockReadGuard<ARCReplacerState> = self.state.read().unwrap();
+        Ok(ReplacerSizeResponse::new(arc_state.num_evictable))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_pool::eviction::replacer::Replacer as _;
+    use crate::types::CrabDBError;
+    use super::ARCReplacer;
+
+    fn load(replacer: &ARCReplacer, frame_id: usize, page_id: u64) {
+        assert!(replacer.record_page_access(frame_id, page_id).is_ok());
+        assert!(replacer.set_evictable(frame_id, true).is_ok());
+    }
+
+    #[test]
+    pub fn test_arc_evicts_recent_before_frequent() {
+        let replacer = ARCReplacer::new(4);
+        for frame_id in 0..4 {
+            load(&replacer, frame_id, frame_id as u64);
+        }
+        // 0 and 1 are seen twice and move to T2
+        assert!(replacer.record_page_access(0, 0).is_ok());
+        assert!(replacer.record_page_access(1, 1).is_ok());
+        assert_eq!(4, replacer.size().unwrap().num_evictable_frames());
+
+        assert_eq!(Some(2), replacer.evict().unwrap().frame_id());
+        assert_eq!(Some(3), replacer.evict().unwrap().frame_id());
+        assert_eq!(Some(0), replacer.evict().unwrap().frame_id());
+        assert_eq!(Some(1), replacer.evict().unwrap().frame_id());
+        assert_eq!(None, replacer.evict().unwrap().frame_id());
+    }
+
+    #[test]
+    pub fn test_arc_scan_does_not_flush_frequent_pages() {
+        let replacer = ARCReplacer::new(4);
+        load(&replacer, 0, 100);
+        load(&replacer, 1, 101);
+        assert!(replacer.record_page_access(0, 100).is_ok());
+        assert!(replacer.record_page_access(1, 101).is_ok());
+
+        // a long scan cycles through the remaining two frames
+        let mut page_id = 0;
+        load(&replacer, 2, page_id);
+        load(&replacer, 3, page_id + 1);
+        for _ in 0..10 {
+            let victim = replacer.evict().unwrap().frame_id().unwrap();
+            assert!(victim == 2 || victim == 3, "scan evicted hot frame {victim}");
+            page_id += 2;
+            load(&replacer, victim, page_id);
+        }
+        assert_eq!(0, replacer.target_recent_size());
+    }
+
+    #[test]
+    pub fn test_arc_ghost_hit_adapts_target() {
+        let replacer = ARCReplacer::new(2);
+        load(&replacer, 0, 10);
+        load(&replacer, 1, 11);
+        assert_eq!(Some(0), replacer.evict().unwrap().frame_id());
+
+        // page 10 comes back while it is still remembered in B1
+        load(&replacer, 0, 10);
+        assert_eq!(1, replacer.target_recent_size());
+        // it was promoted straight to T2, so T1's page 11 is the next victim
+        assert_eq!(Some(1), replacer.evict().unwrap().frame_id());
+
+        load(&replacer, 1, 12);
+        assert!(replacer.record_page_access(1, 12).is_ok());
+        assert_eq!(Some(0), replacer.evict().unwrap().frame_id());
+        // page 10 comes back from B2, shrinking T1's target again
+        load(&replacer, 0, 10);
+        assert_eq!(0, replacer.target_recent_size());
+    }
+
+    #[test]
+    pub fn test_arc_remove_and_pinning() {
+        let replacer = ARCReplacer::new(2);
+        assert!(replacer.record_page_access(0, 1).is_ok());
+        assert_eq!(None, replacer.evict().unwrap().frame_id());
+        assert!(matches!(replacer.remove(0), Err(CrabDBError::NotEvictable(0))));
+        assert!(replacer.set_evictable(0, true).is_ok());
+        assert!(replacer.remove(0).is_ok());
+        assert_eq!(0, replacer.size().unwrap().num_evictable_frames());
+        assert!(matches!(replacer.remove(0), Err(CrabDBError::FrameNotFound(0))));
+    }
+}
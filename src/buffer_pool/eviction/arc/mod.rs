@@ -0,0 +1 @@
+pub mod arc_replacer;
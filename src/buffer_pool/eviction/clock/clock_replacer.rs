@@ -0,0 +1,164 @@
+use std::sync::{Mutex, MutexGuard};
+
+use crate::buffer_pool::{common::FrameId, eviction::replacer::Replacer};
+use crate::types::{CrabDBError, CrabDbResult};
+use crate::buffer_pool::eviction::replacer::responses::*;
+
+// CLOCK, the second-chance approximation of LRU. Frames sit on a circle in frame id order,
+// each with a reference bit set on access. The hand sweeps the circle clearing reference
+// bits and evicts the first evictable frame whose bit is already clear.
+pub struct ClockReplacer {
+    state: Mutex<ClockReplacerState>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ClockSlot {
+    referenced: bool,
+    is_evictable: bool,
+}
+
+#[derive(Debug)]
+pub struct ClockReplacerState {
+    // Indexed by frame id; None for frames the replacer is not tracking.
+    slots: Vec<Option<ClockSlot>>,
+    hand: usize,
+    num_evictable: usize,
+}
+
+impl ClockReplacer {
+    pub fn new(replacer_size: usize) -> Self {
+        ClockReplacer {
+            state: Mutex::new(ClockReplacerState {
+                slots: vec![None; replacer_size],
+                hand: 0,
+                num_evictable: 0,
+            }),
+        }
+    }
+}
+
+impl Replacer for ClockReplacer {
+    fn evict(&self) -> CrabDbResult<EvictionResponse> {
+        let mut state: MutexGuard<ClockReplacerState> = self.state.lock().unwrap();
+        if state.num_evictable == 0 {
+            return Ok(EvictionResponse::new(None));
+        }
+        // Two turns are enough: the first clears every reference bit it passes.
+        let num_slots = state.slots.len();
+        for _ in 0..2 * num_slots {
+            let frame_id = state.hand;
+            state.hand = (state.hand + 1) % num_slots;
+            match &mut state.slots[frame_id] {
+                Some(slot) if slot.is_evictable && slot.referenced => slot.referenced = false,
+                Some(slot) if slot.is_evictable => {
+                    state.slots[frame_id] = None;
+                    state.num_evictable -= 1;
+                    return Ok(EvictionResponse::new(Some(frame_id)));
+                },
+                _ => {},
+            }
+        }
+        Ok(EvictionResponse::new(None))
+    }
+
+    fn record_access(&self, frame_id: FrameId) -> CrabDbResult<RecordAccessResponse> {
+        let mut state: MutexGuard<ClockReplacerState> = self.state.lock().unwrap();
+        let slot = state.slots.get_mut(frame_id).ok_or(CrabDBError::FrameOutOfRange(frame_id))?;
+        match slot {
+            Some(slot) => slot.referenced = true,
+            None => *slot = Some(ClockSlot { referenced: true, is_evictable: false }),
+        }
+        Ok(RecordAccessResponse {})
+    }
+
+    fn remove(&self, frame_id: FrameId) -> CrabDbResult<RemoveResponse> {
+        let mut state: MutexGuard<ClockReplacerState> = self.state.lock().unwrap();
+        match state.slots.get(frame_id).copied().flatten() {
+            Some(slot) if slot.is_evictable => {
+                state.slots[frame_id] = None;
+                state.num_evictable -= 1;
+            },
+            Some(_) => return Err(CrabDBError::NotEvictable(frame_id)),
+            None => return Err(CrabDBError::FrameNotFound(frame_id)),
+        }
+        Ok(RemoveResponse {})
+    }
+
+    fn set_evictable(&self, frame_id: FrameId, set_evictable: bool) -> CrabDbResult<SetEvictableResponse> {
+        let mut state: MutexGuard<ClockReplacerState> = self.state.lock().unwrap();
+        let state = &mut *state;
+        let slot = match state.slots.get_mut(frame_id) {
+            Some(Some(slot)) => slot,
+            _ => return Err(CrabDBError::FrameNotFound(frame_id)),
+        };
+        if slot.is_evictable != set_evictable {
+            slot.is_evictable = set_evictable;
+            if set_evictable {
+                state.num_evictable += 1;
+            } else {
+                state.num_evictable -= 1;
+            }
+        }
+        Ok(SetEvictableResponse {})
+    }
+
+    // Slots are indexed by frame id, so the replacer only shrinks once the frames beyond the
+    // new size are gone.
+    fn set_replacer_size(&self, replacer_size: usize) -> CrabDbResult<ResizeResponse> {
+        let mut state: MutexGuard<ClockReplacerState> = self.state.lock().unwrap();
+        if let Some(frame_id) = (replacer_size..state.slots.len()).find(|&frame_id| state.slots[frame_id].is_some()) {
+            return Err(CrabDBError::FrameOutOfRange(frame_id));
+        }
+        state.slots.resize(replacer_size, None);
+        if state.hand >= replacer_size {
+            state.hand = 0;
+        }
+        Ok(ResizeResponse {})
+    }
+
+    fn size(&self) -> CrabDbResult<ReplacerSizeResponse> {
+        Ok(ReplacerSizeResponse::new(self.state.lock().unwrap().num_evictable))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_pool::eviction::replacer::Replacer as _;
+    use crate::types::CrabDBError;
+    use super::ClockReplacer;
+
+    #[test]
+    pub fn test_clock_replacer_gives_referenced_frames_a_second_chance() {
+        let replacer = ClockReplacer::new(4);
+        for frame_id in 0..4 {
+            assert!(replacer.record_access(frame_id).is_ok());
+            assert!(replacer.set_evictable(frame_id, true).is_ok());
+        }
+        assert!(matches!(replacer.record_access(4), Err(CrabDBError::FrameOutOfRange(4))));
+        assert_eq!(4, replacer.size().unwrap().num_evictable_frames());
+
+        // every bit is set, so the first turn clears them all and the hand comes back to 0
+        assert_eq!(Some(0), replacer.evict().unwrap().frame_id());
+        // 2 is touched again and survives the next pass; 3 is pinned
+        assert!(replacer.record_access(2).is_ok());
+        assert!(replacer.set_evictable(3, false).is_ok());
+        assert_eq!(Some(1), replacer.evict().unwrap().frame_id());
+        assert_eq!(Some(2), replacer.evict().unwrap().frame_id());
+        assert_eq!(None, replacer.evict().unwrap().frame_id());
+
+        assert!(matches!(replacer.remove(3), Err(CrabDBError::NotEvictable(3))));
+        assert!(replacer.set_evictable(3, true).is_ok());
+        assert!(replacer.remove(3).is_ok());
+        assert!(matches!(replacer.remove(3), Err(CrabDBError::FrameNotFound(3))));
+        assert_eq!(0, replacer.size().unwrap().num_evictable_frames());
+
+        // frames beyond the new size must be gone before the circle shrinks
+        assert!(replacer.set_replacer_size(6).is_ok());
+        assert!(replacer.record_access(5).is_ok());
+        assert!(matches!(replacer.set_replacer_size(2), Err(CrabDBError::FrameOutOfRange(5))));
+        assert!(replacer.set_evictable(5, true).is_ok());
+        assert!(replacer.remove(5).is_ok());
+        assert!(replacer.set_replacer_size(2).is_ok());
+        assert!(matches!(replacer.record_access(2), Err(CrabDBError::FrameOutOfRange(2))));
+    }
+}
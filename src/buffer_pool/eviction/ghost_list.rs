@@ -0,0 +1,40 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::buffer_pool::eviction::lru_k::common::Timestamp;
+use crate::storage::common::PageId;
+
+// Remembers the ids of recently evicted pages, oldest first, for policies that adapt to
+// pages coming back after eviction.
+#[derive(Debug, Default)]
+pub(crate) struct GhostList {
+    order: BTreeMap<Timestamp, PageId>,
+    positions: HashMap<PageId, Timestamp>,
+}
+
+impl GhostList {
+    pub(crate) fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub(crate) fn push(&mut self, page_id: PageId, timestamp: Timestamp) {
+        self.remove(page_id);
+        self.order.insert(timestamp, page_id);
+        self.positions.insert(page_id, timestamp);
+    }
+
+    pub(crate) fn remove(&mut self, page_id: PageId) -> bool {
+        match self.positions.remove(&page_id) {
+            Some(timestamp) => {
+                self.order.remove(&timestamp);
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub(crate) fn pop_oldest(&mut self) {
+        if let Some((_, page_id)) = self.order.pop_first() {
+            self.positions.remove(&page_id);
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::buffer_pool::{common::FrameId, eviction::replacer::{FrameDebugState, Replacer, ReplacerDebugState}};
+use crate::buffer_pool::eviction::lru_k::common::Timestamp;
+use crate::types::{CrabDBError, CrabDbResult};
+use crate::buffer_pool::eviction::replacer::responses::*;
+
+// Least-recently-used replacement, i.e. LRU-K with k = 1. Only evictable frames live in
+// `lru_order`, keyed by their last access, so the victim is always the first entry.
+pub struct LRUReplacer {
+    replacer_size: AtomicUsize,
+    state: RwLock<LRUReplacerState>,
+}
+
+#[derive(Debug)]
+struct LRUNode {
+    last_access: Timestamp,
+    is_evictable: bool,
+}
+
+#[derive(Debug)]
+pub struct LRUReplacerState {
+    current_timestamp: Timestamp,
+    nodes: HashMap<FrameId, LRUNode>,
+    lru_order: BTreeMap<Timestamp, FrameId>,
+}
+
+impl LRUReplacer {
+    pub fn new(replacer_size: usize) -> Self {
+        LRUReplacer {
+            replacer_size: AtomicUsize::new(replacer_size),
+            state: RwLock::new(LRUReplacerState {
+                current_timestamp: 1,
+                nodes: HashMap::new(),
+                lru_order: BTreeMap::new(),
+            })
+        }
+    }
+}
+
+impl LRUReplacer {
+    fn access_locked(&self, state: &mut LRUReplacerState, frame_id: FrameId) -> CrabDbResult<RecordAccessResponse> {
+        let current_timestamp = state.current_timestamp;
+        match state.nodes.get_mut(&frame_id) {
+            Some(node) => {
+                if node.is_evictable {
+                    state.lru_order.remove(&node.last_access);
+                    state.lru_order.insert(current_timestamp, frame_id);
+                }
+                node.last_access = current_timestamp;
+            },
+            None => {
+                if state.nodes.len() >= self.replacer_size.load(Ordering::Relaxed) {
+                    return Err(CrabDBError::FrameOutOfRange(frame_id))
+                }
+                state.nodes.insert(frame_id, LRUNode { last_access: current_timestamp, is_evictable: false });
+            }
+        }
+        state.current_timestamp += 1;
+        Ok(RecordAccessResponse {  })
+    }
+}
+
+impl Replacer for LRUReplacer {
+
+    fn record_access(&self, frame_id: FrameId) -> CrabDbResult<RecordAccessResponse> {
+        let mut lru_state: RwLockWriteGuard<LRUReplacerState> = self.state.write().unwrap();
+        self.access_locked(&mut lru_state, frame_id)
+    }
+
+    fn record_accesses(&self, frame_ids: &[FrameId]) -> CrabDbResult<RecordAccessResponse> {
+        let mut lru_state: RwLockWriteGuard<LRUReplacerState> = self.state.write().unwrap();
+        for &frame_id in frame_ids {
+            self.access_locked(&mut lru_state, frame_id)?;
+        }
+        Ok(RecordAccessResponse {  })
+    }
+
+    fn evict(&self) -> CrabDbResult<EvictionResponse> {
+        let mut lru_state: RwLockWriteGuard<LRUReplacerState> = self.state.write().unwrap();
+        let evicted_frame = lru_state.lru_order.pop_first().map(|(_, frame_id)| frame_id);
+        if let Some(frame_id) = evicted_frame {
+            lru_state.nodes.remove(&frame_id);
+        }
+        Ok(EvictionResponse::new(evicted_frame))
+    }
+
+    fn remove(&self, frame_id: FrameId) -> CrabDbResult<RemoveResponse> {
+        let mut lru_state: RwLockWriteGuard<LRUReplacerState> = self.state.write().unwrap();
+        match lru_state.nodes.get(&frame_id) {
+            Some(node) if node.is_evictable => {
+                let last_access = node.last_access;
+                lru_state.lru_order.remove(&last_access);
+                lru_state.nodes.remove(&frame_id);
+            },
+            Some(_) => return Err(CrabDBError::NotEvictable(frame_id)),
+            None => return Err(CrabDBError::FrameNotFound(frame_id))
+        }
+        Ok(RemoveResponse {})
+    }
+
+    fn set_evictable(&self, frame_id: FrameId, set_evictable: bool) -> CrabDbResult<SetEvictableResponse> {
+        let mut lru_state: RwLockWriteGuard<LRUReplacerState> = self.state.write().unwrap();
+        let state = &mut *lru_state;
+        let node = match state.nodes.get_mut(&frame_id) {
+            Some(node) => node,
+            None => return Err(CrabDBError::FrameNotFound(frame_id)),
+        };
+        if node.is_evictable != set_evictable {
+            node.is_evictable = set_evictable;
+            if set_evictable {
+                state.lru_order.insert(node.last_access, frame_id);
+            } else {
+                state.lru_order.remove(&node.last_access);
+            }
+        }
+        Ok(SetEvictableResponse {  })
+    }
+
+    fn set_replacer_size(&self, replacer_size: usize) -> CrabDbResult<ResizeResponse> {
+        self.replacer_size.store(replacer_size, Ordering::Relaxed);
+        Ok(ResizeResponse {})
+    }
+
+    fn size(&self) -> CrabDbResult<ReplacerSizeResponse> {
+        let lru_state: RwLockReadGuard<LRUReplacerState> = self.state.read().unwrap();
+        Ok(ReplacerSizeResponse::new(lru_state.lru_order.len()))
+    }
+
+    fn hottest_frames(&self) -> Vec<FrameId> {
+        let lru_state: RwLockReadGuard<LRUReplacerState> = self.state.read().unwrap();
+        let mut frames: Vec<_> = lru_state.nodes.iter().map(|(&frame_id, node)| (node.last_access, frame_id)).collect();
+        frames.sort_unstable_by(|a, b| b.cmp(a));
+        frames.into_iter().map(|(_, frame_id)| frame_id).collect()
+    }
+
+    // LRU is LRU-K with K = 1, so the K-distance is the time since the last access.
+    fn debug_state(&self) -> ReplacerDebugState {
+        let lru_state: RwLockReadGuard<LRUReplacerState> = self.state.read().unwrap();
+        let mut frames: Vec<_> = lru_state.nodes.iter().map(|(&frame_id, node)| FrameDebugState {
+            frame_id,
+            page_id: None,
+            evictable: node.is_evictable,
+            history_length: 1,
+            earliest_access: Some(node.last_access),
+            latest_access: Some(node.last_access),
+            k_distance: Some(lru_state.current_timestamp - node.last_access),
+        }).collect();
+        frames.sort_unstable_by_key(|frame| frame.frame_id);
+        ReplacerDebugState { current_timestamp: lru_state.current_timestamp, frames }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_pool::eviction::replacer::Replacer as _;
+    use crate::types::CrabDBError;
+    use super::LRUReplacer;
+
+    #[test]
+    pub fn test_lru_replacer_evicts_least_recent() {
+        let replacer = LRUReplacer::new(7);
+        for frame_id in 1..=6 {
+            assert!(replacer.record_access(frame_id).is_ok());
+        }
+        for frame_id in 1..=5 {
+            assert!(replacer.set_evictable(frame_id, true).is_ok());
+        }
+        assert_eq!(5, replacer.size().unwrap().num_evictable_frames());
+
+        // touching 1 makes it the most recently used
+        assert!(replacer.record_access(1).is_ok());
+        assert_eq!(Some(2), replacer.evict().unwrap().frame_id());
+        assert_eq!(Some(3), replacer.evict().unwrap().frame_id());
+        assert_eq!(Some(4), replacer.evict().unwrap().frame_id());
+        assert_eq!(2, replacer.size().unwrap().num_evictable_frames());
+
+        // 6 is pinned until set evictable
+        assert!(replacer.set_evictable(6, true).is_ok());
+        assert_eq!(Some(5), replacer.evict().unwrap().frame_id());
+        assert_eq!(Some(6), replacer.evict().unwrap().frame_id());
+        assert_eq!(Some(1), replacer.evict().unwrap().frame_id());
+        assert_eq!(None, replacer.evict().unwrap().frame_id());
+        assert_eq!(0, replacer.size().unwrap().num_evictable_frames());
+    }
+
+    #[test]
+    pub fn test_lru_replacer_set_evictable_toggles_size() {
+        let replacer = LRUReplacer::new(3);
+        assert!(replacer.record_access(1).is_ok());
+        assert!(replacer.set_evictable(1, true).is_ok());
+        assert!(replacer.set_evictable(1, true).is_ok());
+        assert_eq!(1, replacer.size().unwrap().num_evictable_frames());
+        assert!(replacer.set_evictable(1, false).is_ok());
+        assert_eq!(0, replacer.size().unwrap().num_evictable_frames());
+        assert_eq!(None, replacer.evict().unwrap().frame_id());
+        assert!(matches!(replacer.set_evictable(2, true), Err(CrabDBError::FrameNotFound(2))));
+    }
+
+    #[test]
+    pub fn test_lru_replacer_remove() {
+        let replacer = LRUReplacer::new(3);
+        assert!(replacer.record_access(1).is_ok());
+        assert!(matches!(replacer.remove(1), Err(CrabDBError::NotEvictable(1))));
+        assert!(replacer.set_evictable(1, true).is_ok());
+        assert!(replacer.remove(1).is_ok());
+        assert!(matches!(replacer.remove(1), Err(CrabDBError::FrameNotFound(1))));
+        assert_eq!(0, replacer.size().unwrap().num_evictable_frames());
+    }
+
+    #[test]
+    pub fn test_lru_replacer_rejects_frames_beyond_capacity() {
+        let replacer = LRUReplacer::new(2);
+        assert!(replacer.record_access(1).is_ok());
+        assert!(replacer.record_access(2).is_ok());
+        assert!(matches!(replacer.record_access(3), Err(CrabDBError::FrameOutOfRange(3))));
+    }
+
+    #[test]
+    pub fn test_lru_replacer_hottest_frames_most_recent_first() {
+        let replacer = LRUReplacer::new(4);
+        for frame_id in [2, 0, 3, 1, 0] {
+            assert!(replacer.record_access(frame_id).is_ok());
+        }
+        assert!(replacer.set_evictable(2, true).is_ok());
+        assert_eq!(vec![0, 1, 3, 2], replacer.hottest_frames());
+    }
+}
@@ -0,0 +1 @@
+pub mod lru_replacer;
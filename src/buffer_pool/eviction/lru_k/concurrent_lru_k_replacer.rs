@@ -0,0 +1,286 @@
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::buffer_pool::{common::FrameId, eviction::replacer::{FrameDebugState, Replacer, ReplacerDebugState}};
+use crate::types::{CrabDBError, CrabDbResult};
+use crate::buffer_pool::eviction::replacer::responses::*;
+use crate::buffer_pool::eviction::timestamp_clock::{LogicalClock, TimestampClock};
+
+use super::common::Timestamp;
+
+const EMPTY: u8 = 0;
+const PINNED: u8 = 1;
+const EVICTABLE: u8 = 2;
+
+// One slot per frame. The last k access timestamps live in a small ring of atomics, so
+// recording an access never takes a lock; a racing reader may see a history that is one
+// access stale, which only makes the k-distance approximate.
+struct FrameSlot {
+    status: AtomicU8,
+    accesses: AtomicU64,
+    history: Box<[AtomicU64]>,
+}
+
+impl FrameSlot {
+    fn new(max_accesses: usize) -> Self {
+        FrameSlot {
+            status: AtomicU8::new(EMPTY),
+            accesses: AtomicU64::new(0),
+            history: (0..max_accesses).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    fn record(&self, timestamp: Timestamp) {
+        let k = self.history.len() as u64;
+        let slot = self.accesses.fetch_add(1, Ordering::AcqRel) % k;
+        self.history[slot as usize].store(timestamp, Ordering::Release);
+    }
+
+    fn reset(&self) {
+        self.accesses.store(0, Ordering::Release);
+        for timestamp in self.history.iter() {
+            timestamp.store(0, Ordering::Release);
+        }
+    }
+
+    // Same ordering as LRUKReplacer: frames with fewer than k accesses first, then the one
+    // whose oldest retained access is earliest.
+    fn eviction_key(&self) -> (bool, Timestamp) {
+        let has_k_accesses = self.accesses.load(Ordering::Acquire) >= self.history.len() as u64;
+        let earliest = self.history.iter()
+            .map(|timestamp| timestamp.load(Ordering::Acquire))
+            .filter(|timestamp| *timestamp != 0)
+            .min()
+            .unwrap_or(0);
+        (has_k_accesses, earliest)
+    }
+}
+
+// An LRU-K approximation for very hot pools. Accesses and pin changes are single atomic
+// operations. Eviction sweeps forward from a shared clock hand, looks at up to `sample_size`
+// evictable frames and claims the best of them with a compare-and-swap, retrying if another
+// thread pinned or evicted it first. With `sample_size >= replacer_size` it picks exactly
+// what LRUKReplacer would.
+pub struct ConcurrentLRUKReplacer {
+    sample_size: usize,
+    clock: Arc<dyn TimestampClock>,
+    clock_hand: AtomicUsize,
+    num_evictable: AtomicUsize,
+    slots: Box<[FrameSlot]>,
+}
+
+impl ConcurrentLRUKReplacer {
+    pub fn new(replacer_size: usize, max_accesses: usize, sample_size: usize) -> Self {
+        ConcurrentLRUKReplacer {
+            sample_size: sample_size.max(1),
+            clock: Arc::new(LogicalClock::default()),
+            clock_hand: AtomicUsize::new(0),
+            num_evictable: AtomicUsize::new(0),
+            slots: (0..replacer_size).map(|_| FrameSlot::new(max_accesses.max(1))).collect(),
+        }
+    }
+
+    // As LRUKReplacer::with_clock.
+    pub fn with_clock(mut self, clock: Arc<dyn TimestampClock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn slot(&self, frame_id: FrameId) -> CrabDbResult<&FrameSlot> {
+        self.slots.get(frame_id)
+            .ok_or(CrabDBError::FrameOutOfRange(frame_id))
+    }
+
+    fn find_candidate(&self) -> Option<FrameId> {
+        let replacer_size = self.slots.len();
+        let start = self.clock_hand.fetch_add(1, Ordering::Relaxed) % replacer_size;
+        let mut best: Option<((bool, Timestamp), FrameId)> = None;
+        let mut sampled = 0;
+        for offset in 0..replacer_size {
+            let frame_id = (start + offset) % replacer_size;
+            let slot = &self.slots[frame_id];
+            if slot.status.load(Ordering::Acquire) != EVICTABLE {
+                continue;
+            }
+            let key = slot.eviction_key();
+            if best.is_none_or(|(best_key, _)| key < best_key) {
+                best = Some((key, frame_id));
+            }
+            sampled += 1;
+            if sampled >= self.sample_size {
+                break;
+            }
+        }
+        best.map(|(_, frame_id)| frame_id)
+    }
+}
+
+impl Replacer for ConcurrentLRUKReplacer {
+
+    fn record_access(&self, frame_id: FrameId) -> CrabDbResult<RecordAccessResponse> {
+        let slot = self.slot(frame_id)?;
+        let timestamp = self.clock.tick();
+        if slot.status.compare_exchange(EMPTY, PINNED, Ordering::AcqRel, Ordering::Acquire).is_ok() {
+            slot.reset();
+        }
+        slot.record(timestamp);
+        Ok(RecordAccessResponse {  })
+    }
+
+    fn evict(&self) -> CrabDbResult<EvictionResponse> {
+        if self.slots.is_empty() {
+            return Ok(EvictionResponse::new(None));
+        }
+        while self.num_evictable.load(Ordering::Acquire) > 0 {
+            let frame_id = match self.find_candidate() {
+                Some(frame_id) => frame_id,
+                None => break,
+            };
+            let claimed = self.slots[frame_id].status
+                .compare_exchange(EVICTABLE, EMPTY, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok();
+            if claimed {
+                self.num_evictable.fetch_sub(1, Ordering::AcqRel);
+                return Ok(EvictionResponse::new(Some(frame_id)));
+            }
+        }
+        Ok(EvictionResponse::new(None))
+    }
+
+    fn remove(&self, frame_id: FrameId) -> CrabDbResult<RemoveResponse> {
+        let slot = self.slot(frame_id)?;
+        match slot.status.compare_exchange(EVICTABLE, EMPTY, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => {
+                self.num_evictable.fetch_sub(1, Ordering::AcqRel);
+                Ok(RemoveResponse {})
+            },
+            Err(PINNED) => Err(CrabDBError::NotEvictable(frame_id)),
+            Err(_) => Err(CrabDBError::FrameNotFound(frame_id)),
+        }
+    }
+
+    fn set_evictable(&self, frame_id: FrameId, set_evictable: bool) -> CrabDbResult<SetEvictableResponse> {
+        let slot = self.slot(frame_id)?;
+        let (from, to) = if set_evictable { (PINNED, EVICTABLE) } else { (EVICTABLE, PINNED) };
+        match slot.status.compare_exchange(from, to, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => {
+                if set_evictable {
+                    self.num_evictable.fetch_add(1, Ordering::AcqRel);
+                } else {
+                    self.num_evictable.fetch_sub(1, Ordering::AcqRel);
+                }
+            },
+            Err(EMPTY) => return Err(CrabDBError::FrameNotFound(frame_id)),
+            // Already in the requested state.
+            Err(_) => (),
+        }
+        Ok(SetEvictableResponse {  })
+    }
+
+    fn size(&self) -> CrabDbResult<ReplacerSizeResponse> {
+        Ok(ReplacerSizeResponse::new(self.num_evictable.load(Ordering::Acquire)))
+    }
+
+    // Read without stopping accesses, so a frame may show up a timestamp behind.
+    fn debug_state(&self) -> ReplacerDebugState {
+        let current_timestamp = self.clock.now();
+        let frames = self.slots.iter().enumerate().filter_map(|(frame_id, slot)| {
+            let status = slot.status.load(Ordering::Acquire);
+            if status == EMPTY {
+                return None;
+            }
+            let history: Vec<_> = slot.history.iter()
+                .map(|timestamp| timestamp.load(Ordering::Acquire))
+                .filter(|timestamp| *timestamp != 0)
+                .collect();
+            let earliest_access = history.iter().min().copied();
+            Some(FrameDebugState {
+                frame_id,
+                page_id: None,
+                evictable: status == EVICTABLE,
+                history_length: history.len(),
+                earliest_access,
+                latest_access: history.iter().max().copied(),
+                k_distance: earliest_access
+                    .filter(|_| history.len() >= slot.history.len())
+                    .map(|earliest| current_timestamp.saturating_sub(earliest)),
+            })
+        }).collect();
+        ReplacerDebugState { current_timestamp, frames }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    use crate::buffer_pool::eviction::replacer::Replacer as _;
+    use crate::types::CrabDBError;
+    use super::ConcurrentLRUKReplacer;
+
+    #[test]
+    pub fn test_concurrent_lru_k_matches_lru_k_with_full_sample() {
+        let replacer = ConcurrentLRUKReplacer::new(7, 2, 7);
+        for frame_id in 1..=6 {
+            assert!(replacer.record_access(frame_id).is_ok());
+        }
+        for frame_id in 1..=5 {
+            assert!(replacer.set_evictable(frame_id, true).is_ok());
+        }
+        assert!(replacer.set_evictable(6, false).is_ok());
+        assert_eq!(5, replacer.size().unwrap().num_evictable_frames());
+
+        // frame 1 now has two accesses, everybody else has infinite k-distance
+        assert!(replacer.record_access(1).is_ok());
+        assert_eq!(Some(2), replacer.evict().unwrap().frame_id());
+        assert_eq!(Some(3), replacer.evict().unwrap().frame_id());
+        assert_eq!(Some(4), replacer.evict().unwrap().frame_id());
+        assert_eq!(Some(5), replacer.evict().unwrap().frame_id());
+        assert_eq!(Some(1), replacer.evict().unwrap().frame_id());
+        assert_eq!(None, replacer.evict().unwrap().frame_id());
+        assert_eq!(0, replacer.size().unwrap().num_evictable_frames());
+    }
+
+    #[test]
+    pub fn test_concurrent_lru_k_errors() {
+        let replacer = ConcurrentLRUKReplacer::new(2, 2, 2);
+        assert!(matches!(replacer.record_access(2), Err(CrabDBError::FrameOutOfRange(2))));
+        assert!(matches!(replacer.set_evictable(0, true), Err(CrabDBError::FrameNotFound(0))));
+        assert!(replacer.record_access(0).is_ok());
+        assert!(matches!(replacer.remove(0), Err(CrabDBError::NotEvictable(0))));
+        assert!(replacer.set_evictable(0, true).is_ok());
+        assert!(replacer.remove(0).is_ok());
+        assert!(matches!(replacer.remove(0), Err(CrabDBError::FrameNotFound(0))));
+    }
+
+    #[test]
+    pub fn test_concurrent_lru_k_never_evicts_twice() {
+        let replacer = Arc::new(ConcurrentLRUKReplacer::new(256, 2, 8));
+        for frame_id in 0..256 {
+            replacer.record_access(frame_id).unwrap();
+            replacer.set_evictable(frame_id, true).unwrap();
+        }
+
+        let handles: Vec<_> = (0..4).map(|_| {
+            let replacer = replacer.clone();
+            std::thread::spawn(move || {
+                let mut evicted = Vec::new();
+                while let Some(frame_id) = replacer.evict().unwrap().frame_id() {
+                    evicted.push(frame_id);
+                }
+                evicted
+            })
+        }).collect();
+
+        let mut seen = HashSet::new();
+        for handle in handles {
+            for frame_id in handle.join().unwrap() {
+                assert!(seen.insert(frame_id), "frame {frame_id} was evicted twice");
+            }
+        }
+        assert_eq!(256, seen.len());
+        assert_eq!(0, replacer.size().unwrap().num_evictable_frames());
+    }
+}
@@ -13,7 +13,7 @@ pub struct LRUKNode {
 impl LRUKNode {
     pub fn new(max_accesses: usize, frame_id: FrameId) -> Self {
         LRUKNode {
-            max_accesses: max_accesses,
+            max_accesses,
             history: VecDeque::new(),
             _frame_id: frame_id,
             is_evictable: false,
@@ -28,6 +28,10 @@ impl LRUKNode {
         self.history.front()
     }
 
+    pub fn back_of_history(&self) -> Option<&Timestamp> {
+        self.history.back()
+    }
+
     pub fn record_history(&mut self, timestamp: Timestamp) {
         self.history.push_back(timestamp);
         if self.history.len() > self.max_accesses {
@@ -35,6 +39,27 @@ impl LRUKNode {
         }
     }
 
+    // An access too soon after the last one to be independent of it: it moves the last entry
+    // forward instead of adding one.
+    pub fn record_correlated(&mut self, timestamp: Timestamp) {
+        match self.history.back_mut() {
+            Some(last) => *last = timestamp,
+            None => self.history.push_back(timestamp),
+        }
+    }
+
+    // Forgets accesses before `cutoff`, all of them or all but the latest. Returns whether
+    // any were dropped.
+    pub fn forget_before(&mut self, cutoff: Timestamp, keep_latest: bool) -> bool {
+        let keep = usize::from(keep_latest);
+        let mut forgot = false;
+        while self.history.len() > keep && self.history.front().is_some_and(|&timestamp| timestamp < cutoff) {
+            self.history.pop_front();
+            forgot = true;
+        }
+        forgot
+    }
+
     pub fn is_evictable(&self) -> bool {
         self.is_evictable
     }
@@ -1,110 +1,171 @@
-use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
-use std::collections::HashMap;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use crate::buffer_pool::{common::FrameId, eviction::replacer::Replacer};
+use crate::buffer_pool::{common::FrameId, eviction::replacer::{AccessType, FrameDebugState, Replacer, ReplacerDebugState}};
+use crate::storage::common::PageId;
 use crate::types::{CrabDBError, CrabDbResult};
 use crate::buffer_pool::eviction::replacer::responses::*;
+use crate::buffer_pool::eviction::timestamp_clock::{LogicalClock, TimestampClock};
 
 use super::{common::Timestamp, lru_k_node::LRUKNode};
 
+// Periods are measured on the replacer's clock, which by default is its own and ticks once
+// per recorded access; see `with_clock`.
 pub struct LRUKReplacer {
     max_accesses: usize,
-    replacer_size: usize,
+    clock: Arc<dyn TimestampClock>,
+    // Accesses this soon after the previous one are correlated with it, e.g. a transaction
+    // reading a page and then updating it, and count as one. Off if 0.
+    correlated_period: Timestamp,
+    // History older than this is forgotten, so pages that were hot long ago do not keep
+    // K accesses forever. Off if 0.
+    max_age: Timestamp,
+    replacer_size: AtomicUsize,
     state: RwLock<LRUKReplacerState>,
 }
 
+// Orders evictable frames by backward k-distance, largest first: frames with fewer than k
+// accesses (infinite distance) sort before the rest, and within each group the frame whose
+// oldest retained access is earliest wins.
+type EvictionKey = (bool, Timestamp, FrameId);
+
 #[derive(Debug)]
 pub struct LRUKReplacerState {
     current_size: usize,
-    current_timestamp: Timestamp,
     node_store: HashMap<FrameId, LRUKNode>,
+    eviction_order: BTreeSet<EvictionKey>,
 }
 
 impl LRUKReplacer {
     pub fn new(replacer_size: usize, max_accesses: usize) -> Self {
+        Self::with_aging(replacer_size, max_accesses, 0, 0)
+    }
+
+    pub fn with_aging(replacer_size: usize, max_accesses: usize, correlated_period: Timestamp, max_age: Timestamp) -> Self {
         LRUKReplacer {
-            replacer_size,
-            max_accesses: max_accesses,
+            replacer_size: AtomicUsize::new(replacer_size),
+            max_accesses,
+            clock: Arc::new(LogicalClock::default()),
+            correlated_period,
+            max_age,
             state: RwLock::new(LRUKReplacerState {
                 current_size: 0,
-                current_timestamp: 1,
                 node_store: HashMap::new(),
+                eviction_order: BTreeSet::new(),
             })
         }
     }
-}
 
-impl Replacer for LRUKReplacer {
-   
-    fn record_access(&mut self, frame_id: FrameId) -> CrabDbResult<RecordAccessResponse> {
+    // Timestamps accesses with `clock`, e.g. one shared with other replacers so theirs
+    // compare, or one telling time so periods are measured in it.
+    pub fn with_clock(mut self, clock: Arc<dyn TimestampClock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn eviction_key(&self, frame_id: FrameId, node: &LRUKNode) -> EvictionKey {
+        let earliest_timestamp = *node.front_of_history().unwrap_or_else(|| panic!("Can never not have a history when the node has been accessed and present {frame_id}"));
+        (node.history_length() >= self.max_accesses, earliest_timestamp, frame_id)
+    }
+
+    // Without `promote` an access only brings a new frame in, with a single entry of history,
+    // and leaves the history of a tracked frame alone.
+    fn access(&self, frame_id: FrameId, promote: bool) -> CrabDbResult<RecordAccessResponse> {
         let mut lruk_state: RwLockWriteGuard<LRUKReplacerState> = self.state.write().unwrap();
-        let current_timestamp = lruk_state.current_timestamp;
-        let node = lruk_state.node_store.get_mut(&frame_id);
+        self.access_locked(&mut lruk_state, frame_id, promote)
+    }
+
+    fn access_locked(&self, state: &mut LRUKReplacerState, frame_id: FrameId, promote: bool) -> CrabDbResult<RecordAccessResponse> {
+        let current_timestamp = self.clock.tick();
+        let node = state.node_store.get_mut(&frame_id);
         match node {
+            Some(_) if !promote => {},
             Some(node) => {
-                node.record_history(current_timestamp);
+                if node.is_evictable() {
+                    state.eviction_order.remove(&self.eviction_key(frame_id, node));
+                }
+                if self.max_age > 0 {
+                    node.forget_before(current_timestamp.saturating_sub(self.max_age), false);
+                }
+                // On a clock telling time accesses can share a timestamp, so 0 is checked for.
+                let correlated = self.correlated_period > 0 && node.back_of_history()
+                    .is_some_and(|&last| current_timestamp.saturating_sub(last) <= self.correlated_period);
+                if correlated {
+                    node.record_correlated(current_timestamp);
+                } else {
+                    node.record_history(current_timestamp);
+                }
+                if node.is_evictable() {
+                    state.eviction_order.insert(self.eviction_key(frame_id, node));
+                }
             },
             None => {
-                if lruk_state.node_store.len() > self.replacer_size {
-                    return Err(CrabDBError::new("Frame cannot exceed replacer size".into()))
+                if state.node_store.len() > self.replacer_size.load(Ordering::Relaxed) {
+                    return Err(CrabDBError::FrameOutOfRange(frame_id))
                 }
                 let mut node = LRUKNode::new(self.max_accesses, frame_id);
                 node.record_history(current_timestamp);
-                lruk_state.node_store.insert(frame_id, node);
+                state.node_store.insert(frame_id, node);
             }
         }
-        lruk_state.current_timestamp += 1;
         Ok(RecordAccessResponse {  })
     }
 
-    fn evict(&mut self) -> CrabDbResult<EvictionResponse> {
-        
-        let mut evicted_frame: Option<FrameId> = None;
-        let mut max_k_distance = 0;
-        {
-            let lruk_state: RwLockReadGuard<LRUKReplacerState> = self.state.read().unwrap();
-
-            let current_timestamp = lruk_state.current_timestamp;
+    // Frames with K accesses whose oldest is past `max_age` lose what is too old, which puts
+    // them among the frames with fewer than K accesses. They sort first within their group,
+    // so only those need looking at.
+    fn forget_stale_history(&self, state: &mut LRUKReplacerState) {
+        if self.max_age == 0 {
+            return;
+        }
+        let cutoff = self.clock.now().saturating_sub(self.max_age);
+        while let Some(&key) = state.eviction_order.range((true, 0, 0)..).next() {
+            let (_, earliest, frame_id) = key;
+            if earliest >= cutoff {
+                break;
+            }
+            state.eviction_order.remove(&key);
+            let node = state.node_store.get_mut(&frame_id).unwrap();
+            node.forget_before(cutoff, true);
+            state.eviction_order.insert(self.eviction_key(frame_id, node));
+        }
+    }
+}
 
-            for (frame_id, node) in lruk_state.node_store.iter() {
-                if !node.is_evictable() {
-                    continue
-                }
-                
-                let node_history_length = node.history_length();
-                if node_history_length == 0 {
-                    panic!("How is the node there in the map if it's history length() is 0?, frame_id {}, Node details: {:?}", frame_id, node);
-                }
+impl Replacer for LRUKReplacer {
+   
+    fn record_access(&self, frame_id: FrameId) -> CrabDbResult<RecordAccessResponse> {
+        self.access(frame_id, true)
+    }
 
-                let start_distance = if node_history_length >= self.max_accesses {
-                    current_timestamp
-                } else {
-                    u64::MAX
-                };
+    // Each access gets the next timestamp, as if they had been recorded one by one.
+    fn record_accesses(&self, frame_ids: &[FrameId]) -> CrabDbResult<RecordAccessResponse> {
+        let mut lruk_state: RwLockWriteGuard<LRUKReplacerState> = self.state.write().unwrap();
+        for &frame_id in frame_ids {
+            self.access_locked(&mut lruk_state, frame_id, true)?;
+        }
+        Ok(RecordAccessResponse {  })
+    }
 
-                let node_earliest_timestamp = node.front_of_history().expect(format!("Can never not have a history when the node has been accessed and present {frame_id}").as_str());
-                
-                let backwards_k_distance = start_distance - node_earliest_timestamp;
+    // Scans do not promote: a page a scan reads once per tuple would otherwise reach K
+    // accesses and compete with the pages that are really used often.
+    fn record_access_with_type(&self, frame_id: FrameId, _page_id: PageId, access_type: AccessType) -> CrabDbResult<RecordAccessResponse> {
+        self.access(frame_id, access_type != AccessType::Scan)
+    }
 
-                if backwards_k_distance > max_k_distance {
-                    evicted_frame = Some(*frame_id);
-                    max_k_distance = backwards_k_distance;
-                }
-            }
-        }
-        
+    fn evict(&self) -> CrabDbResult<EvictionResponse> {
+        let mut lruk_state: RwLockWriteGuard<LRUKReplacerState> = self.state.write().unwrap();
+        self.forget_stale_history(&mut lruk_state);
+        let evicted_frame = lruk_state.eviction_order.pop_first().map(|(_, _, frame_id)| frame_id);
         if let Some(frame) = evicted_frame {
-            match self.remove(frame) {
-                Ok(_) => (),
-                Err(e) => return Err(CrabDBError::new(format!("Failed to remove evicted frame from replacer: {e}").into()))
-            }
-        } 
-
+            lruk_state.node_store.remove(&frame);
+            lruk_state.current_size -= 1;
+        }
         Ok(EvictionResponse::new(evicted_frame))
-        
     }
 
-    fn remove(&mut self, frame_id: FrameId) -> CrabDbResult<RemoveResponse> {
+    fn remove(&self, frame_id: FrameId) -> CrabDbResult<RemoveResponse> {
         let mut lruk_state: RwLockWriteGuard<LRUKReplacerState> = self.state.write().unwrap();
         let node = lruk_state.node_store.get(&frame_id);
 
@@ -112,23 +173,26 @@ impl Replacer for LRUKReplacer {
             Some(node) => {
                 match node.is_evictable() {
                     true => {
+                        let key = self.eviction_key(frame_id, node);
+                        lruk_state.eviction_order.remove(&key);
                         lruk_state.node_store.remove(&frame_id);
                         lruk_state.current_size -= 1;
                     },
-                    false => return Err(CrabDBError::new("Frame is marked as not evictable".into()))
+                    false => return Err(CrabDBError::NotEvictable(frame_id))
                 }
             },
-            None => return Err(CrabDBError::new("Frame doesn't exist; invalid remove command".into()))
+            None => return Err(CrabDBError::FrameNotFound(frame_id))
         }
         
         Ok(RemoveResponse {})
     }
 
-    fn set_evictable(&mut self, frame_id: FrameId, set_evictable: bool) -> CrabDbResult<SetEvictableResponse> {
+    fn set_evictable(&self, frame_id: FrameId, set_evictable: bool) -> CrabDbResult<SetEvictableResponse> {
         let mut lruk_state: RwLockWriteGuard<LRUKReplacerState> = self.state.write().unwrap();
-        let node = lruk_state.node_store.get_mut(&frame_id);
+        let state = &mut *lruk_state;
+        let node = state.node_store.get_mut(&frame_id);
         if node.is_none() {
-            return Err(CrabDBError::new("Frame doesn't exist to set_evictable".into()));
+            return Err(CrabDBError::FrameNotFound(frame_id));
         } 
         
         if let Some(node) = node {
@@ -138,17 +202,16 @@ impl Replacer for LRUKReplacer {
                         true => (),
                         false => {
                             node.set_evictable(false);
-                            lruk_state.current_size -= 1;
+                            state.eviction_order.remove(&self.eviction_key(frame_id, node));
+                            state.current_size -= 1;
                         },
                     }
                 },
                 false => {
-                    match set_evictable {
-                        true => {
-                            node.set_evictable(true);
-                            lruk_state.current_size += 1;
-                        },
-                        false => (),
+                    if set_evictable {
+                        node.set_evictable(true);
+                        state.eviction_order.insert(self.eviction_key(frame_id, node));
+                        state.current_size += 1;
                     }
                 },
             } 
@@ -157,16 +220,57 @@ impl Replacer for LRUKReplacer {
         Ok(SetEvictableResponse {  })
     }
 
+    fn set_replacer_size(&self, replacer_size: usize) -> CrabDbResult<ResizeResponse> {
+        self.replacer_size.store(replacer_size, Ordering::Relaxed);
+        Ok(ResizeResponse {})
+    }
+
     fn size(&self) -> CrabDbResult<ReplacerSizeResponse> {
         let lruk_state: RwLockReadGuard<LRUKReplacerState> = self.state.read().unwrap();
         Ok(ReplacerSizeResponse::new(lruk_state.current_size))
     }
+
+    fn hottest_frames(&self) -> Vec<FrameId> {
+        let lruk_state: RwLockReadGuard<LRUKReplacerState> = self.state.read().unwrap();
+        let mut keys: Vec<_> = lruk_state.node_store.iter()
+            .map(|(&frame_id, node)| self.eviction_key(frame_id, node))
+            .collect();
+        keys.sort_unstable_by(|a, b| b.cmp(a));
+        keys.into_iter().map(|(_, _, frame_id)| frame_id).collect()
+    }
+
+    fn debug_state(&self) -> ReplacerDebugState {
+        let lruk_state: RwLockReadGuard<LRUKReplacerState> = self.state.read().unwrap();
+        let current_timestamp = self.clock.now();
+        let mut frames: Vec<_> = lruk_state.node_store.iter().map(|(&frame_id, node)| {
+            let earliest_access = node.front_of_history().copied();
+            FrameDebugState {
+                frame_id,
+                page_id: None,
+                evictable: node.is_evictable(),
+                history_length: node.history_length(),
+                earliest_access,
+                latest_access: node.back_of_history().copied(),
+                k_distance: earliest_access
+                    .filter(|_| node.history_length() >= self.max_accesses)
+                    .map(|earliest| current_timestamp.saturating_sub(earliest)),
+            }
+        }).collect();
+        frames.sort_unstable_by_key(|frame| frame.frame_id);
+        ReplacerDebugState { current_timestamp, frames }
+    }
     
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::buffer_pool::eviction::replacer::Replacer as _;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::buffer_pool::eviction::replacer::{AccessType, Replacer as _};
+    use crate::buffer_pool::eviction::timestamp_clock::MonotonicClock;
+    use crate::platform::ManualClock;
+    use crate::types::CrabDBError;
     use super::LRUKReplacer;
 
     #[test]
@@ -177,7 +281,7 @@ mod tests {
 
     #[test]
     pub fn test_lru_record_access_set_evictable_basic() {
-        let mut replacer: LRUKReplacer = LRUKReplacer::new(7, 2);
+        let replacer: LRUKReplacer = LRUKReplacer::new(7, 2);
         assert!(replacer.record_access(1).is_ok());
         assert!(replacer.record_access(2).is_ok());
         assert!(replacer.record_access(3).is_ok());
@@ -194,13 +298,13 @@ mod tests {
 
     #[test]
     pub fn test_lru_record_remove_basic() {
-        let mut replacer: LRUKReplacer = LRUKReplacer::new(7, 2);
+        let replacer: LRUKReplacer = LRUKReplacer::new(7, 2);
         assert!(replacer.record_access(1).is_ok());
         
         let rm = replacer.remove(1);
         match rm {
             Ok(_) => panic!("Test should have thrown an error!"),
-            Err(e) => assert_eq!("Frame is marked as not evictable", e.message())
+            Err(e) => assert!(matches!(e, CrabDBError::NotEvictable(1)))
         }
 
         assert!(replacer.set_evictable(1, true).is_ok());
@@ -209,17 +313,14 @@ mod tests {
 
     #[test]
     pub fn test_lru_record_remove_non_existent_frame() {
-        let mut replacer: LRUKReplacer = LRUKReplacer::new(7, 2);
+        let replacer: LRUKReplacer = LRUKReplacer::new(7, 2);
         
-        assert_eq!(
-            "Frame doesn't exist; invalid remove command",
-            replacer.remove(1).unwrap_err().message()
-        );
+        assert!(matches!(replacer.remove(1), Err(CrabDBError::FrameNotFound(1))));
     }
 
     #[test]
     pub fn test_lru_record_evict_non_existent_frame() {
-        let mut replacer: LRUKReplacer = LRUKReplacer::new(7, 2);
+        let replacer: LRUKReplacer = LRUKReplacer::new(7, 2);
         
         assert_eq!(
             None,
@@ -227,9 +328,171 @@ mod tests {
         );
     }
 
+    #[test]
+    pub fn test_lru_k_scan_accesses_do_not_promote() {
+        let replacer: LRUKReplacer = LRUKReplacer::new(4, 2);
+        // frame 0 is hot; 1 and 2 are read over and over by a scan
+        assert!(replacer.record_access(0).is_ok());
+        assert!(replacer.record_access(0).is_ok());
+        for _ in 0..3 {
+            for frame_id in 1..3 {
+                assert!(replacer.record_access_with_type(frame_id, frame_id as u64, AccessType::Scan).is_ok());
+            }
+        }
+        // a lookup counts as usual
+        assert!(replacer.record_access_with_type(3, 3, AccessType::Lookup).is_ok());
+        assert!(replacer.record_access_with_type(3, 3, AccessType::Index).is_ok());
+        for frame_id in 0..4 {
+            assert!(replacer.set_evictable(frame_id, true).is_ok());
+        }
+        // the scanned frames keep a single access and go first
+        assert_eq!(Some(1), replacer.evict().unwrap().frame_id());
+        assert_eq!(Some(2), replacer.evict().unwrap().frame_id());
+        assert_eq!(Some(0), replacer.evict().unwrap().frame_id());
+        assert_eq!(Some(3), replacer.evict().unwrap().frame_id());
+    }
+
+    #[test]
+    pub fn test_lru_k_debug_state_explains_eviction_order() {
+        let replacer: LRUKReplacer = LRUKReplacer::new(4, 2);
+        for frame_id in [0, 1, 0, 2] {
+            assert!(replacer.record_access(frame_id).is_ok());
+        }
+        assert!(replacer.set_evictable(0, true).is_ok());
+        assert!(replacer.set_evictable(1, true).is_ok());
+
+        let state = replacer.debug_state();
+        assert_eq!(5, state.current_timestamp);
+        let frame_0 = &state.frames[0];
+        assert_eq!((0, true, 2), (frame_0.frame_id, frame_0.evictable, frame_0.history_length));
+        assert_eq!((Some(1), Some(3), Some(4)), (frame_0.earliest_access, frame_0.latest_access, frame_0.k_distance));
+        // a single access is infinitely far back, so frame 1 goes first
+        let frame_1 = &state.frames[1];
+        assert_eq!((1, Some(2), None), (frame_1.history_length, frame_1.earliest_access, frame_1.k_distance));
+        assert!(!state.frames[2].evictable);
+        assert_eq!(3, state.frames.len());
+        assert_eq!(Some(1), replacer.evict().unwrap().frame_id());
+    }
+
+    #[test]
+    pub fn test_lru_k_evicts_infinite_distance_frames_by_earliest_access() {
+        let replacer: LRUKReplacer = LRUKReplacer::new(4, 3);
+        // frame 2 reaches K accesses; the others stay infinitely far back
+        for frame_id in [0, 1, 0, 2, 2, 2, 3] {
+            assert!(replacer.record_access(frame_id).is_ok());
+        }
+        for frame_id in 0..4 {
+            assert!(replacer.set_evictable(frame_id, true).is_ok());
+        }
+        // frame 0 was touched again after frame 1, but its first access is the earlier one
+        let evicted: Vec<_> = (0..5).map(|_| replacer.evict().unwrap().frame_id()).collect();
+        assert_eq!(vec![Some(0), Some(1), Some(3), Some(2), None], evicted);
+    }
+
+    #[test]
+    pub fn test_lru_k_breaks_timestamp_ties_by_frame_id() {
+        let time = Arc::new(ManualClock::new(0));
+        let clock = MonotonicClock::new(Duration::from_millis(1)).with_source(time.clone());
+        let replacer: LRUKReplacer = LRUKReplacer::new(6, 2).with_clock(Arc::new(clock));
+        for frame_id in [2, 1, 0] {
+            assert!(replacer.record_access(frame_id).is_ok());
+        }
+        time.advance(Duration::from_millis(1));
+        for frame_id in [5, 4, 5, 4] {
+            assert!(replacer.record_access(frame_id).is_ok());
+        }
+        for frame_id in [0, 1, 2, 4, 5] {
+            assert!(replacer.set_evictable(frame_id, true).is_ok());
+        }
+        let evicted: Vec<_> = (0..5).map(|_| replacer.evict().unwrap().frame_id().unwrap()).collect();
+        assert_eq!(vec![0, 1, 2, 4, 5], evicted);
+    }
+
+    #[test]
+    pub fn test_lru_k_set_evictable_keeps_the_order_in_step() {
+        let replacer: LRUKReplacer = LRUKReplacer::new(4, 2);
+        for frame_id in 0..3 {
+            assert!(replacer.record_access(frame_id).is_ok());
+            assert!(replacer.set_evictable(frame_id, true).is_ok());
+        }
+        // an access while pinned reaches K and is ordered by once the frame is evictable again
+        assert!(replacer.set_evictable(0, false).is_ok());
+        assert_eq!(2, replacer.size().unwrap().num_evictable_frames());
+        assert!(replacer.record_access(0).is_ok());
+        assert!(replacer.set_evictable(0, true).is_ok());
+        assert!(replacer.set_evictable(0, true).is_ok());
+        assert_eq!(3, replacer.size().unwrap().num_evictable_frames());
+        // an access while evictable moves the frame within the order
+        assert!(replacer.record_access(1).is_ok());
+
+        // a pinned frame is passed over, and goes in its place once unpinned
+        assert!(replacer.set_evictable(2, false).is_ok());
+        assert!(replacer.set_evictable(2, false).is_ok());
+        assert_eq!(Some(0), replacer.evict().unwrap().frame_id());
+        assert_eq!(1, replacer.size().unwrap().num_evictable_frames());
+        assert!(replacer.set_evictable(2, true).is_ok());
+        assert_eq!(Some(2), replacer.evict().unwrap().frame_id());
+        assert_eq!(Some(1), replacer.evict().unwrap().frame_id());
+        assert_eq!(None, replacer.evict().unwrap().frame_id());
+        assert_eq!(0, replacer.size().unwrap().num_evictable_frames());
+    }
+
+    #[test]
+    pub fn test_lru_k_record_accesses_matches_one_by_one() {
+        let batched: LRUKReplacer = LRUKReplacer::new(4, 2);
+        let one_by_one: LRUKReplacer = LRUKReplacer::new(4, 2);
+        let accesses = [0, 1, 2, 1, 3, 0];
+        assert!(batched.record_accesses(&accesses).is_ok());
+        for frame_id in accesses {
+            assert!(one_by_one.record_access(frame_id).is_ok());
+        }
+        assert_eq!(one_by_one.debug_state(), batched.debug_state());
+        assert_eq!(7, batched.debug_state().current_timestamp);
+    }
+
+    #[test]
+    pub fn test_lru_k_correlated_accesses_count_once() {
+        let replacer: LRUKReplacer = LRUKReplacer::with_aging(4, 2, 1, 0);
+        // a burst of accesses to frame 0, then one each to frames 1 and 2
+        for frame_id in [0, 0, 0, 1, 2] {
+            assert!(replacer.record_access(frame_id).is_ok());
+        }
+        let frame_0 = &replacer.debug_state().frames[0];
+        assert_eq!((1, Some(3)), (frame_0.history_length, frame_0.latest_access));
+        for frame_id in 0..3 {
+            assert!(replacer.set_evictable(frame_id, true).is_ok());
+        }
+        // without the correlated period the burst would have made frame 0 the last to go
+        assert_eq!(Some(0), replacer.evict().unwrap().frame_id());
+    }
+
+    #[test]
+    pub fn test_lru_k_forgets_stale_history() {
+        let replacer: LRUKReplacer = LRUKReplacer::with_aging(8, 2, 0, 10);
+        // frame 0 was hot long ago, frame 1 was touched once just now
+        assert!(replacer.record_access(0).is_ok());
+        assert!(replacer.record_access(0).is_ok());
+        for _ in 0..10 {
+            assert!(replacer.record_access(3).is_ok());
+        }
+        assert!(replacer.record_access(1).is_ok());
+        assert!(replacer.set_evictable(0, true).is_ok());
+        assert!(replacer.set_evictable(1, true).is_ok());
+        assert_eq!(Some(0), replacer.evict().unwrap().frame_id());
+
+        // an access after a long gap starts the history over
+        assert!(replacer.record_access(4).is_ok());
+        for _ in 0..10 {
+            assert!(replacer.record_access(3).is_ok());
+        }
+        assert!(replacer.record_access(4).is_ok());
+        let frame_4 = replacer.debug_state().frames.into_iter().find(|frame| frame.frame_id == 4).unwrap();
+        assert_eq!((1, None), (frame_4.history_length, frame_4.k_distance));
+    }
+
     #[test]
     pub fn test_lru_k_cmu_test_case() {
-        let mut replacer: LRUKReplacer = LRUKReplacer::new(7, 2);
+        let replacer: LRUKReplacer = LRUKReplacer::new(7, 2);
 
         // Scenario: add six elements to the replacer. We have [1,2,3,4,5]. Frame 6 is non-evictable.
         assert!(replacer.record_access(1).is_ok());
@@ -306,11 +569,32 @@ mod tests {
         assert_eq!(None, replacer.evict().unwrap().frame_id());
         assert_eq!(0, replacer.size().unwrap().num_evictable_frames());
 
-        assert_eq!(
-            "Frame doesn't exist; invalid remove command",
-            replacer.remove(1).unwrap_err().message()
-        );
+        assert!(matches!(replacer.remove(1), Err(CrabDBError::FrameNotFound(1))));
         
         assert_eq!(0, replacer.size().unwrap().num_evictable_frames());
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn test_lru_k_shared_across_threads() {
+        let replacer = Arc::new(LRUKReplacer::new(64, 2));
+        let handles: Vec<_> = (0..4).map(|thread| {
+            let replacer = replacer.clone();
+            std::thread::spawn(move || {
+                for frame_id in (thread * 16)..((thread + 1) * 16) {
+                    replacer.record_access(frame_id).unwrap();
+                    replacer.record_access(frame_id).unwrap();
+                    replacer.set_evictable(frame_id, true).unwrap();
+                }
+            })
+        }).collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(64, replacer.size().unwrap().num_evictable_frames());
+        for _ in 0..64 {
+            assert!(replacer.evict().unwrap().frame_id().is_some());
+        }
+        assert_eq!(0, replacer.size().unwrap().num_evictable_frames());
+    }
+}
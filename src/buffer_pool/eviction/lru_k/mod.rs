@@ -1,3 +1,4 @@
+pub mod common;
+pub mod concurrent_lru_k_replacer;
 pub mod lru_k_node;
 pub mod lru_k_replacer;
-pub mod common;
\ No newline at end of file
@@ -1,2 +1,11 @@
+pub mod arc;
+pub mod clock;
+pub(crate) mod ghost_list;
+pub mod lru;
 pub mod lru_k;
-pub mod replacer;
\ No newline at end of file
+pub mod policy;
+pub mod replacer;
+pub mod sieve;
+pub mod timestamp_clock;
+pub mod tinylfu;
+pub mod two_q;
@@ -0,0 +1,181 @@
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::arc::arc_replacer::ARCReplacer;
+use super::clock::clock_replacer::ClockReplacer;
+use super::lru::lru_replacer::LRUReplacer;
+use super::lru_k::concurrent_lru_k_replacer::ConcurrentLRUKReplacer;
+use super::lru_k::lru_k_replacer::LRUKReplacer;
+use super::replacer::Replacer;
+use super::sieve::sieve_replacer::SieveReplacer;
+use super::timestamp_clock::TimestampClock;
+use super::tinylfu::tinylfu_replacer::TinyLFUReplacer;
+use super::two_q::two_q_replacer::TwoQReplacer;
+
+// Which replacer a buffer pool evicts with, and how it is tuned. `build` makes one, so the
+// policy can come from configuration instead of being fixed in code. Policies also parse
+// from and print as the names below, e.g. "lru-k:2" or "sieve".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplacerPolicy {
+    // Accesses within `correlated_period` ticks of the last one count as that one, and
+    // history older than `max_age` ticks is forgotten; 0 turns either off. Ticks are
+    // accesses unless the replacer is built with a clock that tells time.
+    LruK { k: usize, correlated_period: u64, max_age: u64 },
+    // LRU-K that samples `sample_size` frames per eviction instead of taking a lock.
+    ConcurrentLruK { k: usize, sample_size: usize },
+    Lru,
+    Clock,
+    Sieve,
+    Arc,
+    TwoQ,
+    TinyLfu,
+}
+
+impl Default for ReplacerPolicy {
+    fn default() -> Self {
+        ReplacerPolicy::lru_k(2)
+    }
+}
+
+impl ReplacerPolicy {
+    // LRU-K without a correlated period or aging.
+    pub fn lru_k(k: usize) -> Self {
+        ReplacerPolicy::LruK { k, correlated_period: 0, max_age: 0 }
+    }
+
+    pub fn build(self, replacer_size: usize) -> Box<dyn Replacer> {
+        self.build_with_clock(replacer_size, None)
+    }
+
+    // LRU-K replacers timestamp accesses with `clock` if given, their own otherwise. The
+    // others keep counting their own, as they need every timestamp to be distinct.
+    pub fn build_with_clock(self, replacer_size: usize, clock: Option<Arc<dyn TimestampClock>>) -> Box<dyn Replacer> {
+        match self {
+            ReplacerPolicy::LruK { k, correlated_period, max_age } => {
+                let replacer = LRUKReplacer::with_aging(replacer_size, k, correlated_period, max_age);
+                Box::new(match clock {
+                    Some(clock) => replacer.with_clock(clock),
+                    None => replacer,
+                })
+            },
+            ReplacerPolicy::ConcurrentLruK { k, sample_size } => {
+                let replacer = ConcurrentLRUKReplacer::new(replacer_size, k, sample_size);
+                Box::new(match clock {
+                    Some(clock) => replacer.with_clock(clock),
+                    None => replacer,
+                })
+            },
+            ReplacerPolicy::Lru => Box::new(LRUReplacer::new(replacer_size)),
+            ReplacerPolicy::Clock => Box::new(ClockReplacer::new(replacer_size)),
+            ReplacerPolicy::Sieve => Box::new(SieveReplacer::new(replacer_size)),
+            ReplacerPolicy::Arc => Box::new(ARCReplacer::new(replacer_size)),
+            ReplacerPolicy::TwoQ => Box::new(TwoQReplacer::new(replacer_size)),
+            ReplacerPolicy::TinyLfu => Box::new(TinyLFUReplacer::new(replacer_size)),
+        }
+    }
+
+    pub fn validate(&self) -> CrabDbResult<()> {
+        match *self {
+            ReplacerPolicy::LruK { k: 0, .. } | ReplacerPolicy::ConcurrentLruK { k: 0, .. } => {
+                Err(CrabDBError::InvalidInput("K of LRU-K must be at least 1".to_string()))
+            },
+            ReplacerPolicy::ConcurrentLruK { sample_size: 0, .. } => {
+                Err(CrabDBError::InvalidInput("LRU-K must sample at least one frame per eviction".to_string()))
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Display for ReplacerPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplacerPolicy::LruK { k, correlated_period: 0, max_age: 0 } => write!(f, "lru-k:{k}"),
+            ReplacerPolicy::LruK { k, correlated_period, max_age } => write!(f, "lru-k:{k}:{correlated_period}:{max_age}"),
+            ReplacerPolicy::ConcurrentLruK { k, sample_size } => write!(f, "concurrent-lru-k:{k}:{sample_size}"),
+            ReplacerPolicy::Lru => write!(f, "lru"),
+            ReplacerPolicy::Clock => write!(f, "clock"),
+            ReplacerPolicy::Sieve => write!(f, "sieve"),
+            ReplacerPolicy::Arc => write!(f, "arc"),
+            ReplacerPolicy::TwoQ => write!(f, "2q"),
+            ReplacerPolicy::TinyLfu => write!(f, "tinylfu"),
+        }
+    }
+}
+
+impl FromStr for ReplacerPolicy {
+    type Err = CrabDBError;
+
+    // Parameters left out take their defaults: "lru-k" is "lru-k:2:0:0".
+    fn from_str(s: &str) -> CrabDbResult<Self> {
+        let invalid = || CrabDBError::InvalidInput(format!("Unknown replacer policy {s}"));
+        let mut parts = s.split(':');
+        let name = parts.next().unwrap_or_default().to_ascii_lowercase();
+        let mut parameter = |default: usize| match parts.next() {
+            Some(part) => part.parse::<usize>().map_err(|_| invalid()),
+            None => Ok(default),
+        };
+        let policy = match name.as_str() {
+            "lru-k" => ReplacerPolicy::LruK {
+                k: parameter(2)?,
+                correlated_period: parameter(0)? as u64,
+                max_age: parameter(0)? as u64,
+            },
+            "concurrent-lru-k" => ReplacerPolicy::ConcurrentLruK { k: parameter(2)?, sample_size: parameter(8)? },
+            "lru" => ReplacerPolicy::Lru,
+            "clock" => ReplacerPolicy::Clock,
+            "sieve" => ReplacerPolicy::Sieve,
+            "arc" => ReplacerPolicy::Arc,
+            "2q" => ReplacerPolicy::TwoQ,
+            "tinylfu" => ReplacerPolicy::TinyLfu,
+            _ => return Err(invalid()),
+        };
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::CrabDBError;
+    use super::ReplacerPolicy;
+
+    #[test]
+    pub fn test_replacer_policy_builds_every_replacer() {
+        let policies = [
+            ReplacerPolicy::lru_k(3),
+            ReplacerPolicy::LruK { k: 2, correlated_period: 4, max_age: 1000 },
+            ReplacerPolicy::ConcurrentLruK { k: 2, sample_size: 4 },
+            ReplacerPolicy::Lru,
+            ReplacerPolicy::Clock,
+            ReplacerPolicy::Sieve,
+            ReplacerPolicy::Arc,
+            ReplacerPolicy::TwoQ,
+            ReplacerPolicy::TinyLfu,
+        ];
+        for policy in policies {
+            assert_eq!(policy, policy.to_string().parse().unwrap());
+            let replacer = policy.build(4);
+            for frame_id in 0..4 {
+                replacer.record_access(frame_id).unwrap();
+                replacer.set_evictable(frame_id, true).unwrap();
+            }
+            let mut evicted: Vec<_> = (0..4).map(|_| replacer.evict().unwrap().frame_id().unwrap()).collect();
+            evicted.sort();
+            assert_eq!(vec![0, 1, 2, 3], evicted, "{policy}");
+            assert_eq!(None, replacer.evict().unwrap().frame_id(), "{policy}");
+        }
+
+        assert_eq!(ReplacerPolicy::lru_k(2), "LRU-K".parse().unwrap());
+        assert_eq!(ReplacerPolicy::LruK { k: 2, correlated_period: 3, max_age: 0 }, "lru-k:2:3".parse().unwrap());
+        assert!(matches!("lru-k:x".parse::<ReplacerPolicy>(), Err(CrabDBError::InvalidInput(_))));
+        assert!(matches!("mru".parse::<ReplacerPolicy>(), Err(CrabDBError::InvalidInput(_))));
+        assert!(ReplacerPolicy::lru_k(0).validate().is_err());
+        assert!(ReplacerPolicy::ConcurrentLruK { k: 2, sample_size: 0 }.validate().is_err());
+    }
+}
@@ -1,12 +1,80 @@
-use crate::{buffer_pool::common::FrameId, types::CrabDbResult};
+use crate::{buffer_pool::common::FrameId, storage::common::PageId, types::{CrabDBError, CrabDbResult}};
+use crate::buffer_pool::eviction::lru_k::common::Timestamp;
 use responses::*;
 
-pub trait Replacer {
-    fn evict(&mut self) -> CrabDbResult<EvictionResponse>;
-    fn record_access(&mut self, frame_id: FrameId) -> CrabDbResult<RecordAccessResponse>;
-    fn remove(&mut self, frame_id: FrameId) -> CrabDbResult<RemoveResponse>;
-    fn set_evictable(&mut self, frame_id: FrameId, set_evictable: bool) -> CrabDbResult<SetEvictableResponse>;
+// What a page is fetched for, as a hint to the replacer. A scan reads each page once and
+// moves on, so scan-resistant policies keep it from pushing out pages that are used often.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AccessType {
+    #[default]
+    Lookup,
+    Scan,
+    Index,
+}
+
+// What a replacer knows about one frame it tracks, to explain why it was or was not evicted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameDebugState {
+    pub frame_id: FrameId,
+    // Filled in by the buffer pool; replacers do not know which page a frame holds.
+    pub page_id: Option<PageId>,
+    pub evictable: bool,
+    // Accesses the replacer still remembers, at most K.
+    pub history_length: usize,
+    pub earliest_access: Option<Timestamp>,
+    pub latest_access: Option<Timestamp>,
+    // Backward K-distance at the replacer's current timestamp, or None if it is infinite
+    // because the frame has fewer than K accesses. Frames with the largest distance go first.
+    pub k_distance: Option<u64>,
+}
+
+// A snapshot of a replacer's bookkeeping, frames in id order.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReplacerDebugState {
+    pub current_timestamp: Timestamp,
+    pub frames: Vec<FrameDebugState>,
+}
+
+pub trait Replacer: Send + Sync {
+    fn evict(&self) -> CrabDbResult<EvictionResponse>;
+    fn record_access(&self, frame_id: FrameId) -> CrabDbResult<RecordAccessResponse>;
+    // Policies that remember pages after their frame is reclaimed (e.g. ARC's ghost lists)
+    // need to know which page the frame holds; everyone else can ignore it.
+    fn record_page_access(&self, frame_id: FrameId, _page_id: PageId) -> CrabDbResult<RecordAccessResponse> {
+        self.record_access(frame_id)
+    }
+    // `record_page_access` with the reason for the access. Policies that do not tell
+    // accesses apart can ignore it.
+    fn record_access_with_type(&self, frame_id: FrameId, page_id: PageId, _access_type: AccessType) -> CrabDbResult<RecordAccessResponse> {
+        self.record_page_access(frame_id, page_id)
+    }
+    // Several accesses, in the order they happened. Policies that lock for every access can
+    // lock once for all of them.
+    fn record_accesses(&self, frame_ids: &[FrameId]) -> CrabDbResult<RecordAccessResponse> {
+        for &frame_id in frame_ids {
+            self.record_access(frame_id)?;
+        }
+        Ok(RecordAccessResponse {})
+    }
+    fn remove(&self, frame_id: FrameId) -> CrabDbResult<RemoveResponse>;
+    fn set_evictable(&self, frame_id: FrameId, set_evictable: bool) -> CrabDbResult<SetEvictableResponse>;
     fn size(&self) -> CrabDbResult<ReplacerSizeResponse>;
+    // Changes how many frames the replacer tracks, following a buffer pool resize. Frames
+    // already tracked stay; shrinking only stops new ones from being added beyond the size.
+    fn set_replacer_size(&self, _replacer_size: usize) -> CrabDbResult<ResizeResponse> {
+        Err(CrabDBError::InvalidInput("This replacer cannot be resized".to_string()))
+    }
+    // Every tracked frame, pinned or not, from the one the replacer would keep longest to the
+    // one it would evict first. Policies that keep no such order return none.
+    fn hottest_frames(&self) -> Vec<FrameId> {
+        Vec::new()
+    }
+    // Policies that do not keep access timestamps report no frames.
+    fn debug_state(&self) -> ReplacerDebugState {
+        ReplacerDebugState::default()
+    }
 }
 
 pub mod responses {
@@ -19,6 +87,8 @@ pub mod responses {
     #[derive(Debug)]
     pub struct SetEvictableResponse {}
     #[derive(Debug)]
+    pub struct ResizeResponse {}
+    #[derive(Debug)]
     pub struct EvictionResponse {
         frame_id: Option<FrameId>,
     }
@@ -0,0 +1 @@
+pub mod sieve_replacer;
@@ -0,0 +1,182 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+use std::sync::{Mutex, MutexGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::buffer_pool::{common::FrameId, eviction::replacer::Replacer};
+use crate::buffer_pool::eviction::lru_k::common::Timestamp;
+use crate::types::{CrabDBError, CrabDbResult};
+use crate::buffer_pool::eviction::replacer::responses::*;
+
+// SIEVE (Zhang et al., NSDI '24). Frames queue up in the order they were first accessed and
+// never move; an access only sets a visited bit. The hand walks from the oldest frame
+// towards the newest, clearing visited bits, and evicts the first evictable frame it finds
+// unvisited. It stays where it stopped, so frames behind it are not looked at again until it
+// wraps around.
+pub struct SieveReplacer {
+    replacer_size: AtomicUsize,
+    state: Mutex<SieveReplacerState>,
+}
+
+#[derive(Debug)]
+struct SieveNode {
+    position: Timestamp,
+    visited: bool,
+    is_evictable: bool,
+}
+
+#[derive(Debug)]
+pub struct SieveReplacerState {
+    next_position: Timestamp,
+    num_evictable: usize,
+    nodes: HashMap<FrameId, SieveNode>,
+    // Every tracked frame, oldest first.
+    queue: BTreeMap<Timestamp, FrameId>,
+    // Position the next eviction starts looking from.
+    hand: Timestamp,
+}
+
+impl SieveReplacer {
+    pub fn new(replacer_size: usize) -> Self {
+        SieveReplacer {
+            replacer_size: AtomicUsize::new(replacer_size),
+            state: Mutex::new(SieveReplacerState {
+                next_position: 0,
+                num_evictable: 0,
+                nodes: HashMap::new(),
+                queue: BTreeMap::new(),
+                hand: 0,
+            }),
+        }
+    }
+}
+
+impl Replacer for SieveReplacer {
+    fn evict(&self) -> CrabDbResult<EvictionResponse> {
+        let mut state: MutexGuard<SieveReplacerState> = self.state.lock().unwrap();
+        let state = &mut *state;
+        if state.num_evictable == 0 {
+            return Ok(EvictionResponse::new(None));
+        }
+        // From the hand to the newest frame, then around from the oldest; two turns at most,
+        // as the first clears every visited bit.
+        let hand = state.hand;
+        let turn = state.queue.range(hand..).chain(state.queue.range(..hand));
+        let order: Vec<(Timestamp, FrameId)> = turn.map(|(&position, &frame_id)| (position, frame_id)).collect();
+        for &(position, frame_id) in order.iter().chain(order.iter()) {
+            let node = state.nodes.get_mut(&frame_id).unwrap();
+            if !node.is_evictable {
+                continue;
+            }
+            if node.visited {
+                node.visited = false;
+                continue;
+            }
+            state.nodes.remove(&frame_id);
+            state.queue.remove(&position);
+            state.num_evictable -= 1;
+            state.hand = state.queue.range((Bound::Excluded(position), Bound::Unbounded))
+                .next()
+                .map_or(0, |(&next, _)| next);
+            return Ok(EvictionResponse::new(Some(frame_id)));
+        }
+        Ok(EvictionResponse::new(None))
+    }
+
+    fn record_access(&self, frame_id: FrameId) -> CrabDbResult<RecordAccessResponse> {
+        let mut state: MutexGuard<SieveReplacerState> = self.state.lock().unwrap();
+        let state = &mut *state;
+        match state.nodes.get_mut(&frame_id) {
+            Some(node) => node.visited = true,
+            None => {
+                if state.nodes.len() >= self.replacer_size.load(Ordering::Relaxed) {
+                    return Err(CrabDBError::FrameOutOfRange(frame_id));
+                }
+                let position = state.next_position;
+                state.next_position += 1;
+                state.nodes.insert(frame_id, SieveNode { position, visited: false, is_evictable: false });
+                state.queue.insert(position, frame_id);
+            },
+        }
+        Ok(RecordAccessResponse {})
+    }
+
+    fn remove(&self, frame_id: FrameId) -> CrabDbResult<RemoveResponse> {
+        let mut state: MutexGuard<SieveReplacerState> = self.state.lock().unwrap();
+        match state.nodes.get(&frame_id) {
+            Some(node) if node.is_evictable => {
+                let position = node.position;
+                state.nodes.remove(&frame_id);
+                state.queue.remove(&position);
+                state.num_evictable -= 1;
+            },
+            Some(_) => return Err(CrabDBError::NotEvictable(frame_id)),
+            None => return Err(CrabDBError::FrameNotFound(frame_id)),
+        }
+        Ok(RemoveResponse {})
+    }
+
+    fn set_evictable(&self, frame_id: FrameId, set_evictable: bool) -> CrabDbResult<SetEvictableResponse> {
+        let mut state: MutexGuard<SieveReplacerState> = self.state.lock().unwrap();
+        let state = &mut *state;
+        let node = match state.nodes.get_mut(&frame_id) {
+            Some(node) => node,
+            None => return Err(CrabDBError::FrameNotFound(frame_id)),
+        };
+        if node.is_evictable != set_evictable {
+            node.is_evictable = set_evictable;
+            if set_evictable {
+                state.num_evictable += 1;
+            } else {
+                state.num_evictable -= 1;
+            }
+        }
+        Ok(SetEvictableResponse {})
+    }
+
+    fn set_replacer_size(&self, replacer_size: usize) -> CrabDbResult<ResizeResponse> {
+        self.replacer_size.store(replacer_size, Ordering::Relaxed);
+        Ok(ResizeResponse {})
+    }
+
+    fn size(&self) -> CrabDbResult<ReplacerSizeResponse> {
+        Ok(ReplacerSizeResponse::new(self.state.lock().unwrap().num_evictable))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_pool::eviction::replacer::Replacer as _;
+    use crate::types::CrabDBError;
+    use super::SieveReplacer;
+
+    #[test]
+    pub fn test_sieve_replacer_keeps_visited_frames_in_place() {
+        let replacer = SieveReplacer::new(4);
+        for frame_id in 0..4 {
+            assert!(replacer.record_access(frame_id).is_ok());
+            assert!(replacer.set_evictable(frame_id, true).is_ok());
+        }
+        assert!(matches!(replacer.record_access(4), Err(CrabDBError::FrameOutOfRange(4))));
+
+        // 0 and 1 are visited, so the hand passes them and takes 2
+        assert!(replacer.record_access(0).is_ok());
+        assert!(replacer.record_access(1).is_ok());
+        assert_eq!(Some(2), replacer.evict().unwrap().frame_id());
+        // a new frame joins at the newest end, which the hand reaches right after 3
+        assert!(replacer.record_access(2).is_ok());
+        assert!(replacer.set_evictable(2, true).is_ok());
+        assert_eq!(Some(3), replacer.evict().unwrap().frame_id());
+        assert_eq!(Some(2), replacer.evict().unwrap().frame_id());
+        // the hand wraps to the oldest frames, whose bits the first pass cleared
+        assert_eq!(Some(0), replacer.evict().unwrap().frame_id());
+        assert_eq!(1, replacer.size().unwrap().num_evictable_frames());
+
+        assert!(replacer.set_evictable(1, false).is_ok());
+        assert!(matches!(replacer.remove(1), Err(CrabDBError::NotEvictable(1))));
+        assert_eq!(None, replacer.evict().unwrap().frame_id());
+        assert!(replacer.set_evictable(1, true).is_ok());
+        assert!(replacer.remove(1).is_ok());
+        assert!(matches!(replacer.remove(1), Err(CrabDBError::FrameNotFound(1))));
+    }
+}
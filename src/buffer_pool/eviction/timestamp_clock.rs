@@ -0,0 +1,141 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::platform::{self, Clock};
+
+use super::lru_k::common::Timestamp;
+
+// Where timestamps ordering events come from, such as the accesses LRU-K replacers keep the
+// history of. One clock shared by several replacers puts their timestamps on one scale.
+// Timestamps never go backwards and are never 0, which replacers keep for "no access".
+pub trait TimestampClock: Send + Sync + fmt::Debug {
+    // The timestamp of something happening now. A logical clock moves on with every call;
+    // one that tells time gives things happening close together the same timestamp.
+    fn tick(&self) -> Timestamp;
+
+    // The timestamp `tick` would give now, without moving a logical clock on.
+    fn now(&self) -> Timestamp;
+}
+
+// Counts ticks: each gets the next number, from 1. What a replacer uses unless given a
+// clock, so its periods are measured in accesses.
+#[derive(Debug)]
+pub struct LogicalClock {
+    next: AtomicU64,
+}
+
+impl Default for LogicalClock {
+    fn default() -> Self {
+        LogicalClock { next: AtomicU64::new(1) }
+    }
+}
+
+impl TimestampClock for LogicalClock {
+    fn tick(&self) -> Timestamp {
+        self.next.fetch_add(1, Ordering::AcqRel)
+    }
+
+    fn now(&self) -> Timestamp {
+        self.next.load(Ordering::Acquire)
+    }
+}
+
+// Monotonic time in units of `resolution`, from 1, read off the engine's clock (see
+// `platform::set_clock`) or another. Timestamps line up with the time of traces, and periods
+// are measured in units of time. The coarser the resolution, the more accesses share a
+// timestamp and the less a replacer tells them apart.
+pub struct MonotonicClock {
+    source: Option<Arc<dyn Clock>>,
+    resolution_micros: u64,
+}
+
+impl MonotonicClock {
+    pub fn new(resolution: Duration) -> Self {
+        MonotonicClock { source: None, resolution_micros: (resolution.as_micros() as u64).max(1) }
+    }
+
+    // Reads time off `source` rather than the engine's clock, e.g. a `platform::ManualClock`
+    // a test moves on.
+    pub fn with_source(mut self, source: Arc<dyn Clock>) -> Self {
+        self.source = Some(source);
+        self
+    }
+}
+
+impl Default for MonotonicClock {
+    // Milliseconds.
+    fn default() -> Self {
+        MonotonicClock::new(Duration::from_millis(1))
+    }
+}
+
+impl fmt::Debug for MonotonicClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MonotonicClock").field("resolution_micros", &self.resolution_micros).finish_non_exhaustive()
+    }
+}
+
+impl TimestampClock for MonotonicClock {
+    fn tick(&self) -> Timestamp {
+        self.now()
+    }
+
+    fn now(&self) -> Timestamp {
+        let micros = match &self.source {
+            Some(source) => source.monotonic_micros(),
+            None => platform::clock().monotonic_micros(),
+        };
+        1 + micros / self.resolution_micros
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::buffer_pool::eviction::replacer::Replacer as _;
+    use crate::platform::ManualClock;
+    use super::{LogicalClock, MonotonicClock, TimestampClock};
+
+    #[test]
+    pub fn test_replacers_share_a_clock() {
+        let logical = LogicalClock::default();
+        assert_eq!((1, 2, 3), (logical.tick(), logical.tick(), logical.now()));
+
+        let time = Arc::new(ManualClock::new(0));
+        let monotonic = MonotonicClock::new(Duration::from_millis(1)).with_source(time.clone());
+        assert_eq!(1, monotonic.tick());
+        time.advance(Duration::from_micros(2_500));
+        assert_eq!((3, 3), (monotonic.tick(), monotonic.now()));
+
+        // Two replacers on one logical clock number their accesses in the order they came.
+        let clock: Arc<dyn TimestampClock> = Arc::new(LogicalClock::default());
+        let first = LRUKReplacer::new(4, 2).with_clock(clock.clone());
+        let second = LRUKReplacer::new(4, 2).with_clock(clock.clone());
+        for frame_id in 0..2 {
+            assert!(first.record_access(frame_id).is_ok());
+            assert!(second.record_access(frame_id).is_ok());
+        }
+        let latest = |replacer: &LRUKReplacer| replacer.debug_state().frames.iter().map(|frame| frame.latest_access.unwrap()).collect::<Vec<_>>();
+        assert_eq!((vec![1, 3], vec![2, 4]), (latest(&first), latest(&second)));
+        assert_eq!(5, second.debug_state().current_timestamp);
+
+        // On a clock telling time, history is forgotten after `max_age` of it.
+        let replacer = LRUKReplacer::with_aging(4, 2, 0, 10).with_clock(Arc::new(MonotonicClock::default().with_source(time.clone())));
+        for _ in 0..2 {
+            assert!(replacer.record_access(0).is_ok());
+        }
+        assert!(replacer.record_access(1).is_ok());
+        assert!(replacer.set_evictable(0, true).is_ok() && replacer.set_evictable(1, true).is_ok());
+        // Frame 0's two accesses a moment ago keep it, however many accesses come between.
+        assert_eq!(Some(1), replacer.evict().unwrap().frame_id());
+        assert!(replacer.record_access(1).is_ok() && replacer.set_evictable(1, true).is_ok());
+        time.advance(Duration::from_millis(20));
+        assert!(replacer.record_access(1).is_ok());
+        assert_eq!(Some(0), replacer.evict().unwrap().frame_id());
+    }
+}
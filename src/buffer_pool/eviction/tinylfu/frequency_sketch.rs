@@ -0,0 +1,138 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+const DEPTH: usize = 4;
+const MAX_COUNT: u8 = 15;
+const SEEDS: [u64; DEPTH] = [
+    0x9e37_79b9_7f4a_7c15,
+    0xc2b2_ae3d_27d4_eb4f,
+    0x1656_67b1_9e37_79f9,
+    0x85eb_ca77_c2b2_ae63,
+];
+
+// Count-min sketch with 4-bit saturating counters, as used by TinyLFU to estimate how often
+// a key has been seen recently. Once `sample_size` increments have been recorded every counter
+// is halved, so old popularity fades instead of pinning keys forever.
+#[derive(Debug)]
+pub struct FrequencySketch {
+    table: Vec<[u8; DEPTH]>,
+    mask: usize,
+    sample_size: usize,
+    additions: usize,
+}
+
+impl FrequencySketch {
+    pub fn new(expected_keys: usize) -> Self {
+        let width = expected_keys.max(16).next_power_of_two();
+        FrequencySketch {
+            table: vec![[0; DEPTH]; width],
+            mask: width - 1,
+            sample_size: width * 10,
+            additions: 0,
+        }
+    }
+
+    pub fn frequency<K: Hash>(&self, key: &K) -> u8 {
+        let hash = hash_key(key);
+        (0..DEPTH)
+            .map(|row| self.table[self.index(hash, row)][row])
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn increment<K: Hash>(&mut self, key: &K) {
+        let hash = hash_key(key);
+        let mut added = false;
+        for row in 0..DEPTH {
+            let index = self.index(hash, row);
+            let counter = &mut self.table[index][row];
+            if *counter < MAX_COUNT {
+                *counter += 1;
+                added = true;
+            }
+        }
+        if added {
+            self.additions += 1;
+            if self.additions >= self.sample_size {
+                self.reset();
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for counters in self.table.iter_mut() {
+            for counter in counters.iter_mut() {
+                *counter >>= 1;
+            }
+        }
+        self.additions /= 2;
+    }
+
+    fn index(&self, hash: u64, row: usize) -> usize {
+        let mixed = (hash ^ SEEDS[row]).wrapping_mul(SEEDS[(row + 1) % DEPTH]);
+        ((mixed >> 32) as usize) & self.mask
+    }
+}
+
+fn hash_key<K: Hash>(key: &K) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FrequencySketch;
+
+    #[test]
+    pub fn test_sketch_counts_increments() {
+        let mut sketch = FrequencySketch::new(64);
+        assert_eq!(0, sketch.frequency(&7u64));
+        for _ in 0..5 {
+            sketch.increment(&7u64);
+        }
+        sketch.increment(&8u64);
+        assert_eq!(5, sketch.frequency(&7u64));
+        assert!(sketch.frequency(&8u64) >= 1);
+    }
+
+    #[test]
+    pub fn test_sketch_counters_saturate() {
+        let mut sketch = FrequencySketch::new(64);
+        for _ in 0..100 {
+            sketch.increment(&1u64);
+        }
+        assert_eq!(15, sketch.frequency(&1u64));
+    }
+
+    #[test]
+    pub fn test_sketch_reset_halves_counts() {
+        let mut sketch = FrequencySketch::new(64);
+        for _ in 0..10 {
+            sketch.increment(&1u64);
+        }
+        sketch.reset();
+        assert_eq!(5, sketch.frequency(&1u64));
+    }
+
+    #[test]
+    pub fn test_sketch_ages_after_sample_size() {
+        let mut sketch = FrequencySketch::new(16);
+        for _ in 0..15 {
+            sketch.increment(&1u64);
+        }
+        assert_eq!(15, sketch.frequency(&1u64));
+
+        // key 1 is saturated, so only a reset can lower it; that happens at 160 additions
+        let mut reset_seen = false;
+        for key in 100..400u64 {
+            sketch.increment(&key);
+            if sketch.frequency(&1u64) < 15 {
+                assert_eq!(7, sketch.frequency(&1u64));
+                reset_seen = true;
+                break;
+            }
+        }
+        assert!(reset_seen);
+    }
+}
@@ -0,0 +1,2 @@
+pub mod frequency_sketch;
+pub mod tinylfu_replacer;
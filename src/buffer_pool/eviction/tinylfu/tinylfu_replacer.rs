@@ -0,0 +1,302 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::buffer_pool::{common::FrameId, eviction::replacer::Replacer};
+use crate::buffer_pool::eviction::lru_k::common::Timestamp;
+use crate::storage::common::PageId;
+use crate::types::{CrabDBError, CrabDbResult};
+use crate::buffer_pool::eviction::replacer::responses::*;
+
+use super::frequency_sketch::FrequencySketch;
+
+// W-TinyLFU. New frames enter a small LRU window; the rest of the pool is a segmented LRU
+// (probation + protected). Once the window is full, its oldest frame has to beat the main
+// region's LRU victim on estimated frequency to stay, otherwise it is the one evicted.
+pub struct TinyLFUReplacer {
+    replacer_size: usize,
+    window_size: usize,
+    protected_size: usize,
+    state: RwLock<TinyLFUReplacerState>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Segment {
+    Window,
+    Probation,
+    Protected,
+}
+
+#[derive(Debug)]
+struct TinyLFUEntry {
+    segment: Segment,
+    position: Timestamp,
+    key: u64,
+    is_evictable: bool,
+}
+
+#[derive(Debug)]
+pub struct TinyLFUReplacerState {
+    current_timestamp: Timestamp,
+    num_evictable: usize,
+    sketch: FrequencySketch,
+    entries: HashMap<FrameId, TinyLFUEntry>,
+    window: BTreeMap<Timestamp, FrameId>,
+    probation: BTreeMap<Timestamp, FrameId>,
+    protected: BTreeMap<Timestamp, FrameId>,
+}
+
+impl TinyLFUReplacerState {
+    fn segment_mut(&mut self, segment: Segment) -> &mut BTreeMap<Timestamp, FrameId> {
+        match segment {
+            Segment::Window => &mut self.window,
+            Segment::Probation => &mut self.probation,
+            Segment::Protected => &mut self.protected,
+        }
+    }
+
+    fn next_timestamp(&mut self) -> Timestamp {
+        let timestamp = self.current_timestamp;
+        self.current_timestamp += 1;
+        timestamp
+    }
+
+    fn move_to(&mut self, frame_id: FrameId, segment: Segment) {
+        let timestamp = self.next_timestamp();
+        let entry = self.entries.get_mut(&frame_id).expect("frame must have an entry");
+        let (old_segment, old_position) = (entry.segment, entry.position);
+        entry.segment = segment;
+        entry.position = timestamp;
+        self.segment_mut(old_segment).remove(&old_position);
+        self.segment_mut(segment).insert(timestamp, frame_id);
+    }
+
+    fn oldest_evictable(&self, segment: Segment) -> Option<FrameId> {
+        let frames = match segment {
+            Segment::Window => &self.window,
+            Segment::Probation => &self.probation,
+            Segment::Protected => &self.protected,
+        };
+        frames.values()
+            .find(|frame_id| self.entries[*frame_id].is_evictable)
+            .copied()
+    }
+
+    fn frequency(&self, frame_id: FrameId) -> u8 {
+        self.sketch.frequency(&self.entries[&frame_id].key)
+    }
+}
+
+impl TinyLFUReplacer {
+    // Defaults from the W-TinyLFU paper: a 1% window and 80% of the main region protected.
+    pub fn new(replacer_size: usize) -> Self {
+        let window_size = (replacer_size / 100).max(1);
+        let protected_size = (replacer_size.saturating_sub(window_size) * 4) / 5;
+        Self::with_segment_sizes(replacer_size, window_size, protected_size)
+    }
+
+    pub fn with_segment_sizes(replacer_size: usize, window_size: usize, protected_size: usize) -> Self {
+        TinyLFUReplacer {
+            replacer_size,
+            window_size,
+            protected_size,
+            state: RwLock::new(TinyLFUReplacerState {
+                current_timestamp: 1,
+                num_evictable: 0,
+                sketch: FrequencySketch::new(replacer_size),
+                entries: HashMap::new(),
+                window: BTreeMap::new(),
+                probation: BTreeMap::new(),
+                protected: BTreeMap::new(),
+            })
+        }
+    }
+
+    pub fn estimated_frequency(&self, page_id: PageId) -> u8 {
+        self.state.read().unwrap().sketch.frequency(&page_id)
+    }
+
+    fn access(&self, frame_id: FrameId, page_id: Option<PageId>) -> CrabDbResult<RecordAccessResponse> {
+        let mut tinylfu_state: RwLockWriteGuard<TinyLFUReplacerState> = self.state.write().unwrap();
+
+        if let Some(entry) = tinylfu_state.entries.get_mut(&frame_id) {
+            if let Some(page_id) = page_id {
+                entry.key = page_id;
+            }
+            let (segment, key) = (entry.segment, entry.key);
+            tinylfu_state.sketch.increment(&key);
+            match segment {
+                Segment::Window => tinylfu_state.move_to(frame_id, Segment::Window),
+                Segment::Protected => tinylfu_state.move_to(frame_id, Segment::Protected),
+                Segment::Probation => {
+                    tinylfu_state.move_to(frame_id, Segment::Protected);
+                    if tinylfu_state.protected.len() > self.protected_size {
+                        let (_, &demoted) = tinylfu_state.protected.first_key_value().expect("protected is non-empty");
+                        tinylfu_state.move_to(demoted, Segment::Probation);
+                    }
+                },
+            }
+            return Ok(RecordAccessResponse {  });
+        }
+
+        if tinylfu_state.entries.len() >= self.replacer_size {
+            return Err(CrabDBError::FrameOutOfRange(frame_id));
+        }
+
+        // Frames with no known page are counted under their frame id.
+        let key = page_id.unwrap_or(frame_id as u64);
+        tinylfu_state.sketch.increment(&key);
+        let timestamp = tinylfu_state.next_timestamp();
+        tinylfu_state.window.insert(timestamp, frame_id);
+        tinylfu_state.entries.insert(frame_id, TinyLFUEntry {
+            segment: Segment::Window,
+            position: timestamp,
+            key,
+            is_evictable: false,
+        });
+
+        // Spill the window into probation while the main region still has free frames.
+        let main_size = self.replacer_size - self.window_size;
+        while tinylfu_state.window.len() > self.window_size
+            && tinylfu_state.probation.len() + tinylfu_state.protected.len() < main_size {
+            let (_, &oldest) = tinylfu_state.window.first_key_value().expect("window is non-empty");
+            tinylfu_state.move_to(oldest, Segment::Probation);
+        }
+        Ok(RecordAccessResponse {  })
+    }
+}
+
+impl Replacer for TinyLFUReplacer {
+
+    fn record_access(&self, frame_id: FrameId) -> CrabDbResult<RecordAccessResponse> {
+        self.access(frame_id, None)
+    }
+
+    fn record_page_access(&self, frame_id: FrameId, page_id: PageId) -> CrabDbResult<RecordAccessResponse> {
+        self.access(frame_id, Some(page_id))
+    }
+
+    fn evict(&self) -> CrabDbResult<EvictionResponse> {
+        let mut tinylfu_state: RwLockWriteGuard<TinyLFUReplacerState> = self.state.write().unwrap();
+        let main_victim = tinylfu_state.oldest_evictable(Segment::Probation)
+            .or_else(|| tinylfu_state.oldest_evictable(Segment::Protected));
+
+        let victim = if tinylfu_state.window.len() >= self.window_size {
+            match (tinylfu_state.oldest_evictable(Segment::Window), main_victim) {
+                // Admission: the window candidate replaces the main victim only if it is
+                // more popular; otherwise the candidate itself is dropped.
+                (Some(candidate), Some(main_victim)) => {
+                    if tinylfu_state.frequency(candidate) > tinylfu_state.frequency(main_victim) {
+                        tinylfu_state.move_to(candidate, Segment::Probation);
+                        Some(main_victim)
+                    } else {
+                        Some(candidate)
+                    }
+                },
+                (candidate, main_victim) => candidate.or(main_victim),
+            }
+        } else {
+            main_victim.or_else(|| tinylfu_state.oldest_evictable(Segment::Window))
+        };
+
+        if let Some(frame_id) = victim {
+            let entry = tinylfu_state.entries.remove(&frame_id).expect("victim must have an entry");
+            tinylfu_state.segment_mut(entry.segment).remove(&entry.position);
+            tinylfu_state.num_evictable -= 1;
+        }
+        Ok(EvictionResponse::new(victim))
+    }
+
+    fn remove(&self, frame_id: FrameId) -> CrabDbResult<RemoveResponse> {
+        let mut tinylfu_state: RwLockWriteGuard<TinyLFUReplacerState> = self.state.write().unwrap();
+        match tinylfu_state.entries.get(&frame_id) {
+            Some(entry) if entry.is_evictable => {
+                let (segment, position) = (entry.segment, entry.position);
+                tinylfu_state.segment_mut(segment).remove(&position);
+                tinylfu_state.entries.remove(&frame_id);
+                tinylfu_state.num_evictable -= 1;
+            },
+            Some(_) => return Err(CrabDBError::NotEvictable(frame_id)),
+            None => return Err(CrabDBError::FrameNotFound(frame_id))
+        }
+        Ok(RemoveResponse {})
+    }
+
+    fn set_evictable(&self, frame_id: FrameId, set_evictable: bool) -> CrabDbResult<SetEvictableResponse> {
+        let mut tinylfu_state: RwLockWriteGuard<TinyLFUReplacerState> = self.state.write().unwrap();
+        let entry = match tinylfu_state.entries.get_mut(&frame_id) {
+            Some(entry) => entry,
+            None => return Err(CrabDBError::FrameNotFound(frame_id)),
+        };
+        if entry.is_evictable != set_evictable {
+            entry.is_evictable = set_evictable;
+            if set_evictable {
+                tinylfu_state.num_evictable += 1;
+            } else {
+                tinylfu_state.num_evictable -= 1;
+            }
+        }
+        Ok(SetEvictableResponse {  })
+    }
+
+    fn size(&self) -> CrabDbResult<ReplacerSizeResponse> {
+        let tinylfu_state: RwLockReadGuard<TinyLFUReplacerState> = self.state.read().unwrap();
+        Ok(ReplacerSizeResponse::new(tinylfu_state.num_evictable))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_pool::eviction::replacer::Replacer as _;
+    use crate::types::CrabDBError;
+    use super::TinyLFUReplacer;
+
+    fn load(replacer: &TinyLFUReplacer, frame_id: usize, page_id: u64) {
+        assert!(replacer.record_page_access(frame_id, page_id).is_ok());
+        assert!(replacer.set_evictable(frame_id, true).is_ok());
+    }
+
+    #[test]
+    pub fn test_tinylfu_rejects_one_hit_wonders() {
+        let replacer = TinyLFUReplacer::with_segment_sizes(4, 1, 2);
+        for frame_id in 0..3 {
+            load(&replacer, frame_id, frame_id as u64);
+            for _ in 0..3 {
+                assert!(replacer.record_page_access(frame_id, frame_id as u64).is_ok());
+            }
+        }
+        load(&replacer, 3, 100);
+        // the window now holds a page seen once; it loses against the popular pages
+        assert_eq!(Some(3), replacer.evict().unwrap().frame_id());
+        for page_id in 101..110 {
+            load(&replacer, 3, page_id);
+            assert_eq!(Some(3), replacer.evict().unwrap().frame_id());
+        }
+        assert_eq!(3, replacer.size().unwrap().num_evictable_frames());
+    }
+
+    #[test]
+    pub fn test_tinylfu_admits_popular_window_page() {
+        let replacer = TinyLFUReplacer::with_segment_sizes(3, 1, 1);
+        load(&replacer, 0, 10);
+        load(&replacer, 1, 11);
+        load(&replacer, 2, 12);
+        for _ in 0..5 {
+            assert!(replacer.record_page_access(2, 12).is_ok());
+        }
+        // 12 sits in the window and is far more popular than probation's 10
+        assert_eq!(Some(0), replacer.evict().unwrap().frame_id());
+        assert!(replacer.estimated_frequency(12) >= 6);
+    }
+
+    #[test]
+    pub fn test_tinylfu_skips_pinned_frames() {
+        let replacer = TinyLFUReplacer::new(4);
+        assert!(replacer.record_page_access(0, 1).is_ok());
+        assert!(replacer.record_page_access(1, 2).is_ok());
+        assert_eq!(None, replacer.evict().unwrap().frame_id());
+        assert!(replacer.set_evictable(1, true).is_ok());
+        assert_eq!(Some(1), replacer.evict().unwrap().frame_id());
+        assert!(matches!(replacer.remove(0), Err(CrabDBError::NotEvictable(0))));
+    }
+}
@@ -0,0 +1 @@
+pub mod two_q_replacer;
@@ -0,0 +1,276 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::buffer_pool::{common::FrameId, eviction::replacer::Replacer};
+use crate::buffer_pool::eviction::ghost_list::GhostList;
+use crate::buffer_pool::eviction::lru_k::common::Timestamp;
+use crate::storage::common::PageId;
+use crate::types::{CrabDBError, CrabDbResult};
+use crate::buffer_pool::eviction::replacer::responses::*;
+
+// Full 2Q (Johnson & Shasha). First-time pages enter the A1in FIFO, and re-touching them
+// there does not promote them, so a scan only ever churns A1in. Pages evicted from A1in are
+// remembered in the A1out ghost queue; coming back while still remembered lands them in Am,
+// a plain LRU for the hot set.
+pub struct TwoQReplacer {
+    replacer_size: usize,
+    max_in_size: usize,
+    max_out_size: usize,
+    state: RwLock<TwoQReplacerState>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TwoQList {
+    In,
+    Main,
+}
+
+#[derive(Debug)]
+struct TwoQEntry {
+    list: TwoQList,
+    position: Timestamp,
+    page_id: Option<PageId>,
+    is_evictable: bool,
+}
+
+#[derive(Debug)]
+pub struct TwoQReplacerState {
+    current_timestamp: Timestamp,
+    num_evictable: usize,
+    entries: HashMap<FrameId, TwoQEntry>,
+    a1_in: BTreeMap<Timestamp, FrameId>,
+    a1_out: GhostList,
+    am: BTreeMap<Timestamp, FrameId>,
+}
+
+impl TwoQReplacerState {
+    fn list_mut(&mut self, list: TwoQList) -> &mut BTreeMap<Timestamp, FrameId> {
+        match list {
+            TwoQList::In => &mut self.a1_in,
+            TwoQList::Main => &mut self.am,
+        }
+    }
+
+    fn next_timestamp(&mut self) -> Timestamp {
+        let timestamp = self.current_timestamp;
+        self.current_timestamp += 1;
+        timestamp
+    }
+
+    fn find_victim(&self, list: TwoQList) -> Option<FrameId> {
+        let frames = match list {
+            TwoQList::In => &self.a1_in,
+            TwoQList::Main => &self.am,
+        };
+        frames.values()
+            .find(|frame_id| self.entries[*frame_id].is_evictable)
+            .copied()
+    }
+}
+
+impl TwoQReplacer {
+    // Uses the paper's recommended tuning: A1in holds a quarter of the frames and A1out
+    // remembers half as many pages as there are frames.
+    pub fn new(replacer_size: usize) -> Self {
+        Self::with_queue_sizes(replacer_size, (replacer_size / 4).max(1), (replacer_size / 2).max(1))
+    }
+
+    pub fn with_queue_sizes(replacer_size: usize, max_in_size: usize, max_out_size: usize) -> Self {
+        TwoQReplacer {
+            replacer_size,
+            max_in_size,
+            max_out_size,
+            state: RwLock::new(TwoQReplacerState {
+                current_timestamp: 1,
+                num_evictable: 0,
+                entries: HashMap::new(),
+                a1_in: BTreeMap::new(),
+                a1_out: GhostList::default(),
+                am: BTreeMap::new(),
+            })
+        }
+    }
+
+    fn access(&self, frame_id: FrameId, page_id: Option<PageId>) -> CrabDbResult<RecordAccessResponse> {
+        let mut two_q_state: RwLockWriteGuard<TwoQReplacerState> = self.state.write().unwrap();
+        let timestamp = two_q_state.next_timestamp();
+
+        if let Some(entry) = two_q_state.entries.get_mut(&frame_id) {
+            if page_id.is_some() {
+                entry.page_id = page_id;
+            }
+            // Hits in A1in are treated as correlated references and leave the FIFO alone.
+            if entry.list == TwoQList::Main {
+                let old_position = entry.position;
+                entry.position = timestamp;
+                two_q_state.am.remove(&old_position);
+                two_q_state.am.insert(timestamp, frame_id);
+            }
+            return Ok(RecordAccessResponse {  });
+        }
+
+        if two_q_state.entries.len() >= self.replacer_size {
+            return Err(CrabDBError::FrameOutOfRange(frame_id));
+        }
+
+        let list = match page_id {
+            Some(page_id) if two_q_state.a1_out.remove(page_id) => TwoQList::Main,
+            _ => TwoQList::In,
+        };
+        two_q_state.list_mut(list).insert(timestamp, frame_id);
+        two_q_state.entries.insert(frame_id, TwoQEntry {
+            list,
+            position: timestamp,
+            page_id,
+            is_evictable: false,
+        });
+        Ok(RecordAccessResponse {  })
+    }
+}
+
+impl Replacer for TwoQReplacer {
+
+    fn record_access(&self, frame_id: FrameId) -> CrabDbResult<RecordAccessResponse> {
+        self.access(frame_id, None)
+    }
+
+    fn record_page_access(&self, frame_id: FrameId, page_id: PageId) -> CrabDbResult<RecordAccessResponse> {
+        self.access(frame_id, Some(page_id))
+    }
+
+    fn evict(&self) -> CrabDbResult<EvictionResponse> {
+        let mut two_q_state: RwLockWriteGuard<TwoQReplacerState> = self.state.write().unwrap();
+        let (preferred, fallback) = if two_q_state.a1_in.len() > self.max_in_size {
+            (TwoQList::In, TwoQList::Main)
+        } else {
+            (TwoQList::Main, TwoQList::In)
+        };
+        let victim = two_q_state.find_victim(preferred).or_else(|| two_q_state.find_victim(fallback));
+
+        if let Some(frame_id) = victim {
+            let entry = two_q_state.entries.remove(&frame_id).expect("victim must have an entry");
+            two_q_state.list_mut(entry.list).remove(&entry.position);
+            two_q_state.num_evictable -= 1;
+            if let (TwoQList::In, Some(page_id)) = (entry.list, entry.page_id) {
+                let timestamp = two_q_state.next_timestamp();
+                two_q_state.a1_out.push(page_id, timestamp);
+                while two_q_state.a1_out.len() > self.max_out_size {
+                    two_q_state.a1_out.pop_oldest();
+                }
+            }
+        }
+        Ok(EvictionResponse::new(victim))
+    }
+
+    fn remove(&self, frame_id: FrameId) -> CrabDbResult<RemoveResponse> {
+        let mut two_q_state: RwLockWriteGuard<TwoQReplacerState> = self.state.write().unwrap();
+        match two_q_state.entries.get(&frame_id) {
+            Some(entry) if entry.is_evictable => {
+                let (list, position) = (entry.list, entry.position);
+                two_q_state.list_mut(list).remove(&position);
+                two_q_state.entries.remove(&frame_id);
+                two_q_state.num_evictable -= 1;
+            },
+            Some(_) => return Err(CrabDBError::NotEvictable(frame_id)),
+            None => return Err(CrabDBError::FrameNotFound(frame_id))
+        }
+        Ok(RemoveResponse {})
+    }
+
+    fn set_evictable(&self, frame_id: FrameId, set_evictable: bool) -> CrabDbResult<SetEvictableResponse> {
+        let mut two_q_state: RwLockWriteGuard<TwoQReplacerState> = self.state.write().unwrap();
+        let entry = match two_q_state.entries.get_mut(&frame_id) {
+            Some(entry) => entry,
+            None => return Err(CrabDBError::FrameNotFound(frame_id)),
+        };
+        if entry.is_evictable != set_evictable {
+            entry.is_evictable = set_evictable;
+            if set_evictable {
+                two_q_state.num_evictable += 1;
+            } else {
+                two_q_state.num_evictable -= 1;
+            }
+        }
+        Ok(SetEvictableResponse {  })
+    }
+
+    fn size(&self) -> CrabDbResult<ReplacerSizeResponse> {
+        let two_q_state: RwLockReadGuard<TwoQReplacerState> = self.state.read().unwrap();
+        Ok(ReplacerSizeResponse::new(two_q_state.num_evictable))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer_pool::eviction::replacer::Replacer as _;
+    use crate::types::CrabDBError;
+    use super::TwoQReplacer;
+
+    fn load(replacer: &TwoQReplacer, frame_id: usize, page_id: u64) {
+        assert!(replacer.record_page_access(frame_id, page_id).is_ok());
+        assert!(replacer.set_evictable(frame_id, true).is_ok());
+    }
+
+    #[test]
+    pub fn test_two_q_rereference_in_a1in_does_not_promote() {
+        let replacer = TwoQReplacer::with_queue_sizes(3, 1, 2);
+        load(&replacer, 0, 10);
+        load(&replacer, 1, 11);
+        // touching page 10 again while it sits in A1in keeps its FIFO position
+        assert!(replacer.record_page_access(0, 10).is_ok());
+        assert_eq!(Some(0), replacer.evict().unwrap().frame_id());
+        assert_eq!(Some(1), replacer.evict().unwrap().frame_id());
+    }
+
+    #[test]
+    pub fn test_two_q_ghost_hit_lands_in_am() {
+        let replacer = TwoQReplacer::with_queue_sizes(3, 1, 2);
+        load(&replacer, 0, 10);
+        load(&replacer, 1, 11);
+        assert_eq!(Some(0), replacer.evict().unwrap().frame_id());
+
+        // page 10 returns while remembered in A1out and goes to Am
+        load(&replacer, 0, 10);
+        load(&replacer, 2, 12);
+        // A1in holds 11 and 12, above its target of 1, so it is drained first
+        assert_eq!(Some(1), replacer.evict().unwrap().frame_id());
+        assert_eq!(Some(0), replacer.evict().unwrap().frame_id());
+        assert_eq!(Some(2), replacer.evict().unwrap().frame_id());
+        assert_eq!(None, replacer.evict().unwrap().frame_id());
+    }
+
+    #[test]
+    pub fn test_two_q_scan_keeps_hot_set() {
+        let replacer = TwoQReplacer::with_queue_sizes(4, 1, 2);
+        // warm up pages 100 and 101 into Am via A1out
+        load(&replacer, 0, 100);
+        load(&replacer, 1, 101);
+        assert_eq!(Some(0), replacer.evict().unwrap().frame_id());
+        assert_eq!(Some(1), replacer.evict().unwrap().frame_id());
+        load(&replacer, 0, 100);
+        load(&replacer, 1, 101);
+
+        load(&replacer, 2, 0);
+        load(&replacer, 3, 1);
+        let mut page_id = 1;
+        for _ in 0..10 {
+            let victim = replacer.evict().unwrap().frame_id().unwrap();
+            assert!(victim == 2 || victim == 3, "scan evicted hot frame {victim}");
+            page_id += 1;
+            load(&replacer, victim, page_id);
+        }
+    }
+
+    #[test]
+    pub fn test_two_q_remove_and_pinning() {
+        let replacer = TwoQReplacer::new(4);
+        assert!(replacer.record_page_access(0, 1).is_ok());
+        assert_eq!(None, replacer.evict().unwrap().frame_id());
+        assert!(matches!(replacer.remove(0), Err(CrabDBError::NotEvictable(0))));
+        assert!(replacer.set_evictable(0, true).is_ok());
+        assert_eq!(1, replacer.size().unwrap().num_evictable_frames());
+        assert!(replacer.remove(0).is_ok());
+        assert_eq!(0, replacer.size().unwrap().num_evictable_frames());
+    }
+}
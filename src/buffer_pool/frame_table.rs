@@ -0,0 +1,52 @@
+use std::ops::Index;
+use std::sync::{Arc, OnceLock};
+
+use crate::buffer_pool::{common::FrameId, page::Page};
+
+const FIRST_SEGMENT_SIZE: usize = 64;
+const NUM_SEGMENTS: usize = 48;
+
+type Segment = Box<[OnceLock<Arc<Page>>]>;
+
+// The buffer pool's frames, indexed by frame id. Frames live in segments that double in size
+// (64, 128, 256, ...) and are only ever added, never moved, so a page can be lent out by
+// reference while the pool grows. A frame's page is allocated the first time the pool grows
+// over it and kept from then on, ready for the pool to grow again.
+pub(crate) struct FrameTable {
+    segments: [OnceLock<Segment>; NUM_SEGMENTS],
+}
+
+impl FrameTable {
+    pub(crate) fn new(num_frames: usize) -> Self {
+        let frames = FrameTable { segments: std::array::from_fn(|_| OnceLock::new()) };
+        frames.grow(num_frames);
+        frames
+    }
+
+    // Makes sure frames 0..num_frames have a page.
+    pub(crate) fn grow(&self, num_frames: usize) {
+        for frame_id in 0..num_frames {
+            let (segment, offset) = Self::locate(frame_id);
+            let segment = self.segments[segment]
+                .get_or_init(|| (0..FIRST_SEGMENT_SIZE << segment).map(|_| OnceLock::new()).collect());
+            segment[offset].get_or_init(|| Arc::new(Page::new()));
+        }
+    }
+
+    // Segment s holds frames FIRST_SEGMENT_SIZE * (2^s - 1) up to FIRST_SEGMENT_SIZE * (2^(s+1) - 1).
+    fn locate(frame_id: FrameId) -> (usize, usize) {
+        let segment = (frame_id / FIRST_SEGMENT_SIZE + 1).ilog2() as usize;
+        (segment, frame_id - FIRST_SEGMENT_SIZE * ((1 << segment) - 1))
+    }
+}
+
+impl Index<FrameId> for FrameTable {
+    type Output = Arc<Page>;
+
+    fn index(&self, frame_id: FrameId) -> &Arc<Page> {
+        let (segment, offset) = Self::locate(frame_id);
+        self.segments[segment].get()
+            .and_then(|segment| segment[offset].get())
+            .unwrap_or_else(|| panic!("Frame {frame_id} is not in the buffer pool"))
+    }
+}
@@ -1,2 +1,10 @@
+pub mod access_strategy;
+pub mod background_writer;
+pub mod buffer_pool_manager;
+pub mod common;
 pub mod eviction;
-pub mod common;
\ No newline at end of file
+pub(crate) mod frame_table;
+pub mod page;
+pub mod page_guard;
+pub mod parallel_buffer_pool_manager;
+pub mod shared_buffer_pool;
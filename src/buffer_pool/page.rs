@@ -0,0 +1,174 @@
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::thread::{self, ThreadId};
+use std::time::Duration;
+
+use crate::platform::Stopwatch;
+use crate::storage::common::{PageId, INVALID_PAGE_ID, PAGE_SIZE};
+use crate::wal::common::{Lsn, INVALID_LSN};
+
+// A buffer pool frame. The metadata is only modified by the buffer pool manager while it
+// holds its own latch, so plain atomic loads are enough for readers; the page bytes are
+// protected by the per-page latch.
+#[derive(Debug)]
+pub struct Page {
+    page_id: AtomicU64,
+    pin_count: AtomicUsize,
+    is_dirty: AtomicBool,
+    // LSN of the last log record that changed this page.
+    page_lsn: AtomicU64,
+    // LSN of the first log record that changed the page since it was last written back, or
+    // INVALID_LSN; what a checkpoint records of it in the dirty page table.
+    rec_lsn: AtomicU64,
+    // Whether the page was changed since without a log record, such as index and catalog
+    // pages are; commits log such pages whole.
+    unlogged: AtomicBool,
+    data: RwLock<[u8; PAGE_SIZE]>,
+    // Threads blocked on the latch. Only threads that find it taken touch this.
+    latch_waiters: Mutex<Vec<LatchWaiter>>,
+}
+
+#[derive(Debug)]
+struct LatchWaiter {
+    thread_id: ThreadId,
+    thread_name: String,
+    exclusive: bool,
+    since: Stopwatch,
+}
+
+// A thread blocked on a page latch, as `BufferPoolManager::latch_waits` reports it. Latches
+// are not tracked while held, so who holds it is not known: a reader waits for a writer,
+// a writer for either.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatchWait {
+    pub page_id: PageId,
+    pub thread: String,
+    pub exclusive: bool,
+    pub waited: Duration,
+}
+
+impl Display for LatchWait {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mode = if self.exclusive { "write" } else { "read" };
+        write!(f, "thread {} has waited {:?} to latch page {} for {mode}", self.thread, self.waited, self.page_id)
+    }
+}
+
+impl Page {
+    pub fn new() -> Self {
+        Page {
+            page_id: AtomicU64::new(INVALID_PAGE_ID),
+            pin_count: AtomicUsize::new(0),
+            is_dirty: AtomicBool::new(false),
+            page_lsn: AtomicU64::new(INVALID_LSN),
+            rec_lsn: AtomicU64::new(INVALID_LSN),
+            unlogged: AtomicBool::new(false),
+            data: RwLock::new([0; PAGE_SIZE]),
+            latch_waiters: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn page_id(&self) -> PageId {
+        self.page_id.load(Ordering::Acquire)
+    }
+
+    pub fn pin_count(&self) -> usize {
+        self.pin_count.load(Ordering::Acquire)
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.is_dirty.load(Ordering::Acquire)
+    }
+
+    pub fn page_lsn(&self) -> Lsn {
+        self.page_lsn.load(Ordering::Acquire)
+    }
+
+    // Called by whoever logs a change to the page, after making the change, with the latch
+    // still held.
+    pub fn set_page_lsn(&self, lsn: Lsn) {
+        self.page_lsn.store(lsn, Ordering::Release);
+        let _ = self.rec_lsn.compare_exchange(INVALID_LSN, lsn, Ordering::AcqRel, Ordering::Acquire);
+    }
+
+    pub(crate) fn rec_lsn(&self) -> Lsn {
+        self.rec_lsn.load(Ordering::Acquire)
+    }
+
+    // Set by the buffer pool as it reads the page in and writes it back.
+    pub(crate) fn set_rec_lsn(&self, lsn: Lsn) {
+        self.rec_lsn.store(lsn, Ordering::Release);
+    }
+
+    pub fn is_unlogged(&self) -> bool {
+        self.unlogged.load(Ordering::Acquire)
+    }
+
+    // Set when the page is changed without a log record, cleared by whoever logs it whole.
+    pub(crate) fn set_unlogged(&self, unlogged: bool) {
+        self.unlogged.store(unlogged, Ordering::Release);
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, [u8; PAGE_SIZE]> {
+        match self.data.try_read() {
+            Ok(data) => data,
+            Err(_) => self.wait_for_latch(false, || self.data.read().unwrap()),
+        }
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<'_, [u8; PAGE_SIZE]> {
+        match self.data.try_write() {
+            Ok(data) => data,
+            Err(_) => self.wait_for_latch(true, || self.data.write().unwrap()),
+        }
+    }
+
+    // Blocks in `acquire`, listed among the latch's waiters until it returns.
+    fn wait_for_latch<T>(&self, exclusive: bool, acquire: impl FnOnce() -> T) -> T {
+        let thread = thread::current();
+        let thread_name = thread.name().map_or_else(|| format!("{:?}", thread.id()), str::to_string);
+        self.latch_waiters.lock().unwrap().push(LatchWaiter { thread_id: thread.id(), thread_name, exclusive, since: Stopwatch::start() });
+        let guard = acquire();
+        self.latch_waiters.lock().unwrap().retain(|waiter| waiter.thread_id != thread.id());
+        guard
+    }
+
+    // The threads blocked on the latch right now.
+    pub fn latch_waits(&self) -> Vec<LatchWait> {
+        self.latch_waiters.lock().unwrap().iter().map(|waiter| LatchWait {
+            page_id: self.page_id(),
+            thread: waiter.thread_name.clone(),
+            exclusive: waiter.exclusive,
+            waited: waiter.since.elapsed(),
+        }).collect()
+    }
+
+    pub(crate) fn reset(&self, page_id: PageId) {
+        self.page_id.store(page_id, Ordering::Release);
+        self.pin_count.store(0, Ordering::Release);
+        self.is_dirty.store(false, Ordering::Release);
+        self.page_lsn.store(INVALID_LSN, Ordering::Release);
+        self.rec_lsn.store(INVALID_LSN, Ordering::Release);
+        self.unlogged.store(false, Ordering::Release);
+        self.write().fill(0);
+    }
+
+    pub(crate) fn pin(&self) -> usize {
+        self.pin_count.fetch_add(1, Ordering::AcqRel) + 1
+    }
+
+    pub(crate) fn unpin(&self) -> usize {
+        self.pin_count.fetch_sub(1, Ordering::AcqRel) - 1
+    }
+
+    pub(crate) fn set_dirty(&self, is_dirty: bool) {
+        self.is_dirty.store(is_dirty, Ordering::Release);
+    }
+}
+
+impl Default for Page {
+    fn default() -> Self {
+        Self::new()
+    }
+}
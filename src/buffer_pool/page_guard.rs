@@ -0,0 +1,136 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::{RwLockReadGuard, RwLockWriteGuard};
+
+use crate::storage::common::{PageId, PAGE_SIZE};
+use crate::wal::common::Lsn;
+
+use super::buffer_pool_manager::BufferPoolManager;
+use super::page::Page;
+
+// A pinned page with its latch held for reading. Dropping the guard unpins the page and
+// then releases the latch, so a page a thread is still looking at can never be evicted.
+pub struct ReadPageGuard<'a> {
+    bpm: &'a BufferPoolManager,
+    page: &'a Page,
+    data: RwLockReadGuard<'a, [u8; PAGE_SIZE]>,
+}
+
+impl<'a> ReadPageGuard<'a> {
+    pub(crate) fn new(bpm: &'a BufferPoolManager, page: &'a Page) -> Self {
+        ReadPageGuard { bpm, page, data: page.read() }
+    }
+
+    pub fn page_id(&self) -> PageId {
+        self.page.page_id()
+    }
+
+    pub fn page(&self) -> &Page {
+        self.page
+    }
+}
+
+impl Deref for ReadPageGuard<'_> {
+    type Target = [u8; PAGE_SIZE];
+
+    fn deref(&self) -> &Self::Target {
+        &self.data
+    }
+}
+
+impl Drop for ReadPageGuard<'_> {
+    fn drop(&mut self) {
+        // Only fails if the page is not pinned, which the guard itself rules out.
+        let _ = self.bpm.unpin_page(self.page.page_id(), false);
+    }
+}
+
+// A pinned page with its latch held for writing. The page is marked dirty on unpin once it
+// has been borrowed mutably, and unlogged too unless its page LSN moved meanwhile.
+pub struct WritePageGuard<'a> {
+    bpm: &'a BufferPoolManager,
+    page: &'a Page,
+    data: RwLockWriteGuard<'a, [u8; PAGE_SIZE]>,
+    is_dirty: bool,
+    // The page LSN when the latch was taken.
+    page_lsn: Lsn,
+}
+
+impl<'a> WritePageGuard<'a> {
+    pub(crate) fn new(bpm: &'a BufferPoolManager, page: &'a Page) -> Self {
+        let data = page.write();
+        WritePageGuard { bpm, page, data, is_dirty: false, page_lsn: page.page_lsn() }
+    }
+
+    // For a page borrowed mutably that turned out not to change.
+    pub(crate) fn set_unchanged(&mut self) {
+        self.is_dirty = false;
+    }
+
+    pub fn page_id(&self) -> PageId {
+        self.page.page_id()
+    }
+
+    pub fn page(&self) -> &Page {
+        self.page
+    }
+}
+
+impl Deref for WritePageGuard<'_> {
+    type Target = [u8; PAGE_SIZE];
+
+    fn deref(&self) -> &Self::Target {
+        &self.data
+    }
+}
+
+impl DerefMut for WritePageGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.is_dirty = true;
+        &mut self.data
+    }
+}
+
+impl Drop for WritePageGuard<'_> {
+    fn drop(&mut self) {
+        let unlogged = self.is_dirty && self.page.page_lsn() == self.page_lsn;
+        let _ = self.bpm.unpin(self.page.page_id(), self.is_dirty, unlogged);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::storage::common::PAGE_HEADER_SIZE;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+
+    #[test]
+    pub fn test_page_guards_unpin_on_drop() {
+        let bpm = BufferPoolManager::new(2, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(2, 2)));
+        let page_id = {
+            let mut guard = bpm.new_page_write().unwrap();
+            guard[PAGE_HEADER_SIZE] = 42;
+            assert_eq!(1, guard.page().pin_count());
+            guard.page_id()
+        };
+
+        {
+            let first = bpm.fetch_page_read(page_id).unwrap();
+            let second = bpm.fetch_page_read(page_id).unwrap();
+            assert_eq!(42, first[PAGE_HEADER_SIZE]);
+            assert_eq!(2, second.page().pin_count());
+            assert!(second.page().is_dirty());
+        }
+
+        // with every guard gone both frames can be reused
+        let a = bpm.new_page().unwrap();
+        let b = bpm.new_page().unwrap();
+        assert_ne!(page_id, a.page_id());
+        assert_ne!(page_id, b.page_id());
+        bpm.unpin_page(a.page_id(), false).unwrap();
+        bpm.unpin_page(b.page_id(), false).unwrap();
+        assert_eq!(42, bpm.fetch_page_read(page_id).unwrap()[PAGE_HEADER_SIZE]);
+    }
+}
@@ -0,0 +1,232 @@
+use std::sync::Arc;
+
+use crate::buffer_pool::buffer_pool_manager::{BufferPoolManager, EvictedPage};
+use crate::buffer_pool::eviction::policy::ReplacerPolicy;
+use crate::buffer_pool::eviction::replacer::AccessType;
+use crate::buffer_pool::eviction::timestamp_clock::{LogicalClock, TimestampClock};
+use crate::buffer_pool::page::Page;
+use crate::buffer_pool::page_guard::{ReadPageGuard, WritePageGuard};
+use crate::metrics::buffer_pool_metrics::BufferPoolMetricsSnapshot;
+use crate::storage::common::PageId;
+use crate::storage::disk::disk_manager::DiskManager;
+use crate::storage::disk::disk_scheduler::DiskScheduler;
+use crate::types::CrabDbResult;
+use crate::wal::log_manager::LogManager;
+
+// Several independent buffer pools behind the BufferPoolManager API. Page p always lives in
+// pool p % N, so threads working on different pages mostly take different latches. Every
+// pool has its own frames and replacer; they share the disk scheduler, the log and the clock
+// replacers timestamp accesses with, so history in one pool compares with the others'.
+pub struct ParallelBufferPoolManager {
+    instances: Vec<BufferPoolManager>,
+    disk_scheduler: Arc<DiskScheduler>,
+}
+
+impl ParallelBufferPoolManager {
+    // `num_instances` pools of `pool_size` frames each.
+    pub fn new(num_instances: usize, pool_size: usize, disk_manager: Arc<dyn DiskManager>, policy: ReplacerPolicy) -> Self {
+        Self::with_log_manager(num_instances, pool_size, Arc::new(DiskScheduler::new(disk_manager, 1)), policy, None)
+    }
+
+    pub fn with_log_manager(
+        num_instances: usize,
+        pool_size: usize,
+        disk_scheduler: Arc<DiskScheduler>,
+        policy: ReplacerPolicy,
+        log_manager: Option<Arc<LogManager>>,
+    ) -> Self {
+        assert!(num_instances > 0, "A parallel buffer pool needs at least one instance");
+        let clock: Arc<dyn TimestampClock> = Arc::new(LogicalClock::default());
+        let instances = (0..num_instances)
+            .map(|_| BufferPoolManager::with_log_manager(
+                pool_size,
+                disk_scheduler.clone(),
+                Arc::from(policy.build_with_clock(pool_size, Some(clock.clone()))),
+                log_manager.clone(),
+            ))
+            .collect();
+        ParallelBufferPoolManager { instances, disk_scheduler }
+    }
+
+    pub fn num_instances(&self) -> usize {
+        self.instances.len()
+    }
+
+    // Frames across every instance.
+    pub fn pool_size(&self) -> usize {
+        self.instances.iter().map(|instance| instance.pool_size()).sum()
+    }
+
+    pub fn disk_scheduler(&self) -> &Arc<DiskScheduler> {
+        &self.disk_scheduler
+    }
+
+    pub fn metrics_snapshot(&self) -> BufferPoolMetricsSnapshot {
+        self.instances.iter().map(|instance| instance.metrics_snapshot()).sum()
+    }
+
+    // Registers `listener` with every instance; see `BufferPoolManager::on_evict`.
+    pub fn on_evict(&self, listener: impl Fn(&EvictedPage) + Send + Sync + 'static) {
+        let listener = Arc::new(listener);
+        for instance in &self.instances {
+            let listener = listener.clone();
+            instance.on_evict(move |evicted| listener(evicted));
+        }
+    }
+
+    // The page id is allocated first, so it is the instance it maps to that has to find a
+    // frame; the id is given back if that instance is full.
+    pub fn new_page(&self) -> CrabDbResult<Arc<Page>> {
+        let page_id = self.disk_scheduler.disk_manager().allocate_page()?;
+        self.deallocate_on_error(page_id, self.instance(page_id).new_page_with_id(page_id))
+    }
+
+    pub fn fetch_page(&self, page_id: PageId) -> CrabDbResult<Arc<Page>> {
+        self.instance(page_id).fetch_page(page_id)
+    }
+
+    pub fn fetch_page_with_access(&self, page_id: PageId, access_type: AccessType) -> CrabDbResult<Arc<Page>> {
+        self.instance(page_id).fetch_page_with_access(page_id, access_type)
+    }
+
+    pub fn fetch_pages(&self, page_ids: &[PageId]) -> CrabDbResult<Vec<Arc<Page>>> {
+        self.prefetch(page_ids)?;
+        let mut pages = Vec::with_capacity(page_ids.len());
+        for &page_id in page_ids {
+            match self.fetch_page(page_id) {
+                Ok(page) => pages.push(page),
+                Err(e) => {
+                    for page in pages {
+                        self.unpin_page(page.page_id(), false)?;
+                    }
+                    return Err(e);
+                },
+            }
+        }
+        Ok(pages)
+    }
+
+    pub fn prefetch(&self, page_ids: &[PageId]) -> CrabDbResult<()> {
+        for &page_id in page_ids {
+            self.instance(page_id).prefetch(&[page_id])?;
+        }
+        Ok(())
+    }
+
+    pub fn new_page_write(&self) -> CrabDbResult<WritePageGuard<'_>> {
+        let page_id = self.disk_scheduler.disk_manager().allocate_page()?;
+        self.deallocate_on_error(page_id, self.instance(page_id).new_page_write_with_id(page_id))
+    }
+
+    pub fn fetch_page_read(&self, page_id: PageId) -> CrabDbResult<ReadPageGuard<'_>> {
+        self.instance(page_id).fetch_page_read(page_id)
+    }
+
+    pub fn fetch_page_read_with_access(&self, page_id: PageId, access_type: AccessType) -> CrabDbResult<ReadPageGuard<'_>> {
+        self.instance(page_id).fetch_page_read_with_access(page_id, access_type)
+    }
+
+    pub fn fetch_page_write(&self, page_id: PageId) -> CrabDbResult<WritePageGuard<'_>> {
+        self.instance(page_id).fetch_page_write(page_id)
+    }
+
+    pub fn pin_permanently(&self, page_id: PageId) -> CrabDbResult<()> {
+        self.instance(page_id).pin_permanently(page_id)
+    }
+
+    pub fn unpin_permanently(&self, page_id: PageId) -> CrabDbResult<()> {
+        self.instance(page_id).unpin_permanently(page_id)
+    }
+
+    pub fn unpin_page(&self, page_id: PageId, is_dirty: bool) -> CrabDbResult<()> {
+        self.instance(page_id).unpin_page(page_id, is_dirty)
+    }
+
+    pub fn flush_page(&self, page_id: PageId) -> CrabDbResult<()> {
+        self.instance(page_id).flush_page(page_id)
+    }
+
+    pub fn flush_all_pages(&self) -> CrabDbResult<()> {
+        for instance in &self.instances {
+            instance.flush_all_pages()?;
+        }
+        Ok(())
+    }
+
+    pub fn delete_page(&self, page_id: PageId) -> CrabDbResult<()> {
+        self.instance(page_id).delete_page(page_id)
+    }
+
+    fn instance(&self, page_id: PageId) -> &BufferPoolManager {
+        &self.instances[(page_id % self.instances.len() as PageId) as usize]
+    }
+
+    fn deallocate_on_error<T>(&self, page_id: PageId, result: CrabDbResult<T>) -> CrabDbResult<T> {
+        if result.is_err() {
+            let _ = self.disk_scheduler.disk_manager().deallocate_page(page_id);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use crate::buffer_pool::eviction::policy::ReplacerPolicy;
+    use crate::storage::common::PAGE_HEADER_SIZE;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use crate::types::CrabDBError;
+    use super::ParallelBufferPoolManager;
+
+    #[test]
+    pub fn test_parallel_bpm_shards_pages_by_id() {
+        let bpm = ParallelBufferPoolManager::new(2, 1, Arc::new(MemoryDiskManager::new()), ReplacerPolicy::Lru);
+        assert_eq!((2, 2), (bpm.num_instances(), bpm.pool_size()));
+
+        // pages 0 and 1 land in different instances, filling both
+        for page_id in 0..2 {
+            let page = bpm.new_page().unwrap();
+            assert_eq!(page_id, page.page_id());
+            page.write()[PAGE_HEADER_SIZE] = page_id as u8 + 1;
+        }
+        // page 2 belongs with the pinned page 0, so it does not fit even after page 1 is unpinned
+        assert!(bpm.unpin_page(1, true).is_ok());
+        assert!(matches!(bpm.new_page(), Err(CrabDBError::BufferPoolFull)));
+        // page 2 was given back, so the next page is 3, which evicts page 1
+        assert!(bpm.unpin_page(0, true).is_ok());
+        assert_eq!(3, bpm.new_page_write().unwrap().page_id());
+
+        assert_eq!(1, bpm.fetch_page_read(0).unwrap()[PAGE_HEADER_SIZE]);
+        assert_eq!(2, bpm.fetch_page_read(1).unwrap()[PAGE_HEADER_SIZE]);
+        let pages = bpm.fetch_pages(&[1, 0]).unwrap();
+        assert_eq!(vec![1, 0], pages.iter().map(|page| page.page_id()).collect::<Vec<_>>());
+        for page in pages {
+            assert!(bpm.unpin_page(page.page_id(), false).is_ok());
+        }
+        assert!(bpm.flush_all_pages().is_ok());
+        let metrics = bpm.metrics_snapshot();
+        assert_eq!((2, 3), (metrics.pool_size, metrics.new_pages));
+    }
+
+    #[test]
+    pub fn test_parallel_bpm_shared_across_threads() {
+        let bpm = Arc::new(ParallelBufferPoolManager::new(4, 4, Arc::new(MemoryDiskManager::new()), ReplacerPolicy::default()));
+        let handles: Vec<_> = (0..4).map(|_| {
+            let bpm = bpm.clone();
+            thread::spawn(move || {
+                for _ in 0..32 {
+                    let page_id = bpm.new_page().unwrap().page_id();
+                    bpm.fetch_page_write(page_id).unwrap()[PAGE_HEADER_SIZE] = page_id as u8;
+                    assert!(bpm.unpin_page(page_id, true).is_ok());
+                    assert_eq!(page_id as u8, bpm.fetch_page_read(page_id).unwrap()[PAGE_HEADER_SIZE]);
+                }
+            })
+        }).collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(128, bpm.metrics_snapshot().new_pages);
+    }
+}
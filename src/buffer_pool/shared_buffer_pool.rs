@@ -0,0 +1,147 @@
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+use crate::buffer_pool::common::FrameId;
+use crate::buffer_pool::frame_table::FrameTable;
+use crate::types::CrabDbResult;
+
+// Frames that several buffer pool managers draw from, one per database, so the databases of
+// an engine share one pool of memory. Each manager keeps its own page table and replacer for
+// the frames it holds, and holds at most its quota of them. Once every frame is taken, a
+// manager that needs one evicts a page of the lowest-priority manager that has one to evict,
+// among equals the one holding the most frames, itself included. Only when it has nothing
+// of its own to evict does it take a frame from a manager ranked above it.
+pub struct SharedBufferPool {
+    frames: Arc<FrameTable>,
+    capacity: usize,
+    free_list: Mutex<VecDeque<FrameId>>,
+    members: Mutex<Vec<Weak<BufferPoolManager>>>,
+}
+
+// Which manager gives up a frame first: lowest priority, then most frames held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct EvictionRank {
+    pub(crate) priority: u8,
+    pub(crate) frames_held: usize,
+}
+
+impl EvictionRank {
+    fn order(&self) -> (u8, Reverse<usize>) {
+        (self.priority, Reverse(self.frames_held))
+    }
+}
+
+impl SharedBufferPool {
+    pub fn new(capacity: usize) -> Self {
+        SharedBufferPool {
+            frames: Arc::new(FrameTable::new(capacity)),
+            capacity,
+            free_list: Mutex::new((0..capacity).collect()),
+            members: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn free_frames(&self) -> usize {
+        self.free_list.lock().unwrap().len()
+    }
+
+    pub(crate) fn frames(&self) -> &Arc<FrameTable> {
+        &self.frames
+    }
+
+    pub(crate) fn join(&self, member: &Arc<BufferPoolManager>) {
+        let mut members = self.members.lock().unwrap();
+        members.retain(|member| member.strong_count() > 0);
+        members.push(Arc::downgrade(member));
+    }
+
+    pub(crate) fn take_free_frame(&self) -> Option<FrameId> {
+        self.free_list.lock().unwrap().pop_front()
+    }
+
+    pub(crate) fn return_frames(&self, frame_ids: impl IntoIterator<Item = FrameId>) {
+        self.free_list.lock().unwrap().extend(frame_ids);
+    }
+
+    // Evicts a page of another member for `claimant` and hands it the frame: of the members
+    // that go before `rank`, or of any member if there is no rank. Members busy with their
+    // own pages are passed over rather than waited for, so two members reclaiming from each
+    // other cannot deadlock.
+    pub(crate) fn reclaim_frame(&self, claimant: &BufferPoolManager, rank: Option<EvictionRank>) -> CrabDbResult<Option<FrameId>> {
+        let members: Vec<_> = self.members.lock().unwrap().iter().filter_map(Weak::upgrade).collect();
+        let mut candidates: Vec<_> = members.iter()
+            .filter(|member| !std::ptr::eq(member.as_ref(), claimant))
+            .filter_map(|member| Some((member.eviction_rank()?, member)))
+            .filter(|(member_rank, _)| member_rank.frames_held > 0 && rank.is_none_or(|rank| member_rank.order() < rank.order()))
+            .collect();
+        candidates.sort_by_key(|(member_rank, _)| member_rank.order());
+        for (_, member) in candidates {
+            if let Some(frame_id) = member.release_frame()? {
+                return Ok(Some(frame_id));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru::lru_replacer::LRUReplacer;
+    use crate::storage::disk::disk_scheduler::DiskScheduler;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use crate::types::CrabDBError;
+    use super::SharedBufferPool;
+
+    fn member(pool: &Arc<SharedBufferPool>, quota: usize, priority: u8) -> Arc<BufferPoolManager> {
+        let disk_scheduler = Arc::new(DiskScheduler::new(Arc::new(MemoryDiskManager::new()), 1));
+        BufferPoolManager::in_shared_pool(pool, quota, priority, disk_scheduler, Arc::new(LRUReplacer::new(pool.capacity())), None)
+    }
+
+    // Adds `count` unpinned pages.
+    fn add_pages(bpm: &BufferPoolManager, count: usize) {
+        for _ in 0..count {
+            let page_id = bpm.new_page().unwrap().page_id();
+            bpm.unpin_page(page_id, true).unwrap();
+        }
+    }
+
+    #[test]
+    pub fn test_shared_buffer_pool_evicts_by_priority() {
+        let pool = Arc::new(SharedBufferPool::new(8));
+        let (low, high, capped) = (member(&pool, 8, 0), member(&pool, 8, 1), member(&pool, 2, 2));
+        add_pages(&capped, 4);
+        assert_eq!(2, capped.frames_held());
+
+        add_pages(&low, 6);
+        assert_eq!(0, pool.free_frames());
+        // a full pool takes frames from the lowest priority first
+        add_pages(&high, 4);
+        assert_eq!((2, 4, 2), (low.frames_held(), high.frames_held(), capped.frames_held()));
+        // and a lower priority makes do with its own
+        add_pages(&low, 3);
+        assert_eq!((2, 4), (low.frames_held(), high.frames_held()));
+
+        // with nothing of its own to evict, it takes from whoever has something
+        for _ in 0..3 {
+            low.new_page().unwrap();
+        }
+        assert_eq!((3, 3), (low.frames_held(), high.frames_held()));
+        // but never past its quota
+        assert!(capped.new_page().is_ok());
+        assert!(capped.new_page().is_ok());
+        assert!(matches!(capped.new_page(), Err(CrabDBError::BufferPoolFull)));
+
+        // a manager's frames go back to the pool with it
+        drop(high);
+        assert!(pool.free_frames() >= 2);
+    }
+}
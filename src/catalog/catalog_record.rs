@@ -0,0 +1,366 @@
+use std::time::Duration;
+
+use crate::index::table_index::IndexKind;
+use crate::storage::common::PageId;
+use crate::types::schema::{Column, Schema};
+use crate::types::value::{DataType, Value};
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::statistics::{ColumnStatistics, TableStatistics};
+use super::table_info::{MaterializedView, Oid, Ttl};
+
+// One entry of the catalog heap. Serialized layout, all integers little endian:
+//   table: 1 | oid u32 | name | first_page_id u64 | column count u16 | (name | type u8 | nullable u8)*
+//          [| ttl column u16 | ttl micros u64]
+//   index: 2 | oid u32 | name | table_oid u32 | kind u8 | root_page_id u64 | key count u16 | column index u16*
+//   statistics: 3 | table_oid u32 | row_count u64 | column count u16
+//               | (null_count u64 | distinct_count u64 | bound count u16 | (type u8 | value)*)*
+//   kv store: 4 | oid u32 | name | first_page_id u64 | tree_header_page_id u64
+//   free space map: 5 | first_page_id u64
+//   materialized view: 6 | table_oid u32 | query length u32 | query | source count u16 | oid u32*
+//                      [| state column count u16]
+//   page size: 7 | page_size u32
+// where every name is a u16 length followed by UTF-8 bytes, and an index's root page is the
+// page it is reopened from (the header of a B+ tree, which full-text indexes are too, or a
+// hash directory). Tables without a TTL end after their columns, as they did before TTLs, and
+// views without state columns after their sources.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum CatalogRecord {
+    Table {
+        oid: Oid,
+        name: String,
+        first_page_id: PageId,
+        schema: Schema,
+        ttl: Option<Ttl>,
+    },
+    Index {
+        oid: Oid,
+        name: String,
+        table_oid: Oid,
+        kind: IndexKind,
+        root_page_id: PageId,
+        key_columns: Vec<usize>,
+    },
+    Statistics {
+        table_oid: Oid,
+        statistics: TableStatistics,
+    },
+    KvStore {
+        oid: Oid,
+        name: String,
+        first_page_id: PageId,
+        tree_header_page_id: PageId,
+    },
+    FreeSpaceMap {
+        first_page_id: PageId,
+    },
+    // Follows the record of the table the view is kept in.
+    MaterializedView {
+        table_oid: Oid,
+        view: MaterializedView,
+    },
+    // The bytes per page of the database file, recorded when it is created. Files from before
+    // it have pages of PAGE_SIZE bytes.
+    PageSize {
+        page_size: u32,
+    },
+}
+
+const TABLE_TAG: u8 = 1;
+const INDEX_TAG: u8 = 2;
+const STATISTICS_TAG: u8 = 3;
+const KV_STORE_TAG: u8 = 4;
+const FREE_SPACE_MAP_TAG: u8 = 5;
+const MATERIALIZED_VIEW_TAG: u8 = 6;
+const PAGE_SIZE_TAG: u8 = 7;
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(&(s.len() as u16).to_le_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn write_data_type(out: &mut Vec<u8>, data_type: DataType) {
+    out.push(data_type.tag());
+    if let DataType::Vector(dimensions) = data_type {
+        out.extend_from_slice(&dimensions.to_le_bytes());
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> CrabDbResult<&'a [u8]> {
+        let bytes = self.bytes.get(self.position..self.position + len)
+            .ok_or_else(|| CrabDBError::Corruption("Catalog record is truncated".into()))?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> CrabDbResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> CrabDbResult<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> CrabDbResult<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> CrabDbResult<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn str(&mut self) -> CrabDbResult<String> {
+        let len = self.u16()? as usize;
+        self.utf8(len)
+    }
+
+    fn utf8(&mut self, len: usize) -> CrabDbResult<String> {
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| CrabDBError::Corruption("Catalog record holds a name that is not valid UTF-8".into()))
+    }
+
+    fn data_type(&mut self) -> CrabDbResult<DataType> {
+        let tag = self.u8()?;
+        match DataType::from_tag(tag) {
+            Some(DataType::Vector(_)) => Ok(DataType::Vector(self.u16()?)),
+            Some(data_type) => Ok(data_type),
+            None => Err(CrabDBError::Corruption(format!("Unknown data type tag {tag} in catalog"))),
+        }
+    }
+
+    fn value(&mut self) -> CrabDbResult<Value> {
+        let data_type = self.data_type()?;
+        let (value, len) = Value::deserialize(data_type, &self.bytes[self.position..])
+            .map_err(|_| CrabDBError::Corruption("Catalog record is truncated".into()))?;
+        self.position += len;
+        Ok(value)
+    }
+}
+
+impl CatalogRecord {
+    pub(crate) fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            CatalogRecord::Table { oid, name, first_page_id, schema, ttl } => {
+                out.push(TABLE_TAG);
+                out.extend_from_slice(&oid.to_le_bytes());
+                write_str(&mut out, name);
+                out.extend_from_slice(&first_page_id.to_le_bytes());
+                out.extend_from_slice(&(schema.column_count() as u16).to_le_bytes());
+                for column in schema.columns() {
+                    write_str(&mut out, column.name());
+                    write_data_type(&mut out, column.data_type());
+                    out.push(column.is_nullable() as u8);
+                }
+                if let Some(ttl) = ttl {
+                    out.extend_from_slice(&(ttl.column as u16).to_le_bytes());
+                    out.extend_from_slice(&(ttl.after.as_micros() as u64).to_le_bytes());
+                }
+            },
+            CatalogRecord::Index { oid, name, table_oid, kind, root_page_id, key_columns } => {
+                out.push(INDEX_TAG);
+                out.extend_from_slice(&oid.to_le_bytes());
+                write_str(&mut out, name);
+                out.extend_from_slice(&table_oid.to_le_bytes());
+                out.push(kind.tag());
+                out.extend_from_slice(&root_page_id.to_le_bytes());
+                out.extend_from_slice(&(key_columns.len() as u16).to_le_bytes());
+                for key_column in key_columns {
+                    out.extend_from_slice(&(*key_column as u16).to_le_bytes());
+                }
+            },
+            CatalogRecord::Statistics { table_oid, statistics } => {
+                out.push(STATISTICS_TAG);
+                out.extend_from_slice(&table_oid.to_le_bytes());
+                out.extend_from_slice(&statistics.row_count.to_le_bytes());
+                out.extend_from_slice(&(statistics.columns.len() as u16).to_le_bytes());
+                for column in &statistics.columns {
+                    out.extend_from_slice(&column.null_count.to_le_bytes());
+                    out.extend_from_slice(&column.distinct_count.to_le_bytes());
+                    out.extend_from_slice(&(column.histogram.len() as u16).to_le_bytes());
+                    // Histogram bounds are never NULL, so every one has a type.
+                    for bound in &column.histogram {
+                        write_data_type(&mut out, bound.data_type().unwrap());
+                        bound.serialize(&mut out);
+                    }
+                }
+            },
+            CatalogRecord::KvStore { oid, name, first_page_id, tree_header_page_id } => {
+                out.push(KV_STORE_TAG);
+                out.extend_from_slice(&oid.to_le_bytes());
+                write_str(&mut out, name);
+                out.extend_from_slice(&first_page_id.to_le_bytes());
+                out.extend_from_slice(&tree_header_page_id.to_le_bytes());
+            },
+            CatalogRecord::FreeSpaceMap { first_page_id } => {
+                out.push(FREE_SPACE_MAP_TAG);
+                out.extend_from_slice(&first_page_id.to_le_bytes());
+            },
+            CatalogRecord::PageSize { page_size } => {
+                out.push(PAGE_SIZE_TAG);
+                out.extend_from_slice(&page_size.to_le_bytes());
+            },
+            CatalogRecord::MaterializedView { table_oid, view } => {
+                out.push(MATERIALIZED_VIEW_TAG);
+                out.extend_from_slice(&table_oid.to_le_bytes());
+                out.extend_from_slice(&(view.query.len() as u32).to_le_bytes());
+                out.extend_from_slice(view.query.as_bytes());
+                out.extend_from_slice(&(view.sources.len() as u16).to_le_bytes());
+                for source in &view.sources {
+                    out.extend_from_slice(&source.to_le_bytes());
+                }
+                if view.state_columns > 0 {
+                    out.extend_from_slice(&(view.state_columns as u16).to_le_bytes());
+                }
+            },
+        }
+        out
+    }
+
+    pub(crate) fn deserialize(bytes: &[u8]) -> CrabDbResult<Self> {
+        let mut reader = Reader { bytes, position: 0 };
+        match reader.u8()? {
+            TABLE_TAG => {
+                let oid = reader.u32()?;
+                let name = reader.str()?;
+                let first_page_id = reader.u64()?;
+                let columns = (0..reader.u16()?).map(|_| {
+                    let name = reader.str()?;
+                    let data_type = reader.data_type()?;
+                    let column = Column::new(name, data_type);
+                    Ok(if reader.u8()? == 0 { column.not_null() } else { column })
+                }).collect::<CrabDbResult<Vec<_>>>()?;
+                let ttl = match reader.position < reader.bytes.len() {
+                    true => Some(Ttl { column: reader.u16()? as usize, after: Duration::from_micros(reader.u64()?) }),
+                    false => None,
+                };
+                Ok(CatalogRecord::Table { oid, name, first_page_id, schema: Schema::new(columns), ttl })
+            },
+            INDEX_TAG => {
+                let oid = reader.u32()?;
+                let name = reader.str()?;
+                let table_oid = reader.u32()?;
+                let tag = reader.u8()?;
+                let kind = IndexKind::from_tag(tag)
+                    .ok_or_else(|| CrabDBError::Corruption(format!("Unknown index kind tag {tag} in catalog")))?;
+                let root_page_id = reader.u64()?;
+                let key_columns = (0..reader.u16()?)
+                    .map(|_| Ok(reader.u16()? as usize))
+                    .collect::<CrabDbResult<Vec<_>>>()?;
+                Ok(CatalogRecord::Index { oid, name, table_oid, kind, root_page_id, key_columns })
+            },
+            STATISTICS_TAG => {
+                let table_oid = reader.u32()?;
+                let row_count = reader.u64()?;
+                let columns = (0..reader.u16()?).map(|_| {
+                    let null_count = reader.u64()?;
+                    let distinct_count = reader.u64()?;
+                    let histogram = (0..reader.u16()?)
+                        .map(|_| reader.value())
+                        .collect::<CrabDbResult<Vec<_>>>()?;
+                    Ok(ColumnStatistics { null_count, distinct_count, histogram })
+                }).collect::<CrabDbResult<Vec<_>>>()?;
+                Ok(CatalogRecord::Statistics { table_oid, statistics: TableStatistics { row_count, columns } })
+            },
+            KV_STORE_TAG => {
+                let oid = reader.u32()?;
+                let name = reader.str()?;
+                let first_page_id = reader.u64()?;
+                let tree_header_page_id = reader.u64()?;
+                Ok(CatalogRecord::KvStore { oid, name, first_page_id, tree_header_page_id })
+            },
+            FREE_SPACE_MAP_TAG => Ok(CatalogRecord::FreeSpaceMap { first_page_id: reader.u64()? }),
+            PAGE_SIZE_TAG => Ok(CatalogRecord::PageSize { page_size: reader.u32()? }),
+            MATERIALIZED_VIEW_TAG => {
+                let table_oid = reader.u32()?;
+                let len = reader.u32()? as usize;
+                let query = reader.utf8(len)?;
+                let sources = (0..reader.u16()?)
+                    .map(|_| reader.u32())
+                    .collect::<CrabDbResult<Vec<_>>>()?;
+                let state_columns = match reader.position < reader.bytes.len() {
+                    true => reader.u16()? as usize,
+                    false => 0,
+                };
+                Ok(CatalogRecord::MaterializedView { table_oid, view: MaterializedView { query, sources, state_columns } })
+            },
+            tag => Err(CrabDBError::Corruption(format!("Unknown catalog record tag {tag}"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::catalog::statistics::{ColumnStatistics, TableStatistics};
+    use crate::catalog::table_info::{MaterializedView, Ttl};
+    use crate::index::table_index::IndexKind;
+    use crate::types::schema::{Column, Schema};
+    use crate::types::value::{DataType, Value};
+    use super::CatalogRecord;
+
+    #[test]
+    pub fn test_catalog_record_round_trip() {
+        let records = [
+            CatalogRecord::Table {
+                oid: 1,
+                name: "users".into(),
+                first_page_id: 7,
+                schema: Schema::new(vec![
+                    Column::new("id", DataType::Int64).not_null(),
+                    Column::new("email", DataType::Varchar),
+                    Column::new("embedding", DataType::Vector(3)),
+                ]),
+                ttl: None,
+            },
+            CatalogRecord::Table {
+                oid: 4,
+                name: "sessions".into(),
+                first_page_id: 13,
+                schema: Schema::new(vec![Column::new("touched_at", DataType::Timestamp)]),
+                ttl: Some(Ttl { column: 0, after: Duration::from_secs(3600) }),
+            },
+            CatalogRecord::Index {
+                oid: 2,
+                name: "users_email".into(),
+                table_oid: 1,
+                kind: IndexKind::Hash,
+                root_page_id: 9,
+                key_columns: vec![1],
+            },
+            CatalogRecord::Statistics {
+                table_oid: 1,
+                statistics: TableStatistics {
+                    row_count: 40,
+                    columns: vec![
+                        ColumnStatistics { null_count: 0, distinct_count: 40, histogram: vec![Value::Int64(1), Value::Int64(20), Value::Int64(40)] },
+                        ColumnStatistics { null_count: 4, distinct_count: 2, histogram: vec![Value::Varchar("a@crab.db".into()), Value::Varchar("b@crab.db".into())] },
+                    ],
+                },
+            },
+            CatalogRecord::KvStore { oid: 3, name: "sessions".into(), first_page_id: 11, tree_header_page_id: 12 },
+            CatalogRecord::FreeSpaceMap { first_page_id: 1 },
+            CatalogRecord::PageSize { page_size: 4096 },
+            CatalogRecord::MaterializedView {
+                table_oid: 5,
+                view: MaterializedView { query: "SELECT id, COUNT(*) FROM users GROUP BY id".into(), sources: vec![1], state_columns: 0 },
+            },
+            CatalogRecord::MaterializedView {
+                table_oid: 6,
+                view: MaterializedView { query: "SELECT AVG(id) FROM users".into(), sources: vec![1], state_columns: 2 },
+            },
+        ];
+        for record in records {
+            let bytes = record.serialize();
+            assert_eq!(record, CatalogRecord::deserialize(&bytes).unwrap());
+            assert!(CatalogRecord::deserialize(&bytes[..bytes.len() - 1]).is_err());
+        }
+    }
+}
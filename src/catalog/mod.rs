@@ -0,0 +1,6 @@
+pub(crate) mod catalog_record;
+pub mod statistics;
+pub mod system_catalog;
+pub mod table_info;
+pub mod trigger;
+pub mod udf;
@@ -0,0 +1,267 @@
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::hash::{DefaultHasher, Hasher};
+use std::ops::Bound;
+
+use crate::execution::hash_key::group_key;
+use crate::types::value::Value;
+use crate::types::CrabDbResult;
+
+// How many buckets a column histogram has.
+pub const HISTOGRAM_BUCKETS: usize = 16;
+// How many values per column ANALYZE keeps to build histograms from.
+pub const SAMPLE_SIZE: usize = 4096;
+// How many of the smallest value hashes the distinct count estimate keeps.
+const DISTINCT_SKETCH_SIZE: usize = 1024;
+
+// What ANALYZE learned about the values of one column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnStatistics {
+    pub null_count: u64,
+    pub distinct_count: u64,
+    // Bounds of an equi-depth histogram over the non-NULL values: each of the buckets between
+    // consecutive bounds holds about as many values. The first and last bounds are the
+    // column's minimum and maximum. Empty if the column holds only NULLs.
+    pub histogram: Vec<Value>,
+}
+
+// What ANALYZE learned about a table, with one entry per column of its schema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableStatistics {
+    pub row_count: u64,
+    pub columns: Vec<ColumnStatistics>,
+}
+
+impl ColumnStatistics {
+    pub fn min(&self) -> Option<&Value> {
+        self.histogram.first()
+    }
+
+    pub fn max(&self) -> Option<&Value> {
+        self.histogram.last()
+    }
+
+    fn non_null_fraction(&self, row_count: u64) -> f64 {
+        if row_count == 0 {
+            return 0.0;
+        }
+        row_count.saturating_sub(self.null_count) as f64 / row_count as f64
+    }
+
+    // Estimated fraction of the table's rows where the column equals a given value.
+    pub fn equal_fraction(&self, row_count: u64) -> f64 {
+        if self.distinct_count == 0 {
+            return 0.0;
+        }
+        self.non_null_fraction(row_count) / self.distinct_count as f64
+    }
+
+    // Estimated fraction of the table's rows where the column is between the bounds.
+    pub fn range_fraction(&self, row_count: u64, start: Bound<&Value>, end: Bound<&Value>) -> f64 {
+        let below = |bound: Bound<&Value>, unbounded: f64| match bound {
+            Bound::Included(value) | Bound::Excluded(value) => self.fraction_below(value),
+            Bound::Unbounded => unbounded,
+        };
+        let fraction = (below(end, 1.0) - below(start, 0.0)).max(0.0);
+        // A range with equal bounds still holds the values equal to them.
+        let fraction = fraction.max(match (start, end) {
+            (Bound::Included(_), Bound::Included(_)) => self.equal_fraction(row_count) / self.non_null_fraction(row_count).max(f64::MIN_POSITIVE),
+            _ => 0.0,
+        });
+        fraction.min(1.0) * self.non_null_fraction(row_count)
+    }
+
+    // Estimated fraction of the non-NULL values below `value`, interpolating linearly inside
+    // a bucket when the values are numbers and assuming half the bucket otherwise.
+    fn fraction_below(&self, value: &Value) -> f64 {
+        let bounds = &self.histogram;
+        let compare = |bound: &Value| bound.compare(value).unwrap_or(Ordering::Equal);
+        if bounds.len() < 2 {
+            // Without buckets, assume a third of the values on either side.
+            return match bounds.first().map(compare) {
+                Some(Ordering::Greater) => 0.0,
+                Some(Ordering::Less) => 1.0,
+                _ => 1.0 / 3.0,
+            };
+        }
+        let buckets = (bounds.len() - 1) as f64;
+        let Some(bucket) = bounds.windows(2).position(|bucket| compare(&bucket[1]) != Ordering::Less) else {
+            return 1.0;
+        };
+        if compare(&bounds[0]) != Ordering::Less {
+            return 0.0;
+        }
+        let (low, high) = (&bounds[bucket], &bounds[bucket + 1]);
+        let within = match (low.as_f64(), high.as_f64(), value.as_f64()) {
+            (Some(low), Some(high), Some(value)) if high > low => ((value - low) / (high - low)).clamp(0.0, 1.0),
+            _ => 0.5,
+        };
+        (bucket as f64 + within) / buckets
+    }
+}
+
+// Estimates how many distinct values it was shown by keeping the smallest hashes it saw: if
+// the k-th smallest of n uniformly spread hashes sits at fraction f of the hash space, there
+// are about (k - 1) / f of them.
+struct DistinctSketch {
+    smallest: BTreeSet<u64>,
+}
+
+impl DistinctSketch {
+    fn insert(&mut self, value: &Value) {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(&group_key(std::slice::from_ref(value)));
+        let hash = hasher.finish();
+        if self.smallest.len() < DISTINCT_SKETCH_SIZE {
+            self.smallest.insert(hash);
+        } else if hash < *self.smallest.last().unwrap() && self.smallest.insert(hash) {
+            self.smallest.pop_last();
+        }
+    }
+
+    fn estimate(&self) -> u64 {
+        if self.smallest.len() < DISTINCT_SKETCH_SIZE {
+            return self.smallest.len() as u64;
+        }
+        let fraction = *self.smallest.last().unwrap() as f64 / u64::MAX as f64;
+        ((DISTINCT_SKETCH_SIZE - 1) as f64 / fraction) as u64
+    }
+}
+
+// splitmix64
+fn next_random(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+struct ColumnBuilder {
+    null_count: u64,
+    non_null_count: u64,
+    min: Option<Value>,
+    max: Option<Value>,
+    distinct: DistinctSketch,
+    // A uniform sample of the non-NULL values, kept by reservoir sampling.
+    sample: Vec<Value>,
+}
+
+// Builds TableStatistics from one pass over a table's rows.
+pub struct StatisticsBuilder {
+    row_count: u64,
+    columns: Vec<ColumnBuilder>,
+    // State of the random number generator used for sampling, seeded so ANALYZE of the same
+    // rows gives the same statistics.
+    random: u64,
+}
+
+impl StatisticsBuilder {
+    pub fn new(column_count: usize) -> Self {
+        let columns = (0..column_count).map(|_| ColumnBuilder {
+            null_count: 0,
+            non_null_count: 0,
+            min: None,
+            max: None,
+            distinct: DistinctSketch { smallest: BTreeSet::new() },
+            sample: Vec::new(),
+        }).collect();
+        StatisticsBuilder { row_count: 0, columns, random: 0x2545_f491_4f6c_dd1d }
+    }
+
+    pub fn add_row(&mut self, row: &[Value]) {
+        self.row_count += 1;
+        for (column, value) in self.columns.iter_mut().zip(row) {
+            if value.is_null() {
+                column.null_count += 1;
+                continue;
+            }
+            let random = next_random(&mut self.random);
+            column.non_null_count += 1;
+            if column.min.as_ref().is_none_or(|min| value.compare(min) == Some(Ordering::Less)) {
+                column.min = Some(value.clone());
+            }
+            if column.max.as_ref().is_none_or(|max| value.compare(max) == Some(Ordering::Greater)) {
+                column.max = Some(value.clone());
+            }
+            column.distinct.insert(value);
+            if column.sample.len() < SAMPLE_SIZE {
+                column.sample.push(value.clone());
+            } else {
+                let slot = (random % column.non_null_count) as usize;
+                if slot < SAMPLE_SIZE {
+                    column.sample[slot] = value.clone();
+                }
+            }
+        }
+    }
+
+    pub fn build(self) -> TableStatistics {
+        let columns = self.columns.into_iter().map(|mut column| {
+            column.sample.sort_by(|a, b| a.compare(b).unwrap_or(Ordering::Equal));
+            let mut histogram = Vec::new();
+            if let (Some(min), Some(max)) = (column.min, column.max) {
+                histogram.push(min);
+                let last = column.sample.len().saturating_sub(1);
+                for bucket in 1..HISTOGRAM_BUCKETS.min(column.sample.len()) {
+                    histogram.push(column.sample[bucket * last / HISTOGRAM_BUCKETS.min(column.sample.len())].clone());
+                }
+                histogram.push(max);
+            }
+            ColumnStatistics {
+                null_count: column.null_count,
+                distinct_count: column.distinct.estimate().min(column.non_null_count),
+                histogram,
+            }
+        }).collect();
+        TableStatistics { row_count: self.row_count, columns }
+    }
+}
+
+impl TableStatistics {
+    // Collects statistics from every row of a table.
+    pub fn collect(column_count: usize, rows: impl IntoIterator<Item = CrabDbResult<Vec<Value>>>) -> CrabDbResult<Self> {
+        let mut builder = StatisticsBuilder::new(column_count);
+        for row in rows {
+            builder.add_row(&row?);
+        }
+        Ok(builder.build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use crate::types::value::Value;
+    use super::TableStatistics;
+
+    #[test]
+    pub fn test_statistics_estimates() {
+        // id is unique, size has 10 values, name is NULL on every fourth row
+        let rows = (0..20_000i64).map(|id| {
+            let name = if id % 4 == 0 { Value::Null } else { Value::Varchar(format!("crab{}", id % 500)) };
+            Ok(vec![Value::Int64(id), Value::Int32((id % 10) as i32), name])
+        });
+        let stats = TableStatistics::collect(3, rows).unwrap();
+        assert_eq!(20_000, stats.row_count);
+        let [id, size, name] = &stats.columns[..] else {
+            panic!("expected three columns");
+        };
+        assert_eq!((Some(&Value::Int64(0)), Some(&Value::Int64(19_999))), (id.min(), id.max()));
+        assert_eq!(17, id.histogram.len());
+        assert!((18_000..22_000).contains(&id.distinct_count), "{}", id.distinct_count);
+        assert_eq!(10, size.distinct_count);
+        assert_eq!(5_000, name.null_count);
+        // the rows that would hold crab0, crab4, ... are the NULL ones; small counts are exact
+        assert_eq!(375, name.distinct_count);
+
+        assert!((id.equal_fraction(stats.row_count) - 1.0 / 20_000.0).abs() < 1e-5);
+        assert!((size.equal_fraction(stats.row_count) - 0.1).abs() < 1e-9);
+        let quarter = id.range_fraction(stats.row_count, Bound::Included(&Value::Int64(5_000)), Bound::Excluded(&Value::Int64(10_000)));
+        assert!((quarter - 0.25).abs() < 0.03, "{quarter}");
+        let open = id.range_fraction(stats.row_count, Bound::Excluded(&Value::Int64(19_000)), Bound::Unbounded);
+        assert!((open - 0.05).abs() < 0.02, "{open}");
+        assert_eq!(0.0, id.range_fraction(stats.row_count, Bound::Unbounded, Bound::Excluded(&Value::Int64(-1))));
+    }
+}
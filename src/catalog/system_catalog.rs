@@ -0,0 +1,730 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+use crate::index::bplus_tree::BPlusTree;
+use crate::index::extendible_hash::ExtendibleHashIndex;
+use crate::index::full_text::FullTextIndex;
+use crate::index::hnsw::HnswIndex;
+use crate::index::rtree::RTreeIndex;
+use crate::index::index_key::key_size;
+use crate::index::table_index::{Index, IndexKind};
+use crate::storage::common::{PageId, PAGE_SIZE};
+use crate::storage::free_space_map::FreeSpaceMap;
+use crate::storage::page::table_page::MAX_TUPLE_SIZE;
+use crate::storage::table::table_heap::TableHeap;
+use crate::storage::table::tuple::{Rid, Tuple};
+use crate::types::schema::Schema;
+use crate::types::value::DataType;
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::catalog_record::CatalogRecord;
+use super::statistics::TableStatistics;
+use super::table_info::{IndexInfo, KvStoreInfo, MaterializedView, Oid, TableInfo, Ttl};
+use super::trigger::Trigger;
+use super::udf::{AggregateUdf, ScalarUdf, Udf, BUILTIN_FUNCTIONS};
+
+// The catalog heap always starts on the first page of the database file.
+pub const CATALOG_PAGE_ID: PageId = 0;
+
+// Identifies a session, which temporary tables belong to.
+pub type SessionId = u64;
+
+// Tracks every table and index. Entries are persisted as records in a table heap rooted at
+// `CATALOG_PAGE_ID` and read back into memory when the catalog is opened. Names are
+// case-insensitive.
+//
+// Temporary tables are the exception: they live in the temporary storage, apart from the
+// database's pages, and only in memory here, each seen by the session that created it alone
+// until the session drops it. They have no indexes, and their statistics are not persisted.
+// Neither are triggers and user-defined functions, which are callbacks of the embedder,
+// registered again on every open.
+pub struct Catalog {
+    bpm: Arc<BufferPoolManager>,
+    temp_bpm: Option<Arc<BufferPoolManager>>,
+    heap: TableHeap,
+    state: RwLock<CatalogState>,
+    // The triggers of each table, in the order they were created, which is the order they run in.
+    triggers: RwLock<HashMap<Oid, Vec<Arc<Trigger>>>>,
+    // User-defined functions by lowercase name.
+    functions: RwLock<HashMap<String, Udf>>,
+}
+
+#[derive(Default)]
+struct CatalogState {
+    tables: HashMap<String, Arc<TableInfo>>,
+    table_names: HashMap<Oid, String>,
+    indexes: HashMap<String, Arc<IndexInfo>>,
+    // The latest statistics of each analyzed table, with the rid of their catalog record
+    // unless the table is temporary.
+    statistics: HashMap<Oid, (Option<Rid>, Arc<TableStatistics>)>,
+    kv_stores: HashMap<String, Arc<KvStoreInfo>>,
+    // The materialized views, by the oid of the table each is kept in.
+    views: HashMap<Oid, Arc<MaterializedView>>,
+    // As recorded when the database file was created, if it was.
+    page_size: Option<usize>,
+    temp_tables: HashMap<SessionId, HashMap<String, Arc<TableInfo>>>,
+    next_oid: Oid,
+}
+
+impl CatalogState {
+    fn add_table(&mut self, table: TableInfo) -> Arc<TableInfo> {
+        let table = Arc::new(table);
+        self.next_oid = self.next_oid.max(table.oid() + 1);
+        self.table_names.insert(table.oid(), table.name().to_lowercase());
+        self.tables.insert(table.name().to_lowercase(), table.clone());
+        table
+    }
+
+    fn add_index(&mut self, index: IndexInfo) -> Arc<IndexInfo> {
+        let index = Arc::new(index);
+        self.next_oid = self.next_oid.max(index.oid() + 1);
+        self.indexes.insert(index.name().to_lowercase(), index.clone());
+        index
+    }
+
+    fn add_kv_store(&mut self, kv_store: KvStoreInfo) -> Arc<KvStoreInfo> {
+        let kv_store = Arc::new(kv_store);
+        self.next_oid = self.next_oid.max(kv_store.oid() + 1);
+        self.kv_stores.insert(kv_store.name().to_lowercase(), kv_store.clone());
+        kv_store
+    }
+}
+
+// The column a TTL counts from must be a TIMESTAMP.
+fn check_ttl(table_name: &str, schema: &Schema, ttl: Option<Ttl>) -> CrabDbResult<()> {
+    match ttl {
+        Some(ttl) if ttl.column >= schema.column_count() || schema.column(ttl.column).data_type() != DataType::Timestamp => {
+            Err(CrabDBError::InvalidInput(format!("The TTL of table {table_name} must count from a TIMESTAMP column")))
+        },
+        _ => Ok(()),
+    }
+}
+
+fn key_schema(table_schema: &Schema, key_columns: &[usize]) -> Schema {
+    Schema::new(key_columns.iter().map(|&column| table_schema.column(column).clone()).collect())
+}
+
+impl Catalog {
+    // Opens the catalog of the database behind `bpm`, creating it if the database is empty.
+    pub fn open(bpm: Arc<BufferPoolManager>) -> CrabDbResult<Self> {
+        if bpm.disk_scheduler().disk_manager().num_pages() == 0 {
+            let heap = TableHeap::new(bpm.clone())?;
+            if heap.first_page_id() != CATALOG_PAGE_ID {
+                return Err(CrabDBError::new(format!(
+                    "Catalog must start on page {CATALOG_PAGE_ID}, got page {}", heap.first_page_id()
+                )));
+            }
+            // Every statement looks tables up here, so the first catalog page never leaves the pool.
+            bpm.pin_permanently(CATALOG_PAGE_ID)?;
+            heap.insert_tuple(&Tuple::new(CatalogRecord::PageSize { page_size: PAGE_SIZE as u32 }.serialize()))?;
+            Self::create_free_space_map(&bpm, &heap)?;
+            let state = CatalogState { page_size: Some(PAGE_SIZE), ..CatalogState::default() };
+            return Ok(Catalog { bpm, temp_bpm: None, heap, state: RwLock::new(state), triggers: RwLock::default(), functions: RwLock::default() });
+        }
+
+        let heap = TableHeap::open(bpm.clone(), CATALOG_PAGE_ID)?;
+        let (state, free_space_map) = Self::read_state(&bpm, &heap)?;
+        if let Some(page_size) = state.page_size.filter(|&page_size| page_size != PAGE_SIZE) {
+            return Err(CrabDBError::InvalidInput(format!(
+                "The database was created with pages of {page_size} bytes; this build reads pages of {PAGE_SIZE}"
+            )));
+        }
+        bpm.pin_permanently(CATALOG_PAGE_ID)?;
+        match free_space_map {
+            Some(free_space_map) => bpm.set_free_space_map(free_space_map)?,
+            // Databases from before the free space map get one on their next open.
+            None => Self::create_free_space_map(&bpm, &heap)?,
+        }
+        Ok(Catalog { bpm, temp_bpm: None, heap, state: RwLock::new(state), triggers: RwLock::default(), functions: RwLock::default() })
+    }
+
+    // Keeps temporary tables in the pages of `temp_bpm`, which nothing logs; see
+    // `TempDiskManager`. Without it they cannot be created.
+    pub fn with_temp_storage(mut self, temp_bpm: Arc<BufferPoolManager>) -> Self {
+        self.temp_bpm = Some(temp_bpm);
+        self
+    }
+
+    // Reads the catalog again from its pages, for a replica whose pages the primary's log
+    // changed underneath: tables, indexes and key-value stores are opened afresh, so none
+    // keeps a stale root or page list. The catalog must not change meanwhile.
+    pub(crate) fn reload(&self) -> CrabDbResult<()> {
+        let heap = TableHeap::open(self.bpm.clone(), CATALOG_PAGE_ID)?;
+        let (state, _) = Self::read_state(&self.bpm, &heap)?;
+        *self.state.write().unwrap() = state;
+        Ok(())
+    }
+
+    fn read_state(bpm: &Arc<BufferPoolManager>, heap: &TableHeap) -> CrabDbResult<(CatalogState, Option<FreeSpaceMap>)> {
+        let mut state = CatalogState::default();
+        let mut free_space_map = None;
+        for entry in heap.iter() {
+            let (rid, tuple) = entry?;
+            match CatalogRecord::deserialize(tuple.data())? {
+                CatalogRecord::Table { oid, name, first_page_id, schema, ttl } => {
+                    let table_heap = TableHeap::open(bpm.clone(), first_page_id)?;
+                    state.add_table(TableInfo::new(oid, name, schema, table_heap).with_ttl(ttl));
+                },
+                CatalogRecord::Index { oid, name, table_oid, kind, root_page_id, key_columns } => {
+                    let table_schema = state.table_names.get(&table_oid)
+                        .and_then(|table_name| state.tables.get(table_name))
+                        .map(|table| table.schema().clone())
+                        .ok_or_else(|| CrabDBError::Corruption(format!(
+                            "Index {name} belongs to table {table_oid}, which is not in the catalog"
+                        )))?;
+                    let index: Arc<dyn Index> = match kind {
+                        IndexKind::BPlusTree => Arc::new(BPlusTree::open(bpm.clone(), root_page_id)?),
+                        IndexKind::Hash => Arc::new(ExtendibleHashIndex::open(bpm.clone(), root_page_id)?),
+                        IndexKind::FullText => Arc::new(FullTextIndex::open(bpm.clone(), root_page_id)?),
+                        IndexKind::Hnsw => Arc::new(HnswIndex::open(bpm.clone(), root_page_id)?),
+                        IndexKind::RTree => Arc::new(RTreeIndex::open(bpm.clone(), root_page_id)?),
+                    };
+                    let key_schema = key_schema(&table_schema, &key_columns);
+                    state.add_index(IndexInfo::new(oid, name, table_oid, key_columns, key_schema, index));
+                },
+                CatalogRecord::Statistics { table_oid, statistics } => {
+                    state.statistics.insert(table_oid, (Some(rid), Arc::new(statistics)));
+                },
+                CatalogRecord::KvStore { oid, name, first_page_id, tree_header_page_id } => {
+                    let heap = TableHeap::open(bpm.clone(), first_page_id)?;
+                    let tree = BPlusTree::open(bpm.clone(), tree_header_page_id)?;
+                    state.add_kv_store(KvStoreInfo::new(oid, name, heap, tree));
+                },
+                CatalogRecord::FreeSpaceMap { first_page_id } => {
+                    free_space_map = Some(FreeSpaceMap::open(bpm, first_page_id)?);
+                },
+                CatalogRecord::PageSize { page_size } => state.page_size = Some(page_size as usize),
+                CatalogRecord::MaterializedView { table_oid, view } => {
+                    if !state.table_names.contains_key(&table_oid) {
+                        return Err(CrabDBError::Corruption(format!(
+                            "A materialized view is kept in table {table_oid}, which is not in the catalog"
+                        )));
+                    }
+                    state.views.insert(table_oid, Arc::new(view));
+                },
+            }
+        }
+        Ok((state, free_space_map))
+    }
+
+    fn create_free_space_map(bpm: &BufferPoolManager, heap: &TableHeap) -> CrabDbResult<()> {
+        let free_space_map = FreeSpaceMap::create(bpm)?;
+        heap.insert_tuple(&Tuple::new(CatalogRecord::FreeSpaceMap { first_page_id: free_space_map.first_page_id() }.serialize()))?;
+        bpm.set_free_space_map(free_space_map)
+    }
+
+    // The bytes per page of the database file, fixed when it was created.
+    pub fn page_size(&self) -> usize {
+        self.state.read().unwrap().page_size.unwrap_or(PAGE_SIZE)
+    }
+
+    // Refuses to go on with pages of `page_size` bytes unless the file was created with them.
+    pub(crate) fn check_page_size(&self, page_size: usize) -> CrabDbResult<()> {
+        match self.page_size() {
+            created if created == page_size => Ok(()),
+            created => Err(CrabDBError::InvalidInput(format!(
+                "The database was created with pages of {created} bytes, not {page_size}"
+            ))),
+        }
+    }
+
+    pub(crate) fn bpm(&self) -> &Arc<BufferPoolManager> {
+        &self.bpm
+    }
+
+    pub fn create_table(&self, name: &str, schema: Schema) -> CrabDbResult<Arc<TableInfo>> {
+        self.create_table_with_ttl(name, schema, None)
+    }
+
+    // Creates a table whose rows expire after `ttl`, if it has one.
+    pub fn create_table_with_ttl(&self, name: &str, schema: Schema, ttl: Option<Ttl>) -> CrabDbResult<Arc<TableInfo>> {
+        check_ttl(name, &schema, ttl)?;
+        self.insert_table(&mut self.state.write().unwrap(), name, schema, ttl)
+    }
+
+    // Creates the table a materialized view is kept in, empty until the view is refreshed.
+    pub fn create_materialized_view(&self, name: &str, schema: Schema, view: MaterializedView) -> CrabDbResult<Arc<TableInfo>> {
+        let mut state = self.state.write().unwrap();
+        let table = self.insert_table(&mut state, name, schema, None)?;
+        let record = CatalogRecord::MaterializedView { table_oid: table.oid(), view: view.clone() };
+        self.heap.insert_tuple(&Tuple::new(record.serialize()))?;
+        state.views.insert(table.oid(), Arc::new(view));
+        Ok(table)
+    }
+
+    // The materialized view kept in `table`, if it is one.
+    pub fn view_of(&self, table: &TableInfo) -> Option<Arc<MaterializedView>> {
+        self.state.read().unwrap().views.get(&table.oid()).cloned()
+    }
+
+    // Every materialized view with the table it is kept in, oldest first, so a view reading
+    // another comes after it.
+    pub fn materialized_views(&self) -> Vec<(Arc<TableInfo>, Arc<MaterializedView>)> {
+        let state = self.state.read().unwrap();
+        let mut views: Vec<_> = state.views.iter()
+            .filter_map(|(oid, view)| {
+                let table = state.table_names.get(oid).and_then(|name| state.tables.get(name))?;
+                Some((table.clone(), view.clone()))
+            })
+            .collect();
+        views.sort_by_key(|(table, _)| table.oid());
+        views
+    }
+
+    fn insert_table(&self, state: &mut CatalogState, name: &str, schema: Schema, ttl: Option<Ttl>) -> CrabDbResult<Arc<TableInfo>> {
+        if state.tables.contains_key(&name.to_lowercase()) {
+            return Err(CrabDBError::InvalidInput(format!("Table {name} already exists")));
+        }
+        let oid = state.next_oid;
+        let table_heap = TableHeap::new(self.bpm.clone())?;
+        let record = CatalogRecord::Table {
+            oid,
+            name: name.to_string(),
+            first_page_id: table_heap.first_page_id(),
+            schema: schema.clone(),
+            ttl,
+        };
+        self.heap.insert_tuple(&Tuple::new(record.serialize()))?;
+        Ok(state.add_table(TableInfo::new(oid, name.to_string(), schema, table_heap).with_ttl(ttl)))
+    }
+
+    // Creates a temporary table of `session`, which hides a table of the same name from it.
+    pub fn create_temp_table(&self, session: SessionId, name: &str, schema: Schema, ttl: Option<Ttl>) -> CrabDbResult<Arc<TableInfo>> {
+        check_ttl(name, &schema, ttl)?;
+        let temp_bpm = self.temp_bpm.as_ref()
+            .ok_or_else(|| CrabDBError::InvalidInput(format!("Temporary table {name} cannot be created: there is no temporary storage")))?;
+        let mut state = self.state.write().unwrap();
+        if state.temp_tables.get(&session).is_some_and(|tables| tables.contains_key(&name.to_lowercase())) {
+            return Err(CrabDBError::InvalidInput(format!("Table {name} already exists")));
+        }
+        let oid = state.next_oid;
+        state.next_oid += 1;
+        let table = TableInfo::new(oid, name.to_string(), schema, TableHeap::new(temp_bpm.clone())?).temporary().with_ttl(ttl);
+        let table = Arc::new(table);
+        state.temp_tables.entry(session).or_default().insert(name.to_lowercase(), table.clone());
+        Ok(table)
+    }
+
+    pub fn temp_table(&self, session: SessionId, name: &str) -> Option<Arc<TableInfo>> {
+        self.state.read().unwrap().temp_tables.get(&session)?.get(&name.to_lowercase()).cloned()
+    }
+
+    // The temporary tables of every session.
+    pub fn temp_tables(&self) -> Vec<Arc<TableInfo>> {
+        let state = self.state.read().unwrap();
+        let mut tables: Vec<_> = state.temp_tables.values().flat_map(|tables| tables.values().cloned()).collect();
+        tables.sort_by_key(|table| table.oid());
+        tables
+    }
+
+    // Drops the temporary tables of `session` and returns them. Their pages are freed once
+    // the last of them is let go of.
+    pub fn drop_temp_tables(&self, session: SessionId) -> Vec<Arc<TableInfo>> {
+        let mut state = self.state.write().unwrap();
+        let tables: Vec<_> = state.temp_tables.remove(&session).into_iter().flat_map(HashMap::into_values).collect();
+        for table in &tables {
+            state.statistics.remove(&table.oid());
+        }
+        tables
+    }
+
+    // Creates an index over `key_columns` of a table and fills it from the rows the table
+    // already holds. Keys must be unique, except in full-text indexes, which index the terms
+    // of a single VARCHAR column.
+    pub fn create_index(
+        &self,
+        name: &str,
+        table_name: &str,
+        key_columns: &[&str],
+        kind: IndexKind,
+    ) -> CrabDbResult<Arc<IndexInfo>> {
+        let mut state = self.state.write().unwrap();
+        if state.indexes.contains_key(&name.to_lowercase()) {
+            return Err(CrabDBError::InvalidInput(format!("Index {name} already exists")));
+        }
+        let table = state.tables.get(&table_name.to_lowercase())
+            .ok_or_else(|| CrabDBError::InvalidInput(format!("Table {table_name} does not exist")))?
+            .clone();
+        let key_columns = key_columns.iter()
+            .map(|column| table.schema().index_of(column)
+                .ok_or_else(|| CrabDBError::InvalidInput(format!("Column {column} does not exist in table {table_name}"))))
+            .collect::<CrabDbResult<Vec<_>>>()?;
+
+        let key_schema = key_schema(table.schema(), &key_columns);
+        if kind == IndexKind::FullText && (key_columns.len() != 1 || key_schema.column(0).data_type() != DataType::Varchar) {
+            return Err(CrabDBError::InvalidInput(format!("Full-text index {name} must be over a single VARCHAR column")));
+        }
+        let is_vector = |column: usize| matches!(key_schema.column(column).data_type(), DataType::Vector(_));
+        if kind == IndexKind::Hnsw && (key_columns.len() != 1 || !is_vector(0)) {
+            return Err(CrabDBError::InvalidInput(format!("HNSW index {name} must be over a single VECTOR column")));
+        }
+        if kind != IndexKind::Hnsw && (0..key_columns.len()).any(is_vector) {
+            return Err(CrabDBError::InvalidInput(format!("Index {name} cannot be over a VECTOR column; use HNSW")));
+        }
+        let is_geometry = |column: usize| key_schema.column(column).data_type() == DataType::Geometry;
+        if kind == IndexKind::RTree && (key_columns.len() != 1 || !is_geometry(0)) {
+            return Err(CrabDBError::InvalidInput(format!("R-tree index {name} must be over a single GEOMETRY column")));
+        }
+        if kind != IndexKind::RTree && (0..key_columns.len()).any(is_geometry) {
+            return Err(CrabDBError::InvalidInput(format!("Index {name} cannot be over a GEOMETRY column; use RTREE")));
+        }
+        let (index, root_page_id): (Arc<dyn Index>, PageId) = match kind {
+            IndexKind::BPlusTree => {
+                let tree = BPlusTree::new(self.bpm.clone(), key_size(&key_schema))?;
+                let header_page_id = tree.header_page_id();
+                (Arc::new(tree), header_page_id)
+            },
+            IndexKind::Hash => {
+                let hash = ExtendibleHashIndex::new(self.bpm.clone(), key_size(&key_schema))?;
+                let directory_page_id = hash.directory_page_id();
+                (Arc::new(hash), directory_page_id)
+            },
+            IndexKind::FullText => {
+                let full_text = FullTextIndex::new(self.bpm.clone())?;
+                let header_page_id = full_text.header_page_id();
+                (Arc::new(full_text), header_page_id)
+            },
+            IndexKind::Hnsw => {
+                let DataType::Vector(dimensions) = key_schema.column(0).data_type() else { unreachable!() };
+                let hnsw = HnswIndex::new(self.bpm.clone(), dimensions as usize)?;
+                let header_page_id = hnsw.header_page_id();
+                (Arc::new(hnsw), header_page_id)
+            },
+            IndexKind::RTree => {
+                let rtree = RTreeIndex::new(self.bpm.clone())?;
+                let header_page_id = rtree.header_page_id();
+                (Arc::new(rtree), header_page_id)
+            },
+        };
+
+        let oid = state.next_oid;
+        let info = IndexInfo::new(oid, name.to_string(), table.oid(), key_columns.clone(), key_schema, index);
+        for entry in table.heap().iter() {
+            let (rid, tuple) = entry?;
+            for key in info.entries_for_row(&tuple.values(table.schema())?, rid)? {
+                if !info.index().insert(&key, rid)? && kind.is_unique() {
+                    return Err(CrabDBError::InvalidInput(format!("Index {name} cannot be built: table {table_name} has duplicate keys")));
+                }
+            }
+        }
+
+        let record = CatalogRecord::Index {
+            oid,
+            name: name.to_string(),
+            table_oid: table.oid(),
+            kind,
+            root_page_id,
+            key_columns,
+        };
+        self.heap.insert_tuple(&Tuple::new(record.serialize()))?;
+        Ok(state.add_index(info))
+    }
+
+    pub fn table(&self, name: &str) -> Option<Arc<TableInfo>> {
+        self.state.read().unwrap().tables.get(&name.to_lowercase()).cloned()
+    }
+
+    pub fn table_by_oid(&self, oid: Oid) -> Option<Arc<TableInfo>> {
+        let state = self.state.read().unwrap();
+        state.table_names.get(&oid).and_then(|name| state.tables.get(name)).cloned()
+    }
+
+    pub fn table_names(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.state.read().unwrap().tables.values()
+            .map(|table| table.name().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    pub fn index(&self, name: &str) -> Option<Arc<IndexInfo>> {
+        self.state.read().unwrap().indexes.get(&name.to_lowercase()).cloned()
+    }
+
+    pub fn table_indexes(&self, table_name: &str) -> Vec<Arc<IndexInfo>> {
+        match self.table(table_name) {
+            Some(table) => self.indexes_of(&table),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn indexes_of(&self, table: &TableInfo) -> Vec<Arc<IndexInfo>> {
+        let mut indexes: Vec<_> = self.state.read().unwrap().indexes.values()
+            .filter(|index| index.table_oid() == table.oid())
+            .cloned()
+            .collect();
+        indexes.sort_by_key(|index| index.oid());
+        indexes
+    }
+
+    // Adds a trigger to a table, after those it already has. A materialized view takes none,
+    // as its rows only change with the tables it reads.
+    pub fn create_trigger(&self, table_name: &str, trigger: Trigger) -> CrabDbResult<Arc<Trigger>> {
+        let table = self.table(table_name)
+            .ok_or_else(|| CrabDBError::InvalidInput(format!("Table {table_name} does not exist")))?;
+        if self.view_of(&table).is_some() {
+            return Err(CrabDBError::InvalidInput(format!("Materialized view {table_name} cannot have triggers")));
+        }
+        let mut triggers = self.triggers.write().unwrap();
+        let table_triggers = triggers.entry(table.oid()).or_default();
+        if table_triggers.iter().any(|existing| existing.name().eq_ignore_ascii_case(trigger.name())) {
+            return Err(CrabDBError::InvalidInput(format!("Trigger {} already exists on table {table_name}", trigger.name())));
+        }
+        let trigger = Arc::new(trigger);
+        table_triggers.push(trigger.clone());
+        Ok(trigger)
+    }
+
+    // Removes a trigger from a table. Statements already running may still call it.
+    pub fn drop_trigger(&self, table_name: &str, name: &str) -> CrabDbResult<()> {
+        let table = self.table(table_name)
+            .ok_or_else(|| CrabDBError::InvalidInput(format!("Table {table_name} does not exist")))?;
+        let mut triggers = self.triggers.write().unwrap();
+        let table_triggers = triggers.entry(table.oid()).or_default();
+        let position = table_triggers.iter().position(|trigger| trigger.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| CrabDBError::InvalidInput(format!("Trigger {name} does not exist on table {table_name}")))?;
+        table_triggers.remove(position);
+        Ok(())
+    }
+
+    pub fn triggers_of(&self, table: &TableInfo) -> Vec<Arc<Trigger>> {
+        self.triggers.read().unwrap().get(&table.oid()).cloned().unwrap_or_default()
+    }
+
+    pub fn create_scalar_function(&self, function: ScalarUdf) -> CrabDbResult<Arc<ScalarUdf>> {
+        let function = Arc::new(function);
+        self.create_function(Udf::Scalar(function.clone()))?;
+        Ok(function)
+    }
+
+    pub fn create_aggregate_function(&self, function: AggregateUdf) -> CrabDbResult<Arc<AggregateUdf>> {
+        let function = Arc::new(function);
+        self.create_function(Udf::Aggregate(function.clone()))?;
+        Ok(function)
+    }
+
+    fn create_function(&self, function: Udf) -> CrabDbResult<()> {
+        let name = function.name().to_lowercase();
+        if BUILTIN_FUNCTIONS.contains(&name.as_str()) {
+            return Err(CrabDBError::InvalidInput(format!("Function {} is built in", function.name())));
+        }
+        let mut functions = self.functions.write().unwrap();
+        if functions.contains_key(&name) {
+            return Err(CrabDBError::InvalidInput(format!("Function {} already exists", function.name())));
+        }
+        functions.insert(name, function);
+        Ok(())
+    }
+
+    // Removes a user-defined function. Statements bound before still call it.
+    pub fn drop_function(&self, name: &str) -> CrabDbResult<()> {
+        self.functions.write().unwrap().remove(&name.to_lowercase())
+            .map(|_| ())
+            .ok_or_else(|| CrabDBError::InvalidInput(format!("Function {name} does not exist")))
+    }
+
+    pub fn function(&self, name: &str) -> Option<Udf> {
+        self.functions.read().unwrap().get(&name.to_lowercase()).cloned()
+    }
+
+    // Creates a key-value store whose B+ tree takes keys of `key_size` bytes.
+    pub fn create_kv_store(&self, name: &str, key_size: usize) -> CrabDbResult<Arc<KvStoreInfo>> {
+        let mut state = self.state.write().unwrap();
+        if state.kv_stores.contains_key(&name.to_lowercase()) {
+            return Err(CrabDBError::InvalidInput(format!("Key-value store {name} already exists")));
+        }
+        let oid = state.next_oid;
+        let heap = TableHeap::new(self.bpm.clone())?;
+        let tree = BPlusTree::new(self.bpm.clone(), key_size)?;
+        let record = CatalogRecord::KvStore {
+            oid,
+            name: name.to_string(),
+            first_page_id: heap.first_page_id(),
+            tree_header_page_id: tree.header_page_id(),
+        };
+        self.heap.insert_tuple(&Tuple::new(record.serialize()))?;
+        Ok(state.add_kv_store(KvStoreInfo::new(oid, name.to_string(), heap, tree)))
+    }
+
+    pub fn kv_store(&self, name: &str) -> Option<Arc<KvStoreInfo>> {
+        self.state.read().unwrap().kv_stores.get(&name.to_lowercase()).cloned()
+    }
+
+    pub fn kv_store_names(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.state.read().unwrap().kv_stores.values()
+            .map(|kv_store| kv_store.name().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    // Statistics from the last ANALYZE of the table, if it was ever analyzed.
+    pub fn table_statistics(&self, table_name: &str) -> Option<Arc<TableStatistics>> {
+        self.table(table_name).and_then(|table| self.statistics_of(&table))
+    }
+
+    pub fn statistics_of(&self, table: &TableInfo) -> Option<Arc<TableStatistics>> {
+        self.state.read().unwrap().statistics.get(&table.oid()).map(|(_, statistics)| statistics.clone())
+    }
+
+    pub fn set_table_statistics(&self, table_name: &str, statistics: TableStatistics) -> CrabDbResult<()> {
+        let table = self.table(table_name)
+            .ok_or_else(|| CrabDBError::InvalidInput(format!("Table {table_name} does not exist")))?;
+        self.set_statistics_of(&table, statistics)
+    }
+
+    // Replaces the statistics of a table. Histograms are dropped, widest first, until the
+    // record fits in a page.
+    pub fn set_statistics_of(&self, table: &TableInfo, mut statistics: TableStatistics) -> CrabDbResult<()> {
+        let table_name = table.name();
+        if statistics.columns.len() != table.schema().column_count() {
+            return Err(CrabDBError::InvalidInput(format!(
+                "Table {table_name} has {} columns but statistics were collected for {}",
+                table.schema().column_count(), statistics.columns.len()
+            )));
+        }
+        let mut state = self.state.write().unwrap();
+        if table.is_temporary() {
+            state.statistics.insert(table.oid(), (None, Arc::new(statistics)));
+            return Ok(());
+        }
+        let bytes = loop {
+            let bytes = CatalogRecord::Statistics { table_oid: table.oid(), statistics: statistics.clone() }.serialize();
+            if bytes.len() <= MAX_TUPLE_SIZE {
+                break bytes;
+            }
+            let widest = statistics.columns.iter_mut()
+                .filter(|column| !column.histogram.is_empty())
+                .max_by_key(|column| column.histogram.iter().map(|bound| bound.to_string().len()).sum::<usize>())
+                .ok_or_else(|| CrabDBError::new(format!("Table {table_name} has too many columns to store statistics")))?;
+            widest.histogram.clear();
+        };
+        let rid = self.heap.insert_tuple(&Tuple::new(bytes))?;
+        if let Some((Some(old_rid), _)) = state.statistics.insert(table.oid(), (Some(rid), Arc::new(statistics))) {
+            self.heap.apply_delete(old_rid)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use tempfile::TempDir;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::catalog::catalog_record::CatalogRecord;
+    use crate::catalog::statistics::TableStatistics;
+    use crate::index::table_index::IndexKind;
+    use crate::storage::common::PAGE_SIZE;
+    use crate::storage::disk::file_disk_manager::FileDiskManager;
+    use crate::storage::table::tuple::Tuple;
+    use crate::types::schema::{Column, Schema};
+    use crate::types::value::{DataType, Value};
+    use crate::types::CrabDBError;
+    use super::Catalog;
+
+    fn buffer_pool(path: &Path) -> Arc<BufferPoolManager> {
+        let disk_manager = Arc::new(FileDiskManager::new(path).unwrap());
+        Arc::new(BufferPoolManager::new(8, disk_manager, Arc::new(LRUKReplacer::new(8, 2))))
+    }
+
+    fn users_schema() -> Schema {
+        Schema::new(vec![
+            Column::new("id", DataType::Int64).not_null(),
+            Column::new("name", DataType::Varchar),
+        ])
+    }
+
+    #[test]
+    pub fn test_catalog_create_and_lookup() {
+        let dir = TempDir::new().unwrap();
+        let catalog = Catalog::open(buffer_pool(&dir.path().join("test.db"))).unwrap();
+
+        let users = catalog.create_table("users", users_schema()).unwrap();
+        let orders = catalog.create_table("orders", Schema::new(vec![Column::new("user_id", DataType::Int64)])).unwrap();
+        assert_ne!(users.oid(), orders.oid());
+        assert_eq!(users.oid(), catalog.table("USERS").unwrap().oid());
+        assert_eq!("orders", catalog.table_by_oid(orders.oid()).unwrap().name());
+        assert_eq!(vec!["orders".to_string(), "users".to_string()], catalog.table_names());
+        assert_eq!("Table Users already exists", catalog.create_table("Users", users_schema()).err().unwrap().to_string());
+
+        let index = catalog.create_index("users_name", "users", &["name"], IndexKind::Hash).unwrap();
+        assert_eq!(&[1], index.key_columns());
+        assert_eq!(IndexKind::Hash, index.kind());
+        assert_eq!(
+            vec![index.oid()],
+            catalog.table_indexes("users").iter().map(|index| index.oid()).collect::<Vec<_>>()
+        );
+        assert!(catalog.table_indexes("orders").is_empty());
+        for _ in 0..2 {
+            orders.heap().insert_tuple(&Tuple::from_values(orders.schema(), &[Value::Int64(7)]).unwrap()).unwrap();
+        }
+        assert_eq!(
+            "Index orders_user cannot be built: table orders has duplicate keys",
+            catalog.create_index("orders_user", "orders", &["user_id"], IndexKind::BPlusTree).err().unwrap().to_string()
+        );
+        assert_eq!(
+            "Column email does not exist in table users",
+            catalog.create_index("users_email", "users", &["email"], IndexKind::Hash).err().unwrap().to_string()
+        );
+        assert_eq!(
+            "Table missing does not exist",
+            catalog.create_index("missing_id", "missing", &["id"], IndexKind::BPlusTree).err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    pub fn test_catalog_survives_restart() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.db");
+        let (users_oid, rid) = {
+            let bpm = buffer_pool(&path);
+            let catalog = Catalog::open(bpm.clone()).unwrap();
+            let users = catalog.create_table("users", users_schema()).unwrap();
+            let row = Tuple::from_values(users.schema(), &[Value::Int64(1), Value::Varchar("ferris".into())]).unwrap();
+            let rid = users.heap().insert_tuple(&row).unwrap();
+            // the index picks up the row that is already in the table
+            catalog.create_index("users_id", "users", &["id"], IndexKind::BPlusTree).unwrap();
+            // only the latest statistics of a table are kept
+            for row_count in [1, 2] {
+                let statistics = TableStatistics::collect(2, (0..row_count).map(|id| Ok(vec![Value::Int64(id), Value::Null]))).unwrap();
+                catalog.set_table_statistics("users", statistics).unwrap();
+            }
+            bpm.flush_all_pages().unwrap();
+            (users.oid(), rid)
+        };
+
+        let catalog = Catalog::open(buffer_pool(&path)).unwrap();
+        let users = catalog.table("users").unwrap();
+        assert_eq!(users_oid, users.oid());
+        assert_eq!(&users_schema(), users.schema());
+        assert_eq!(
+            vec![Value::Int64(1), Value::Varchar("ferris".into())],
+            users.heap().get_tuple(rid).unwrap().values(users.schema()).unwrap()
+        );
+        let users_id = catalog.index("users_id").unwrap();
+        assert_eq!(users_oid, users_id.table_oid());
+        assert_eq!(IndexKind::BPlusTree, users_id.kind());
+        let key = users_id.key_for_row(&[Value::Int64(1), Value::Null]).unwrap();
+        assert_eq!(Some(rid), users_id.index().get(&key).unwrap());
+        let statistics = catalog.table_statistics("users").unwrap();
+        assert_eq!((2, 2), (statistics.row_count, statistics.columns[1].null_count));
+        // new objects never reuse an oid from before the restart
+        let orders = catalog.create_table("orders", users_schema()).unwrap();
+        assert!(orders.oid() > catalog.index("users_id").unwrap().oid());
+
+        // the page size the file was created with sticks, and a file of another is refused
+        assert_eq!(PAGE_SIZE, catalog.page_size());
+        catalog.check_page_size(PAGE_SIZE).unwrap();
+        assert!(matches!(catalog.check_page_size(2 * PAGE_SIZE), Err(CrabDBError::InvalidInput(_))));
+        catalog.heap.insert_tuple(&Tuple::new(CatalogRecord::PageSize { page_size: 2 * PAGE_SIZE as u32 }.serialize())).unwrap();
+        catalog.bpm().flush_all_pages().unwrap();
+        drop(catalog);
+        assert!(matches!(Catalog::open(buffer_pool(&path)), Err(CrabDBError::InvalidInput(_))));
+    }
+}
@@ -0,0 +1,238 @@
+use std::any::Any;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::index::bplus_tree::BPlusTree;
+use crate::index::full_text::FullTextIndex;
+use crate::index::hnsw::{self, HnswIndex};
+use crate::index::rtree::{self, RTreeIndex};
+use crate::index::index_key::encode_key;
+use crate::index::table_index::{Index, IndexKind};
+use crate::storage::table::table_heap::TableHeap;
+use crate::storage::table::tuple::Rid;
+use crate::types::schema::Schema;
+use crate::types::value::Value;
+use crate::types::CrabDbResult;
+
+// Object id shared by tables, indexes and key-value stores.
+pub type Oid = u32;
+
+// When the rows of a table expire: `after` past the timestamp in the column at position
+// `column`. Rows whose timestamp is NULL never do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ttl {
+    pub column: usize,
+    pub after: Duration,
+}
+
+impl Ttl {
+    // Whether `row` has expired at `now`, in microseconds since the Unix epoch.
+    pub fn is_expired(&self, row: &[Value], now: u64) -> bool {
+        match row[self.column] {
+            Value::Timestamp(at) => at.saturating_add(self.after.as_micros() as i64) <= now as i64,
+            _ => false,
+        }
+    }
+}
+
+// The query a materialized view's table holds the result of, as SQL, and the tables it
+// reads, whose changes keep the table up to date; see `db::materialized_view`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaterializedView {
+    pub query: String,
+    pub sources: Vec<Oid>,
+    // Columns after those of the query that hold what maintaining the view needs, such as
+    // the running sums of its aggregates; queries do not see them.
+    pub state_columns: usize,
+}
+
+pub struct TableInfo {
+    oid: Oid,
+    name: String,
+    schema: Schema,
+    heap: TableHeap,
+    // Whether this is a temporary table of a session, whose pages go back to the temporary
+    // storage once nothing holds the table any more.
+    temporary: bool,
+    // Expired rows are invisible to scans, and vacuum deletes them (see
+    // `vacuum::remove_expired_rows`).
+    ttl: Option<Ttl>,
+}
+
+impl TableInfo {
+    pub(crate) fn new(oid: Oid, name: String, schema: Schema, heap: TableHeap) -> Self {
+        TableInfo { oid, name, schema, heap: heap.owned_by(oid), temporary: false, ttl: None }
+    }
+
+    pub(crate) fn with_ttl(mut self, ttl: Option<Ttl>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub(crate) fn temporary(mut self) -> Self {
+        self.temporary = true;
+        self
+    }
+
+    pub fn is_temporary(&self) -> bool {
+        self.temporary
+    }
+
+    pub fn oid(&self) -> Oid {
+        self.oid
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    pub fn heap(&self) -> &TableHeap {
+        &self.heap
+    }
+
+    pub fn ttl(&self) -> Option<Ttl> {
+        self.ttl
+    }
+
+    // Whether `row` of this table has expired at `now`; see `Ttl`.
+    pub fn is_expired(&self, row: &[Value], now: u64) -> bool {
+        self.ttl.is_some_and(|ttl| ttl.is_expired(row, now))
+    }
+}
+
+impl Drop for TableInfo {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = self.heap.delete_pages();
+        }
+    }
+}
+
+pub struct IndexInfo {
+    oid: Oid,
+    name: String,
+    table_oid: Oid,
+    // Positions in the table schema of the columns that make up the key.
+    key_columns: Vec<usize>,
+    key_schema: Schema,
+    index: Arc<dyn Index>,
+}
+
+impl IndexInfo {
+    pub(crate) fn new(
+        oid: Oid,
+        name: String,
+        table_oid: Oid,
+        key_columns: Vec<usize>,
+        key_schema: Schema,
+        index: Arc<dyn Index>,
+    ) -> Self {
+        IndexInfo { oid, name, table_oid, key_columns, key_schema, index }
+    }
+
+    pub fn oid(&self) -> Oid {
+        self.oid
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn table_oid(&self) -> Oid {
+        self.table_oid
+    }
+
+    pub fn key_columns(&self) -> &[usize] {
+        &self.key_columns
+    }
+
+    pub fn key_schema(&self) -> &Schema {
+        &self.key_schema
+    }
+
+    pub fn kind(&self) -> IndexKind {
+        self.index.kind()
+    }
+
+    pub fn index(&self) -> &dyn Index {
+        self.index.as_ref()
+    }
+
+    pub(crate) fn shared_index(&self) -> Arc<dyn Index> {
+        self.index.clone()
+    }
+
+    pub fn full_text(&self) -> Option<&FullTextIndex> {
+        (self.index.as_ref() as &dyn Any).downcast_ref()
+    }
+
+    pub fn hnsw(&self) -> Option<&HnswIndex> {
+        (self.index.as_ref() as &dyn Any).downcast_ref()
+    }
+
+    pub fn rtree(&self) -> Option<&RTreeIndex> {
+        (self.index.as_ref() as &dyn Any).downcast_ref()
+    }
+
+    // Encodes the index key of a full table row.
+    pub fn key_for_row(&self, row: &[Value]) -> CrabDbResult<Vec<u8>> {
+        let values: Vec<_> = self.key_columns.iter().map(|&column| row[column].clone()).collect();
+        encode_key(&self.key_schema, &values)
+    }
+
+    // The keys of the entries a row at `rid` has: its key, unless the index holds several
+    // entries per row, as full-text indexes do with a posting per term of its text, or keys
+    // them by the row, as HNSW and R-tree indexes do.
+    pub fn entries_for_row(&self, row: &[Value], rid: Rid) -> CrabDbResult<Vec<Vec<u8>>> {
+        match self.kind() {
+            IndexKind::FullText => Ok(match &row[self.key_columns[0]] {
+                Value::Varchar(text) => FullTextIndex::entries(text, rid),
+                _ => Vec::new(),
+            }),
+            IndexKind::Hnsw => Ok(match &row[self.key_columns[0]] {
+                Value::Vector(vector) => vec![hnsw::key(vector, rid)],
+                _ => Vec::new(),
+            }),
+            IndexKind::RTree => Ok(match &row[self.key_columns[0]] {
+                Value::Geometry(geometry) => vec![rtree::key(geometry, rid)],
+                _ => Vec::new(),
+            }),
+            _ => Ok(vec![self.key_for_row(row)?]),
+        }
+    }
+}
+
+// A key-value store: its entries live in a heap of their own and a B+ tree finds them by key
+// (see `kv::KvStore`).
+pub struct KvStoreInfo {
+    oid: Oid,
+    name: String,
+    heap: TableHeap,
+    tree: BPlusTree,
+}
+
+impl KvStoreInfo {
+    pub(crate) fn new(oid: Oid, name: String, heap: TableHeap, tree: BPlusTree) -> Self {
+        KvStoreInfo { oid, name, heap: heap.owned_by(oid), tree }
+    }
+
+    pub fn oid(&self) -> Oid {
+        self.oid
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn heap(&self) -> &TableHeap {
+        &self.heap
+    }
+
+    pub fn tree(&self) -> &BPlusTree {
+        &self.tree
+    }
+}
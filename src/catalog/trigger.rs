@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use crate::execution::trigger::TriggerRow;
+use crate::types::CrabDbResult;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerTiming {
+    // Before the row is written: the trigger may change the new row or skip the write.
+    Before,
+    // Right after the row is written.
+    After,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerEvent {
+    Insert,
+    Update,
+    Delete,
+}
+
+pub type TriggerFn = Arc<dyn Fn(&mut TriggerRow<'_>) -> CrabDbResult<()> + Send + Sync>;
+
+// A row trigger: a callback of the embedder that runs for every row INSERT, UPDATE, DELETE
+// or COPY FROM writes to a table, in the statement's transaction. An error from it fails the
+// statement. Triggers are not persisted, so they are registered again on every open.
+#[derive(Clone)]
+pub struct Trigger {
+    name: String,
+    timing: TriggerTiming,
+    event: TriggerEvent,
+    callback: TriggerFn,
+}
+
+impl Trigger {
+    pub fn new(
+        name: impl Into<String>,
+        timing: TriggerTiming,
+        event: TriggerEvent,
+        callback: impl Fn(&mut TriggerRow<'_>) -> CrabDbResult<()> + Send + Sync + 'static,
+    ) -> Self {
+        Trigger { name: name.into(), timing, event, callback: Arc::new(callback) }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn timing(&self) -> TriggerTiming {
+        self.timing
+    }
+
+    pub fn event(&self) -> TriggerEvent {
+        self.event
+    }
+
+    pub(crate) fn call(&self, row: &mut TriggerRow<'_>) -> CrabDbResult<()> {
+        (self.callback)(row)
+    }
+}
+
+impl std::fmt::Debug for Trigger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Trigger").field("name", &self.name).field("timing", &self.timing).field("event", &self.event).finish()
+    }
+}
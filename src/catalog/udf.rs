@@ -0,0 +1,188 @@
+use std::sync::Arc;
+
+use crate::types::value::{DataType, Value};
+use crate::types::{CrabDBError, CrabDbResult};
+
+// Functions the binder resolves itself, which user-defined ones cannot shadow.
+pub const BUILTIN_FUNCTIONS: &[&str] = &["count", "sum", "min", "max", "avg", "bm25", "st_contains", "st_intersects"];
+
+pub type UdfBody = Arc<dyn Fn(&[Value]) -> CrabDbResult<Value> + Send + Sync>;
+
+// A scalar function of the embedder, called once per row with its arguments converted to
+// the types it declares. NULL arguments are passed on as they are. Functions are not
+// persisted, so they are registered again on every open, before any query or materialized
+// view using them runs.
+pub struct ScalarUdf {
+    name: String,
+    arguments: Vec<DataType>,
+    return_type: DataType,
+    body: UdfBody,
+}
+
+impl ScalarUdf {
+    pub fn new(
+        name: impl Into<String>,
+        arguments: Vec<DataType>,
+        return_type: DataType,
+        body: impl Fn(&[Value]) -> CrabDbResult<Value> + Send + Sync + 'static,
+    ) -> Self {
+        ScalarUdf { name: name.into(), arguments, return_type, body: Arc::new(body) }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn arguments(&self) -> &[DataType] {
+        &self.arguments
+    }
+
+    pub fn return_type(&self) -> DataType {
+        self.return_type
+    }
+
+    pub(crate) fn call(&self, arguments: Vec<Value>) -> CrabDbResult<Value> {
+        let arguments = arguments.into_iter().zip(&self.arguments)
+            .map(|(value, data_type)| coerce(value, *data_type))
+            .collect::<CrabDbResult<Vec<_>>>()?;
+        returned(&self.name, (self.body)(&arguments)?, self.return_type)
+    }
+}
+
+// An aggregate function of the embedder over one argument. It is called once per group with
+// the group's non-NULL arguments, converted to the type it declares, and with none for a
+// group without any.
+pub struct AggregateUdf {
+    name: String,
+    argument: DataType,
+    return_type: DataType,
+    body: UdfBody,
+}
+
+impl AggregateUdf {
+    pub fn new(
+        name: impl Into<String>,
+        argument: DataType,
+        return_type: DataType,
+        body: impl Fn(&[Value]) -> CrabDbResult<Value> + Send + Sync + 'static,
+    ) -> Self {
+        AggregateUdf { name: name.into(), argument, return_type, body: Arc::new(body) }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn argument(&self) -> DataType {
+        self.argument
+    }
+
+    pub fn return_type(&self) -> DataType {
+        self.return_type
+    }
+
+    pub(crate) fn coerce(&self, value: Value) -> CrabDbResult<Value> {
+        coerce(value, self.argument)
+    }
+
+    pub(crate) fn call(&self, values: &[Value]) -> CrabDbResult<Value> {
+        returned(&self.name, (self.body)(values)?, self.return_type)
+    }
+}
+
+// Expressions hold functions behind an Arc, and a call is the same as another only when it
+// calls the same registration of a function.
+impl PartialEq for ScalarUdf {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl PartialEq for AggregateUdf {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Eq for AggregateUdf {}
+
+impl std::fmt::Debug for ScalarUdf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScalarUdf").field("name", &self.name).field("arguments", &self.arguments).field("return_type", &self.return_type).finish()
+    }
+}
+
+impl std::fmt::Debug for AggregateUdf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AggregateUdf").field("name", &self.name).field("argument", &self.argument).field("return_type", &self.return_type).finish()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Udf {
+    Scalar(Arc<ScalarUdf>),
+    Aggregate(Arc<AggregateUdf>),
+}
+
+impl Udf {
+    pub fn name(&self) -> &str {
+        match self {
+            Udf::Scalar(function) => function.name(),
+            Udf::Aggregate(function) => function.name(),
+        }
+    }
+}
+
+// Whether values of type `from` can be passed for an argument of type `to`: the conversions
+// of `Value::cast_to`, plus integers to DOUBLE as arithmetic does.
+pub(crate) fn coercible(from: DataType, to: DataType) -> bool {
+    from == to || matches!(
+        (from, to),
+        (DataType::Int32 | DataType::Int64, DataType::Int32 | DataType::Int64 | DataType::Float64)
+            | (DataType::Int64, DataType::Timestamp)
+            | (DataType::Varchar, DataType::Vector(_) | DataType::Geometry)
+    )
+}
+
+fn coerce(value: Value, data_type: DataType) -> CrabDbResult<Value> {
+    match value {
+        Value::Int64(i) if data_type == DataType::Float64 => Ok(Value::Float64(i as f64)),
+        value => value.cast_to(data_type),
+    }
+}
+
+fn returned(name: &str, value: Value, return_type: DataType) -> CrabDbResult<Value> {
+    coerce(value, return_type).map_err(|_| CrabDBError::InvalidInput(format!("Function {name} returned a value that is not {return_type}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_udf_converts_arguments_and_results() {
+        let scale = ScalarUdf::new("scale", vec![DataType::Float64, DataType::Int64], DataType::Float64, |arguments| {
+            match arguments {
+                [Value::Float64(x), Value::Int64(factor)] => Ok(Value::Float64(x * *factor as f64)),
+                [..] => Ok(Value::Null),
+            }
+        });
+        assert_eq!(Value::Float64(6.0), scale.call(vec![Value::Int32(3), Value::Int32(2)]).unwrap());
+        assert_eq!(Value::Float64(1.0), scale.call(vec![Value::Int64(1), Value::Int64(1)]).unwrap());
+        assert_eq!(Value::Null, scale.call(vec![Value::Null, Value::Int32(2)]).unwrap());
+        assert!(scale.call(vec![Value::Varchar("x".into()), Value::Int32(2)]).is_err());
+
+        let broken = ScalarUdf::new("broken", vec![], DataType::Int32, |_| Ok(Value::Varchar("crab".into())));
+        assert_eq!("Function broken returned a value that is not INT", broken.call(vec![]).unwrap_err().to_string());
+
+        let longest = AggregateUdf::new("longest", DataType::Varchar, DataType::Int64, |values| {
+            Ok(Value::Int64(values.iter().filter_map(Value::as_str).map(str::len).max().unwrap_or(0) as i64))
+        });
+        assert_eq!(Value::Int64(5), longest.call(&[Value::Varchar("crab".into()), Value::Varchar("crabs".into())]).unwrap());
+        assert!(longest.coerce(Value::Int32(1)).is_err());
+
+        assert!(coercible(DataType::Int32, DataType::Float64));
+        assert!(!coercible(DataType::Float64, DataType::Int64));
+        assert!(!coercible(DataType::Varchar, DataType::Int32));
+    }
+}
@@ -0,0 +1,783 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt::Display;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::buffer_pool::page::LatchWait;
+use crate::catalog::table_info::Oid;
+use crate::platform::Stopwatch;
+use crate::storage::table::tuple::Rid;
+use crate::types::{CrabDBError, CrabDbResult};
+use crate::wal::common::TxnId;
+
+use super::transaction::{Transaction, TransactionState};
+
+// Rows are locked shared or exclusive. Tables can also be locked in the intention modes,
+// which say that the transaction locks rows of the table in that mode (and, for
+// SharedIntentionExclusive, reads the whole table besides), so a lock on the whole table
+// and locks on its rows conflict where they should.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LockMode {
+    IntentionShared,
+    IntentionExclusive,
+    Shared,
+    SharedIntentionExclusive,
+    Exclusive,
+}
+
+impl LockMode {
+    fn is_compatible(&self, other: LockMode) -> bool {
+        use LockMode::*;
+        match self {
+            IntentionShared => other != Exclusive,
+            IntentionExclusive => matches!(other, IntentionShared | IntentionExclusive),
+            Shared => matches!(other, IntentionShared | Shared),
+            SharedIntentionExclusive => other == IntentionShared,
+            Exclusive => false,
+        }
+    }
+
+    // Whether holding `self` grants everything `other` does.
+    pub fn covers(&self, other: LockMode) -> bool {
+        use LockMode::*;
+        match self {
+            IntentionShared => other == IntentionShared,
+            IntentionExclusive => matches!(other, IntentionShared | IntentionExclusive),
+            Shared => matches!(other, IntentionShared | Shared),
+            SharedIntentionExclusive => other != Exclusive,
+            Exclusive => true,
+        }
+    }
+
+    // The weakest mode that covers both. Shared and IntentionExclusive are the one pair
+    // neither of which covers the other.
+    fn combine(self, other: LockMode) -> LockMode {
+        if self.covers(other) {
+            self
+        } else if other.covers(self) {
+            other
+        } else {
+            LockMode::SharedIntentionExclusive
+        }
+    }
+
+    // The mode a row lock in `self` takes on its table.
+    fn intention(self) -> LockMode {
+        match self {
+            LockMode::Shared | LockMode::IntentionShared => LockMode::IntentionShared,
+            _ => LockMode::IntentionExclusive,
+        }
+    }
+}
+
+impl Display for LockMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LockMode::IntentionShared => write!(f, "intention shared"),
+            LockMode::IntentionExclusive => write!(f, "intention exclusive"),
+            LockMode::Shared => write!(f, "shared"),
+            LockMode::SharedIntentionExclusive => write!(f, "shared intention exclusive"),
+            LockMode::Exclusive => write!(f, "exclusive"),
+        }
+    }
+}
+
+// A lockable resource. Locking a row first takes the matching intention lock on its table,
+// so a transaction locking the whole table waits for the rows locked in it without looking
+// at them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LockTarget {
+    Table(Oid),
+    Row(Oid, Rid),
+}
+
+impl Display for LockTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LockTarget::Table(oid) => write!(f, "table {oid}"),
+            LockTarget::Row(oid, rid) => write!(f, "row {}:{} of table {oid}", rid.page_id(), rid.slot_id()),
+        }
+    }
+}
+
+struct LockRequest {
+    txn_id: TxnId,
+    mode: LockMode,
+    granted: bool,
+    // The stronger mode a granted request waits to be upgraded to. The request keeps
+    // holding `mode` meanwhile.
+    upgrade: Option<LockMode>,
+    // When the request, or its upgrade, was made, for how long it has waited.
+    requested: Stopwatch,
+}
+
+impl LockRequest {
+    fn new(txn_id: TxnId, mode: LockMode) -> Self {
+        LockRequest { txn_id, mode, granted: false, upgrade: None, requested: Stopwatch::start() }
+    }
+
+    fn is_waiting(&self) -> bool {
+        !self.granted || self.upgrade.is_some()
+    }
+
+    // The mode the request waits for.
+    fn wanted(&self) -> LockMode {
+        self.upgrade.unwrap_or(self.mode)
+    }
+}
+
+// A transaction waiting for a lock, as `LockManager::lock_waits` reports it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockWait {
+    pub txn_id: TxnId,
+    pub target: LockTarget,
+    pub mode: LockMode,
+    pub waited: Duration,
+    // Transactions holding the resource in modes that conflict with the one asked for.
+    pub held_by: Vec<(TxnId, LockMode)>,
+    // Transactions waiting ahead of it, which get the lock first.
+    pub queued_behind: Vec<TxnId>,
+}
+
+impl Display for LockWait {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "transaction {} has waited {:?} to lock {} {}", self.txn_id, self.waited, self.target, self.mode)?;
+        if !self.held_by.is_empty() {
+            let holders: Vec<_> = self.held_by.iter().map(|(txn_id, mode)| format!("{txn_id} ({mode})")).collect();
+            write!(f, ", held by {}", holders.join(", "))?;
+        }
+        if !self.queued_behind.is_empty() {
+            let waiters: Vec<_> = self.queued_behind.iter().map(|txn_id| txn_id.to_string()).collect();
+            write!(f, ", behind {}", waiters.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+// Everything waiting in a database, longest first: transactions for locks and threads for
+// page latches. See `CrabDb::lock_waits`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LockWaits {
+    pub locks: Vec<LockWait>,
+    pub latches: Vec<LatchWait>,
+}
+
+impl Display for LockWaits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.locks.is_empty() && self.latches.is_empty() {
+            return writeln!(f, "nothing is waiting");
+        }
+        for wait in &self.locks {
+            writeln!(f, "{wait}")?;
+        }
+        for wait in &self.latches {
+            writeln!(f, "{wait}")?;
+        }
+        Ok(())
+    }
+}
+
+// Granted requests come first, in the order they were granted, then the waiting ones in
+// arrival order. A granted request waiting for an upgrade stays where it is.
+#[derive(Default)]
+struct LockRequestQueue {
+    requests: VecDeque<LockRequest>,
+}
+
+impl LockRequestQueue {
+    fn position(&self, txn_id: TxnId) -> Option<usize> {
+        self.requests.iter().position(|request| request.txn_id == txn_id)
+    }
+
+    // The one transaction allowed to be waiting to upgrade its lock.
+    fn upgrading(&self) -> Option<TxnId> {
+        self.requests.iter().find(|request| request.upgrade.is_some()).map(|request| request.txn_id)
+    }
+
+    // Requests are granted in arrival order: one is grantable once everything ahead of it
+    // is granted, with no upgrade pending, and compatible with it, so a stream of readers
+    // cannot starve a writer. An upgrade goes ahead of every waiter: it only waits for the
+    // other locks granted.
+    fn grantable(&self, txn_id: TxnId) -> bool {
+        self.position(txn_id).is_some_and(|position| self.blockers(position).next().is_none())
+    }
+
+    fn grant(&mut self, txn_id: TxnId) {
+        if let Some(request) = self.requests.iter_mut().find(|request| request.txn_id == txn_id) {
+            request.granted = true;
+            if let Some(upgrade) = request.upgrade.take() {
+                request.mode = upgrade;
+            }
+        }
+    }
+
+    // What the request at `position` waits for: every request ahead of it that is still
+    // waiting itself or holds an incompatible lock; for an upgrade, every other lock
+    // granted that is incompatible with the mode it asks for.
+    fn blockers(&self, position: usize) -> Box<dyn Iterator<Item = &LockRequest> + '_> {
+        let waiter = &self.requests[position];
+        let wanted = waiter.wanted();
+        if waiter.upgrade.is_some() {
+            let txn_id = waiter.txn_id;
+            return Box::new(self.requests.iter().filter(move |request| {
+                request.txn_id != txn_id && request.granted && !request.mode.is_compatible(wanted)
+            }));
+        }
+        Box::new(self.requests.iter().take(position).filter(move |request| request.is_waiting() || !request.mode.is_compatible(wanted)))
+    }
+
+    // Takes the request of `txn_id` back out, once it will not be granted. A lock it was
+    // upgrading stays held, as it was, until the transaction finishes.
+    fn withdraw(&mut self, txn_id: TxnId) {
+        if let Some(position) = self.position(txn_id) {
+            if self.requests[position].granted {
+                self.requests[position].upgrade = None;
+            } else {
+                self.requests.remove(position);
+            }
+        }
+    }
+}
+
+// Multi-granularity locks on tables and rows for strict two-phase locking: transactions
+// acquire locks as they go and `TransactionManager` releases all of them when the
+// transaction commits or aborts. Each resource has a FIFO queue of requests; a lock can be
+// upgraded to a stronger mode, and the upgrade waits ahead of every other waiter.
+//
+// Deadlocks are broken by `detect_deadlocks`, which `with_deadlock_detection` runs
+// periodically on a background thread: it looks for cycles in the waits-for graph and
+// aborts the youngest transaction of each, whose pending `lock` call then fails with a
+// deadlock error. With a `lock_timeout`, a transaction that waits longer than that for a
+// lock gives up and is aborted too, deadlocked or not.
+pub struct LockManager {
+    shared: Arc<LockTable>,
+    detector: Option<(Sender<()>, JoinHandle<()>)>,
+    timeout: Option<Duration>,
+}
+
+#[derive(Default)]
+struct LockTable {
+    state: Mutex<LockTableState>,
+    released: Condvar,
+}
+
+#[derive(Default)]
+struct LockTableState {
+    queues: HashMap<LockTarget, LockRequestQueue>,
+    // Waiting transactions chosen to break a deadlock that have not noticed yet.
+    victims: HashSet<TxnId>,
+}
+
+impl LockTableState {
+    // Edges from each waiting transaction to the transactions it waits for.
+    fn waits_for(&self) -> BTreeMap<TxnId, BTreeSet<TxnId>> {
+        let mut graph: BTreeMap<TxnId, BTreeSet<TxnId>> = BTreeMap::new();
+        for queue in self.queues.values() {
+            for (position, waiter) in queue.requests.iter().enumerate() {
+                if !waiter.is_waiting() || self.victims.contains(&waiter.txn_id) {
+                    continue;
+                }
+                let holders = queue.blockers(position)
+                    .map(|request| request.txn_id)
+                    .filter(|txn_id| !self.victims.contains(txn_id));
+                graph.entry(waiter.txn_id).or_default().extend(holders);
+            }
+        }
+        graph
+    }
+}
+
+// Returns the transactions on some cycle of `graph`, if it has one. Walks in txn id order so
+// the result does not depend on hashing.
+fn find_cycle(graph: &BTreeMap<TxnId, BTreeSet<TxnId>>) -> Option<Vec<TxnId>> {
+    fn visit(
+        graph: &BTreeMap<TxnId, BTreeSet<TxnId>>,
+        txn_id: TxnId,
+        path: &mut Vec<TxnId>,
+        done: &mut HashSet<TxnId>,
+    ) -> Option<Vec<TxnId>> {
+        if let Some(position) = path.iter().position(|&on_path| on_path == txn_id) {
+            return Some(path[position..].to_vec());
+        }
+        if done.contains(&txn_id) {
+            return None;
+        }
+        path.push(txn_id);
+        for &next in graph.get(&txn_id).into_iter().flatten() {
+            if let Some(cycle) = visit(graph, next, path, done) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        done.insert(txn_id);
+        None
+    }
+
+    let mut done = HashSet::new();
+    graph.keys().find_map(|&txn_id| visit(graph, txn_id, &mut Vec::new(), &mut done))
+}
+
+impl Default for LockManager {
+    fn default() -> Self {
+        LockManager { shared: Arc::new(LockTable::default()), detector: None, timeout: None }
+    }
+}
+
+impl LockManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // A lock manager that checks for deadlocks every `interval`.
+    pub fn with_deadlock_detection(interval: Duration) -> Self {
+        let shared = Arc::new(LockTable::default());
+        let (stop, stopped) = mpsc::channel::<()>();
+        let detector = {
+            let shared = shared.clone();
+            std::thread::Builder::new()
+                .name("crab-db-deadlock-detector".into())
+                .spawn(move || {
+                    // Dropping the lock manager drops the sender, which ends the loop.
+                    while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                        shared.detect_deadlocks();
+                    }
+                })
+                .expect("failed to spawn deadlock detector")
+        };
+        LockManager { shared, detector: Some((stop, detector)), timeout: None }
+    }
+
+    // How long a transaction waits for a lock before giving up; for as long as it takes if
+    // None.
+    pub fn lock_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn lock_table(&self, txn: &Transaction, mode: LockMode, oid: Oid) -> CrabDbResult<()> {
+        self.lock(txn, mode, LockTarget::Table(oid))
+    }
+
+    pub fn lock_row(&self, txn: &Transaction, mode: LockMode, oid: Oid, rid: Rid) -> CrabDbResult<()> {
+        self.lock(txn, mode, LockTarget::Row(oid, rid))
+    }
+
+    // Blocks until `txn` holds `target` in `mode` or a mode covering it. A row is locked
+    // only after its table is locked in the matching intention mode.
+    pub fn lock(&self, txn: &Transaction, mode: LockMode, target: LockTarget) -> CrabDbResult<()> {
+        if let LockTarget::Row(oid, _) = target {
+            if !matches!(mode, LockMode::Shared | LockMode::Exclusive) {
+                return Err(CrabDBError::InvalidInput(format!("Cannot lock {target} in {mode} mode: rows are locked shared or exclusive")));
+            }
+            self.lock_one(txn, mode.intention(), LockTarget::Table(oid))?;
+        }
+        self.lock_one(txn, mode, target)
+    }
+
+    fn lock_one(&self, txn: &Transaction, mode: LockMode, target: LockTarget) -> CrabDbResult<()> {
+        match txn.state() {
+            TransactionState::Growing => {},
+            TransactionState::Aborted => return Err(CrabDBError::TxnAborted(txn.id())),
+            txn_state => return Err(CrabDBError::InvalidInput(format!(
+                "Transaction {} cannot lock {target}: it is {txn_state}", txn.id()
+            ))),
+        }
+        let held = txn.lock_mode(target);
+        if held.is_some_and(|held| held.covers(mode)) {
+            return Ok(());
+        }
+        // An upgrade asks for a mode covering both what is held and what is wanted.
+        let mode = held.map_or(mode, |held| held.combine(mode));
+
+        let mut state = self.shared.state.lock().unwrap();
+        let queue = state.queues.entry(target).or_default();
+        if held.is_some() {
+            if queue.upgrading().is_some() {
+                return Err(CrabDBError::Conflict(format!(
+                    "Transaction {} cannot upgrade its lock on {target}: another upgrade is already waiting", txn.id()
+                )));
+            }
+            // The lock held stays granted, and keeps others out, until the upgrade is.
+            let position = queue.position(txn.id()).unwrap();
+            let request = &mut queue.requests[position];
+            request.upgrade = Some(mode);
+            request.requested = Stopwatch::start();
+        } else {
+            queue.requests.push_back(LockRequest::new(txn.id(), mode));
+        }
+
+        let requested = Stopwatch::start();
+        loop {
+            if state.victims.remove(&txn.id()) {
+                state.queues.get_mut(&target).unwrap().withdraw(txn.id());
+                txn.set_state(TransactionState::Aborted);
+                // Whoever queued up behind the victim may be able to go now.
+                self.shared.released.notify_all();
+                return Err(CrabDBError::Deadlock(format!(
+                    "Transaction {} was aborted to break a deadlock while waiting to lock {target}", txn.id()
+                )));
+            }
+            let queue = state.queues.get_mut(&target).unwrap();
+            if queue.grantable(txn.id()) {
+                queue.grant(txn.id());
+                break;
+            }
+            trace_event!(tracing::Level::DEBUG, txn_id = txn.id(), %target, ?mode, "waiting for lock");
+            let Some(timeout) = self.timeout else {
+                state = self.shared.released.wait(state).unwrap();
+                continue;
+            };
+            let waited = requested.elapsed();
+            if waited >= timeout {
+                let queue = state.queues.get_mut(&target).unwrap();
+                let position = queue.position(txn.id()).unwrap();
+                let blockers: Vec<_> = queue.blockers(position).map(|request| request.txn_id.to_string()).collect();
+                queue.withdraw(txn.id());
+                txn.set_state(TransactionState::Aborted);
+                self.shared.released.notify_all();
+                return Err(CrabDBError::LockTimeout(format!(
+                    "Transaction {} gave up waiting to lock {target} {mode} after {waited:?}, for transaction(s) {}",
+                    txn.id(), blockers.join(", ")
+                )));
+            }
+            state = self.shared.released.wait_timeout(state, timeout - waited).unwrap().0;
+        }
+        txn.add_lock(target, mode);
+        Ok(())
+    }
+
+    // Aborts the youngest transaction on every cycle of the waits-for graph and returns the
+    // victims.
+    pub fn detect_deadlocks(&self) -> Vec<TxnId> {
+        self.shared.detect_deadlocks()
+    }
+
+    // Every transaction waiting for a lock, longest first, with the transactions it waits
+    // for.
+    pub fn lock_waits(&self) -> Vec<LockWait> {
+        let state = self.shared.state.lock().unwrap();
+        let mut waits = Vec::new();
+        for (target, queue) in &state.queues {
+            for (position, waiter) in queue.requests.iter().enumerate() {
+                if !waiter.is_waiting() {
+                    continue;
+                }
+                let (held_by, queued_behind): (Vec<_>, Vec<_>) = queue.blockers(position).partition(|request| request.granted);
+                waits.push(LockWait {
+                    txn_id: waiter.txn_id,
+                    target: *target,
+                    mode: waiter.wanted(),
+                    waited: waiter.requested.elapsed(),
+                    held_by: held_by.into_iter().map(|request| (request.txn_id, request.mode)).collect(),
+                    queued_behind: queued_behind.into_iter().map(|request| request.txn_id).collect(),
+                });
+            }
+        }
+        waits.sort_by(|a, b| b.waited.cmp(&a.waited).then(a.txn_id.cmp(&b.txn_id)));
+        waits
+    }
+
+    // Releases every lock `txn` holds; only called once the transaction has finished.
+    pub(crate) fn release_all(&self, txn: &Transaction) {
+        let locks = txn.take_locks();
+        if locks.is_empty() {
+            return;
+        }
+        let mut state = self.shared.state.lock().unwrap();
+        for target in locks.keys() {
+            if let Some(queue) = state.queues.get_mut(target) {
+                queue.requests.retain(|request| request.txn_id != txn.id());
+                if queue.requests.is_empty() {
+                    state.queues.remove(target);
+                }
+            }
+        }
+        self.shared.released.notify_all();
+    }
+}
+
+impl LockTable {
+    fn detect_deadlocks(&self) -> Vec<TxnId> {
+        let mut state = self.state.lock().unwrap();
+        let mut victims = Vec::new();
+        loop {
+            let graph = state.waits_for();
+            let Some(cycle) = find_cycle(&graph) else {
+                break;
+            };
+            let victim = cycle.into_iter().max().unwrap();
+            state.victims.insert(victim);
+            victims.push(victim);
+        }
+        if !victims.is_empty() {
+            self.released.notify_all();
+        }
+        victims
+    }
+}
+
+impl Drop for LockManager {
+    fn drop(&mut self) {
+        if let Some((stop, detector)) = self.detector.take() {
+            drop(stop);
+            let _ = detector.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::concurrency::transaction::{IsolationLevel, TransactionState};
+    use crate::concurrency::transaction_manager::TransactionManager;
+    use crate::storage::table::tuple::Rid;
+    use crate::types::{CrabDBError, ErrorCode};
+    use super::{LockManager, LockMode, LockTarget};
+
+    fn transaction_manager() -> Arc<TransactionManager> {
+        Arc::new(TransactionManager::new(Arc::new(LockManager::new())))
+    }
+
+    #[test]
+    pub fn test_lock_manager_shared_and_exclusive() {
+        let txn_manager = transaction_manager();
+        let lock_manager = txn_manager.lock_manager().clone();
+        let reader1 = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        let reader2 = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        lock_manager.lock_table(&reader1, LockMode::Shared, 1).unwrap();
+        lock_manager.lock_table(&reader2, LockMode::Shared, 1).unwrap();
+        lock_manager.lock_row(&reader1, LockMode::Exclusive, 2, Rid::new(3, 0)).unwrap();
+        assert_eq!(Some(LockMode::Exclusive), reader1.lock_mode(LockTarget::Row(2, Rid::new(3, 0))));
+
+        // the writer waits for both readers to finish
+        let (sender, receiver) = mpsc::channel();
+        let writer = {
+            let txn_manager = txn_manager.clone();
+            let lock_manager = lock_manager.clone();
+            thread::spawn(move || {
+                let writer = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+                lock_manager.lock_table(&writer, LockMode::Exclusive, 1).unwrap();
+                sender.send(()).unwrap();
+                txn_manager.commit(&writer).unwrap();
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        txn_manager.commit(&reader1).unwrap();
+        assert!(receiver.try_recv().is_err());
+        txn_manager.abort(&reader2).unwrap();
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        writer.join().unwrap();
+
+        assert_eq!(
+            format!("Transaction {} cannot lock table 1: it is committed", reader1.id()),
+            lock_manager.lock_table(&reader1, LockMode::Shared, 1).err().unwrap().to_string().as_str()
+        );
+    }
+
+    #[test]
+    pub fn test_lock_manager_row_locks_take_intention_locks() {
+        let txn_manager = transaction_manager();
+        let lock_manager = txn_manager.lock_manager().clone();
+        let writer = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        let reader = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        lock_manager.lock_row(&writer, LockMode::Exclusive, 1, Rid::new(1, 0)).unwrap();
+        lock_manager.lock_row(&reader, LockMode::Shared, 1, Rid::new(1, 1)).unwrap();
+        assert_eq!(Some(LockMode::IntentionExclusive), writer.lock_mode(LockTarget::Table(1)));
+        assert_eq!(Some(LockMode::IntentionShared), reader.lock_mode(LockTarget::Table(1)));
+        assert!(matches!(
+            lock_manager.lock_row(&reader, LockMode::IntentionShared, 1, Rid::new(1, 2)),
+            Err(CrabDBError::InvalidInput(_))
+        ));
+
+        // reading the whole table waits for the writer's rows, not the reader's
+        let (sender, receiver) = mpsc::channel();
+        let table_reader = {
+            let txn_manager = txn_manager.clone();
+            let lock_manager = lock_manager.clone();
+            thread::spawn(move || {
+                let table_reader = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+                lock_manager.lock_table(&table_reader, LockMode::Shared, 1).unwrap();
+                sender.send(()).unwrap();
+                txn_manager.commit(&table_reader).unwrap();
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert!(receiver.try_recv().is_err());
+        txn_manager.commit(&writer).unwrap();
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        table_reader.join().unwrap();
+
+        // reading the table and writing some of its rows holds it shared intention exclusive
+        lock_manager.lock_table(&reader, LockMode::Shared, 1).unwrap();
+        lock_manager.lock_row(&reader, LockMode::Exclusive, 1, Rid::new(1, 0)).unwrap();
+        assert_eq!(Some(LockMode::SharedIntentionExclusive), reader.lock_mode(LockTarget::Table(1)));
+        let other = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        lock_manager.lock_row(&other, LockMode::Shared, 1, Rid::new(2, 0)).unwrap();
+        txn_manager.commit(&reader).unwrap();
+        txn_manager.commit(&other).unwrap();
+    }
+
+    #[test]
+    pub fn test_lock_manager_upgrade_goes_first() {
+        let txn_manager = transaction_manager();
+        let lock_manager = txn_manager.lock_manager().clone();
+        let upgrader = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        let reader = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        lock_manager.lock_table(&upgrader, LockMode::Shared, 7).unwrap();
+        lock_manager.lock_table(&reader, LockMode::Shared, 7).unwrap();
+
+        // a writer queues up first, then the upgrade is requested
+        let (sender, receiver) = mpsc::channel();
+        let writer = {
+            let txn_manager = txn_manager.clone();
+            let lock_manager = lock_manager.clone();
+            let sender = sender.clone();
+            thread::spawn(move || {
+                let writer = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+                lock_manager.lock_table(&writer, LockMode::Exclusive, 7).unwrap();
+                sender.send("writer").unwrap();
+                txn_manager.commit(&writer).unwrap();
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        let upgrade = {
+            let txn_manager = txn_manager.clone();
+            let lock_manager = lock_manager.clone();
+            let upgrader = upgrader.clone();
+            thread::spawn(move || {
+                lock_manager.lock_table(&upgrader, LockMode::Exclusive, 7).unwrap();
+                sender.send("upgrader").unwrap();
+                txn_manager.commit(&upgrader).unwrap();
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert!(receiver.try_recv().is_err());
+
+        txn_manager.commit(&reader).unwrap();
+        assert_eq!("upgrader", receiver.recv_timeout(Duration::from_secs(5)).unwrap());
+        assert_eq!("writer", receiver.recv_timeout(Duration::from_secs(5)).unwrap());
+        upgrade.join().unwrap();
+        writer.join().unwrap();
+    }
+
+    #[test]
+    pub fn test_lock_manager_keeps_the_lock_being_upgraded() {
+        let txn_manager = Arc::new(TransactionManager::new(Arc::new(
+            LockManager::new().lock_timeout(Some(Duration::from_millis(100)))
+        )));
+        let lock_manager = txn_manager.lock_manager().clone();
+        let upgrader = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        let reader = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        lock_manager.lock_table(&upgrader, LockMode::Shared, 3).unwrap();
+        lock_manager.lock_table(&reader, LockMode::Shared, 3).unwrap();
+
+        // the upgrade waiting for the reader still holds the shared lock
+        let upgrade = {
+            let lock_manager = lock_manager.clone();
+            let upgrader = upgrader.clone();
+            thread::spawn(move || lock_manager.lock_table(&upgrader, LockMode::Exclusive, 3).unwrap_err())
+        };
+        thread::sleep(Duration::from_millis(30));
+        let waits = lock_manager.lock_waits();
+        assert_eq!(1, waits.len());
+        assert_eq!((upgrader.id(), LockMode::Exclusive), (waits[0].txn_id, waits[0].mode));
+        assert_eq!(vec![(reader.id(), LockMode::Shared)], waits[0].held_by);
+        assert!(matches!(upgrade.join().unwrap(), CrabDBError::LockTimeout(_)));
+        assert_eq!(Some(LockMode::Shared), upgrader.lock_mode(LockTarget::Table(3)));
+
+        // and keeps it after giving up, until it finishes
+        txn_manager.commit(&reader).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let writer = {
+            let txn_manager = txn_manager.clone();
+            let lock_manager = lock_manager.clone();
+            thread::spawn(move || {
+                let writer = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+                lock_manager.lock_table(&writer, LockMode::Exclusive, 3).unwrap();
+                sender.send(()).unwrap();
+                txn_manager.commit(&writer).unwrap();
+            })
+        };
+        thread::sleep(Duration::from_millis(30));
+        assert!(receiver.try_recv().is_err());
+        txn_manager.abort(&upgrader).unwrap();
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        writer.join().unwrap();
+    }
+
+    #[test]
+    pub fn test_lock_manager_breaks_deadlock() {
+        let txn_manager = Arc::new(TransactionManager::new(Arc::new(
+            LockManager::with_deadlock_detection(Duration::from_millis(10))
+        )));
+        let lock_manager = txn_manager.lock_manager().clone();
+        let older = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        let younger = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        lock_manager.lock_row(&older, LockMode::Exclusive, 1, Rid::new(1, 0)).unwrap();
+        lock_manager.lock_row(&younger, LockMode::Exclusive, 1, Rid::new(2, 0)).unwrap();
+
+        let waiter = {
+            let txn_manager = txn_manager.clone();
+            let lock_manager = lock_manager.clone();
+            let older = older.clone();
+            thread::spawn(move || {
+                lock_manager.lock_row(&older, LockMode::Exclusive, 1, Rid::new(2, 0)).unwrap();
+                txn_manager.commit(&older).unwrap();
+            })
+        };
+        thread::sleep(Duration::from_millis(20));
+        // closing the cycle gets the younger transaction picked as the victim
+        let error = lock_manager.lock_row(&younger, LockMode::Shared, 1, Rid::new(1, 0)).err().unwrap();
+        assert!(matches!(error, CrabDBError::Deadlock(_)));
+        assert_eq!(TransactionState::Aborted, younger.state());
+        txn_manager.abort(&younger).unwrap();
+        waiter.join().unwrap();
+        assert_eq!(TransactionState::Committed, older.state());
+        assert!(lock_manager.detect_deadlocks().is_empty());
+    }
+
+    #[test]
+    pub fn test_lock_manager_reports_waits_and_times_out() {
+        let txn_manager = Arc::new(TransactionManager::new(Arc::new(
+            LockManager::new().lock_timeout(Some(Duration::from_millis(200)))
+        )));
+        let lock_manager = txn_manager.lock_manager().clone();
+        let holder = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        lock_manager.lock_row(&holder, LockMode::Shared, 1, Rid::new(1, 0)).unwrap();
+        assert!(lock_manager.lock_waits().is_empty());
+
+        let waiter = {
+            let txn_manager = txn_manager.clone();
+            let lock_manager = lock_manager.clone();
+            thread::spawn(move || {
+                let waiter = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+                let error = lock_manager.lock_row(&waiter, LockMode::Exclusive, 1, Rid::new(1, 0)).err().unwrap();
+                assert_eq!(TransactionState::Aborted, waiter.state());
+                txn_manager.abort(&waiter).unwrap();
+                error
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        let waits = lock_manager.lock_waits();
+        assert_eq!(1, waits.len());
+        assert_eq!(LockTarget::Row(1, Rid::new(1, 0)), waits[0].target);
+        assert_eq!(LockMode::Exclusive, waits[0].mode);
+        assert_eq!(vec![(holder.id(), LockMode::Shared)], waits[0].held_by);
+        assert!(waits[0].queued_behind.is_empty());
+        assert!(waits[0].to_string().contains(&format!("held by {} (shared)", holder.id())));
+
+        // the holder never lets go, so the waiter gives up
+        let error = waiter.join().unwrap();
+        assert!(matches!(error, CrabDBError::LockTimeout(_)));
+        assert_eq!(ErrorCode::LockTimeout, error.code());
+        assert!(error.is_retryable());
+        assert!(lock_manager.lock_waits().is_empty());
+        txn_manager.commit(&holder).unwrap();
+    }
+}
@@ -0,0 +1,6 @@
+pub mod lock_manager;
+pub mod mvcc;
+pub(crate) mod ssi;
+pub mod transaction;
+pub mod transaction_manager;
+pub mod vacuum;
@@ -0,0 +1,241 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use crate::buffer_pool::access_strategy::BufferAccessStrategy;
+use crate::buffer_pool::eviction::replacer::AccessType;
+use crate::storage::common::{PageId, INVALID_PAGE_ID};
+use crate::storage::table::table_heap::TableHeap;
+use crate::storage::table::tuple::{Rid, SlotId, Timestamp, Tuple, TupleMeta};
+use crate::types::CrabDbResult;
+
+use super::transaction::{Transaction, TXN_TS_FLAG};
+
+// A version a write replaced: the tuple as it was and the commit timestamp it carried.
+pub(crate) struct UndoVersion {
+    pub ts: Timestamp,
+    pub tuple: Tuple,
+}
+
+// Older versions of heap tuples. The heap always holds the newest version of a row in place
+// and each tuple's chain holds what it replaced, oldest first. Lock order: a page latch is
+// taken before the chains, never after, so a reader sees a tuple and its chain consistently.
+#[derive(Default)]
+pub(crate) struct VersionStore {
+    chains: Mutex<HashMap<Rid, Vec<UndoVersion>>>,
+}
+
+// Whether `txn` sees a version stamped with `ts`: its own writes, and whatever committed no
+// later than its snapshot.
+pub(crate) fn is_visible(txn: &Transaction, ts: Timestamp) -> bool {
+    ts == txn.temp_ts() || (ts & TXN_TS_FLAG == 0 && ts <= txn.read_ts())
+}
+
+impl VersionStore {
+    pub(crate) fn push(&self, rid: Rid, version: UndoVersion) {
+        self.chains.lock().unwrap().entry(rid).or_default().push(version);
+    }
+
+    pub(crate) fn pop(&self, rid: Rid) -> Option<UndoVersion> {
+        let mut chains = self.chains.lock().unwrap();
+        let chain = chains.get_mut(&rid)?;
+        let version = chain.pop();
+        if chain.is_empty() {
+            chains.remove(&rid);
+        }
+        version
+    }
+
+    // Drops the versions of `rid` that no snapshot at or after `watermark` can reach, given
+    // the timestamp of the tuple in the heap. Returns how many were dropped.
+    pub(crate) fn prune(&self, rid: Rid, head_ts: Timestamp, watermark: Timestamp) -> usize {
+        let mut chains = self.chains.lock().unwrap();
+        let Some(chain) = chains.get_mut(&rid) else {
+            return 0;
+        };
+        let before = chain.len();
+        if head_ts & TXN_TS_FLAG == 0 && head_ts <= watermark {
+            chain.clear();
+        } else if let Some(oldest_needed) = chain.iter().rposition(|version| version.ts <= watermark) {
+            chain.drain(..oldest_needed);
+        }
+        let removed = before - chain.len();
+        if chain.is_empty() {
+            chains.remove(&rid);
+        }
+        removed
+    }
+
+    // Drops every version of `rid`, once its head is the only one a snapshot can read.
+    pub(crate) fn discard(&self, rid: Rid) {
+        self.chains.lock().unwrap().remove(&rid);
+    }
+
+    // Drops the versions of every tuple on `page_ids`, once the heap they were in is gone.
+    pub(crate) fn forget(&self, page_ids: &HashSet<PageId>) {
+        self.chains.lock().unwrap().retain(|rid, _| !page_ids.contains(&rid.page_id()));
+    }
+
+    // The versions of `rid` its writes replaced, oldest first.
+    pub(crate) fn replaced(&self, rid: Rid) -> Vec<Tuple> {
+        self.chains.lock().unwrap().get(&rid).map_or_else(Vec::new, |chain| chain.iter().map(|version| version.tuple.clone()).collect())
+    }
+
+    pub(crate) fn has_versions(&self, rid: Rid) -> bool {
+        self.chains.lock().unwrap().contains_key(&rid)
+    }
+
+    // The version of a heap tuple `txn` sees, given the newest one; must be called with the
+    // tuple's page latched.
+    pub(crate) fn visible_version(&self, txn: &Transaction, rid: Rid, meta: TupleMeta, data: &[u8]) -> Option<Tuple> {
+        if is_visible(txn, meta.ts) {
+            return (!meta.is_deleted).then(|| Tuple::new(data.to_vec()));
+        }
+        let chains = self.chains.lock().unwrap();
+        chains.get(&rid)?.iter().rev()
+            .find(|version| version.ts <= txn.read_ts())
+            .map(|version| version.tuple.clone())
+    }
+
+    // The version of a heap tuple committed as of `txn`'s snapshot, leaving out the writes
+    // of `txn` itself; must be called with the tuple's page latched.
+    pub(crate) fn committed_version(&self, txn: &Transaction, rid: Rid, meta: TupleMeta, data: &[u8]) -> Option<Tuple> {
+        if meta.ts & TXN_TS_FLAG == 0 && meta.ts <= txn.read_ts() {
+            return (!meta.is_deleted).then(|| Tuple::new(data.to_vec()));
+        }
+        let chains = self.chains.lock().unwrap();
+        chains.get(&rid)?.iter().rev()
+            .find(|version| version.ts <= txn.read_ts())
+            .map(|version| version.tuple.clone())
+    }
+}
+
+// Walks a heap yielding the version of each row visible to a transaction, skipping rows it
+// cannot see. Like `TableIterator`, it only holds a page while reading from it.
+pub struct SnapshotIterator<'a> {
+    heap: &'a TableHeap,
+    versions: &'a VersionStore,
+    txn: &'a Transaction,
+    page_id: PageId,
+    slot_id: SlotId,
+    strategy: Option<BufferAccessStrategy>,
+}
+
+impl<'a> SnapshotIterator<'a> {
+    pub(crate) fn new(heap: &'a TableHeap, versions: &'a VersionStore, txn: &'a Transaction) -> Self {
+        SnapshotIterator { heap, versions, txn, page_id: heap.first_page_id(), slot_id: 0, strategy: None }
+    }
+
+    // Reads the heap's pages through the strategy's ring of frames.
+    pub fn with_strategy(mut self, strategy: BufferAccessStrategy) -> Self {
+        self.strategy = Some(strategy);
+        self
+    }
+
+    fn advance(&mut self) -> CrabDbResult<Option<(Rid, Tuple)>> {
+        while self.page_id != INVALID_PAGE_ID {
+            let (found, next_page_id) = self.heap.read_page_with_strategy(self.page_id, AccessType::Lookup, self.strategy.as_ref(), |page| {
+                while self.slot_id < page.num_slots() {
+                    let rid = Rid::new(self.page_id, self.slot_id);
+                    self.slot_id += 1;
+                    if let Ok((meta, data)) = page.get_tuple_with_meta(rid.slot_id()) {
+                        if let Some(tuple) = self.versions.visible_version(self.txn, rid, meta, data) {
+                            let tuple = self.heap.resolve_overflow(page.is_overflow(rid.slot_id())?, tuple)?;
+                            return Ok((Some((rid, tuple)), self.page_id));
+                        }
+                    }
+                }
+                Ok((None, page.next_page_id()))
+            })?;
+            if found.is_some() {
+                return Ok(found);
+            }
+            self.page_id = next_page_id;
+            self.slot_id = 0;
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for SnapshotIterator<'_> {
+    type Item = CrabDbResult<(Rid, Tuple)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.advance() {
+            Ok(entry) => entry.map(Ok),
+            Err(e) => {
+                self.page_id = INVALID_PAGE_ID;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::concurrency::transaction::{IsolationLevel, Transaction, TXN_TS_FLAG};
+    use crate::storage::table::tuple::{Rid, Tuple, TupleMeta};
+    use super::{is_visible, UndoVersion, VersionStore};
+
+    fn transaction(id: u64, read_ts: u64, versions: &Arc<VersionStore>) -> Transaction {
+        Transaction::new(id, IsolationLevel::SnapshotIsolation, read_ts, versions.clone())
+    }
+
+    fn version(ts: u64, data: &[u8]) -> UndoVersion {
+        UndoVersion { ts, tuple: Tuple::new(data.to_vec()) }
+    }
+
+    #[test]
+    pub fn test_mvcc_sees_own_writes_and_commits_up_to_its_snapshot() {
+        let versions = Arc::new(VersionStore::default());
+        let txn = transaction(7, 10, &versions);
+        assert!(is_visible(&txn, 10) && is_visible(&txn, 1));
+        assert!(!is_visible(&txn, 11));
+        assert!(is_visible(&txn, txn.temp_ts()));
+        assert!(!is_visible(&txn, TXN_TS_FLAG | 8));
+    }
+
+    #[test]
+    pub fn test_mvcc_walks_the_version_chain_back_to_the_snapshot() {
+        let versions = Arc::new(VersionStore::default());
+        let rid = Rid::new(3, 1);
+        versions.push(rid, version(5, b"five"));
+        versions.push(rid, version(9, b"nine"));
+        let txn = transaction(7, 10, &versions);
+        let head = |ts, is_deleted| TupleMeta { ts, is_deleted };
+        let seen = |meta| versions.visible_version(&txn, rid, meta, b"head").map(|tuple| tuple.data().to_vec());
+
+        // a head committed after the snapshot, or written by another transaction, is skipped
+        assert_eq!(Some(b"nine".to_vec()), seen(head(12, false)));
+        assert_eq!(Some(b"nine".to_vec()), seen(head(TXN_TS_FLAG | 8, false)));
+        // a visible head is the row, unless it is a delete
+        assert_eq!(Some(b"head".to_vec()), seen(head(10, false)));
+        assert_eq!(None, seen(head(10, true)));
+        assert_eq!(Some(b"head".to_vec()), seen(head(txn.temp_ts(), false)));
+        // nothing at or before the snapshot: the row did not exist yet
+        let early = transaction(8, 4, &versions);
+        assert!(versions.visible_version(&early, rid, head(12, false), b"head").is_none());
+
+        // the committed version leaves out the transaction's own write
+        let committed = versions.committed_version(&txn, rid, head(txn.temp_ts(), false), b"head");
+        assert_eq!(Some(b"nine".to_vec()), committed.map(|tuple| tuple.data().to_vec()));
+    }
+
+    #[test]
+    pub fn test_mvcc_prunes_versions_no_snapshot_reaches() {
+        let versions = VersionStore::default();
+        let rid = Rid::new(3, 1);
+        for ts in [5, 9, 12] {
+            versions.push(rid, version(ts, b"old"));
+        }
+        // a snapshot at 10 still needs the version at 9, but not the one at 5
+        assert_eq!(1, versions.prune(rid, 15, 10));
+        assert_eq!(0, versions.prune(rid, TXN_TS_FLAG | 8, 10));
+        assert!(versions.has_versions(rid));
+        // once the head itself is old enough, no version is needed
+        assert_eq!(2, versions.prune(rid, 15, 15));
+        assert!(!versions.has_versions(rid));
+        assert_eq!(0, versions.prune(rid, 15, 15));
+    }
+}
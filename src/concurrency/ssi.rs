@@ -0,0 +1,175 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use crate::storage::common::PageId;
+use crate::storage::table::tuple::{Rid, Timestamp};
+use crate::types::{CrabDBError, CrabDbResult};
+use crate::wal::common::TxnId;
+
+// What a serializable transaction read or wrote. A scan reads the whole heap, identified by
+// its first page, and every write also counts as a write to its heap, so an insert
+// conflicts with a scan that could have returned the new row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum SsiKey {
+    Row(Rid),
+    Heap(PageId),
+}
+
+struct SsiTxn {
+    read_ts: Timestamp,
+    commit_ts: Option<Timestamp>,
+    reads: HashSet<SsiKey>,
+    writes: HashSet<SsiKey>,
+    // Some concurrent transaction read what this one wrote (rw-antidependency into it).
+    in_conflict: bool,
+    // This one read something a concurrent transaction wrote (rw-antidependency out of it).
+    out_conflict: bool,
+}
+
+// Tracks reads and writes of serializable transactions to find the dangerous structures of
+// Serializable Snapshot Isolation: a transaction with rw-antidependencies both into and out
+// of it. Whichever transaction would complete one at commit is aborted instead, which
+// rules out anomalies like write skew that plain snapshot isolation allows. Conflicts are
+// recorded at commit, so the check is conservative: a transaction can be aborted because of
+// one that later aborts itself.
+#[derive(Default)]
+pub(crate) struct SsiTracker {
+    txns: Mutex<HashMap<TxnId, SsiTxn>>,
+}
+
+impl SsiTracker {
+    pub(crate) fn register(&self, txn_id: TxnId, read_ts: Timestamp) {
+        self.txns.lock().unwrap().insert(txn_id, SsiTxn {
+            read_ts,
+            commit_ts: None,
+            reads: HashSet::new(),
+            writes: HashSet::new(),
+            in_conflict: false,
+            out_conflict: false,
+        });
+    }
+
+    // Does nothing for transactions that are not serializable.
+    pub(crate) fn record_read(&self, txn_id: TxnId, key: SsiKey) {
+        if let Some(txn) = self.txns.lock().unwrap().get_mut(&txn_id) {
+            txn.reads.insert(key);
+        }
+    }
+
+    pub(crate) fn record_write(&self, txn_id: TxnId, key: SsiKey) {
+        if let Some(txn) = self.txns.lock().unwrap().get_mut(&txn_id) {
+            txn.writes.insert(key);
+        }
+    }
+
+    // Adds the rw-antidependencies between `txn_id` and every transaction concurrent with
+    // it, then either records it as committed at `commit_ts` or, if that would leave a
+    // dangerous structure, fails without changing anything. Commits must be checked one at
+    // a time, in timestamp order.
+    pub(crate) fn check_commit(&self, txn_id: TxnId, commit_ts: Timestamp) -> CrabDbResult<()> {
+        let mut txns = self.txns.lock().unwrap();
+        let Some(txn) = txns.get(&txn_id) else {
+            return Ok(());
+        };
+        let mut in_conflict = txn.in_conflict;
+        let mut out_conflict = txn.out_conflict;
+        // (other transaction, it now has an edge into it, it now has an edge out of it)
+        let mut edges = Vec::new();
+        for (&other_id, other) in txns.iter() {
+            let concurrent = other.commit_ts.is_none_or(|other_commit_ts| other_commit_ts > txn.read_ts);
+            if other_id == txn_id || !concurrent {
+                continue;
+            }
+            let other_out = !other.reads.is_disjoint(&txn.writes);
+            let other_in = !txn.reads.is_disjoint(&other.writes);
+            in_conflict |= other_out;
+            out_conflict |= other_in;
+            if other_out || other_in {
+                edges.push((other_id, other_in, other_out));
+            }
+        }
+
+        let pivot_committed = edges.iter().any(|&(other_id, other_in, other_out)| {
+            let other = &txns[&other_id];
+            other.commit_ts.is_some() && (other.in_conflict || other_in) && (other.out_conflict || other_out)
+        });
+        if (in_conflict && out_conflict) || pivot_committed {
+            return Err(CrabDBError::Conflict(format!(
+                "Transaction {txn_id} could not be serialized with concurrent transactions"
+            )));
+        }
+
+        for (other_id, other_in, other_out) in edges {
+            let other = txns.get_mut(&other_id).unwrap();
+            other.in_conflict |= other_in;
+            other.out_conflict |= other_out;
+        }
+        let txn = txns.get_mut(&txn_id).unwrap();
+        txn.in_conflict = in_conflict;
+        txn.out_conflict = out_conflict;
+        txn.commit_ts = Some(commit_ts);
+        Ok(())
+    }
+
+    pub(crate) fn remove(&self, txn_id: TxnId) {
+        self.txns.lock().unwrap().remove(&txn_id);
+    }
+
+    // Forgets committed transactions that no running transaction overlaps with anymore.
+    pub(crate) fn prune(&self, watermark: Timestamp) {
+        self.txns.lock().unwrap().retain(|_, txn| txn.commit_ts.is_none_or(|commit_ts| commit_ts > watermark));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::table::tuple::Rid;
+    use crate::types::CrabDBError;
+    use super::{SsiKey, SsiTracker};
+
+    #[test]
+    pub fn test_ssi_aborts_one_of_a_write_skew_pair() {
+        let (x, y) = (SsiKey::Row(Rid::new(1, 0)), SsiKey::Row(Rid::new(1, 1)));
+        let tracker = SsiTracker::default();
+        // T1 reads x and writes y, T2 reads y and writes x, from the same snapshot
+        for (txn_id, read, written) in [(1, x, y), (2, y, x)] {
+            tracker.register(txn_id, 10);
+            tracker.record_read(txn_id, read);
+            tracker.record_write(txn_id, written);
+        }
+        let mut aborted = 0;
+        for (txn_id, commit_ts) in [(1, 11), (2, 12)] {
+            match tracker.check_commit(txn_id, commit_ts) {
+                Ok(()) => {},
+                Err(CrabDBError::Conflict(_)) => {
+                    tracker.remove(txn_id);
+                    aborted += 1;
+                },
+                Err(e) => panic!("unexpected error {e}"),
+            }
+        }
+        assert_eq!(1, aborted);
+    }
+
+    #[test]
+    pub fn test_ssi_prune_does_not_abort_read_only_transactions() {
+        let x = SsiKey::Row(Rid::new(1, 0));
+        let tracker = SsiTracker::default();
+        // a reader of x and a writer of x that commits first
+        tracker.register(1, 10);
+        tracker.record_read(1, x);
+        tracker.register(2, 10);
+        tracker.record_read(2, x);
+        tracker.record_write(2, x);
+        tracker.check_commit(2, 11).unwrap();
+
+        // pruning drops the committed writer but keeps the running reader, which commits, as
+        // does a reader that starts after both
+        tracker.prune(11);
+        tracker.check_commit(1, 12).unwrap();
+        tracker.register(3, 12);
+        tracker.record_read(3, x);
+        tracker.prune(12);
+        tracker.check_commit(3, 13).unwrap();
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+use crate::storage::common::{PageId, PAGE_HEADER_SIZE};
+use crate::storage::page::table_page::TablePage;
+use crate::storage::table::tuple::{Rid, Timestamp, Tuple};
+use crate::types::{CrabDBError, CrabDbResult};
+use crate::wal::common::{Lsn, TxnId, INVALID_LSN};
+use crate::wal::log_manager::LogManager;
+use crate::wal::log_record::LogRecordBody;
+
+use super::lock_manager::{LockMode, LockTarget};
+use super::mvcc::{UndoVersion, VersionStore};
+
+// Versions written by a transaction that has not committed yet carry this flag plus its id
+// in place of a commit timestamp.
+pub(crate) const TXN_TS_FLAG: Timestamp = 1 << 62;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IsolationLevel {
+    // Reads see a consistent snapshot and concurrent writes to the same row conflict, but
+    // anomalies such as write skew are possible.
+    #[default]
+    SnapshotIsolation,
+    // Snapshot isolation that also aborts transactions whose reads and writes could not
+    // have happened in some serial order. Scans lock the rows they return in shared mode.
+    Serializable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionState {
+    // Running; under strict two-phase locking a transaction keeps acquiring locks until it
+    // finishes and releases them all at once.
+    Growing,
+    Committed,
+    Aborted,
+}
+
+impl Display for TransactionState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransactionState::Growing => write!(f, "growing"),
+            TransactionState::Committed => write!(f, "committed"),
+            TransactionState::Aborted => write!(f, "aborted"),
+        }
+    }
+}
+
+// What a tuple looked like before a write of the transaction.
+pub(crate) enum PriorVersion {
+    // The transaction inserted the tuple; there is nothing to go back to.
+    Inserted,
+    // A committed version, which the write pushed onto the tuple's undo chain.
+    Committed,
+    // A version the transaction itself wrote earlier.
+    Own(Tuple),
+}
+
+// A write of the transaction, remembered so commit can stamp the tuple and a rollback can
+// undo it.
+pub(crate) struct WriteRecord {
+    pub rid: Rid,
+    pub bpm: Arc<BufferPoolManager>,
+    // First page of the heap the tuple is in.
+    pub heap: PageId,
+    pub prior: PriorVersion,
+    // LSN of the write's log record, if the buffer pool has a log.
+    pub lsn: Lsn,
+}
+
+// The writes made since a savepoint was taken, or since the transaction began for the first
+// segment, which has no name, in the order they were made.
+struct WriteSegment {
+    savepoint: Option<String>,
+    writes: Vec<WriteRecord>,
+    // What a rollback does besides undoing the writes, such as pointing an index entry back
+    // at a row the transaction deleted; see `on_rollback`.
+    rollback_actions: Vec<RollbackAction>,
+}
+
+type RollbackAction = Box<dyn FnOnce() -> CrabDbResult<()> + Send>;
+
+impl WriteSegment {
+    fn new(savepoint: Option<String>) -> Self {
+        WriteSegment { savepoint, writes: Vec::new(), rollback_actions: Vec::new() }
+    }
+}
+
+// Where the transaction's log records go, once it has logged one, and the last one's LSN,
+// which the next one links back to.
+struct TxnLog {
+    log_manager: Option<Arc<LogManager>>,
+    last_lsn: Lsn,
+}
+
+pub struct Transaction {
+    id: TxnId,
+    isolation: IsolationLevel,
+    // The snapshot: versions committed at or before this timestamp are visible.
+    read_ts: Timestamp,
+    state: Mutex<TransactionState>,
+    // Every lock the transaction holds, so they can be released when it finishes.
+    locks: Mutex<HashMap<LockTarget, LockMode>>,
+    write_set: Mutex<Vec<WriteSegment>>,
+    log: Mutex<TxnLog>,
+    versions: Arc<VersionStore>,
+    finished: AtomicBool,
+    // Whether the snapshot is of the past, for a time-travel query; such a transaction
+    // cannot write.
+    historical: bool,
+}
+
+impl Transaction {
+    pub(crate) fn new(id: TxnId, isolation: IsolationLevel, read_ts: Timestamp, versions: Arc<VersionStore>) -> Self {
+        Transaction {
+            id,
+            isolation,
+            read_ts,
+            state: Mutex::new(TransactionState::Growing),
+            locks: Mutex::new(HashMap::new()),
+            write_set: Mutex::new(vec![WriteSegment::new(None)]),
+            log: Mutex::new(TxnLog { log_manager: None, last_lsn: INVALID_LSN }),
+            versions,
+            finished: AtomicBool::new(false),
+            historical: false,
+        }
+    }
+
+    pub(crate) fn historical(mut self) -> Self {
+        self.historical = true;
+        self
+    }
+
+    pub fn is_historical(&self) -> bool {
+        self.historical
+    }
+
+    pub fn id(&self) -> TxnId {
+        self.id
+    }
+
+    pub fn isolation(&self) -> IsolationLevel {
+        self.isolation
+    }
+
+    pub fn read_ts(&self) -> Timestamp {
+        self.read_ts
+    }
+
+    // What the transaction's uncommitted versions carry instead of a commit timestamp.
+    pub(crate) fn temp_ts(&self) -> Timestamp {
+        TXN_TS_FLAG | self.id
+    }
+
+    pub fn state(&self) -> TransactionState {
+        *self.state.lock().unwrap()
+    }
+
+    pub(crate) fn set_state(&self, state: TransactionState) {
+        *self.state.lock().unwrap() = state;
+    }
+
+    // The mode the transaction holds `target` in, if any.
+    pub fn lock_mode(&self, target: LockTarget) -> Option<LockMode> {
+        self.locks.lock().unwrap().get(&target).copied()
+    }
+
+    pub(crate) fn add_lock(&self, target: LockTarget, mode: LockMode) {
+        self.locks.lock().unwrap().insert(target, mode);
+    }
+
+    pub(crate) fn take_locks(&self) -> HashMap<LockTarget, LockMode> {
+        std::mem::take(&mut *self.locks.lock().unwrap())
+    }
+
+    // Returns true the first time it is called, so a transaction is only retired once.
+    pub(crate) fn finish(&self) -> bool {
+        !self.finished.swap(true, Ordering::SeqCst)
+    }
+
+    pub(crate) fn add_write(&self, record: WriteRecord) {
+        let mut write_set = self.write_set.lock().unwrap();
+        write_set.last_mut().unwrap().writes.push(record);
+    }
+
+    // Every tuple the transaction wrote, for commit to stamp.
+    pub(crate) fn written(&self) -> Vec<(Rid, Arc<BufferPoolManager>)> {
+        let write_set = self.write_set.lock().unwrap();
+        let mut written: HashMap<Rid, Arc<BufferPoolManager>> = HashMap::new();
+        for record in write_set.iter().flat_map(|segment| &segment.writes) {
+            written.entry(record.rid).or_insert_with(|| record.bpm.clone());
+        }
+        written.into_iter().collect()
+    }
+
+    // Every tuple the transaction wrote, with the first page of its heap and whether the
+    // transaction inserted it, in rid order.
+    pub(crate) fn changed_rows(&self) -> Vec<(PageId, Rid, bool)> {
+        let write_set = self.write_set.lock().unwrap();
+        let mut changed: BTreeMap<Rid, (PageId, bool)> = BTreeMap::new();
+        for record in write_set.iter().flat_map(|segment| &segment.writes) {
+            changed.entry(record.rid).or_insert((record.heap, matches!(record.prior, PriorVersion::Inserted)));
+        }
+        changed.into_iter().map(|(rid, (heap, inserted))| (heap, rid, inserted)).collect()
+    }
+
+    // Marks a point the transaction can later roll back to without aborting. Taking a
+    // savepoint under a name that is already in use moves it.
+    pub fn savepoint(&self, name: &str) -> CrabDbResult<()> {
+        self.check_growing()?;
+        let mut write_set = self.write_set.lock().unwrap();
+        write_set.push(WriteSegment::new(Some(name.to_string())));
+        Ok(())
+    }
+
+    // Undoes every write made since savepoint `name` was taken and discards the savepoints
+    // taken after it. The savepoint itself stays, so it can be rolled back to again. Locks
+    // acquired since are kept.
+    pub fn rollback_to(&self, name: &str) -> CrabDbResult<()> {
+        self.check_growing()?;
+        let mut write_set = self.write_set.lock().unwrap();
+        let position = write_set.iter()
+            .rposition(|segment| segment.savepoint.as_deref() == Some(name))
+            .ok_or_else(|| CrabDBError::InvalidInput(format!("Savepoint {name} does not exist")))?;
+        let segments: Vec<WriteSegment> = write_set.drain(position..).collect();
+        let undo_next_lsn = write_set.iter().flat_map(|segment| &segment.writes).last().map_or(INVALID_LSN, |record| record.lsn);
+        write_set.push(WriteSegment::new(Some(name.to_string())));
+        self.roll_back(segments, undo_next_lsn)
+    }
+
+    // Undoes all of the transaction's writes.
+    pub(crate) fn undo_all(&self) -> CrabDbResult<()> {
+        let segments = std::mem::take(&mut *self.write_set.lock().unwrap());
+        self.roll_back(segments, INVALID_LSN)
+    }
+
+    // Has a rollback of the writes made so far, whole or to a savepoint taken before now,
+    // run `action` once it has undone them.
+    pub(crate) fn on_rollback(&self, action: impl FnOnce() -> CrabDbResult<()> + Send + 'static) {
+        let mut write_set = self.write_set.lock().unwrap();
+        write_set.last_mut().unwrap().rollback_actions.push(Box::new(action));
+    }
+
+    // Undoes the writes of `segments`, then runs their rollback actions, newest first.
+    fn roll_back(&self, segments: Vec<WriteSegment>, undo_next_lsn: Lsn) -> CrabDbResult<()> {
+        let mut writes = Vec::new();
+        let mut actions = Vec::new();
+        for segment in segments {
+            writes.extend(segment.writes);
+            actions.extend(segment.rollback_actions);
+        }
+        self.undo(writes, undo_next_lsn)?;
+        for action in actions.into_iter().rev() {
+            action()?;
+        }
+        Ok(())
+    }
+
+    // Undoes `writes`, newest first, logging a compensation record for each that points the
+    // rest of a rollback interrupted by a crash at the write before, the last one at
+    // `undo_next_lsn`.
+    fn undo(&self, mut writes: Vec<WriteRecord>, undo_next_lsn: Lsn) -> CrabDbResult<()> {
+        while let Some(record) = writes.pop() {
+            let rid = record.rid;
+            let undo_next_lsn = writes.last().map_or(undo_next_lsn, |before| before.lsn);
+            record.bpm.write_page_logged(
+                rid.page_id(),
+                |data| {
+                    // Popped with the page latched, as the undo chains are always taken after it.
+                    let prior = match record.prior {
+                        PriorVersion::Inserted => None,
+                        PriorVersion::Committed => self.versions.pop(rid).map(|UndoVersion { ts, tuple }| (ts, tuple)),
+                        PriorVersion::Own(tuple) => Some((self.temp_ts(), tuple)),
+                    };
+                    let prior = prior.as_ref().map(|(ts, tuple)| (*ts, tuple.data()));
+                    if !TablePage::new(data).restore_tuple(rid.slot_id(), prior)? {
+                        return Err(CrabDBError::new(format!("Cannot roll back row {rid}: its page is full")));
+                    }
+                    Ok(())
+                },
+                |log_manager, _, before, after| {
+                    let body = LogRecordBody::compensation(rid.page_id(), before, after, undo_next_lsn);
+                    body.map(|body| self.log(log_manager, |_| body)).transpose()
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    // Appends the transaction's next log record to `log_manager`, which its earlier ones went
+    // to as well, without flushing it. `body` gets the LSN of the one before.
+    pub(crate) fn log(&self, log_manager: &Arc<LogManager>, body: impl FnOnce(Lsn) -> LogRecordBody) -> CrabDbResult<Lsn> {
+        let mut log = self.log.lock().unwrap();
+        let lsn = log_manager.append_buffered(self.id, log.last_lsn, body(log.last_lsn))?;
+        log.log_manager.get_or_insert_with(|| log_manager.clone());
+        log.last_lsn = lsn;
+        Ok(lsn)
+    }
+
+    // Logs how the transaction ended, Commit or Abort, if it logged anything before. Returns
+    // the log and the LSN to flush it to.
+    pub(crate) fn log_end(&self, body: LogRecordBody) -> CrabDbResult<Option<(Arc<LogManager>, Lsn)>> {
+        let log_manager = self.log.lock().unwrap().log_manager.clone();
+        match log_manager {
+            Some(log_manager) => {
+                let lsn = self.log(&log_manager, |_| body)?;
+                Ok(Some((log_manager, lsn)))
+            },
+            None => Ok(None),
+        }
+    }
+
+    // Logs the pages of `bpm` changed without log records whole, such as those of indexes,
+    // as part of the transaction, so redoing it brings them back. Such changes are never
+    // undone, so neither are these records.
+    pub(crate) fn log_unlogged_pages(&self, bpm: &BufferPoolManager) -> CrabDbResult<()> {
+        let Some(log_manager) = bpm.log_manager() else {
+            return Ok(());
+        };
+        bpm.log_unlogged_pages(|page_id, image| {
+            self.log(log_manager, |prev_lsn| LogRecordBody::Compensation {
+                page_id,
+                offset: PAGE_HEADER_SIZE as u16,
+                after: image.to_vec(),
+                undo_next_lsn: prev_lsn,
+            })
+        })
+    }
+
+    pub(crate) fn check_growing(&self) -> CrabDbResult<()> {
+        match self.state() {
+            TransactionState::Growing => Ok(()),
+            TransactionState::Aborted => Err(CrabDBError::TxnAborted(self.id)),
+            state => Err(CrabDBError::InvalidInput(format!("Transaction {} is {state}", self.id))),
+        }
+    }
+}
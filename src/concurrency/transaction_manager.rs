@@ -0,0 +1,847 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+use crate::platform;
+use crate::storage::page::table_page::TablePage;
+use crate::storage::table::table_heap::TableHeap;
+use crate::storage::table::tuple::{Rid, Timestamp, Tuple};
+use crate::types::{CrabDBError, CrabDbResult};
+use crate::wal::common::{Lsn, INVALID_LSN};
+use crate::wal::log_record::LogRecordBody;
+
+use super::lock_manager::{LockManager, LockMode};
+use super::mvcc::{is_visible, SnapshotIterator, UndoVersion, VersionStore};
+use super::ssi::{SsiKey, SsiTracker};
+use super::transaction::{IsolationLevel, PriorVersion, Transaction, TransactionState, WriteRecord};
+
+// A point in the history of the database to read it as of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsOf {
+    // Microseconds since the Unix epoch, on the engine's clock (see `platform::clock`).
+    Timestamp(u64),
+    // A position in the write-ahead log: what was committed by then.
+    Lsn(Lsn),
+    // A commit timestamp, such as the one a `SnapshotPin` holds.
+    Commit(Timestamp),
+}
+
+// A commit that wrote something: its timestamp, when it happened and the LSN of its Commit
+// record, or of the one before it if it was not logged.
+struct CommitPoint {
+    ts: Timestamp,
+    time_micros: u64,
+    lsn: Lsn,
+}
+
+// Commits whose log is durable, waiting to be published in timestamp order, and why the
+// database went offline if it did.
+#[derive(Default)]
+struct Publishing {
+    durable: BTreeMap<Timestamp, DurableCommit>,
+    offline: Option<String>,
+}
+
+struct DurableCommit {
+    wrote: bool,
+    // LSN of the Commit record, if the transaction logged anything.
+    lsn: Option<Lsn>,
+    time_micros: u64,
+}
+
+// The snapshots versions are kept for, each counted.
+#[derive(Default)]
+struct Snapshots {
+    running: BTreeMap<Timestamp, usize>,
+    pinned: BTreeMap<Timestamp, usize>,
+    // The oldest snapshot vacuum still keeps versions for. It only moves forward, and no
+    // snapshot older than it can begin.
+    horizon: Timestamp,
+}
+
+// Starts and finishes transactions and runs their reads and writes against table heaps
+// under snapshot isolation. Every transaction reads the database as of the latest commit
+// when it began, so readers never block writers. Writes lock their row exclusively, and
+// its table in intention exclusive mode, if the heap belongs to one: of two transactions
+// writing the same row, the second waits for the first to finish and then fails with a
+// conflict if the first committed. Serializable transactions are also
+// checked for rw-antidependency cycles (see `SsiTracker`) when they commit. Finishing a
+// transaction, either way, releases all of its locks.
+//
+// Writes to heaps whose buffer pool has a log are logged as the transaction's, and so is
+// stamping them at commit, followed by its Commit record. The log is flushed up to that,
+// along with the commits that came in meanwhile, before the commit is published: snapshots
+// only see commits that survive a crash, in timestamp order. A flush that fails takes the
+// manager offline, as nothing can tell what reached the disk; every write and commit after
+// that fails. Rollbacks log a compensation record per write undone.
+//
+// Versions are kept for the snapshots of running transactions, for those pinned (see
+// `SnapshotPin`) and, given a retention, for every snapshot of the last while, so queries can
+// read the database as it was then (see `begin_as_of`). Commits are remembered with their
+// time and LSN for as long, to find the snapshot of a point in time; nothing before the
+// manager was created is.
+pub struct TransactionManager {
+    lock_manager: Arc<LockManager>,
+    next_txn_id: AtomicU64,
+    // Timestamp of the latest commit published: its versions are all stamped and its log is
+    // durable. Snapshots start here.
+    last_commit_ts: AtomicU64,
+    // Serializes commits so timestamps are handed out in order; holds the last one handed
+    // out, which runs ahead of `last_commit_ts` while commits wait for their flush.
+    commit_latch: Mutex<Timestamp>,
+    publishing: Mutex<Publishing>,
+    published: Condvar,
+    versions: Arc<VersionStore>,
+    snapshots: Mutex<Snapshots>,
+    // Oldest first, from the last one at or before the horizon. Lock order: snapshots, then
+    // history.
+    history: Mutex<VecDeque<CommitPoint>>,
+    retention: Option<Duration>,
+    ssi: SsiTracker,
+}
+
+impl TransactionManager {
+    pub fn new(lock_manager: Arc<LockManager>) -> Self {
+        Self::with_last_commit_ts(lock_manager, 0)
+    }
+
+    // For heaps written before a restart: they hold versions stamped up to `last_commit_ts`,
+    // and new snapshots must see them.
+    pub fn with_last_commit_ts(lock_manager: Arc<LockManager>, last_commit_ts: Timestamp) -> Self {
+        TransactionManager {
+            lock_manager,
+            next_txn_id: AtomicU64::new(0),
+            last_commit_ts: AtomicU64::new(last_commit_ts),
+            commit_latch: Mutex::new(last_commit_ts),
+            publishing: Mutex::new(Publishing::default()),
+            published: Condvar::new(),
+            versions: Arc::new(VersionStore::default()),
+            snapshots: Mutex::new(Snapshots { horizon: last_commit_ts, ..Default::default() }),
+            history: Mutex::new(VecDeque::from([CommitPoint {
+                ts: last_commit_ts,
+                time_micros: platform::clock().now_micros(),
+                lsn: INVALID_LSN,
+            }])),
+            retention: None,
+            ssi: SsiTracker::default(),
+        }
+    }
+
+    // Keeps the versions of the last `retention` of commits for time-travel queries. The
+    // history starts now, with what the log holds up to `start_lsn`.
+    pub fn with_history(mut self, retention: Option<Duration>, start_lsn: Lsn) -> Self {
+        self.retention = retention;
+        self.history.get_mut().unwrap()[0].lsn = start_lsn;
+        self
+    }
+
+    pub fn lock_manager(&self) -> &Arc<LockManager> {
+        &self.lock_manager
+    }
+
+    pub fn last_commit_ts(&self) -> Timestamp {
+        self.last_commit_ts.load(Ordering::SeqCst)
+    }
+
+    pub fn begin(&self, isolation: IsolationLevel) -> Arc<Transaction> {
+        let id = self.next_txn_id.fetch_add(1, Ordering::Relaxed);
+        // Taking the snapshot under the same lock `watermark` reads it with means vacuum
+        // never sees a watermark past a snapshot that is about to be registered.
+        let mut snapshots = self.snapshots.lock().unwrap();
+        let read_ts = self.last_commit_ts();
+        *snapshots.running.entry(read_ts).or_default() += 1;
+        if isolation == IsolationLevel::Serializable {
+            self.ssi.register(id, read_ts);
+        }
+        Arc::new(Transaction::new(id, isolation, read_ts, self.versions.clone()))
+    }
+
+    // Begins a transaction reading the database as it was at `as_of`, which cannot write.
+    // Fails if vacuum may have removed versions it needs.
+    pub fn begin_as_of(&self, as_of: AsOf) -> CrabDbResult<Arc<Transaction>> {
+        let id = self.next_txn_id.fetch_add(1, Ordering::Relaxed);
+        let mut snapshots = self.snapshots.lock().unwrap();
+        let read_ts = self.snapshot_of(&snapshots, as_of)?;
+        *snapshots.running.entry(read_ts).or_default() += 1;
+        Ok(Arc::new(Transaction::new(id, IsolationLevel::SnapshotIsolation, read_ts, self.versions.clone()).historical()))
+    }
+
+    // Keeps vacuum from removing the versions the current snapshot needs until the pin is
+    // dropped.
+    pub fn pin_snapshot(self: &Arc<Self>) -> SnapshotPin {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        let ts = self.last_commit_ts();
+        *snapshots.pinned.entry(ts).or_default() += 1;
+        SnapshotPin { txn_manager: self.clone(), ts }
+    }
+
+    // `pin_snapshot` for the snapshot of `as_of`, if its versions are still kept.
+    pub fn pin_snapshot_as_of(self: &Arc<Self>, as_of: AsOf) -> CrabDbResult<SnapshotPin> {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        let ts = self.snapshot_of(&snapshots, as_of)?;
+        *snapshots.pinned.entry(ts).or_default() += 1;
+        Ok(SnapshotPin { txn_manager: self.clone(), ts })
+    }
+
+    fn unpin(&self, ts: Timestamp) {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        release(&mut snapshots.pinned, ts);
+    }
+
+    // The snapshot that reads the database as of `as_of`: the last commit by then, or any
+    // later one that wrote nothing.
+    fn snapshot_of(&self, snapshots: &Snapshots, as_of: AsOf) -> CrabDbResult<Timestamp> {
+        let history = self.history.lock().unwrap();
+        let after = match as_of {
+            AsOf::Timestamp(micros) => history.partition_point(|point| point.time_micros <= micros),
+            AsOf::Lsn(lsn) => history.partition_point(|point| point.lsn <= lsn),
+            AsOf::Commit(ts) => history.partition_point(|point| point.ts <= ts),
+        };
+        // Read under the history lock, so every commit it covers that wrote is in there.
+        let last_commit_ts = self.last_commit_ts();
+        let read_ts = match (as_of, history.get(after)) {
+            _ if after == 0 => None,
+            (AsOf::Commit(ts), _) => Some(ts).filter(|&ts| ts <= last_commit_ts),
+            (_, Some(next)) => Some(next.ts - 1),
+            (_, None) => Some(last_commit_ts),
+        };
+        match read_ts {
+            Some(read_ts) if read_ts >= snapshots.horizon => Ok(read_ts),
+            _ => Err(CrabDBError::InvalidInput(format!(
+                "The database as of {as_of:?} is no longer kept; the oldest state kept is as of commit {}", snapshots.horizon
+            ))),
+        }
+    }
+
+    // The oldest snapshot still in use, pinned or retained: versions that were replaced at
+    // or before it can no longer be seen by anyone.
+    pub fn watermark(&self) -> Timestamp {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        let mut history = self.history.lock().unwrap();
+        let retained = match self.retention {
+            Some(retention) => {
+                let cutoff = platform::clock().now_micros().saturating_sub(retention.as_micros() as u64);
+                match history.partition_point(|point| point.time_micros <= cutoff) {
+                    0 => history[0].ts,
+                    after => history.get(after).map_or_else(|| self.last_commit_ts(), |next| next.ts - 1),
+                }
+            },
+            None => self.last_commit_ts(),
+        };
+        let oldest = [snapshots.running.keys().next(), snapshots.pinned.keys().next()].into_iter().flatten()
+            .fold(retained, |oldest, &ts| oldest.min(ts));
+        snapshots.horizon = snapshots.horizon.max(oldest);
+        while history.len() > 1 && history[1].ts <= snapshots.horizon {
+            history.pop_front();
+        }
+        snapshots.horizon
+    }
+
+    // Transactions begun and not yet committed or aborted.
+    pub fn active_transactions(&self) -> usize {
+        self.snapshots.lock().unwrap().running.values().sum()
+    }
+
+    // Runs `f` if no transaction is running, and keeps any from beginning until it returns;
+    // None, without running it, if one is. `f` must not call back into the manager.
+    pub(crate) fn while_idle<R>(&self, f: impl FnOnce() -> R) -> Option<R> {
+        let snapshots = self.snapshots.lock().unwrap();
+        if !snapshots.running.is_empty() {
+            return None;
+        }
+        let result = f();
+        drop(snapshots);
+        Some(result)
+    }
+
+    fn retire(&self, txn: &Transaction) {
+        if !txn.finish() {
+            return;
+        }
+        let mut snapshots = self.snapshots.lock().unwrap();
+        release(&mut snapshots.running, txn.read_ts());
+    }
+
+    // Logs a fuzzy checkpoint of `bpm` to its log, with the newest commit timestamp handed
+    // out for a log that starts at it to carry; every Commit record logged before it has a
+    // timestamp no newer. Returns its LSN and that of the first record recovery from it
+    // reads; see `LogManager::append_checkpoint`.
+    pub(crate) fn log_checkpoint(&self, bpm: &BufferPoolManager) -> CrabDbResult<(Lsn, Lsn)> {
+        let Some(log_manager) = bpm.log_manager() else {
+            return Err(CrabDBError::InvalidInput("A checkpoint needs a log manager".to_string()));
+        };
+        let begin_lsn = log_manager.next_lsn();
+        let dirty_pages = bpm.dirty_page_table();
+        let last_ts = self.commit_latch.lock().unwrap();
+        log_manager.append_checkpoint(*last_ts, begin_lsn, dirty_pages)
+    }
+
+    pub(crate) fn versions(&self) -> &VersionStore {
+        &self.versions
+    }
+
+    pub fn commit(&self, txn: &Transaction) -> CrabDbResult<()> {
+        self.commit_with(txn, |_| Ok(()))
+    }
+
+    // Commits, calling `before_publish` with the commit timestamp once the transaction is
+    // sure to commit but before anyone can see its writes. Commits are serialized around
+    // it, so what it does happens in commit order, and what it logs as the transaction's
+    // comes before its Commit record. If it fails, the transaction aborts. The pages of the
+    // buffer pools the transaction wrote to that were changed without log records are
+    // logged whole first, so its index entries are redone with it.
+    pub(crate) fn commit_with(&self, txn: &Transaction, before_publish: impl FnOnce(Timestamp) -> CrabDbResult<()>) -> CrabDbResult<()> {
+        match txn.state() {
+            TransactionState::Growing => {},
+            TransactionState::Aborted => return Err(CrabDBError::TxnAborted(txn.id())),
+            state => return Err(CrabDBError::InvalidInput(format!("Transaction {} cannot commit: it is {state}", txn.id()))),
+        }
+        self.check_online()?;
+        let written = txn.written();
+        let (commit_ts, commit) = {
+            let mut last_ts = self.commit_latch.lock().unwrap();
+            let commit_ts = *last_ts + 1;
+            let mut bpms: Vec<&Arc<BufferPoolManager>> = Vec::new();
+            for (_, bpm) in &written {
+                if !bpms.iter().any(|seen| Arc::ptr_eq(seen, bpm)) {
+                    bpms.push(bpm);
+                }
+            }
+            let checked = self.ssi.check_commit(txn.id(), commit_ts)
+                .and_then(|_| bpms.iter().try_for_each(|bpm| txn.log_unlogged_pages(bpm)))
+                .and_then(|_| before_publish(commit_ts));
+            if let Err(e) = checked {
+                drop(last_ts);
+                let _ = self.abort(txn);
+                return Err(e);
+            }
+            let stamped = written.iter().try_for_each(|(rid, bpm)| {
+                bpm.write_page_logged(
+                    rid.page_id(),
+                    |data| TablePage::new(data).set_tuple_ts(rid.slot_id(), commit_ts),
+                    |log_manager, _, before, after| {
+                        let body = LogRecordBody::update(rid.page_id(), before, after);
+                        body.map(|body| txn.log(log_manager, |_| body)).transpose()
+                    },
+                ).map(|_| ())
+            }).and_then(|_| txn.log_end(LogRecordBody::Commit { commit_ts }));
+            match stamped {
+                Ok(commit) => {
+                    *last_ts = commit_ts;
+                    (commit_ts, commit)
+                },
+                // Rolled back with the latch held, stamps included, so that no later commit
+                // takes the timestamp while versions carry it.
+                Err(e) => {
+                    if let Err(abort_error) = self.abort(txn) {
+                        return Err(self.go_offline(format!("transaction {} could not be rolled back: {abort_error}", txn.id())));
+                    }
+                    return Err(e);
+                },
+            }
+        };
+        // Flushed with the commit latch let go, so commits arriving meanwhile join the flush.
+        if let Some((log_manager, lsn)) = &commit {
+            if let Err(e) = log_manager.flush(*lsn) {
+                let e = self.go_offline(e.to_string());
+                self.retire(txn);
+                self.lock_manager.release_all(txn);
+                return Err(e);
+            }
+        }
+        let durable = DurableCommit {
+            wrote: !written.is_empty(),
+            lsn: commit.map(|(_, lsn)| lsn),
+            time_micros: platform::clock().now_micros(),
+        };
+        let published = self.publish(commit_ts, durable);
+        if published.is_ok() {
+            txn.set_state(TransactionState::Committed);
+        }
+        self.retire(txn);
+        self.ssi.prune(self.watermark());
+        self.lock_manager.release_all(txn);
+        published
+    }
+
+    // Publishes the durable commit at `commit_ts`, along with any after it that only waited
+    // for it, and waits until every commit before it is published too.
+    fn publish(&self, commit_ts: Timestamp, commit: DurableCommit) -> CrabDbResult<()> {
+        let mut publishing = self.publishing.lock().unwrap();
+        publishing.durable.insert(commit_ts, commit);
+        loop {
+            let next_ts = self.last_commit_ts() + 1;
+            if let Some(next) = publishing.durable.remove(&next_ts) {
+                let mut history = self.history.lock().unwrap();
+                if next.wrote {
+                    let lsn = next.lsn.unwrap_or_else(|| history.back().map_or(INVALID_LSN, |point| point.lsn));
+                    history.push_back(CommitPoint { ts: next_ts, time_micros: next.time_micros, lsn });
+                }
+                self.last_commit_ts.store(next_ts, Ordering::SeqCst);
+                self.published.notify_all();
+                continue;
+            }
+            if self.last_commit_ts() >= commit_ts {
+                return Ok(());
+            }
+            if let Some(reason) = &publishing.offline {
+                return Err(CrabDBError::Offline(reason.clone()));
+            }
+            publishing = self.published.wait(publishing).unwrap();
+        }
+    }
+
+    // Stops taking writes and commits, and wakes the commits waiting to be published, which
+    // never will be if they come after the one that failed.
+    fn go_offline(&self, reason: String) -> CrabDBError {
+        let mut publishing = self.publishing.lock().unwrap();
+        trace_event!(tracing::Level::ERROR, %reason, "database going offline");
+        let reason = publishing.offline.get_or_insert(reason).clone();
+        self.published.notify_all();
+        CrabDBError::Offline(reason)
+    }
+
+    fn check_online(&self) -> CrabDbResult<()> {
+        match &self.publishing.lock().unwrap().offline {
+            Some(reason) => Err(CrabDBError::Offline(reason.clone())),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.publishing.lock().unwrap().offline.is_some()
+    }
+
+    // Rolls back the transaction's writes. Also finishes a transaction that was already
+    // aborted by a deadlock or a write conflict.
+    pub fn abort(&self, txn: &Transaction) -> CrabDbResult<()> {
+        if txn.state() == TransactionState::Committed {
+            return Err(CrabDBError::InvalidInput(format!("Transaction {} cannot abort: it is committed", txn.id())));
+        }
+        txn.set_state(TransactionState::Aborted);
+        txn.undo_all()?;
+        txn.log_end(LogRecordBody::Abort)?;
+        self.ssi.remove(txn.id());
+        self.retire(txn);
+        self.lock_manager.release_all(txn);
+        Ok(())
+    }
+
+    pub fn insert(&self, txn: &Transaction, heap: &TableHeap, tuple: &Tuple) -> CrabDbResult<Rid> {
+        txn.check_growing()?;
+        check_writable(txn)?;
+        self.check_online()?;
+        if let Some(oid) = heap.owner() {
+            self.lock_manager.lock_table(txn, LockMode::IntentionExclusive, oid)?;
+        }
+        self.insert_version(txn, heap, tuple)
+    }
+
+    fn insert_version(&self, txn: &Transaction, heap: &TableHeap, tuple: &Tuple) -> CrabDbResult<Rid> {
+        let (rid, lsn) = heap.insert_tuple_with_ts(tuple, txn.temp_ts(), Some(&|log_manager, rid, before, after| {
+            txn.log(log_manager, |_| LogRecordBody::tuple_write(rid, before, after, None))
+        }))?;
+        self.record_write(txn, heap, rid, PriorVersion::Inserted, lsn);
+        // Nobody else can see the new row yet, so this never waits.
+        self.lock_row(txn, heap, rid)?;
+        Ok(rid)
+    }
+
+    fn lock_row(&self, txn: &Transaction, heap: &TableHeap, rid: Rid) -> CrabDbResult<()> {
+        match heap.owner() {
+            Some(oid) => self.lock_manager.lock_row(txn, LockMode::Exclusive, oid, rid),
+            None => Ok(()),
+        }
+    }
+
+    // The version of the row at `rid` that `txn` sees, if any.
+    pub fn get(&self, txn: &Transaction, heap: &TableHeap, rid: Rid) -> CrabDbResult<Option<Tuple>> {
+        txn.check_growing()?;
+        self.ssi.record_read(txn.id(), SsiKey::Row(rid));
+        heap.read_page(rid.page_id(), |page| {
+            let visible = page.get_tuple_with_meta(rid.slot_id()).ok()
+                .and_then(|(meta, data)| self.versions.visible_version(txn, rid, meta, data));
+            // Every version of a slot is stored the way the slot is, inline or in overflow pages.
+            match visible {
+                Some(tuple) => Ok(Some(heap.resolve_overflow(page.is_overflow(rid.slot_id())?, tuple)?)),
+                None => Ok(None),
+            }
+        })
+    }
+
+    // The version of the row at `rid` that `txn` saw before it wrote to it, if any.
+    pub(crate) fn get_committed(&self, txn: &Transaction, heap: &TableHeap, rid: Rid) -> CrabDbResult<Option<Tuple>> {
+        heap.read_page(rid.page_id(), |page| {
+            let committed = page.get_tuple_with_meta(rid.slot_id()).ok()
+                .and_then(|(meta, data)| self.versions.committed_version(txn, rid, meta, data));
+            match committed {
+                Some(tuple) => Ok(Some(heap.resolve_overflow(page.is_overflow(rid.slot_id())?, tuple)?)),
+                None => Ok(None),
+            }
+        })
+    }
+
+    // Replaces the row at `rid` and returns where the new version lives: normally in place,
+    // but a version that no longer fits in the page moves to a new rid.
+    pub fn update(&self, txn: &Transaction, heap: &TableHeap, rid: Rid, tuple: &Tuple) -> CrabDbResult<Rid> {
+        if self.write(txn, heap, rid, Some(tuple))? {
+            return Ok(rid);
+        }
+        self.insert_version(txn, heap, tuple)
+    }
+
+    pub fn delete(&self, txn: &Transaction, heap: &TableHeap, rid: Rid) -> CrabDbResult<()> {
+        self.write(txn, heap, rid, None).map(|_| ())
+    }
+
+    // Writes a new version of the row at `rid`, or deletes it when `tuple` is None. Returns
+    // false if the new version did not fit in the page, in which case the row was deleted
+    // here instead.
+    fn write(&self, txn: &Transaction, heap: &TableHeap, rid: Rid, tuple: Option<&Tuple>) -> CrabDbResult<bool> {
+        txn.check_growing()?;
+        check_writable(txn)?;
+        self.check_online()?;
+        self.lock_row(txn, heap, rid)?;
+        let slot_id = rid.slot_id();
+        let ((done, prior, _), lsn) = heap.bpm().write_page_logged(rid.page_id(), |data| {
+            let mut page = TablePage::new(data);
+            let (meta, data) = page.get_tuple_with_meta(slot_id)?;
+            let own = meta.ts == txn.temp_ts();
+            if !own && !is_visible(txn, meta.ts) {
+                txn.set_state(TransactionState::Aborted);
+                return Err(CrabDBError::Conflict(format!(
+                    "Transaction {} conflicts with a concurrent write to row {rid}", txn.id()
+                )));
+            }
+            if meta.is_deleted {
+                return Err(CrabDBError::NotFound(format!("Row {rid} does not exist")));
+            }
+            let old = Tuple::new(data.to_vec());
+            let logged_prior = (meta.ts, data.to_vec());
+            let in_place = match tuple {
+                Some(tuple) => page.update_tuple(slot_id, tuple.data())?,
+                None => false,
+            };
+            if !in_place {
+                page.mark_delete(slot_id)?;
+            }
+            page.set_tuple_ts(slot_id, txn.temp_ts())?;
+            let prior = if own {
+                PriorVersion::Own(old)
+            } else {
+                self.versions.push(rid, UndoVersion { ts: meta.ts, tuple: old });
+                PriorVersion::Committed
+            };
+            Ok((in_place || tuple.is_none(), prior, logged_prior))
+        }, |log_manager, (_, _, logged_prior), before, after| {
+            txn.log(log_manager, |_| LogRecordBody::tuple_write(rid, before, after, Some(logged_prior.clone()))).map(Some)
+        })?;
+        self.record_write(txn, heap, rid, prior, lsn);
+        Ok(done)
+    }
+
+    fn record_write(&self, txn: &Transaction, heap: &TableHeap, rid: Rid, prior: PriorVersion, lsn: Lsn) {
+        txn.add_write(WriteRecord { rid, bpm: heap.bpm().clone(), heap: heap.first_page_id(), prior, lsn });
+        self.ssi.record_write(txn.id(), SsiKey::Row(rid));
+        self.ssi.record_write(txn.id(), SsiKey::Heap(heap.first_page_id()));
+    }
+
+    // Scans the rows of `heap` visible to `txn`.
+    pub fn scan<'a>(&'a self, txn: &'a Transaction, heap: &'a TableHeap) -> SnapshotIterator<'a> {
+        self.ssi.record_read(txn.id(), SsiKey::Heap(heap.first_page_id()));
+        SnapshotIterator::new(heap, &self.versions, txn)
+    }
+}
+
+fn release(snapshots: &mut BTreeMap<Timestamp, usize>, ts: Timestamp) {
+    if let Some(count) = snapshots.get_mut(&ts) {
+        *count -= 1;
+        if *count == 0 {
+            snapshots.remove(&ts);
+        }
+    }
+}
+
+fn check_writable(txn: &Transaction) -> CrabDbResult<()> {
+    if txn.is_historical() {
+        return Err(CrabDBError::InvalidInput(format!("Transaction {} reads the past and cannot write", txn.id())));
+    }
+    Ok(())
+}
+
+// Keeps the versions of a snapshot from vacuum until dropped (see
+// `TransactionManager::pin_snapshot`).
+pub struct SnapshotPin {
+    txn_manager: Arc<TransactionManager>,
+    ts: Timestamp,
+}
+
+impl SnapshotPin {
+    pub fn timestamp(&self) -> Timestamp {
+        self.ts
+    }
+
+    // Where to read the database as of to see the pinned snapshot.
+    pub fn as_of(&self) -> AsOf {
+        AsOf::Commit(self.ts)
+    }
+}
+
+impl Drop for SnapshotPin {
+    fn drop(&mut self) {
+        self.txn_manager.unpin(self.ts);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::concurrency::lock_manager::LockManager;
+    use crate::concurrency::transaction::{IsolationLevel, Transaction};
+    use crate::storage::disk::disk_scheduler::DiskScheduler;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use crate::storage::table::table_heap::TableHeap;
+    use crate::storage::table::tuple::Tuple;
+    use crate::platform;
+    use crate::types::{CrabDBError, ErrorCode};
+    use crate::wal::log_manager::{LogManager, LogManagerOptions};
+    use super::{AsOf, TransactionManager};
+
+    fn setup() -> (TransactionManager, TableHeap) {
+        let bpm = Arc::new(BufferPoolManager::new(8, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(8, 2))));
+        (TransactionManager::new(Arc::new(LockManager::new())), TableHeap::new(bpm).unwrap())
+    }
+
+    fn tuple(s: &str) -> Tuple {
+        Tuple::new(s.as_bytes().to_vec())
+    }
+
+    fn scan(txn_manager: &TransactionManager, txn: &Transaction, heap: &TableHeap) -> Vec<Tuple> {
+        txn_manager.scan(txn, heap).map(|entry| entry.unwrap().1).collect()
+    }
+
+    #[test]
+    pub fn test_mvcc_snapshot_reads() {
+        let (txn_manager, heap) = setup();
+        let writer = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        let a = txn_manager.insert(&writer, &heap, &tuple("a1")).unwrap();
+        let b = txn_manager.insert(&writer, &heap, &tuple("b1")).unwrap();
+        let before = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        assert_eq!(vec![tuple("a1"), tuple("b1")], scan(&txn_manager, &writer, &heap));
+        assert!(scan(&txn_manager, &before, &heap).is_empty());
+        txn_manager.commit(&writer).unwrap();
+
+        let old = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        let writer = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        txn_manager.update(&writer, &heap, a, &tuple("a2 is longer than before")).unwrap();
+        txn_manager.delete(&writer, &heap, b).unwrap();
+        txn_manager.commit(&writer).unwrap();
+        let new = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        assert_eq!(3, txn_manager.active_transactions());
+
+        assert!(scan(&txn_manager, &before, &heap).is_empty());
+        assert_eq!(vec![tuple("a1"), tuple("b1")], scan(&txn_manager, &old, &heap));
+        assert_eq!(vec![tuple("a2 is longer than before")], scan(&txn_manager, &new, &heap));
+        assert_eq!(Some(tuple("a1")), txn_manager.get(&old, &heap, a).unwrap());
+        assert_eq!(None, txn_manager.get(&new, &heap, b).unwrap());
+    }
+
+    #[test]
+    pub fn test_mvcc_conflicts_and_abort() {
+        let (txn_manager, heap) = setup();
+        let setup_txn = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        let rid = txn_manager.insert(&setup_txn, &heap, &tuple("v1")).unwrap();
+        txn_manager.commit(&setup_txn).unwrap();
+
+        let first = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        let second = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        txn_manager.update(&first, &heap, rid, &tuple("v2")).unwrap();
+        let error = txn_manager.delete(&second, &heap, rid).unwrap_err();
+        assert!(matches!(error, CrabDBError::Conflict(_)));
+        assert!(error.is_retryable() && error.is_transient() && error.code().is_client_error());
+        assert!(matches!(txn_manager.commit(&second), Err(CrabDBError::TxnAborted(_))));
+        txn_manager.abort(&second).unwrap();
+
+        // rolling back restores the committed version and drops the insert
+        let inserted = txn_manager.insert(&first, &heap, &tuple("new")).unwrap();
+        txn_manager.abort(&first).unwrap();
+        let reader = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        assert_eq!(vec![tuple("v1")], scan(&txn_manager, &reader, &heap));
+        assert_eq!(None, txn_manager.get(&reader, &heap, inserted).unwrap());
+
+        // a commit after the snapshot was taken also conflicts
+        let stale = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        let fresh = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        txn_manager.update(&fresh, &heap, rid, &tuple("v3")).unwrap();
+        txn_manager.commit(&fresh).unwrap();
+        assert!(matches!(txn_manager.update(&stale, &heap, rid, &tuple("v4")).unwrap_err(), CrabDBError::Conflict(_)));
+    }
+
+    #[test]
+    pub fn test_serializable_prevents_write_skew() {
+        let (txn_manager, heap) = setup();
+        let setup_txn = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        let x = txn_manager.insert(&setup_txn, &heap, &tuple("x")).unwrap();
+        let y = txn_manager.insert(&setup_txn, &heap, &tuple("y")).unwrap();
+        txn_manager.commit(&setup_txn).unwrap();
+
+        // each transaction reads both rows and writes the one the other did not
+        let run = |isolation| {
+            let first = txn_manager.begin(isolation);
+            let second = txn_manager.begin(isolation);
+            for txn in [&first, &second] {
+                txn_manager.get(txn, &heap, x).unwrap();
+                txn_manager.get(txn, &heap, y).unwrap();
+            }
+            txn_manager.update(&first, &heap, x, &tuple("x by first")).unwrap();
+            txn_manager.update(&second, &heap, y, &tuple("y by second")).unwrap();
+            (txn_manager.commit(&first), txn_manager.commit(&second))
+        };
+
+        let (first, second) = run(IsolationLevel::SnapshotIsolation);
+        assert!(first.is_ok() && second.is_ok());
+
+        let (first, second) = run(IsolationLevel::Serializable);
+        let error = first.unwrap_err();
+        assert!(matches!(error, CrabDBError::Conflict(_)));
+        assert!(error.to_string().ends_with("could not be serialized with concurrent transactions"));
+        assert!(second.is_ok());
+        let reader = txn_manager.begin(IsolationLevel::Serializable);
+        assert_eq!(vec![tuple("x by first"), tuple("y by second")], scan(&txn_manager, &reader, &heap));
+        txn_manager.commit(&reader).unwrap();
+    }
+
+    #[test]
+    pub fn test_savepoints_roll_back_part_of_a_transaction() {
+        let (txn_manager, heap) = setup();
+        let setup_txn = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        let committed = txn_manager.insert(&setup_txn, &heap, &tuple("committed")).unwrap();
+        txn_manager.commit(&setup_txn).unwrap();
+
+        let txn = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        let own = txn_manager.insert(&txn, &heap, &tuple("own v1")).unwrap();
+        txn.savepoint("s1").unwrap();
+        txn_manager.update(&txn, &heap, own, &tuple("own v2")).unwrap();
+        txn_manager.update(&txn, &heap, committed, &tuple("changed")).unwrap();
+        txn.savepoint("s2").unwrap();
+        txn_manager.insert(&txn, &heap, &tuple("late")).unwrap();
+        txn_manager.delete(&txn, &heap, own).unwrap();
+        assert_eq!(vec![tuple("changed"), tuple("late")], scan(&txn_manager, &txn, &heap));
+
+        txn.rollback_to("s2").unwrap();
+        assert_eq!(vec![tuple("changed"), tuple("own v2")], scan(&txn_manager, &txn, &heap));
+        txn.rollback_to("s1").unwrap();
+        assert_eq!(vec![tuple("committed"), tuple("own v1")], scan(&txn_manager, &txn, &heap));
+        assert_eq!("Savepoint s2 does not exist", txn.rollback_to("s2").unwrap_err().to_string());
+
+        // the savepoint survives being rolled back to
+        txn_manager.update(&txn, &heap, committed, &tuple("again")).unwrap();
+        txn.rollback_to("s1").unwrap();
+        txn_manager.commit(&txn).unwrap();
+        let reader = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        assert_eq!(vec![tuple("committed"), tuple("own v1")], scan(&txn_manager, &reader, &heap));
+    }
+
+    #[test]
+    pub fn test_mvcc_reads_the_past_it_keeps() {
+        let (txn_manager, heap) = setup();
+        let txn_manager = Arc::new(txn_manager.with_history(Some(Duration::from_secs(3600)), 0));
+        let writer = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        let rid = txn_manager.insert(&writer, &heap, &tuple("v1")).unwrap();
+        txn_manager.commit(&writer).unwrap();
+        let v1 = txn_manager.last_commit_ts();
+        thread::sleep(Duration::from_millis(2));
+        let then = platform::clock().now_micros();
+        thread::sleep(Duration::from_millis(2));
+        let writer = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        txn_manager.update(&writer, &heap, rid, &tuple("v2")).unwrap();
+        txn_manager.commit(&writer).unwrap();
+
+        // the retention keeps the watermark back, so the past stays readable
+        assert!(txn_manager.watermark() < v1);
+        for as_of in [AsOf::Timestamp(then), AsOf::Commit(v1)] {
+            let past = txn_manager.begin_as_of(as_of).unwrap();
+            assert!(past.is_historical());
+            assert_eq!(vec![tuple("v1")], scan(&txn_manager, &past, &heap));
+            let error = txn_manager.update(&past, &heap, rid, &tuple("v3")).unwrap_err();
+            assert!(matches!(error, CrabDBError::InvalidInput(_)));
+            txn_manager.abort(&past).unwrap();
+        }
+        let now = txn_manager.begin_as_of(AsOf::Timestamp(platform::clock().now_micros())).unwrap();
+        assert_eq!(vec![tuple("v2")], scan(&txn_manager, &now, &heap));
+        txn_manager.abort(&now).unwrap();
+        assert!(txn_manager.begin_as_of(AsOf::Commit(txn_manager.last_commit_ts() + 1)).is_err());
+
+        // without it only pins keep the past, and what they stop keeping is gone for good
+        let (txn_manager, heap) = setup();
+        let txn_manager = Arc::new(txn_manager);
+        let writer = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        let rid = txn_manager.insert(&writer, &heap, &tuple("v1")).unwrap();
+        txn_manager.commit(&writer).unwrap();
+        let pin = txn_manager.pin_snapshot();
+        let writer = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        txn_manager.update(&writer, &heap, rid, &tuple("v2")).unwrap();
+        txn_manager.commit(&writer).unwrap();
+        assert_eq!(pin.timestamp(), txn_manager.watermark());
+        let past = txn_manager.begin_as_of(pin.as_of()).unwrap();
+        assert_eq!(vec![tuple("v1")], scan(&txn_manager, &past, &heap));
+        txn_manager.abort(&past).unwrap();
+        let as_of = pin.as_of();
+        drop(pin);
+        assert_eq!(txn_manager.last_commit_ts(), txn_manager.watermark());
+        assert!(matches!(txn_manager.begin_as_of(as_of), Err(CrabDBError::InvalidInput(_))));
+        assert!(txn_manager.pin_snapshot_as_of(as_of).is_err());
+    }
+
+    #[test]
+    pub fn test_commit_is_published_only_once_durable() {
+        let logged = |buffer_size| {
+            let log_manager = Arc::new(LogManager::in_memory(LogManagerOptions { buffer_size, ..Default::default() }).unwrap());
+            let bpm = Arc::new(BufferPoolManager::with_log_manager(
+                8,
+                Arc::new(DiskScheduler::new(Arc::new(MemoryDiskManager::new()), 1)),
+                Arc::new(LRUKReplacer::new(8, 2)),
+                Some(log_manager.clone()),
+            ));
+            (TransactionManager::new(Arc::new(LockManager::new())), TableHeap::new(bpm).unwrap(), log_manager)
+        };
+
+        let (txn_manager, heap, log_manager) = logged(64 * 1024);
+        let writer = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        txn_manager.insert(&writer, &heap, &tuple("kept")).unwrap();
+        txn_manager.commit(&writer).unwrap();
+        assert_eq!(1, txn_manager.last_commit_ts());
+
+        // the Commit record never reaches the log, so the commit is never seen
+        let writer = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        txn_manager.insert(&writer, &heap, &tuple("lost")).unwrap();
+        log_manager.fail_writes(true);
+        let error = txn_manager.commit(&writer).unwrap_err();
+        assert!(matches!(error, CrabDBError::Offline(_)));
+        assert_eq!(ErrorCode::Io, error.code());
+        assert!(!error.is_transient());
+        assert!(txn_manager.is_offline());
+        assert_eq!(1, txn_manager.last_commit_ts());
+        let reader = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        assert_eq!(vec![tuple("kept")], scan(&txn_manager, &reader, &heap));
+        assert!(matches!(txn_manager.insert(&reader, &heap, &tuple("more")), Err(CrabDBError::Offline(_))));
+        assert!(matches!(txn_manager.commit(&reader), Err(CrabDBError::Offline(_))));
+
+        // with no room in the log buffer, stamping fails, and so does rolling it back
+        let (txn_manager, heap, log_manager) = logged(1);
+        let writer = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        txn_manager.insert(&writer, &heap, &tuple("stamped")).unwrap();
+        log_manager.fail_writes(true);
+        assert!(matches!(txn_manager.commit(&writer), Err(CrabDBError::Offline(_))));
+        assert_eq!(0, txn_manager.last_commit_ts());
+        let reader = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        assert!(scan(&txn_manager, &reader, &heap).is_empty());
+    }
+}
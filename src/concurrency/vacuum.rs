@@ -0,0 +1,538 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::ops::Bound;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::catalog::system_catalog::Catalog;
+use crate::catalog::table_info::{IndexInfo, TableInfo};
+use crate::db::materialized_view;
+use crate::execution::executor::ExecutorContext;
+use crate::kv::kv_store::KvStore;
+use crate::platform;
+use crate::storage::common::{PageId, INVALID_PAGE_ID, PAGE_SIZE};
+use crate::storage::page::table_page::SLOT_SIZE;
+use crate::storage::table::table_heap::TableHeap;
+use crate::storage::table::tuple::{Rid, Timestamp, Tuple};
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::mvcc::VersionStore;
+use super::transaction::{IsolationLevel, TXN_TS_FLAG};
+use super::transaction_manager::TransactionManager;
+
+// Pages whose holes add up to at least this much are compacted.
+const COMPACT_THRESHOLD: usize = PAGE_SIZE / 4;
+
+// Stale index entries are removed this many at a time.
+const PRUNE_BATCH: usize = 256;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VacuumStats {
+    // Old versions dropped from undo chains.
+    pub versions_removed: usize,
+    // Slots of deleted tuples handed back to their page.
+    pub tuples_reclaimed: usize,
+    pub pages_compacted: usize,
+    // Keys of key-value stores deleted because they expired.
+    pub expired_keys_removed: usize,
+    // Rows of tables with a TTL deleted because they expired.
+    pub expired_rows_removed: usize,
+    // Tuples compaction moved off sparsely filled pages, and the pages that freed.
+    pub tuples_moved: usize,
+    pub pages_freed: usize,
+    // Sparse pages compaction left for another time, as transactions were running.
+    pub pages_skipped: usize,
+    // Entries of full-text indexes that no row needed any more.
+    pub index_entries_removed: usize,
+}
+
+impl VacuumStats {
+    fn add(&mut self, other: VacuumStats) {
+        self.versions_removed += other.versions_removed;
+        self.tuples_reclaimed += other.tuples_reclaimed;
+        self.pages_compacted += other.pages_compacted;
+        self.expired_keys_removed += other.expired_keys_removed;
+        self.expired_rows_removed += other.expired_rows_removed;
+        self.tuples_moved += other.tuples_moved;
+        self.pages_freed += other.pages_freed;
+        self.pages_skipped += other.pages_skipped;
+        self.index_entries_removed += other.index_entries_removed;
+    }
+}
+
+// Reclaims what MVCC leaves behind once no snapshot can see it: replaced versions in undo
+// chains, and deleted tuples whose delete every running transaction already sees. Pages
+// left with large holes are compacted in place, so rids stay valid. Full-text indexes lose
+// the entries of rows that no longer need them (see `prune_index`). Expired keys of
+// key-value stores and expired rows of tables with a TTL are deleted first, so they go the
+// same way. `run` vacuums every table and key-value store in the catalog once;
+// `with_interval` also runs it periodically on a background thread. `run_full`, and
+// background runs given a fill factor, go on to compact tables; see `compact_table`.
+pub struct Vacuum {
+    shared: Arc<VacuumTarget>,
+    worker: Option<(Sender<()>, JoinHandle<()>)>,
+}
+
+struct VacuumTarget {
+    catalog: Arc<Catalog>,
+    txn_manager: Arc<TransactionManager>,
+    // Fill factor below which background runs compact pages.
+    compact_below: Option<f64>,
+}
+
+impl Vacuum {
+    pub fn new(catalog: Arc<Catalog>, txn_manager: Arc<TransactionManager>) -> Self {
+        Vacuum { shared: Arc::new(VacuumTarget { catalog, txn_manager, compact_below: None }), worker: None }
+    }
+
+    pub fn with_interval(
+        catalog: Arc<Catalog>,
+        txn_manager: Arc<TransactionManager>,
+        interval: Duration,
+        compact_below: Option<f64>,
+    ) -> Self {
+        let shared = Arc::new(VacuumTarget { catalog, txn_manager, compact_below });
+        let (stop, stopped) = mpsc::channel::<()>();
+        let worker = {
+            let shared = shared.clone();
+            std::thread::Builder::new()
+                .name("crab-db-vacuum".into())
+                .spawn(move || {
+                    while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                        // A failed pass is retried on the next tick.
+                        let _ = shared.run(shared.compact_below);
+                    }
+                })
+                .expect("failed to spawn vacuum worker")
+        };
+        Vacuum { shared, worker: Some((stop, worker)) }
+    }
+
+    pub fn run(&self) -> CrabDbResult<VacuumStats> {
+        self.shared.run(None)
+    }
+
+    // Like `run`, then compacts every table's pages filled less than `fill_factor`.
+    pub fn run_full(&self, fill_factor: f64) -> CrabDbResult<VacuumStats> {
+        self.shared.run(Some(fill_factor))
+    }
+}
+
+impl VacuumTarget {
+    fn run(&self, compact_below: Option<f64>) -> CrabDbResult<VacuumStats> {
+        let mut stats = VacuumStats::default();
+        let tables: Vec<_> = self.catalog.table_names().iter()
+            .filter_map(|name| self.catalog.table(name))
+            .chain(self.catalog.temp_tables())
+            .collect();
+        for table in tables {
+            let indexes = self.catalog.indexes_of(&table);
+            stats.expired_rows_removed += remove_expired_rows(&self.catalog, &self.txn_manager, &table)?;
+            stats.add(vacuum_heap(&self.txn_manager, table.heap())?);
+            for index in &indexes {
+                if !index.kind().is_unique() {
+                    stats.add(prune_index(&self.txn_manager, &table, index)?);
+                }
+            }
+            if let Some(fill_factor) = compact_below {
+                stats.add(compact_table(&self.txn_manager, &table, &indexes, fill_factor)?);
+            }
+        }
+        for name in self.catalog.kv_store_names() {
+            if let Some(kv_store) = self.catalog.kv_store(&name) {
+                stats.expired_keys_removed += KvStore::new(kv_store.clone(), self.txn_manager.clone()).remove_expired()?;
+                stats.add(vacuum_heap(&self.txn_manager, kv_store.heap())?);
+            }
+        }
+        Ok(stats)
+    }
+}
+
+impl Drop for Vacuum {
+    fn drop(&mut self) {
+        if let Some((stop, worker)) = self.worker.take() {
+            drop(stop);
+            let _ = worker.join();
+        }
+    }
+}
+
+// Deletes the rows of a table with a TTL that have expired, each in a transaction of its
+// own like the keys of `KvStore::remove_expired`; a row written meanwhile is left to the
+// next run. Their index entries go as those of any deleted row do, and the materialized
+// views reading the table take the delete in before it commits. Returns how many rows were
+// deleted.
+pub fn remove_expired_rows(catalog: &Arc<Catalog>, txn_manager: &Arc<TransactionManager>, table: &TableInfo) -> CrabDbResult<usize> {
+    if table.ttl().is_none() {
+        return Ok(0);
+    }
+    let now = platform::clock().now_micros();
+    let expired = {
+        let txn = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        let expired = txn_manager.scan(&txn, table.heap())
+            .filter_map(|entry| match entry.and_then(|(rid, tuple)| Ok((rid, tuple.values(table.schema())?))) {
+                Ok((rid, row)) => table.is_expired(&row, now).then_some(Ok(rid)),
+                Err(e) => Some(Err(e)),
+            })
+            .collect::<CrabDbResult<Vec<_>>>();
+        txn_manager.commit(&txn)?;
+        expired?
+    };
+    let mut removed = 0;
+    for rid in expired {
+        let txn = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        let remove = || {
+            // The row may have been updated since it was found expired.
+            let still_expired = match txn_manager.get(&txn, table.heap(), rid)? {
+                Some(tuple) => table.is_expired(&tuple.values(table.schema())?, now),
+                None => false,
+            };
+            if still_expired {
+                txn_manager.delete(&txn, table.heap(), rid)?;
+                materialized_view::maintain(&ExecutorContext::new(catalog.clone(), txn_manager.clone(), txn.clone()))?;
+            }
+            Ok(still_expired)
+        };
+        match remove().and_then(|removed| txn_manager.commit(&txn).map(|_| removed)) {
+            Ok(removed_row) => removed += removed_row as usize,
+            Err(e) => {
+                let _ = txn_manager.abort(&txn);
+                if !e.is_retryable() {
+                    return Err(e);
+                }
+            },
+        }
+    }
+    Ok(removed)
+}
+
+// Vacuums a single heap against the transaction manager's current watermark.
+pub fn vacuum_heap(txn_manager: &TransactionManager, heap: &TableHeap) -> CrabDbResult<VacuumStats> {
+    let watermark = txn_manager.watermark();
+    let versions = txn_manager.versions();
+    let mut stats = VacuumStats::default();
+    let mut page_id = heap.first_page_id();
+    while page_id != INVALID_PAGE_ID {
+        // Each page is latched on its own, and before the undo chains, like readers do.
+        let mut stubs = Vec::new();
+        let (next_page_id, free_space) = heap.write_page(page_id, |page| {
+            for slot_id in 0..page.num_slots() {
+                let Ok(meta) = page.tuple_meta(slot_id) else {
+                    continue;
+                };
+                let rid = Rid::new(page_id, slot_id);
+                stats.versions_removed += versions.prune(rid, meta.ts, watermark);
+                let committed = meta.ts & TXN_TS_FLAG == 0;
+                if meta.is_deleted && committed && meta.ts <= watermark && !versions.has_versions(rid) {
+                    stubs.extend(TableHeap::take_overflow_stub(page, slot_id)?);
+                    page.apply_delete(slot_id)?;
+                    stats.tuples_reclaimed += 1;
+                }
+            }
+            if page.reclaimable_space() - page.free_space() >= COMPACT_THRESHOLD {
+                page.compact();
+                stats.pages_compacted += 1;
+            }
+            Ok((page.next_page_id(), page.reclaimable_space()))
+        })?;
+        heap.free_overflow(&stubs)?;
+        heap.record_free_space(page_id, free_space)?;
+        page_id = next_page_id;
+    }
+    Ok(stats)
+}
+
+fn heap_pages(heap: &TableHeap) -> CrabDbResult<HashSet<PageId>> {
+    let mut pages = HashSet::new();
+    let mut page_id = heap.first_page_id();
+    while page_id != INVALID_PAGE_ID {
+        pages.insert(page_id);
+        page_id = heap.read_page(page_id, |page| Ok(page.next_page_id()))?;
+    }
+    Ok(pages)
+}
+
+// The entries a row of `table` needs in an index holding several per row, or None if it may
+// need any it has: it has older versions still, or a version too new for every snapshot to
+// see. A row that is gone needs none, and neither does a slot outside the table's pages.
+fn needed_entries(
+    versions: &VersionStore,
+    table: &TableInfo,
+    index: &IndexInfo,
+    pages: &HashSet<PageId>,
+    watermark: Timestamp,
+    rid: Rid,
+) -> CrabDbResult<Option<HashSet<Vec<u8>>>> {
+    if !pages.contains(&rid.page_id()) {
+        return Ok(Some(HashSet::new()));
+    }
+    if versions.has_versions(rid) {
+        return Ok(None);
+    }
+    let heap = table.heap();
+    heap.read_page(rid.page_id(), |page| {
+        let Ok((meta, data)) = page.get_tuple_with_meta(rid.slot_id()) else {
+            return Ok(Some(HashSet::new()));
+        };
+        if meta.ts & TXN_TS_FLAG != 0 || meta.ts > watermark {
+            return Ok(None);
+        }
+        if meta.is_deleted {
+            return Ok(Some(HashSet::new()));
+        }
+        let tuple = heap.resolve_overflow(page.is_overflow(rid.slot_id())?, Tuple::new(data.to_vec()))?;
+        Ok(Some(index.entries_for_row(&tuple.values(table.schema())?, rid)?.into_iter().collect()))
+    })
+}
+
+// Removes the entries of an index holding several per row, like a full-text index, that no
+// snapshot can need: entries of rows that are gone, and entries the only version of a row no
+// longer has. Writes leave these behind, as they may yet abort. Candidates are found first
+// and then removed a batch at a time while no transaction is running and none can begin,
+// each checked again; batches that find a transaction running are left for another time.
+pub fn prune_index(txn_manager: &TransactionManager, table: &TableInfo, index: &IndexInfo) -> CrabDbResult<VacuumStats> {
+    let watermark = txn_manager.watermark();
+    let versions = txn_manager.versions();
+    let pages = heap_pages(table.heap())?;
+    let mut needed: HashMap<Rid, Option<HashSet<Vec<u8>>>> = HashMap::new();
+    let mut stale = Vec::new();
+    for entry in index.index().range(Bound::Unbounded, Bound::Unbounded)? {
+        let (key, rid) = entry?;
+        let keys = match needed.entry(rid) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(needed_entries(versions, table, index, &pages, watermark, rid)?),
+        };
+        if keys.as_ref().is_some_and(|keys| !keys.contains(&key)) {
+            stale.push((key, rid));
+        }
+    }
+    let mut stats = VacuumStats::default();
+    for batch in stale.chunks(PRUNE_BATCH) {
+        let pruned = txn_manager.while_idle(|| {
+            // The table may have grown onto pages freed since.
+            let pages = heap_pages(table.heap())?;
+            let mut removed = 0;
+            for (key, rid) in batch {
+                let needed = needed_entries(versions, table, index, &pages, watermark, *rid)?;
+                if needed.is_some_and(|keys| !keys.contains(key)) && index.index().remove(key)? {
+                    removed += 1;
+                }
+            }
+            Ok::<_, CrabDBError>(removed)
+        });
+        if let Some(removed) = pruned {
+            stats.index_entries_removed += removed?;
+        }
+    }
+    Ok(stats)
+}
+
+// Removes the entries the rows at `rids` no longer need from those of `indexes` holding
+// several per row, as `prune_index` does, without walking the indexes: the candidates are
+// the entries of the versions the rows' writes replaced, which their undo chains hold until
+// this prunes them too. Run right after the transaction that wrote the rows commits, so the
+// statistics of a full-text index follow its updates and deletes at once. Only runs while
+// no transaction is; otherwise, and for versions a transaction replaced itself, vacuum
+// gets to the entries.
+pub fn prune_rows(txn_manager: &TransactionManager, table: &TableInfo, indexes: &[Arc<IndexInfo>], rids: &[Rid]) -> CrabDbResult<VacuumStats> {
+    let indexes: Vec<_> = indexes.iter().filter(|index| !index.kind().is_unique()).collect();
+    if indexes.is_empty() || rids.is_empty() {
+        return Ok(VacuumStats::default());
+    }
+    let watermark = txn_manager.watermark();
+    let versions = txn_manager.versions();
+    let pruned = txn_manager.while_idle(|| {
+        let pages = heap_pages(table.heap())?;
+        let mut stats = VacuumStats::default();
+        for &rid in rids {
+            let replaced = versions.replaced(rid);
+            if replaced.is_empty() {
+                continue;
+            }
+            if let Some(meta) = pages.contains(&rid.page_id()).then(|| table.heap().tuple_meta(rid)).transpose()? {
+                stats.versions_removed += versions.prune(rid, meta.ts, watermark);
+            }
+            for index in &indexes {
+                let Some(needed) = needed_entries(versions, table, index, &pages, watermark, rid)? else {
+                    continue;
+                };
+                for tuple in &replaced {
+                    for key in index.entries_for_row(&tuple.values(table.schema())?, rid)? {
+                        if !needed.contains(&key) && index.index().remove(&key)? {
+                            stats.index_entries_removed += 1;
+                        }
+                    }
+                }
+            }
+        }
+        Ok::<_, CrabDBError>(stats)
+    });
+    pruned.unwrap_or(Ok(VacuumStats::default()))
+}
+
+// Moves the tuples off the pages of `table` filled less than `fill_factor`, last page
+// first, into pages before them with room, and frees the pages that leaves empty. A moved
+// tuple gets a new rid, so each page is only moved off while no transaction is running and
+// none can begin; one that is gets skipped. Index entries follow their tuples. A page with
+// tuples vacuum has yet to reclaim, or versions it has yet to prune, keeps them.
+pub fn compact_table(
+    txn_manager: &TransactionManager,
+    table: &TableInfo,
+    indexes: &[Arc<IndexInfo>],
+    fill_factor: f64,
+) -> CrabDbResult<VacuumStats> {
+    let heap = table.heap();
+    // Every page of the heap in chain order, with the room it has.
+    let mut pages: Vec<(PageId, usize)> = Vec::new();
+    let mut page_id = heap.first_page_id();
+    while page_id != INVALID_PAGE_ID {
+        let (next_page_id, room) = heap.read_page(page_id, |page| Ok((page.next_page_id(), page.reclaimable_space())))?;
+        pages.push((page_id, room));
+        page_id = next_page_id;
+    }
+    let mut stats = VacuumStats::default();
+    // The first page anchors the heap, so it is never moved off.
+    for source in (1..pages.len()).rev() {
+        let (page_id, room) = pages[source];
+        if (PAGE_SIZE - room) as f64 >= fill_factor * PAGE_SIZE as f64 {
+            continue;
+        }
+        let targets = &mut pages[..source];
+        match txn_manager.while_idle(|| compact_page(txn_manager.versions(), table, indexes, page_id, targets)) {
+            Some(compacted) => {
+                let (moved, freed) = compacted?;
+                stats.tuples_moved += moved;
+                stats.pages_freed += freed as usize;
+            },
+            None => stats.pages_skipped += 1,
+        }
+    }
+    Ok(stats)
+}
+
+// Moves the tuples of `page_id` to the first of `targets` with room for them, and frees the
+// page if that empties it. Returns how many moved and whether the page was freed.
+fn compact_page(
+    versions: &VersionStore,
+    table: &TableInfo,
+    indexes: &[Arc<IndexInfo>],
+    page_id: PageId,
+    targets: &mut [(PageId, usize)],
+) -> CrabDbResult<(usize, bool)> {
+    let heap = table.heap();
+    let tuples = heap.read_page(page_id, |page| {
+        let mut tuples = Vec::new();
+        for slot_id in 0..page.num_slots() {
+            let Ok((meta, data)) = page.get_tuple_with_meta(slot_id) else {
+                continue;
+            };
+            let rid = Rid::new(page_id, slot_id);
+            if meta.is_deleted || meta.ts & TXN_TS_FLAG != 0 || versions.has_versions(rid) {
+                return Ok(None);
+            }
+            tuples.push((rid, data.len() + SLOT_SIZE));
+        }
+        Ok(Some(tuples))
+    })?;
+    let Some(tuples) = tuples else {
+        return Ok((0, false));
+    };
+    let mut moved = 0;
+    for (rid, needed) in tuples {
+        let mut new_rid = None;
+        for (target_page_id, room) in targets.iter_mut().filter(|(_, room)| *room >= needed) {
+            new_rid = heap.move_tuple(rid, *target_page_id)?;
+            // A target that turns out to be too full is not tried for this much again.
+            *room = match new_rid {
+                Some(_) => room.saturating_sub(needed),
+                None => needed - 1,
+            };
+            if new_rid.is_some() {
+                break;
+            }
+        }
+        let Some(new_rid) = new_rid else {
+            return Ok((moved, false));
+        };
+        repoint_indexes(table, indexes, rid, new_rid)?;
+        moved += 1;
+    }
+    Ok((moved, heap.free_page(page_id)?))
+}
+
+// Points the index entries for the tuple now at `to` there, if they pointed at `from`.
+// Entries of indexes that are not unique hold their rid, so they are replaced instead.
+fn repoint_indexes(table: &TableInfo, indexes: &[Arc<IndexInfo>], from: Rid, to: Rid) -> CrabDbResult<()> {
+    if indexes.is_empty() {
+        return Ok(());
+    }
+    let row = table.heap().get_tuple(to)?.values(table.schema())?;
+    for index in indexes {
+        if !index.kind().is_unique() {
+            for key in index.entries_for_row(&row, from)? {
+                index.index().remove(&key)?;
+            }
+            for key in index.entries_for_row(&row, to)? {
+                index.index().insert(&key, to)?;
+            }
+            continue;
+        }
+        let key = index.key_for_row(&row)?;
+        if index.index().get(&key)? == Some(from) {
+            index.index().remove(&key)?;
+            index.index().insert(&key, to)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::concurrency::lock_manager::LockManager;
+    use crate::concurrency::transaction::IsolationLevel;
+    use crate::concurrency::transaction_manager::TransactionManager;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use crate::storage::table::table_heap::TableHeap;
+    use crate::storage::table::tuple::Tuple;
+    use super::{vacuum_heap, VacuumStats};
+
+    #[test]
+    pub fn test_vacuum_respects_active_snapshots() {
+        let bpm = Arc::new(BufferPoolManager::new(8, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(8, 2))));
+        let heap = TableHeap::new(bpm).unwrap();
+        let txn_manager = TransactionManager::new(Arc::new(LockManager::new()));
+
+        let writer = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        let rids: Vec<_> = (0..40)
+            .map(|i| txn_manager.insert(&writer, &heap, &Tuple::new(vec![i; 100])).unwrap())
+            .collect();
+        txn_manager.commit(&writer).unwrap();
+
+        let reader = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        let writer = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        for rid in &rids[..30] {
+            txn_manager.delete(&writer, &heap, *rid).unwrap();
+        }
+        txn_manager.update(&writer, &heap, rids[35], &Tuple::new(vec![0; 10])).unwrap();
+        txn_manager.commit(&writer).unwrap();
+
+        // the reader still needs every version it started with
+        assert_eq!(VacuumStats::default(), vacuum_heap(&txn_manager, &heap).unwrap());
+        assert_eq!(40, txn_manager.scan(&reader, &heap).count());
+        txn_manager.commit(&reader).unwrap();
+
+        let stats = vacuum_heap(&txn_manager, &heap).unwrap();
+        assert_eq!(31, stats.versions_removed);
+        assert_eq!(30, stats.tuples_reclaimed);
+        assert!(stats.pages_compacted >= 1);
+        let reader = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        assert_eq!(10, txn_manager.scan(&reader, &heap).count());
+        assert_eq!(Some(Tuple::new(vec![0; 10])), txn_manager.get(&reader, &heap, rids[35]).unwrap());
+    }
+}
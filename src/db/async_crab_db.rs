@@ -0,0 +1,252 @@
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use crate::concurrency::transaction::{Transaction, TransactionState};
+use crate::storage::disk::disk_scheduler::{disk_promise, DiskCompletion};
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::crab_db::{CrabDb, DEFAULT_SESSION};
+use super::options::CrabDbOptions;
+use super::row_iterator::RowIterator;
+
+// Threads `AsyncCrabDb::open` runs statements on.
+pub const DEFAULT_STATEMENT_WORKERS: usize = 4;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// A CrabDb for async callers. Statements and commits run on worker threads of its own and
+// hand their results back through the completions the disk scheduler uses, so awaiting one
+// parks a task rather than a thread, under any runtime, and callers need no
+// `spawn_blocking` of their own.
+pub struct AsyncCrabDb {
+    db: Arc<CrabDb>,
+    sender: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl AsyncCrabDb {
+    pub fn new(db: CrabDb, num_workers: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..num_workers.max(1)).map(|worker| {
+            let receiver = receiver.clone();
+            std::thread::Builder::new()
+                .name(format!("crab-db-statement-{worker}"))
+                .spawn(move || Self::run_worker(receiver))
+                .expect("failed to spawn statement worker")
+        }).collect();
+        AsyncCrabDb { db: Arc::new(db), sender: Some(sender), workers }
+    }
+
+    pub fn open(path: impl AsRef<Path>, options: CrabDbOptions) -> CrabDbResult<Self> {
+        Ok(Self::new(CrabDb::open(path, options)?, DEFAULT_STATEMENT_WORKERS))
+    }
+
+    // The database underneath, for what has no async variant.
+    pub fn db(&self) -> &Arc<CrabDb> {
+        &self.db
+    }
+
+    // `CrabDb::execute`. The statements start running at once, before they are awaited.
+    pub fn execute(&self, sql: &str) -> DiskCompletion<u64> {
+        let sql = sql.to_string();
+        self.spawn(move |db| db.execute(&sql))
+    }
+
+    // `CrabDb::query`, started at once like `execute`. The rows are all read before the
+    // completion fires, since a cursor cannot leave the thread it runs on.
+    pub fn query(&self, sql: &str) -> DiskCompletion<RowIterator> {
+        let sql = sql.to_string();
+        self.spawn(move |db| Ok(db.run(&sql)?.rows))
+    }
+
+    // Reads `key` from the key-value store `store` in a transaction of its own.
+    pub fn fetch(&self, store: &str, key: &[u8]) -> DiskCompletion<Option<Vec<u8>>> {
+        let (store, key) = (store.to_string(), key.to_vec());
+        self.spawn(move |db| {
+            let txn = db.begin_transaction();
+            let value = db.kv_store(&store)?.get(txn.txn(), &key)?;
+            txn.commit()?;
+            Ok(value)
+        })
+    }
+
+    pub fn begin_transaction(&self) -> AsyncDbTransaction<'_> {
+        AsyncDbTransaction { db: self, txn: self.db.begin() }
+    }
+
+    // `CrabDb::close`, once the statements already started are done. Fails if the database
+    // is still shared through `db`.
+    pub async fn close(self) -> CrabDbResult<()> {
+        let (promise, completion) = disk_promise();
+        std::thread::Builder::new()
+            .name("crab-db-close".to_string())
+            .spawn(move || {
+                let db = self.db.clone();
+                drop(self);
+                promise.complete(match Arc::try_unwrap(db) {
+                    Ok(db) => db.close(),
+                    Err(_) => Err(CrabDBError::InvalidInput("The database is still in use and cannot be closed".to_string())),
+                });
+            })
+            .map_err(|e| CrabDBError::new(format!("Failed to close the database: {e}")))?;
+        completion.await
+    }
+
+    // Queues `job` for a worker. A job that cannot be queued drops its promise, which
+    // completes it with an error.
+    fn spawn<T: Send + 'static>(&self, job: impl FnOnce(&CrabDb) -> CrabDbResult<T> + Send + 'static) -> DiskCompletion<T> {
+        let (promise, completion) = disk_promise();
+        let db = self.db.clone();
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(Box::new(move || promise.complete(job(&db))));
+        }
+        completion
+    }
+
+    fn run_worker(receiver: Arc<Mutex<Receiver<Job>>>) {
+        loop {
+            let job = match receiver.lock().unwrap().recv() {
+                Ok(job) => job,
+                Err(_) => return,
+            };
+            job();
+        }
+    }
+}
+
+impl Drop for AsyncCrabDb {
+    fn drop(&mut self) {
+        self.sender.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+// `DbTransaction` for async callers. Its statements run one at a time. Dropping it without
+// committing rolls it back.
+pub struct AsyncDbTransaction<'a> {
+    db: &'a AsyncCrabDb,
+    txn: Arc<Transaction>,
+}
+
+impl AsyncDbTransaction<'_> {
+    pub fn txn(&self) -> &Arc<Transaction> {
+        &self.txn
+    }
+
+    // `DbTransaction::execute`.
+    pub async fn execute(&mut self, sql: &str) -> CrabDbResult<u64> {
+        let (txn, sql) = (self.txn.clone(), sql.to_string());
+        self.db.spawn(move |db| Ok(db.run_in_transaction(DEFAULT_SESSION, &txn, &sql)?.count)).await
+    }
+
+    // `DbTransaction::query`.
+    pub async fn query(&mut self, sql: &str) -> CrabDbResult<RowIterator> {
+        let (txn, sql) = (self.txn.clone(), sql.to_string());
+        self.db.spawn(move |db| Ok(db.run_in_transaction(DEFAULT_SESSION, &txn, &sql)?.rows)).await
+    }
+
+    // Reads `key` from the key-value store `store`.
+    pub async fn fetch(&mut self, store: &str, key: &[u8]) -> CrabDbResult<Option<Vec<u8>>> {
+        let (txn, store, key) = (self.txn.clone(), store.to_string(), key.to_vec());
+        self.db.spawn(move |db| db.kv_store(&store)?.get(&txn, &key)).await
+    }
+
+    // Writes `value` under `key` in the key-value store `store`.
+    pub async fn put(&mut self, store: &str, key: &[u8], value: &[u8]) -> CrabDbResult<()> {
+        let (txn, store, key, value) = (self.txn.clone(), store.to_string(), key.to_vec(), value.to_vec());
+        self.db.spawn(move |db| db.kv_store(&store)?.put(&txn, &key, &value)).await
+    }
+
+    pub async fn commit(self) -> CrabDbResult<()> {
+        let txn = self.txn.clone();
+        self.db.spawn(move |db| db.commit(&txn)).await
+    }
+
+    pub async fn rollback(self) -> CrabDbResult<()> {
+        let txn = self.txn.clone();
+        self.db.spawn(move |db| db.txn_manager().abort(&txn)).await
+    }
+}
+
+impl Drop for AsyncDbTransaction<'_> {
+    fn drop(&mut self) {
+        if self.txn.state() != TransactionState::Committed {
+            let _ = self.db.db.txn_manager().abort(&self.txn);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    use tempfile::TempDir;
+
+    use crate::db::options::CrabDbOptions;
+    use crate::types::value::Value;
+    use super::AsyncCrabDb;
+
+    #[test]
+    pub fn test_async_crab_db_runs_statements_and_transactions() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("crabs.db");
+        block_on(async {
+            let db = AsyncCrabDb::open(&path, CrabDbOptions::default()).unwrap();
+            db.execute("CREATE TABLE crabs (id BIGINT NOT NULL, name VARCHAR)").await.unwrap();
+            assert_eq!(2, db.execute("INSERT INTO crabs VALUES (1, 'ferris'), (2, 'sebastian')").await.unwrap());
+
+            // statements of different callers are in flight together
+            let (first, second) = (db.query("SELECT name FROM crabs WHERE id = 1"), db.query("SELECT name FROM crabs WHERE id = 2"));
+            assert_eq!(vec![vec![Value::Varchar("ferris".into())]], first.await.unwrap().collect::<Vec<_>>());
+            assert_eq!(vec![vec![Value::Varchar("sebastian".into())]], second.await.unwrap().collect::<Vec<_>>());
+
+            let mut txn = db.begin_transaction();
+            txn.execute("DELETE FROM crabs WHERE id = 2").await.unwrap();
+            txn.put("shells", b"ferris", b"red").await.unwrap();
+            assert_eq!(Some(b"red".to_vec()), txn.fetch("shells", b"ferris").await.unwrap());
+            assert_eq!(None, db.fetch("shells", b"ferris").await.unwrap());
+            txn.commit().await.unwrap();
+            assert_eq!(Some(b"red".to_vec()), db.fetch("shells", b"ferris").await.unwrap());
+
+            // a transaction dropped unfinished is rolled back
+            let mut txn = db.begin_transaction();
+            txn.execute("DELETE FROM crabs").await.unwrap();
+            drop(txn);
+            assert_eq!(1, db.execute("SELECT * FROM crabs").await.unwrap());
+
+            let shared = db.db().clone();
+            assert!(db.close().await.is_err());
+            drop(shared);
+        });
+
+        let db = AsyncCrabDb::open(&path, CrabDbOptions::default()).unwrap();
+        assert_eq!(1, block_on(db.execute("SELECT * FROM crabs")).unwrap());
+        assert!(block_on(db.close()).is_ok());
+    }
+
+    // Minimal executor so the async API can be tested without pulling in a runtime.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        struct ThreadWaker(std::thread::Thread);
+        impl Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        let mut context = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+            std::thread::park();
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::catalog::table_info::TableInfo;
+use crate::platform::Stopwatch;
+use crate::storage::table::tuple::{Rid, Tuple};
+use crate::types::value::Value;
+use crate::types::{CrabDBError, CrabDbResult};
+use crate::wal::common::{Lsn, TxnId};
+use crate::wal::log_manager::{LogHold, LogManager};
+use crate::wal::log_record::{LogRecordBody, RowChangeKind};
+
+// How long `recv` waits for the log at a time before looking again.
+const RECV_WAIT: Duration = Duration::from_secs(60);
+
+// A committed change to a row of the table a stream follows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowChange {
+    // Where the change is in the log; a stream resumed after it starts with the next one.
+    pub lsn: Lsn,
+    pub txn_id: TxnId,
+    pub kind: RowChangeKind,
+    pub rid: Rid,
+    // The row as the change left it; None for a delete.
+    pub row: Option<Vec<Value>>,
+}
+
+// The committed changes to one table, decoded from the row changes commits log (see
+// `CrabDbOptions::capture_changes`), in commit order. Reading waits on log flushes rather
+// than polling. Changes are handed out once their transaction's commit record is in the
+// log, so an aborted or unfinished transaction never shows up. The log the stream has yet to
+// read is kept from being recycled while it lives.
+pub struct ChangeStream {
+    log_manager: Arc<LogManager>,
+    hold: LogHold,
+    table: Arc<TableInfo>,
+    // LSN of the last change handed out; changes up to it are skipped.
+    position: Lsn,
+    // How far the log file has been read, in bytes and records.
+    offset: u64,
+    scanned_lsn: Lsn,
+    uncommitted: HashMap<TxnId, Vec<RowChange>>,
+    committed: VecDeque<RowChange>,
+}
+
+impl ChangeStream {
+    pub(crate) fn new(log_manager: Arc<LogManager>, table: Arc<TableInfo>, after_lsn: Lsn) -> Self {
+        ChangeStream {
+            hold: log_manager.hold(after_lsn + 1),
+            log_manager,
+            table,
+            position: after_lsn,
+            offset: 0,
+            scanned_lsn: 0,
+            uncommitted: HashMap::new(),
+            committed: VecDeque::new(),
+        }
+    }
+
+    pub fn table(&self) -> &Arc<TableInfo> {
+        &self.table
+    }
+
+    // LSN of the last change handed out, or the one the stream started after: what to pass
+    // `CrabDb::subscribe_after` to pick up where this stream left off.
+    pub fn position(&self) -> Lsn {
+        self.position
+    }
+
+    // Blocks until the next change is committed.
+    pub fn recv(&mut self) -> CrabDbResult<RowChange> {
+        loop {
+            if let Some(change) = self.recv_timeout(RECV_WAIT)? {
+                return Ok(change);
+            }
+        }
+    }
+
+    // The next change, waiting at most `timeout` for it to be committed.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> CrabDbResult<Option<RowChange>> {
+        let started = Stopwatch::start();
+        loop {
+            if let Some(change) = self.committed.pop_front() {
+                self.position = change.lsn;
+                return Ok(Some(change));
+            }
+            if self.log_manager.flushed_lsn() > self.scanned_lsn {
+                self.read_log()?;
+                continue;
+            }
+            let elapsed = started.elapsed();
+            if elapsed >= timeout {
+                return Ok(None);
+            }
+            self.log_manager.wait_for_flush(self.scanned_lsn, timeout - elapsed);
+        }
+    }
+
+    fn read_log(&mut self) -> CrabDbResult<()> {
+        // Whatever was flushed before the file is read is in the file.
+        let flushed_lsn = self.log_manager.flushed_lsn();
+        let (records, offset) = self.log_manager.records_from(self.offset)?;
+        let read_lsn = self.scanned_lsn.max(self.position);
+        if let Some(first) = records.first().filter(|first| first.lsn > read_lsn + 1) {
+            return Err(CrabDBError::InvalidInput(format!(
+                "The log from LSN {} to {} has been recycled; subscribe again to read on from now", read_lsn + 1, first.lsn - 1,
+            )));
+        }
+        self.offset = offset;
+        self.scanned_lsn = self.scanned_lsn.max(flushed_lsn);
+        for record in records {
+            self.scanned_lsn = self.scanned_lsn.max(record.lsn);
+            match record.body {
+                LogRecordBody::RowChange { table, rid, kind, after } if table == self.table.oid() && record.lsn > self.position => {
+                    let row = match kind {
+                        RowChangeKind::Delete => None,
+                        RowChangeKind::Insert | RowChangeKind::Update => Some(Tuple::new(after).values(self.table.schema())?),
+                    };
+                    let change = RowChange { lsn: record.lsn, txn_id: record.txn_id, kind, rid, row };
+                    self.uncommitted.entry(record.txn_id).or_default().push(change);
+                },
+                LogRecordBody::Commit { .. } => {
+                    if let Some(changes) = self.uncommitted.remove(&record.txn_id) {
+                        self.committed.extend(changes);
+                    }
+                },
+                // Recovery aborts a transaction that crashed before its commit was logged.
+                LogRecordBody::Abort => {
+                    self.uncommitted.remove(&record.txn_id);
+                },
+                _ => (),
+            }
+        }
+        self.hold.advance(self.scanned_lsn + 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,1868 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use sqlparser::ast::Statement;
+
+use crate::buffer_pool::background_writer::BackgroundWriter;
+use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+use crate::buffer_pool::eviction::replacer::ReplacerDebugState;
+use crate::catalog::system_catalog::{Catalog, SessionId};
+use crate::catalog::table_info::TableInfo;
+use crate::catalog::trigger::Trigger;
+use crate::catalog::udf::{AggregateUdf, ScalarUdf};
+use crate::concurrency::lock_manager::{LockManager, LockWaits};
+use crate::concurrency::transaction::{Transaction, TransactionState, TXN_TS_FLAG};
+use crate::concurrency::transaction_manager::{AsOf, SnapshotPin, TransactionManager};
+use crate::concurrency::vacuum::{self, Vacuum, VacuumStats};
+use crate::execution::copy::{self, CopyFormat};
+use crate::execution::executor::{execute, ExecutorContext};
+use crate::index::table_index::IndexKind;
+use crate::kv::kv_store::{KvStore, KV_TREE_KEY_SIZE};
+use crate::metrics::crab_db_metrics::CrabDbMetricsSnapshot;
+use crate::platform;
+use crate::sql::binder::{parse, parse_as_of, Binder};
+use crate::sql::physical_plan::PhysicalPlan;
+use crate::sql::planner::{Plan, Planner};
+use crate::storage::common::PageId;
+use crate::storage::disk::disk_manager::DiskManager;
+use crate::storage::disk::disk_scheduler::DiskScheduler;
+use crate::storage::disk::file_disk_manager::{FileDiskManager, FileDiskManagerOptions};
+use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+use crate::storage::disk::temp_disk_manager::TempDiskManager;
+use crate::storage::table::tuple::Rid;
+use crate::types::schema::{Column, Schema};
+use crate::types::value::{DataType, Value};
+use crate::types::{CrabDBError, CrabDbResult};
+use crate::wal::archive::{RecoveryTarget, WalArchive, WalArchiver, WalSegment};
+use crate::wal::checkpointer::{self, Checkpointer};
+use crate::wal::common::{Lsn, TxnId, INVALID_LSN, SYSTEM_TXN_ID};
+use crate::wal::log_manager::{LogHold, LogManager};
+use crate::wal::log_record::{LogRecordBody, RowChangeKind};
+use crate::wal::recovery_manager::RecoveryManager;
+
+use super::change_stream::ChangeStream;
+use super::cursor::Cursor;
+use super::engine::EngineShare;
+use super::materialized_view;
+use super::backup::{restore_backup, write_backup, BackupStats};
+use super::options::CrabDbOptions;
+use super::replication::ReplicationServer;
+use super::row_iterator::RowIterator;
+use super::session::Session;
+use super::transaction::DbTransaction;
+
+// Savepoint taken before every statement of a transaction, so that one failing leaves the
+// transaction as it was before the statement.
+const STATEMENT_SAVEPOINT: &str = "crab_db_statement";
+
+// The session statements run on the database itself belong to, which ends when it closes.
+pub(crate) const DEFAULT_SESSION: SessionId = 0;
+
+// What running one statement produced.
+pub(crate) struct StatementOutput {
+    pub(crate) rows: RowIterator,
+    // Rows changed by a statement that writes, otherwise rows returned.
+    pub(crate) count: u64,
+}
+
+impl Default for StatementOutput {
+    fn default() -> Self {
+        StatementOutput { rows: RowIterator::new(Schema::new(Vec::new()), Vec::new()), count: 0 }
+    }
+}
+
+// An embedded database stored in one file, with its write-ahead log next to it in a file of
+// the same name plus ".wal" and the pages to warm the buffer pool up with in one ending in
+// ".warmup". Statements run outside a transaction commit on their own.
+// Commits wait for their log records to reach disk, so committed rows survive a restart.
+// Temporary tables are kept in a file ending in ".temp", which every open starts afresh.
+pub struct CrabDb {
+    bpm: Arc<BufferPoolManager>,
+    log_manager: Arc<LogManager>,
+    catalog: Arc<Catalog>,
+    txn_manager: Arc<TransactionManager>,
+    vacuum: Option<Vacuum>,
+    background_writer: Option<BackgroundWriter>,
+    checkpointer: Option<Checkpointer>,
+    wal_archive: Option<Arc<WalArchive>>,
+    wal_archiver: Option<WalArchiver>,
+    // With `CrabDbOptions::capture_changes`, keeps the whole log, for change streams to
+    // resume from any position they got to, across restarts too.
+    _change_log_hold: Option<LogHold>,
+    // Whether this is a replica's, whose log is a copy of its primary's and never gets
+    // records of its own, checkpoints included.
+    replica: bool,
+    // Where the hot pages are recorded on close, if `CrabDbOptions::warmup` is on.
+    warmup_path: Option<PathBuf>,
+    // The file of the temporary storage, removed on close; None if it is in memory.
+    temp_path: Option<PathBuf>,
+    next_session: AtomicU64,
+    options: CrabDbOptions,
+}
+
+fn wal_path(path: &Path, wal_dir: Option<&Path>) -> PathBuf {
+    let path = match (wal_dir, path.file_name()) {
+        (Some(wal_dir), Some(file_name)) => wal_dir.join(file_name),
+        _ => path.to_path_buf(),
+    };
+    let mut wal_path = OsString::from(path.as_os_str());
+    wal_path.push(".wal");
+    wal_path.into()
+}
+
+fn warmup_path(path: &Path) -> PathBuf {
+    let mut warmup_path = OsString::from(path.as_os_str());
+    warmup_path.push(".warmup");
+    warmup_path.into()
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut temp_path = OsString::from(path.as_os_str());
+    temp_path.push(".temp");
+    temp_path.into()
+}
+
+// Storage for temporary tables: the file at `temp_path`, emptied of whatever the last open
+// left in it, or memory.
+fn temp_storage(temp_path: Option<&Path>, options: &CrabDbOptions) -> CrabDbResult<Arc<BufferPoolManager>> {
+    let disk_manager: Arc<dyn DiskManager> = match temp_path {
+        Some(temp_path) => {
+            match std::fs::remove_file(temp_path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(CrabDBError::io(format!("Failed to remove temporary file {}", temp_path.display()), e));
+                },
+                _ => {},
+            }
+            let temp_options = FileDiskManagerOptions { encryption: options.encryption.clone(), ..Default::default() };
+            Arc::new(FileDiskManager::with_options(temp_path, temp_options)?)
+        },
+        None => Arc::new(MemoryDiskManager::new()),
+    };
+    Ok(Arc::new(BufferPoolManager::new(
+        options.temp_pool_size,
+        Arc::new(TempDiskManager::new(disk_manager)),
+        options.replacer_of_size(options.temp_pool_size),
+    )))
+}
+
+// The page ids are stored as little-endian u64s, hottest first. A file that is missing or
+// cannot be read lists no pages: it is only a hint.
+fn read_warmup_file(warmup_path: &Path) -> Vec<PageId> {
+    let Ok(bytes) = std::fs::read(warmup_path) else {
+        return Vec::new();
+    };
+    bytes.chunks_exact(8).map(|chunk| PageId::from_le_bytes(chunk.try_into().unwrap())).collect()
+}
+
+// Written beside and renamed over the old file, so a crash midway leaves the old one.
+fn write_warmup_file(warmup_path: &Path, page_ids: &[PageId]) -> CrabDbResult<()> {
+    let bytes: Vec<u8> = page_ids.iter().flat_map(|page_id| page_id.to_le_bytes()).collect();
+    let mut tmp_path = OsString::from(warmup_path.as_os_str());
+    tmp_path.push(".tmp");
+    std::fs::write(&tmp_path, bytes)
+        .and_then(|_| std::fs::rename(&tmp_path, warmup_path))
+        .map_err(|e| CrabDBError::io(format!("Failed to write warmup file {}", warmup_path.display()), e))
+}
+
+impl CrabDb {
+    // Opens the database at `path`, creating it if it does not exist, and recovers it from
+    // its write-ahead log.
+    pub fn open(path: impl AsRef<Path>, options: CrabDbOptions) -> CrabDbResult<Self> {
+        options.validate()?;
+        let disk_manager = Arc::new(FileDiskManager::with_options(path.as_ref(), options.disk_manager_options())?);
+        Self::open_file(path.as_ref(), disk_manager, true, options, None)
+    }
+
+    // Like `open`, with pages read from and written to `disk_manager` instead of the file at
+    // `path`. The write-ahead log still goes next to `path`; temporary tables are kept in
+    // memory, since only `disk_manager` knows how pages should be stored.
+    pub fn open_with_disk_manager(path: impl AsRef<Path>, disk_manager: Arc<dyn DiskManager>, options: CrabDbOptions) -> CrabDbResult<Self> {
+        Self::open_file(path.as_ref(), disk_manager, false, options, None)
+    }
+
+    // Opens the database at `path` for an engine, in a share of its buffer pool and disk
+    // workers; `options.pool_size` is its quota of the pool's frames.
+    pub(crate) fn open_in_engine(path: &Path, options: CrabDbOptions, share: EngineShare<'_>) -> CrabDbResult<Self> {
+        options.validate()?;
+        let disk_manager = Arc::new(FileDiskManager::with_options(path, options.disk_manager_options())?);
+        Self::open_file(path, disk_manager, true, options, Some(share))
+    }
+
+    fn open_file(
+        path: &Path,
+        disk_manager: Arc<dyn DiskManager>,
+        temp_file: bool,
+        options: CrabDbOptions,
+        share: Option<EngineShare<'_>>,
+    ) -> CrabDbResult<Self> {
+        options.validate()?;
+        if let Some(wal_dir) = &options.wal_dir {
+            std::fs::create_dir_all(wal_dir)
+                .map_err(|e| CrabDBError::io(format!("Failed to create WAL directory {}", wal_dir.display()), e))?;
+        }
+        let log_manager = LogManager::with_options(wal_path(path, options.wal_dir.as_deref()), options.log_manager_options())?;
+        let warmup_path = options.warmup.then(|| warmup_path(path));
+        let temp_path = temp_file.then(|| temp_path(path));
+        Self::open_with(disk_manager, log_manager, warmup_path, temp_path, options, share)
+    }
+
+    // Opens a database that lives in memory and is gone once dropped: its pages are held by a
+    // `MemoryDiskManager` and its log in a buffer. It touches no files, which makes it the way
+    // to run crab-db where there are none, such as in a browser under wasm. There is nothing
+    // to warm up from, and no archive to keep its log in.
+    pub fn open_in_memory(options: CrabDbOptions) -> CrabDbResult<Self> {
+        options.validate()?;
+        if options.wal_archive_dir.is_some() {
+            return Err(CrabDBError::InvalidInput("An in-memory database cannot archive its log".to_string()));
+        }
+        let log_manager = LogManager::in_memory(options.log_manager_options())?;
+        Self::open_with(Arc::new(MemoryDiskManager::new()), log_manager, None, None, options, None)
+    }
+
+    fn open_with(
+        disk_manager: Arc<dyn DiskManager>,
+        log_manager: LogManager,
+        warmup_path: Option<PathBuf>,
+        temp_path: Option<PathBuf>,
+        options: CrabDbOptions,
+        share: Option<EngineShare<'_>>,
+    ) -> CrabDbResult<Self> {
+        let log_manager = Arc::new(log_manager);
+        let bpm = match share {
+            Some(share) => BufferPoolManager::in_shared_pool(
+                share.pool,
+                options.pool_size,
+                share.priority,
+                Arc::new(share.disk_scheduler.share(disk_manager)),
+                options.replacer_of_size(share.pool.capacity()),
+                Some(log_manager.clone()),
+            ),
+            None => Arc::new(BufferPoolManager::with_log_manager(
+                options.pool_size,
+                Arc::new(DiskScheduler::new(disk_manager, options.disk_workers)),
+                options.replacer(),
+                Some(log_manager.clone()),
+            )),
+        };
+        bpm.set_slow_io_threshold(options.slow_io_threshold);
+        let last_commit_ts = RecoveryManager::new(bpm.clone(), log_manager.clone()).recover()?.last_commit_ts();
+
+        // Archived and shipped log has to carry every change to restore from or replay, not
+        // just the ones recovery needs.
+        if options.wal_archive_dir.is_some() || options.replication {
+            bpm.start_full_page_writes()?;
+        }
+        let wal_archive = match &options.wal_archive_dir {
+            Some(dir) => Some(Arc::new(WalArchive::open(dir)?.with_encryption(options.encryption.clone()))),
+            None => None,
+        };
+
+        let catalog = Arc::new(Catalog::open(bpm.clone())?.with_temp_storage(temp_storage(temp_path.as_deref(), &options)?));
+        catalog.check_page_size(options.page_size)?;
+        bpm.flush_all_pages()?;
+        if let Some(warmup_path) = &warmup_path {
+            bpm.warm_up(&read_warmup_file(warmup_path))?;
+        }
+        let txn_manager = Arc::new(
+            TransactionManager::with_last_commit_ts(Arc::new(options.lock_manager()), last_commit_ts)
+                .with_history(options.history_retention, log_manager.next_lsn().saturating_sub(1)),
+        );
+        let vacuum = options.vacuum_interval
+            .map(|interval| Vacuum::with_interval(catalog.clone(), txn_manager.clone(), interval, options.compact_below));
+        let background_writer = options.flush_interval
+            .map(|interval| BackgroundWriter::new(bpm.clone(), interval, options.flush_dirty_ratio));
+        let change_log_hold = options.capture_changes.then(|| log_manager.hold(INVALID_LSN));
+        // Every page is clean by now, so this recycles all of the log recovery read.
+        checkpointer::checkpoint(&bpm, &txn_manager, wal_archive.as_deref())?;
+        let checkpointer = options.checkpoint_interval
+            .map(|interval| Checkpointer::new(bpm.clone(), txn_manager.clone(), wal_archive.clone(), interval));
+        let wal_archiver = match (&wal_archive, options.wal_archive_interval) {
+            (Some(archive), Some(interval)) => Some(WalArchiver::new(archive.clone(), log_manager.clone(), interval)),
+            _ => None,
+        };
+        Ok(CrabDb {
+            bpm,
+            log_manager,
+            catalog,
+            txn_manager,
+            vacuum,
+            background_writer,
+            checkpointer,
+            wal_archive,
+            wal_archiver,
+            _change_log_hold: change_log_hold,
+            replica: false,
+            warmup_path,
+            temp_path,
+            next_session: AtomicU64::new(DEFAULT_SESSION + 1),
+            options,
+        })
+    }
+
+    // Creates the database at `path`, which must not exist yet, from a backup `backup_to`
+    // took, and opens it.
+    pub fn restore_from(backup_path: impl AsRef<Path>, path: impl AsRef<Path>, options: CrabDbOptions) -> CrabDbResult<Self> {
+        Self::restore(backup_path.as_ref(), path.as_ref(), options, None)
+    }
+
+    // Like `restore_from`, then replays the log archived in `archive_dir` since the backup
+    // up to `target`. Archive the restored database somewhere else: its log goes on from
+    // the target, not from where the old archive ends.
+    pub fn restore_to(
+        backup_path: impl AsRef<Path>,
+        archive_dir: impl AsRef<Path>,
+        path: impl AsRef<Path>,
+        options: CrabDbOptions,
+        target: RecoveryTarget,
+    ) -> CrabDbResult<Self> {
+        let archive = WalArchive::open(archive_dir.as_ref())?.with_encryption(options.encryption.clone());
+        Self::restore(backup_path.as_ref(), path.as_ref(), options, Some((&archive, target)))
+    }
+
+    fn restore(backup_path: &Path, path: &Path, options: CrabDbOptions, archive: Option<(&WalArchive, RecoveryTarget)>) -> CrabDbResult<Self> {
+        Self::restore_files(backup_path, path, &options, archive)?;
+        Self::open(path, options)
+    }
+
+    // Writes the database file and log of a backup to `path`, without opening them.
+    pub(crate) fn restore_files(
+        backup_path: &Path,
+        path: &Path,
+        options: &CrabDbOptions,
+        archive: Option<(&WalArchive, RecoveryTarget)>,
+    ) -> CrabDbResult<()> {
+        options.validate()?;
+        if let Some(wal_dir) = &options.wal_dir {
+            std::fs::create_dir_all(wal_dir)
+                .map_err(|e| CrabDBError::io(format!("Failed to create WAL directory {}", wal_dir.display()), e))?;
+        }
+        let wal_path = wal_path(path, options.wal_dir.as_deref());
+        for existing in [path, wal_path.as_path()] {
+            if existing.exists() {
+                return Err(CrabDBError::InvalidInput(format!("Cannot restore over {}: it already exists", existing.display())));
+            }
+        }
+        if let Err(e) = restore_backup(backup_path, path, &wal_path, options, archive) {
+            let _ = std::fs::remove_file(path);
+            let _ = std::fs::remove_file(&wal_path);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    // Opens the replica at `path` for `Replica`: its log is a copy of its primary's, which
+    // is redone on top of the pages but never rolled back or added to, and nothing runs in
+    // the background. Its snapshots see every version its pages hold, since the primary
+    // stamped them; it must never commit. The rows transactions still open on the primary
+    // wrote are left in `open_writes`, for `RecoveryManager::replay` to carry on with.
+    pub(crate) fn open_replica(path: &Path, options: CrabDbOptions, open_writes: &mut HashMap<TxnId, Vec<Rid>>) -> CrabDbResult<Self> {
+        options.validate()?;
+        let disk_manager = Arc::new(FileDiskManager::with_options(path, options.disk_manager_options())?);
+        if disk_manager.num_pages() == 0 {
+            return Err(CrabDBError::InvalidInput(format!(
+                "{} is empty: a replica starts from a backup of its primary", path.display(),
+            )));
+        }
+        let disk_scheduler = Arc::new(DiskScheduler::new(disk_manager, options.disk_workers));
+        let log_manager = Arc::new(LogManager::with_options(wal_path(path, options.wal_dir.as_deref()), options.log_manager_options())?);
+        let bpm = Arc::new(BufferPoolManager::with_log_manager(
+            options.pool_size, disk_scheduler, options.replacer(), Some(log_manager.clone()),
+        ));
+        bpm.set_slow_io_threshold(options.slow_io_threshold);
+        let txn_manager = Arc::new(TransactionManager::with_last_commit_ts(Arc::new(LockManager::new().lock_timeout(options.lock_timeout)), TXN_TS_FLAG - 1));
+        RecoveryManager::new(bpm.clone(), log_manager.clone()).replay(&log_manager.records()?, txn_manager.versions(), open_writes)?;
+        let catalog = Arc::new(Catalog::open(bpm.clone())?);
+        catalog.check_page_size(options.page_size)?;
+        Ok(CrabDb {
+            bpm,
+            log_manager,
+            catalog,
+            txn_manager,
+            vacuum: None,
+            background_writer: None,
+            checkpointer: None,
+            wal_archive: None,
+            wal_archiver: None,
+            _change_log_hold: None,
+            replica: true,
+            warmup_path: None,
+            temp_path: None,
+            next_session: AtomicU64::new(DEFAULT_SESSION + 1),
+            options,
+        })
+    }
+
+    pub(crate) fn bpm(&self) -> &Arc<BufferPoolManager> {
+        &self.bpm
+    }
+
+    pub(crate) fn log_manager(&self) -> &Arc<LogManager> {
+        &self.log_manager
+    }
+
+    pub fn catalog(&self) -> &Arc<Catalog> {
+        &self.catalog
+    }
+
+    pub fn txn_manager(&self) -> &Arc<TransactionManager> {
+        &self.txn_manager
+    }
+
+    pub fn metrics_snapshot(&self) -> CrabDbMetricsSnapshot {
+        CrabDbMetricsSnapshot {
+            buffer_pool: self.bpm.metrics_snapshot(),
+            wal: self.log_manager.metrics_snapshot(),
+            active_transactions: self.txn_manager.active_transactions(),
+        }
+    }
+
+    // What the buffer pool's replacer knows about each frame, for working out why a page was
+    // evicted.
+    pub fn replacer_debug_state(&self) -> ReplacerDebugState {
+        self.bpm.replacer_debug_state()
+    }
+
+    // What is waiting right now, for working out why a query hangs: transactions waiting
+    // for locks, with the transactions they wait for, and threads waiting for page latches.
+    pub fn lock_waits(&self) -> LockWaits {
+        LockWaits { locks: self.txn_manager.lock_manager().lock_waits(), latches: self.bpm.latch_waits() }
+    }
+
+    // Runs the statements in `sql`, each in a transaction of its own, and returns how many
+    // rows the last one changed, or returned if it is a query.
+    pub fn execute(&self, sql: &str) -> CrabDbResult<u64> {
+        self.execute_with(sql, &[])
+    }
+
+    // `execute` with values for the placeholders $1, $2, ... of the statements.
+    pub fn execute_with(&self, sql: &str, parameters: &[Value]) -> CrabDbResult<u64> {
+        Ok(self.run_with(DEFAULT_SESSION, sql, parameters)?.count)
+    }
+
+    // Runs the statements in `sql`, each in a transaction of its own, and returns a cursor
+    // over the rows of the last one. If that is a query, it runs as the rows are fetched, and
+    // its transaction commits once they run out.
+    pub fn query(&self, sql: &str) -> CrabDbResult<Cursor> {
+        self.query_with(sql, &[])
+    }
+
+    // `query` with values for the placeholders $1, $2, ... of the statements.
+    pub fn query_with(&self, sql: &str, parameters: &[Value]) -> CrabDbResult<Cursor> {
+        self.query_statements(DEFAULT_SESSION, parse_as_of(sql)?, parameters)
+    }
+
+    // `query` reading the database as it was at `as_of`, for the queries in `sql` without an
+    // AS OF clause of their own. See `CrabDbOptions::history_retention` for how far back.
+    pub fn query_as_of(&self, sql: &str, as_of: AsOf) -> CrabDbResult<Cursor> {
+        let statements = parse_as_of(sql)?.into_iter()
+            .map(|(statement, own)| (statement, own.or(Some(as_of))))
+            .collect();
+        self.query_statements(DEFAULT_SESSION, statements, &[])
+    }
+
+    pub(crate) fn query_statements(
+        &self,
+        session: SessionId,
+        statements: Vec<(Statement, Option<AsOf>)>,
+        parameters: &[Value],
+    ) -> CrabDbResult<Cursor> {
+        let Some(((last, as_of), statements)) = statements.split_last() else {
+            return Ok(Cursor::from(StatementOutput::default().rows));
+        };
+        for (statement, as_of) in statements {
+            self.run_autocommit(session, statement, *as_of, parameters)?;
+        }
+        let txn = self.begin_as_of(last, *as_of)?;
+        match self.open_cursor(session, &txn, last, parameters) {
+            Ok(Some(cursor)) => Ok(cursor.ending(self.txn_manager.clone(), txn)),
+            Ok(None) => {
+                self.txn_manager.abort(&txn)?;
+                Ok(Cursor::from(self.run_autocommit(session, last, *as_of, parameters)?.rows))
+            },
+            Err(e) => {
+                // The error that ended the statement is the one to report, whatever the
+                // abort after it runs into.
+                let _ = self.txn_manager.abort(&txn);
+                Err(e)
+            },
+        }
+    }
+
+    pub(crate) fn run(&self, sql: &str) -> CrabDbResult<StatementOutput> {
+        self.run_with(DEFAULT_SESSION, sql, &[])
+    }
+
+    pub(crate) fn run_with(&self, session: SessionId, sql: &str, parameters: &[Value]) -> CrabDbResult<StatementOutput> {
+        let mut output = StatementOutput::default();
+        for (statement, as_of) in parse_as_of(sql)? {
+            output = self.run_autocommit(session, &statement, as_of, parameters)?;
+        }
+        Ok(output)
+    }
+
+    fn run_autocommit(&self, session: SessionId, statement: &Statement, as_of: Option<AsOf>, parameters: &[Value]) -> CrabDbResult<StatementOutput> {
+        let txn = self.begin_as_of(statement, as_of)?;
+        let output = match self.run_statement(session, &txn, statement, parameters) {
+            Ok(output) => output,
+            Err(e) => {
+                let _ = self.txn_manager.abort(&txn);
+                return Err(e);
+            },
+        };
+        self.commit(&txn)?;
+        Ok(output)
+    }
+
+    // Loads the rows of the file at `path` into `table` in a transaction of its own, like
+    // COPY table FROM, and returns how many there were.
+    pub fn import(&self, table: &str, path: impl AsRef<Path>, format: CopyFormat) -> CrabDbResult<u64> {
+        let table = self.table_info(table)?;
+        self.run_copy(|ctx| copy::import(ctx, &table, path.as_ref(), format))
+    }
+
+    // Writes the rows of `table` to the file at `path`, like COPY table TO, and returns how
+    // many there were.
+    pub fn export(&self, table: &str, path: impl AsRef<Path>, format: CopyFormat) -> CrabDbResult<u64> {
+        let table = self.table_info(table)?;
+        self.run_copy(|ctx| copy::export(ctx, &table, path.as_ref(), format))
+    }
+
+    fn run_copy(&self, copy: impl FnOnce(&ExecutorContext) -> CrabDbResult<u64>) -> CrabDbResult<u64> {
+        let txn = self.begin();
+        let ctx = ExecutorContext::new(self.catalog.clone(), self.txn_manager.clone(), txn.clone());
+        match copy(&ctx) {
+            Ok(count) => {
+                self.commit(&txn)?;
+                Ok(count)
+            },
+            Err(e) => {
+                let _ = self.txn_manager.abort(&txn);
+                Err(e)
+            },
+        }
+    }
+
+    // The key-value store called `name`, created if it does not exist yet. Its reads and
+    // writes run in the transactions of `begin_transaction`.
+    pub fn kv_store(&self, name: &str) -> CrabDbResult<KvStore> {
+        let info = match self.catalog.kv_store(name) {
+            Some(info) => info,
+            None => self.catalog.create_kv_store(name, KV_TREE_KEY_SIZE)?,
+        };
+        Ok(KvStore::new(info, self.txn_manager.clone()))
+    }
+
+    // Adds a row trigger to a table. Triggers live as long as the database is open, so the
+    // embedder creates them again after every open.
+    pub fn create_trigger(&self, table: &str, trigger: Trigger) -> CrabDbResult<()> {
+        self.catalog.create_trigger(table, trigger).map(|_| ())
+    }
+
+    pub fn drop_trigger(&self, table: &str, name: &str) -> CrabDbResult<()> {
+        self.catalog.drop_trigger(table, name)
+    }
+
+    // Registers a scalar function queries can call by name. Like triggers, functions live
+    // as long as the database is open.
+    pub fn create_function(&self, function: ScalarUdf) -> CrabDbResult<()> {
+        self.catalog.create_scalar_function(function).map(|_| ())
+    }
+
+    pub fn create_aggregate(&self, function: AggregateUdf) -> CrabDbResult<()> {
+        self.catalog.create_aggregate_function(function).map(|_| ())
+    }
+
+    pub fn drop_function(&self, name: &str) -> CrabDbResult<()> {
+        self.catalog.drop_function(name)
+    }
+
+    pub fn begin_transaction(&self) -> DbTransaction<'_> {
+        DbTransaction::new(self, DEFAULT_SESSION, self.begin())
+    }
+
+    // Starts a session of its own, for temporary tables only it sees; see `Session`.
+    pub fn session(&self) -> Session<'_> {
+        Session::new(self, self.next_session.fetch_add(1, Ordering::Relaxed))
+    }
+
+    // Drops the temporary tables of `session`, along with the versions of their rows.
+    pub(crate) fn end_session(&self, session: SessionId) {
+        for table in self.catalog.drop_temp_tables(session) {
+            self.txn_manager.versions().forget(&table.heap().page_ids());
+        }
+    }
+
+    pub(crate) fn begin(&self) -> Arc<Transaction> {
+        self.txn_manager.begin(self.options.isolation)
+    }
+
+    // A transaction to run `statement` in, reading the past if it has an AS OF clause, which
+    // only queries can.
+    fn begin_as_of(&self, statement: &Statement, as_of: Option<AsOf>) -> CrabDbResult<Arc<Transaction>> {
+        match as_of {
+            None => Ok(self.begin()),
+            Some(_) if !matches!(statement, Statement::Query(_)) => Err(CrabDBError::InvalidInput("AS OF only applies to queries".to_string())),
+            Some(as_of) => self.txn_manager.begin_as_of(as_of),
+        }
+    }
+
+    // Keeps the database as it is now readable with `query_as_of(.., pin.as_of())` until the
+    // pin is dropped, whatever the history retention.
+    pub fn pin_snapshot(&self) -> SnapshotPin {
+        self.txn_manager.pin_snapshot()
+    }
+
+    // `pin_snapshot` of the database as it was at `as_of`, if that is still kept.
+    pub fn pin_snapshot_as_of(&self, as_of: AsOf) -> CrabDbResult<SnapshotPin> {
+        self.txn_manager.pin_snapshot_as_of(as_of)
+    }
+
+    // Runs vacuum once, on top of the background runs if there are any.
+    pub fn vacuum(&self) -> CrabDbResult<VacuumStats> {
+        match &self.vacuum {
+            Some(vacuum) => vacuum.run(),
+            None => Vacuum::new(self.catalog.clone(), self.txn_manager.clone()).run(),
+        }
+    }
+
+    // Runs vacuum once, then compacts every table: tuples are moved off pages filled less
+    // than `compact_below` of the options, or half if unset, and the pages that empties go
+    // back to the free space map. Pages are only moved off while no transaction is running;
+    // the stats count the ones skipped.
+    pub fn vacuum_full(&self) -> CrabDbResult<VacuumStats> {
+        let fill_factor = self.options.compact_below.unwrap_or(0.5);
+        match &self.vacuum {
+            Some(vacuum) => vacuum.run_full(fill_factor),
+            None => Vacuum::new(self.catalog.clone(), self.txn_manager.clone()).run_full(fill_factor),
+        }
+    }
+
+    // Writes a backup of the database to `path` without stopping reads or writes; see
+    // `write_backup`. The backup holds what was on disk when it finished.
+    pub fn backup_to(&self, path: impl AsRef<Path>) -> CrabDbResult<BackupStats> {
+        write_backup(&self.bpm, &self.log_manager, &self.txn_manager, path.as_ref())
+    }
+
+    // LSN of the last record in the log on disk. A replica that applied up to it has every
+    // commit made so far.
+    pub fn flushed_lsn(&self) -> Lsn {
+        self.log_manager.flushed_lsn()
+    }
+
+    // Ships the log to the read replicas that connect to `listener`, until the server is
+    // dropped. Needs `CrabDbOptions::replication`, on since before the backups the replicas
+    // start from were taken.
+    pub fn serve_replicas(&self, listener: TcpListener) -> CrabDbResult<ReplicationServer> {
+        if !self.options.replication {
+            return Err(CrabDBError::InvalidInput("Serving replicas needs replication on".to_string()));
+        }
+        ReplicationServer::new(self.log_manager.clone(), listener)
+    }
+
+    // Copies the log written since the last time into the WAL archive, if there is one, and
+    // returns the new segment.
+    pub fn archive_wal(&self) -> CrabDbResult<Option<WalSegment>> {
+        match &self.wal_archive {
+            Some(archive) => archive.archive(&self.log_manager),
+            None => Ok(None),
+        }
+    }
+
+    // Takes a fuzzy checkpoint and recycles the log before what recovery from it reads, as
+    // far as the archive, backups and readers of the log allow. Returns how many records
+    // were recycled.
+    pub fn checkpoint(&self) -> CrabDbResult<usize> {
+        checkpointer::checkpoint(&self.bpm, &self.txn_manager, self.wal_archive.as_deref())
+    }
+
+    // Grows or shrinks the buffer pool to `pool_size` frames while the database stays open;
+    // see `BufferPoolManager::resize`. For a database of an engine, this is its quota of the
+    // shared pool.
+    pub fn resize_buffer_pool(&self, pool_size: usize) -> CrabDbResult<()> {
+        self.bpm.resize(pool_size)
+    }
+
+    // Stops background work, writes every page back to the database file and records the
+    // hot pages for the next open. Dropping the database does the same but cannot report
+    // errors.
+    pub fn close(mut self) -> CrabDbResult<()> {
+        self.shut_down()
+    }
+
+    fn shut_down(&mut self) -> CrabDbResult<()> {
+        self.vacuum.take();
+        self.background_writer.take();
+        self.checkpointer.take();
+        self.wal_archiver.take();
+        self.end_session(DEFAULT_SESSION);
+        if let Some(temp_path) = self.temp_path.take() {
+            // Open temporary tables are gone with the file; the next open empties it anyway.
+            let _ = std::fs::remove_file(temp_path);
+        }
+        self.bpm.flush_all_pages()?;
+        if let Some(warmup_path) = &self.warmup_path {
+            write_warmup_file(warmup_path, &self.bpm.hot_pages())?;
+        }
+        self.archive_wal()?;
+        if !self.replica {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn commit(&self, txn: &Arc<Transaction>) -> CrabDbResult<()> {
+        // Materialized views take in the transaction's changes as part of it.
+        if txn.state() == TransactionState::Growing {
+            let ctx = ExecutorContext::new(self.catalog.clone(), self.txn_manager.clone(), txn.clone());
+            if let Err(e) = materialized_view::maintain(&ctx) {
+                let _ = self.txn_manager.abort(txn);
+                return Err(e);
+            }
+        }
+        let changed = txn.changed_rows();
+        // Catalog pages a DDL statement changed are logged with the transaction too.
+        self.txn_manager.commit_with(txn, |_| {
+            txn.log_unlogged_pages(&self.bpm)?;
+            if self.options.capture_changes {
+                self.log_row_changes(txn)?;
+            }
+            Ok(())
+        })?;
+        if self.wal_archive.is_some() || self.options.replication {
+            // Follows the commit's records, so a restore to this time includes them and
+            // replicas apply them together.
+            let lsn = self.log_manager.append(SYSTEM_TXN_ID, INVALID_LSN, LogRecordBody::Clock {
+                unix_micros: platform::clock().now_micros(),
+            })?;
+            self.log_manager.flush(lsn)?;
+        }
+        // The commit stands whether or not this gets to run; vacuum prunes what it leaves.
+        let _ = self.prune_written_rows(&changed);
+        Ok(())
+    }
+
+    // Removes the index entries the versions a committed transaction replaced no longer
+    // need, so full-text statistics follow its updates and deletes (see `prune_rows`).
+    fn prune_written_rows(&self, changed: &[(PageId, Rid, bool)]) -> CrabDbResult<()> {
+        let mut replaced: HashMap<PageId, Vec<Rid>> = HashMap::new();
+        for &(heap, rid, inserted) in changed {
+            if !inserted {
+                replaced.entry(heap).or_default().push(rid);
+            }
+        }
+        if replaced.is_empty() {
+            return Ok(());
+        }
+        for (heap, table) in self.tables_by_heap() {
+            if let Some(rids) = replaced.get(&heap) {
+                vacuum::prune_rows(&self.txn_manager, &table, &self.catalog.indexes_of(&table), rids)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn tables_by_heap(&self) -> HashMap<PageId, Arc<TableInfo>> {
+        self.catalog.table_names().iter()
+            .filter_map(|name| self.catalog.table(name))
+            .map(|table| (table.heap().first_page_id(), table))
+            .collect()
+    }
+
+    // Logs the table rows `txn` changed, as it left them, ahead of its Commit record. Change
+    // streams decode them from the log.
+    fn log_row_changes(&self, txn: &Transaction) -> CrabDbResult<()> {
+        let changed = txn.changed_rows();
+        if changed.is_empty() {
+            return Ok(());
+        }
+        let tables = self.tables_by_heap();
+        for (heap, rid, inserted) in changed {
+            let Some(table) = tables.get(&heap) else {
+                continue;
+            };
+            let deleted = table.heap().tuple_meta(rid)?.is_deleted;
+            let (kind, after) = match (inserted, deleted) {
+                (true, true) => continue,
+                (true, false) => (RowChangeKind::Insert, table.heap().get_tuple(rid)?.data().to_vec()),
+                (false, false) => (RowChangeKind::Update, table.heap().get_tuple(rid)?.data().to_vec()),
+                (false, true) => (RowChangeKind::Delete, Vec::new()),
+            };
+            txn.log(&self.log_manager, |_| LogRecordBody::RowChange { table: table.oid(), rid, kind, after })?;
+        }
+        Ok(())
+    }
+
+    // The committed changes to the rows of `table` from now on. Needs
+    // `CrabDbOptions::capture_changes`.
+    pub fn subscribe(&self, table: &str) -> CrabDbResult<ChangeStream> {
+        self.subscribe_after(table, self.log_manager.flushed_lsn())
+    }
+
+    // The committed changes to the rows of `table` logged after `lsn`, such as the position
+    // an earlier stream got to.
+    pub fn subscribe_after(&self, table: &str, lsn: Lsn) -> CrabDbResult<ChangeStream> {
+        if !self.options.capture_changes {
+            return Err(CrabDBError::InvalidInput("Subscribing to changes needs capture_changes on".to_string()));
+        }
+        Ok(ChangeStream::new(self.log_manager.clone(), self.table_info(table)?, lsn))
+    }
+
+    fn table_info(&self, table: &str) -> CrabDbResult<Arc<TableInfo>> {
+        self.catalog.table(table).ok_or_else(|| CrabDBError::InvalidInput(format!("Table {table} does not exist")))
+    }
+
+    // Runs the statements in `sql` in `txn`, which stays open, and returns the output of the
+    // last one.
+    pub(crate) fn run_in_transaction(&self, session: SessionId, txn: &Arc<Transaction>, sql: &str) -> CrabDbResult<StatementOutput> {
+        let mut output = StatementOutput::default();
+        for statement in parse(sql)? {
+            output = self.run_in_savepoint(txn, |db| db.run_statement(session, txn, &statement, &[]))?;
+        }
+        Ok(output)
+    }
+
+    // `query` for a transaction that stays open: the cursor leaves it be.
+    pub(crate) fn query_in_transaction(&self, session: SessionId, txn: &Arc<Transaction>, sql: &str) -> CrabDbResult<Cursor> {
+        let statements = parse(sql)?;
+        let Some((last, statements)) = statements.split_last() else {
+            return Ok(Cursor::from(StatementOutput::default().rows));
+        };
+        for statement in statements {
+            self.run_in_savepoint(txn, |db| db.run_statement(session, txn, statement, &[]))?;
+        }
+        self.run_in_savepoint(txn, |db| match db.open_cursor(session, txn, last, &[])? {
+            Some(cursor) => Ok(cursor),
+            None => Ok(Cursor::from(db.run_statement(session, txn, last, &[])?.rows)),
+        })
+    }
+
+    // Runs one statement of `txn`, undoing only what the statement did if it fails.
+    fn run_in_savepoint<T>(&self, txn: &Arc<Transaction>, run: impl FnOnce(&Self) -> CrabDbResult<T>) -> CrabDbResult<T> {
+        txn.savepoint(STATEMENT_SAVEPOINT)?;
+        run(self).or_else(|e| {
+            // A conflict or deadlock aborts the whole transaction instead.
+            if txn.state() == TransactionState::Growing {
+                txn.rollback_to(STATEMENT_SAVEPOINT)?;
+            }
+            Err(e)
+        })
+    }
+
+    // A cursor running `statement` in `txn` if it is a query; None for any other statement,
+    // which `run_statement` runs to completion instead.
+    fn open_cursor(&self, session: SessionId, txn: &Arc<Transaction>, statement: &Statement, parameters: &[Value]) -> CrabDbResult<Option<Cursor>> {
+        if !matches!(statement, Statement::Query(_)) {
+            return Ok(None);
+        }
+        let bound = Binder::new(&self.catalog).with_parameters(parameters).in_session(session).bind(statement)?;
+        let Plan::Execute(plan) = self.planner(txn).plan(bound) else {
+            return Ok(None);
+        };
+        let ctx = ExecutorContext::new(self.catalog.clone(), self.txn_manager.clone(), txn.clone());
+        Cursor::open(plan, ctx).map(Some)
+    }
+
+    // Reads of the past scan whole tables, as indexes only know the rows as they are now.
+    fn planner(&self, txn: &Transaction) -> Planner<'_> {
+        let planner = Planner::new(&self.catalog);
+        if txn.is_historical() { planner.without_indexes() } else { planner }
+    }
+
+    // Runs one statement of `session` in `txn`. CREATE TABLE takes effect at once, whether or
+    // not the transaction commits.
+    pub(crate) fn run_statement(
+        &self,
+        session: SessionId,
+        txn: &Arc<Transaction>,
+        statement: &Statement,
+        parameters: &[Value],
+    ) -> CrabDbResult<StatementOutput> {
+        let bound = Binder::new(&self.catalog).with_parameters(parameters).in_session(session).bind(statement)?;
+        let ctx = ExecutorContext::new(self.catalog.clone(), self.txn_manager.clone(), txn.clone());
+        match self.planner(txn).plan(bound) {
+            Plan::CreateTable { name, schema, ttl, temporary: true, if_not_exists, .. } => {
+                if !(if_not_exists && self.catalog.temp_table(session, &name).is_some()) {
+                    self.catalog.create_temp_table(session, &name, schema, ttl)?;
+                }
+                Ok(StatementOutput::default())
+            },
+            Plan::CreateTable { name, schema, unique_keys, ttl, if_not_exists, .. } => {
+                if !(if_not_exists && self.catalog.table(&name).is_some()) {
+                    self.catalog.create_table_with_ttl(&name, schema, ttl)?;
+                    for key in &unique_keys {
+                        let columns: Vec<_> = key.columns.iter().map(String::as_str).collect();
+                        self.catalog.create_index(&key.name, &name, &columns, IndexKind::BPlusTree)?;
+                    }
+                }
+                Ok(StatementOutput::default())
+            },
+            Plan::CreateIndex { name, table, columns, kind, if_not_exists } => {
+                if !(if_not_exists && self.catalog.index(&name).is_some()) {
+                    let columns: Vec<_> = columns.iter().map(String::as_str).collect();
+                    self.catalog.create_index(&name, table.name(), &columns, kind)?;
+                }
+                Ok(StatementOutput::default())
+            },
+            // The view's table is created at once, like any other, and filled in `txn`.
+            Plan::CreateMaterializedView { name, schema, view, if_not_exists } => {
+                if if_not_exists && self.catalog.table(&name).is_some() {
+                    return Ok(StatementOutput::default());
+                }
+                let count = materialized_view::create(&ctx, &name, schema, view)?;
+                Ok(StatementOutput { count, ..StatementOutput::default() })
+            },
+            Plan::RefreshMaterializedView { table, view } => {
+                let count = materialized_view::refresh(&ctx, &table, &view)?;
+                Ok(StatementOutput { count, ..StatementOutput::default() })
+            },
+            Plan::Execute(plan) => {
+                let mut executor = plan.build(&ctx);
+                let schema = executor.output_schema().clone();
+                let rows: Vec<_> = execute(executor.as_mut())?.into_iter().map(|row| row.values).collect();
+                let count = match &plan {
+                    PhysicalPlan::Insert { .. } | PhysicalPlan::Update { .. } | PhysicalPlan::Delete { .. } | PhysicalPlan::Analyze { .. } => {
+                        rows.first().and_then(|row| row[0].as_i64()).unwrap_or(0) as u64
+                    },
+                    _ => rows.len() as u64,
+                };
+                Ok(StatementOutput { rows: RowIterator::new(schema, rows), count })
+            },
+            Plan::Copy { table, path, format, to } => {
+                let count = if to { copy::export(&ctx, &table, &path, format)? } else { copy::import(&ctx, &table, &path, format)? };
+                Ok(StatementOutput { count, ..StatementOutput::default() })
+            },
+            Plan::Explain { plan, analyze } => {
+                let text = if analyze { plan.explain_analyze(&ctx)? } else { plan.explain() };
+                let rows: Vec<_> = text.lines().map(|line| vec![Value::Varchar(line.to_string())]).collect();
+                let schema = Schema::new(vec![Column::new("plan", DataType::Varchar).not_null()]);
+                Ok(StatementOutput { count: rows.len() as u64, rows: RowIterator::new(schema, rows) })
+            },
+        }
+    }
+}
+
+impl Drop for CrabDb {
+    fn drop(&mut self) {
+        let _ = self.shut_down();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+    use std::time::Duration;
+
+    use tempfile::TempDir;
+
+    use crate::buffer_pool::eviction::policy::ReplacerPolicy;
+    use crate::catalog::trigger::{Trigger, TriggerEvent, TriggerTiming};
+    use crate::catalog::udf::{AggregateUdf, ScalarUdf};
+    use crate::concurrency::transaction_manager::AsOf;
+    use crate::execution::copy::CopyFormat;
+    use crate::execution::trigger::TriggerRow;
+    use crate::platform;
+    use crate::storage::disk::page_codec::PageCompression;
+    use crate::storage::disk::temp_disk_manager::TEMP_PAGE_ID_BASE;
+    use crate::storage::encryption::KeyRing;
+    use crate::types::geometry::Geometry;
+    use crate::types::value::{DataType, Value};
+    use crate::types::{CrabDBError, CrabDbResult, ErrorCode};
+    use crate::wal::log_manager::SyncMode;
+    use crate::wal::log_record::RowChangeKind;
+    use super::{read_warmup_file, CrabDb, CrabDbOptions};
+
+    #[test]
+    pub fn test_crab_db_end_to_end() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("crabs.db");
+        {
+            let db = CrabDb::open(&path, CrabDbOptions::default()).unwrap();
+            db.execute("CREATE TABLE crabs (id BIGINT NOT NULL, name VARCHAR)").unwrap();
+            assert_eq!(2, db.execute("INSERT INTO crabs VALUES (1, 'ferris'), (2, 'sebastian')").unwrap());
+            let rows = db.query("SELECT name FROM crabs WHERE id = 2").unwrap();
+            assert_eq!("name", rows.schema().column(0).name());
+            assert_eq!(vec![vec![Value::Varchar("sebastian".into())]], rows.fetch_all().unwrap());
+
+            // A failed statement undoes only itself; rolling back undoes the rest.
+            let txn = db.begin_transaction();
+            assert_eq!(1, txn.execute("UPDATE crabs SET name = 'crabby' WHERE id = 1").unwrap());
+            assert!(txn.execute("INSERT INTO crabs VALUES (3, 'larry'), (NULL, 'nobody')").is_err());
+            assert_eq!(2, txn.query("SELECT * FROM crabs").unwrap().fetch_all().unwrap().len());
+            txn.rollback().unwrap();
+            let txn = db.begin_transaction();
+            txn.execute("DELETE FROM crabs WHERE id = 2; INSERT INTO crabs VALUES (3, 'larry')").unwrap();
+            // other transactions see the changes once they are committed
+            assert_eq!(1, db.query("SELECT * FROM crabs WHERE id = 2").unwrap().fetch_all().unwrap().len());
+            txn.commit().unwrap();
+            let plan: Vec<_> = db.query("EXPLAIN SELECT * FROM crabs").unwrap().fetch_all().unwrap().into_iter().map(|row| row[0].to_string()).collect();
+            assert_eq!(vec!["Projection [#0, #1]", "  SeqScan crabs"], plan);
+            db.close().unwrap();
+        }
+
+        let db = CrabDb::open(&path, CrabDbOptions::default()).unwrap();
+        let rows: Vec<_> = db.query("SELECT id, name FROM crabs ORDER BY id").unwrap().fetch_all().unwrap();
+        assert_eq!(vec![
+            vec![Value::Int64(1), Value::Varchar("ferris".into())],
+            vec![Value::Int64(3), Value::Varchar("larry".into())],
+        ], rows);
+        // an uncommitted transaction is gone after a restart
+        let txn = db.begin_transaction();
+        txn.execute("INSERT INTO crabs VALUES (4, 'pinchy')").unwrap();
+        std::mem::forget(txn);
+        drop(db);
+        let db = CrabDb::open(&path, CrabDbOptions::default()).unwrap();
+        assert_eq!(2, db.execute("SELECT * FROM crabs").unwrap());
+        assert_eq!(1, db.execute("INSERT INTO crabs VALUES (4, 'pinchy')").unwrap());
+
+        let txn = db.begin_transaction();
+        db.kv_store("habitats").unwrap().put(txn.txn(), b"ferris", b"rust").unwrap();
+        txn.commit().unwrap();
+        drop(db);
+        let db = CrabDb::open(&path, CrabDbOptions::default()).unwrap();
+        let txn = db.begin_transaction();
+        assert_eq!(Some(b"rust".to_vec()), db.kv_store("habitats").unwrap().get(txn.txn(), b"ferris").unwrap());
+    }
+
+    #[test]
+    pub fn test_crab_db_recovers_committed_rows_after_a_crash() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("crabs.db");
+        let options = CrabDbOptions::default().vacuum_interval(None).flush_interval(None).warmup(false);
+        {
+            let db = CrabDb::open(&path, options.clone()).unwrap();
+            db.execute("CREATE TABLE crabs (id BIGINT NOT NULL, name VARCHAR)").unwrap();
+            db.execute("INSERT INTO crabs VALUES (1, 'ferris')").unwrap();
+            let txn = db.begin_transaction();
+            txn.execute("UPDATE crabs SET name = 'crabby' WHERE id = 1").unwrap();
+            // the uncommitted update reaches disk, then the process dies
+            db.bpm().flush_all_pages().unwrap();
+            std::mem::forget(txn);
+            std::mem::forget(db);
+        }
+
+        let db = CrabDb::open(&path, options).unwrap();
+        let rows = db.query("SELECT id, name FROM crabs").unwrap().fetch_all().unwrap();
+        assert_eq!(vec![vec![Value::Int64(1), Value::Varchar("ferris".into())]], rows);
+        assert_eq!(1, db.execute("UPDATE crabs SET name = 'crabby' WHERE id = 1").unwrap());
+    }
+
+    #[test]
+    pub fn test_crab_db_in_memory() {
+        // What a wasm build starts with: nothing in the background and no files.
+        let options = CrabDbOptions::default().vacuum_interval(None).flush_interval(None).deadlock_detection_interval(None).warmup(false);
+        let db = CrabDb::open_in_memory(options.clone()).unwrap();
+        db.execute("CREATE TABLE crabs (id BIGINT NOT NULL, name VARCHAR)").unwrap();
+        db.execute("INSERT INTO crabs VALUES (1, 'ferris'), (2, 'sebastian')").unwrap();
+        db.execute("UPDATE crabs SET name = 'crabby' WHERE id = 1").unwrap();
+        let rows: Vec<_> = db.query("SELECT name FROM crabs ORDER BY id").unwrap().fetch_all().unwrap();
+        assert_eq!(vec![vec![Value::Varchar("crabby".into())], vec![Value::Varchar("sebastian".into())]], rows);
+        db.close().unwrap();
+
+        // Nothing outlives the database.
+        let db = CrabDb::open_in_memory(options.clone()).unwrap();
+        assert!(db.query("SELECT * FROM crabs").is_err());
+        assert!(CrabDb::open_in_memory(options.wal_archive_dir("archive")).is_err());
+    }
+
+    #[test]
+    pub fn test_crab_db_enforces_key_constraints() {
+        let db = CrabDb::open_in_memory(CrabDbOptions::default().vacuum_interval(None).flush_interval(None)).unwrap();
+        db.execute("CREATE TABLE crabs (id BIGINT PRIMARY KEY, name VARCHAR UNIQUE)").unwrap();
+        db.execute("INSERT INTO crabs VALUES (1, 'ferris'), (2, 'sebastian')").unwrap();
+        let violation = |sql: &str| match db.execute(sql) {
+            Err(CrabDBError::ConstraintViolation { table, constraint }) => format!("{table}.{constraint}"),
+            other => panic!("expected a constraint violation, got {other:?}"),
+        };
+        assert_eq!("crabs.crabs_pkey", violation("INSERT INTO crabs VALUES (3, 'larry'), (1, 'impostor')"));
+        assert_eq!("crabs.crabs_name_key", violation("UPDATE crabs SET name = 'ferris' WHERE id = 2"));
+        assert_eq!(2, db.execute("SELECT * FROM crabs").unwrap());
+        assert!(db.execute("INSERT INTO crabs VALUES (NULL, 'nobody')").is_err());
+
+        // A deleted or rolled back row gives its key up; an uncommitted one holds on to it
+        // until its transaction ends.
+        let txn = db.begin_transaction();
+        txn.execute("DELETE FROM crabs WHERE id = 1; INSERT INTO crabs VALUES (1, 'ferris')").unwrap();
+        txn.execute("INSERT INTO crabs VALUES (3, 'larry')").unwrap();
+        thread::scope(|scope| {
+            let inserter = scope.spawn(|| db.execute("INSERT INTO crabs VALUES (3, 'pinchy')"));
+            while db.lock_waits().locks.is_empty() && !inserter.is_finished() {
+                thread::sleep(Duration::from_millis(5));
+            }
+            assert!(!inserter.is_finished());
+            txn.rollback().unwrap();
+            inserter.join().unwrap().unwrap();
+        });
+        assert_eq!(ErrorCode::InvalidInput, db.execute("INSERT INTO crabs VALUES (4, 'pinchy')").unwrap_err().code());
+    }
+
+    #[test]
+    pub fn test_crab_db_vacuum_full_compacts_tables() {
+        let db = CrabDb::open_in_memory(CrabDbOptions::default().vacuum_interval(None).flush_interval(None)).unwrap();
+        db.execute("CREATE TABLE crabs (id BIGINT PRIMARY KEY, kept BOOLEAN, name VARCHAR)").unwrap();
+        let values: Vec<_> = (0..600).map(|id| format!("({id}, {}, '{}')", id % 10 == 0, "crab".repeat(50))).collect();
+        db.execute(&format!("INSERT INTO crabs VALUES {}", values.join(", "))).unwrap();
+        db.execute("DELETE FROM crabs WHERE kept = false").unwrap();
+        let heap_pages = || db.catalog().table("crabs").unwrap().heap().num_pages();
+        let pages = heap_pages();
+
+        // An open transaction keeps every tuple where it is.
+        let txn = db.begin_transaction();
+        db.vacuum().unwrap();
+        let stats = db.vacuum_full().unwrap();
+        assert_eq!((0, 0), (stats.tuples_moved, stats.pages_freed));
+        assert!(stats.pages_skipped > 0);
+        txn.commit().unwrap();
+
+        let free_pages = db.bpm().free_space_map().unwrap().num_free_pages();
+        let stats = db.vacuum_full().unwrap();
+        assert!(stats.tuples_moved > 0 && stats.pages_freed > 0);
+        assert_eq!(pages - stats.pages_freed, heap_pages());
+        assert_eq!(free_pages + stats.pages_freed, db.bpm().free_space_map().unwrap().num_free_pages());
+
+        // Rows and their keys moved together.
+        assert_eq!(60, db.execute("SELECT * FROM crabs").unwrap());
+        let rows = db.query("SELECT id FROM crabs WHERE id = 590").unwrap().fetch_all().unwrap();
+        assert_eq!(vec![vec![Value::Int64(590)]], rows);
+        assert!(db.execute("INSERT INTO crabs VALUES (590, true, 'impostor')").is_err());
+        db.execute("INSERT INTO crabs VALUES (591, true, 'pinchy')").unwrap();
+    }
+
+    #[test]
+    pub fn test_crab_db_searches_text_through_a_full_text_index() {
+        let db = CrabDb::open_in_memory(CrabDbOptions::default().vacuum_interval(None).flush_interval(None)).unwrap();
+        db.execute("CREATE TABLE notes (id BIGINT PRIMARY KEY, body VARCHAR)").unwrap();
+        db.execute("INSERT INTO notes VALUES (1, 'the crab walks sideways on the beach'), (2, 'a hermit crab borrows a shell'), \
+            (3, 'the beach is sandy'), (4, 'crab crab crab'), (5, NULL)").unwrap();
+        assert!(db.execute("CREATE INDEX notes_body ON notes USING GIST (body)").is_err());
+        assert!(db.execute("CREATE UNIQUE INDEX notes_id ON notes USING FULLTEXT (id)").is_err());
+        assert!(db.execute("CREATE INDEX notes_body ON notes USING FULLTEXT (id)").is_err());
+        assert!(db.query("SELECT BM25(body, 'crab') FROM notes").is_err());
+        db.execute("CREATE INDEX notes_body ON notes USING FULLTEXT (body)").unwrap();
+        db.execute("CREATE INDEX IF NOT EXISTS notes_body ON notes USING FULLTEXT (body)").unwrap();
+
+        let ids = |sql: &str| db.query(sql).unwrap().fetch_all().unwrap().into_iter().map(|row| row[0].clone()).collect::<Vec<_>>();
+        let query = "SELECT id FROM notes WHERE MATCH (body) AGAINST ('Crab, beach!')";
+        let plan: Vec<_> = ids(&format!("EXPLAIN {query}")).iter().map(Value::to_string).collect();
+        assert!(plan.iter().any(|line| line.contains("IndexScan notes using notes_body match 'crab beach'")), "{plan:?}");
+        assert_eq!(vec![Value::Int64(1)], ids(query));
+        assert_eq!(vec![Value::Int64(3), Value::Int64(4), Value::Int64(2), Value::Int64(1)],
+            ids("SELECT id, BM25(body, 'crab sandy') AS score FROM notes WHERE BM25(body, 'crab sandy') > 0 ORDER BY score DESC"));
+
+        // What an update leaves behind goes once it commits, so scores come back with the text.
+        let scores = || db.query("SELECT BM25(body, 'crab sandy') FROM notes ORDER BY id").unwrap().fetch_all().unwrap();
+        let before = scores();
+        db.execute("UPDATE notes SET body = 'crab' WHERE id = 3").unwrap();
+        assert_ne!(before, scores());
+        db.execute("UPDATE notes SET body = 'the beach is sandy' WHERE id = 3").unwrap();
+        assert_eq!(before, scores());
+        assert_eq!(0, db.vacuum().unwrap().index_entries_removed);
+
+        // Rows changed or deleted while a snapshot may still need them are rechecked until
+        // vacuum prunes what they left behind, and new text is searchable at once.
+        let reader = db.begin_transaction();
+        db.execute("UPDATE notes SET body = 'a crab on the beach' WHERE id = 2").unwrap();
+        db.execute("DELETE FROM notes WHERE id = 1").unwrap();
+        assert_eq!(vec![Value::Int64(2)], ids(query));
+        assert_eq!(vec![Value::Int64(2), Value::Int64(4)], ids("SELECT id FROM notes WHERE MATCH (body) AGAINST ('crab') ORDER BY id"));
+        reader.commit().unwrap();
+        let stats = db.vacuum().unwrap();
+        assert!(stats.index_entries_removed > 0, "{stats:?}");
+        assert_eq!(0, db.vacuum().unwrap().index_entries_removed);
+        db.vacuum_full().unwrap();
+        assert_eq!(vec![Value::Int64(2)], ids(query));
+        assert_eq!(vec![Value::Int64(3)], ids("SELECT id FROM notes WHERE MATCH (body) AGAINST ('SANDY' IN NATURAL LANGUAGE MODE)"));
+    }
+
+    #[test]
+    pub fn test_crab_db_finds_nearest_vectors_through_an_hnsw_index() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("crabs.db");
+        let db = CrabDb::open(&path, CrabDbOptions::default().vacuum_interval(None)).unwrap();
+        assert!(db.execute("CREATE TABLE shells (id BIGINT, embedding VECTOR)").is_err());
+        db.execute("CREATE TABLE shells (id BIGINT PRIMARY KEY, embedding VECTOR(3))").unwrap();
+        db.execute("INSERT INTO shells VALUES (1, '[0, 0, 0]'), (2, '[1, 0, 0]'), (3, '[0, 2, 0]'), (4, '[5, 5, 5]'), (5, NULL)").unwrap();
+        assert!(db.execute("INSERT INTO shells VALUES (6, '[1, 2]')").is_err());
+        assert!(db.execute("CREATE INDEX shells_embedding ON shells (embedding)").is_err());
+        assert!(db.execute("CREATE INDEX shells_embedding ON shells USING HNSW (id)").is_err());
+        db.execute("CREATE INDEX shells_embedding ON shells USING HNSW (embedding)").unwrap();
+
+        let ids = |sql: &str, parameters: &[Value]| {
+            db.query_with(sql, parameters).unwrap().fetch_all().unwrap().into_iter().map(|row| row[0].clone()).collect::<Vec<_>>()
+        };
+        let query = "SELECT id, embedding <-> $1 AS distance FROM shells ORDER BY embedding <-> $1 LIMIT 2";
+        let near = [Value::Varchar("[0.9, 0.1, 0]".into())];
+        let plan: Vec<_> = ids(&format!("EXPLAIN {query}"), &near).iter().map(Value::to_string).collect();
+        assert!(plan.iter().any(|line| line.contains("IndexScan shells using shells_embedding nearest 2 to [0.9, 0.1, 0]")), "{plan:?}");
+        assert_eq!(vec![Value::Int64(2), Value::Int64(1)], ids(query, &near));
+        let rows = db.query("SELECT embedding <-> '[0, 0, 0]', embedding FROM shells WHERE id = 3").unwrap().fetch_all().unwrap();
+        assert_eq!(vec![vec![Value::Float64(2.0), Value::Vector(vec![0.0, 2.0, 0.0])]], rows);
+        assert!(db.query_with(query, &[]).is_err());
+        assert!(db.query_with(query, &[Value::Varchar("[1, 2]".into())]).is_err());
+        // A filter leaves the order to a full sort.
+        let plan: Vec<_> = ids("EXPLAIN SELECT id FROM shells WHERE id > 1 ORDER BY embedding <-> '[0, 0, 0]' LIMIT 2", &[]).iter().map(Value::to_string).collect();
+        assert!(!plan.iter().any(|line| line.contains("nearest")), "{plan:?}");
+
+        // Rows that move or go away are found where they are now, and what they left behind
+        // goes as they commit, leaving vacuum nothing to prune.
+        db.execute("UPDATE shells SET embedding = '[1, 1, 0]' WHERE id = 2").unwrap();
+        db.execute("DELETE FROM shells WHERE id = 1").unwrap();
+        assert_eq!(vec![Value::Int64(2), Value::Int64(3)], ids(query, &near));
+        assert_eq!(0, db.vacuum().unwrap().index_entries_removed);
+        db.close().unwrap();
+        let db = CrabDb::open(&path, CrabDbOptions::default().vacuum_interval(None)).unwrap();
+        let far = [Value::Vector(vec![4.0, 4.0, 4.0])];
+        let rows = db.query_with(query, &far).unwrap().fetch_all().unwrap();
+        assert_eq!(Value::Int64(4), rows[0][0]);
+        assert_eq!(Some(3.0f32.sqrt() as f64), rows[0][1].as_f64());
+    }
+
+    #[test]
+    pub fn test_crab_db_finds_geometries_through_an_rtree_index() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("crabs.db");
+        let db = CrabDb::open(&path, CrabDbOptions::default().vacuum_interval(None)).unwrap();
+        db.execute("CREATE TABLE burrows (id BIGINT PRIMARY KEY, area GEOMETRY)").unwrap();
+        db.execute("INSERT INTO burrows VALUES (1, 'POINT(1 1)'), (2, 'POINT(4 2)'), (3, 'BOX(0 0, 3 3)'), (4, 'box(10 10, 12 14)'), (5, NULL)").unwrap();
+        assert!(db.execute("INSERT INTO burrows VALUES (6, 'POINT(1)')").is_err());
+        assert!(db.execute("CREATE INDEX burrows_area ON burrows (area)").is_err());
+        assert!(db.execute("CREATE INDEX burrows_area ON burrows USING RTREE (id)").is_err());
+        assert!(db.execute("CREATE UNIQUE INDEX burrows_area ON burrows USING RTREE (area)").is_err());
+
+        let ids = |sql: &str| {
+            db.query(sql).unwrap().fetch_all().unwrap().into_iter().map(|row| row[0].clone()).collect::<Vec<_>>()
+        };
+        let plan = |sql: &str| ids(&format!("EXPLAIN {sql}")).iter().map(Value::to_string).collect::<Vec<_>>();
+        let queries = [
+            ("SELECT id FROM burrows WHERE ST_CONTAINS(area, 'POINT(2 2)') ORDER BY id", "containing POINT(2 2)", vec![3]),
+            ("SELECT id FROM burrows WHERE ST_CONTAINS('BOX(0 0, 5 5)', area) ORDER BY id", "within BOX(0 0, 5 5)", vec![1, 2, 3]),
+            ("SELECT id FROM burrows WHERE ST_INTERSECTS(area, 'BOX(2 1, 11 11)') ORDER BY id", "intersecting BOX(2 1, 11 11)", vec![2, 3, 4]),
+        ];
+        let expected = |wanted: &[i64]| wanted.iter().map(|&id| Value::Int64(id)).collect::<Vec<_>>();
+        for (query, _, wanted) in &queries {
+            assert_eq!(expected(wanted), ids(query), "{query}");
+        }
+        db.execute("CREATE INDEX burrows_area ON burrows USING RTREE (area)").unwrap();
+        for (query, lookup, wanted) in &queries {
+            assert!(plan(query).iter().any(|line| line.contains(&format!("IndexScan burrows using burrows_area {lookup}"))), "{query}");
+            assert_eq!(expected(wanted), ids(query), "{query}");
+        }
+        let nearest = "SELECT id, area <-> 'POINT(5 0)' FROM burrows ORDER BY area <-> 'POINT(5 0)' LIMIT 2";
+        assert!(plan(nearest).iter().any(|line| line.contains("nearest 2 to POINT(5 0)")), "{:?}", plan(nearest));
+        let rows = db.query(nearest).unwrap().fetch_all().unwrap();
+        assert_eq!(vec![vec![Value::Int64(3), Value::Float64(2.0)], vec![Value::Int64(2), Value::Float64(5.0f64.sqrt())]], rows);
+        assert_eq!(vec![Value::Boolean(true)], ids("SELECT ST_INTERSECTS(area, 'POINT(3 3)') FROM burrows WHERE id = 3"));
+        assert!(db.query("SELECT id FROM burrows WHERE ST_CONTAINS(area, id)").is_err());
+        assert!(db.query("SELECT id FROM burrows WHERE ST_CONTAINS(area)").is_err());
+        assert!(db.query("SELECT id FROM burrows WHERE ST_CONTAINS(area, 'CIRCLE(0 0, 1)')").is_err());
+
+        // Rows that move or go away are found where they are now, through the index and
+        // after reopening, and leave nothing behind for vacuum.
+        db.execute("UPDATE burrows SET area = 'POINT(11 11)' WHERE id = 2").unwrap();
+        db.execute("DELETE FROM burrows WHERE id = 3").unwrap();
+        assert_eq!(expected(&[1]), ids(queries[1].0));
+        assert_eq!(expected(&[2, 4]), ids(queries[2].0));
+        assert_eq!(0, db.vacuum().unwrap().index_entries_removed);
+        db.close().unwrap();
+        let db = CrabDb::open(&path, CrabDbOptions::default().vacuum_interval(None)).unwrap();
+        let rows = db.query("SELECT id, area FROM burrows ORDER BY area <-> 'POINT(11 12)' LIMIT 1").unwrap().fetch_all().unwrap();
+        assert_eq!(vec![vec![Value::Int64(4), Value::Geometry(Geometry::bbox(10.0, 10.0, 12.0, 14.0))]], rows);
+        let rows = db.query("SELECT id FROM burrows WHERE ST_INTERSECTS('POINT(11 11)', area) ORDER BY id").unwrap().fetch_all().unwrap();
+        assert_eq!(vec![vec![Value::Int64(2)], vec![Value::Int64(4)]], rows);
+    }
+
+    #[test]
+    pub fn test_crab_db_reads_the_past_as_of_a_time_or_an_lsn() {
+        let options = CrabDbOptions::default().vacuum_interval(None).flush_interval(None);
+        let db = CrabDb::open_in_memory(options.clone().history_retention(Some(Duration::from_secs(3600)))).unwrap();
+        db.execute("CREATE TABLE crabs (id BIGINT PRIMARY KEY, name VARCHAR)").unwrap();
+        db.execute("INSERT INTO crabs VALUES (1, 'pinchy'), (2, 'clawdia')").unwrap();
+        let lsn = db.flushed_lsn();
+        thread::sleep(Duration::from_millis(2));
+        let then = platform::clock().now_micros();
+        thread::sleep(Duration::from_millis(2));
+        db.execute("UPDATE crabs SET name = 'snappy' WHERE id = 1").unwrap();
+        db.execute("DELETE FROM crabs WHERE id = 2").unwrap();
+        db.vacuum().unwrap();
+
+        let past = vec![vec![Value::Int64(1), Value::Varchar("pinchy".into())], vec![Value::Int64(2), Value::Varchar("clawdia".into())]];
+        let rows = db.query(&format!("SELECT * FROM crabs AS OF LSN {lsn}")).unwrap().fetch_all().unwrap();
+        assert_eq!(past, rows);
+        let rows = db.query(&format!("SELECT * FROM crabs ORDER BY id AS OF TIMESTAMP {then}")).unwrap().fetch_all().unwrap();
+        assert_eq!(past, rows);
+        // the key lookup scans the table instead, as the index only has the row as it is now
+        let rows = db.query_as_of("SELECT name FROM crabs WHERE id = 1", AsOf::Timestamp(then)).unwrap().fetch_all().unwrap();
+        assert_eq!(vec![vec![Value::Varchar("pinchy".into())]], rows);
+        let rows = db.query("SELECT name FROM crabs WHERE id = 1").unwrap().fetch_all().unwrap();
+        assert_eq!(vec![vec![Value::Varchar("snappy".into())]], rows);
+        assert!(db.execute(&format!("DELETE FROM crabs AS OF LSN {lsn}")).is_err());
+        assert!(db.query(&format!("SELECT * FROM crabs AS OF LSN {lsn}; SELECT 1 AS OF LSN")).is_err());
+        assert!(db.begin_transaction().query(&format!("SELECT * FROM crabs AS OF LSN {lsn}")).is_err());
+
+        // without a retention only a pin keeps the past from vacuum
+        let db = CrabDb::open_in_memory(options).unwrap();
+        db.execute("CREATE TABLE crabs (id BIGINT PRIMARY KEY, name VARCHAR)").unwrap();
+        db.execute("INSERT INTO crabs VALUES (1, 'pinchy')").unwrap();
+        let lsn = db.flushed_lsn();
+        let pin = db.pin_snapshot();
+        db.execute("UPDATE crabs SET name = 'snappy' WHERE id = 1").unwrap();
+        db.vacuum().unwrap();
+        let rows = db.query_as_of("SELECT name FROM crabs", pin.as_of()).unwrap().fetch_all().unwrap();
+        assert_eq!(vec![vec![Value::Varchar("pinchy".into())]], rows);
+        assert!(db.pin_snapshot_as_of(AsOf::Lsn(lsn)).is_ok());
+        drop(pin);
+        db.vacuum().unwrap();
+        assert!(matches!(db.query(&format!("SELECT * FROM crabs AS OF LSN {lsn}")), Err(CrabDBError::InvalidInput(_))));
+    }
+
+    #[test]
+    pub fn test_crab_db_expires_rows_of_tables_with_a_ttl() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("crabs.db");
+        let options = CrabDbOptions::default().vacuum_interval(None).flush_interval(None);
+        let db = CrabDb::open(&path, options.clone()).unwrap();
+        db.execute("CREATE TABLE sessions (id INT PRIMARY KEY, touched_at TIMESTAMP) WITH (ttl_column = 'touched_at', ttl_seconds = 60)").unwrap();
+        let now = platform::clock().now_micros() as i64;
+        let minutes_ago = |minutes: i64| Value::Int64(now - minutes * 60_000_000);
+        db.execute_with("INSERT INTO sessions VALUES (1, $1), (2, $2), (3, NULL)", &[minutes_ago(0), minutes_ago(5)]).unwrap();
+        // a view counts expired rows until vacuum deletes them
+        db.execute("CREATE MATERIALIZED VIEW session_count AS SELECT COUNT(*) FROM sessions").unwrap();
+        let session_count = |db: &CrabDb| db.query("SELECT * FROM session_count").unwrap().fetch_all().unwrap()[0][0].clone();
+        assert_eq!(Value::Int64(3), session_count(&db));
+
+        let live = vec![vec![Value::Int32(1)], vec![Value::Int32(3)]];
+        assert_eq!(live, db.query("SELECT id FROM sessions ORDER BY id").unwrap().fetch_all().unwrap());
+        assert!(db.query("SELECT id FROM sessions WHERE id = 2").unwrap().fetch_all().unwrap().is_empty());
+        assert_eq!(0, db.execute("UPDATE sessions SET touched_at = NULL WHERE id = 2").unwrap());
+        // an expired row does not hold on to its key
+        db.execute_with("INSERT INTO sessions VALUES (2, $1)", &[minutes_ago(0)]).unwrap();
+        db.execute_with("INSERT INTO sessions VALUES (4, $1)", &[minutes_ago(2)]).unwrap();
+        assert_eq!(Value::Int64(5), session_count(&db));
+        assert_eq!(2, db.vacuum().unwrap().expired_rows_removed);
+        assert_eq!(0, db.vacuum().unwrap().expired_rows_removed);
+        assert_eq!(Value::Int64(3), session_count(&db));
+        let rows = db.query("SELECT id FROM sessions ORDER BY id").unwrap().fetch_all().unwrap();
+        assert_eq!(vec![vec![Value::Int32(1)], vec![Value::Int32(2)], vec![Value::Int32(3)]], rows);
+        drop(db);
+
+        let db = CrabDb::open(&path, options).unwrap();
+        assert_eq!(1, db.catalog().table("sessions").unwrap().ttl().unwrap().column);
+        db.execute_with("INSERT INTO sessions VALUES (5, $1)", &[minutes_ago(2)]).unwrap();
+        assert_eq!(3, db.query("SELECT id FROM sessions").unwrap().fetch_all().unwrap().len());
+        for bad in [
+            "CREATE TABLE bad (id INT, at TIMESTAMP) WITH (ttl_column = 'id')",
+            "CREATE TABLE bad (id INT, at TIMESTAMP) WITH (ttl_column = 'gone')",
+            "CREATE TABLE bad (id INT, at TIMESTAMP) WITH (ttl_seconds = 60)",
+            "CREATE TABLE bad (id INT, at TIMESTAMP) WITH (ttl_column = 'at', ttl_seconds = -1)",
+            "CREATE TABLE bad (id INT, at TIMESTAMP) WITH (fill = 'at')",
+        ] {
+            assert!(matches!(db.execute(bad), Err(CrabDBError::InvalidInput(_))), "{bad}");
+        }
+    }
+
+    #[test]
+    pub fn test_crab_db_keeps_materialized_views_up_to_date() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("crabs.db");
+        let options = CrabDbOptions::default().vacuum_interval(None).flush_interval(None);
+        let db = CrabDb::open(&path, options.clone()).unwrap();
+        db.execute("CREATE TABLE beaches (id INT, name VARCHAR)").unwrap();
+        db.execute("CREATE TABLE crabs (id INT, beach_id INT, weight INT)").unwrap();
+        db.execute("INSERT INTO beaches VALUES (1, 'sandy'), (2, 'rocky')").unwrap();
+        db.execute("INSERT INTO crabs VALUES (1, 1, 10), (2, 1, 20), (3, 2, 5)").unwrap();
+        assert_eq!(2, db.execute("CREATE MATERIALIZED VIEW beach_weights AS SELECT beach_id, COUNT(*) AS crabs, SUM(weight) AS weight FROM crabs GROUP BY beach_id").unwrap());
+        db.execute("CREATE MATERIALIZED VIEW crab_beaches (beach, name, crab) AS SELECT b.id, b.name, c.id FROM crabs c JOIN beaches b ON c.beach_id = b.id").unwrap();
+        db.execute("CREATE MATERIALIZED VIEW crab_count AS SELECT COUNT(*), SUM(weight) AS total, AVG(weight) FROM crabs").unwrap();
+        db.execute("CREATE MATERIALIZED VIEW heaviest AS SELECT id FROM crabs ORDER BY weight DESC LIMIT 1").unwrap();
+        let weights = |db: &CrabDb| db.query("SELECT * FROM beach_weights ORDER BY beach_id").unwrap().fetch_all().unwrap();
+        let crab_beaches = |db: &CrabDb| db.query("SELECT crab, name FROM crab_beaches ORDER BY crab").unwrap().fetch_all().unwrap();
+        let count = |db: &CrabDb| db.query("SELECT * FROM crab_count").unwrap().fetch_all().unwrap();
+        let heaviest = |db: &CrabDb| db.query("SELECT * FROM heaviest").unwrap().fetch_all().unwrap();
+        let stats = |count: i64, total: i64| vec![Value::Int64(count), Value::Int64(total), Value::Float64(total as f64 / count as f64)];
+        let weight = |beach: i32, crabs: i64, weight: i64| vec![Value::Int32(beach), Value::Int64(crabs), Value::Int64(weight)];
+        let crab = |crab: i32, beach: &str| vec![Value::Int32(crab), Value::Varchar(beach.into())];
+        assert_eq!(vec![weight(1, 2, 30), weight(2, 1, 5)], weights(&db));
+        assert_eq!(vec![crab(1, "sandy"), crab(2, "sandy"), crab(3, "rocky")], crab_beaches(&db));
+
+        // views with a key take in every commit; the crab moving beaches changes both
+        db.execute("UPDATE crabs SET beach_id = 2 WHERE id = 2").unwrap();
+        db.execute("INSERT INTO crabs VALUES (4, 3, 7)").unwrap();
+        db.execute("UPDATE beaches SET name = 'stony' WHERE id = 2").unwrap();
+        let txn = db.begin_transaction();
+        txn.execute("DELETE FROM crabs WHERE id = 1").unwrap();
+        txn.execute("INSERT INTO beaches VALUES (3, 'muddy')").unwrap();
+        assert_eq!(vec![weight(1, 1, 10), weight(2, 2, 25), weight(3, 1, 7)], txn.query("SELECT * FROM beach_weights ORDER BY beach_id").unwrap().fetch_all().unwrap());
+        txn.commit().unwrap();
+        let txn = db.begin_transaction();
+        txn.execute("DELETE FROM crabs").unwrap();
+        txn.rollback().unwrap();
+        assert_eq!(vec![weight(2, 2, 25), weight(3, 1, 7)], weights(&db));
+        assert_eq!(vec![crab(2, "stony"), crab(3, "stony"), crab(4, "muddy")], crab_beaches(&db));
+        // one aggregating the table without a key takes changed rows out of its sums and back in
+        assert_eq!(vec![stats(3, 32)], count(&db));
+        db.execute("INSERT INTO crabs VALUES (5, 3, 1)").unwrap();
+        assert_eq!(vec![stats(4, 33)], count(&db));
+        db.execute("UPDATE crabs SET weight = 50 WHERE id = 5").unwrap();
+        assert_eq!(vec![stats(4, 82)], count(&db));
+        // the others only change with REFRESH
+        assert_eq!(vec![vec![Value::Int32(2)]], heaviest(&db));
+        assert_eq!(1, db.execute("REFRESH MATERIALIZED VIEW heaviest").unwrap());
+        assert_eq!(vec![vec![Value::Int32(5)]], heaviest(&db));
+        db.execute("DELETE FROM crabs WHERE id = 5").unwrap();
+        assert_eq!(1, db.execute("REFRESH MATERIALIZED VIEW crab_count").unwrap());
+        assert_eq!(vec![stats(3, 32)], count(&db));
+        drop(db);
+
+        let db = CrabDb::open(&path, options).unwrap();
+        // a commit changing more keys than are refreshed one by one refreshes the whole view
+        let values: Vec<_> = (10..100).map(|id| format!("({id}, {id}, 1)")).collect();
+        db.execute(&format!("INSERT INTO crabs VALUES {}", values.join(", "))).unwrap();
+        db.execute("UPDATE crabs SET weight = 6 WHERE id = 3").unwrap();
+        let rows = weights(&db);
+        assert_eq!((92, &[weight(2, 2, 26), weight(3, 1, 7), weight(10, 1, 1)][..]), (rows.len(), &rows[..3]));
+        assert_eq!(3, crab_beaches(&db).len());
+        assert_eq!(vec![stats(93, 123)], count(&db));
+        for bad in [
+            "INSERT INTO beach_weights VALUES (5, 1, 1)",
+            "UPDATE crab_beaches SET name = 'sandy'",
+            "DELETE FROM crab_count",
+            "REFRESH MATERIALIZED VIEW crabs",
+            "CREATE VIEW crab_ids AS SELECT id FROM crabs",
+            "CREATE MATERIALIZED VIEW crab_ids (id, beach) AS SELECT id FROM crabs",
+        ] {
+            assert!(matches!(db.execute(bad), Err(CrabDBError::InvalidInput(_))), "{bad}");
+        }
+        db.execute("DELETE FROM crabs").unwrap();
+        assert_eq!(vec![vec![Value::Int64(0), Value::Null, Value::Null]], count(&db));
+    }
+
+    #[test]
+    pub fn test_crab_db_calls_row_triggers() {
+        let dir = TempDir::new().unwrap();
+        let db = CrabDb::open(dir.path().join("crabs.db"), CrabDbOptions::default().vacuum_interval(None).flush_interval(None)).unwrap();
+        db.execute("CREATE TABLE crabs (id INT, name VARCHAR, weight INT)").unwrap();
+        db.execute("CREATE TABLE audit (event VARCHAR, id INT, weight INT)").unwrap();
+        let audit = |event: &'static str| {
+            move |row: &mut TriggerRow<'_>| {
+                let values = row.new_row().or(row.old_row()).unwrap().to_vec();
+                row.execute("INSERT INTO audit VALUES ($1, $2, $3)", &[Value::Varchar(event.into()), values[0].clone(), values[2].clone()])?;
+                Ok(())
+            }
+        };
+        db.create_trigger("crabs", Trigger::new("audit_insert", TriggerTiming::After, TriggerEvent::Insert, audit("insert"))).unwrap();
+        db.create_trigger("crabs", Trigger::new("audit_update", TriggerTiming::After, TriggerEvent::Update, audit("update"))).unwrap();
+        db.create_trigger("crabs", Trigger::new("audit_delete", TriggerTiming::After, TriggerEvent::Delete, audit("delete"))).unwrap();
+        // crabs never lose weight, and ones without a name are not kept
+        db.create_trigger("crabs", Trigger::new("no_loss", TriggerTiming::Before, TriggerEvent::Update, |row| {
+            let (old, new) = (row.old_row().unwrap()[2].clone(), row.new_row().unwrap()[2].clone());
+            if new.as_i64() < old.as_i64() { row.set("weight", old) } else { Ok(()) }
+        })).unwrap();
+        db.create_trigger("crabs", Trigger::new("named", TriggerTiming::Before, TriggerEvent::Insert, |row| {
+            if row.new_row().unwrap()[1] == Value::Null { row.skip() } else { Ok(()) }
+        })).unwrap();
+        let audit_rows = |db: &CrabDb| db.query("SELECT * FROM audit").unwrap().fetch_all().unwrap();
+        let entry = |event: &str, id: i32, weight: i32| vec![Value::Varchar(event.into()), Value::Int32(id), Value::Int32(weight)];
+
+        assert_eq!(2, db.execute("INSERT INTO crabs VALUES (1, 'ferris', 10), (2, NULL, 5), (3, 'larry', 7)").unwrap());
+        assert_eq!(2, db.execute("UPDATE crabs SET weight = weight - 1").unwrap());
+        assert_eq!(1, db.execute("UPDATE crabs SET weight = 12 WHERE id = 1").unwrap());
+        assert_eq!(1, db.execute("DELETE FROM crabs WHERE id = 3").unwrap());
+        assert_eq!(vec![vec![Value::Int32(1), Value::Int32(12)]], db.query("SELECT id, weight FROM crabs").unwrap().fetch_all().unwrap());
+        assert_eq!(vec![
+            entry("insert", 1, 10), entry("insert", 3, 7), entry("update", 1, 10), entry("update", 3, 7), entry("update", 1, 12), entry("delete", 3, 7),
+        ], audit_rows(&db));
+
+        // COPY FROM calls them too
+        let csv = dir.path().join("crabs.csv");
+        std::fs::write(&csv, "4,pinchy,3\n5,,2\n").unwrap();
+        assert_eq!(1, db.import("crabs", &csv, CopyFormat::Csv { header: false }).unwrap());
+        assert_eq!(entry("insert", 4, 3), audit_rows(&db)[6]);
+
+        // a failing trigger fails its statement and undoes what the statement's triggers did
+        db.create_trigger("crabs", Trigger::new("light", TriggerTiming::Before, TriggerEvent::Insert, |row| {
+            match row.new_row().unwrap()[2].as_i64() {
+                Some(weight) if weight > 100 => Err(CrabDBError::ConstraintViolation { table: "crabs".into(), constraint: "light".into() }),
+                _ => Ok(()),
+            }
+        })).unwrap();
+        assert!(matches!(db.execute("INSERT INTO crabs VALUES (6, 'sebastian', 1), (7, 'heavy', 500)"), Err(CrabDBError::ConstraintViolation { .. })));
+        assert_eq!(7, audit_rows(&db).len());
+        assert_eq!(2, db.query("SELECT * FROM crabs").unwrap().fetch_all().unwrap().len());
+
+        // a trigger setting itself off runs into the depth limit
+        db.drop_trigger("crabs", "audit_insert").unwrap();
+        db.create_trigger("crabs", Trigger::new("breed", TriggerTiming::After, TriggerEvent::Insert, |row| {
+            row.execute("INSERT INTO crabs VALUES (8, 'baby', 1)", &[]).map(|_| ())
+        })).unwrap();
+        assert!(matches!(db.execute("INSERT INTO crabs VALUES (8, 'baby', 1)"), Err(CrabDBError::InvalidInput(_))));
+        assert_eq!(2, db.query("SELECT * FROM crabs").unwrap().fetch_all().unwrap().len());
+
+        db.execute("CREATE MATERIALIZED VIEW crab_count AS SELECT COUNT(*), SUM(weight) AS total, AVG(weight) FROM crabs").unwrap();
+        db.execute("CREATE MATERIALIZED VIEW heaviest AS SELECT id FROM crabs ORDER BY weight DESC LIMIT 1").unwrap();
+        for (table, name) in [("crab_count", "x"), ("missing", "x"), ("crabs", "BREED")] {
+            assert!(db.create_trigger(table, Trigger::new(name, TriggerTiming::After, TriggerEvent::Delete, |_| Ok(()))).is_err(), "{table}.{name}");
+        }
+        assert!(db.drop_trigger("crabs", "audit_insert").is_err());
+    }
+
+    #[test]
+    pub fn test_crab_db_calls_user_defined_functions() {
+        let dir = TempDir::new().unwrap();
+        let db = CrabDb::open(dir.path().join("crabs.db"), CrabDbOptions::default().vacuum_interval(None).flush_interval(None)).unwrap();
+        db.execute("CREATE TABLE crabs (id INT, name VARCHAR, beach_id INT, weight DOUBLE)").unwrap();
+        db.execute("INSERT INTO crabs VALUES (1, 'ferris', 1, 1.5), (2, 'sebastian', 1, 3.0), (3, NULL, 2, 2.0), (4, 'larry', 2, NULL)").unwrap();
+        db.create_function(ScalarUdf::new("grams", vec![DataType::Float64], DataType::Int64, |arguments| {
+            Ok(arguments[0].as_f64().map_or(Value::Null, |kilos| Value::Int64((kilos * 1000.0) as i64)))
+        })).unwrap();
+        db.create_function(ScalarUdf::new("label", vec![DataType::Int64, DataType::Varchar], DataType::Varchar, |arguments| {
+            let name = arguments[1].as_str().unwrap_or("unnamed");
+            Ok(Value::Varchar(format!("{}: {name}", arguments[0].as_i64().unwrap())))
+        })).unwrap();
+        db.create_aggregate(AggregateUdf::new("median", DataType::Float64, DataType::Float64, |values| {
+            let mut values: Vec<_> = values.iter().filter_map(Value::as_f64).collect();
+            values.sort_by(f64::total_cmp);
+            Ok(match values.len() {
+                0 => Value::Null,
+                n if n % 2 == 1 => Value::Float64(values[n / 2]),
+                n => Value::Float64((values[n / 2 - 1] + values[n / 2]) / 2.0),
+            })
+        })).unwrap();
+        let rows = |sql: &str| db.query(sql).unwrap().fetch_all().unwrap();
+
+        assert_eq!(vec![vec![Value::Varchar("2: sebastian".into())], vec![Value::Varchar("3: unnamed".into())]],
+            rows("SELECT label(id, name) FROM crabs WHERE grams(weight) >= 2000 ORDER BY id"));
+        assert_eq!(vec![
+            vec![Value::Int32(1), Value::Float64(2.25), Value::Int64(2250)],
+            vec![Value::Int32(2), Value::Float64(2.0), Value::Int64(2000)],
+        ], rows("SELECT beach_id, median(weight), grams(median(weight)) FROM crabs GROUP BY beach_id ORDER BY beach_id"));
+        assert_eq!(vec![vec![Value::Null]], rows("SELECT median(weight) FROM crabs WHERE id > 10"));
+        assert_eq!(2, db.execute("UPDATE crabs SET name = label(id, name) WHERE beach_id = 2").unwrap());
+        assert_eq!(vec![vec![Value::Varchar("3: unnamed".into())], vec![Value::Varchar("4: larry".into())]],
+            rows("SELECT name FROM crabs WHERE beach_id = 2 ORDER BY id"));
+
+        // an error of the function fails the statement
+        db.create_function(ScalarUdf::new("picky", vec![DataType::Int32], DataType::Int32, |arguments| match arguments[0] {
+            Value::Int32(4) => Err(CrabDBError::InvalidInput("no crab 4".into())),
+            ref value => Ok(value.clone()),
+        })).unwrap();
+        assert_eq!("no crab 4", db.execute("UPDATE crabs SET id = picky(id)").unwrap_err().to_string());
+        assert!(db.create_function(ScalarUdf::new("Grams", vec![], DataType::Int64, |_| Ok(Value::Null))).is_err());
+        db.drop_function("picky").unwrap();
+        assert!(matches!(db.execute("UPDATE crabs SET id = picky(id)"), Err(CrabDBError::InvalidInput(_))));
+    }
+
+    #[test]
+    pub fn test_crab_db_keeps_temporary_tables_to_their_session() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("crabs.db");
+        let temp_path = dir.path().join("crabs.db.temp");
+        let options = CrabDbOptions::default().vacuum_interval(None).flush_interval(None);
+        let db = CrabDb::open(&path, options.clone()).unwrap();
+        assert!(temp_path.exists());
+        db.execute("CREATE TABLE crabs (id INT)").unwrap();
+        db.execute("INSERT INTO crabs VALUES (1)").unwrap();
+
+        let session = db.session();
+        session.execute("CREATE TEMP TABLE crabs (id INT, name VARCHAR)").unwrap();
+        session.execute("CREATE TEMPORARY TABLE IF NOT EXISTS crabs (id INT)").unwrap();
+        session.execute("INSERT INTO crabs VALUES (2, 'pinchy'), (3, 'snappy')").unwrap();
+        session.execute("UPDATE crabs SET name = 'clawdia' WHERE id = 3").unwrap();
+        session.execute("ANALYZE crabs").unwrap();
+        let rows = session.query("SELECT * FROM crabs ORDER BY id").unwrap().fetch_all().unwrap();
+        assert_eq!(vec![vec![Value::Int32(2), Value::Varchar("pinchy".into())], vec![Value::Int32(3), Value::Varchar("clawdia".into())]], rows);
+        let txn = session.begin_transaction();
+        txn.execute("DELETE FROM crabs WHERE id = 2").unwrap();
+        txn.rollback().unwrap();
+        assert_eq!(2, session.query("SELECT * FROM crabs").unwrap().fetch_all().unwrap().len());
+        let table = db.catalog().temp_table(session.id(), "crabs").unwrap();
+        assert!(table.heap().first_page_id() >= TEMP_PAGE_ID_BASE);
+        assert!(db.catalog().table_statistics("crabs").is_none());
+        drop(table);
+
+        // the permanent table of the name is what other sessions see
+        assert_eq!(vec![vec![Value::Int32(1)]], db.query("SELECT * FROM crabs").unwrap().fetch_all().unwrap());
+        assert!(db.session().query("SELECT name FROM crabs").is_err());
+        assert!(session.execute("CREATE TEMP TABLE keyed (id INT PRIMARY KEY)").is_err());
+        assert!(session.execute("CREATE TEMP TABLE named (id INT)").is_ok());
+        assert!(session.execute("CREATE INDEX named_id ON named (id)").is_err());
+
+        let id = session.id();
+        let dropped_page_ids: HashSet<_> = db.catalog().temp_tables().iter().flat_map(|table| table.heap().page_ids()).collect();
+        drop(session);
+        assert!(db.catalog().temp_table(id, "crabs").is_none());
+        assert!(db.catalog().temp_tables().is_empty());
+        let session = db.session();
+        session.execute("CREATE TEMP TABLE lobsters (id INT)").unwrap();
+        let page_ids = db.catalog().temp_table(session.id(), "lobsters").unwrap().heap().page_ids();
+        assert!(page_ids.is_subset(&dropped_page_ids));
+        session.execute("INSERT INTO lobsters VALUES (7)").unwrap();
+        assert_eq!(vec![vec![Value::Int32(7)]], session.query("SELECT * FROM lobsters").unwrap().fetch_all().unwrap());
+        drop(session);
+
+        // temporary tables of the database itself last until it closes
+        db.execute("CREATE TEMP TABLE shrimps (id INT)").unwrap();
+        db.execute("INSERT INTO shrimps VALUES (1)").unwrap();
+        drop(db);
+        assert!(!temp_path.exists());
+        let db = CrabDb::open(&path, options).unwrap();
+        assert!(db.query("SELECT * FROM shrimps").is_err());
+        assert_eq!(vec![vec![Value::Int32(1)]], db.query("SELECT * FROM crabs").unwrap().fetch_all().unwrap());
+    }
+
+    #[test]
+    pub fn test_crab_db_options_are_validated_at_open() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("crabs.db");
+        let open_error = |options: CrabDbOptions| match CrabDb::open(&path, options) {
+            Err(e) => e,
+            Ok(_) => panic!("the options should have been rejected"),
+        };
+        assert!(matches!(open_error(CrabDbOptions::default().pool_size(0)), CrabDBError::InvalidInput(_)));
+        assert_eq!(ErrorCode::InvalidInput, open_error(CrabDbOptions::default().page_size(8192)).code());
+        assert_eq!(ErrorCode::InvalidInput, open_error(CrabDbOptions::default().lru_k(0)).code());
+        assert_eq!(ErrorCode::InvalidInput, open_error(CrabDbOptions::default().disk_workers(0)).code());
+        assert_eq!(ErrorCode::InvalidInput, open_error(CrabDbOptions::default().flush_dirty_ratio(1.5)).code());
+        assert_eq!(ErrorCode::InvalidInput, open_error(CrabDbOptions::default().temp_pool_size(0)).code());
+        assert_eq!(ErrorCode::InvalidInput, open_error(CrabDbOptions::default().deadlock_detection_interval(Some(Duration::ZERO))).code());
+        assert!(!path.exists());
+
+        let options = CrabDbOptions::default()
+            .pool_size(8)
+            .eviction_policy(ReplacerPolicy::Sieve)
+            .sync_mode(SyncMode::Off)
+            .wal_dir(dir.path().join("wal"))
+            .disk_workers(2)
+            .vacuum_interval(None)
+            .flush_interval(Some(Duration::from_millis(10)))
+            .flush_dirty_ratio(0.0);
+        {
+            let db = CrabDb::open(&path, options.clone()).unwrap();
+            db.execute("CREATE TABLE crabs (id INT NOT NULL)").unwrap();
+            db.execute("INSERT INTO crabs VALUES (1), (2), (3)").unwrap();
+        }
+        assert!(dir.path().join("wal").join("crabs.db.wal").exists());
+        assert!(!dir.path().join("crabs.db.wal").exists());
+        let db = CrabDb::open(&path, options).unwrap();
+        assert_eq!(3, db.execute("SELECT * FROM crabs").unwrap());
+    }
+
+    #[test]
+    pub fn test_crab_db_warms_up_from_the_pages_it_held() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("crabs.db");
+        let options = CrabDbOptions::default().pool_size(16).vacuum_interval(None);
+        let (crabs_page, hot_pages) = {
+            let db = CrabDb::open(&path, options.clone()).unwrap();
+            db.execute("CREATE TABLE crabs (id BIGINT NOT NULL)").unwrap();
+            db.execute("CREATE TABLE shrimp (id BIGINT NOT NULL, name VARCHAR)").unwrap();
+            db.execute("INSERT INTO crabs VALUES (1), (2)").unwrap();
+            for id in 0..300 {
+                db.execute(&format!("INSERT INTO shrimp VALUES ({id}, '{}')", "x".repeat(200))).unwrap();
+            }
+            // the crabs are what is really used
+            let crabs_page = db.catalog().table("crabs").unwrap().heap().first_page_id();
+            for _ in 0..2 {
+                db.bpm.fetch_page(crabs_page).unwrap();
+                db.bpm.unpin_page(crabs_page, false).unwrap();
+            }
+            let hot_pages = db.bpm.hot_pages();
+            db.close().unwrap();
+            (crabs_page, hot_pages)
+        };
+        assert_eq!(crabs_page, hot_pages[0]);
+        assert_eq!(hot_pages, read_warmup_file(&dir.path().join("crabs.db.warmup")));
+
+        // opening reads every table through the pool, which pushes the crabs out again
+        let cold = CrabDb::open(&path, options.clone().warmup(false)).unwrap();
+        assert!(!cold.bpm.hot_pages().contains(&crabs_page));
+        drop(cold);
+        let db = CrabDb::open(&path, options).unwrap();
+        let resident = db.bpm.hot_pages();
+        assert!(hot_pages[..8].iter().all(|page_id| resident.contains(page_id)), "{hot_pages:?} warmed up to {resident:?}");
+        assert_eq!(2, db.execute("SELECT * FROM crabs").unwrap());
+    }
+
+    #[test]
+    pub fn test_crab_db_streams_committed_changes() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("crabs.db");
+        let options = CrabDbOptions::default().capture_changes(true);
+        let position = {
+            let db = CrabDb::open(&path, options.clone()).unwrap();
+            db.execute("CREATE TABLE crabs (id BIGINT NOT NULL, name VARCHAR)").unwrap();
+            db.execute("CREATE TABLE shrimp (id BIGINT NOT NULL)").unwrap();
+            let mut changes = db.subscribe("crabs").unwrap();
+            db.execute("INSERT INTO crabs VALUES (1, 'ferris'), (2, 'sebastian')").unwrap();
+            db.execute("INSERT INTO shrimp VALUES (1)").unwrap();
+            let txn = db.begin_transaction();
+            txn.execute("DELETE FROM crabs WHERE id = 2").unwrap();
+            txn.rollback().unwrap();
+            let txn = db.begin_transaction();
+            txn.execute("UPDATE crabs SET name = 'crabby' WHERE id = 1; DELETE FROM crabs WHERE id = 2").unwrap();
+
+            let row = |id: i64, name: &str| Some(vec![Value::Int64(id), Value::Varchar(name.into())]);
+            let inserted: Vec<_> = (0..2).map(|_| changes.recv().unwrap()).collect();
+            assert_eq!(vec![(RowChangeKind::Insert, row(1, "ferris")), (RowChangeKind::Insert, row(2, "sebastian"))],
+                inserted.into_iter().map(|change| (change.kind, change.row)).collect::<Vec<_>>());
+            // the open transaction's changes show up once it commits
+            assert_eq!(None, changes.recv_timeout(Duration::from_millis(20)).unwrap());
+            txn.commit().unwrap();
+            let update = changes.recv().unwrap();
+            assert_eq!((RowChangeKind::Update, row(1, "crabby")), (update.kind, update.row));
+            let position = changes.position();
+            db.execute("INSERT INTO crabs VALUES (3, 'larry')").unwrap();
+            assert!(matches!(db.subscribe("lobsters"), Err(CrabDBError::InvalidInput(_))));
+            position
+        };
+
+        // a stream resumed from a position picks up after it, across a restart
+        let db = CrabDb::open(&path, options).unwrap();
+        let mut changes = db.subscribe_after("crabs", position).unwrap();
+        let delete = changes.recv().unwrap();
+        assert_eq!((RowChangeKind::Delete, None), (delete.kind, delete.row));
+        assert_eq!(RowChangeKind::Insert, changes.recv().unwrap().kind);
+        assert_eq!(None, changes.recv_timeout(Duration::from_millis(20)).unwrap());
+        let db = CrabDb::open(dir.path().join("plain.db"), CrabDbOptions::default()).unwrap();
+        db.execute("CREATE TABLE crabs (id BIGINT NOT NULL)").unwrap();
+        assert!(matches!(db.subscribe("crabs"), Err(CrabDBError::InvalidInput(_))));
+    }
+
+    #[test]
+    pub fn test_crab_db_times_out_waiting_for_a_row_lock() {
+        let options = CrabDbOptions::default().vacuum_interval(None).flush_interval(None).lock_timeout(Some(Duration::from_millis(300)));
+        let db = CrabDb::open_in_memory(options).unwrap();
+        db.execute("CREATE TABLE crabs (id BIGINT NOT NULL, name VARCHAR)").unwrap();
+        db.execute("INSERT INTO crabs VALUES (1, 'ferris'), (2, 'sebastian')").unwrap();
+        let holder = db.begin_transaction();
+        holder.execute("UPDATE crabs SET name = 'crabby' WHERE id = 1").unwrap();
+
+        thread::scope(|scope| {
+            let waiter = scope.spawn(|| {
+                let txn = db.begin_transaction();
+                // a row nobody holds is free to write
+                txn.execute("UPDATE crabs SET name = 'sebby' WHERE id = 2")?;
+                txn.execute("UPDATE crabs SET name = 'pinchy' WHERE id = 1")
+            });
+            let mut waits = db.lock_waits().locks;
+            while waits.is_empty() && !waiter.is_finished() {
+                thread::sleep(Duration::from_millis(5));
+                waits = db.lock_waits().locks;
+            }
+            assert_eq!(1, waits.len());
+            assert_eq!(vec![holder.txn().id()], waits[0].held_by.iter().map(|(txn_id, _)| *txn_id).collect::<Vec<_>>());
+            assert_eq!(ErrorCode::LockTimeout, waiter.join().unwrap().unwrap_err().code());
+        });
+        // the transaction that timed out was rolled back, and let go of the row it wrote
+        assert!(db.lock_waits().locks.is_empty());
+        holder.execute("UPDATE crabs SET name = 'sebastian jr' WHERE id = 2").unwrap();
+        holder.commit().unwrap();
+        let rows = db.query("SELECT name FROM crabs ORDER BY id").unwrap().fetch_all().unwrap();
+        assert_eq!(vec![vec![Value::Varchar("crabby".into())], vec![Value::Varchar("sebastian jr".into())]], rows);
+
+        // key-value stores lock their entries the same way
+        let habitats = db.kv_store("habitats").unwrap();
+        let txn = db.begin_transaction();
+        habitats.put(txn.txn(), b"ferris", b"rust").unwrap();
+        txn.commit().unwrap();
+        let holder = db.begin_transaction();
+        habitats.put(holder.txn(), b"ferris", b"sea").unwrap();
+        let waiter = db.begin_transaction();
+        assert_eq!(ErrorCode::LockTimeout, habitats.put(waiter.txn(), b"ferris", b"sand").unwrap_err().code());
+    }
+
+    #[test]
+    pub fn test_crab_db_keeps_the_key_of_a_row_whose_delete_rolls_back() {
+        let db = CrabDb::open_in_memory(CrabDbOptions::default().vacuum_interval(None).flush_interval(None)).unwrap();
+        db.execute("CREATE TABLE t (id BIGINT PRIMARY KEY, v BIGINT)").unwrap();
+        db.execute("INSERT INTO t VALUES (5, 1)").unwrap();
+        let rows = |sql: &str| db.query(sql).unwrap().fetch_all().unwrap();
+        let original = vec![vec![Value::Int64(5), Value::Int64(1)]];
+
+        let deleter = db.begin_transaction();
+        assert_eq!(1, deleter.execute("DELETE FROM t WHERE id = 5").unwrap());
+        thread::scope(|scope| {
+            // the insert waits for the delete to commit or roll back before taking the key
+            let inserter = scope.spawn(|| db.execute("INSERT INTO t VALUES (5, 2)"));
+            while db.lock_waits().locks.is_empty() && !inserter.is_finished() {
+                thread::sleep(Duration::from_millis(5));
+            }
+            deleter.rollback().unwrap();
+            assert!(matches!(inserter.join().unwrap().unwrap_err(), CrabDBError::ConstraintViolation { .. }));
+        });
+        assert_eq!(original, rows("SELECT id, v FROM t"));
+        assert_eq!(original, rows("SELECT id, v FROM t WHERE id = 5"));
+
+        // a transaction may take over the key of a row it deleted itself, and gives it back
+        // when it rolls back
+        let txn = db.begin_transaction();
+        txn.execute("DELETE FROM t WHERE id = 5").unwrap();
+        txn.execute("INSERT INTO t VALUES (5, 3)").unwrap();
+        assert_eq!(vec![vec![Value::Int64(5), Value::Int64(3)]], txn.query("SELECT id, v FROM t WHERE id = 5").unwrap().fetch_all().unwrap());
+        txn.rollback().unwrap();
+        assert_eq!(original, rows("SELECT id, v FROM t"));
+        assert_eq!(original, rows("SELECT id, v FROM t WHERE id = 5"));
+        assert!(matches!(db.execute("INSERT INTO t VALUES (5, 4)").unwrap_err(), CrabDBError::ConstraintViolation { .. }));
+    }
+
+    #[test]
+    pub fn test_crab_db_breaks_deadlocks() {
+        let options = CrabDbOptions::default().vacuum_interval(None).flush_interval(None).deadlock_detection_interval(Some(Duration::from_millis(20)));
+        let db = CrabDb::open_in_memory(options).unwrap();
+        db.execute("CREATE TABLE crabs (id BIGINT NOT NULL, name VARCHAR)").unwrap();
+        db.execute("INSERT INTO crabs VALUES (1, 'ferris'), (2, 'sebastian')").unwrap();
+
+        // Each transaction writes one row, then the one the other wrote.
+        let barrier = Barrier::new(2);
+        let run = |first: i64, second: i64| -> CrabDbResult<()> {
+            let txn = db.begin_transaction();
+            txn.execute(&format!("UPDATE crabs SET name = 'crab {first}' WHERE id = {first}"))?;
+            barrier.wait();
+            txn.execute(&format!("UPDATE crabs SET name = 'crab {first}' WHERE id = {second}"))?;
+            txn.commit()
+        };
+        let results = thread::scope(|scope| {
+            let threads = [scope.spawn(|| run(1, 2)), scope.spawn(|| run(2, 1))];
+            threads.map(|thread| thread.join().unwrap())
+        });
+        let deadlocked: Vec<_> = results.iter().filter_map(|result| result.as_ref().err()).map(CrabDBError::code).collect();
+        assert_eq!(vec![ErrorCode::Deadlock], deadlocked);
+        // the survivor wrote both rows
+        let names = db.query("SELECT name FROM crabs").unwrap().fetch_all().unwrap();
+        assert_eq!(names[0], names[1]);
+    }
+
+    #[test]
+    pub fn test_crab_db_stores_pages_and_log_as_its_options_say() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("crabs.db");
+        let options = CrabDbOptions::default()
+            .vacuum_interval(None)
+            .flush_interval(None)
+            .warmup(false)
+            .direct_io(true)
+            .compression(PageCompression::Lz4)
+            .encryption(Arc::new(KeyRing::new(1, [5; 32])));
+        {
+            let db = CrabDb::open(&path, options.clone()).unwrap();
+            db.execute("CREATE TABLE crabs (id BIGINT NOT NULL, secret VARCHAR)").unwrap();
+            db.execute("INSERT INTO crabs VALUES (1, 'ferris hides the treasure under rock nine')").unwrap();
+            db.close().unwrap();
+        }
+        for file in [path.clone(), path.with_extension("db.wal")] {
+            let bytes = std::fs::read(&file).unwrap();
+            assert!(!bytes.is_empty());
+            assert!(!bytes.windows(9).any(|window| window == b"rock nine"), "{} holds plaintext", file.display());
+        }
+
+        let db = CrabDb::open(&path, options).unwrap();
+        let rows = db.query("SELECT secret FROM crabs").unwrap().fetch_all().unwrap();
+        assert_eq!(vec![vec![Value::Varchar("ferris hides the treasure under rock nine".into())]], rows);
+        drop(db);
+        assert!(CrabDb::open(&path, CrabDbOptions::default()).is_err());
+    }
+}
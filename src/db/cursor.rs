@@ -0,0 +1,209 @@
+use std::mem::ManuallyDrop;
+use std::sync::Arc;
+
+use crate::concurrency::transaction::Transaction;
+use crate::concurrency::transaction_manager::TransactionManager;
+use crate::execution::executor::{Executor, ExecutorContext};
+use crate::sql::physical_plan::PhysicalPlan;
+use crate::types::schema::Schema;
+use crate::types::value::Value;
+use crate::types::CrabDbResult;
+
+use super::row_iterator::RowIterator;
+
+// An executor tree with the plan and context it borrows. Those are kept behind raw
+// pointers, so they stay where they are and nothing else can reach them while the tree
+// points into them; the tree is dropped before them.
+struct OpenQuery {
+    executor: ManuallyDrop<Box<dyn Executor>>,
+    ctx: *mut ExecutorContext,
+    plan: *mut PhysicalPlan,
+}
+
+impl OpenQuery {
+    fn new(plan: PhysicalPlan, ctx: ExecutorContext) -> CrabDbResult<Self> {
+        let (plan, ctx) = (Box::into_raw(Box::new(plan)), Box::into_raw(Box::new(ctx)));
+        // SAFETY: both pointers come from live boxes that are only freed on drop, after the
+        // executor, and are only ever read through.
+        let executor: Box<dyn Executor + '_> = unsafe { (*plan).build(&*ctx) };
+        // SAFETY: the executor borrows nothing but the plan and context, which outlive it.
+        let executor = unsafe { std::mem::transmute::<Box<dyn Executor + '_>, Box<dyn Executor>>(executor) };
+        let mut query = OpenQuery { executor: ManuallyDrop::new(executor), ctx, plan };
+        query.executor.init()?;
+        Ok(query)
+    }
+}
+
+impl Drop for OpenQuery {
+    fn drop(&mut self) {
+        // SAFETY: the executor is dropped once, here, before what it borrows, and the
+        // pointers came from `Box::into_raw` in `new`.
+        unsafe {
+            ManuallyDrop::drop(&mut self.executor);
+            drop(Box::from_raw(self.ctx));
+            drop(Box::from_raw(self.plan));
+        }
+    }
+}
+
+enum Source {
+    // A query still running: rows are pulled from its executors as they are fetched.
+    Query(OpenQuery),
+    // Rows of a statement that already ran to completion, such as an INSERT.
+    Rows(RowIterator),
+    Done,
+}
+
+// The rows of a statement, with the schema they follow. A query is run as its rows are
+// fetched, a batch at a time with `fetch_next` or one by one as an iterator, so a result of
+// any size never has to fit in memory; scans only pin a page while reading from it. The
+// query runs in its transaction until its rows run out or the cursor is dropped, so read it
+// before committing a transaction it came from. A cursor from `CrabDb::query` ends its own
+// transaction then. After an error there are no more rows.
+pub struct Cursor {
+    schema: Schema,
+    source: Source,
+    // The transaction the cursor ends once it is done, if it is its own.
+    owned_txn: Option<(Arc<TransactionManager>, Arc<Transaction>)>,
+}
+
+impl Cursor {
+    pub(crate) fn open(plan: PhysicalPlan, ctx: ExecutorContext) -> CrabDbResult<Self> {
+        let query = OpenQuery::new(plan, ctx)?;
+        let schema = query.executor.output_schema().clone();
+        Ok(Cursor { schema, source: Source::Query(query), owned_txn: None })
+    }
+
+    // Commits `txn` once the rows run out, or aborts it on an error or if the cursor is
+    // dropped first. Only for transactions that ran nothing but the query.
+    pub(crate) fn ending(mut self, txn_manager: Arc<TransactionManager>, txn: Arc<Transaction>) -> Self {
+        self.owned_txn = Some((txn_manager, txn));
+        self
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    // Up to `n` more rows: fewer only once the rows run out, after which there are none.
+    pub fn fetch_next(&mut self, n: usize) -> CrabDbResult<Vec<Vec<Value>>> {
+        let mut rows = Vec::with_capacity(n.min(1024));
+        while rows.len() < n {
+            match self.pull() {
+                Ok(Some(row)) => rows.push(row),
+                Ok(None) => break,
+                Err(e) => {
+                    // The error says more than a failure to abort could.
+                    let _ = self.finish(false);
+                    return Err(e);
+                },
+            }
+        }
+        Ok(rows)
+    }
+
+    // Every row left.
+    pub fn fetch_all(mut self) -> CrabDbResult<Vec<Vec<Value>>> {
+        self.fetch_next(usize::MAX)
+    }
+
+    fn pull(&mut self) -> CrabDbResult<Option<Vec<Value>>> {
+        let row = match &mut self.source {
+            Source::Query(query) => query.executor.next()?.map(|row| row.values),
+            Source::Rows(rows) => rows.next(),
+            Source::Done => return Ok(None),
+        };
+        if row.is_none() {
+            self.finish(true)?;
+        }
+        Ok(row)
+    }
+
+    // Stops the query, then ends the transaction if the cursor owns it.
+    fn finish(&mut self, commit: bool) -> CrabDbResult<()> {
+        self.source = Source::Done;
+        match self.owned_txn.take() {
+            Some((txn_manager, txn)) if commit => txn_manager.commit(&txn),
+            Some((txn_manager, txn)) => txn_manager.abort(&txn),
+            None => Ok(()),
+        }
+    }
+}
+
+impl From<RowIterator> for Cursor {
+    fn from(rows: RowIterator) -> Self {
+        Cursor { schema: rows.schema().clone(), source: Source::Rows(rows), owned_txn: None }
+    }
+}
+
+impl Iterator for Cursor {
+    type Item = CrabDbResult<Vec<Value>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.fetch_next(1).map(|mut rows| rows.pop()).transpose()
+    }
+}
+
+impl Drop for Cursor {
+    fn drop(&mut self) {
+        let _ = self.finish(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use crate::db::crab_db::CrabDb;
+    use crate::db::options::CrabDbOptions;
+    use crate::types::value::Value;
+
+    #[test]
+    pub fn test_cursor_fetches_rows_in_batches() {
+        let dir = TempDir::new().unwrap();
+        let db = CrabDb::open(dir.path().join("crabs.db"), CrabDbOptions::default()).unwrap();
+        db.execute("CREATE TABLE crabs (id BIGINT NOT NULL, name VARCHAR)").unwrap();
+        let values: Vec<_> = (0..5000).map(|id| format!("({id}, 'crab {id}')")).collect();
+        db.execute(&format!("INSERT INTO crabs VALUES {}", values.join(", "))).unwrap();
+
+        // The query runs in a transaction of its own until its rows run out, and sees the
+        // table as it was when it started.
+        let mut cursor = db.query("SELECT id FROM crabs").unwrap();
+        assert_eq!("id", cursor.schema().column(0).name());
+        assert_eq!(1, db.txn_manager().active_transactions());
+        let mut ids = cursor.fetch_next(2000).unwrap();
+        assert_eq!(2000, ids.len());
+        db.execute("INSERT INTO crabs VALUES (5000, 'latecomer')").unwrap();
+        ids.extend(cursor.fetch_next(2000).unwrap());
+        let rest = cursor.fetch_next(2000).unwrap();
+        assert_eq!(1000, rest.len());
+        ids.extend(rest);
+        assert_eq!(0, db.txn_manager().active_transactions());
+        assert!(cursor.fetch_next(2000).unwrap().is_empty());
+        let mut ids: Vec<_> = ids.iter().map(|row| row[0].as_i64().unwrap()).collect();
+        ids.sort();
+        assert_eq!((0..5000).collect::<Vec<_>>(), ids);
+
+        // Dropping a cursor early ends its transaction too.
+        let mut cursor = db.query("SELECT name FROM crabs WHERE id >= 4000").unwrap();
+        assert_eq!(10, cursor.fetch_next(10).unwrap().len());
+        drop(cursor);
+        assert_eq!(0, db.txn_manager().active_transactions());
+
+        // Statements before the last run to completion; one that is not a query gives its
+        // rows at once.
+        let cursor = db.query("DELETE FROM crabs WHERE id >= 10; SELECT id FROM crabs WHERE id < 2").unwrap();
+        assert_eq!(2, cursor.count());
+        let mut cursor = db.query("INSERT INTO crabs VALUES (10, 'ferris')").unwrap();
+        assert_eq!(vec![vec![Value::Int64(1)]], cursor.fetch_next(10).unwrap());
+
+        // A cursor from a transaction leaves it open, and sees what it wrote.
+        let txn = db.begin_transaction();
+        txn.execute("INSERT INTO crabs VALUES (11, 'sebastian')").unwrap();
+        let names = txn.query("SELECT name FROM crabs WHERE id >= 10").unwrap().fetch_all().unwrap();
+        assert_eq!(2, names.len());
+        assert!(txn.query("SELECT missing FROM crabs").is_err());
+        txn.commit().unwrap();
+        assert_eq!(12, db.execute("SELECT * FROM crabs").unwrap());
+    }
+}
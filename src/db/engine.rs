@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::buffer_pool::eviction::timestamp_clock::LogicalClock;
+use crate::buffer_pool::shared_buffer_pool::SharedBufferPool;
+use crate::storage::disk::disk_scheduler::DiskScheduler;
+use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::crab_db::CrabDb;
+use super::options::{DatabaseQuota, EngineOptions};
+
+// What a database opened by an engine shares with the others.
+#[derive(Clone, Copy)]
+pub(crate) struct EngineShare<'a> {
+    pub(crate) pool: &'a Arc<SharedBufferPool>,
+    pub(crate) disk_scheduler: &'a DiskScheduler,
+    pub(crate) priority: u8,
+}
+
+// Serves several named databases from one directory, each in a file of its own, NAME.db,
+// with its own log and catalog. Their pages share one buffer pool and their reads and writes
+// one set of disk workers. Each database holds at most its quota of the pool's frames; once
+// the pool is full, pages of lower-priority databases are evicted first.
+pub struct CrabDbEngine {
+    dir: PathBuf,
+    options: EngineOptions,
+    pool: Arc<SharedBufferPool>,
+    // Owns the disk workers the databases share; it has no pages of its own.
+    disk_scheduler: DiskScheduler,
+    databases: Mutex<HashMap<String, Arc<CrabDb>>>,
+}
+
+impl CrabDbEngine {
+    // Serves the databases in `dir`, creating it if it does not exist. None of them is open
+    // until `open_database`.
+    pub fn open(dir: impl AsRef<Path>, mut options: EngineOptions) -> CrabDbResult<Self> {
+        options.validate()?;
+        options.database.replacer_clock.get_or_insert_with(|| Arc::new(LogicalClock::default()));
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| CrabDBError::io(format!("Failed to create database directory {}", dir.display()), e))?;
+        let pool = Arc::new(SharedBufferPool::new(options.pool_size));
+        let disk_scheduler = DiskScheduler::new(Arc::new(MemoryDiskManager::new()), options.disk_workers);
+        Ok(CrabDbEngine { dir, options, pool, disk_scheduler, databases: Mutex::new(HashMap::new()) })
+    }
+
+    pub fn buffer_pool(&self) -> &Arc<SharedBufferPool> {
+        &self.pool
+    }
+
+    // Opens the database `name`, creating it if it does not exist, with `quota` of the
+    // buffer pool. Names are letters, digits, '_' and '-'.
+    pub fn open_database(&self, name: &str, quota: DatabaseQuota) -> CrabDbResult<Arc<CrabDb>> {
+        check_name(name)?;
+        let mut databases = self.databases.lock().unwrap();
+        if databases.contains_key(name) {
+            return Err(CrabDBError::InvalidInput(format!("Database {name} is already open")));
+        }
+        let options = self.options.database.clone();
+        let options = match quota.frames {
+            Some(frames) => options.pool_size(frames),
+            None => options,
+        };
+        let share = EngineShare { pool: &self.pool, disk_scheduler: &self.disk_scheduler, priority: quota.priority };
+        let db = Arc::new(CrabDb::open_in_engine(&self.path(name), options, share)?);
+        databases.insert(name.to_string(), db.clone());
+        Ok(db)
+    }
+
+    // The open database `name`.
+    pub fn database(&self, name: &str) -> CrabDbResult<Arc<CrabDb>> {
+        self.databases.lock().unwrap().get(name).cloned()
+            .ok_or_else(|| CrabDBError::InvalidInput(format!("Database {name} is not open")))
+    }
+
+    // Names of the open databases, sorted.
+    pub fn database_names(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.databases.lock().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    // Closes the database `name`, giving its frames back to the pool. Fails, leaving it
+    // open, while anything else still holds it.
+    pub fn close_database(&self, name: &str) -> CrabDbResult<()> {
+        let mut databases = self.databases.lock().unwrap();
+        let Some(db) = databases.remove(name) else {
+            return Err(CrabDBError::InvalidInput(format!("Database {name} is not open")));
+        };
+        match Arc::try_unwrap(db) {
+            Ok(db) => db.close(),
+            Err(db) => {
+                databases.insert(name.to_string(), db);
+                Err(CrabDBError::InvalidInput(format!("Database {name} is still in use")))
+            },
+        }
+    }
+
+    // Closes every open database; see `close_database`. The first failure is returned once
+    // the rest are closed.
+    pub fn close(self) -> CrabDbResult<()> {
+        let mut result = Ok(());
+        for name in self.database_names() {
+            let closed = self.close_database(&name);
+            if result.is_ok() {
+                result = closed;
+            }
+        }
+        result
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.db"))
+    }
+}
+
+fn check_name(name: &str) -> CrabDbResult<()> {
+    let valid = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if name.is_empty() || !valid {
+        return Err(CrabDBError::InvalidInput(format!("{name:?} is not a database name: use letters, digits, '_' and '-'")));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use crate::db::options::{CrabDbOptions, DatabaseQuota, EngineOptions};
+    use super::CrabDbEngine;
+
+    fn fill(db: &crate::CrabDb, rows: usize) {
+        db.execute("CREATE TABLE crabs (id BIGINT NOT NULL, name VARCHAR)").unwrap();
+        let values: Vec<_> = (0..rows).map(|id| format!("({id}, '{}')", "crab".repeat(50))).collect();
+        db.execute(&format!("INSERT INTO crabs VALUES {}", values.join(", "))).unwrap();
+    }
+
+    #[test]
+    pub fn test_engine_databases_share_the_buffer_pool() {
+        let dir = TempDir::new().unwrap();
+        let database = CrabDbOptions::default().vacuum_interval(None).flush_interval(None).warmup(false);
+        let options = EngineOptions::default().pool_size(64).database(database);
+        let engine = CrabDbEngine::open(dir.path(), options.clone()).unwrap();
+        assert!(engine.open_database("../escape", DatabaseQuota::default()).is_err());
+
+        let low = engine.open_database("low", DatabaseQuota::default().priority(0)).unwrap();
+        let high = engine.open_database("high", DatabaseQuota::default().priority(1)).unwrap();
+        let capped = engine.open_database("capped", DatabaseQuota::default().frames(8).priority(2)).unwrap();
+        assert!(engine.open_database("low", DatabaseQuota::default()).is_err());
+        assert_eq!(vec!["capped", "high", "low"], engine.database_names());
+
+        // A quota holds however much the database reads.
+        fill(&capped, 500);
+        assert_eq!(500, capped.execute("SELECT * FROM crabs").unwrap());
+        assert!(capped.bpm().frames_held() <= 8);
+
+        // Once the pool is full, the lower priority gives way.
+        let held = |db: &crate::CrabDb| db.bpm().frames_held();
+        fill(&low, 500);
+        let low_held = held(&low);
+        fill(&high, 500);
+        assert_eq!(500, high.execute("SELECT * FROM crabs").unwrap());
+        assert!(held(&low) < low_held);
+        assert_eq!(64, held(&low) + held(&high) + held(&capped));
+        // and makes room for itself out of its own pages
+        let high_held = held(&high);
+        assert_eq!(500, low.execute("SELECT * FROM crabs").unwrap());
+        assert_eq!(high_held, held(&high));
+
+        // Databases are files of their own, and closing one frees its frames.
+        drop(low);
+        let free = engine.buffer_pool().free_frames();
+        engine.close_database("low").unwrap();
+        assert!(engine.buffer_pool().free_frames() > free);
+        assert!(engine.close_database("high").is_err());
+        drop((high, capped));
+        engine.close().unwrap();
+        let engine = CrabDbEngine::open(dir.path(), options).unwrap();
+        let low = engine.open_database("low", DatabaseQuota::default()).unwrap();
+        assert_eq!(500, low.execute("SELECT * FROM crabs").unwrap());
+        assert!(engine.database("high").is_err());
+    }
+}
@@ -0,0 +1,624 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::catalog::system_catalog::Catalog;
+use crate::catalog::table_info::{MaterializedView, Oid, TableInfo};
+use crate::execution::aggregation_executor::{AggregateExpression, AggregateFunction};
+use crate::execution::executor::{execute, ExecutorContext, Row};
+use crate::execution::expression::{arithmetic, type_name, ArithmeticOp, CompareOp, Expression};
+use crate::execution::hash_key::group_key;
+use crate::execution::insert_executor::insert_index_entry;
+use crate::execution::join::JoinType;
+use crate::sql::binder::{parse, Binder};
+use crate::sql::logical_plan::{BoundStatement, LogicalPlan};
+use crate::sql::planner::Planner;
+use crate::storage::common::PageId;
+use crate::storage::table::tuple::Tuple;
+use crate::types::schema::{Column, Schema};
+use crate::types::value::{DataType, Value};
+use crate::types::{CrabDBError, CrabDbResult};
+
+// The most keys a commit refreshes a view for one by one. Past that it refreshes the whole
+// view, which reads each table once instead of matching every row against every key.
+pub const MAX_INCREMENTAL_KEYS: usize = 64;
+
+// A column of a view that holds, in every row, the value one column of each table scanned
+// by its query had in the rows the view row came from. A row of such a table then only
+// affects the rows of the view holding the key it had before or after a change, and those
+// rows can be computed again from the rows of the tables holding the same keys alone.
+//
+// Views get one when their query joins its tables with inner joins, on the key or not, as
+// long as WHERE or ON clauses equate the key columns of all of them, and groups, if at all,
+// by the key. A LIMIT, an outer join or a global aggregate leave a view without a key. Those
+// that aggregate one table into COUNT, SUM and AVG are kept up to date by `Aggregation`; the
+// rest only change with REFRESH MATERIALIZED VIEW.
+struct ViewKey {
+    column: usize,
+    // The table and key column of each scan, in the order of `LogicalPlan::scans`.
+    sources: Vec<(Oid, usize)>,
+}
+
+// Which columns of the scans of a plan are known to hold equal values: a union-find over
+// every column of every scan, numbered in scan order.
+#[derive(Default)]
+struct Equalities {
+    parents: Vec<usize>,
+    // Where the columns of each scan start.
+    scan_starts: Vec<usize>,
+}
+
+impl Equalities {
+    fn add_scan(&mut self, width: usize) -> usize {
+        let start = self.parents.len();
+        self.parents.extend(start..start + width);
+        self.scan_starts.push(start);
+        start
+    }
+
+    fn find(&mut self, mut column: usize) -> usize {
+        while self.parents[column] != column {
+            self.parents[column] = self.parents[self.parents[column]];
+            column = self.parents[column];
+        }
+        column
+    }
+
+    // Merges the columns each `a = b` conjunct of `predicate` compares, given which scan
+    // column each column of the row it reads is.
+    fn add_predicate(&mut self, predicate: &Expression, columns: &[Option<usize>]) {
+        match predicate {
+            Expression::And(left, right) => {
+                self.add_predicate(left, columns);
+                self.add_predicate(right, columns);
+            },
+            Expression::Compare(CompareOp::Eq, left, right) => {
+                if let (Expression::Column(left), Expression::Column(right)) = (left.as_ref(), right.as_ref()) {
+                    if let (Some(left), Some(right)) = (columns[*left], columns[*right]) {
+                        let (left, right) = (self.find(left), self.find(right));
+                        self.parents[left] = right;
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // For each column of the plan's output, the scan column it passes on unchanged, if any.
+    // None if the plan cannot have a key.
+    fn columns_of(&mut self, plan: &LogicalPlan) -> Option<Vec<Option<usize>>> {
+        let passed_on = |expression: &Expression, columns: &[Option<usize>]| match expression {
+            Expression::Column(i) => columns[*i],
+            _ => None,
+        };
+        match plan {
+            LogicalPlan::Scan { table } => {
+                let width = table.schema().column_count();
+                let start = self.add_scan(width);
+                Some((start..start + width).map(Some).collect())
+            },
+            LogicalPlan::Values { schema, .. } => Some(vec![None; schema.column_count()]),
+            LogicalPlan::Filter { input, predicate } => {
+                let columns = self.columns_of(input)?;
+                self.add_predicate(predicate, &columns);
+                Some(columns)
+            },
+            LogicalPlan::Projection { input, expressions, .. } => {
+                let columns = self.columns_of(input)?;
+                Some(expressions.iter().map(|expression| passed_on(expression, &columns)).collect())
+            },
+            LogicalPlan::Join { left, right, join_type: JoinType::Inner, predicate, .. } => {
+                let mut columns = self.columns_of(left)?;
+                columns.extend(self.columns_of(right)?);
+                if let Some(predicate) = predicate {
+                    self.add_predicate(predicate, &columns);
+                }
+                Some(columns)
+            },
+            LogicalPlan::Aggregate { input, group_by, aggregates, .. } if !group_by.is_empty() => {
+                let columns = self.columns_of(input)?;
+                let mut output: Vec<_> = group_by.iter().map(|expression| passed_on(expression, &columns)).collect();
+                output.extend(aggregates.iter().map(|_| None));
+                Some(output)
+            },
+            LogicalPlan::Sort { input, .. } => self.columns_of(input),
+            _ => None,
+        }
+    }
+}
+
+fn view_key(plan: &LogicalPlan) -> Option<ViewKey> {
+    let mut equalities = Equalities::default();
+    let columns = equalities.columns_of(plan)?;
+    let scans = plan.scans();
+    if scans.is_empty() {
+        return None;
+    }
+    for (column, scan_column) in columns.iter().enumerate() {
+        let Some(scan_column) = scan_column else {
+            continue;
+        };
+        let root = equalities.find(*scan_column);
+        let mut sources = Vec::with_capacity(scans.len());
+        for (scan, table) in scans.iter().enumerate() {
+            let start = equalities.scan_starts[scan];
+            let key = (0..table.schema().column_count()).find(|&i| equalities.find(start + i) == root);
+            match key {
+                Some(key) => sources.push((table.oid(), key)),
+                None => break,
+            }
+        }
+        if sources.len() == scans.len() {
+            return Some(ViewKey { column, sources });
+        }
+    }
+    None
+}
+
+// A view whose query filters and projects the rows of one table into a single row of COUNT,
+// SUM and AVG, with no GROUP BY. The rows its table's changes take away and add are taken
+// from and added to the running count of each aggregate, and the sum of those of SUM and
+// AVG, kept in state columns after the view's own; a commit never reads the table again.
+struct Aggregation<'a> {
+    // The rows the aggregates read.
+    input: &'a LogicalPlan,
+    aggregates: &'a [AggregateExpression],
+    // The aggregates' values, in order.
+    schema: &'a Schema,
+    // The view's columns, computed from the aggregates' values, unless they are those.
+    output: Option<&'a [Expression]>,
+}
+
+impl Aggregation<'_> {
+    fn of(plan: &LogicalPlan) -> Option<Aggregation<'_>> {
+        let (aggregate, output) = match plan {
+            LogicalPlan::Projection { input, expressions, .. } => (input.as_ref(), Some(expressions.as_slice())),
+            plan => (plan, None),
+        };
+        let LogicalPlan::Aggregate { input, group_by, aggregates, schema } = aggregate else {
+            return None;
+        };
+        let summed = aggregates.iter().all(|aggregate| matches!(
+            aggregate.function,
+            AggregateFunction::CountStar | AggregateFunction::Count | AggregateFunction::Sum | AggregateFunction::Avg
+        ));
+        (group_by.is_empty() && summed && reads_one_table(input)).then_some(Aggregation { input, aggregates, schema, output })
+    }
+
+    // The state columns: the count of each aggregate, followed for SUM and AVG by the sum.
+    fn state_columns(&self) -> Vec<Column> {
+        let mut columns = Vec::new();
+        for (i, aggregate) in self.aggregates.iter().enumerate() {
+            columns.push(Column::new(format!("${i}_count"), DataType::Int64));
+            match aggregate.function {
+                AggregateFunction::Sum => columns.push(Column::new(format!("${i}_sum"), self.schema.column(i).data_type())),
+                AggregateFunction::Avg => columns.push(Column::new(format!("${i}_sum"), DataType::Float64)),
+                _ => (),
+            }
+        }
+        columns
+    }
+
+    // The state of the aggregates over no rows.
+    fn empty_state(&self) -> Vec<Value> {
+        self.aggregates.iter()
+            .flat_map(|aggregate| {
+                let mut state = vec![Value::Int64(0)];
+                state.resize(self.state_width(aggregate), Value::Null);
+                state
+            })
+            .collect()
+    }
+
+    // Adds what `rows`, rows of `input`, give the aggregates to `state`, or with a `sign` of
+    // -1 takes it away. Values are counted and summed as the aggregates themselves do.
+    fn accumulate(&self, state: &mut [Value], rows: &[Row], sign: i64) -> CrabDbResult<()> {
+        let op = if sign < 0 { ArithmeticOp::Subtract } else { ArithmeticOp::Add };
+        for row in rows {
+            let mut column = 0;
+            for aggregate in self.aggregates {
+                let value = match aggregate.function {
+                    AggregateFunction::CountStar => Value::Boolean(true),
+                    _ => aggregate.argument.evaluate(&row.values)?,
+                };
+                let count = match &state[column] {
+                    Value::Int64(count) if !value.is_null() => count + sign,
+                    Value::Int64(count) => *count,
+                    _ => return Err(CrabDBError::Corruption("The state of a materialized view is damaged".to_string())),
+                };
+                state[column] = Value::Int64(count);
+                let sum = match aggregate.function {
+                    AggregateFunction::Sum if !value.is_null() => {
+                        let value = match value {
+                            Value::Int32(i) => Value::Int64(i as i64),
+                            other => other,
+                        };
+                        let previous = match std::mem::replace(&mut state[column + 1], Value::Null) {
+                            Value::Null => Value::Int64(0),
+                            previous => previous,
+                        };
+                        Some(arithmetic(op, previous, value)?)
+                    },
+                    AggregateFunction::Avg if !value.is_null() => {
+                        let Some(value) = value.as_f64() else {
+                            return Err(CrabDBError::InvalidInput(format!("Cannot average {}", type_name(&value))));
+                        };
+                        Some(Value::Float64(state[column + 1].as_f64().unwrap_or(0.0) + sign as f64 * value))
+                    },
+                    _ => None,
+                };
+                if let Some(sum) = sum {
+                    // Once no value is left the sum starts over, rather than carry rounding on.
+                    state[column + 1] = if count == 0 { Value::Null } else { sum };
+                }
+                column += self.state_width(aggregate);
+            }
+        }
+        Ok(())
+    }
+
+    fn state_width(&self, aggregate: &AggregateExpression) -> usize {
+        match aggregate.function {
+            AggregateFunction::Sum | AggregateFunction::Avg => 2,
+            _ => 1,
+        }
+    }
+
+    // The row of the view `state` gives, state columns included.
+    fn row(&self, state: Vec<Value>) -> CrabDbResult<Vec<Value>> {
+        let mut values = Vec::with_capacity(self.aggregates.len());
+        let mut column = 0;
+        for aggregate in self.aggregates {
+            let count = match state[column] {
+                Value::Int64(count) => count,
+                _ => 0,
+            };
+            values.push(match aggregate.function {
+                AggregateFunction::Sum if count > 0 => state[column + 1].clone(),
+                AggregateFunction::Avg if count > 0 => Value::Float64(state[column + 1].as_f64().unwrap_or(0.0) / count as f64),
+                AggregateFunction::Sum | AggregateFunction::Avg => Value::Null,
+                _ => Value::Int64(count),
+            });
+            column += self.state_width(aggregate);
+        }
+        let mut row = match self.output {
+            Some(output) => output.iter().map(|expression| expression.evaluate(&values)).collect::<CrabDbResult<Vec<_>>>()?,
+            None => values,
+        };
+        row.extend(state);
+        Ok(row)
+    }
+}
+
+// Whether `plan` only filters and projects the rows of a single scan.
+fn reads_one_table(plan: &LogicalPlan) -> bool {
+    match plan {
+        LogicalPlan::Scan { .. } => true,
+        LogicalPlan::Filter { input, .. } | LogicalPlan::Projection { input, .. } => reads_one_table(input),
+        _ => false,
+    }
+}
+
+// Runs `plan`, filters and projections over one scan, on `rows` of the scanned table.
+fn run_on(ctx: &ExecutorContext, plan: &LogicalPlan, rows: &[Vec<Value>]) -> CrabDbResult<Vec<Row>> {
+    fn copy(plan: &LogicalPlan, rows: &[Vec<Value>]) -> LogicalPlan {
+        match plan {
+            LogicalPlan::Scan { table } => LogicalPlan::Values {
+                rows: rows.iter().map(|row| row.iter().cloned().map(Expression::Constant).collect()).collect(),
+                schema: table.schema().clone(),
+            },
+            LogicalPlan::Filter { input, predicate } => {
+                LogicalPlan::Filter { input: Box::new(copy(input, rows)), predicate: predicate.clone() }
+            },
+            LogicalPlan::Projection { input, expressions, schema } => {
+                LogicalPlan::Projection { input: Box::new(copy(input, rows)), expressions: expressions.clone(), schema: schema.clone() }
+            },
+            _ => unreachable!("an aggregation reads one table through filters and projections"),
+        }
+    }
+    if rows.is_empty() {
+        return Ok(Vec::new());
+    }
+    let plan = Planner::new(ctx.catalog()).plan_query(copy(plan, rows));
+    let mut executor = plan.build(ctx);
+    execute(executor.as_mut())
+}
+
+// Whether the value at `column` is one of `keys`, NULL included.
+fn key_predicate(column: usize, keys: &[Value]) -> Expression {
+    keys.iter()
+        .map(|key| match key {
+            Value::Null => Expression::IsNull(Box::new(Expression::Column(column))),
+            key => Expression::compare(CompareOp::Eq, Expression::Column(column), Expression::Constant(key.clone())),
+        })
+        .reduce(Expression::or)
+        .unwrap_or(Expression::Constant(Value::Boolean(false)))
+}
+
+// Narrows each scan of `plan` to the rows whose key column holds one of `keys`.
+fn restrict(plan: LogicalPlan, sources: &mut std::slice::Iter<(Oid, usize)>, keys: &[Value]) -> LogicalPlan {
+    let mut restrict_input = |input: Box<LogicalPlan>| Box::new(restrict(*input, sources, keys));
+    match plan {
+        LogicalPlan::Scan { table } => {
+            let (_, column) = sources.next().expect("a view key has a column for every scan");
+            LogicalPlan::Filter { input: Box::new(LogicalPlan::Scan { table }), predicate: key_predicate(*column, keys) }
+        },
+        LogicalPlan::Values { .. } => plan,
+        LogicalPlan::Filter { input, predicate } => LogicalPlan::Filter { input: restrict_input(input), predicate },
+        LogicalPlan::Projection { input, expressions, schema } => {
+            LogicalPlan::Projection { input: restrict_input(input), expressions, schema }
+        },
+        LogicalPlan::Join { left, right, join_type, predicate, schema } => {
+            let left = restrict_input(left);
+            LogicalPlan::Join { left, right: restrict_input(right), join_type, predicate, schema }
+        },
+        LogicalPlan::Aggregate { input, group_by, aggregates, schema } => {
+            LogicalPlan::Aggregate { input: restrict_input(input), group_by, aggregates, schema }
+        },
+        LogicalPlan::Sort { input, keys } => LogicalPlan::Sort { input: restrict_input(input), keys },
+        LogicalPlan::Limit { input, limit, offset } => LogicalPlan::Limit { input: restrict_input(input), limit, offset },
+    }
+}
+
+// The query of a view, bound against the catalog as it is now.
+fn bind(catalog: &Catalog, name: &str, query: &str) -> CrabDbResult<LogicalPlan> {
+    let corrupt = || CrabDBError::Corruption(format!("Materialized view {name} does not hold a query"));
+    let [statement] = &parse(query)?[..] else {
+        return Err(corrupt());
+    };
+    match Binder::new(catalog).bind(statement)? {
+        BoundStatement::Select(plan) => Ok(plan),
+        _ => Err(corrupt()),
+    }
+}
+
+// The aggregation of the view kept in `table`, if it keeps state for one.
+fn aggregation<'a>(table: &TableInfo, view: &MaterializedView, plan: &'a LogicalPlan) -> CrabDbResult<Option<Aggregation<'a>>> {
+    if view.state_columns == 0 {
+        return Ok(None);
+    }
+    match Aggregation::of(plan) {
+        Some(aggregation) if aggregation.state_columns().len() == view.state_columns => Ok(Some(aggregation)),
+        _ => Err(CrabDBError::Corruption(format!("Materialized view {} keeps state its query has no use for", table.name()))),
+    }
+}
+
+// Creates the table of the view `name`, with the state columns an `Aggregation` needs after
+// the columns of `schema`, and fills it in the transaction of `ctx`. Returns how many rows
+// that is.
+pub(crate) fn create(ctx: &ExecutorContext, name: &str, schema: Schema, mut view: MaterializedView) -> CrabDbResult<u64> {
+    let plan = bind(ctx.catalog(), name, &view.query)?;
+    let mut columns = schema.columns().to_vec();
+    if let Some(aggregation) = Aggregation::of(&plan) {
+        let state_columns = aggregation.state_columns();
+        view.state_columns = state_columns.len();
+        columns.extend(state_columns);
+    }
+    let table = ctx.catalog().create_materialized_view(name, Schema::new(columns), view.clone())?;
+    refresh(ctx, &table, &view)
+}
+
+// Runs the query of the view kept in `table` in the transaction of `ctx` and replaces the
+// rows of the table with its result. Returns how many rows that is.
+pub fn refresh(ctx: &ExecutorContext, table: &TableInfo, view: &MaterializedView) -> CrabDbResult<u64> {
+    let plan = bind(ctx.catalog(), table.name(), &view.query)?;
+    let Some(aggregation) = aggregation(table, view, &plan)? else {
+        return replace_rows(ctx, table, plan, None);
+    };
+    // Rows are read from the heap rather than scanned, so expired rows of a table with a TTL
+    // count until vacuum deletes them and maintenance takes them out.
+    let source = aggregation.input.scans()[0];
+    let rows = ctx.txn_manager().scan(ctx.txn(), source.heap())
+        .map(|entry| entry.and_then(|(_, tuple)| tuple.values(source.schema())))
+        .collect::<CrabDbResult<Vec<_>>>()?;
+    let mut state = aggregation.empty_state();
+    aggregation.accumulate(&mut state, &run_on(ctx, aggregation.input, &rows)?, 1)?;
+    delete_rows(ctx, table, None)?;
+    insert_rows(ctx, table, &[aggregation.row(state)?])?;
+    Ok(1)
+}
+
+// Replaces the rows of `table`, or with `keys` only those whose key is one of them, with the
+// rows `plan` produces.
+fn replace_rows(ctx: &ExecutorContext, table: &TableInfo, plan: LogicalPlan, keys: Option<(usize, &[Value])>) -> CrabDbResult<u64> {
+    delete_rows(ctx, table, keys)?;
+    let plan = Planner::new(ctx.catalog()).plan_query(plan);
+    let mut executor = plan.build(ctx);
+    let rows: Vec<_> = execute(executor.as_mut())?.into_iter().map(|row| row.values).collect();
+    insert_rows(ctx, table, &rows)?;
+    Ok(rows.len() as u64)
+}
+
+// Deletes the rows of `table`, or with `keys` only those whose key is one of them.
+fn delete_rows(ctx: &ExecutorContext, table: &TableInfo, keys: Option<(usize, &[Value])>) -> CrabDbResult<()> {
+    let keys = keys.map(|(column, keys)| {
+        (column, keys.iter().map(|key| group_key(std::slice::from_ref(key))).collect::<HashSet<_>>())
+    });
+    let replaced = |tuple: &Tuple| -> CrabDbResult<bool> {
+        let Some((column, keys)) = &keys else {
+            return Ok(true);
+        };
+        let values = tuple.values(table.schema())?;
+        Ok(keys.contains(&group_key(&values[*column..=*column])))
+    };
+    // The latest rows are deleted along with the visible ones, so a row another transaction
+    // wrote since the snapshot makes the delete fail with a conflict: two transactions
+    // refreshing the same rows would otherwise both add them.
+    let mut rids = BTreeSet::new();
+    for entry in table.heap().iter().chain(ctx.txn_manager().scan(ctx.txn(), table.heap())) {
+        let (rid, tuple) = entry?;
+        if replaced(&tuple)? {
+            rids.insert(rid);
+        }
+    }
+    for rid in rids {
+        ctx.txn_manager().delete(ctx.txn(), table.heap(), rid)?;
+    }
+    Ok(())
+}
+
+fn insert_rows(ctx: &ExecutorContext, table: &TableInfo, rows: &[Vec<Value>]) -> CrabDbResult<()> {
+    let indexes = ctx.catalog().indexes_of(table);
+    for row in rows {
+        let tuple = Tuple::from_values(table.schema(), row)?;
+        let rid = ctx.txn_manager().insert(ctx.txn(), table.heap(), &tuple)?;
+        for index in &indexes {
+            insert_index_entry(ctx, table, index, &tuple.values(table.schema())?, rid)?;
+        }
+    }
+    Ok(())
+}
+
+// Brings the views reading tables the transaction of `ctx` changed up to date, before it
+// commits. Views are maintained oldest first, so the changes to a view reach the views
+// reading it. Those with a key (see `ViewKey`) have the rows of the keys the changed rows had
+// before and after computed again, or all of them past `MAX_INCREMENTAL_KEYS`; those with an
+// `Aggregation` take the changed rows out of their aggregates as they were and put them back
+// in as they are. The others are left for REFRESH MATERIALIZED VIEW.
+pub(crate) fn maintain(ctx: &ExecutorContext) -> CrabDbResult<()> {
+    for (table, view) in ctx.catalog().materialized_views() {
+        let sources: HashMap<PageId, Arc<TableInfo>> = view.sources.iter()
+            .filter_map(|&oid| ctx.catalog().table_by_oid(oid))
+            .map(|source| (source.heap().first_page_id(), source))
+            .collect();
+        let changed: Vec<_> = ctx.txn().changed_rows().into_iter()
+            .filter(|(heap, ..)| sources.contains_key(heap))
+            .collect();
+        if changed.is_empty() {
+            continue;
+        }
+        let plan = bind(ctx.catalog(), table.name(), &view.query)?;
+        if let Some(aggregation) = aggregation(&table, &view, &plan)? {
+            let width = table.schema().column_count() - view.state_columns;
+            let Some(entry) = ctx.txn_manager().scan(ctx.txn(), table.heap()).next() else {
+                refresh(ctx, &table, &view)?;
+                continue;
+            };
+            let mut state = entry?.1.values(table.schema())?.split_off(width);
+            let (mut before, mut after) = (Vec::new(), Vec::new());
+            for (heap, rid, inserted) in changed {
+                let source = &sources[&heap];
+                if let Some(tuple) = ctx.txn_manager().get(ctx.txn(), source.heap(), rid)? {
+                    after.push(tuple.values(source.schema())?);
+                }
+                if !inserted {
+                    if let Some(tuple) = ctx.txn_manager().get_committed(ctx.txn(), source.heap(), rid)? {
+                        before.push(tuple.values(source.schema())?);
+                    }
+                }
+            }
+            aggregation.accumulate(&mut state, &run_on(ctx, aggregation.input, &before)?, -1)?;
+            aggregation.accumulate(&mut state, &run_on(ctx, aggregation.input, &after)?, 1)?;
+            delete_rows(ctx, &table, None)?;
+            insert_rows(ctx, &table, &[aggregation.row(state)?])?;
+            continue;
+        }
+        let Some(key) = view_key(&plan) else {
+            continue;
+        };
+
+        let mut keys = Vec::new();
+        let mut seen = HashSet::new();
+        for (heap, rid, inserted) in changed {
+            let source = &sources[&heap];
+            let after = ctx.txn_manager().get(ctx.txn(), source.heap(), rid)?;
+            let before = match inserted {
+                true => None,
+                false => ctx.txn_manager().get_committed(ctx.txn(), source.heap(), rid)?,
+            };
+            for tuple in after.into_iter().chain(before) {
+                let values = tuple.values(source.schema())?;
+                for &(_, column) in key.sources.iter().filter(|(oid, _)| *oid == source.oid()) {
+                    if seen.insert(group_key(&values[column..=column])) {
+                        keys.push(values[column].clone());
+                    }
+                }
+            }
+        }
+        if keys.len() > MAX_INCREMENTAL_KEYS {
+            replace_rows(ctx, &table, plan, None)?;
+        } else if !keys.is_empty() {
+            let plan = restrict(plan, &mut key.sources.iter(), &keys);
+            replace_rows(ctx, &table, plan, Some((key.column, &keys)))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::catalog::system_catalog::Catalog;
+    use crate::sql::binder::{parse, Binder};
+    use crate::sql::logical_plan::BoundStatement;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use crate::types::schema::{Column, Schema};
+    use crate::types::value::DataType;
+    use super::{view_key, Aggregation};
+
+    #[test]
+    pub fn test_view_key_follows_equi_joins_and_groups() {
+        let bpm = Arc::new(BufferPoolManager::new(8, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(8, 2))));
+        let catalog = Catalog::open(bpm).unwrap();
+        let crabs = catalog.create_table("crabs", Schema::new(vec![Column::new("id", DataType::Int32), Column::new("beach_id", DataType::Int32)])).unwrap();
+        let beaches = catalog.create_table("beaches", Schema::new(vec![Column::new("id", DataType::Int32), Column::new("name", DataType::Varchar)])).unwrap();
+        let key = |sql: &str| {
+            let BoundStatement::Select(plan) = Binder::new(&catalog).bind(&parse(sql).unwrap()[0]).unwrap() else { unreachable!() };
+            view_key(&plan).map(|key| (key.column, key.sources))
+        };
+        assert_eq!(Some((0, vec![(crabs.oid(), 0)])), key("SELECT * FROM crabs"));
+        assert_eq!(Some((0, vec![(crabs.oid(), 1)])), key("SELECT beach_id, COUNT(*) FROM crabs GROUP BY beach_id HAVING COUNT(*) > 1"));
+        assert_eq!(
+            Some((1, vec![(crabs.oid(), 1), (beaches.oid(), 0)])),
+            key("SELECT b.name, b.id, COUNT(*) FROM crabs c, beaches b WHERE c.beach_id = b.id GROUP BY b.name, b.id")
+        );
+        assert_eq!(
+            Some((0, vec![(crabs.oid(), 0), (crabs.oid(), 1)])),
+            key("SELECT a.id FROM crabs a JOIN crabs b ON a.id = b.beach_id")
+        );
+        for no_key in [
+            "SELECT COUNT(*) FROM crabs",
+            "SELECT id FROM crabs LIMIT 1",
+            "SELECT c.id, b.name FROM crabs c JOIN beaches b ON c.beach_id = b.id",
+            "SELECT b.id FROM crabs c LEFT JOIN beaches b ON c.beach_id = b.id",
+            "SELECT id + 1 FROM crabs",
+            "SELECT 1",
+        ] {
+            assert_eq!(None, key(no_key), "{no_key}");
+        }
+    }
+
+    #[test]
+    pub fn test_aggregation_sums_one_table_into_one_row() {
+        let bpm = Arc::new(BufferPoolManager::new(8, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(8, 2))));
+        let catalog = Catalog::open(bpm).unwrap();
+        catalog.create_table("crabs", Schema::new(vec![Column::new("id", DataType::Int32), Column::new("weight", DataType::Int32)])).unwrap();
+        let state = |sql: &str| {
+            let BoundStatement::Select(plan) = Binder::new(&catalog).bind(&parse(sql).unwrap()[0]).unwrap() else { unreachable!() };
+            Aggregation::of(&plan).map(|aggregation| {
+                aggregation.state_columns().iter().map(|column| (column.name().to_string(), column.data_type())).collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(Some(vec![("$0_count".to_string(), DataType::Int64)]), state("SELECT COUNT(*) FROM crabs"));
+        assert_eq!(
+            Some(vec![
+                ("$0_count".to_string(), DataType::Int64),
+                ("$0_sum".to_string(), DataType::Int64),
+                ("$1_count".to_string(), DataType::Int64),
+                ("$1_sum".to_string(), DataType::Float64),
+            ]),
+            state("SELECT SUM(weight) + 1, AVG(weight) FROM (SELECT weight FROM crabs WHERE id > 2) AS heavy")
+        );
+        for no_aggregation in [
+            "SELECT MAX(weight) FROM crabs",
+            "SELECT id, COUNT(*) FROM crabs GROUP BY id",
+            "SELECT COUNT(*) FROM crabs HAVING COUNT(*) > 1",
+            "SELECT COUNT(*) FROM crabs a, crabs b",
+            "SELECT id FROM crabs",
+        ] {
+            assert_eq!(None, state(no_aggregation), "{no_aggregation}");
+        }
+    }
+}
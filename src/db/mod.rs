@@ -0,0 +1,12 @@
+pub mod async_crab_db;
+pub mod backup;
+pub mod change_stream;
+pub mod crab_db;
+pub mod cursor;
+pub mod engine;
+pub mod materialized_view;
+pub mod options;
+pub mod replication;
+pub mod row_iterator;
+pub mod session;
+pub mod transaction;
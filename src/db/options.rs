@@ -0,0 +1,421 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::buffer_pool::eviction::policy::ReplacerPolicy;
+use crate::buffer_pool::eviction::replacer::Replacer;
+use crate::buffer_pool::eviction::timestamp_clock::TimestampClock;
+use crate::concurrency::lock_manager::LockManager;
+use crate::concurrency::transaction::IsolationLevel;
+use crate::platform;
+use crate::storage::common::PAGE_SIZE;
+use crate::storage::disk::file_disk_manager::FileDiskManagerOptions;
+use crate::storage::disk::page_codec::PageCompression;
+use crate::storage::encryption::KeyRing;
+use crate::types::{CrabDBError, CrabDbResult};
+use crate::wal::log_manager::{LogManagerOptions, SyncMode};
+
+// How a database is set up. Start from the defaults and change what you need:
+//   CrabDbOptions::default().pool_size(256).sync_mode(SyncMode::Off)
+// Nothing is checked until `CrabDb::open`, which rejects options that cannot work. Where
+// there are no threads, as under wasm, nothing runs in the background by default.
+#[derive(Debug, Clone)]
+pub struct CrabDbOptions {
+    // Bytes per page of the database file, chosen when the file is created and recorded in
+    // its catalog; a file is only opened with the page size it was created with. Page layouts
+    // are built for PAGE_SIZE, the only size supported so far.
+    pub page_size: usize,
+    // Frames in the buffer pool.
+    pub pool_size: usize,
+    // Frames in the buffer pool of the temporary storage, which holds temporary tables apart
+    // from the database's pages.
+    pub temp_pool_size: usize,
+    // The replacer that picks the pages the buffer pool evicts.
+    pub eviction_policy: ReplacerPolicy,
+    // The clock LRU-K replacers timestamp accesses with, e.g. a `MonotonicClock` so their
+    // periods are in time and their timestamps line up with traces. Shared by every
+    // database given the same one; if None, each replacer counts its own accesses.
+    pub replacer_clock: Option<Arc<dyn TimestampClock>>,
+    pub sync_mode: SyncMode,
+    // Where the write-ahead log lives; next to the database file if None.
+    pub wal_dir: Option<PathBuf>,
+    // Threads reading and writing pages of the database file.
+    pub disk_workers: usize,
+    // Whether the database file is opened with O_DIRECT, keeping its pages out of the OS
+    // page cache, where that is supported; it is opened buffered elsewhere.
+    pub direct_io: bool,
+    // How pages are compressed on disk. Each page records how it was written, so this can
+    // change between opens and pages pick it up as they are written back.
+    pub compression: PageCompression,
+    // Seals pages, the write-ahead log, its archive and temporary tables with the active
+    // key of the ring; everything is stored in the clear if None. A database written with
+    // a key cannot be opened without it.
+    pub encryption: Option<Arc<KeyRing>>,
+    // Isolation level of every transaction, including the one each statement run outside
+    // a transaction gets.
+    pub isolation: IsolationLevel,
+    // How long a transaction waits for a lock before it is aborted; for as long as it takes
+    // if None.
+    pub lock_timeout: Option<Duration>,
+    // How often transactions waiting for locks are checked for deadlocks, aborting one
+    // transaction of each; never if None, leaving deadlocked transactions to the lock
+    // timeout.
+    pub deadlock_detection_interval: Option<Duration>,
+    // How often vacuum runs in the background; never if None, leaving it to `CrabDb::vacuum`.
+    pub vacuum_interval: Option<Duration>,
+    // How far back queries can read the database with AS OF: vacuum keeps the versions they
+    // need for this long. If None, only as far back as running transactions and pinned
+    // snapshots (see `CrabDb::pin_snapshot`) keep them.
+    pub history_retention: Option<Duration>,
+    // Fraction of a page, from 0 to 1, below which background vacuum runs move the tuples
+    // off it and free it, as `CrabDb::vacuum_full` does; they leave pages be if None.
+    pub compact_below: Option<f64>,
+    // How often the background writer looks for dirty pages to write back; never if None.
+    pub flush_interval: Option<Duration>,
+    // Fraction of dirty frames, from 0 to 1, at which the background writer starts writing.
+    pub flush_dirty_ratio: f64,
+    // How often a checkpoint is taken in the background, recycling the log recovery no
+    // longer needs; otherwise only by `CrabDb::checkpoint`, on open and on close. Log a
+    // replica has yet to apply is only kept while it is connected.
+    pub checkpoint_interval: Option<Duration>,
+    // Where segments of the write-ahead log are archived for point-in-time recovery; not
+    // archived if None. Archiving logs every page the database writes in full.
+    pub wal_archive_dir: Option<PathBuf>,
+    // How often new log is archived in the background; otherwise only by
+    // `CrabDb::archive_wal` and on close.
+    pub wal_archive_interval: Option<Duration>,
+    // Whether closing the database records which pages the buffer pool held, hottest
+    // first, in a file next to it, for the next open to read them back in.
+    pub warmup: bool,
+    // Whether commits log the rows they changed, for `CrabDb::subscribe`. Costs a log
+    // flush per commit that changed a table, and the log is never recycled, so streams can
+    // resume from any position.
+    pub capture_changes: bool,
+    // Whether read replicas can follow the database through `CrabDb::serve_replicas`. Like
+    // archiving, it logs every page the database writes in full, so the log carries every
+    // change for replicas to redo.
+    pub replication: bool,
+    // Page reads and writes and log syncs slower than this are counted in the metrics and
+    // reported as tracing events, with the page and how long they took; none are if None.
+    pub slow_io_threshold: Option<Duration>,
+}
+
+impl Default for CrabDbOptions {
+    fn default() -> Self {
+        CrabDbOptions {
+            page_size: PAGE_SIZE,
+            pool_size: 1024,
+            temp_pool_size: 256,
+            eviction_policy: ReplacerPolicy::lru_k(2),
+            replacer_clock: None,
+            sync_mode: SyncMode::Full,
+            wal_dir: None,
+            disk_workers: 1,
+            direct_io: false,
+            compression: PageCompression::None,
+            encryption: None,
+            isolation: IsolationLevel::SnapshotIsolation,
+            lock_timeout: None,
+            deadlock_detection_interval: platform::HAS_THREADS.then_some(Duration::from_millis(100)),
+            vacuum_interval: platform::HAS_THREADS.then_some(Duration::from_secs(10)),
+            history_retention: None,
+            compact_below: None,
+            flush_interval: platform::HAS_THREADS.then_some(Duration::from_secs(1)),
+            flush_dirty_ratio: 0.1,
+            checkpoint_interval: platform::HAS_THREADS.then_some(Duration::from_secs(60)),
+            wal_archive_dir: None,
+            wal_archive_interval: platform::HAS_THREADS.then_some(Duration::from_secs(60)),
+            warmup: platform::HAS_THREADS,
+            capture_changes: false,
+            replication: false,
+            slow_io_threshold: Some(Duration::from_millis(100)),
+        }
+    }
+}
+
+impl CrabDbOptions {
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    pub fn pool_size(mut self, pool_size: usize) -> Self {
+        self.pool_size = pool_size;
+        self
+    }
+
+    pub fn temp_pool_size(mut self, temp_pool_size: usize) -> Self {
+        self.temp_pool_size = temp_pool_size;
+        self
+    }
+
+    // Evicts with LRU-K.
+    pub fn lru_k(mut self, k: usize) -> Self {
+        self.eviction_policy = ReplacerPolicy::lru_k(k);
+        self
+    }
+
+    pub fn eviction_policy(mut self, eviction_policy: ReplacerPolicy) -> Self {
+        self.eviction_policy = eviction_policy;
+        self
+    }
+
+    pub fn replacer_clock(mut self, replacer_clock: Arc<dyn TimestampClock>) -> Self {
+        self.replacer_clock = Some(replacer_clock);
+        self
+    }
+
+    pub fn sync_mode(mut self, sync_mode: SyncMode) -> Self {
+        self.sync_mode = sync_mode;
+        self
+    }
+
+    pub fn wal_dir(mut self, wal_dir: impl Into<PathBuf>) -> Self {
+        self.wal_dir = Some(wal_dir.into());
+        self
+    }
+
+    pub fn disk_workers(mut self, disk_workers: usize) -> Self {
+        self.disk_workers = disk_workers;
+        self
+    }
+
+    pub fn direct_io(mut self, direct_io: bool) -> Self {
+        self.direct_io = direct_io;
+        self
+    }
+
+    pub fn compression(mut self, compression: PageCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn encryption(mut self, encryption: Arc<KeyRing>) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
+    pub fn isolation(mut self, isolation: IsolationLevel) -> Self {
+        self.isolation = isolation;
+        self
+    }
+
+    pub fn lock_timeout(mut self, lock_timeout: Option<Duration>) -> Self {
+        self.lock_timeout = lock_timeout;
+        self
+    }
+
+    pub fn deadlock_detection_interval(mut self, deadlock_detection_interval: Option<Duration>) -> Self {
+        self.deadlock_detection_interval = deadlock_detection_interval;
+        self
+    }
+
+    pub fn vacuum_interval(mut self, vacuum_interval: Option<Duration>) -> Self {
+        self.vacuum_interval = vacuum_interval;
+        self
+    }
+
+    pub fn history_retention(mut self, history_retention: Option<Duration>) -> Self {
+        self.history_retention = history_retention;
+        self
+    }
+
+    pub fn compact_below(mut self, compact_below: Option<f64>) -> Self {
+        self.compact_below = compact_below;
+        self
+    }
+
+    pub fn flush_interval(mut self, flush_interval: Option<Duration>) -> Self {
+        self.flush_interval = flush_interval;
+        self
+    }
+
+    pub fn flush_dirty_ratio(mut self, flush_dirty_ratio: f64) -> Self {
+        self.flush_dirty_ratio = flush_dirty_ratio;
+        self
+    }
+
+    pub fn checkpoint_interval(mut self, checkpoint_interval: Option<Duration>) -> Self {
+        self.checkpoint_interval = checkpoint_interval;
+        self
+    }
+
+    pub fn wal_archive_dir(mut self, wal_archive_dir: impl Into<PathBuf>) -> Self {
+        self.wal_archive_dir = Some(wal_archive_dir.into());
+        self
+    }
+
+    pub fn wal_archive_interval(mut self, wal_archive_interval: Option<Duration>) -> Self {
+        self.wal_archive_interval = wal_archive_interval;
+        self
+    }
+
+    pub fn warmup(mut self, warmup: bool) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    pub fn capture_changes(mut self, capture_changes: bool) -> Self {
+        self.capture_changes = capture_changes;
+        self
+    }
+
+    pub fn replication(mut self, replication: bool) -> Self {
+        self.replication = replication;
+        self
+    }
+
+    pub fn slow_io_threshold(mut self, slow_io_threshold: Option<Duration>) -> Self {
+        self.slow_io_threshold = slow_io_threshold;
+        self
+    }
+
+    pub fn validate(&self) -> CrabDbResult<()> {
+        let invalid = |message: String| Err(CrabDBError::InvalidInput(message));
+        if self.page_size != PAGE_SIZE {
+            return invalid(format!("Page size {} is not supported; pages are {PAGE_SIZE} bytes", self.page_size));
+        }
+        if self.pool_size == 0 {
+            return invalid("The buffer pool needs at least one frame".to_string());
+        }
+        if self.temp_pool_size == 0 {
+            return invalid("The buffer pool of the temporary storage needs at least one frame".to_string());
+        }
+        self.eviction_policy.validate()?;
+        if self.disk_workers == 0 {
+            return invalid("At least one disk worker is needed".to_string());
+        }
+        if self.lock_timeout.is_some_and(|timeout| timeout.is_zero()) {
+            return invalid("The lock timeout must not be zero".to_string());
+        }
+        if self.deadlock_detection_interval.is_some_and(|interval| interval.is_zero()) {
+            return invalid("The deadlock detection interval must not be zero".to_string());
+        }
+        if self.vacuum_interval.is_some_and(|interval| interval.is_zero()) {
+            return invalid("The vacuum interval must not be zero".to_string());
+        }
+        if self.flush_interval.is_some_and(|interval| interval.is_zero()) {
+            return invalid("The flush interval must not be zero".to_string());
+        }
+        if self.checkpoint_interval.is_some_and(|interval| interval.is_zero()) {
+            return invalid("The checkpoint interval must not be zero".to_string());
+        }
+        if self.wal_archive_interval.is_some_and(|interval| interval.is_zero()) {
+            return invalid("The WAL archive interval must not be zero".to_string());
+        }
+        let background = self.vacuum_interval.is_some() || self.flush_interval.is_some() || self.deadlock_detection_interval.is_some()
+            || self.checkpoint_interval.is_some() || (self.wal_archive_dir.is_some() && self.wal_archive_interval.is_some());
+        if background && !platform::HAS_THREADS {
+            return invalid("Nothing can run in the background without threads; leave the intervals unset".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.flush_dirty_ratio) {
+            return invalid(format!("Dirty ratio {} is not between 0 and 1", self.flush_dirty_ratio));
+        }
+        if let Some(compact_below) = self.compact_below.filter(|fill| !(0.0..=1.0).contains(fill)) {
+            return invalid(format!("Fill factor {compact_below} is not between 0 and 1"));
+        }
+        if let Some(wal_dir) = &self.wal_dir {
+            if wal_dir.exists() && !wal_dir.is_dir() {
+                return invalid(format!("WAL directory {} is not a directory", wal_dir.display()));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn disk_manager_options(&self) -> FileDiskManagerOptions {
+        FileDiskManagerOptions { direct_io: self.direct_io, compression: self.compression, encryption: self.encryption.clone() }
+    }
+
+    pub(crate) fn log_manager_options(&self) -> LogManagerOptions {
+        LogManagerOptions {
+            sync_mode: self.sync_mode,
+            slow_io_threshold: self.slow_io_threshold,
+            encryption: self.encryption.clone(),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn lock_manager(&self) -> LockManager {
+        match self.deadlock_detection_interval {
+            Some(interval) => LockManager::with_deadlock_detection(interval),
+            None => LockManager::new(),
+        }
+        .lock_timeout(self.lock_timeout)
+    }
+
+    pub(crate) fn replacer(&self) -> Arc<dyn Replacer> {
+        self.replacer_of_size(self.pool_size)
+    }
+
+    pub(crate) fn replacer_of_size(&self, replacer_size: usize) -> Arc<dyn Replacer> {
+        Arc::from(self.eviction_policy.build_with_clock(replacer_size, self.replacer_clock.clone()))
+    }
+}
+
+// How an engine serving several databases is set up; see `CrabDbEngine`.
+#[derive(Debug, Clone)]
+pub struct EngineOptions {
+    // Frames of the buffer pool the databases share.
+    pub pool_size: usize,
+    // Threads reading and writing pages of every database.
+    pub disk_workers: usize,
+    // How each database is set up. Its pool_size is the quota a database gets unless it
+    // asks for another one, and its disk_workers go unused. Without a replacer_clock the
+    // databases share a logical one, so accesses to any of them are counted on one scale.
+    pub database: CrabDbOptions,
+}
+
+impl Default for EngineOptions {
+    fn default() -> Self {
+        EngineOptions { pool_size: 4096, disk_workers: 2, database: CrabDbOptions::default() }
+    }
+}
+
+impl EngineOptions {
+    pub fn pool_size(mut self, pool_size: usize) -> Self {
+        self.pool_size = pool_size;
+        self
+    }
+
+    pub fn disk_workers(mut self, disk_workers: usize) -> Self {
+        self.disk_workers = disk_workers;
+        self
+    }
+
+    pub fn database(mut self, database: CrabDbOptions) -> Self {
+        self.database = database;
+        self
+    }
+
+    pub fn validate(&self) -> CrabDbResult<()> {
+        if self.pool_size == 0 {
+            return Err(CrabDBError::InvalidInput("The buffer pool needs at least one frame".to_string()));
+        }
+        if self.disk_workers == 0 {
+            return Err(CrabDBError::InvalidInput("At least one disk worker is needed".to_string()));
+        }
+        self.database.validate()
+    }
+}
+
+// A database's share of its engine's buffer pool.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DatabaseQuota {
+    // The most frames the database holds at once; the pool_size of the engine's database
+    // options if None.
+    pub frames: Option<usize>,
+    // Once the pool is full, pages of databases with a lower priority are evicted first.
+    pub priority: u8,
+}
+
+impl DatabaseQuota {
+    pub fn frames(mut self, frames: usize) -> Self {
+        self.frames = Some(frames);
+        self
+    }
+
+    pub fn priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+}
@@ -0,0 +1,571 @@
+use std::collections::HashMap;
+use std::io::{BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use sqlparser::ast::Statement;
+
+use crate::kv::kv_store::KvStore;
+use crate::sql::binder::parse;
+use crate::types::{CrabDBError, CrabDbResult};
+use crate::storage::table::tuple::Rid;
+use crate::wal::common::{Lsn, TxnId};
+use crate::wal::log_manager::LogManager;
+use crate::wal::log_record::{LogRecord, LogRecordBody};
+use crate::wal::recovery_manager::RecoveryManager;
+
+use super::crab_db::{CrabDb, StatementOutput, DEFAULT_SESSION};
+use super::options::CrabDbOptions;
+use super::row_iterator::RowIterator;
+
+// Replication protocol over TCP, integers little endian:
+//   replica -> primary, once: magic | LSN of the last record the replica holds (u64)
+//   primary -> replica, over and over: flushed LSN (u64) | byte length (u32) | the log
+//     records following those sent before, none for a heartbeat
+//   replica -> primary, after applying records: LSN of the last one applied (u64)
+const MAGIC: &[u8; 8] = b"CRABREP1";
+// How long the primary waits for new log before sending a heartbeat instead; also how long
+// a dropped server takes to let go of its replicas.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(200);
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+// How long a replica that lost its primary waits before connecting again.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+// A replica connected to a primary, as the primary sees it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicaInfo {
+    pub addr: SocketAddr,
+    // Last record shipped to the replica.
+    pub sent_lsn: Lsn,
+    // Last record the replica reported applied.
+    pub applied_lsn: Lsn,
+    // Records in the primary's log the replica has yet to apply.
+    pub lag: u64,
+}
+
+// Ships a primary's write-ahead log to the replicas that connect, each from where it left
+// off, as the log is flushed. With `CrabDbOptions::replication` the log holds every page
+// the primary writes in full, so replaying it repeats every change. Dropping the server
+// disconnects the replicas; they keep trying to connect again.
+pub struct ReplicationServer {
+    local_addr: SocketAddr,
+    shared: Arc<ServerShared>,
+    acceptor: Option<JoinHandle<()>>,
+}
+
+struct ServerShared {
+    log_manager: Arc<LogManager>,
+    stop: AtomicBool,
+    replicas: Mutex<HashMap<SocketAddr, ReplicaInfo>>,
+}
+
+impl ReplicationServer {
+    pub(crate) fn new(log_manager: Arc<LogManager>, listener: TcpListener) -> CrabDbResult<Self> {
+        let io_error = |e| CrabDBError::io("Failed to listen for replicas".to_string(), e);
+        let local_addr = listener.local_addr().map_err(io_error)?;
+        listener.set_nonblocking(true).map_err(io_error)?;
+        let shared = Arc::new(ServerShared { log_manager, stop: AtomicBool::new(false), replicas: Mutex::new(HashMap::new()) });
+        let acceptor = {
+            let shared = shared.clone();
+            std::thread::Builder::new()
+                .name("crab-db-replication".to_string())
+                .spawn(move || shared.accept(listener))
+                .map_err(io_error)?
+        };
+        Ok(ReplicationServer { local_addr, shared, acceptor: Some(acceptor) })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    // The replicas connected now, by address.
+    pub fn replicas(&self) -> Vec<ReplicaInfo> {
+        let flushed_lsn = self.shared.log_manager.flushed_lsn();
+        let mut replicas: Vec<_> = self.shared.replicas.lock().unwrap().values()
+            .map(|replica| ReplicaInfo { lag: flushed_lsn.saturating_sub(replica.applied_lsn), ..replica.clone() })
+            .collect();
+        replicas.sort_by_key(|replica| replica.addr);
+        replicas
+    }
+}
+
+impl Drop for ReplicationServer {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::SeqCst);
+        if let Some(acceptor) = self.acceptor.take() {
+            let _ = acceptor.join();
+        }
+    }
+}
+
+impl ServerShared {
+    fn accept(self: Arc<Self>, listener: TcpListener) {
+        let mut shippers: Vec<JoinHandle<()>> = Vec::new();
+        while !self.stop.load(Ordering::SeqCst) {
+            match listener.accept() {
+                Ok((stream, addr)) => {
+                    let shared = self.clone();
+                    let shipper = std::thread::Builder::new()
+                        .name(format!("crab-db-replication-{addr}"))
+                        .spawn(move || {
+                            let _ = shared.ship(stream, addr);
+                            shared.replicas.lock().unwrap().remove(&addr);
+                        });
+                    shippers.extend(shipper);
+                },
+                Err(_) => std::thread::sleep(ACCEPT_POLL_INTERVAL),
+            }
+            shippers.retain(|shipper| !shipper.is_finished());
+        }
+        for shipper in shippers {
+            let _ = shipper.join();
+        }
+    }
+
+    // Sends the log after the position the replica asks for, then whatever is flushed next,
+    // until the replica goes away or the server stops.
+    fn ship(self: &Arc<Self>, stream: TcpStream, addr: SocketAddr) -> CrabDbResult<()> {
+        let io_error = |e| CrabDBError::io(format!("Lost replica {addr}"), e);
+        stream.set_nonblocking(false).map_err(io_error)?;
+        stream.set_nodelay(true).map_err(io_error)?;
+        stream.set_read_timeout(Some(HEARTBEAT_INTERVAL * 10)).map_err(io_error)?;
+        let mut handshake = [0; 16];
+        (&stream).read_exact(&mut handshake).map_err(io_error)?;
+        stream.set_read_timeout(None).map_err(io_error)?;
+        if &handshake[..8] != MAGIC {
+            return Err(CrabDBError::InvalidInput(format!("{addr} is not a crab-db replica")));
+        }
+        let mut sent_lsn = Lsn::from_le_bytes(handshake[8..].try_into().unwrap());
+        if sent_lsn > self.log_manager.flushed_lsn() {
+            return Err(CrabDBError::InvalidInput(format!("Replica {addr} is ahead of its primary, at LSN {sent_lsn}")));
+        }
+        self.replicas.lock().unwrap().insert(addr, ReplicaInfo { addr, sent_lsn, applied_lsn: sent_lsn, lag: 0 });
+        // The log the replica has yet to apply is kept while it is connected, so it can
+        // pick up from there if the connection breaks.
+        let hold = self.log_manager.hold(sent_lsn + 1);
+
+        let acks = {
+            let mut stream = stream.try_clone().map_err(io_error)?;
+            let shared = self.clone();
+            let hold = hold.clone();
+            std::thread::spawn(move || {
+                let mut lsn = [0; 8];
+                while stream.read_exact(&mut lsn).is_ok() {
+                    let applied_lsn = Lsn::from_le_bytes(lsn);
+                    hold.advance(applied_lsn + 1);
+                    if let Some(replica) = shared.replicas.lock().unwrap().get_mut(&addr) {
+                        replica.applied_lsn = applied_lsn;
+                    }
+                }
+            })
+        };
+        let mut offset = 0;
+        let shipped = loop {
+            if self.stop.load(Ordering::SeqCst) {
+                break Ok(());
+            }
+            let flushed_lsn = self.log_manager.wait_for_flush(sent_lsn, HEARTBEAT_INTERVAL);
+            let (records, next_offset) = match self.log_manager.records_from(offset) {
+                Ok(read) => read,
+                Err(e) => break Err(e),
+            };
+            offset = next_offset;
+            let after_lsn = sent_lsn;
+            if records.iter().find(|record| record.lsn > after_lsn).is_some_and(|next| next.lsn > after_lsn + 1) {
+                break Err(CrabDBError::InvalidInput(format!(
+                    "Replica {addr} needs log from LSN {} on, which the primary has recycled; restore it from a new backup",
+                    after_lsn + 1,
+                )));
+            }
+            let mut bytes = Vec::new();
+            for record in records.iter().filter(|record| record.lsn > after_lsn) {
+                record.serialize(&mut bytes);
+                sent_lsn = record.lsn;
+            }
+            let mut frame = Vec::with_capacity(12 + bytes.len());
+            frame.extend_from_slice(&flushed_lsn.max(sent_lsn).to_le_bytes());
+            frame.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+            frame.extend_from_slice(&bytes);
+            if let Err(e) = (&stream).write_all(&frame) {
+                break Err(io_error(e));
+            }
+            if let Some(replica) = self.replicas.lock().unwrap().get_mut(&addr) {
+                replica.sent_lsn = sent_lsn;
+            }
+        };
+        let _ = stream.shutdown(Shutdown::Both);
+        let _ = acks.join();
+        shipped
+    }
+}
+
+// Where a replica is, as it sees it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplicaStatus {
+    pub connected: bool,
+    // Last record of the primary's log applied here.
+    pub applied_lsn: Lsn,
+    // Last record the primary reported flushed.
+    pub primary_lsn: Lsn,
+    // When the primary made the last commit applied here, if one was.
+    pub last_commit_time: Option<SystemTime>,
+    // Why the replica last lost its primary, or could not reach it.
+    pub last_error: Option<String>,
+}
+
+impl ReplicaStatus {
+    // Records the primary flushed that are not applied here yet. Pages the primary writes
+    // back between commits count too: they are applied with the next commit.
+    pub fn lag(&self) -> u64 {
+        self.primary_lsn.saturating_sub(self.applied_lsn)
+    }
+
+    // How stale queries here can be: zero once everything the primary flushed is applied,
+    // otherwise the time since the primary made the last commit applied.
+    pub fn replay_delay(&self) -> Duration {
+        if self.lag() == 0 {
+            return Duration::ZERO;
+        }
+        self.last_commit_time.and_then(|time| time.elapsed().ok()).unwrap_or_default()
+    }
+}
+
+// A read-only copy of a primary database that follows it. It starts from a backup of the
+// primary and then replays the log the primary ships, through recovery's redo, one commit
+// or more at a time, keeping a copy of that log so it resumes where it stopped when opened
+// again. Queries see the pages as of a commit of the primary, the latest applied when they
+// start. Rows that transactions still open on the primary wrote over read as they were
+// committed, from the versions their log records carry.
+pub struct Replica {
+    shared: Arc<ReplicaShared>,
+    receiver: Option<JoinHandle<()>>,
+}
+
+struct ReplicaShared {
+    db: CrabDb,
+    primary: SocketAddr,
+    // Held shared by queries and exclusively while log is applied, so no query sees the
+    // pages halfway between two commits of the primary.
+    apply_latch: RwLock<()>,
+    status: Mutex<ReplicaStatus>,
+    applied: Condvar,
+    stop: AtomicBool,
+    // The connection to the primary, to cut it when the replica is dropped.
+    stream: Mutex<Option<TcpStream>>,
+    // Rows written by transactions not yet committed or aborted in the log applied, by
+    // transaction.
+    open_writes: Mutex<HashMap<TxnId, Vec<Rid>>>,
+}
+
+impl Replica {
+    // Creates the replica at `path`, which must not exist yet, from a backup of the primary
+    // at `primary` taken with `CrabDbOptions::replication` on, and starts following it.
+    pub fn restore_from(backup_path: impl AsRef<Path>, path: impl AsRef<Path>, primary: SocketAddr, options: CrabDbOptions) -> CrabDbResult<Self> {
+        CrabDb::restore_files(backup_path.as_ref(), path.as_ref(), &options, None)?;
+        Self::open(path, primary, options)
+    }
+
+    // Opens the replica at `path` and follows the primary at `primary` from where it left
+    // off, connecting again whenever the connection is lost.
+    pub fn open(path: impl AsRef<Path>, primary: SocketAddr, options: CrabDbOptions) -> CrabDbResult<Self> {
+        let mut open_writes = HashMap::new();
+        let db = CrabDb::open_replica(path.as_ref(), options, &mut open_writes)?;
+        let applied_lsn = db.log_manager().next_lsn() - 1;
+        let shared = Arc::new(ReplicaShared {
+            db,
+            primary,
+            apply_latch: RwLock::new(()),
+            status: Mutex::new(ReplicaStatus { applied_lsn, primary_lsn: applied_lsn, ..Default::default() }),
+            applied: Condvar::new(),
+            stop: AtomicBool::new(false),
+            stream: Mutex::new(None),
+            open_writes: Mutex::new(open_writes),
+        });
+        let receiver = {
+            let shared = shared.clone();
+            std::thread::Builder::new()
+                .name("crab-db-replica".to_string())
+                .spawn(move || shared.receive())
+                .map_err(|e| CrabDBError::io("Failed to start the replica".to_string(), e))?
+        };
+        Ok(Replica { shared, receiver: Some(receiver) })
+    }
+
+    pub fn status(&self) -> ReplicaStatus {
+        self.shared.status.lock().unwrap().clone()
+    }
+
+    // Waits until the log up to `lsn`, such as `CrabDb::flushed_lsn` on the primary after a
+    // commit, is applied, for at most `timeout`. Returns whether it was.
+    pub fn wait_for_lsn(&self, lsn: Lsn, timeout: Duration) -> bool {
+        let status = self.shared.status.lock().unwrap();
+        let (status, _) = self.shared.applied.wait_timeout_while(status, timeout, |status| status.applied_lsn < lsn).unwrap();
+        status.applied_lsn >= lsn
+    }
+
+    // Runs the queries in `sql` and returns the rows of the last one. Anything that would
+    // write is rejected.
+    pub fn query(&self, sql: &str) -> CrabDbResult<RowIterator> {
+        let statements = parse(sql)?;
+        if let Some(statement) = statements.iter().find(|statement| !is_read_only(statement)) {
+            return Err(CrabDBError::InvalidInput(format!("A replica only runs queries, not {statement}")));
+        }
+        let db = &self.shared.db;
+        let _applying = self.shared.apply_latch.read().unwrap();
+        let mut output = StatementOutput::default();
+        for statement in statements {
+            let txn = db.begin();
+            let ran = db.run_statement(DEFAULT_SESSION, &txn, &statement, &[]);
+            // Nothing to roll back: this only ends the snapshot, after which the statement's
+            // own error comes first.
+            let ended = db.txn_manager().abort(&txn);
+            output = ran?;
+            ended?;
+        }
+        Ok(output.rows)
+    }
+
+    // Reads `key` from the key-value store `store`; None if the store does not exist.
+    pub fn fetch(&self, store: &str, key: &[u8]) -> CrabDbResult<Option<Vec<u8>>> {
+        let db = &self.shared.db;
+        let _applying = self.shared.apply_latch.read().unwrap();
+        let Some(info) = db.catalog().kv_store(store) else {
+            return Ok(None);
+        };
+        let txn = db.begin();
+        let value = KvStore::new(info, db.txn_manager().clone()).get(&txn, key);
+        let ended = db.txn_manager().abort(&txn);
+        let value = value?;
+        ended?;
+        Ok(value)
+    }
+}
+
+impl Drop for Replica {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::SeqCst);
+        if let Some(stream) = self.shared.stream.lock().unwrap().as_ref() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        if let Some(receiver) = self.receiver.take() {
+            receiver.thread().unpark();
+            let _ = receiver.join();
+        }
+    }
+}
+
+fn is_read_only(statement: &Statement) -> bool {
+    match statement {
+        Statement::Query(_) => true,
+        Statement::Explain { statement, analyze, .. } => !analyze || matches!(**statement, Statement::Query(_)),
+        _ => false,
+    }
+}
+
+impl ReplicaShared {
+    fn receive(self: Arc<Self>) {
+        while !self.stop.load(Ordering::SeqCst) {
+            let followed = TcpStream::connect(self.primary)
+                .map_err(|e| CrabDBError::io(format!("Failed to connect to the primary at {}", self.primary), e))
+                .and_then(|stream| self.follow(stream));
+            {
+                let mut status = self.status.lock().unwrap();
+                status.connected = false;
+                if let Err(e) = followed {
+                    status.last_error = Some(e.to_string());
+                }
+            }
+            if !self.stop.load(Ordering::SeqCst) {
+                std::thread::park_timeout(RECONNECT_INTERVAL);
+            }
+        }
+    }
+
+    // Applies what the primary sends until the connection is lost. Records are held back
+    // until one marking the end of a commit arrives.
+    fn follow(&self, stream: TcpStream) -> CrabDbResult<()> {
+        let io_error = |e| CrabDBError::io(format!("Lost the primary at {}", self.primary), e);
+        *self.stream.lock().unwrap() = Some(stream.try_clone().map_err(io_error)?);
+        if self.stop.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        stream.set_nodelay(true).map_err(io_error)?;
+        let mut handshake = MAGIC.to_vec();
+        handshake.extend_from_slice(&self.status.lock().unwrap().applied_lsn.to_le_bytes());
+        (&stream).write_all(&handshake).map_err(io_error)?;
+        self.status.lock().unwrap().connected = true;
+
+        let mut reader = BufReader::new(stream.try_clone().map_err(io_error)?);
+        let mut pending = Vec::new();
+        loop {
+            let mut header = [0; 12];
+            reader.read_exact(&mut header).map_err(io_error)?;
+            let primary_lsn = Lsn::from_le_bytes(header[..8].try_into().unwrap());
+            let mut bytes = vec![0; u32::from_le_bytes(header[8..].try_into().unwrap()) as usize];
+            reader.read_exact(&mut bytes).map_err(io_error)?;
+            let mut position = 0;
+            while let Some((record, size)) = LogRecord::deserialize(&bytes[position..])? {
+                pending.push(record);
+                position += size;
+            }
+            if position != bytes.len() {
+                return Err(CrabDBError::Corruption(format!("The primary at {} sent a torn log record", self.primary)));
+            }
+            self.status.lock().unwrap().primary_lsn = primary_lsn;
+            // The primary logs the time after the records of each commit.
+            if let Some(end) = pending.iter().rposition(|record: &LogRecord| matches!(record.body, LogRecordBody::Clock { .. })) {
+                let batch: Vec<_> = pending.drain(..=end).collect();
+                let applied_lsn = self.apply(&batch)?;
+                (&stream).write_all(&applied_lsn.to_le_bytes()).map_err(io_error)?;
+            }
+        }
+    }
+
+    // Keeps `records` in the log, durably before the pages they change, redoes them, and
+    // reads the catalog again for the tables they may have created or changed.
+    fn apply(&self, records: &[LogRecord]) -> CrabDbResult<Lsn> {
+        let _applying = self.apply_latch.write().unwrap();
+        let log_manager = self.db.log_manager();
+        log_manager.append_copied(records)?;
+        let mut open_writes = self.open_writes.lock().unwrap();
+        RecoveryManager::new(self.db.bpm().clone(), log_manager.clone()).replay(records, self.db.txn_manager().versions(), &mut open_writes)?;
+        self.db.catalog().reload()?;
+
+        let mut status = self.status.lock().unwrap();
+        if let Some(last) = records.last() {
+            status.applied_lsn = last.lsn;
+            if let LogRecordBody::Clock { unix_micros } = last.body {
+                status.last_commit_time = Some(UNIX_EPOCH + Duration::from_micros(unix_micros));
+            }
+        }
+        self.applied.notify_all();
+        Ok(status.applied_lsn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::time::{Duration, Instant};
+
+    use tempfile::TempDir;
+
+    use crate::db::crab_db::CrabDb;
+    use crate::db::options::CrabDbOptions;
+    use crate::types::value::Value;
+    use crate::types::CrabDBError;
+    use super::Replica;
+
+    const CATCH_UP: Duration = Duration::from_secs(10);
+
+    fn names(replica: &Replica) -> Vec<Value> {
+        replica.query("SELECT name FROM crabs ORDER BY id").unwrap().map(|row| row[0].clone()).collect()
+    }
+
+    #[test]
+    pub fn test_replica_follows_its_primary() {
+        let dir = TempDir::new().unwrap();
+        let options = CrabDbOptions::default().replication(true).vacuum_interval(None).flush_interval(None);
+        let primary = CrabDb::open(dir.path().join("primary.db"), options.clone()).unwrap();
+        let server = primary.serve_replicas(TcpListener::bind("127.0.0.1:0").unwrap()).unwrap();
+        primary.execute("CREATE TABLE crabs (id BIGINT NOT NULL, name VARCHAR)").unwrap();
+        primary.execute("INSERT INTO crabs VALUES (1, 'ferris')").unwrap();
+        let backup_path = dir.path().join("primary.backup");
+        primary.backup_to(&backup_path).unwrap();
+        primary.execute("INSERT INTO crabs VALUES (2, 'sebastian')").unwrap();
+
+        let replica_path = dir.path().join("replica.db");
+        let replica = Replica::restore_from(&backup_path, &replica_path, server.local_addr(), options.clone()).unwrap();
+        assert!(replica.wait_for_lsn(primary.flushed_lsn(), CATCH_UP));
+        assert_eq!(vec![Value::Varchar("ferris".into()), Value::Varchar("sebastian".into())], names(&replica));
+        let status = replica.status();
+        assert!(status.connected);
+        assert_eq!(0, status.lag());
+        assert!(status.last_commit_time.is_some());
+
+        // tables created and rows changed on the primary show up as it commits
+        let txn = primary.begin_transaction();
+        txn.execute("UPDATE crabs SET name = 'crabby' WHERE id = 1").unwrap();
+        txn.execute("CREATE TABLE shrimp (id BIGINT NOT NULL)").unwrap();
+        txn.execute("INSERT INTO shrimp VALUES (7)").unwrap();
+        primary.kv_store("shells").unwrap().put(txn.txn(), b"ferris", b"red").unwrap();
+        txn.commit().unwrap();
+        assert!(replica.wait_for_lsn(primary.flushed_lsn(), CATCH_UP));
+        assert_eq!(vec![Value::Varchar("crabby".into()), Value::Varchar("sebastian".into())], names(&replica));
+        assert_eq!(1, replica.query("SELECT * FROM shrimp").unwrap().len());
+        assert_eq!(Some(b"red".to_vec()), replica.fetch("shells", b"ferris").unwrap());
+        assert_eq!(None, replica.fetch("pearls", b"ferris").unwrap());
+        assert!(matches!(replica.query("DELETE FROM crabs"), Err(CrabDBError::InvalidInput(_))));
+        assert!(matches!(replica.query("CREATE TABLE lobsters (id BIGINT)"), Err(CrabDBError::InvalidInput(_))));
+
+        // the primary hears of what the replica applied a moment after it is applied
+        let deadline = Instant::now() + CATCH_UP;
+        while server.replicas()[0].applied_lsn != replica.status().applied_lsn && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let replicas = server.replicas();
+        assert_eq!(1, replicas.len());
+        assert_eq!(replica.status().applied_lsn, replicas[0].applied_lsn);
+
+        // a replica opened again resumes from the log it kept
+        drop(replica);
+        primary.execute("DELETE FROM crabs WHERE id = 2").unwrap();
+        let replica = Replica::open(&replica_path, server.local_addr(), options.clone()).unwrap();
+        assert!(replica.wait_for_lsn(primary.flushed_lsn(), CATCH_UP));
+        assert_eq!(vec![Value::Varchar("crabby".into())], names(&replica));
+        drop(replica);
+
+        let plain = CrabDb::open(dir.path().join("plain.db"), CrabDbOptions::default()).unwrap();
+        assert!(matches!(plain.serve_replicas(TcpListener::bind("127.0.0.1:0").unwrap()), Err(CrabDBError::InvalidInput(_))));
+    }
+
+    #[test]
+    pub fn test_replica_reads_past_writes_of_open_transactions() {
+        let dir = TempDir::new().unwrap();
+        let options = CrabDbOptions::default().replication(true).vacuum_interval(None).flush_interval(None);
+        let primary = CrabDb::open(dir.path().join("primary.db"), options.clone()).unwrap();
+        let server = primary.serve_replicas(TcpListener::bind("127.0.0.1:0").unwrap()).unwrap();
+        primary.execute("CREATE TABLE crabs (id BIGINT NOT NULL, name VARCHAR)").unwrap();
+        primary.execute("INSERT INTO crabs VALUES (1, 'ferris'), (2, 'sebastian')").unwrap();
+        let backup_path = dir.path().join("primary.backup");
+        primary.backup_to(&backup_path).unwrap();
+        let replica_path = dir.path().join("replica.db");
+        let replica = Replica::restore_from(&backup_path, &replica_path, server.local_addr(), options.clone()).unwrap();
+        let ferris_and_sebastian = vec![Value::Varchar("ferris".into()), Value::Varchar("sebastian".into())];
+
+        // the open transaction's writes are shipped with the other one's commit
+        let open = primary.begin_transaction();
+        open.execute("UPDATE crabs SET name = 'crabby' WHERE id = 1").unwrap();
+        open.execute("DELETE FROM crabs WHERE id = 2").unwrap();
+        primary.execute("CREATE TABLE shrimp (id BIGINT NOT NULL)").unwrap();
+        assert!(replica.wait_for_lsn(primary.flushed_lsn(), CATCH_UP));
+        assert_eq!(ferris_and_sebastian, names(&replica));
+
+        // a replica opened again finds the open writes in the log it kept
+        drop(replica);
+        let replica = Replica::open(&replica_path, server.local_addr(), options.clone()).unwrap();
+        assert!(replica.wait_for_lsn(primary.flushed_lsn(), CATCH_UP));
+        assert_eq!(ferris_and_sebastian, names(&replica));
+
+        // rolled back, the rows are as they were
+        open.rollback().unwrap();
+        primary.execute("INSERT INTO shrimp VALUES (7)").unwrap();
+        assert!(replica.wait_for_lsn(primary.flushed_lsn(), CATCH_UP));
+        assert_eq!(ferris_and_sebastian, names(&replica));
+
+        // committed, the writes show up
+        let open = primary.begin_transaction();
+        open.execute("UPDATE crabs SET name = 'crabby' WHERE id = 1").unwrap();
+        primary.execute("INSERT INTO shrimp VALUES (8)").unwrap();
+        assert!(replica.wait_for_lsn(primary.flushed_lsn(), CATCH_UP));
+        assert_eq!(ferris_and_sebastian, names(&replica));
+        open.commit().unwrap();
+        assert!(replica.wait_for_lsn(primary.flushed_lsn(), CATCH_UP));
+        assert_eq!(vec![Value::Varchar("crabby".into()), Value::Varchar("sebastian".into())], names(&replica));
+    }
+}
@@ -0,0 +1,32 @@
+use crate::types::schema::Schema;
+use crate::types::value::Value;
+
+// The rows a statement returned, in order, with the schema they follow.
+pub struct RowIterator {
+    schema: Schema,
+    rows: std::vec::IntoIter<Vec<Value>>,
+}
+
+impl RowIterator {
+    pub(crate) fn new(schema: Schema, rows: Vec<Vec<Value>>) -> Self {
+        RowIterator { schema, rows: rows.into_iter() }
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+}
+
+impl Iterator for RowIterator {
+    type Item = Vec<Value>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rows.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rows.size_hint()
+    }
+}
+
+impl ExactSizeIterator for RowIterator {}
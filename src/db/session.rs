@@ -0,0 +1,52 @@
+use crate::catalog::system_catalog::SessionId;
+use crate::sql::binder::parse_as_of;
+use crate::types::value::Value;
+use crate::types::CrabDbResult;
+
+use super::crab_db::CrabDb;
+use super::cursor::Cursor;
+use super::transaction::DbTransaction;
+
+// A session of a CrabDb. Its statements run like those of `CrabDb::execute` and
+// `CrabDb::query`, and also see the temporary tables made in it, which no other session sees
+// and which are dropped when it is.
+pub struct Session<'a> {
+    db: &'a CrabDb,
+    id: SessionId,
+}
+
+impl<'a> Session<'a> {
+    pub(crate) fn new(db: &'a CrabDb, id: SessionId) -> Self {
+        Session { db, id }
+    }
+
+    pub fn id(&self) -> SessionId {
+        self.id
+    }
+
+    pub fn execute(&self, sql: &str) -> CrabDbResult<u64> {
+        self.execute_with(sql, &[])
+    }
+
+    pub fn execute_with(&self, sql: &str, parameters: &[Value]) -> CrabDbResult<u64> {
+        Ok(self.db.run_with(self.id, sql, parameters)?.count)
+    }
+
+    pub fn query(&self, sql: &str) -> CrabDbResult<Cursor> {
+        self.query_with(sql, &[])
+    }
+
+    pub fn query_with(&self, sql: &str, parameters: &[Value]) -> CrabDbResult<Cursor> {
+        self.db.query_statements(self.id, parse_as_of(sql)?, parameters)
+    }
+
+    pub fn begin_transaction(&self) -> DbTransaction<'_> {
+        DbTransaction::new(self.db, self.id, self.db.begin())
+    }
+}
+
+impl Drop for Session<'_> {
+    fn drop(&mut self) {
+        self.db.end_session(self.id);
+    }
+}
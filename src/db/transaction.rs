@@ -0,0 +1,58 @@
+use std::sync::Arc;
+
+use crate::catalog::system_catalog::SessionId;
+use crate::concurrency::transaction::{Transaction, TransactionState};
+use crate::types::CrabDbResult;
+
+use super::crab_db::{CrabDb, StatementOutput};
+use super::cursor::Cursor;
+
+// A transaction of a CrabDb spanning several statements. Dropping it without committing
+// rolls it back.
+pub struct DbTransaction<'a> {
+    db: &'a CrabDb,
+    session: SessionId,
+    txn: Arc<Transaction>,
+}
+
+impl<'a> DbTransaction<'a> {
+    pub(crate) fn new(db: &'a CrabDb, session: SessionId, txn: Arc<Transaction>) -> Self {
+        DbTransaction { db, session, txn }
+    }
+
+    pub fn txn(&self) -> &Arc<Transaction> {
+        &self.txn
+    }
+
+    // Runs the statements in `sql` and returns how many rows the last one changed, or
+    // returned if it is a query.
+    pub fn execute(&self, sql: &str) -> CrabDbResult<u64> {
+        Ok(self.run(sql)?.count)
+    }
+
+    // Runs the statements in `sql` and returns a cursor over the rows of the last one; see
+    // `CrabDb::query`. Read it before committing.
+    pub fn query(&self, sql: &str) -> CrabDbResult<Cursor> {
+        self.db.query_in_transaction(self.session, &self.txn, sql)
+    }
+
+    fn run(&self, sql: &str) -> CrabDbResult<StatementOutput> {
+        self.db.run_in_transaction(self.session, &self.txn, sql)
+    }
+
+    pub fn commit(self) -> CrabDbResult<()> {
+        self.db.commit(&self.txn)
+    }
+
+    pub fn rollback(self) -> CrabDbResult<()> {
+        self.db.txn_manager().abort(&self.txn)
+    }
+}
+
+impl Drop for DbTransaction<'_> {
+    fn drop(&mut self) {
+        if self.txn.state() != TransactionState::Committed {
+            let _ = self.db.txn_manager().abort(&self.txn);
+        }
+    }
+}
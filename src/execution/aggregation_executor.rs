@@ -0,0 +1,314 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::Arc;
+
+use crate::catalog::udf::AggregateUdf;
+use crate::types::schema::Schema;
+use crate::types::value::Value;
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::executor::{Executor, Row};
+use super::expression::{arithmetic, type_name, ArithmeticOp, Expression};
+use super::hash_key::group_key;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AggregateFunction {
+    // COUNT(*): counts rows, NULL or not.
+    CountStar,
+    Count,
+    Sum,
+    Min,
+    Max,
+    Avg,
+    User(Arc<AggregateUdf>),
+}
+
+impl Display for AggregateFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            AggregateFunction::CountStar | AggregateFunction::Count => "COUNT",
+            AggregateFunction::Sum => "SUM",
+            AggregateFunction::Min => "MIN",
+            AggregateFunction::Max => "MAX",
+            AggregateFunction::Avg => "AVG",
+            AggregateFunction::User(function) => function.name(),
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregateExpression {
+    pub function: AggregateFunction,
+    // Ignored by COUNT(*).
+    pub argument: Expression,
+}
+
+impl AggregateExpression {
+    pub fn new(function: AggregateFunction, argument: Expression) -> Self {
+        AggregateExpression { function, argument }
+    }
+
+    pub fn count_star() -> Self {
+        AggregateExpression { function: AggregateFunction::CountStar, argument: Expression::Constant(Value::Null) }
+    }
+}
+
+// The running state of one aggregate over one group. Apart from COUNT(*), NULL inputs are
+// skipped, and an aggregate that saw no other input is NULL (COUNT is 0).
+enum Accumulator {
+    Count(i64),
+    Sum(Value),
+    Min(Value),
+    Max(Value),
+    Avg { sum: f64, count: i64 },
+    // A user-defined aggregate gets all of the group's values at once.
+    User(Arc<AggregateUdf>, Vec<Value>),
+}
+
+impl Accumulator {
+    fn new(function: &AggregateFunction) -> Self {
+        match function {
+            AggregateFunction::CountStar | AggregateFunction::Count => Accumulator::Count(0),
+            AggregateFunction::Sum => Accumulator::Sum(Value::Null),
+            AggregateFunction::Min => Accumulator::Min(Value::Null),
+            AggregateFunction::Max => Accumulator::Max(Value::Null),
+            AggregateFunction::Avg => Accumulator::Avg { sum: 0.0, count: 0 },
+            AggregateFunction::User(function) => Accumulator::User(function.clone(), Vec::new()),
+        }
+    }
+
+    fn add(&mut self, function: &AggregateFunction, value: Value) -> CrabDbResult<()> {
+        if value.is_null() && *function != AggregateFunction::CountStar {
+            return Ok(());
+        }
+        match self {
+            Accumulator::Count(count) => *count += 1,
+            Accumulator::Sum(sum) => {
+                // Integer sums are BIGINT however narrow their inputs.
+                let value = match value {
+                    Value::Int32(i) => Value::Int64(i as i64),
+                    other => other,
+                };
+                let previous = match std::mem::replace(sum, Value::Null) {
+                    Value::Null => Value::Int64(0),
+                    previous => previous,
+                };
+                *sum = arithmetic(ArithmeticOp::Add, previous, value)?;
+            },
+            Accumulator::Min(current) => replace_if(current, value, Ordering::Less)?,
+            Accumulator::Max(current) => replace_if(current, value, Ordering::Greater)?,
+            Accumulator::Avg { sum, count } => {
+                let Some(value) = value.as_f64() else {
+                    return Err(CrabDBError::InvalidInput(format!("Cannot average {}", type_name(&value))));
+                };
+                *sum += value;
+                *count += 1;
+            },
+            Accumulator::User(function, values) => values.push(function.coerce(value)?),
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> CrabDbResult<Value> {
+        Ok(match self {
+            Accumulator::Count(count) => Value::Int64(count),
+            Accumulator::Sum(value) | Accumulator::Min(value) | Accumulator::Max(value) => value,
+            Accumulator::Avg { count: 0, .. } => Value::Null,
+            Accumulator::Avg { sum, count } => Value::Float64(sum / count as f64),
+            Accumulator::User(function, values) => function.call(&values)?,
+        })
+    }
+}
+
+// Replaces `current` with `value` if it is NULL or `value` compares to it as `wanted`.
+fn replace_if(current: &mut Value, value: Value, wanted: Ordering) -> CrabDbResult<()> {
+    let replace = current.is_null() || value.compare(current).ok_or_else(|| CrabDBError::InvalidInput(format!(
+        "Cannot compare {} with {}", type_name(&value), type_name(current)
+    )))? == wanted;
+    if replace {
+        *current = value;
+    }
+    Ok(())
+}
+
+// Hash aggregation: `init` drains the child into one set of accumulators per distinct
+// group-by key, then `next` hands out a row per group, in the order groups were first seen.
+// Output rows are the group-by values followed by the aggregates, and HAVING is evaluated
+// on them. Without group-by keys there is exactly one group, even over no input.
+pub struct AggregationExecutor<'a> {
+    child: Box<dyn Executor + 'a>,
+    group_by: Vec<Expression>,
+    aggregates: Vec<AggregateExpression>,
+    having: Option<Expression>,
+    schema: Schema,
+    results: std::vec::IntoIter<Vec<Value>>,
+}
+
+impl<'a> AggregationExecutor<'a> {
+    // `schema` names and types the output columns.
+    pub fn new(
+        child: Box<dyn Executor + 'a>,
+        group_by: Vec<Expression>,
+        aggregates: Vec<AggregateExpression>,
+        having: Option<Expression>,
+        schema: Schema,
+    ) -> Self {
+        AggregationExecutor { child, group_by, aggregates, having, schema, results: Vec::new().into_iter() }
+    }
+}
+
+impl Executor for AggregationExecutor<'_> {
+    fn init(&mut self) -> CrabDbResult<()> {
+        let mut positions: HashMap<Vec<u8>, usize> = HashMap::new();
+        let mut groups: Vec<(Vec<Value>, Vec<Accumulator>)> = Vec::new();
+        let new_accumulators = |aggregates: &[AggregateExpression]| {
+            aggregates.iter().map(|aggregate| Accumulator::new(&aggregate.function)).collect::<Vec<_>>()
+        };
+        if self.group_by.is_empty() {
+            positions.insert(Vec::new(), 0);
+            groups.push((Vec::new(), new_accumulators(&self.aggregates)));
+        }
+
+        self.child.init()?;
+        while let Some(row) = self.child.next()? {
+            let keys = self.group_by.iter().map(|key| key.evaluate(&row.values)).collect::<CrabDbResult<Vec<_>>>()?;
+            let position = *positions.entry(group_key(&keys)).or_insert_with(|| {
+                groups.push((keys, new_accumulators(&self.aggregates)));
+                groups.len() - 1
+            });
+            for (aggregate, accumulator) in self.aggregates.iter().zip(groups[position].1.iter_mut()) {
+                let value = match aggregate.function {
+                    AggregateFunction::CountStar => Value::Null,
+                    _ => aggregate.argument.evaluate(&row.values)?,
+                };
+                accumulator.add(&aggregate.function, value)?;
+            }
+        }
+
+        let mut results = Vec::with_capacity(groups.len());
+        for (mut values, accumulators) in groups {
+            for accumulator in accumulators {
+                values.push(accumulator.finish()?);
+            }
+            if self.having.as_ref().map_or(Ok(true), |having| having.matches(&values))? {
+                results.push(values);
+            }
+        }
+        self.results = results.into_iter();
+        Ok(())
+    }
+
+    fn next(&mut self) -> CrabDbResult<Option<Row>> {
+        Ok(self.results.next().map(Row::new))
+    }
+
+    fn output_schema(&self) -> &Schema {
+        &self.schema
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::catalog::system_catalog::Catalog;
+    use crate::concurrency::lock_manager::LockManager;
+    use crate::concurrency::transaction::IsolationLevel;
+    use crate::concurrency::transaction_manager::TransactionManager;
+    use crate::execution::executor::{execute, ExecutorContext};
+    use crate::execution::expression::{CompareOp, Expression};
+    use crate::execution::seq_scan_executor::SeqScanExecutor;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use crate::storage::table::tuple::Tuple;
+    use crate::types::schema::{Column, Schema};
+    use crate::types::value::{DataType, Value};
+    use super::{AggregateExpression, AggregateFunction, AggregationExecutor};
+
+    #[test]
+    pub fn test_hash_aggregation() {
+        let bpm = Arc::new(BufferPoolManager::new(8, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(8, 2))));
+        let catalog = Arc::new(Catalog::open(bpm).unwrap());
+        let txn_manager = Arc::new(TransactionManager::new(Arc::new(LockManager::new())));
+        let schema = Schema::new(vec![
+            Column::new("beach", DataType::Varchar), Column::new("legs", DataType::Int32), Column::new("weight", DataType::Float64),
+        ]);
+        let table = catalog.create_table("crabs", schema.clone()).unwrap();
+        let empty = catalog.create_table("no_crabs", schema.clone()).unwrap();
+
+        let txn = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        let rows = [
+            ("north", Value::Int32(10), Value::Float64(1.5)),
+            ("south", Value::Int32(8), Value::Null),
+            ("north", Value::Null, Value::Float64(2.5)),
+            ("north", Value::Int32(6), Value::Float64(0.5)),
+            ("east", Value::Int32(10), Value::Float64(3.0)),
+        ];
+        for (beach, legs, weight) in rows {
+            let tuple = Tuple::from_values(&schema, &[Value::Varchar(beach.into()), legs, weight]).unwrap();
+            txn_manager.insert(&txn, table.heap(), &tuple).unwrap();
+        }
+        txn_manager.commit(&txn).unwrap();
+        let ctx = ExecutorContext::new(catalog, txn_manager.clone(), txn_manager.begin(IsolationLevel::SnapshotIsolation));
+
+        let aggregates = vec![
+            AggregateExpression::count_star(),
+            AggregateExpression::new(AggregateFunction::Count, Expression::Column(1)),
+            AggregateExpression::new(AggregateFunction::Sum, Expression::Column(1)),
+            AggregateExpression::new(AggregateFunction::Min, Expression::Column(2)),
+            AggregateExpression::new(AggregateFunction::Max, Expression::Column(1)),
+            AggregateExpression::new(AggregateFunction::Avg, Expression::Column(2)),
+        ];
+        let output = Schema::new(
+            ["beach", "count_star", "count", "sum", "min", "max", "avg"].iter()
+                .zip([DataType::Varchar, DataType::Int64, DataType::Int64, DataType::Int64, DataType::Float64, DataType::Int32, DataType::Float64])
+                .map(|(name, data_type)| Column::new(*name, data_type))
+                .collect(),
+        );
+        // SELECT beach, COUNT(*), COUNT(legs), SUM(legs), MIN(weight), MAX(legs), AVG(weight)
+        // FROM crabs GROUP BY beach HAVING COUNT(*) < 3
+        let having = Expression::compare(CompareOp::Lt, Expression::Column(1), Expression::Constant(Value::Int64(3)));
+        let mut aggregation = AggregationExecutor::new(
+            Box::new(SeqScanExecutor::new(&ctx, &table, None)),
+            vec![Expression::Column(0)],
+            aggregates.clone(),
+            Some(having),
+            output.clone(),
+        );
+        let values: Vec<_> = execute(&mut aggregation).unwrap().into_iter().map(|row| row.values).collect();
+        assert_eq!(vec![
+            vec![Value::Varchar("south".into()), Value::Int64(1), Value::Int64(1), Value::Int64(8), Value::Null, Value::Int32(8), Value::Null],
+            vec![Value::Varchar("east".into()), Value::Int64(1), Value::Int64(1), Value::Int64(10), Value::Float64(3.0), Value::Int32(10), Value::Float64(3.0)],
+        ], values);
+
+        let mut aggregation = AggregationExecutor::new(
+            Box::new(SeqScanExecutor::new(&ctx, &table, None)), vec![Expression::Column(0)], aggregates.clone(), None, output.clone(),
+        );
+        let north = &execute(&mut aggregation).unwrap()[0].values;
+        assert_eq!(
+            &vec![Value::Varchar("north".into()), Value::Int64(3), Value::Int64(2), Value::Int64(16), Value::Float64(0.5), Value::Int32(10), Value::Float64(1.5)],
+            north
+        );
+
+        // Without GROUP BY an empty input still makes one row.
+        let mut aggregation = AggregationExecutor::new(
+            Box::new(SeqScanExecutor::new(&ctx, &empty, None)), Vec::new(), aggregates, None, output,
+        );
+        assert_eq!(
+            vec![Value::Int64(0), Value::Int64(0), Value::Null, Value::Null, Value::Null, Value::Null],
+            execute(&mut aggregation).unwrap()[0].values
+        );
+
+        let mut bad = AggregationExecutor::new(
+            Box::new(SeqScanExecutor::new(&ctx, &table, None)),
+            Vec::new(),
+            vec![AggregateExpression::new(AggregateFunction::Sum, Expression::Column(0))],
+            None,
+            Schema::new(vec![Column::new("sum", DataType::Int64)]),
+        );
+        assert_eq!("Cannot apply arithmetic to BIGINT and VARCHAR", execute(&mut bad).unwrap_err().to_string());
+    }
+}
@@ -0,0 +1,58 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::platform::Stopwatch;
+use crate::types::schema::Schema;
+use crate::types::CrabDbResult;
+
+use super::executor::{Executor, Row};
+
+// What one operator did while a query ran, for EXPLAIN ANALYZE.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OperatorStats {
+    pub rows: u64,
+    // How many times the operator was (re)started, such as the inner side of a nested loop
+    // join once per outer row.
+    pub loops: u64,
+    // Time spent in the operator's `init` and `next`, including the time of its children.
+    pub elapsed: Duration,
+}
+
+// Runs its child unchanged while recording its OperatorStats.
+pub struct AnalyzeExecutor<'a> {
+    child: Box<dyn Executor + 'a>,
+    stats: Rc<RefCell<OperatorStats>>,
+}
+
+impl<'a> AnalyzeExecutor<'a> {
+    pub fn new(child: Box<dyn Executor + 'a>, stats: Rc<RefCell<OperatorStats>>) -> Self {
+        AnalyzeExecutor { child, stats }
+    }
+}
+
+impl Executor for AnalyzeExecutor<'_> {
+    fn init(&mut self) -> CrabDbResult<()> {
+        let start = Stopwatch::start();
+        let result = self.child.init();
+        let mut stats = self.stats.borrow_mut();
+        stats.loops += 1;
+        stats.elapsed += start.elapsed();
+        result
+    }
+
+    fn next(&mut self) -> CrabDbResult<Option<Row>> {
+        let start = Stopwatch::start();
+        let result = self.child.next();
+        let mut stats = self.stats.borrow_mut();
+        if matches!(result, Ok(Some(_))) {
+            stats.rows += 1;
+        }
+        stats.elapsed += start.elapsed();
+        result
+    }
+
+    fn output_schema(&self) -> &Schema {
+        self.child.output_schema()
+    }
+}
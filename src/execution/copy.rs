@@ -0,0 +1,527 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::catalog::table_info::{IndexInfo, TableInfo};
+use crate::catalog::trigger::TriggerEvent;
+use crate::concurrency::lock_manager::LockMode;
+use crate::platform;
+use crate::storage::table::tuple::{Rid, Tuple};
+use crate::types::geometry::Geometry;
+use crate::types::schema::Schema;
+use crate::types::value::{DataType, Value};
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::executor::ExecutorContext;
+use super::insert_executor::{insert_index_entry, insert_index_key};
+use super::trigger::Triggers;
+
+// Rows read from a file before they are written to the table.
+pub const COPY_BATCH_ROWS: usize = 1024;
+
+// Rows per Parquet row group written by an export.
+#[cfg(feature = "parquet")]
+pub const PARQUET_ROW_GROUP_ROWS: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyFormat {
+    // RFC 4180 CSV, one row per record in table column order. An empty unquoted field is
+    // NULL and a quoted one an empty string. Timestamps are microseconds since the epoch.
+    Csv { header: bool },
+    // Columns are matched by name. Needs the parquet feature.
+    Parquet,
+}
+
+// Loads the rows of the file at `path` into `table` in the context's transaction and
+// returns how many there were. The indexes are filled once the whole file is in, each in
+// key order.
+pub(crate) fn import(ctx: &ExecutorContext, table: &TableInfo, path: &Path, format: CopyFormat) -> CrabDbResult<u64> {
+    ctx.lock_table(table, LockMode::IntentionExclusive)?;
+    let mut loader = BulkLoader::new(ctx, table);
+    match format {
+        CopyFormat::Csv { header } => read_csv(path, table.schema(), header, |rows| loader.load(rows))?,
+        CopyFormat::Parquet => read_parquet(path, table.schema(), |rows| loader.load(rows))?,
+    }
+    loader.finish()
+}
+
+// Writes the rows of `table` the context's transaction sees, bar expired ones, to the file
+// at `path`, replacing it, and returns how many there were.
+pub(crate) fn export(ctx: &ExecutorContext, table: &TableInfo, path: &Path, format: CopyFormat) -> CrabDbResult<u64> {
+    ctx.lock_table(table, LockMode::IntentionShared)?;
+    let now = platform::clock().now_micros();
+    let rows = ctx.txn_manager().scan(ctx.txn(), table.heap())
+        .map(|entry| entry.and_then(|(_, tuple)| tuple.values(table.schema())))
+        .filter(|row| !row.as_ref().is_ok_and(|row| table.is_expired(row, now)));
+    match format {
+        CopyFormat::Csv { header } => write_csv(path, table.schema(), header, rows),
+        CopyFormat::Parquet => write_parquet(path, table.schema(), rows),
+    }
+}
+
+// Index keys of loaded rows with where the rows went.
+type IndexEntries = Vec<(Vec<u8>, Rid)>;
+
+// Loads rows into a table, indexing them once they are all in. When the table has INSERT
+// triggers, which may read it, each row is indexed as it is loaded instead.
+struct BulkLoader<'a> {
+    ctx: &'a ExecutorContext,
+    table: &'a TableInfo,
+    // The keys of the loaded rows, one list per index of the table.
+    index_keys: Vec<(Arc<IndexInfo>, IndexEntries)>,
+    triggers: Triggers<'a>,
+    count: u64,
+}
+
+impl<'a> BulkLoader<'a> {
+    fn new(ctx: &'a ExecutorContext, table: &'a TableInfo) -> Self {
+        let index_keys = ctx.catalog().indexes_of(table).into_iter().map(|index| (index, Vec::new())).collect();
+        let triggers = Triggers::of(ctx, table, TriggerEvent::Insert);
+        BulkLoader { ctx, table, index_keys, triggers, count: 0 }
+    }
+
+    fn load(&mut self, rows: Vec<Vec<Value>>) -> CrabDbResult<()> {
+        if !self.triggers.is_empty() {
+            return rows.into_iter().try_for_each(|row| self.load_with_triggers(row));
+        }
+        let tuples = rows.iter().map(|row| Tuple::from_values(self.table.schema(), row)).collect::<CrabDbResult<Vec<_>>>()?;
+        for tuple in &tuples {
+            let rid = self.ctx.txn_manager().insert(self.ctx.txn(), self.table.heap(), tuple)?;
+            if !self.index_keys.is_empty() {
+                let values = tuple.values(self.table.schema())?;
+                for (index, keys) in &mut self.index_keys {
+                    keys.extend(index.entries_for_row(&values, rid)?.into_iter().map(|key| (key, rid)));
+                }
+            }
+        }
+        self.count += tuples.len() as u64;
+        Ok(())
+    }
+
+    fn load_with_triggers(&mut self, mut row: Vec<Value>) -> CrabDbResult<()> {
+        if !self.triggers.before(None, Some(&mut row))? {
+            return Ok(());
+        }
+        let tuple = Tuple::from_values(self.table.schema(), &row)?;
+        let rid = self.ctx.txn_manager().insert(self.ctx.txn(), self.table.heap(), &tuple)?;
+        let mut values = tuple.values(self.table.schema())?;
+        for (index, _) in &self.index_keys {
+            insert_index_entry(self.ctx, self.table, index, &values, rid)?;
+        }
+        self.triggers.after(None, Some(&mut values))?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> CrabDbResult<u64> {
+        for (index, mut keys) in self.index_keys {
+            keys.sort_unstable();
+            for (key, rid) in keys {
+                insert_index_key(self.ctx, self.table, &index, &key, rid)?;
+            }
+        }
+        Ok(self.count)
+    }
+}
+
+fn read_csv(path: &Path, schema: &Schema, header: bool, mut load: impl FnMut(Vec<Vec<Value>>) -> CrabDbResult<()>) -> CrabDbResult<()> {
+    let file = File::open(path).map_err(|e| CrabDBError::io(format!("Failed to open {}", path.display()), e))?;
+    let mut reader = BufReader::new(file);
+    let read_error = |e| CrabDBError::io(format!("Failed to read {}", path.display()), e);
+    let (mut line, mut batch) = (0, Vec::with_capacity(COPY_BATCH_ROWS));
+    if header {
+        read_csv_record(&mut reader, &mut line).map_err(read_error)?;
+    }
+    while let Some(fields) = read_csv_record(&mut reader, &mut line).map_err(read_error)? {
+        if fields.len() != schema.column_count() {
+            return Err(CrabDBError::InvalidInput(format!(
+                "Line {line} of {} has {} fields but the table has {} columns", path.display(), fields.len(), schema.column_count()
+            )));
+        }
+        let row = fields.iter().zip(schema.columns())
+            .map(|(field, column)| match field {
+                None => Ok(Value::Null),
+                Some(text) => parse_csv_field(text, column.data_type()),
+            })
+            .collect::<CrabDbResult<Vec<_>>>()
+            .map_err(|e| CrabDBError::InvalidInput(format!("Line {line} of {}: {e}", path.display())))?;
+        batch.push(row);
+        if batch.len() == COPY_BATCH_ROWS {
+            load(std::mem::replace(&mut batch, Vec::with_capacity(COPY_BATCH_ROWS)))?;
+        }
+    }
+    if !batch.is_empty() {
+        load(batch)?;
+    }
+    Ok(())
+}
+
+// Reads the next record, whose quoted fields may span lines, and advances `line` to the
+// line it ends on. Blank lines are skipped. Fields are None when empty and unquoted.
+fn read_csv_record(reader: &mut impl BufRead, line: &mut usize) -> std::io::Result<Option<Vec<Option<String>>>> {
+    let mut bytes = Vec::new();
+    loop {
+        bytes.clear();
+        if reader.read_until(b'\n', &mut bytes)? == 0 {
+            return Ok(None);
+        }
+        *line += 1;
+        if !matches!(bytes.as_slice(), b"\n" | b"\r\n") {
+            break;
+        }
+    }
+    let (mut fields, mut field, mut quoted, mut in_quotes, mut i) = (Vec::new(), Vec::new(), false, false, 0);
+    loop {
+        let Some(&byte) = bytes.get(i) else {
+            if !in_quotes {
+                break;
+            }
+            if reader.read_until(b'\n', &mut bytes)? == 0 {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Quoted field on line {line} is not closed")));
+            }
+            *line += 1;
+            continue;
+        };
+        i += 1;
+        if in_quotes {
+            match byte {
+                b'"' if bytes.get(i) == Some(&b'"') => {
+                    field.push(b'"');
+                    i += 1;
+                },
+                b'"' => in_quotes = false,
+                _ => field.push(byte),
+            }
+            continue;
+        }
+        match byte {
+            b'"' if field.is_empty() && !quoted => (in_quotes, quoted) = (true, true),
+            b',' => fields.push(csv_field(std::mem::take(&mut field), std::mem::take(&mut quoted))?),
+            b'\n' => break,
+            b'\r' if bytes.get(i) == Some(&b'\n') => (),
+            _ => field.push(byte),
+        }
+    }
+    fields.push(csv_field(field, quoted)?);
+    Ok(Some(fields))
+}
+
+fn csv_field(bytes: Vec<u8>, quoted: bool) -> std::io::Result<Option<String>> {
+    if bytes.is_empty() && !quoted {
+        return Ok(None);
+    }
+    String::from_utf8(bytes).map(Some).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+fn parse_csv_field(text: &str, data_type: DataType) -> CrabDbResult<Value> {
+    let invalid = || CrabDBError::InvalidInput(format!("{text:?} is not a valid {data_type}"));
+    let value = match data_type {
+        DataType::Boolean => match text.to_ascii_lowercase().as_str() {
+            "true" | "t" | "1" => Value::Boolean(true),
+            "false" | "f" | "0" => Value::Boolean(false),
+            _ => return Err(invalid()),
+        },
+        DataType::Int32 => Value::Int32(text.trim().parse().map_err(|_| invalid())?),
+        DataType::Int64 => Value::Int64(text.trim().parse().map_err(|_| invalid())?),
+        DataType::Float64 => Value::Float64(text.trim().parse().map_err(|_| invalid())?),
+        DataType::Varchar => Value::Varchar(text.to_string()),
+        DataType::Timestamp => Value::Timestamp(text.trim().parse().map_err(|_| invalid())?),
+        DataType::Vector(_) => Value::parse_vector(text).and_then(|vector| vector.cast_to(data_type)).map_err(|_| invalid())?,
+        DataType::Geometry => Value::Geometry(Geometry::parse(text).map_err(|_| invalid())?),
+    };
+    Ok(value)
+}
+
+fn write_csv(path: &Path, schema: &Schema, header: bool, rows: impl Iterator<Item = CrabDbResult<Vec<Value>>>) -> CrabDbResult<u64> {
+    let write_error = |e| CrabDBError::io(format!("Failed to write {}", path.display()), e);
+    let file = File::create(path).map_err(write_error)?;
+    let mut writer = BufWriter::new(file);
+    if header {
+        let names: Vec<_> = schema.columns().iter().map(|column| csv_text(column.name())).collect();
+        writeln!(writer, "{}", names.join(",")).map_err(write_error)?;
+    }
+    let mut count = 0;
+    for row in rows {
+        let fields: Vec<_> = row?.iter()
+            .map(|value| match value {
+                Value::Null => String::new(),
+                Value::Varchar(s) => csv_text(s),
+                // The elements, and the corners of a box, are separated by commas too.
+                Value::Vector(_) | Value::Geometry(_) => csv_text(&value.to_string()),
+                other => other.to_string(),
+            })
+            .collect();
+        writeln!(writer, "{}", fields.join(",")).map_err(write_error)?;
+        count += 1;
+    }
+    writer.flush().map_err(write_error)?;
+    Ok(count)
+}
+
+// Quotes text that would otherwise read back as something else.
+fn csv_text(text: &str) -> String {
+    if text.is_empty() || text.contains([',', '"', '\r', '\n']) {
+        return format!("\"{}\"", text.replace('"', "\"\""));
+    }
+    text.to_string()
+}
+
+#[cfg(not(feature = "parquet"))]
+fn parquet_unavailable() -> CrabDBError {
+    CrabDBError::InvalidInput("Parquet files need crab-db built with the parquet feature".to_string())
+}
+
+#[cfg(not(feature = "parquet"))]
+fn read_parquet(_path: &Path, _schema: &Schema, _load: impl FnMut(Vec<Vec<Value>>) -> CrabDbResult<()>) -> CrabDbResult<()> {
+    Err(parquet_unavailable())
+}
+
+#[cfg(not(feature = "parquet"))]
+fn write_parquet(_path: &Path, _schema: &Schema, _rows: impl Iterator<Item = CrabDbResult<Vec<Value>>>) -> CrabDbResult<u64> {
+    Err(parquet_unavailable())
+}
+
+#[cfg(feature = "parquet")]
+fn read_parquet(path: &Path, schema: &Schema, mut load: impl FnMut(Vec<Vec<Value>>) -> CrabDbResult<()>) -> CrabDbResult<()> {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::Field;
+
+    let file = File::open(path).map_err(|e| CrabDBError::io(format!("Failed to open {}", path.display()), e))?;
+    let read_error = |e: parquet::errors::ParquetError| CrabDBError::InvalidInput(format!("Cannot read {}: {e}", path.display()));
+    let reader = SerializedFileReader::new(file).map_err(read_error)?;
+    let fields = reader.metadata().file_metadata().schema_descr().root_schema().get_fields().to_vec();
+    let positions = schema.columns().iter()
+        .map(|column| {
+            fields.iter().position(|field| field.name().eq_ignore_ascii_case(column.name())).ok_or_else(|| {
+                CrabDBError::InvalidInput(format!("{} has no column {}", path.display(), column.name()))
+            })
+        })
+        .collect::<CrabDbResult<Vec<_>>>()?;
+
+    let field_value = |field: &Field, data_type: DataType| {
+        let value = match field {
+            Field::Null => Value::Null,
+            Field::Bool(b) => Value::Boolean(*b),
+            Field::Byte(i) => Value::Int32(*i as i32),
+            Field::Short(i) => Value::Int32(*i as i32),
+            Field::Int(i) => Value::Int32(*i),
+            Field::UByte(i) => Value::Int32(*i as i32),
+            Field::UShort(i) => Value::Int32(*i as i32),
+            Field::UInt(i) => Value::Int64(*i as i64),
+            Field::Long(i) => Value::Int64(*i),
+            Field::Float(f) => Value::Float64(*f as f64),
+            Field::Double(f) => Value::Float64(*f),
+            Field::Str(s) => Value::Varchar(s.clone()),
+            Field::TimestampMillis(ms) => Value::Timestamp(ms.saturating_mul(1000)),
+            Field::TimestampMicros(us) => Value::Timestamp(*us),
+            other => return Err(CrabDBError::InvalidInput(format!("Cannot load the Parquet value {other} into a {data_type} column"))),
+        };
+        value.cast_to(data_type)
+    };
+
+    let mut batch = Vec::with_capacity(COPY_BATCH_ROWS);
+    for row in reader.get_row_iter(None).map_err(read_error)? {
+        let row = row.map_err(read_error)?;
+        let row_fields: Vec<_> = row.get_column_iter().map(|(_, field)| field).collect();
+        let values = positions.iter().zip(schema.columns())
+            .map(|(&position, column)| field_value(row_fields[position], column.data_type()))
+            .collect::<CrabDbResult<Vec<_>>>()?;
+        batch.push(values);
+        if batch.len() == COPY_BATCH_ROWS {
+            load(std::mem::replace(&mut batch, Vec::with_capacity(COPY_BATCH_ROWS)))?;
+        }
+    }
+    if !batch.is_empty() {
+        load(batch)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "parquet")]
+fn write_parquet(path: &Path, schema: &Schema, rows: impl Iterator<Item = CrabDbResult<Vec<Value>>>) -> CrabDbResult<u64> {
+    use parquet::basic::{LogicalType, Repetition, TimeUnit, Type as PhysicalType};
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::format::MicroSeconds;
+    use parquet::schema::types::Type;
+
+    let write_error = |e: parquet::errors::ParquetError| CrabDBError::new(format!("Failed to write {}: {e}", path.display()));
+    let fields = schema.columns().iter()
+        .map(|column| {
+            let (physical_type, logical_type) = match column.data_type() {
+                DataType::Boolean => (PhysicalType::BOOLEAN, None),
+                DataType::Int32 => (PhysicalType::INT32, None),
+                DataType::Int64 => (PhysicalType::INT64, None),
+                DataType::Float64 => (PhysicalType::DOUBLE, None),
+                // Vectors and geometries are written as text, the way they print.
+                DataType::Varchar | DataType::Vector(_) | DataType::Geometry => (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+                DataType::Timestamp => (PhysicalType::INT64, Some(LogicalType::Timestamp {
+                    is_adjusted_to_u_t_c: true,
+                    unit: TimeUnit::MICROS(MicroSeconds {}),
+                })),
+            };
+            let repetition = if column.is_nullable() { Repetition::OPTIONAL } else { Repetition::REQUIRED };
+            Type::primitive_type_builder(column.name(), physical_type)
+                .with_repetition(repetition)
+                .with_logical_type(logical_type)
+                .build()
+                .map(Arc::new)
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(write_error)?;
+    let file_schema = Arc::new(Type::group_type_builder("schema").with_fields(fields).build().map_err(write_error)?);
+    let file = File::create(path).map_err(|e| CrabDBError::io(format!("Failed to write {}", path.display()), e))?;
+    let properties = Arc::new(WriterProperties::builder().set_max_row_group_size(PARQUET_ROW_GROUP_ROWS).build());
+    let mut writer = SerializedFileWriter::new(file, file_schema, properties).map_err(write_error)?;
+
+    let (mut count, mut batch) = (0, Vec::with_capacity(PARQUET_ROW_GROUP_ROWS));
+    let mut rows = rows.peekable();
+    while rows.peek().is_some() {
+        batch.clear();
+        while batch.len() < PARQUET_ROW_GROUP_ROWS {
+            match rows.next() {
+                Some(row) => batch.push(row?),
+                None => break,
+            }
+        }
+        let mut row_group = writer.next_row_group().map_err(write_error)?;
+        for (i, column) in schema.columns().iter().enumerate() {
+            let mut column_writer = row_group.next_column().map_err(write_error)?
+                .ok_or_else(|| CrabDBError::new(format!("Parquet writer ran out of columns at {}", column.name())))?;
+            let values: Vec<_> = batch.iter().map(|row: &Vec<Value>| &row[i]).collect();
+            write_parquet_column(&mut column_writer, column.is_nullable(), column.data_type(), &values).map_err(write_error)?;
+            column_writer.close().map_err(write_error)?;
+        }
+        row_group.close().map_err(write_error)?;
+        count += batch.len() as u64;
+    }
+    writer.close().map_err(write_error)?;
+    Ok(count)
+}
+
+// Writes one column of a row group. An optional column marks its NULLs with definition
+// level 0 and leaves them out of the values.
+#[cfg(feature = "parquet")]
+fn write_parquet_column(
+    writer: &mut parquet::file::writer::SerializedColumnWriter<'_>,
+    nullable: bool,
+    data_type: DataType,
+    values: &[&Value],
+) -> parquet::errors::Result<()> {
+    use parquet::data_type::{BoolType, ByteArray, ByteArrayType, DoubleType, Int32Type, Int64Type};
+
+    let def_levels: Vec<i16> = values.iter().map(|value| i16::from(!value.is_null())).collect();
+    let def_levels = nullable.then_some(def_levels.as_slice());
+    match data_type {
+        DataType::Boolean => {
+            let values: Vec<_> = values.iter().filter_map(|value| value.as_bool()).collect();
+            writer.typed::<BoolType>().write_batch(&values, def_levels, None)?;
+        },
+        DataType::Int32 => {
+            let values: Vec<_> = values.iter().filter_map(|value| value.as_i64().map(|i| i as i32)).collect();
+            writer.typed::<Int32Type>().write_batch(&values, def_levels, None)?;
+        },
+        DataType::Int64 | DataType::Timestamp => {
+            let values: Vec<_> = values.iter().filter_map(|value| value.as_i64()).collect();
+            writer.typed::<Int64Type>().write_batch(&values, def_levels, None)?;
+        },
+        DataType::Float64 => {
+            let values: Vec<_> = values.iter().filter_map(|value| value.as_f64()).collect();
+            writer.typed::<DoubleType>().write_batch(&values, def_levels, None)?;
+        },
+        DataType::Varchar => {
+            let values: Vec<_> = values.iter().filter_map(|value| value.as_str().map(ByteArray::from)).collect();
+            writer.typed::<ByteArrayType>().write_batch(&values, def_levels, None)?;
+        },
+        DataType::Vector(_) | DataType::Geometry => {
+            let values: Vec<_> = values.iter().filter(|value| !value.is_null()).map(|value| ByteArray::from(value.to_string().as_str())).collect();
+            writer.typed::<ByteArrayType>().write_batch(&values, def_levels, None)?;
+        },
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use tempfile::TempDir;
+
+    use crate::db::crab_db::CrabDb;
+    use crate::db::options::CrabDbOptions;
+    use crate::index::table_index::IndexKind;
+    use crate::types::value::Value;
+    use super::{read_csv_record, CopyFormat};
+
+    #[test]
+    pub fn test_read_csv_record() {
+        let mut reader = Cursor::new("1,\"a, \"\"quoted\"\"\nline\",,\"\"\r\n\n2,plain,x,y");
+        let mut line = 0;
+        let fields = read_csv_record(&mut reader, &mut line).unwrap().unwrap();
+        assert_eq!(vec![Some("1".to_string()), Some("a, \"quoted\"\nline".to_string()), None, Some(String::new())], fields);
+        assert_eq!(2, line);
+        let fields = read_csv_record(&mut reader, &mut line).unwrap().unwrap();
+        assert_eq!(vec![Some("2"), Some("plain"), Some("x"), Some("y")], fields.iter().map(|f| f.as_deref()).collect::<Vec<_>>());
+        assert_eq!(4, line);
+        assert!(read_csv_record(&mut reader, &mut line).unwrap().is_none());
+
+        assert!(read_csv_record(&mut Cursor::new("\"open"), &mut 0).is_err());
+    }
+
+    #[test]
+    pub fn test_copy_round_trips_csv() {
+        let dir = TempDir::new().unwrap();
+        let db = CrabDb::open(dir.path().join("crabs.db"), CrabDbOptions::default()).unwrap();
+        db.execute("CREATE TABLE crabs (id BIGINT NOT NULL, name VARCHAR, shy BOOLEAN, weight DOUBLE)").unwrap();
+        db.execute("CREATE TABLE copies (id BIGINT NOT NULL, name VARCHAR, shy BOOLEAN, weight DOUBLE)").unwrap();
+        db.catalog().create_index("copies_id", "copies", &["id"], IndexKind::BPlusTree).unwrap();
+        db.execute("INSERT INTO crabs VALUES (1, 'ferris', false, 1.5), (2, 'says \"hi\", then\nleaves', true, NULL), (3, '', NULL, 2.0)").unwrap();
+
+        let csv = dir.path().join("crabs.csv");
+        assert_eq!(3, db.execute(&format!("COPY crabs TO '{}' WITH (FORMAT csv, HEADER)", csv.display())).unwrap());
+        assert!(std::fs::read_to_string(&csv).unwrap().starts_with("id,name,shy,weight\n1,ferris,false,1.5\n"));
+        assert_eq!(3, db.execute(&format!("COPY copies FROM '{}' CSV HEADER", csv.display())).unwrap());
+        let query = "SELECT * FROM crabs ORDER BY id";
+        assert_eq!(
+            db.query(query).unwrap().fetch_all().unwrap(),
+            db.query(&query.replace("crabs", "copies")).unwrap().fetch_all().unwrap()
+        );
+        // the index was filled, and a file repeating a key loads nothing
+        assert_eq!(vec![vec![Value::Varchar(String::new())]], db.query("SELECT name FROM copies WHERE id = 3").unwrap().fetch_all().unwrap());
+        assert!(db.import("copies", &csv, CopyFormat::Csv { header: true }).is_err());
+        assert_eq!(3, db.execute("SELECT * FROM copies").unwrap());
+
+        let headless = dir.path().join("headless.csv");
+        assert_eq!(3, db.export("copies", &headless, CopyFormat::Csv { header: false }).unwrap());
+        db.execute("DELETE FROM copies").unwrap();
+        assert_eq!(3, db.import("copies", &headless, CopyFormat::Csv { header: false }).unwrap());
+        assert_eq!(3, db.execute("SELECT * FROM copies WHERE id >= 1").unwrap());
+
+        std::fs::write(&headless, "4,crab\n").unwrap();
+        assert!(db.import("copies", &headless, CopyFormat::Csv { header: false }).unwrap_err().to_string().contains("Line 1"));
+        std::fs::write(&headless, "x,crab,true,1\n").unwrap();
+        assert!(db.import("copies", &headless, CopyFormat::Csv { header: false }).is_err());
+        assert!(db.execute(&format!("COPY copies FROM '{}' WITH (FORMAT json)", headless.display())).is_err());
+        assert!(db.execute("COPY copies TO STDOUT").is_err());
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    pub fn test_copy_round_trips_parquet() {
+        let dir = TempDir::new().unwrap();
+        let db = CrabDb::open(dir.path().join("crabs.db"), CrabDbOptions::default()).unwrap();
+        db.execute("CREATE TABLE crabs (id INT NOT NULL, name VARCHAR, shy BOOLEAN, seen TIMESTAMP, weight DOUBLE)").unwrap();
+        db.execute("CREATE TABLE copies (weight DOUBLE, seen TIMESTAMP, shy BOOLEAN, name VARCHAR, id INT NOT NULL)").unwrap();
+        db.execute("INSERT INTO crabs VALUES (1, 'ferris', false, 1700000000000000, 1.5), (2, NULL, true, NULL, NULL)").unwrap();
+
+        let parquet = dir.path().join("crabs.parquet");
+        assert_eq!(2, db.execute(&format!("COPY crabs TO '{}' (FORMAT parquet)", parquet.display())).unwrap());
+        // columns are matched by name
+        assert_eq!(2, db.import("copies", &parquet, CopyFormat::Parquet).unwrap());
+        assert_eq!(
+            db.query("SELECT id, name, shy, seen, weight FROM crabs ORDER BY id").unwrap().fetch_all().unwrap(),
+            db.query("SELECT id, name, shy, seen, weight FROM copies ORDER BY id").unwrap().fetch_all().unwrap()
+        );
+    }
+}
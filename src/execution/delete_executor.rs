@@ -0,0 +1,60 @@
+use crate::catalog::table_info::TableInfo;
+use crate::catalog::trigger::TriggerEvent;
+use crate::concurrency::lock_manager::LockMode;
+use crate::types::schema::Schema;
+use crate::types::value::Value;
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::executor::{Executor, ExecutorContext, Row};
+use super::insert_executor::count_schema;
+use super::trigger::Triggers;
+
+// Deletes the rows its child reads from a table, which must come with their rids, calling
+// the table's DELETE triggers around each row. Index entries are left in place; see
+// `insert_index_entry`.
+pub struct DeleteExecutor<'a> {
+    ctx: &'a ExecutorContext,
+    table: &'a TableInfo,
+    child: Box<dyn Executor + 'a>,
+    schema: Schema,
+    count: Option<i64>,
+}
+
+impl<'a> DeleteExecutor<'a> {
+    pub fn new(ctx: &'a ExecutorContext, table: &'a TableInfo, child: Box<dyn Executor + 'a>) -> Self {
+        DeleteExecutor { ctx, table, child, schema: count_schema(), count: None }
+    }
+}
+
+impl Executor for DeleteExecutor<'_> {
+    fn init(&mut self) -> CrabDbResult<()> {
+        self.ctx.lock_table(self.table, LockMode::IntentionExclusive)?;
+        let mut rows = Vec::new();
+        self.child.init()?;
+        while let Some(row) = self.child.next()? {
+            let rid = row.rid.ok_or_else(|| CrabDBError::new("Rows to delete must come from a table".into()))?;
+            rows.push((rid, row.values));
+        }
+        let triggers = Triggers::of(self.ctx, self.table, TriggerEvent::Delete);
+        let mut count = 0;
+        for (rid, old) in &rows {
+            self.ctx.lock_row(self.table, LockMode::Exclusive, *rid)?;
+            if !triggers.before(Some(old), None)? {
+                continue;
+            }
+            self.ctx.txn_manager().delete(self.ctx.txn(), self.table.heap(), *rid)?;
+            triggers.after(Some(old), None)?;
+            count += 1;
+        }
+        self.count = Some(count);
+        Ok(())
+    }
+
+    fn next(&mut self) -> CrabDbResult<Option<Row>> {
+        Ok(self.count.take().map(|count| Row::new(vec![Value::Int64(count)])))
+    }
+
+    fn output_schema(&self) -> &Schema {
+        &self.schema
+    }
+}
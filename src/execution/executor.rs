@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use crate::catalog::system_catalog::Catalog;
+use crate::catalog::table_info::TableInfo;
+use crate::concurrency::lock_manager::LockMode;
+use crate::concurrency::transaction::{IsolationLevel, Transaction};
+use crate::concurrency::transaction_manager::TransactionManager;
+use crate::storage::table::tuple::Rid;
+use crate::types::schema::Schema;
+use crate::types::value::Value;
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::trigger::MAX_TRIGGER_DEPTH;
+
+// A row flowing between executors. Rows read straight from a table remember where they
+// came from so updates and deletes can find them again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    pub values: Vec<Value>,
+    pub rid: Option<Rid>,
+}
+
+impl Row {
+    pub fn new(values: Vec<Value>) -> Self {
+        Row { values, rid: None }
+    }
+}
+
+// Everything executors of one query share: the catalog to resolve tables in and the
+// transaction the query runs in.
+pub struct ExecutorContext {
+    catalog: Arc<Catalog>,
+    txn_manager: Arc<TransactionManager>,
+    txn: Arc<Transaction>,
+    // How many triggers the query runs inside of.
+    trigger_depth: usize,
+}
+
+impl ExecutorContext {
+    pub fn new(catalog: Arc<Catalog>, txn_manager: Arc<TransactionManager>, txn: Arc<Transaction>) -> Self {
+        ExecutorContext { catalog, txn_manager, txn, trigger_depth: 0 }
+    }
+
+    // The context of a statement a trigger runs, in the same transaction.
+    pub(crate) fn nested(&self) -> CrabDbResult<ExecutorContext> {
+        if self.trigger_depth == MAX_TRIGGER_DEPTH {
+            return Err(CrabDBError::InvalidInput(format!("Triggers nest more than {MAX_TRIGGER_DEPTH} deep")));
+        }
+        Ok(ExecutorContext {
+            catalog: self.catalog.clone(),
+            txn_manager: self.txn_manager.clone(),
+            txn: self.txn.clone(),
+            trigger_depth: self.trigger_depth + 1,
+        })
+    }
+
+    pub fn catalog(&self) -> &Catalog {
+        &self.catalog
+    }
+
+    pub fn txn_manager(&self) -> &TransactionManager {
+        &self.txn_manager
+    }
+
+    pub fn txn(&self) -> &Transaction {
+        &self.txn
+    }
+
+    // Locks `table` in `mode` for the rest of the transaction: an intention mode before its
+    // rows are locked one by one.
+    pub(crate) fn lock_table(&self, table: &TableInfo, mode: LockMode) -> CrabDbResult<()> {
+        self.txn_manager.lock_manager().lock_table(&self.txn, mode, table.oid())
+    }
+
+    pub(crate) fn lock_row(&self, table: &TableInfo, mode: LockMode, rid: Rid) -> CrabDbResult<()> {
+        self.txn_manager.lock_manager().lock_row(&self.txn, mode, table.oid(), rid)
+    }
+
+    // Locks a row a scan returns in shared mode if the transaction is serializable, so no
+    // other transaction writes it until this one ends. Under snapshot isolation readers take
+    // no row locks and never block writers.
+    pub(crate) fn lock_read(&self, table: &TableInfo, rid: Rid) -> CrabDbResult<()> {
+        match self.txn.isolation() {
+            IsolationLevel::Serializable => self.lock_row(table, LockMode::Shared, rid),
+            IsolationLevel::SnapshotIsolation => Ok(()),
+        }
+    }
+}
+
+// A Volcano-style operator: `init` (re)starts it, then each `next` pulls one row until it
+// returns None. Executors form a tree, each pulling rows from its children on demand.
+pub trait Executor {
+    fn init(&mut self) -> CrabDbResult<()>;
+    fn next(&mut self) -> CrabDbResult<Option<Row>>;
+    fn output_schema(&self) -> &Schema;
+}
+
+// Runs an executor to completion, collecting its rows.
+pub fn execute(executor: &mut dyn Executor) -> CrabDbResult<Vec<Row>> {
+    executor.init()?;
+    let mut rows = Vec::new();
+    while let Some(row) = executor.next()? {
+        rows.push(row);
+    }
+    Ok(rows)
+}
@@ -0,0 +1,450 @@
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::sync::Arc;
+
+use crate::catalog::udf::ScalarUdf;
+use crate::index::full_text::{tokenize, Bm25Statistics};
+use crate::index::hnsw::l2_distance;
+use crate::types::schema::Schema;
+use crate::types::value::{DataType, Value};
+use crate::types::{CrabDBError, CrabDbResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+impl CompareOp {
+    fn matches(&self, ordering: Ordering) -> bool {
+        match self {
+            CompareOp::Eq => ordering == Ordering::Equal,
+            CompareOp::NotEq => ordering != Ordering::Equal,
+            CompareOp::Lt => ordering == Ordering::Less,
+            CompareOp::LtEq => ordering != Ordering::Greater,
+            CompareOp::Gt => ordering == Ordering::Greater,
+            CompareOp::GtEq => ordering != Ordering::Less,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithmeticOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpatialOp {
+    // Whether the left geometry holds the right one, boundary included.
+    Contains,
+    Intersects,
+}
+
+// A scalar expression over the columns of a row. Evaluation follows SQL's rules for NULL:
+// it propagates through comparisons and arithmetic, and AND/OR use three-valued logic.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    // The value at this position of the input row.
+    Column(usize),
+    Constant(Value),
+    Compare(CompareOp, Box<Expression>, Box<Expression>),
+    Arithmetic(ArithmeticOp, Box<Expression>, Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    IsNull(Box<Expression>),
+    // Whether the text holds every one of the terms (see `full_text::tokenize`).
+    Match(Box<Expression>, Vec<String>),
+    // How relevant the text is to the terms, scored with BM25 against the statistics of a
+    // full-text index taken when the query was bound.
+    Bm25(Box<Expression>, Vec<String>, Bm25Statistics),
+    // The Euclidean distance between two vectors, or between the closest points of two
+    // geometries, written `<->`.
+    Distance(Box<Expression>, Box<Expression>),
+    // A test of how two geometries lie, written ST_CONTAINS(a, b) or ST_INTERSECTS(a, b).
+    Spatial(SpatialOp, Box<Expression>, Box<Expression>),
+    // A user-defined scalar function applied to its arguments.
+    Call(Arc<ScalarUdf>, Vec<Expression>),
+}
+
+impl Expression {
+    pub fn compare(op: CompareOp, left: Expression, right: Expression) -> Self {
+        Expression::Compare(op, Box::new(left), Box::new(right))
+    }
+
+    pub fn arithmetic(op: ArithmeticOp, left: Expression, right: Expression) -> Self {
+        Expression::Arithmetic(op, Box::new(left), Box::new(right))
+    }
+
+    pub fn and(left: Expression, right: Expression) -> Self {
+        Expression::And(Box::new(left), Box::new(right))
+    }
+
+    pub fn or(left: Expression, right: Expression) -> Self {
+        Expression::Or(Box::new(left), Box::new(right))
+    }
+
+    pub fn evaluate(&self, row: &[Value]) -> CrabDbResult<Value> {
+        match self {
+            Expression::Column(index) => row.get(*index).cloned().ok_or_else(|| CrabDBError::new(format!(
+                "Column {index} is out of range for a row of {} columns", row.len()
+            ))),
+            Expression::Constant(value) => Ok(value.clone()),
+            Expression::Compare(op, left, right) => {
+                let (left, right) = (left.evaluate(row)?, right.evaluate(row)?);
+                if left.is_null() || right.is_null() {
+                    return Ok(Value::Null);
+                }
+                let ordering = left.compare(&right).ok_or_else(|| CrabDBError::InvalidInput(format!(
+                    "Cannot compare {} with {}", type_name(&left), type_name(&right)
+                )))?;
+                Ok(Value::Boolean(op.matches(ordering)))
+            },
+            Expression::Arithmetic(op, left, right) => arithmetic(*op, left.evaluate(row)?, right.evaluate(row)?),
+            Expression::And(left, right) => {
+                match (truth(&left.evaluate(row)?)?, truth(&right.evaluate(row)?)?) {
+                    (Some(false), _) | (_, Some(false)) => Ok(Value::Boolean(false)),
+                    (Some(true), Some(true)) => Ok(Value::Boolean(true)),
+                    _ => Ok(Value::Null),
+                }
+            },
+            Expression::Or(left, right) => {
+                match (truth(&left.evaluate(row)?)?, truth(&right.evaluate(row)?)?) {
+                    (Some(true), _) | (_, Some(true)) => Ok(Value::Boolean(true)),
+                    (Some(false), Some(false)) => Ok(Value::Boolean(false)),
+                    _ => Ok(Value::Null),
+                }
+            },
+            Expression::Not(inner) => Ok(truth(&inner.evaluate(row)?)?.map_or(Value::Null, |b| Value::Boolean(!b))),
+            Expression::IsNull(inner) => Ok(Value::Boolean(inner.evaluate(row)?.is_null())),
+            Expression::Match(text, terms) => Ok(match text.evaluate(row)? {
+                Value::Null => Value::Null,
+                Value::Varchar(text) => {
+                    let tokens = tokenize(&text);
+                    Value::Boolean(terms.iter().all(|term| tokens.contains(term)))
+                },
+                other => return Err(CrabDBError::InvalidInput(format!("MATCH needs a VARCHAR but got {}", type_name(&other)))),
+            }),
+            Expression::Bm25(text, terms, statistics) => Ok(match text.evaluate(row)? {
+                Value::Null => Value::Null,
+                Value::Varchar(text) => Value::Float64(statistics.score(terms, &text)),
+                other => return Err(CrabDBError::InvalidInput(format!("BM25 needs a VARCHAR but got {}", type_name(&other)))),
+            }),
+            Expression::Distance(left, right) => Ok(match (left.evaluate(row)?, right.evaluate(row)?) {
+                (Value::Null, _) | (_, Value::Null) => Value::Null,
+                (Value::Vector(a), Value::Vector(b)) if a.len() == b.len() => Value::Float64(l2_distance(&a, &b) as f64),
+                (Value::Geometry(a), Value::Geometry(b)) => Value::Float64(a.distance(&b)),
+                (a, b) => return Err(CrabDBError::InvalidInput(format!(
+                    "Cannot compute the distance between {} and {}", type_name(&a), type_name(&b)
+                ))),
+            }),
+            Expression::Spatial(op, left, right) => Ok(match (left.evaluate(row)?, right.evaluate(row)?) {
+                (Value::Null, _) | (_, Value::Null) => Value::Null,
+                (Value::Geometry(a), Value::Geometry(b)) => Value::Boolean(match op {
+                    SpatialOp::Contains => a.contains(&b),
+                    SpatialOp::Intersects => a.intersects(&b),
+                }),
+                (a, b) => return Err(CrabDBError::InvalidInput(format!(
+                    "{op} needs two GEOMETRY values but got {} and {}", type_name(&a), type_name(&b)
+                ))),
+            }),
+            Expression::Call(function, arguments) => {
+                function.call(arguments.iter().map(|argument| argument.evaluate(row)).collect::<CrabDbResult<_>>()?)
+            },
+        }
+    }
+
+    // The type of the values this expression produces over rows of `input`. Integer
+    // arithmetic is typed BIGINT since it may overflow an INT, and a bare NULL has no type of
+    // its own, so it is typed as the widest integer.
+    pub fn data_type(&self, input: &Schema) -> DataType {
+        match self {
+            Expression::Column(index) => input.column(*index).data_type(),
+            Expression::Constant(value) => value.data_type().unwrap_or(DataType::Int64),
+            Expression::Arithmetic(_, left, right) => {
+                match (left.data_type(input), right.data_type(input)) {
+                    (DataType::Float64, _) | (_, DataType::Float64) => DataType::Float64,
+                    _ => DataType::Int64,
+                }
+            },
+            Expression::Compare(..) | Expression::And(..) | Expression::Or(..) | Expression::Not(_) | Expression::IsNull(_)
+            | Expression::Match(..) | Expression::Spatial(..) => DataType::Boolean,
+            Expression::Bm25(..) | Expression::Distance(..) => DataType::Float64,
+            Expression::Call(function, _) => function.return_type(),
+        }
+    }
+
+    // Whether a row passes this expression used as a filter: only TRUE does, NULL does not.
+    pub fn matches(&self, row: &[Value]) -> CrabDbResult<bool> {
+        Ok(truth(&self.evaluate(row)?)? == Some(true))
+    }
+
+    // The same expression with every column reference passed through `f`, for moving an
+    // expression onto a row laid out differently.
+    pub fn remap_columns(&self, f: &impl Fn(usize) -> usize) -> Expression {
+        let remap = |expression: &Expression| Box::new(expression.remap_columns(f));
+        match self {
+            Expression::Column(index) => Expression::Column(f(*index)),
+            Expression::Constant(value) => Expression::Constant(value.clone()),
+            Expression::Compare(op, left, right) => Expression::Compare(*op, remap(left), remap(right)),
+            Expression::Arithmetic(op, left, right) => Expression::Arithmetic(*op, remap(left), remap(right)),
+            Expression::And(left, right) => Expression::And(remap(left), remap(right)),
+            Expression::Or(left, right) => Expression::Or(remap(left), remap(right)),
+            Expression::Not(inner) => Expression::Not(remap(inner)),
+            Expression::IsNull(inner) => Expression::IsNull(remap(inner)),
+            Expression::Match(text, terms) => Expression::Match(remap(text), terms.clone()),
+            Expression::Bm25(text, terms, statistics) => Expression::Bm25(remap(text), terms.clone(), statistics.clone()),
+            Expression::Distance(left, right) => Expression::Distance(remap(left), remap(right)),
+            Expression::Spatial(op, left, right) => Expression::Spatial(*op, remap(left), remap(right)),
+            Expression::Call(function, arguments) => {
+                Expression::Call(function.clone(), arguments.iter().map(|argument| argument.remap_columns(f)).collect())
+            },
+        }
+    }
+
+    // The same expression with every column reference replaced by the expression computing
+    // that column, for moving an expression below the projection that produced its input.
+    pub fn substitute(&self, columns: &[Expression]) -> Expression {
+        let substitute = |expression: &Expression| Box::new(expression.substitute(columns));
+        match self {
+            Expression::Column(index) => columns[*index].clone(),
+            Expression::Constant(value) => Expression::Constant(value.clone()),
+            Expression::Compare(op, left, right) => Expression::Compare(*op, substitute(left), substitute(right)),
+            Expression::Arithmetic(op, left, right) => Expression::Arithmetic(*op, substitute(left), substitute(right)),
+            Expression::And(left, right) => Expression::And(substitute(left), substitute(right)),
+            Expression::Or(left, right) => Expression::Or(substitute(left), substitute(right)),
+            Expression::Not(inner) => Expression::Not(substitute(inner)),
+            Expression::IsNull(inner) => Expression::IsNull(substitute(inner)),
+            Expression::Match(text, terms) => Expression::Match(substitute(text), terms.clone()),
+            Expression::Bm25(text, terms, statistics) => Expression::Bm25(substitute(text), terms.clone(), statistics.clone()),
+            Expression::Distance(left, right) => Expression::Distance(substitute(left), substitute(right)),
+            Expression::Spatial(op, left, right) => Expression::Spatial(*op, substitute(left), substitute(right)),
+            Expression::Call(function, arguments) => {
+                Expression::Call(function.clone(), arguments.iter().map(|argument| argument.substitute(columns)).collect())
+            },
+        }
+    }
+
+    // Adds the position of every column this expression reads to `columns`.
+    pub fn collect_columns(&self, columns: &mut BTreeSet<usize>) {
+        match self {
+            Expression::Column(index) => {
+                columns.insert(*index);
+            },
+            Expression::Constant(_) => {},
+            Expression::Compare(_, left, right)
+            | Expression::Arithmetic(_, left, right)
+            | Expression::And(left, right)
+            | Expression::Or(left, right)
+            | Expression::Distance(left, right)
+            | Expression::Spatial(_, left, right) => {
+                left.collect_columns(columns);
+                right.collect_columns(columns);
+            },
+            Expression::Not(inner) | Expression::IsNull(inner) | Expression::Match(inner, _) | Expression::Bm25(inner, ..) => {
+                inner.collect_columns(columns)
+            },
+            Expression::Call(_, arguments) => arguments.iter().for_each(|argument| argument.collect_columns(columns)),
+        }
+    }
+
+    // Whether every column this expression reads is in `columns`.
+    pub fn reads_only(&self, columns: std::ops::Range<usize>) -> bool {
+        match self {
+            Expression::Column(index) => columns.contains(index),
+            Expression::Constant(_) => true,
+            Expression::Compare(_, left, right)
+            | Expression::Arithmetic(_, left, right)
+            | Expression::And(left, right)
+            | Expression::Or(left, right)
+            | Expression::Distance(left, right)
+            | Expression::Spatial(_, left, right) => left.reads_only(columns.clone()) && right.reads_only(columns),
+            Expression::Not(inner) | Expression::IsNull(inner) | Expression::Match(inner, _) | Expression::Bm25(inner, ..) => {
+                inner.reads_only(columns)
+            },
+            Expression::Call(_, arguments) => arguments.iter().all(|argument| argument.reads_only(columns.clone())),
+        }
+    }
+
+    // Splits a predicate into the terms ANDed together at its top.
+    pub fn into_conjuncts(self) -> Vec<Expression> {
+        match self {
+            Expression::And(left, right) => {
+                let mut conjuncts = left.into_conjuncts();
+                conjuncts.extend(right.into_conjuncts());
+                conjuncts
+            },
+            other => vec![other],
+        }
+    }
+
+    // ANDs the terms back together; None if there are none.
+    pub fn conjunction(conjuncts: Vec<Expression>) -> Option<Expression> {
+        conjuncts.into_iter().reduce(Expression::and)
+    }
+}
+
+impl Display for CompareOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbol = match self {
+            CompareOp::Eq => "=",
+            CompareOp::NotEq => "<>",
+            CompareOp::Lt => "<",
+            CompareOp::LtEq => "<=",
+            CompareOp::Gt => ">",
+            CompareOp::GtEq => ">=",
+        };
+        write!(f, "{symbol}")
+    }
+}
+
+impl Display for SpatialOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpatialOp::Contains => write!(f, "ST_CONTAINS"),
+            SpatialOp::Intersects => write!(f, "ST_INTERSECTS"),
+        }
+    }
+}
+
+impl Display for ArithmeticOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbol = match self {
+            ArithmeticOp::Add => "+",
+            ArithmeticOp::Subtract => "-",
+            ArithmeticOp::Multiply => "*",
+            ArithmeticOp::Divide => "/",
+        };
+        write!(f, "{symbol}")
+    }
+}
+
+// Column references print as #position, since an expression does not know the names of the
+// columns of its input.
+impl Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expression::Column(index) => write!(f, "#{index}"),
+            Expression::Constant(Value::Varchar(s)) => write!(f, "'{}'", s.replace('\'', "''")),
+            Expression::Constant(value) => write!(f, "{value}"),
+            Expression::Compare(op, left, right) => write!(f, "{left} {op} {right}"),
+            Expression::Arithmetic(op, left, right) => write!(f, "({left} {op} {right})"),
+            Expression::And(left, right) => write!(f, "{left} AND {right}"),
+            Expression::Or(left, right) => write!(f, "({left} OR {right})"),
+            Expression::Not(inner) => write!(f, "NOT ({inner})"),
+            Expression::IsNull(inner) => write!(f, "{inner} IS NULL"),
+            Expression::Match(text, terms) => write!(f, "MATCH({text}, '{}')", terms.join(" ")),
+            Expression::Bm25(text, terms, _) => write!(f, "BM25({text}, '{}')", terms.join(" ")),
+            Expression::Distance(left, right) => write!(f, "({left} <-> {right})"),
+            Expression::Spatial(op, left, right) => write!(f, "{op}({left}, {right})"),
+            Expression::Call(function, arguments) => {
+                let arguments: Vec<_> = arguments.iter().map(Expression::to_string).collect();
+                write!(f, "{}({})", function.name(), arguments.join(", "))
+            },
+        }
+    }
+}
+
+pub(crate) fn type_name(value: &Value) -> String {
+    value.data_type().map_or("NULL".to_string(), |data_type| data_type.to_string())
+}
+
+fn truth(value: &Value) -> CrabDbResult<Option<bool>> {
+    match value {
+        Value::Null => Ok(None),
+        Value::Boolean(b) => Ok(Some(*b)),
+        other => Err(CrabDBError::InvalidInput(format!("Expected a BOOLEAN but got {}", type_name(other)))),
+    }
+}
+
+pub(crate) fn arithmetic(op: ArithmeticOp, left: Value, right: Value) -> CrabDbResult<Value> {
+    if left.is_null() || right.is_null() {
+        return Ok(Value::Null);
+    }
+    let numeric = |value: &Value| value.data_type().is_some_and(|data_type| data_type.is_numeric());
+    if !numeric(&left) || !numeric(&right) {
+        return Err(CrabDBError::InvalidInput(format!(
+            "Cannot apply arithmetic to {} and {}", type_name(&left), type_name(&right)
+        )));
+    }
+    if matches!(left, Value::Float64(_)) || matches!(right, Value::Float64(_)) {
+        let (a, b) = (left.as_f64().unwrap(), right.as_f64().unwrap());
+        return Ok(Value::Float64(match op {
+            ArithmeticOp::Add => a + b,
+            ArithmeticOp::Subtract => a - b,
+            ArithmeticOp::Multiply => a * b,
+            ArithmeticOp::Divide => a / b,
+        }));
+    }
+    let (a, b) = (left.as_i64().unwrap(), right.as_i64().unwrap());
+    let result = match op {
+        ArithmeticOp::Add => a.checked_add(b),
+        ArithmeticOp::Subtract => a.checked_sub(b),
+        ArithmeticOp::Multiply => a.checked_mul(b),
+        ArithmeticOp::Divide if b == 0 => return Err(CrabDBError::InvalidInput("Division by zero".into())),
+        ArithmeticOp::Divide => a.checked_div(b),
+    }.ok_or_else(|| CrabDBError::InvalidInput(format!("Integer overflow computing {a} and {b}")))?;
+    // Two INTs stay an INT when the result fits.
+    match (left, right) {
+        (Value::Int32(_), Value::Int32(_)) => Ok(i32::try_from(result).map_or(Value::Int64(result), Value::Int32)),
+        _ => Ok(Value::Int64(result)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::geometry::Geometry;
+    use crate::types::value::Value;
+    use super::{ArithmeticOp, CompareOp, Expression, SpatialOp};
+
+    #[test]
+    pub fn test_expression_evaluate() {
+        let row = [Value::Int32(4), Value::Null, Value::Varchar("crab".into()), Value::Float64(0.5)];
+        let column = Expression::Column;
+        let constant = Expression::Constant;
+
+        let sum = Expression::arithmetic(ArithmeticOp::Add, column(0), constant(Value::Int32(3)));
+        assert_eq!(Value::Int32(7), sum.evaluate(&row).unwrap());
+        let scaled = Expression::arithmetic(ArithmeticOp::Multiply, column(0), column(3));
+        assert_eq!(Value::Float64(2.0), scaled.evaluate(&row).unwrap());
+        assert_eq!(Value::Null, Expression::arithmetic(ArithmeticOp::Add, column(0), column(1)).evaluate(&row).unwrap());
+
+        let is_crab = Expression::compare(CompareOp::Eq, column(2), constant(Value::Varchar("crab".into())));
+        let null_cmp = Expression::compare(CompareOp::Lt, column(1), constant(Value::Int32(1)));
+        assert!(is_crab.matches(&row).unwrap());
+        assert!(!null_cmp.matches(&row).unwrap());
+        assert_eq!(Value::Boolean(false), Expression::and(null_cmp.clone(), Expression::Not(Box::new(is_crab.clone()))).evaluate(&row).unwrap());
+        assert_eq!(Value::Null, Expression::and(null_cmp.clone(), is_crab.clone()).evaluate(&row).unwrap());
+        assert_eq!(Value::Boolean(true), Expression::or(null_cmp, is_crab).evaluate(&row).unwrap());
+        assert!(Expression::IsNull(Box::new(column(1))).matches(&row).unwrap());
+
+        assert_eq!(
+            "Cannot compare VARCHAR with INT",
+            Expression::compare(CompareOp::Eq, column(2), column(0)).evaluate(&row).unwrap_err().to_string()
+        );
+        assert_eq!(
+            "Division by zero",
+            Expression::arithmetic(ArithmeticOp::Divide, column(0), constant(Value::Int64(0))).evaluate(&row).unwrap_err().to_string()
+        );
+
+        let square = constant(Value::Geometry(Geometry::bbox(0.0, 0.0, 2.0, 2.0)));
+        let point = constant(Value::Geometry(Geometry::point(5.0, 6.0)));
+        let spatial = |op, left: &Expression, right: &Expression| Expression::Spatial(op, Box::new(left.clone()), Box::new(right.clone()));
+        assert_eq!(Value::Boolean(false), spatial(SpatialOp::Contains, &square, &point).evaluate(&row).unwrap());
+        assert_eq!(Value::Boolean(true), spatial(SpatialOp::Intersects, &square, &square).evaluate(&row).unwrap());
+        assert_eq!(Value::Null, spatial(SpatialOp::Intersects, &square, &column(1)).evaluate(&row).unwrap());
+        assert_eq!(Value::Float64(5.0), Expression::Distance(Box::new(square.clone()), Box::new(point)).evaluate(&row).unwrap());
+        assert_eq!(
+            "ST_CONTAINS needs two GEOMETRY values but got GEOMETRY and INT",
+            spatial(SpatialOp::Contains, &square, &column(0)).evaluate(&row).unwrap_err().to_string()
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use crate::types::schema::Schema;
+use crate::types::CrabDbResult;
+
+use super::executor::{Executor, Row};
+use super::expression::Expression;
+
+// Passes on the rows of its child that match the predicate.
+pub struct FilterExecutor<'a> {
+    child: Box<dyn Executor + 'a>,
+    predicate: Expression,
+}
+
+impl<'a> FilterExecutor<'a> {
+    pub fn new(child: Box<dyn Executor + 'a>, predicate: Expression) -> Self {
+        FilterExecutor { child, predicate }
+    }
+}
+
+impl Executor for FilterExecutor<'_> {
+    fn init(&mut self) -> CrabDbResult<()> {
+        self.child.init()
+    }
+
+    fn next(&mut self) -> CrabDbResult<Option<Row>> {
+        while let Some(row) = self.child.next()? {
+            if self.predicate.matches(&row.values)? {
+                return Ok(Some(row));
+            }
+        }
+        Ok(None)
+    }
+
+    fn output_schema(&self) -> &Schema {
+        self.child.output_schema()
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+
+use crate::storage::table::tuple::Rid;
+use crate::types::schema::Schema;
+use crate::types::value::Value;
+use crate::types::CrabDbResult;
+
+use super::executor::{Executor, ExecutorContext, Row};
+use super::expression::Expression;
+use super::hash_key::join_key;
+use super::join::{join_schema, EquiJoinKeys, JoinType};
+use super::temp_table::TempTable;
+
+// Joins on equal keys: `init` builds a hash table over the right child, then each left row
+// probes it. Built rows are kept in a temp table in the buffer pool, so a large build side
+// spills to disk instead of exhausting memory; only the key of each row and where it is
+// stored stay in memory.
+pub struct HashJoinExecutor<'a> {
+    ctx: &'a ExecutorContext,
+    left: Box<dyn Executor + 'a>,
+    right: Box<dyn Executor + 'a>,
+    join_type: JoinType,
+    keys: EquiJoinKeys,
+    schema: Schema,
+    build: Option<HashTable>,
+    // The left row being probed, the build rows with its key not yet joined, and whether
+    // any of them matched so far.
+    current: Option<(Vec<Value>, Vec<Rid>, bool)>,
+}
+
+struct HashTable {
+    rows: TempTable,
+    buckets: HashMap<Vec<u8>, Vec<Rid>>,
+}
+
+impl<'a> HashJoinExecutor<'a> {
+    pub fn new(
+        ctx: &'a ExecutorContext,
+        left: Box<dyn Executor + 'a>,
+        right: Box<dyn Executor + 'a>,
+        join_type: JoinType,
+        keys: EquiJoinKeys,
+    ) -> Self {
+        let schema = join_schema(left.output_schema(), right.output_schema(), join_type);
+        HashJoinExecutor { ctx, left, right, join_type, keys, schema, build: None, current: None }
+    }
+}
+
+fn evaluate_key(keys: &[Expression], row: &[Value]) -> CrabDbResult<Option<Vec<u8>>> {
+    let values = keys.iter().map(|key| key.evaluate(row)).collect::<CrabDbResult<Vec<_>>>()?;
+    Ok(join_key(&values))
+}
+
+impl Executor for HashJoinExecutor<'_> {
+    fn init(&mut self) -> CrabDbResult<()> {
+        self.current = None;
+        // Drop the previous build first so its pages can be reused.
+        self.build = None;
+        let rows = TempTable::new(self.ctx.catalog().bpm().clone(), self.right.output_schema())?;
+        let mut buckets: HashMap<Vec<u8>, Vec<Rid>> = HashMap::new();
+        self.right.init()?;
+        while let Some(row) = self.right.next()? {
+            // Rows with a NULL key can never match.
+            if let Some(key) = evaluate_key(&self.keys.right, &row.values)? {
+                buckets.entry(key).or_default().push(rows.push(&row.values)?);
+            }
+        }
+        self.build = Some(HashTable { rows, buckets });
+        self.left.init()
+    }
+
+    fn next(&mut self) -> CrabDbResult<Option<Row>> {
+        let Some(build) = &self.build else {
+            return Ok(None);
+        };
+        loop {
+            let Some((left_row, candidates, matched)) = self.current.as_mut() else {
+                let Some(row) = self.left.next()? else {
+                    return Ok(None);
+                };
+                let candidates = evaluate_key(&self.keys.left, &row.values)?
+                    .and_then(|key| build.buckets.get(&key).cloned())
+                    .unwrap_or_default();
+                // Matches come out in the order the right child produced them.
+                self.current = Some((row.values, candidates.into_iter().rev().collect(), false));
+                continue;
+            };
+            while let Some(rid) = candidates.pop() {
+                let mut values = left_row.clone();
+                values.extend(build.rows.get(rid)?);
+                if self.keys.residual.as_ref().map_or(Ok(true), |residual| residual.matches(&values))? {
+                    *matched = true;
+                    return Ok(Some(Row::new(values)));
+                }
+            }
+            let (mut values, _, matched) = self.current.take().unwrap();
+            if self.join_type == JoinType::Left && !matched {
+                values.resize(self.schema.column_count(), Value::Null);
+                return Ok(Some(Row::new(values)));
+            }
+        }
+    }
+
+    fn output_schema(&self) -> &Schema {
+        &self.schema
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::catalog::system_catalog::Catalog;
+    use crate::concurrency::lock_manager::LockManager;
+    use crate::concurrency::transaction::IsolationLevel;
+    use crate::concurrency::transaction_manager::TransactionManager;
+    use crate::execution::executor::{execute, Executor, ExecutorContext, Row};
+    use crate::execution::expression::{CompareOp, Expression};
+    use crate::execution::join::{EquiJoinKeys, JoinType};
+    use crate::execution::nested_loop_join_executor::NestedLoopJoinExecutor;
+    use crate::execution::seq_scan_executor::SeqScanExecutor;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use crate::storage::table::tuple::Tuple;
+    use crate::types::schema::{Column, Schema};
+    use crate::types::value::{DataType, Value};
+    use super::HashJoinExecutor;
+
+    #[test]
+    pub fn test_hash_join_matches_nested_loop_join() {
+        // A pool much smaller than the build side, so the hash table has to spill.
+        let bpm = Arc::new(BufferPoolManager::new(6, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(6, 2))));
+        let catalog = Arc::new(Catalog::open(bpm).unwrap());
+        let txn_manager = Arc::new(TransactionManager::new(Arc::new(LockManager::new())));
+        let crabs = catalog.create_table("crabs", Schema::new(vec![
+            Column::new("id", DataType::Int32), Column::new("name", DataType::Varchar),
+        ])).unwrap();
+        let shells = catalog.create_table("shells", Schema::new(vec![
+            Column::new("crab_id", DataType::Int64), Column::new("pattern", DataType::Varchar),
+        ])).unwrap();
+
+        let txn = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        for id in [1, 2, 3, 4] {
+            let row = [Value::Int32(id), Value::Varchar(format!("crab {id}"))];
+            txn_manager.insert(&txn, crabs.heap(), &Tuple::from_values(crabs.schema(), &row).unwrap()).unwrap();
+        }
+        // Most shells belong to crab 1, a few to crab 2 and every seventh to nobody; crabs 3 and 4 have none.
+        for i in 0..400i64 {
+            let crab_id = if i % 100 == 0 { Value::Int64(2) } else if i % 7 == 0 { Value::Null } else { Value::Int64(1) };
+            let row = [crab_id, Value::Varchar(format!("{:0>64}", i))];
+            txn_manager.insert(&txn, shells.heap(), &Tuple::from_values(shells.schema(), &row).unwrap()).unwrap();
+        }
+        txn_manager.commit(&txn).unwrap();
+
+        let ctx = ExecutorContext::new(catalog, txn_manager.clone(), txn_manager.begin(IsolationLevel::SnapshotIsolation));
+        // crabs.id = shells.crab_id AND crabs.id <> 3 AND shells.pattern >= '...0050'
+        let predicate = Expression::and(
+            Expression::and(
+                Expression::compare(CompareOp::Eq, Expression::Column(0), Expression::Column(2)),
+                Expression::compare(CompareOp::NotEq, Expression::Column(0), Expression::Constant(Value::Int32(3))),
+            ),
+            Expression::compare(CompareOp::GtEq, Expression::Column(3), Expression::Constant(Value::Varchar(format!("{:0>64}", 50)))),
+        );
+        let keys = EquiJoinKeys::extract(&predicate, 2).unwrap();
+        for join_type in [JoinType::Inner, JoinType::Left] {
+            let mut hash_join = HashJoinExecutor::new(
+                &ctx,
+                Box::new(SeqScanExecutor::new(&ctx, &crabs, None)),
+                Box::new(SeqScanExecutor::new(&ctx, &shells, None)),
+                join_type,
+                keys.clone(),
+            );
+            let mut nested_loop_join = NestedLoopJoinExecutor::new(
+                Box::new(SeqScanExecutor::new(&ctx, &crabs, None)),
+                Box::new(SeqScanExecutor::new(&ctx, &shells, None)),
+                join_type,
+                Some(predicate.clone()),
+            );
+            let hashed = execute(&mut hash_join).unwrap();
+            assert_eq!(execute(&mut nested_loop_join).unwrap(), hashed);
+
+            let matched = (50..400).filter(|i| i % 100 == 0 || i % 7 != 0).count();
+            let expected = match join_type {
+                JoinType::Inner => matched,
+                JoinType::Left => matched + 2,
+            };
+            assert_eq!(expected, hashed.len());
+            assert_eq!(4, hash_join.output_schema().column_count());
+            if join_type == JoinType::Left {
+                assert!(hashed.contains(&Row::new(
+                    vec![Value::Int32(3), Value::Varchar("crab 3".into()), Value::Null, Value::Null]
+                )));
+            }
+            // the join can be run again
+            assert_eq!(hashed, execute(&mut hash_join).unwrap());
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use crate::types::value::Value;
+
+// Encodes values so that values SQL considers equal encode the same, whatever the width of
+// their numeric types. NULLs all encode alike, which is how GROUP BY treats them.
+pub(crate) fn group_key(values: &[Value]) -> Vec<u8> {
+    let mut key = Vec::new();
+    for value in values {
+        match value {
+            Value::Null => key.push(0),
+            Value::Boolean(b) => key.extend_from_slice(&[1, *b as u8]),
+            Value::Int32(_) | Value::Int64(_) | Value::Timestamp(_) => {
+                key.push(2);
+                key.extend_from_slice(&value.as_i64().unwrap().to_be_bytes());
+            },
+            Value::Float64(f) if f.fract() == 0.0 && *f >= i64::MIN as f64 && *f < i64::MAX as f64 => {
+                key.push(2);
+                key.extend_from_slice(&(*f as i64).to_be_bytes());
+            },
+            Value::Float64(f) => {
+                key.push(3);
+                key.extend_from_slice(&f.to_bits().to_be_bytes());
+            },
+            Value::Varchar(s) => {
+                key.push(4);
+                key.extend_from_slice(&(s.len() as u32).to_be_bytes());
+                key.extend_from_slice(s.as_bytes());
+            },
+            Value::Vector(vector) => {
+                key.push(5);
+                key.extend_from_slice(&(vector.len() as u32).to_be_bytes());
+                for element in vector {
+                    key.extend_from_slice(&element.to_bits().to_be_bytes());
+                }
+            },
+            Value::Geometry(geometry) => {
+                key.push(6);
+                for coordinate in geometry.coordinates() {
+                    key.extend_from_slice(&coordinate.to_bits().to_be_bytes());
+                }
+            },
+        }
+    }
+    key
+}
+
+// Like `group_key`, but None if any value is NULL, since NULL equals nothing in a join.
+pub(crate) fn join_key(values: &[Value]) -> Option<Vec<u8>> {
+    if values.iter().any(Value::is_null) {
+        return None;
+    }
+    Some(group_key(values))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::value::Value;
+    use super::{group_key, join_key};
+
+    #[test]
+    pub fn test_hash_keys() {
+        assert_eq!(join_key(&[Value::Int32(3)]), join_key(&[Value::Float64(3.0)]));
+        assert_ne!(join_key(&[Value::Int32(3)]), join_key(&[Value::Float64(3.5)]));
+        assert_ne!(group_key(&[Value::Varchar("a".into()), Value::Varchar("b".into())]), group_key(&[Value::Varchar("ab".into()), Value::Varchar("".into())]));
+        assert_eq!(None, join_key(&[Value::Int64(1), Value::Null]));
+        assert_eq!(group_key(&[Value::Null]), group_key(&[Value::Null]));
+    }
+}
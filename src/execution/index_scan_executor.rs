@@ -0,0 +1,124 @@
+use std::ops::Bound;
+
+use crate::catalog::table_info::{IndexInfo, TableInfo};
+use crate::concurrency::lock_manager::LockMode;
+use crate::index::index_key::encode_key;
+use crate::index::rtree::SpatialQuery;
+use crate::platform;
+use crate::storage::table::tuple::Rid;
+use crate::types::geometry::Geometry;
+use crate::types::schema::Schema;
+use crate::types::value::Value;
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::executor::{Executor, ExecutorContext, Row};
+use super::expression::Expression;
+
+// Which entries of an index to read. Values are in key column order; ranges are only over
+// single-column B+ tree indexes, and terms only over full-text ones, which find the rows
+// holding every term. Nearest is over HNSW indexes, which find about the `k` rows closest
+// to the vector, closest first. Spatial and NearestGeometry are over R-tree indexes, which
+// find the rows whose geometry lies against the one given, and the `k` rows closest to it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IndexLookup {
+    Point(Vec<Value>),
+    Range { start: Bound<Value>, end: Bound<Value> },
+    Terms(Vec<String>),
+    Nearest { vector: Vec<f32>, k: usize },
+    Spatial(SpatialQuery),
+    NearestGeometry { geometry: Geometry, k: usize },
+}
+
+// Reads the rows of a table that an index lookup points at. Indexes are not versioned, so
+// an entry may point at a row the transaction cannot see or whose visible version no longer
+// has the key; the row is fetched through the transaction and the predicate, which should
+// imply the lookup, is checked again on it. Like sequential scans, it skips expired rows.
+pub struct IndexScanExecutor<'a> {
+    ctx: &'a ExecutorContext,
+    table: &'a TableInfo,
+    index: &'a IndexInfo,
+    lookup: IndexLookup,
+    predicate: Option<Expression>,
+    rids: std::vec::IntoIter<Rid>,
+    now: u64,
+}
+
+impl<'a> IndexScanExecutor<'a> {
+    pub fn new(
+        ctx: &'a ExecutorContext,
+        table: &'a TableInfo,
+        index: &'a IndexInfo,
+        lookup: IndexLookup,
+        predicate: Option<Expression>,
+    ) -> Self {
+        IndexScanExecutor { ctx, table, index, lookup, predicate, rids: Vec::new().into_iter(), now: 0 }
+    }
+}
+
+impl Executor for IndexScanExecutor<'_> {
+    fn init(&mut self) -> CrabDbResult<()> {
+        self.ctx.lock_table(self.table, LockMode::IntentionShared)?;
+        let key_schema = self.index.key_schema();
+        // The entries are collected up front so no index page stays latched while the table
+        // is read.
+        let rids = match &self.lookup {
+            IndexLookup::Point(values) => self.index.index().get(&encode_key(key_schema, values)?)?.into_iter().collect(),
+            IndexLookup::Range { start, end } => {
+                let encode = |bound: &Bound<Value>| -> CrabDbResult<Bound<Vec<u8>>> {
+                    Ok(match bound {
+                        Bound::Included(value) => Bound::Included(encode_key(key_schema, std::slice::from_ref(value))?),
+                        Bound::Excluded(value) => Bound::Excluded(encode_key(key_schema, std::slice::from_ref(value))?),
+                        Bound::Unbounded => Bound::Unbounded,
+                    })
+                };
+                let (start, end) = (encode(start)?, encode(end)?);
+                self.index.index().range(start.as_ref().map(Vec::as_slice), end.as_ref().map(Vec::as_slice))?
+                    .map(|entry| entry.map(|(_, rid)| rid))
+                    .collect::<CrabDbResult<Vec<_>>>()?
+            },
+            IndexLookup::Terms(terms) => match self.index.full_text() {
+                Some(full_text) => full_text.search(terms)?,
+                None => return Err(CrabDBError::InvalidInput(format!("Index {} is not a full-text index", self.index.name()))),
+            },
+            IndexLookup::Nearest { vector, k } => match self.index.hnsw() {
+                Some(hnsw) => hnsw.search(vector, *k)?,
+                None => return Err(CrabDBError::InvalidInput(format!("Index {} is not an HNSW index", self.index.name()))),
+            },
+            IndexLookup::Spatial(query) => match self.index.rtree() {
+                Some(rtree) => rtree.search(query)?,
+                None => return Err(CrabDBError::InvalidInput(format!("Index {} is not an R-tree index", self.index.name()))),
+            },
+            IndexLookup::NearestGeometry { geometry, k } => match self.index.rtree() {
+                Some(rtree) => rtree.nearest(geometry, *k)?,
+                None => return Err(CrabDBError::InvalidInput(format!("Index {} is not an R-tree index", self.index.name()))),
+            },
+        };
+        self.rids = rids.into_iter();
+        self.now = platform::clock().now_micros();
+        Ok(())
+    }
+
+    fn next(&mut self) -> CrabDbResult<Option<Row>> {
+        for rid in self.rids.by_ref() {
+            let Some(tuple) = self.ctx.txn_manager().get(self.ctx.txn(), self.table.heap(), rid)? else {
+                continue;
+            };
+            let values = tuple.values(self.table.schema())?;
+            if self.table.is_expired(&values, self.now) {
+                continue;
+            }
+            if let Some(predicate) = &self.predicate {
+                if !predicate.matches(&values)? {
+                    continue;
+                }
+            }
+            self.ctx.lock_read(self.table, rid)?;
+            return Ok(Some(Row { values, rid: Some(rid) }));
+        }
+        Ok(None)
+    }
+
+    fn output_schema(&self) -> &Schema {
+        self.table.schema()
+    }
+}
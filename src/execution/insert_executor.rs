@@ -0,0 +1,135 @@
+use crate::catalog::table_info::{IndexInfo, TableInfo};
+use crate::catalog::trigger::TriggerEvent;
+use crate::concurrency::lock_manager::LockMode;
+use crate::platform;
+use crate::storage::table::tuple::{Rid, Tuple};
+use crate::types::schema::{Column, Schema};
+use crate::types::value::{DataType, Value};
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::executor::{Executor, ExecutorContext, Row};
+use super::trigger::Triggers;
+
+// What INSERT, UPDATE and DELETE produce: a single row counting the rows they changed.
+pub(crate) fn count_schema() -> Schema {
+    Schema::new(vec![Column::new("count", DataType::Int64).not_null()])
+}
+
+// Points the index entry for the key of `values` at `rid`. Entries are not removed when
+// rows are deleted or change key, since the transaction doing so may still abort; an
+// existing entry only blocks the new one if the latest version of the row it points at is
+// live, unexpired and still has the key. Indexes holding several entries per row get all
+// of them.
+pub(crate) fn insert_index_entry(ctx: &ExecutorContext, table: &TableInfo, index: &IndexInfo, values: &[Value], rid: Rid) -> CrabDbResult<()> {
+    for key in index.entries_for_row(values, rid)? {
+        insert_index_key(ctx, table, index, &key, rid)?;
+    }
+    Ok(())
+}
+
+// `insert_index_entry` for a key already taken from the row. The row an existing entry
+// points at is locked first, so a delete or key change that may yet roll back is waited
+// out rather than taken for good. The key is only free once the change is visible to the
+// transaction, or made by it, in which case its rollback points the entry back.
+pub(crate) fn insert_index_key(ctx: &ExecutorContext, table: &TableInfo, index: &IndexInfo, key: &[u8], rid: Rid) -> CrabDbResult<()> {
+    // Entries of indexes that are not unique hold their rid, so one already there is this one.
+    if index.index().insert(key, rid)? || !index.kind().is_unique() {
+        return Ok(());
+    }
+    let Some(existing) = index.index().get(key)? else {
+        return Err(CrabDBError::new(format!("Index {} lost the entry for a key it reported", index.name())));
+    };
+    if existing == rid {
+        return Ok(());
+    }
+    ctx.lock_row(table, LockMode::Exclusive, existing)?;
+    let txn = ctx.txn();
+    let mut own = false;
+    if let Ok(meta) = table.heap().tuple_meta(existing) {
+        let live = !meta.is_deleted && {
+            let row = table.heap().get_tuple(existing)?.values(table.schema())?;
+            index.key_for_row(&row)? == key && !table.is_expired(&row, platform::clock().now_micros())
+        };
+        if live {
+            return Err(CrabDBError::ConstraintViolation { table: table.name().to_string(), constraint: index.name().to_string() });
+        }
+        own = meta.ts == txn.temp_ts();
+        if !own && meta.ts > txn.read_ts() {
+            return Err(CrabDBError::Conflict(format!(
+                "Transaction {} conflicts with a concurrent change to row {existing}, which held a key of index {}", txn.id(), index.name()
+            )));
+        }
+    }
+    index.index().remove(key)?;
+    index.index().insert(key, rid)?;
+    if own {
+        let (index, key) = (index.shared_index(), key.to_vec());
+        txn.on_rollback(move || {
+            if index.get(&key)? == Some(rid) {
+                index.remove(&key)?;
+                index.insert(&key, existing)?;
+            }
+            Ok(())
+        });
+    }
+    Ok(())
+}
+
+// Inserts the rows of its child into a table and its indexes, calling the table's INSERT
+// triggers around each row. The child is drained before anything is written, so a query
+// reading the same table does not see its own inserts.
+pub struct InsertExecutor<'a> {
+    ctx: &'a ExecutorContext,
+    table: &'a TableInfo,
+    child: Box<dyn Executor + 'a>,
+    schema: Schema,
+    count: Option<i64>,
+}
+
+impl<'a> InsertExecutor<'a> {
+    pub fn new(ctx: &'a ExecutorContext, table: &'a TableInfo, child: Box<dyn Executor + 'a>) -> Self {
+        InsertExecutor { ctx, table, child, schema: count_schema(), count: None }
+    }
+}
+
+impl Executor for InsertExecutor<'_> {
+    fn init(&mut self) -> CrabDbResult<()> {
+        self.ctx.lock_table(self.table, LockMode::IntentionExclusive)?;
+        let mut rows = Vec::new();
+        self.child.init()?;
+        while let Some(row) = self.child.next()? {
+            rows.push(Tuple::from_values(self.table.schema(), &row.values)?);
+        }
+        let indexes = self.ctx.catalog().indexes_of(self.table);
+        let triggers = Triggers::of(self.ctx, self.table, TriggerEvent::Insert);
+        let mut count = 0;
+        for mut tuple in rows {
+            if triggers.has_before() {
+                let mut values = tuple.values(self.table.schema())?;
+                if !triggers.before(None, Some(&mut values))? {
+                    continue;
+                }
+                tuple = Tuple::from_values(self.table.schema(), &values)?;
+            }
+            let rid = self.ctx.txn_manager().insert(self.ctx.txn(), self.table.heap(), &tuple)?;
+            if !indexes.is_empty() || triggers.has_after() {
+                let mut values = tuple.values(self.table.schema())?;
+                for index in &indexes {
+                    insert_index_entry(self.ctx, self.table, index, &values, rid)?;
+                }
+                triggers.after(None, Some(&mut values))?;
+            }
+            count += 1;
+        }
+        self.count = Some(count);
+        Ok(())
+    }
+
+    fn next(&mut self) -> CrabDbResult<Option<Row>> {
+        Ok(self.count.take().map(|count| Row::new(vec![Value::Int64(count)])))
+    }
+
+    fn output_schema(&self) -> &Schema {
+        &self.schema
+    }
+}
@@ -0,0 +1,89 @@
+use crate::types::schema::{Column, Schema};
+
+use super::expression::{CompareOp, Expression};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinType {
+    Inner,
+    // Every left row comes out at least once, padded with NULLs when nothing matched it.
+    Left,
+}
+
+// Joined rows are the left row followed by the right row.
+pub fn join_schema(left: &Schema, right: &Schema, join_type: JoinType) -> Schema {
+    let right_columns = right.columns().iter().map(|column| match join_type {
+        JoinType::Inner => column.clone(),
+        JoinType::Left => Column::new(column.name(), column.data_type()),
+    });
+    Schema::new(left.columns().iter().cloned().chain(right_columns).collect())
+}
+
+// An equi-join predicate taken apart for a hash join: rows match when each left key equals
+// the corresponding right key and the residual, if any, holds on the joined row. Left keys
+// read the left row and right keys the right row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EquiJoinKeys {
+    pub left: Vec<Expression>,
+    pub right: Vec<Expression>,
+    pub residual: Option<Expression>,
+}
+
+impl EquiJoinKeys {
+    // Pulls the equalities between one side and the other out of a join predicate over
+    // joined rows whose first `left_width` columns come from the left. Returns None when
+    // there are none, in which case only a nested loop join can evaluate the predicate.
+    pub fn extract(predicate: &Expression, left_width: usize) -> Option<EquiJoinKeys> {
+        let mut keys = EquiJoinKeys { left: Vec::new(), right: Vec::new(), residual: None };
+        let mut residual = Vec::new();
+        for conjunct in predicate.clone().into_conjuncts() {
+            let Expression::Compare(CompareOp::Eq, a, b) = &conjunct else {
+                residual.push(conjunct);
+                continue;
+            };
+            // Keys must read a column: constants on either side are left to the residual.
+            let constant = |expression: &Expression| expression.reads_only(0..0);
+            let left_side = |expression: &Expression| !constant(expression) && expression.reads_only(0..left_width);
+            let right_side = |expression: &Expression| !constant(expression) && expression.reads_only(left_width..usize::MAX);
+            let (left, right) = if left_side(a) && right_side(b) {
+                (a, b)
+            } else if left_side(b) && right_side(a) {
+                (b, a)
+            } else {
+                residual.push(conjunct);
+                continue;
+            };
+            keys.left.push((**left).clone());
+            keys.right.push(right.remap_columns(&|index| index - left_width));
+        }
+        if keys.left.is_empty() {
+            return None;
+        }
+        keys.residual = Expression::conjunction(residual);
+        Some(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::execution::expression::{CompareOp, Expression};
+    use crate::types::value::Value;
+    use super::EquiJoinKeys;
+
+    #[test]
+    pub fn test_extract_equi_join_keys() {
+        let column = Expression::Column;
+        let residual = Expression::compare(CompareOp::Lt, column(1), column(3));
+        let predicate = Expression::and(
+            Expression::and(Expression::compare(CompareOp::Eq, column(2), column(0)), residual.clone()),
+            Expression::compare(CompareOp::Eq, column(1), Expression::Constant(Value::Int32(7))),
+        );
+        let keys = EquiJoinKeys::extract(&predicate, 2).unwrap();
+        assert_eq!(vec![column(0)], keys.left);
+        assert_eq!(vec![column(0)], keys.right);
+        assert_eq!(
+            Some(Expression::and(residual.clone(), Expression::compare(CompareOp::Eq, column(1), Expression::Constant(Value::Int32(7))))),
+            keys.residual
+        );
+        assert_eq!(None, EquiJoinKeys::extract(&residual, 2));
+    }
+}
@@ -0,0 +1,51 @@
+use crate::types::schema::Schema;
+use crate::types::CrabDbResult;
+
+use super::executor::{Executor, Row};
+
+// Skips the first `offset` rows of its child, then passes on at most `limit` rows and stops
+// pulling from the child once it has.
+pub struct LimitExecutor<'a> {
+    child: Box<dyn Executor + 'a>,
+    limit: usize,
+    offset: usize,
+    emitted: usize,
+}
+
+impl<'a> LimitExecutor<'a> {
+    pub fn new(child: Box<dyn Executor + 'a>, limit: usize) -> Self {
+        Self::with_offset(child, limit, 0)
+    }
+
+    pub fn with_offset(child: Box<dyn Executor + 'a>, limit: usize, offset: usize) -> Self {
+        LimitExecutor { child, limit, offset, emitted: 0 }
+    }
+}
+
+impl Executor for LimitExecutor<'_> {
+    fn init(&mut self) -> CrabDbResult<()> {
+        self.emitted = 0;
+        self.child.init()?;
+        for _ in 0..self.offset {
+            if self.child.next()?.is_none() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> CrabDbResult<Option<Row>> {
+        if self.emitted == self.limit {
+            return Ok(None);
+        }
+        let row = self.child.next()?;
+        if row.is_some() {
+            self.emitted += 1;
+        }
+        Ok(row)
+    }
+
+    fn output_schema(&self) -> &Schema {
+        self.child.output_schema()
+    }
+}
@@ -0,0 +1,23 @@
+pub mod aggregation_executor;
+pub mod analyze_executor;
+pub mod copy;
+pub mod delete_executor;
+pub mod executor;
+pub mod expression;
+pub mod filter_executor;
+pub mod hash_join_executor;
+pub(crate) mod hash_key;
+pub mod index_scan_executor;
+pub mod insert_executor;
+pub mod join;
+pub mod limit_executor;
+pub mod nested_loop_join_executor;
+pub mod projection_executor;
+pub mod seq_scan_executor;
+pub mod sort_executor;
+pub mod statistics_executor;
+pub(crate) mod temp_table;
+pub mod top_n_executor;
+pub mod trigger;
+pub mod update_executor;
+pub mod values_executor;
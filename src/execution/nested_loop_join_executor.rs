@@ -0,0 +1,68 @@
+use crate::types::schema::Schema;
+use crate::types::value::Value;
+use crate::types::CrabDbResult;
+
+use super::executor::{Executor, Row};
+use super::expression::Expression;
+use super::join::{join_schema, JoinType};
+
+// Joins by rescanning the right child once per left row. Works with any predicate, which is
+// evaluated on the joined row; without one every pair of rows matches.
+pub struct NestedLoopJoinExecutor<'a> {
+    left: Box<dyn Executor + 'a>,
+    right: Box<dyn Executor + 'a>,
+    join_type: JoinType,
+    predicate: Option<Expression>,
+    schema: Schema,
+    // The left row being joined, and whether any right row matched it so far.
+    current: Option<(Vec<Value>, bool)>,
+}
+
+impl<'a> NestedLoopJoinExecutor<'a> {
+    pub fn new(
+        left: Box<dyn Executor + 'a>,
+        right: Box<dyn Executor + 'a>,
+        join_type: JoinType,
+        predicate: Option<Expression>,
+    ) -> Self {
+        let schema = join_schema(left.output_schema(), right.output_schema(), join_type);
+        NestedLoopJoinExecutor { left, right, join_type, predicate, schema, current: None }
+    }
+}
+
+impl Executor for NestedLoopJoinExecutor<'_> {
+    fn init(&mut self) -> CrabDbResult<()> {
+        self.current = None;
+        self.left.init()
+    }
+
+    fn next(&mut self) -> CrabDbResult<Option<Row>> {
+        loop {
+            let Some((left_row, matched)) = self.current.as_mut() else {
+                let Some(row) = self.left.next()? else {
+                    return Ok(None);
+                };
+                self.right.init()?;
+                self.current = Some((row.values, false));
+                continue;
+            };
+            while let Some(right_row) = self.right.next()? {
+                let mut values = left_row.clone();
+                values.extend(right_row.values);
+                if self.predicate.as_ref().map_or(Ok(true), |predicate| predicate.matches(&values))? {
+                    *matched = true;
+                    return Ok(Some(Row::new(values)));
+                }
+            }
+            let (mut values, matched) = self.current.take().unwrap();
+            if self.join_type == JoinType::Left && !matched {
+                values.resize(self.schema.column_count(), Value::Null);
+                return Ok(Some(Row::new(values)));
+            }
+        }
+    }
+
+    fn output_schema(&self) -> &Schema {
+        &self.schema
+    }
+}
@@ -0,0 +1,36 @@
+use crate::types::schema::Schema;
+use crate::types::CrabDbResult;
+
+use super::executor::{Executor, Row};
+use super::expression::Expression;
+
+// Computes each output row from a row of its child, one expression per output column.
+pub struct ProjectionExecutor<'a> {
+    child: Box<dyn Executor + 'a>,
+    expressions: Vec<Expression>,
+    schema: Schema,
+}
+
+impl<'a> ProjectionExecutor<'a> {
+    pub fn new(child: Box<dyn Executor + 'a>, expressions: Vec<Expression>, schema: Schema) -> Self {
+        ProjectionExecutor { child, expressions, schema }
+    }
+}
+
+impl Executor for ProjectionExecutor<'_> {
+    fn init(&mut self) -> CrabDbResult<()> {
+        self.child.init()
+    }
+
+    fn next(&mut self) -> CrabDbResult<Option<Row>> {
+        let Some(row) = self.child.next()? else {
+            return Ok(None);
+        };
+        let values = self.expressions.iter().map(|expression| expression.evaluate(&row.values)).collect::<CrabDbResult<_>>()?;
+        Ok(Some(Row::new(values)))
+    }
+
+    fn output_schema(&self) -> &Schema {
+        &self.schema
+    }
+}
@@ -0,0 +1,127 @@
+use crate::buffer_pool::access_strategy::BufferAccessStrategy;
+use crate::catalog::table_info::TableInfo;
+use crate::concurrency::lock_manager::LockMode;
+use crate::concurrency::mvcc::SnapshotIterator;
+use crate::platform;
+use crate::types::schema::Schema;
+use crate::types::CrabDbResult;
+
+use super::executor::{Executor, ExecutorContext, Row};
+use super::expression::Expression;
+
+// Reads every row of a table visible to the transaction, keeping those that match the
+// predicate if there is one. Rows that had expired when the scan started are skipped.
+pub struct SeqScanExecutor<'a> {
+    ctx: &'a ExecutorContext,
+    table: &'a TableInfo,
+    predicate: Option<Expression>,
+    iter: Option<SnapshotIterator<'a>>,
+    now: u64,
+}
+
+impl<'a> SeqScanExecutor<'a> {
+    pub fn new(ctx: &'a ExecutorContext, table: &'a TableInfo, predicate: Option<Expression>) -> Self {
+        SeqScanExecutor { ctx, table, predicate, iter: None, now: 0 }
+    }
+}
+
+impl Executor for SeqScanExecutor<'_> {
+    fn init(&mut self) -> CrabDbResult<()> {
+        self.ctx.lock_table(self.table, LockMode::IntentionShared)?;
+        let heap = self.table.heap();
+        let mut iter = self.ctx.txn_manager().scan(self.ctx.txn(), heap);
+        // A table too big to keep is read through a ring of frames, so it does not push the
+        // working set out of the pool.
+        if let Some(strategy) = BufferAccessStrategy::for_scan(heap.bpm(), heap.num_pages()) {
+            iter = iter.with_strategy(strategy);
+        }
+        self.iter = Some(iter);
+        self.now = platform::clock().now_micros();
+        Ok(())
+    }
+
+    fn next(&mut self) -> CrabDbResult<Option<Row>> {
+        let Some(iter) = self.iter.as_mut() else {
+            return Ok(None);
+        };
+        for entry in iter {
+            let (rid, tuple) = entry?;
+            let values = tuple.values(self.table.schema())?;
+            if self.table.is_expired(&values, self.now) {
+                continue;
+            }
+            if let Some(predicate) = &self.predicate {
+                if !predicate.matches(&values)? {
+                    continue;
+                }
+            }
+            self.ctx.lock_read(self.table, rid)?;
+            return Ok(Some(Row { values, rid: Some(rid) }));
+        }
+        Ok(None)
+    }
+
+    fn output_schema(&self) -> &Schema {
+        self.table.schema()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::catalog::system_catalog::Catalog;
+    use crate::concurrency::lock_manager::{LockManager, LockMode, LockTarget};
+    use crate::concurrency::transaction::IsolationLevel;
+    use crate::concurrency::transaction_manager::TransactionManager;
+    use crate::execution::executor::{execute, ExecutorContext};
+    use crate::execution::expression::{CompareOp, Expression};
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use crate::storage::table::tuple::Tuple;
+    use crate::types::schema::{Column, Schema};
+    use crate::types::value::{DataType, Value};
+    use super::SeqScanExecutor;
+
+    #[test]
+    pub fn test_seq_scan_filters_visible_rows() {
+        let bpm = Arc::new(BufferPoolManager::new(8, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(8, 2))));
+        let catalog = Arc::new(Catalog::open(bpm).unwrap());
+        let txn_manager = Arc::new(TransactionManager::new(Arc::new(LockManager::new())));
+        let schema = Schema::new(vec![Column::new("id", DataType::Int64), Column::new("name", DataType::Varchar)]);
+        let table = catalog.create_table("crabs", schema.clone()).unwrap();
+
+        let writer = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        for (id, name) in [(1, "ferris"), (2, "sebastian"), (3, "crabby")] {
+            let tuple = Tuple::from_values(&schema, &[Value::Int64(id), Value::Varchar(name.into())]).unwrap();
+            txn_manager.insert(&writer, table.heap(), &tuple).unwrap();
+        }
+        let earlier = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        txn_manager.commit(&writer).unwrap();
+
+        let ctx = ExecutorContext::new(catalog.clone(), txn_manager.clone(), txn_manager.begin(IsolationLevel::SnapshotIsolation));
+        let predicate = Expression::compare(CompareOp::GtEq, Expression::Column(0), Expression::Constant(Value::Int32(2)));
+        let mut scan = SeqScanExecutor::new(&ctx, &table, Some(predicate));
+        let rows = execute(&mut scan).unwrap();
+        assert_eq!(
+            vec![vec![Value::Int64(2), Value::Varchar("sebastian".into())], vec![Value::Int64(3), Value::Varchar("crabby".into())]],
+            rows.iter().map(|row| row.values.clone()).collect::<Vec<_>>()
+        );
+        assert!(rows.iter().all(|row| row.rid.is_some()));
+        assert_eq!(Some(LockMode::IntentionShared), ctx.txn().lock_mode(LockTarget::Table(table.oid())));
+        // only serializable scans lock the rows they return
+        assert_eq!(None, ctx.txn().lock_mode(LockTarget::Row(table.oid(), rows[0].rid.unwrap())));
+        // init restarts the scan
+        assert_eq!(2, execute(&mut scan).unwrap().len());
+
+        let serializable = ExecutorContext::new(catalog.clone(), txn_manager.clone(), txn_manager.begin(IsolationLevel::Serializable));
+        let rows = execute(&mut SeqScanExecutor::new(&serializable, &table, None)).unwrap();
+        assert!(rows.iter().all(|row| {
+            serializable.txn().lock_mode(LockTarget::Row(table.oid(), row.rid.unwrap())) == Some(LockMode::Shared)
+        }));
+
+        let ctx = ExecutorContext::new(catalog, txn_manager, earlier);
+        assert!(execute(&mut SeqScanExecutor::new(&ctx, &table, None)).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,244 @@
+use std::cmp::Ordering;
+
+use crate::storage::table::tuple::Rid;
+use crate::types::schema::Schema;
+use crate::types::value::Value;
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::executor::{Executor, ExecutorContext, Row};
+use super::expression::{type_name, Expression};
+use super::temp_table::TempTable;
+
+// How much of its input a sort keeps in memory before spilling a run, by default.
+pub const DEFAULT_SORT_MEMORY: usize = 4 << 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortKey {
+    pub expression: Expression,
+    pub descending: bool,
+}
+
+impl SortKey {
+    pub fn asc(expression: Expression) -> Self {
+        SortKey { expression, descending: false }
+    }
+
+    pub fn desc(expression: Expression) -> Self {
+        SortKey { expression, descending: true }
+    }
+}
+
+pub(crate) fn evaluate_sort_keys(keys: &[SortKey], row: &[Value]) -> CrabDbResult<Vec<Value>> {
+    keys.iter().map(|key| key.expression.evaluate(row)).collect()
+}
+
+// Orders two rows by their evaluated sort keys. NULLs sort last in ascending order and
+// first in descending order, as if they were larger than any value.
+pub(crate) fn compare_sort_keys(keys: &[SortKey], a: &[Value], b: &[Value]) -> CrabDbResult<Ordering> {
+    for ((key, a), b) in keys.iter().zip(a).zip(b) {
+        let ordering = match (a.is_null(), b.is_null()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => a.compare(b).ok_or_else(|| CrabDBError::InvalidInput(format!(
+                "Cannot compare {} with {}", type_name(a), type_name(b)
+            )))?,
+        };
+        let ordering = if key.descending { ordering.reverse() } else { ordering };
+        if ordering != Ordering::Equal {
+            return Ok(ordering);
+        }
+    }
+    Ok(Ordering::Equal)
+}
+
+// Rough in-memory footprint of a buffered row, to hold a sort to its memory budget.
+fn row_size(row: &[Value]) -> usize {
+    row.iter().map(|value| std::mem::size_of::<Value>() + value.as_str().map_or(0, str::len)).sum()
+}
+
+// A stable sort by a list of key expressions. Input that fits in the memory budget is
+// sorted in place. Beyond that it becomes an external merge sort: each time the buffered
+// rows outgrow the budget they are sorted and written out as a run to a temp table in the
+// buffer pool, which spills to disk as needed, and `next` merges the runs.
+pub struct SortExecutor<'a> {
+    ctx: &'a ExecutorContext,
+    child: Box<dyn Executor + 'a>,
+    keys: Vec<SortKey>,
+    memory_budget: usize,
+    output: SortOutput,
+}
+
+// A row with its evaluated sort keys, so they are computed once per row.
+type KeyedRow = (Vec<Value>, Vec<Value>);
+
+enum SortOutput {
+    Memory(std::vec::IntoIter<Vec<Value>>),
+    // Each run with its read position and the next row it has to offer, if any.
+    Runs(Vec<(TempTable, Rid, Option<KeyedRow>)>),
+}
+
+impl<'a> SortExecutor<'a> {
+    pub fn new(ctx: &'a ExecutorContext, child: Box<dyn Executor + 'a>, keys: Vec<SortKey>) -> Self {
+        Self::with_memory_budget(ctx, child, keys, DEFAULT_SORT_MEMORY)
+    }
+
+    pub fn with_memory_budget(ctx: &'a ExecutorContext, child: Box<dyn Executor + 'a>, keys: Vec<SortKey>, memory_budget: usize) -> Self {
+        SortExecutor { ctx, child, keys, memory_budget, output: SortOutput::Memory(Vec::new().into_iter()) }
+    }
+
+    // Sorts the buffered rows, reporting the first comparison that failed.
+    fn sort(&self, rows: &mut [KeyedRow]) -> CrabDbResult<()> {
+        let mut error = None;
+        rows.sort_by(|(a, _), (b, _)| compare_sort_keys(&self.keys, a, b).unwrap_or_else(|e| {
+            error.get_or_insert(e);
+            Ordering::Equal
+        }));
+        error.map_or(Ok(()), Err)
+    }
+}
+
+fn read_run(keys: &[SortKey], run: &TempTable, cursor: &mut Rid) -> CrabDbResult<Option<KeyedRow>> {
+    let Some(values) = run.read_next(cursor)? else {
+        return Ok(None);
+    };
+    Ok(Some((evaluate_sort_keys(keys, &values)?, values)))
+}
+
+impl Executor for SortExecutor<'_> {
+    fn init(&mut self) -> CrabDbResult<()> {
+        // Free the runs of an earlier pass before making new ones.
+        self.output = SortOutput::Memory(Vec::new().into_iter());
+        let mut buffer = Vec::new();
+        let mut buffered = 0;
+        let mut runs = Vec::new();
+        self.child.init()?;
+        loop {
+            let row = self.child.next()?;
+            let done = row.is_none();
+            if let Some(row) = row {
+                buffered += row_size(&row.values);
+                buffer.push((evaluate_sort_keys(&self.keys, &row.values)?, row.values));
+            }
+            if (done && !runs.is_empty() && !buffer.is_empty()) || buffered > self.memory_budget {
+                self.sort(&mut buffer)?;
+                let run = TempTable::new(self.ctx.catalog().bpm().clone(), self.child.output_schema())?;
+                for (_, values) in buffer.drain(..) {
+                    run.push(&values)?;
+                }
+                buffered = 0;
+                runs.push(run);
+            }
+            if done {
+                break;
+            }
+        }
+
+        if runs.is_empty() {
+            self.sort(&mut buffer)?;
+            self.output = SortOutput::Memory(buffer.into_iter().map(|(_, values)| values).collect::<Vec<_>>().into_iter());
+            return Ok(());
+        }
+        let mut heads = Vec::with_capacity(runs.len());
+        for run in runs {
+            let mut cursor = run.start();
+            let head = read_run(&self.keys, &run, &mut cursor)?;
+            heads.push((run, cursor, head));
+        }
+        self.output = SortOutput::Runs(heads);
+        Ok(())
+    }
+
+    fn next(&mut self) -> CrabDbResult<Option<Row>> {
+        let runs = match &mut self.output {
+            SortOutput::Memory(rows) => return Ok(rows.next().map(Row::new)),
+            SortOutput::Runs(runs) => runs,
+        };
+        // Taking the first of equal heads keeps the merge stable, since earlier runs hold
+        // earlier input.
+        let mut smallest: Option<(usize, &Vec<Value>)> = None;
+        for (i, (_, _, head)) in runs.iter().enumerate() {
+            let Some((keys, _)) = head else {
+                continue;
+            };
+            let smaller = match smallest {
+                None => true,
+                Some((_, best)) => compare_sort_keys(&self.keys, keys, best)? == Ordering::Less,
+            };
+            if smaller {
+                smallest = Some((i, keys));
+            }
+        }
+        let Some((i, _)) = smallest else {
+            return Ok(None);
+        };
+        let (run, cursor, head) = &mut runs[i];
+        let next = read_run(&self.keys, run, cursor)?;
+        let (_, values) = std::mem::replace(head, next).unwrap();
+        Ok(Some(Row::new(values)))
+    }
+
+    fn output_schema(&self) -> &Schema {
+        self.child.output_schema()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::catalog::system_catalog::Catalog;
+    use crate::concurrency::lock_manager::LockManager;
+    use crate::concurrency::transaction::IsolationLevel;
+    use crate::concurrency::transaction_manager::TransactionManager;
+    use crate::execution::executor::{execute, ExecutorContext};
+    use crate::execution::expression::Expression;
+    use crate::execution::seq_scan_executor::SeqScanExecutor;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use crate::storage::table::tuple::Tuple;
+    use crate::types::schema::{Column, Schema};
+    use crate::types::value::{DataType, Value};
+    use super::{SortExecutor, SortKey};
+
+    #[test]
+    pub fn test_external_sort_matches_in_memory_sort() {
+        let bpm = Arc::new(BufferPoolManager::new(6, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(6, 2))));
+        let catalog = Arc::new(Catalog::open(bpm).unwrap());
+        let txn_manager = Arc::new(TransactionManager::new(Arc::new(LockManager::new())));
+        let schema = Schema::new(vec![
+            Column::new("size", DataType::Int32), Column::new("id", DataType::Int64), Column::new("name", DataType::Varchar),
+        ]);
+        let table = catalog.create_table("crabs", schema.clone()).unwrap();
+        let txn = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        for id in 0..600i64 {
+            let size = if id % 11 == 0 { Value::Null } else { Value::Int32((id * 37 % 10) as i32) };
+            let row = [size, Value::Int64(id), Value::Varchar(format!("{id:0>40}"))];
+            txn_manager.insert(&txn, table.heap(), &Tuple::from_values(&schema, &row).unwrap()).unwrap();
+        }
+        txn_manager.commit(&txn).unwrap();
+        let ctx = ExecutorContext::new(catalog, txn_manager.clone(), txn_manager.begin(IsolationLevel::SnapshotIsolation));
+
+        // ORDER BY size DESC puts NULL sizes first; ties keep their input order
+        let keys = vec![SortKey::desc(Expression::Column(0))];
+        let in_memory = execute(&mut SortExecutor::new(&ctx, Box::new(SeqScanExecutor::new(&ctx, &table, None)), keys.clone())).unwrap();
+        let mut spilling = SortExecutor::with_memory_budget(&ctx, Box::new(SeqScanExecutor::new(&ctx, &table, None)), keys, 4096);
+        let external = execute(&mut spilling).unwrap();
+        assert_eq!(in_memory, external);
+        assert!(matches!(&spilling.output, super::SortOutput::Runs(runs) if runs.len() > 10));
+
+        let mut expected: Vec<_> = (0..600i64).collect();
+        expected.sort_by_key(|id| if id % 11 == 0 { i64::MIN } else { -(id * 37 % 10) });
+        assert_eq!(
+            expected.into_iter().map(Value::Int64).collect::<Vec<_>>(),
+            external.iter().map(|row| row.values[1].clone()).collect::<Vec<_>>()
+        );
+
+        // ORDER BY size, id DESC puts NULL sizes last
+        let keys = vec![SortKey::asc(Expression::Column(0)), SortKey::desc(Expression::Column(1))];
+        let sorted = execute(&mut SortExecutor::with_memory_budget(&ctx, Box::new(SeqScanExecutor::new(&ctx, &table, None)), keys, 4096)).unwrap();
+        assert_eq!(vec![Value::Int32(0), Value::Int64(590)], sorted[0].values[..2]);
+        assert_eq!(vec![Value::Null, Value::Int64(0)], sorted[599].values[..2]);
+    }
+}
@@ -0,0 +1,55 @@
+use std::sync::Arc;
+
+use crate::buffer_pool::access_strategy::BufferAccessStrategy;
+use crate::catalog::statistics::TableStatistics;
+use crate::catalog::table_info::TableInfo;
+use crate::concurrency::lock_manager::LockMode;
+use crate::types::schema::Schema;
+use crate::types::value::Value;
+use crate::types::CrabDbResult;
+
+use super::executor::{Executor, ExecutorContext, Row};
+use super::insert_executor::count_schema;
+
+// Collects the statistics of each table from the rows visible to the transaction and stores
+// them in the catalog, for ANALYZE. Outputs how many rows were read.
+pub struct StatisticsExecutor<'a> {
+    ctx: &'a ExecutorContext,
+    tables: &'a [Arc<TableInfo>],
+    schema: Schema,
+    count: Option<i64>,
+}
+
+impl<'a> StatisticsExecutor<'a> {
+    pub fn new(ctx: &'a ExecutorContext, tables: &'a [Arc<TableInfo>]) -> Self {
+        StatisticsExecutor { ctx, tables, schema: count_schema(), count: None }
+    }
+}
+
+impl Executor for StatisticsExecutor<'_> {
+    fn init(&mut self) -> CrabDbResult<()> {
+        let mut count = 0;
+        for table in self.tables {
+            self.ctx.lock_table(table, LockMode::IntentionShared)?;
+            let heap = table.heap();
+            let mut scan = self.ctx.txn_manager().scan(self.ctx.txn(), heap);
+            if let Some(strategy) = BufferAccessStrategy::for_scan(heap.bpm(), heap.num_pages()) {
+                scan = scan.with_strategy(strategy);
+            }
+            let rows = scan.map(|entry| entry.and_then(|(_, tuple)| tuple.values(table.schema())));
+            let statistics = TableStatistics::collect(table.schema().column_count(), rows)?;
+            count += statistics.row_count as i64;
+            self.ctx.catalog().set_statistics_of(table, statistics)?;
+        }
+        self.count = Some(count);
+        Ok(())
+    }
+
+    fn next(&mut self) -> CrabDbResult<Option<Row>> {
+        Ok(self.count.take().map(|count| Row::new(vec![Value::Int64(count)])))
+    }
+
+    fn output_schema(&self) -> &Schema {
+        &self.schema
+    }
+}
@@ -0,0 +1,74 @@
+use std::sync::Arc;
+
+use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+use crate::storage::common::INVALID_PAGE_ID;
+use crate::storage::table::table_heap::TableHeap;
+use crate::storage::table::tuple::{Rid, Tuple};
+use crate::types::schema::{Column, Schema};
+use crate::types::value::Value;
+use crate::types::CrabDbResult;
+
+// Scratch space for executors that have to hold on to more rows than they should keep in
+// memory. Rows live in a private heap in the buffer pool, so once the pool runs out of
+// frames they are written out to disk like any other page. The pages are freed when the
+// table is dropped.
+pub(crate) struct TempTable {
+    heap: TableHeap,
+    schema: Schema,
+}
+
+impl TempTable {
+    pub(crate) fn new(bpm: Arc<BufferPoolManager>, schema: &Schema) -> CrabDbResult<Self> {
+        // Executors produce NULLs in columns that are NOT NULL in the table, e.g. outer joins.
+        let schema = Schema::new(schema.columns().iter()
+            .map(|column| Column::new(column.name(), column.data_type()))
+            .collect());
+        Ok(TempTable { heap: TableHeap::new(bpm)?, schema })
+    }
+
+    pub(crate) fn push(&self, values: &[Value]) -> CrabDbResult<Rid> {
+        let mut data = self.schema.serialize_row(values)?;
+        // The heap cannot hold empty tuples, which is what a row without columns encodes to.
+        if data.is_empty() {
+            data.push(0);
+        }
+        self.heap.insert_tuple(&Tuple::new(data))
+    }
+
+    pub(crate) fn get(&self, rid: Rid) -> CrabDbResult<Vec<Value>> {
+        self.heap.get_tuple(rid)?.values(&self.schema)
+    }
+
+    // Where a scan of the table in insertion order starts. Scans keep their position in a
+    // rid, so they can be kept next to the table rather than borrowing it.
+    pub(crate) fn start(&self) -> Rid {
+        Rid::new(self.heap.first_page_id(), 0)
+    }
+
+    // Reads the row at `cursor` and moves it to the next one; None once the table is done.
+    pub(crate) fn read_next(&self, cursor: &mut Rid) -> CrabDbResult<Option<Vec<Value>>> {
+        while cursor.page_id() != INVALID_PAGE_ID {
+            let (page_id, slot_id) = (cursor.page_id(), cursor.slot_id());
+            let data = self.heap.read_page(page_id, |page| {
+                if slot_id < page.num_slots() {
+                    *cursor = Rid::new(page_id, slot_id + 1);
+                    let tuple = Tuple::new(page.get_tuple(slot_id)?.to_vec());
+                    return Ok(Some(self.heap.resolve_overflow(page.is_overflow(slot_id)?, tuple)?));
+                }
+                *cursor = Rid::new(page.next_page_id(), 0);
+                Ok(None)
+            })?;
+            if let Some(tuple) = data {
+                return Ok(Some(tuple.values(&self.schema)?));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Drop for TempTable {
+    fn drop(&mut self) {
+        // Pages that cannot be freed now are simply leaked.
+        let _ = self.heap.delete_pages();
+    }
+}
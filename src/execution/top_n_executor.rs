@@ -0,0 +1,153 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::Arc;
+
+use crate::types::schema::Schema;
+use crate::types::value::Value;
+use crate::types::CrabDbResult;
+
+use super::executor::{Executor, Row};
+use super::sort_executor::{compare_sort_keys, evaluate_sort_keys, SortKey};
+
+// ORDER BY ... LIMIT n without sorting everything: the best `n` rows seen so far are kept
+// in a max-heap whose top is the worst of them, so each input row costs one comparison with
+// the top and, if it beats it, O(log n) to replace it. Ties keep their input order, like
+// SortExecutor followed by LimitExecutor.
+pub struct TopNExecutor<'a> {
+    child: Box<dyn Executor + 'a>,
+    keys: Arc<[SortKey]>,
+    n: usize,
+    results: std::vec::IntoIter<Vec<Value>>,
+}
+
+struct HeapEntry {
+    keys: Arc<[SortKey]>,
+    sort_values: Vec<Value>,
+    // Position in the input, to break ties.
+    seq: usize,
+    values: Vec<Value>,
+}
+
+impl HeapEntry {
+    // Values that cannot be compared were already reported when the entry was pushed.
+    fn order(&self, other: &Self) -> Ordering {
+        compare_sort_keys(&self.keys, &self.sort_values, &other.sort_values)
+            .unwrap_or(Ordering::Equal)
+            .then(self.seq.cmp(&other.seq))
+    }
+}
+
+impl PartialEq for HeapEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.order(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HeapEntry {}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HeapEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.order(other)
+    }
+}
+
+impl<'a> TopNExecutor<'a> {
+    pub fn new(child: Box<dyn Executor + 'a>, keys: Vec<SortKey>, n: usize) -> Self {
+        TopNExecutor { child, keys: keys.into(), n, results: Vec::new().into_iter() }
+    }
+}
+
+impl Executor for TopNExecutor<'_> {
+    fn init(&mut self) -> CrabDbResult<()> {
+        let mut heap: BinaryHeap<HeapEntry> = BinaryHeap::new();
+        self.child.init()?;
+        let mut seq = 0;
+        while self.n > 0 {
+            let Some(row) = self.child.next()? else {
+                break;
+            };
+            let sort_values = evaluate_sort_keys(&self.keys, &row.values)?;
+            seq += 1;
+            if heap.len() == self.n {
+                let worst = heap.peek().unwrap();
+                // Later rows lose ties, so only a strictly better row gets in.
+                if compare_sort_keys(&self.keys, &sort_values, &worst.sort_values)? != Ordering::Less {
+                    continue;
+                }
+                heap.pop();
+            }
+            heap.push(HeapEntry { keys: self.keys.clone(), sort_values, seq, values: row.values });
+        }
+        self.results = heap.into_sorted_vec().into_iter().map(|entry| entry.values).collect::<Vec<_>>().into_iter();
+        Ok(())
+    }
+
+    fn next(&mut self) -> CrabDbResult<Option<Row>> {
+        Ok(self.results.next().map(Row::new))
+    }
+
+    fn output_schema(&self) -> &Schema {
+        self.child.output_schema()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::catalog::system_catalog::Catalog;
+    use crate::concurrency::lock_manager::LockManager;
+    use crate::concurrency::transaction::IsolationLevel;
+    use crate::concurrency::transaction_manager::TransactionManager;
+    use crate::execution::executor::{execute, ExecutorContext};
+    use crate::execution::expression::Expression;
+    use crate::execution::limit_executor::LimitExecutor;
+    use crate::execution::seq_scan_executor::SeqScanExecutor;
+    use crate::execution::sort_executor::{SortExecutor, SortKey};
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use crate::storage::table::tuple::Tuple;
+    use crate::types::schema::{Column, Schema};
+    use crate::types::value::{DataType, Value};
+    use super::TopNExecutor;
+
+    #[test]
+    pub fn test_top_n_matches_sort_and_limit() {
+        let bpm = Arc::new(BufferPoolManager::new(8, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(8, 2))));
+        let catalog = Arc::new(Catalog::open(bpm).unwrap());
+        let txn_manager = Arc::new(TransactionManager::new(Arc::new(LockManager::new())));
+        let schema = Schema::new(vec![Column::new("size", DataType::Int32), Column::new("id", DataType::Int64)]);
+        let table = catalog.create_table("crabs", schema.clone()).unwrap();
+        let txn = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        for id in 0..200i64 {
+            let size = if id % 13 == 0 { Value::Null } else { Value::Int32((id * 31 % 17) as i32) };
+            txn_manager.insert(&txn, table.heap(), &Tuple::from_values(&schema, &[size, Value::Int64(id)]).unwrap()).unwrap();
+        }
+        txn_manager.commit(&txn).unwrap();
+        let ctx = ExecutorContext::new(catalog, txn_manager.clone(), txn_manager.begin(IsolationLevel::SnapshotIsolation));
+        let scan = || Box::new(SeqScanExecutor::new(&ctx, &table, None));
+
+        for keys in [vec![SortKey::asc(Expression::Column(0))], vec![SortKey::desc(Expression::Column(0))]] {
+            for n in [0, 1, 7, 150, 500] {
+                let sorted = SortExecutor::new(&ctx, scan(), keys.clone());
+                let expected = execute(&mut LimitExecutor::new(Box::new(sorted), n)).unwrap();
+                let top_n = execute(&mut TopNExecutor::new(scan(), keys.clone(), n)).unwrap();
+                assert_eq!(expected, top_n);
+                assert_eq!(n.min(200), top_n.len());
+            }
+        }
+
+        let mut page = LimitExecutor::with_offset(scan(), 5, 197);
+        assert_eq!(
+            vec![Value::Int64(197), Value::Int64(198), Value::Int64(199)],
+            execute(&mut page).unwrap().into_iter().map(|row| row.values[1].clone()).collect::<Vec<_>>()
+        );
+    }
+}
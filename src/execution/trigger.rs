@@ -0,0 +1,222 @@
+use std::sync::Arc;
+
+use crate::catalog::table_info::TableInfo;
+use crate::catalog::trigger::{Trigger, TriggerEvent, TriggerTiming};
+use crate::sql::binder::{parse, Binder};
+use crate::sql::physical_plan::PhysicalPlan;
+use crate::sql::planner::{Plan, Planner};
+use crate::types::value::Value;
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::executor::{execute, ExecutorContext};
+
+// How deep triggers may set each other off through the statements they run, which keeps
+// a trigger writing to its own table from recursing forever.
+pub const MAX_TRIGGER_DEPTH: usize = 16;
+
+// The row a trigger is called for. A BEFORE trigger of an INSERT or UPDATE may change the
+// new row, and any BEFORE trigger may skip the write altogether. Statements a trigger runs
+// read and write in the transaction of the statement that set it off.
+pub struct TriggerRow<'a> {
+    ctx: &'a ExecutorContext,
+    table: &'a TableInfo,
+    timing: TriggerTiming,
+    event: TriggerEvent,
+    old: Option<&'a [Value]>,
+    new: Option<&'a mut Vec<Value>>,
+    skipped: bool,
+}
+
+impl TriggerRow<'_> {
+    pub fn table(&self) -> &TableInfo {
+        self.table
+    }
+
+    pub fn timing(&self) -> TriggerTiming {
+        self.timing
+    }
+
+    pub fn event(&self) -> TriggerEvent {
+        self.event
+    }
+
+    // The row as it was, for UPDATE and DELETE.
+    pub fn old_row(&self) -> Option<&[Value]> {
+        self.old
+    }
+
+    // The row as it is written, for INSERT and UPDATE.
+    pub fn new_row(&self) -> Option<&[Value]> {
+        self.new.as_deref().map(Vec::as_slice)
+    }
+
+    // Sets a column of the new row, cast to the column's type.
+    pub fn set(&mut self, column: &str, value: Value) -> CrabDbResult<()> {
+        if self.timing != TriggerTiming::Before {
+            return Err(CrabDBError::InvalidInput("Only BEFORE triggers can change the row".into()));
+        }
+        let schema = self.table.schema();
+        let index = schema.index_of(column)
+            .ok_or_else(|| CrabDBError::InvalidInput(format!("Table {} has no column {column}", self.table.name())))?;
+        let Some(new) = self.new.as_deref_mut() else {
+            return Err(CrabDBError::InvalidInput("DELETE triggers have no new row to change".into()));
+        };
+        new[index] = value.cast_to(schema.columns()[index].data_type())?;
+        Ok(())
+    }
+
+    // Leaves the row as it is: it is neither written nor counted, and the triggers after
+    // this one are not called.
+    pub fn skip(&mut self) -> CrabDbResult<()> {
+        if self.timing != TriggerTiming::Before {
+            return Err(CrabDBError::InvalidInput("Only BEFORE triggers can skip the row".into()));
+        }
+        self.skipped = true;
+        Ok(())
+    }
+
+    // Runs a query or INSERT, UPDATE or DELETE and returns how many rows it read or wrote.
+    pub fn execute(&self, sql: &str, parameters: &[Value]) -> CrabDbResult<u64> {
+        let (plan, ctx) = self.plan(sql, parameters)?;
+        let rows = execute(plan.build(&ctx).as_mut())?;
+        Ok(match plan {
+            PhysicalPlan::Insert { .. } | PhysicalPlan::Update { .. } | PhysicalPlan::Delete { .. } => {
+                rows.first().and_then(|row| row.values[0].as_i64()).unwrap_or(0) as u64
+            },
+            _ => rows.len() as u64,
+        })
+    }
+
+    // Runs a query and returns its rows.
+    pub fn query(&self, sql: &str, parameters: &[Value]) -> CrabDbResult<Vec<Vec<Value>>> {
+        let (plan, ctx) = self.plan(sql, parameters)?;
+        let rows = execute(plan.build(&ctx).as_mut())?;
+        Ok(rows.into_iter().map(|row| row.values).collect())
+    }
+
+    fn plan(&self, sql: &str, parameters: &[Value]) -> CrabDbResult<(PhysicalPlan, ExecutorContext)> {
+        let [statement] = &parse(sql)?[..] else {
+            return Err(CrabDBError::InvalidInput("A trigger runs one statement at a time".into()));
+        };
+        let ctx = self.ctx.nested()?;
+        let bound = Binder::new(ctx.catalog()).with_parameters(parameters).bind(statement)?;
+        match Planner::new(ctx.catalog()).plan(bound) {
+            Plan::Execute(plan) => Ok((plan, ctx)),
+            _ => Err(CrabDBError::InvalidInput("Triggers can only run queries, INSERT, UPDATE and DELETE".into())),
+        }
+    }
+}
+
+// The triggers of a table for one kind of write, split by when they run.
+pub(crate) struct Triggers<'a> {
+    ctx: &'a ExecutorContext,
+    table: &'a TableInfo,
+    event: TriggerEvent,
+    before: Vec<Arc<Trigger>>,
+    after: Vec<Arc<Trigger>>,
+}
+
+impl<'a> Triggers<'a> {
+    pub(crate) fn of(ctx: &'a ExecutorContext, table: &'a TableInfo, event: TriggerEvent) -> Self {
+        let (before, after) = ctx.catalog().triggers_of(table).into_iter()
+            .filter(|trigger| trigger.event() == event)
+            .partition(|trigger| trigger.timing() == TriggerTiming::Before);
+        Triggers { ctx, table, event, before, after }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.before.is_empty() && self.after.is_empty()
+    }
+
+    pub(crate) fn has_before(&self) -> bool {
+        !self.before.is_empty()
+    }
+
+    pub(crate) fn has_after(&self) -> bool {
+        !self.after.is_empty()
+    }
+
+    // Calls the BEFORE triggers in order. Returns whether the row is still to be written.
+    pub(crate) fn before(&self, old: Option<&[Value]>, mut new: Option<&mut Vec<Value>>) -> CrabDbResult<bool> {
+        for trigger in &self.before {
+            let mut row = self.row(TriggerTiming::Before, old, new.as_deref_mut());
+            trigger.call(&mut row)?;
+            if row.skipped {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // Calls the AFTER triggers in order, once the row is written.
+    pub(crate) fn after(&self, old: Option<&[Value]>, mut new: Option<&mut Vec<Value>>) -> CrabDbResult<()> {
+        for trigger in &self.after {
+            trigger.call(&mut self.row(TriggerTiming::After, old, new.as_deref_mut()))?;
+        }
+        Ok(())
+    }
+
+    fn row<'r>(&'r self, timing: TriggerTiming, old: Option<&'r [Value]>, new: Option<&'r mut Vec<Value>>) -> TriggerRow<'r> {
+        TriggerRow { ctx: self.ctx, table: self.table, timing, event: self.event, old, new, skipped: false }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::catalog::system_catalog::Catalog;
+    use crate::concurrency::lock_manager::LockManager;
+    use crate::concurrency::transaction::IsolationLevel;
+    use crate::concurrency::transaction_manager::TransactionManager;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use crate::types::schema::{Column, Schema};
+    use crate::types::value::DataType;
+
+    #[test]
+    pub fn test_triggers_run_in_order_and_stop_at_skip() {
+        let bpm = Arc::new(BufferPoolManager::new(16, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(16, 2))));
+        let catalog = Arc::new(Catalog::open(bpm).unwrap());
+        let schema = Schema::new(vec![Column::new("id", DataType::Int64), Column::new("name", DataType::Varchar)]);
+        let table = catalog.create_table("t", schema).unwrap();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        catalog.create_trigger("t", Trigger::new("upper", TriggerTiming::Before, TriggerEvent::Insert, |row| {
+            let name = row.new_row().unwrap()[1].as_str().unwrap().to_uppercase();
+            row.set("name", Value::Varchar(name))?;
+            if row.new_row().unwrap()[0] == Value::Int64(0) { row.skip() } else { Ok(()) }
+        })).unwrap();
+        catalog.create_trigger("t", Trigger::new("count", TriggerTiming::Before, TriggerEvent::Insert, move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        })).unwrap();
+        catalog.create_trigger("t", Trigger::new("late", TriggerTiming::After, TriggerEvent::Insert, |row| {
+            assert!(row.set("id", Value::Int64(2)).is_err());
+            assert!(row.skip().is_err());
+            Ok(())
+        })).unwrap();
+        assert!(catalog.create_trigger("t", Trigger::new("COUNT", TriggerTiming::After, TriggerEvent::Delete, |_| Ok(()))).is_err());
+        assert!(catalog.create_trigger("missing", Trigger::new("x", TriggerTiming::After, TriggerEvent::Delete, |_| Ok(()))).is_err());
+
+        let txn_manager = Arc::new(TransactionManager::new(Arc::new(LockManager::new())));
+        let ctx = ExecutorContext::new(catalog.clone(), txn_manager.clone(), txn_manager.begin(IsolationLevel::SnapshotIsolation));
+        let triggers = Triggers::of(&ctx, &table, TriggerEvent::Insert);
+        assert!(Triggers::of(&ctx, &table, TriggerEvent::Delete).is_empty());
+
+        let mut row = vec![Value::Int64(1), Value::Varchar("crab".into())];
+        assert!(triggers.before(None, Some(&mut row)).unwrap());
+        assert_eq!(row[1], Value::Varchar("CRAB".into()));
+        triggers.after(None, Some(&mut row)).unwrap();
+        let mut row = vec![Value::Int64(0), Value::Varchar("crab".into())];
+        assert!(!triggers.before(None, Some(&mut row)).unwrap());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        catalog.drop_trigger("t", "Upper").unwrap();
+        assert!(catalog.drop_trigger("t", "upper").is_err());
+        let names: Vec<_> = catalog.triggers_of(&table).iter().map(|trigger| trigger.name().to_string()).collect();
+        assert_eq!(names, ["count", "late"]);
+    }
+}
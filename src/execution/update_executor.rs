@@ -0,0 +1,81 @@
+use crate::catalog::table_info::TableInfo;
+use crate::catalog::trigger::TriggerEvent;
+use crate::concurrency::lock_manager::LockMode;
+use crate::storage::table::tuple::Tuple;
+use crate::types::schema::Schema;
+use crate::types::value::Value;
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::executor::{Executor, ExecutorContext, Row};
+use super::expression::Expression;
+use super::insert_executor::{count_schema, insert_index_entry};
+use super::trigger::Triggers;
+
+// Updates the rows its child reads from a table, which must come with their rids. Each
+// assignment sets a column to an expression over the old row, and the table's UPDATE
+// triggers are called around each row. The child is drained first, so rows that move while
+// being updated are not read again.
+pub struct UpdateExecutor<'a> {
+    ctx: &'a ExecutorContext,
+    table: &'a TableInfo,
+    child: Box<dyn Executor + 'a>,
+    assignments: Vec<(usize, Expression)>,
+    schema: Schema,
+    count: Option<i64>,
+}
+
+impl<'a> UpdateExecutor<'a> {
+    pub fn new(
+        ctx: &'a ExecutorContext,
+        table: &'a TableInfo,
+        child: Box<dyn Executor + 'a>,
+        assignments: Vec<(usize, Expression)>,
+    ) -> Self {
+        UpdateExecutor { ctx, table, child, assignments, schema: count_schema(), count: None }
+    }
+}
+
+impl Executor for UpdateExecutor<'_> {
+    fn init(&mut self) -> CrabDbResult<()> {
+        self.ctx.lock_table(self.table, LockMode::IntentionExclusive)?;
+        let mut rows = Vec::new();
+        self.child.init()?;
+        while let Some(row) = self.child.next()? {
+            let rid = row.rid.ok_or_else(|| CrabDBError::new("Rows to update must come from a table".into()))?;
+            rows.push((rid, row.values));
+        }
+        let indexes = self.ctx.catalog().indexes_of(self.table);
+        let triggers = Triggers::of(self.ctx, self.table, TriggerEvent::Update);
+        let mut count = 0;
+        for (rid, old) in &rows {
+            self.ctx.lock_row(self.table, LockMode::Exclusive, *rid)?;
+            let mut values = old.clone();
+            for (column, expression) in &self.assignments {
+                values[*column] = expression.evaluate(old)?;
+            }
+            if !triggers.before(Some(old), Some(&mut values))? {
+                continue;
+            }
+            let tuple = Tuple::from_values(self.table.schema(), &values)?;
+            let rid = self.ctx.txn_manager().update(self.ctx.txn(), self.table.heap(), *rid, &tuple)?;
+            if !indexes.is_empty() || triggers.has_after() {
+                let mut values = tuple.values(self.table.schema())?;
+                for index in &indexes {
+                    insert_index_entry(self.ctx, self.table, index, &values, rid)?;
+                }
+                triggers.after(Some(old), Some(&mut values))?;
+            }
+            count += 1;
+        }
+        self.count = Some(count);
+        Ok(())
+    }
+
+    fn next(&mut self) -> CrabDbResult<Option<Row>> {
+        Ok(self.count.take().map(|count| Row::new(vec![Value::Int64(count)])))
+    }
+
+    fn output_schema(&self) -> &Schema {
+        &self.schema
+    }
+}
@@ -0,0 +1,38 @@
+use crate::types::schema::Schema;
+use crate::types::CrabDbResult;
+
+use super::executor::{Executor, Row};
+use super::expression::Expression;
+
+// Produces rows of constant expressions, such as the rows of INSERT ... VALUES.
+pub struct ValuesExecutor {
+    rows: Vec<Vec<Expression>>,
+    schema: Schema,
+    position: usize,
+}
+
+impl ValuesExecutor {
+    pub fn new(rows: Vec<Vec<Expression>>, schema: Schema) -> Self {
+        ValuesExecutor { rows, schema, position: 0 }
+    }
+}
+
+impl Executor for ValuesExecutor {
+    fn init(&mut self) -> CrabDbResult<()> {
+        self.position = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> CrabDbResult<Option<Row>> {
+        let Some(row) = self.rows.get(self.position) else {
+            return Ok(None);
+        };
+        self.position += 1;
+        let values = row.iter().map(|expression| expression.evaluate(&[])).collect::<CrabDbResult<_>>()?;
+        Ok(Some(Row::new(values)))
+    }
+
+    fn output_schema(&self) -> &Schema {
+        &self.schema
+    }
+}
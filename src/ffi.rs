@@ -0,0 +1,395 @@
+// The C ABI for embedding the engine from other languages, in the style of SQLite: open a
+// handle, run statements on it, step through the rows of a query and read their values.
+// Every function returns a status code, and the message of the last failure on the calling
+// thread is kept for `crabdb_errmsg`. The build writes the header to include/crabdb.h; the
+// `///` comments here are what ends up in it.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use crate::db::crab_db::CrabDb;
+use crate::db::options::CrabDbOptions;
+use crate::db::cursor::Cursor;
+use crate::types::value::Value;
+use crate::types::{CrabDBError, CrabDbResult, ErrorCode};
+
+/// The call succeeded.
+pub const CRABDB_OK: c_int = 0;
+/// The statement was malformed or refers to something that does not exist.
+pub const CRABDB_INVALID_INPUT: c_int = 1;
+/// The transaction lost a write conflict; running it again may succeed.
+pub const CRABDB_CONFLICT: c_int = 2;
+/// The transaction was chosen as a deadlock victim; running it again may succeed.
+pub const CRABDB_DEADLOCK: c_int = 3;
+/// The transaction was already aborted.
+pub const CRABDB_TXN_ABORTED: c_int = 4;
+/// The buffer pool ran out of frames.
+pub const CRABDB_RESOURCE_EXHAUSTED: c_int = 5;
+/// Reading or writing a file failed.
+pub const CRABDB_IO: c_int = 6;
+/// The database files are damaged.
+pub const CRABDB_CORRUPTION: c_int = 7;
+/// A bug in the engine.
+pub const CRABDB_INTERNAL: c_int = 8;
+/// The transaction waited too long for a lock; running it again may succeed.
+pub const CRABDB_LOCK_TIMEOUT: c_int = 9;
+/// A page, row or encryption key the database looked for is not there.
+pub const CRABDB_NOT_FOUND: c_int = 10;
+/// A null pointer, or a string that is not UTF-8, was passed in.
+pub const CRABDB_MISUSE: c_int = 21;
+/// `crabdb_rows_next` moved to the next row.
+pub const CRABDB_ROW: c_int = 100;
+/// `crabdb_rows_next` went past the last row.
+pub const CRABDB_DONE: c_int = 101;
+
+/// Types `crabdb_value_type` reports.
+pub const CRABDB_TYPE_NULL: c_int = 0;
+pub const CRABDB_TYPE_BOOLEAN: c_int = 1;
+pub const CRABDB_TYPE_INT32: c_int = 2;
+pub const CRABDB_TYPE_INT64: c_int = 3;
+pub const CRABDB_TYPE_FLOAT64: c_int = 4;
+pub const CRABDB_TYPE_VARCHAR: c_int = 5;
+/// Microseconds since the Unix epoch.
+pub const CRABDB_TYPE_TIMESTAMP: c_int = 6;
+/// Read as text, e.g. "[1, 2.5]".
+pub const CRABDB_TYPE_VECTOR: c_int = 7;
+/// Read as text, e.g. "POINT(1 2)" or "BOX(0 0, 3 4)".
+pub const CRABDB_TYPE_GEOMETRY: c_int = 8;
+
+/// An open database.
+pub struct CrabDbHandle {
+    db: CrabDb,
+}
+
+/// The rows a query returns, read one at a time with `crabdb_rows_next`. A query runs as
+/// its rows are read.
+pub struct CrabDbRows {
+    rows: Cursor,
+    names: Vec<CString>,
+    current: Option<Vec<Value>>,
+    // Values of the current row as NUL-terminated text, made on first request.
+    texts: Vec<Option<Vec<u8>>>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn status(code: ErrorCode) -> c_int {
+    match code {
+        ErrorCode::InvalidInput => CRABDB_INVALID_INPUT,
+        ErrorCode::NotFound => CRABDB_NOT_FOUND,
+        ErrorCode::Conflict => CRABDB_CONFLICT,
+        ErrorCode::Deadlock => CRABDB_DEADLOCK,
+        ErrorCode::LockTimeout => CRABDB_LOCK_TIMEOUT,
+        ErrorCode::TxnAborted => CRABDB_TXN_ABORTED,
+        ErrorCode::ResourceExhausted => CRABDB_RESOURCE_EXHAUSTED,
+        ErrorCode::Io => CRABDB_IO,
+        ErrorCode::Corruption => CRABDB_CORRUPTION,
+        ErrorCode::Internal => CRABDB_INTERNAL,
+    }
+}
+
+fn set_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+fn misuse(message: &str) -> c_int {
+    set_error(message.to_string());
+    CRABDB_MISUSE
+}
+
+// Runs `f`, turning its error, or a panic, into a status code and the thread's last error.
+fn guard(f: impl FnOnce() -> CrabDbResult<c_int>) -> c_int {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(status)) => status,
+        Ok(Err(e)) => {
+            let code = status(e.code());
+            set_error(e.to_string());
+            code
+        },
+        Err(_) => {
+            set_error("The engine panicked".to_string());
+            CRABDB_INTERNAL
+        },
+    }
+}
+
+unsafe fn str_arg<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+/// The message of the last call on this thread that failed. Valid until the next failure on
+/// the thread.
+#[no_mangle]
+pub extern "C" fn crabdb_errmsg() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+/// Opens the database at `path`, creating it if it does not exist, and stores the handle in
+/// `*db_out`, or NULL on failure.
+///
+/// # Safety
+/// `path` must be a NUL-terminated string and `db_out` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn crabdb_open(path: *const c_char, db_out: *mut *mut CrabDbHandle) -> c_int {
+    if db_out.is_null() {
+        return misuse("db_out is null");
+    }
+    *db_out = ptr::null_mut();
+    let Some(path) = str_arg(path) else {
+        return misuse("path is null or not UTF-8");
+    };
+    guard(|| {
+        let db = CrabDb::open(path, CrabDbOptions::default())?;
+        *db_out = Box::into_raw(Box::new(CrabDbHandle { db }));
+        Ok(CRABDB_OK)
+    })
+}
+
+/// Runs the statements in `sql`, each in a transaction of its own, and stores in `*count_out`
+/// (if it is not NULL) how many rows the last one changed, or returned if it is a query.
+///
+/// # Safety
+/// `db` must come from `crabdb_open` and `sql` be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn crabdb_exec(db: *mut CrabDbHandle, sql: *const c_char, count_out: *mut u64) -> c_int {
+    let (Some(handle), Some(sql)) = (db.as_ref(), str_arg(sql)) else {
+        return misuse("db or sql is null, or sql is not UTF-8");
+    };
+    guard(|| {
+        let count = handle.db.execute(sql)?;
+        if !count_out.is_null() {
+            *count_out = count;
+        }
+        Ok(CRABDB_OK)
+    })
+}
+
+/// Runs the statements in `sql`, each in a transaction of its own, and stores the rows of
+/// the last one in `*rows_out`, or NULL on failure. Free them with `crabdb_rows_free`.
+///
+/// # Safety
+/// `db` must come from `crabdb_open`, `sql` be a NUL-terminated string and `rows_out` a
+/// valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn crabdb_query(db: *mut CrabDbHandle, sql: *const c_char, rows_out: *mut *mut CrabDbRows) -> c_int {
+    if rows_out.is_null() {
+        return misuse("rows_out is null");
+    }
+    *rows_out = ptr::null_mut();
+    let (Some(handle), Some(sql)) = (db.as_ref(), str_arg(sql)) else {
+        return misuse("db or sql is null, or sql is not UTF-8");
+    };
+    guard(|| {
+        let rows = handle.db.query(sql)?;
+        let names = rows.schema().columns().iter()
+            .map(|column| CString::new(column.name()).map_err(|_| CrabDBError::InvalidInput(format!("Column name {:?} holds a NUL", column.name()))))
+            .collect::<CrabDbResult<Vec<_>>>()?;
+        let texts = vec![None; names.len()];
+        *rows_out = Box::into_raw(Box::new(CrabDbRows { rows, names, current: None, texts }));
+        Ok(CRABDB_OK)
+    })
+}
+
+/// Closes the database, flushing everything it holds. `db` is freed even if this fails.
+///
+/// # Safety
+/// `db` must come from `crabdb_open` and not be used again; NULL is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn crabdb_close(db: *mut CrabDbHandle) -> c_int {
+    if db.is_null() {
+        return CRABDB_OK;
+    }
+    let handle = Box::from_raw(db);
+    guard(|| {
+        handle.db.close()?;
+        Ok(CRABDB_OK)
+    })
+}
+
+/// How many columns the rows have.
+///
+/// # Safety
+/// `rows` must come from `crabdb_query`.
+#[no_mangle]
+pub unsafe extern "C" fn crabdb_rows_column_count(rows: *const CrabDbRows) -> usize {
+    rows.as_ref().map_or(0, |rows| rows.names.len())
+}
+
+/// The name of column `column`, or NULL if there is no such column. Valid until the rows are
+/// freed.
+///
+/// # Safety
+/// `rows` must come from `crabdb_query`.
+#[no_mangle]
+pub unsafe extern "C" fn crabdb_rows_column_name(rows: *const CrabDbRows, column: usize) -> *const c_char {
+    rows.as_ref().and_then(|rows| rows.names.get(column)).map_or(ptr::null(), |name| name.as_ptr())
+}
+
+/// Moves to the next row: returns CRABDB_ROW if there is one, CRABDB_DONE once the rows are
+/// exhausted, or an error code if the query failed, after which there are no more rows.
+///
+/// # Safety
+/// `rows` must come from `crabdb_query`.
+#[no_mangle]
+pub unsafe extern "C" fn crabdb_rows_next(rows: *mut CrabDbRows) -> c_int {
+    let Some(rows) = rows.as_mut() else {
+        return misuse("rows is null");
+    };
+    rows.current = None;
+    rows.texts.iter_mut().for_each(|text| *text = None);
+    guard(|| {
+        rows.current = rows.rows.next().transpose()?;
+        Ok(if rows.current.is_some() { CRABDB_ROW } else { CRABDB_DONE })
+    })
+}
+
+unsafe fn value<'a>(rows: *const CrabDbRows, column: usize) -> Option<&'a Value> {
+    rows.as_ref()?.current.as_ref()?.get(column)
+}
+
+/// The type of column `column` of the current row, CRABDB_TYPE_NULL when the value is NULL
+/// or there is no such value.
+///
+/// # Safety
+/// `rows` must come from `crabdb_query`.
+#[no_mangle]
+pub unsafe extern "C" fn crabdb_value_type(rows: *const CrabDbRows, column: usize) -> c_int {
+    match value(rows, column) {
+        None | Some(Value::Null) => CRABDB_TYPE_NULL,
+        Some(Value::Boolean(_)) => CRABDB_TYPE_BOOLEAN,
+        Some(Value::Int32(_)) => CRABDB_TYPE_INT32,
+        Some(Value::Int64(_)) => CRABDB_TYPE_INT64,
+        Some(Value::Float64(_)) => CRABDB_TYPE_FLOAT64,
+        Some(Value::Varchar(_)) => CRABDB_TYPE_VARCHAR,
+        Some(Value::Timestamp(_)) => CRABDB_TYPE_TIMESTAMP,
+        Some(Value::Vector(_)) => CRABDB_TYPE_VECTOR,
+        Some(Value::Geometry(_)) => CRABDB_TYPE_GEOMETRY,
+    }
+}
+
+/// Column `column` of the current row as an integer: booleans are 0 or 1, and anything that
+/// is not an integer, boolean or timestamp is 0.
+///
+/// # Safety
+/// `rows` must come from `crabdb_query`.
+#[no_mangle]
+pub unsafe extern "C" fn crabdb_value_int64(rows: *const CrabDbRows, column: usize) -> i64 {
+    match value(rows, column) {
+        Some(Value::Boolean(b)) => *b as i64,
+        Some(value) => value.as_i64().unwrap_or(0),
+        None => 0,
+    }
+}
+
+/// Column `column` of the current row as a double; 0 unless it is a number.
+///
+/// # Safety
+/// `rows` must come from `crabdb_query`.
+#[no_mangle]
+pub unsafe extern "C" fn crabdb_value_double(rows: *const CrabDbRows, column: usize) -> f64 {
+    value(rows, column).and_then(Value::as_f64).unwrap_or(0.0)
+}
+
+/// Column `column` of the current row as NUL-terminated text, values other than VARCHAR
+/// written out as SQL shows them, or NULL when the value is NULL. Stores the length without
+/// the terminator in `*len_out` if it is not NULL; a VARCHAR may hold NULs of its own. Valid
+/// until the next call to `crabdb_rows_next`.
+///
+/// # Safety
+/// `rows` must come from `crabdb_query`.
+#[no_mangle]
+pub unsafe extern "C" fn crabdb_value_text(rows: *mut CrabDbRows, column: usize, len_out: *mut usize) -> *const c_char {
+    let Some(rows) = rows.as_mut() else {
+        return ptr::null();
+    };
+    let Some(value) = rows.current.as_ref().and_then(|row| row.get(column)).filter(|value| !value.is_null()) else {
+        return ptr::null();
+    };
+    let text = rows.texts[column].get_or_insert_with(|| {
+        let mut bytes = match value {
+            Value::Varchar(s) => s.clone().into_bytes(),
+            value => value.to_string().into_bytes(),
+        };
+        bytes.push(0);
+        bytes
+    });
+    if !len_out.is_null() {
+        *len_out = text.len() - 1;
+    }
+    text.as_ptr() as *const c_char
+}
+
+/// Frees rows from `crabdb_query`; NULL is ignored.
+///
+/// # Safety
+/// `rows` must come from `crabdb_query` and not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn crabdb_rows_free(rows: *mut CrabDbRows) {
+    if !rows.is_null() {
+        drop(Box::from_raw(rows));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{CStr, CString};
+    use std::ptr;
+
+    use tempfile::TempDir;
+
+    use super::{
+        crabdb_close, crabdb_errmsg, crabdb_exec, crabdb_open, crabdb_query, crabdb_rows_column_count, crabdb_rows_column_name,
+        crabdb_rows_free, crabdb_rows_next, crabdb_value_double, crabdb_value_int64, crabdb_value_text, crabdb_value_type,
+        CRABDB_DONE, CRABDB_INVALID_INPUT, CRABDB_MISUSE, CRABDB_OK, CRABDB_ROW, CRABDB_TYPE_INT64, CRABDB_TYPE_NULL,
+    };
+
+    #[test]
+    pub fn test_ffi_runs_statements_and_reads_rows() {
+        let dir = TempDir::new().unwrap();
+        let path = CString::new(dir.path().join("crabs.db").to_str().unwrap()).unwrap();
+        let sql = |sql: &str| CString::new(sql).unwrap();
+        unsafe {
+            let mut db = ptr::null_mut();
+            assert_eq!(CRABDB_OK, crabdb_open(path.as_ptr(), &mut db));
+            assert_eq!(CRABDB_OK, crabdb_exec(db, sql("CREATE TABLE crabs (id BIGINT NOT NULL, name VARCHAR, weight DOUBLE)").as_ptr(), ptr::null_mut()));
+            let mut count = 0;
+            assert_eq!(CRABDB_OK, crabdb_exec(db, sql("INSERT INTO crabs VALUES (1, 'ferris', 1.5), (2, NULL, NULL)").as_ptr(), &mut count));
+            assert_eq!(2, count);
+
+            assert_eq!(CRABDB_INVALID_INPUT, crabdb_exec(db, sql("SELECT * FROM missing").as_ptr(), ptr::null_mut()));
+            assert_eq!("Table missing does not exist", CStr::from_ptr(crabdb_errmsg()).to_str().unwrap());
+            assert_eq!(CRABDB_MISUSE, crabdb_exec(db, ptr::null(), ptr::null_mut()));
+
+            let mut rows = ptr::null_mut();
+            assert_eq!(CRABDB_OK, crabdb_query(db, sql("SELECT id, name, weight FROM crabs ORDER BY id").as_ptr(), &mut rows));
+            assert_eq!(3, crabdb_rows_column_count(rows));
+            assert_eq!("name", CStr::from_ptr(crabdb_rows_column_name(rows, 1)).to_str().unwrap());
+            assert!(crabdb_rows_column_name(rows, 3).is_null());
+
+            assert_eq!(CRABDB_ROW, crabdb_rows_next(rows));
+            assert_eq!(CRABDB_TYPE_INT64, crabdb_value_type(rows, 0));
+            assert_eq!(1, crabdb_value_int64(rows, 0));
+            let mut len = 0;
+            assert_eq!("ferris", CStr::from_ptr(crabdb_value_text(rows, 1, &mut len)).to_str().unwrap());
+            assert_eq!(6, len);
+            assert_eq!(1.5, crabdb_value_double(rows, 2));
+            assert_eq!("1.5", CStr::from_ptr(crabdb_value_text(rows, 2, ptr::null_mut())).to_str().unwrap());
+
+            assert_eq!(CRABDB_ROW, crabdb_rows_next(rows));
+            assert_eq!(CRABDB_TYPE_NULL, crabdb_value_type(rows, 1));
+            assert!(crabdb_value_text(rows, 1, ptr::null_mut()).is_null());
+            assert_eq!(CRABDB_DONE, crabdb_rows_next(rows));
+            crabdb_rows_free(rows);
+
+            assert_eq!(CRABDB_OK, crabdb_close(db));
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+use crate::storage::common::{PageId, INVALID_PAGE_ID, PAGE_HEADER_SIZE, PAGE_SIZE};
+use crate::types::{CrabDBError, CrabDbResult};
+
+// Header page layout, after the common page header:
+//   num_hashes u32 | num_words u32 | data page ids u64 * MAX_DATA_PAGES
+// Data pages hold the bit array as little endian u64 words, WORDS_PER_PAGE to a page.
+const NUM_HASHES_OFFSET: usize = PAGE_HEADER_SIZE;
+const NUM_WORDS_OFFSET: usize = PAGE_HEADER_SIZE + 4;
+const DATA_PAGE_IDS_OFFSET: usize = PAGE_HEADER_SIZE + 8;
+const MAX_DATA_PAGES: usize = (PAGE_SIZE - DATA_PAGE_IDS_OFFSET) / 8;
+const WORDS_PER_PAGE: usize = (PAGE_SIZE - PAGE_HEADER_SIZE) / 8;
+const MAX_WORDS: usize = MAX_DATA_PAGES * WORDS_PER_PAGE;
+// Stored as a u32; far more than any sane false positive rate asks for.
+const MAX_HASHES: u32 = 32;
+const SEED: u32 = 0x9e37_79b9;
+
+// A Bloom filter: a bit array with `num_hashes` bits set per key, answering "definitely
+// absent" or "maybe present". Keys cannot be taken out again, so removals only make it less
+// selective. The filter is sized up front for an expected number of keys; going past it
+// raises the false positive rate rather than failing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter {
+    num_hashes: u32,
+    words: Vec<u64>,
+    // Where the filter lives on disk once `write` has been called; INVALID_PAGE_ID before.
+    header_page_id: PageId,
+    data_page_ids: Vec<PageId>,
+}
+
+impl BloomFilter {
+    // Sizes the filter so that `expected_keys` keys give about `false_positive_rate` false
+    // positives: m = -n ln p / (ln 2)^2 bits and k = (m / n) ln 2 hashes.
+    pub fn new(expected_keys: usize, false_positive_rate: f64) -> CrabDbResult<Self> {
+        if expected_keys == 0 || !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(CrabDBError::InvalidInput(format!(
+                "Invalid Bloom filter for {expected_keys} keys at false positive rate {false_positive_rate}"
+            )));
+        }
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-(expected_keys as f64) * false_positive_rate.ln() / (ln2 * ln2)).ceil().max(64.0);
+        let num_words = (num_bits / 64.0).ceil() as usize;
+        if num_words > MAX_WORDS {
+            return Err(CrabDBError::InvalidInput(format!(
+                "A Bloom filter for {expected_keys} keys at false positive rate {false_positive_rate} needs more than the {} bits that fit its pages",
+                MAX_WORDS * 64
+            )));
+        }
+        let num_hashes = ((num_words * 64) as f64 / expected_keys as f64 * ln2).round().clamp(1.0, MAX_HASHES as f64) as u32;
+        Ok(BloomFilter { num_hashes, words: vec![0; num_words], header_page_id: INVALID_PAGE_ID, data_page_ids: Vec::new() })
+    }
+
+    pub fn num_bits(&self) -> usize {
+        self.words.len() * 64
+    }
+
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+
+    pub fn header_page_id(&self) -> PageId {
+        self.header_page_id
+    }
+
+    // Two CRC32C hashes combined as h1 + i * h2 (Kirsch and Mitzenmacher), which is as good
+    // as `num_hashes` independent ones. CRC32C is stable across builds, so the bits can be
+    // persisted.
+    fn bits(&self, key: &[u8]) -> impl Iterator<Item = usize> {
+        let h1 = crc32c::crc32c(key) as u64;
+        let h2 = crc32c::crc32c_append(SEED, key) as u64 | 1;
+        let num_bits = self.num_bits() as u64;
+        (0..self.num_hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits) as usize)
+    }
+
+    // Sets the key's bits and returns the words that changed.
+    fn set_bits(&mut self, key: &[u8]) -> Vec<usize> {
+        let mut changed = Vec::new();
+        for bit in self.bits(key).collect::<Vec<_>>() {
+            let word = bit / 64;
+            let mask = 1 << (bit % 64);
+            if self.words[word] & mask == 0 {
+                self.words[word] |= mask;
+                if !changed.contains(&word) {
+                    changed.push(word);
+                }
+            }
+        }
+        changed
+    }
+
+    pub fn insert(&mut self, key: &[u8]) {
+        self.set_bits(key);
+    }
+
+    // Like `insert`, and writes the words that changed through to the filter's pages.
+    pub fn insert_and_write(&mut self, bpm: &BufferPoolManager, key: &[u8]) -> CrabDbResult<()> {
+        if self.header_page_id == INVALID_PAGE_ID {
+            return Err(CrabDBError::InvalidInput("Bloom filter has not been written to pages".to_string()));
+        }
+        for word in self.set_bits(key) {
+            let mut page = bpm.fetch_page_write(self.data_page_ids[word / WORDS_PER_PAGE])?;
+            let offset = PAGE_HEADER_SIZE + (word % WORDS_PER_PAGE) * 8;
+            page[offset..offset + 8].copy_from_slice(&self.words[word].to_le_bytes());
+        }
+        Ok(())
+    }
+
+    // False means the key was never inserted; true means it may have been.
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        self.bits(key).all(|bit| self.words[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    // Writes the whole filter out, allocating its pages the first time, and returns the
+    // header page id to `open` it from.
+    pub fn write(&mut self, bpm: &BufferPoolManager) -> CrabDbResult<PageId> {
+        if self.header_page_id == INVALID_PAGE_ID {
+            let mut header = bpm.new_page_write()?;
+            let mut data_page_ids = Vec::new();
+            for _ in 0..self.words.len().div_ceil(WORDS_PER_PAGE) {
+                data_page_ids.push(bpm.new_page_write()?.page_id());
+            }
+            header[NUM_HASHES_OFFSET..NUM_WORDS_OFFSET].copy_from_slice(&self.num_hashes.to_le_bytes());
+            header[NUM_WORDS_OFFSET..DATA_PAGE_IDS_OFFSET].copy_from_slice(&(self.words.len() as u32).to_le_bytes());
+            for (i, page_id) in data_page_ids.iter().enumerate() {
+                let offset = DATA_PAGE_IDS_OFFSET + i * 8;
+                header[offset..offset + 8].copy_from_slice(&page_id.to_le_bytes());
+            }
+            self.header_page_id = header.page_id();
+            self.data_page_ids = data_page_ids;
+        }
+        for (words, &page_id) in self.words.chunks(WORDS_PER_PAGE).zip(&self.data_page_ids) {
+            let mut page = bpm.fetch_page_write(page_id)?;
+            for (i, word) in words.iter().enumerate() {
+                let offset = PAGE_HEADER_SIZE + i * 8;
+                page[offset..offset + 8].copy_from_slice(&word.to_le_bytes());
+            }
+        }
+        Ok(self.header_page_id)
+    }
+
+    pub fn open(bpm: &BufferPoolManager, header_page_id: PageId) -> CrabDbResult<Self> {
+        let (num_hashes, num_words, data_page_ids) = {
+            let header = bpm.fetch_page_read(header_page_id)?;
+            let num_hashes = u32::from_le_bytes(header[NUM_HASHES_OFFSET..NUM_WORDS_OFFSET].try_into().unwrap());
+            let num_words = u32::from_le_bytes(header[NUM_WORDS_OFFSET..DATA_PAGE_IDS_OFFSET].try_into().unwrap()) as usize;
+            if num_hashes == 0 || num_hashes > MAX_HASHES || num_words == 0 || num_words > MAX_WORDS {
+                return Err(CrabDBError::Corruption(format!("Page {header_page_id} is not a valid Bloom filter header")));
+            }
+            let data_page_ids: Vec<PageId> = (0..num_words.div_ceil(WORDS_PER_PAGE))
+                .map(|i| {
+                    let offset = DATA_PAGE_IDS_OFFSET + i * 8;
+                    PageId::from_le_bytes(header[offset..offset + 8].try_into().unwrap())
+                })
+                .collect();
+            (num_hashes, num_words, data_page_ids)
+        };
+        let mut words = Vec::with_capacity(num_words);
+        for &page_id in &data_page_ids {
+            let page = bpm.fetch_page_read(page_id)?;
+            let count = (num_words - words.len()).min(WORDS_PER_PAGE);
+            words.extend((0..count).map(|i| {
+                let offset = PAGE_HEADER_SIZE + i * 8;
+                u64::from_le_bytes(page[offset..offset + 8].try_into().unwrap())
+            }));
+        }
+        Ok(BloomFilter { num_hashes, words, header_page_id, data_page_ids })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use super::BloomFilter;
+
+    #[test]
+    pub fn test_bloom_filter_round_trips_through_pages() {
+        let mut filter = BloomFilter::new(1000, 0.01).unwrap();
+        assert_eq!(9600, filter.num_bits());
+        assert_eq!(7, filter.num_hashes());
+        for i in 0..1000u64 {
+            filter.insert(&i.to_le_bytes());
+        }
+        assert!((0..1000u64).all(|i| filter.may_contain(&i.to_le_bytes())));
+        let false_positives = (1000..11000u64).filter(|i| filter.may_contain(&i.to_le_bytes())).count();
+        assert!(false_positives < 300, "{false_positives} false positives");
+
+        // big enough to need a few data pages
+        let bpm = BufferPoolManager::new(4, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(4, 2)));
+        let mut filter = BloomFilter::new(100_000, 0.01).unwrap();
+        filter.insert(b"first");
+        let header_page_id = filter.write(&bpm).unwrap();
+        filter.insert_and_write(&bpm, b"second").unwrap();
+        let reopened = BloomFilter::open(&bpm, header_page_id).unwrap();
+        assert_eq!(filter, reopened);
+        assert!(reopened.may_contain(b"first") && reopened.may_contain(b"second"));
+
+        assert!(BloomFilter::new(10, 1.0).is_err());
+        assert!(BloomFilter::new(0, 0.1).is_err());
+        assert!(BloomFilter::new(1 << 30, 0.001).is_err());
+        assert!(BloomFilter::new(10, 0.1).unwrap().insert_and_write(&bpm, b"key").is_err());
+    }
+}
@@ -0,0 +1,706 @@
+use std::ops::Bound;
+use std::sync::{Arc, RwLock, RwLockWriteGuard};
+
+use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+use crate::buffer_pool::eviction::replacer::AccessType;
+use crate::buffer_pool::page_guard::{ReadPageGuard, WritePageGuard};
+use crate::storage::common::{PageId, INVALID_PAGE_ID, PAGE_SIZE};
+use crate::storage::table::tuple::Rid;
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::bplus_tree_iterator::BPlusTreeIterator;
+use super::bplus_tree_page::{internal_capacity, leaf_capacity, InternalNode, LeafNode, Node, TreeHeader};
+use super::table_index::{Index, IndexIterator, IndexKind};
+
+// A node latched by a pessimistic delete, along with its left sibling, which is latched
+// before the node itself so latches on one level are always taken left to right.
+struct LatchedNode<'a> {
+    guard: WritePageGuard<'a>,
+    node: Node,
+    left_sibling: Option<(WritePageGuard<'a>, Node)>,
+}
+
+fn search(leaf: &LeafNode, key: &[u8]) -> Result<usize, usize> {
+    leaf.keys.binary_search_by(|k| k.as_slice().cmp(key))
+}
+
+fn read_leaf(data: &[u8; PAGE_SIZE], page_id: PageId) -> CrabDbResult<LeafNode> {
+    match Node::read(data, page_id)? {
+        Node::Leaf(leaf) => Ok(leaf),
+        Node::Internal(_) => Err(CrabDBError::Corruption(format!("Page {page_id} is not a B+ tree leaf"))),
+    }
+}
+
+fn internal_mut(node: &mut Node) -> CrabDbResult<&mut InternalNode> {
+    match node {
+        Node::Internal(internal) => Ok(internal),
+        Node::Leaf(_) => Err(CrabDBError::Corruption("B+ tree leaf found above another node".into())),
+    }
+}
+
+// A disk-resident B+ tree mapping unique fixed-size keys to record ids. Keys are compared
+// as raw bytes, so callers encode them in an order-preserving way (big endian integers and
+// so on). The root page id lives in a header page, which is all that is needed to reopen
+// the tree.
+//
+// Concurrency follows latch crabbing. Readers hold at most a node and its parent, read
+// latched. Writers first descend the same way and write-latch only the leaf; if the leaf
+// would split or underflow they start over, write-latching the path from the root and
+// letting go of everything above a node that is guaranteed to absorb the change.
+pub struct BPlusTree {
+    bpm: Arc<BufferPoolManager>,
+    header_page_id: PageId,
+    key_size: usize,
+    leaf_max_size: usize,
+    internal_max_size: usize,
+    // Latch over the root pointer: readers hold it until the root page is latched, and
+    // writers for as long as the root might change.
+    root_page_id: RwLock<PageId>,
+}
+
+impl BPlusTree {
+    pub fn new(bpm: Arc<BufferPoolManager>, key_size: usize) -> CrabDbResult<Self> {
+        let leaf_max_size = leaf_capacity(key_size).saturating_sub(1);
+        let internal_max_size = internal_capacity(key_size).saturating_sub(1);
+        Self::with_max_sizes(bpm, key_size, leaf_max_size, internal_max_size)
+    }
+
+    // Smaller node sizes than a page allows make splits and merges easy to exercise.
+    pub fn with_max_sizes(
+        bpm: Arc<BufferPoolManager>,
+        key_size: usize,
+        leaf_max_size: usize,
+        internal_max_size: usize,
+    ) -> CrabDbResult<Self> {
+        if key_size == 0 || leaf_max_size < 2 || leaf_max_size >= leaf_capacity(key_size)
+            || internal_max_size < 3 || internal_max_size >= internal_capacity(key_size) {
+            return Err(CrabDBError::InvalidInput(format!(
+                "Invalid B+ tree node sizes for {key_size} byte keys: leaf {leaf_max_size}, internal {internal_max_size}"
+            )));
+        }
+        let header_page_id = bpm.new_page_write()?.page_id();
+        let root_page_id = {
+            let mut guard = bpm.new_page_write()?;
+            Node::Leaf(LeafNode { keys: Vec::new(), rids: Vec::new(), next_page_id: INVALID_PAGE_ID })
+                .write(&mut guard, key_size);
+            guard.page_id()
+        };
+
+        let tree = BPlusTree {
+            bpm,
+            header_page_id,
+            key_size,
+            leaf_max_size,
+            internal_max_size,
+            root_page_id: RwLock::new(INVALID_PAGE_ID),
+        };
+        tree.set_root(&mut tree.root_page_id.write().unwrap(), root_page_id)?;
+        Ok(tree)
+    }
+
+    pub fn open(bpm: Arc<BufferPoolManager>, header_page_id: PageId) -> CrabDbResult<Self> {
+        let header = TreeHeader::read(&*bpm.fetch_page_read(header_page_id)?);
+        Ok(BPlusTree {
+            bpm,
+            header_page_id,
+            key_size: header.key_size,
+            leaf_max_size: header.leaf_max_size,
+            internal_max_size: header.internal_max_size,
+            root_page_id: RwLock::new(header.root_page_id),
+        })
+    }
+
+    pub fn header_page_id(&self) -> PageId {
+        self.header_page_id
+    }
+
+    pub fn key_size(&self) -> usize {
+        self.key_size
+    }
+
+    pub fn get(&self, key: &[u8]) -> CrabDbResult<Option<Rid>> {
+        self.check_key(key)?;
+        let (_guard, leaf) = self.find_leaf_read(Some(key))?;
+        Ok(search(&leaf, key).ok().map(|i| leaf.rids[i]))
+    }
+
+    // Returns false, leaving the tree untouched, if the key is already present.
+    pub fn insert(&self, key: &[u8], rid: Rid) -> CrabDbResult<bool> {
+        self.check_key(key)?;
+        if let Some((mut guard, mut leaf)) = self.find_leaf_write(key)? {
+            match search(&leaf, key) {
+                Ok(_) => return Ok(false),
+                Err(position) if leaf.keys.len() < self.leaf_max_size => {
+                    leaf.keys.insert(position, key.to_vec());
+                    leaf.rids.insert(position, rid);
+                    Node::Leaf(leaf).write(&mut guard, self.key_size);
+                    return Ok(true);
+                },
+                Err(_) => (),
+            }
+        }
+        self.insert_pessimistic(key, rid)
+    }
+
+    // Returns false if the key was not present.
+    pub fn remove(&self, key: &[u8]) -> CrabDbResult<bool> {
+        self.check_key(key)?;
+        if let Some((mut guard, mut leaf)) = self.find_leaf_write(key)? {
+            match search(&leaf, key) {
+                Err(_) => return Ok(false),
+                Ok(position) if leaf.keys.len() > self.leaf_max_size / 2 => {
+                    leaf.keys.remove(position);
+                    leaf.rids.remove(position);
+                    Node::Leaf(leaf).write(&mut guard, self.key_size);
+                    return Ok(true);
+                },
+                Ok(_) => (),
+            }
+        }
+        self.remove_pessimistic(key)
+    }
+
+    pub fn iter(&self) -> BPlusTreeIterator<'_> {
+        BPlusTreeIterator::new(self, Bound::Unbounded, Bound::Unbounded)
+    }
+
+    // Entries with keys in the given bounds, in key order.
+    pub fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> BPlusTreeIterator<'_> {
+        BPlusTreeIterator::new(self, start.map(|key| key.to_vec()), end.map(|key| key.to_vec()))
+    }
+
+    // Copy of the leaf holding the first key within `start`, with that key's position, or
+    // None past the last key. Walks right along the leaves, latching each before letting go
+    // of the previous one, when the key is not in the leaf the descent lands on.
+    pub(crate) fn seek(&self, start: &Bound<Vec<u8>>) -> CrabDbResult<Option<(LeafNode, usize)>> {
+        // The guard is never read, only held so the next leaf is latched before this one
+        // is released.
+        let (mut _guard, mut leaf) = match start {
+            Bound::Included(key) | Bound::Excluded(key) => self.find_leaf_read(Some(key))?,
+            Bound::Unbounded => self.find_leaf_read(None)?,
+        };
+        loop {
+            let position = match start {
+                Bound::Included(key) => leaf.keys.partition_point(|k| k < key),
+                Bound::Excluded(key) => leaf.keys.partition_point(|k| k <= key),
+                Bound::Unbounded => 0,
+            };
+            if position < leaf.keys.len() {
+                return Ok(Some((leaf, position)));
+            }
+            if leaf.next_page_id == INVALID_PAGE_ID {
+                return Ok(None);
+            }
+            let next_guard = self.bpm.fetch_page_read_with_access(leaf.next_page_id, AccessType::Index)?;
+            leaf = read_leaf(&next_guard, next_guard.page_id())?;
+            _guard = next_guard;
+        }
+    }
+
+    fn check_key(&self, key: &[u8]) -> CrabDbResult<()> {
+        if key.len() != self.key_size {
+            return Err(CrabDBError::InvalidInput(format!("Key must be {} bytes, got {}", self.key_size, key.len())));
+        }
+        Ok(())
+    }
+
+    fn min_size(&self, node: &Node) -> usize {
+        match node {
+            Node::Leaf(_) => self.leaf_max_size / 2,
+            Node::Internal(_) => self.internal_max_size.div_ceil(2),
+        }
+    }
+
+    // Read-latched descent to the leaf that may hold `key`, or the leftmost leaf.
+    fn find_leaf_read(&self, key: Option<&[u8]>) -> CrabDbResult<(ReadPageGuard<'_>, LeafNode)> {
+        let root_page_id = self.root_page_id.read().unwrap();
+        let mut guard = self.bpm.fetch_page_read_with_access(*root_page_id, AccessType::Index)?;
+        drop(root_page_id);
+        loop {
+            match Node::read(&guard, guard.page_id())? {
+                Node::Leaf(leaf) => return Ok((guard, leaf)),
+                Node::Internal(internal) => {
+                    let child_page_id = internal.children[key.map_or(0, |key| internal.child_index(key))];
+                    guard = self.bpm.fetch_page_read_with_access(child_page_id, AccessType::Index)?;
+                },
+            }
+        }
+    }
+
+    // Optimistic descent for writers: read latches down to the last internal level, then a
+    // write latch on the leaf. None if the root is itself a leaf.
+    fn find_leaf_write(&self, key: &[u8]) -> CrabDbResult<Option<(WritePageGuard<'_>, LeafNode)>> {
+        let root_page_id = self.root_page_id.read().unwrap();
+        let mut guard = self.bpm.fetch_page_read(*root_page_id)?;
+        drop(root_page_id);
+        loop {
+            let internal = match Node::read(&guard, guard.page_id())? {
+                Node::Leaf(_) => return Ok(None),
+                Node::Internal(internal) => internal,
+            };
+            let child_page_id = internal.children[internal.child_index(key)];
+            if internal.level == 1 {
+                let leaf_guard = self.bpm.fetch_page_write(child_page_id)?;
+                drop(guard);
+                let leaf = read_leaf(&leaf_guard, child_page_id)?;
+                return Ok(Some((leaf_guard, leaf)));
+            }
+            guard = self.bpm.fetch_page_read(child_page_id)?;
+        }
+    }
+
+    fn insert_pessimistic(&self, key: &[u8], rid: Rid) -> CrabDbResult<bool> {
+        let mut root_latch = Some(self.root_page_id.write().unwrap());
+        let mut path: Vec<(WritePageGuard<'_>, Node)> = Vec::new();
+        let mut page_id = **root_latch.as_ref().unwrap();
+        loop {
+            let guard = self.bpm.fetch_page_write(page_id)?;
+            let node = Node::read(&guard, page_id)?;
+            // A node with room to spare absorbs a split below it, so nothing above changes.
+            let has_room = match &node {
+                Node::Leaf(leaf) => leaf.keys.len() < self.leaf_max_size,
+                Node::Internal(internal) => internal.children.len() < self.internal_max_size,
+            };
+            if has_room {
+                root_latch = None;
+                path.clear();
+            }
+            let child_page_id = match &node {
+                Node::Internal(internal) => Some(internal.children[internal.child_index(key)]),
+                Node::Leaf(_) => None,
+            };
+            path.push((guard, node));
+            match child_page_id {
+                Some(child_page_id) => page_id = child_page_id,
+                None => break,
+            }
+        }
+
+        let (mut guard, node) = path.pop().unwrap();
+        let Node::Leaf(mut leaf) = node else {
+            return Err(CrabDBError::Corruption(format!("Page {} is not a B+ tree leaf", guard.page_id())));
+        };
+        let position = match search(&leaf, key) {
+            Ok(_) => return Ok(false),
+            Err(position) => position,
+        };
+        leaf.keys.insert(position, key.to_vec());
+        leaf.rids.insert(position, rid);
+        if leaf.keys.len() <= self.leaf_max_size {
+            Node::Leaf(leaf).write(&mut guard, self.key_size);
+            return Ok(true);
+        }
+
+        let mut right_guard = self.bpm.new_page_write()?;
+        let mid = leaf.keys.len() / 2;
+        let right = LeafNode {
+            keys: leaf.keys.split_off(mid),
+            rids: leaf.rids.split_off(mid),
+            next_page_id: leaf.next_page_id,
+        };
+        let mut separator = right.keys[0].clone();
+        let mut right_page_id = right_guard.page_id();
+        let (mut left_page_id, mut left_level) = (guard.page_id(), 0);
+        leaf.next_page_id = right_page_id;
+        Node::Leaf(right).write(&mut right_guard, self.key_size);
+        Node::Leaf(leaf).write(&mut guard, self.key_size);
+
+        loop {
+            let Some((mut guard, mut node)) = path.pop() else {
+                // The split reached the root: grow the tree by a level.
+                let mut root_latch = root_latch.expect("the root latch is held while the root may split");
+                let mut root_guard = self.bpm.new_page_write()?;
+                Node::Internal(InternalNode {
+                    level: left_level + 1,
+                    keys: vec![separator],
+                    children: vec![left_page_id, right_page_id],
+                }).write(&mut root_guard, self.key_size);
+                self.set_root(&mut root_latch, root_guard.page_id())?;
+                return Ok(true);
+            };
+            let internal = internal_mut(&mut node)?;
+            let index = internal.child_index(&separator);
+            internal.keys.insert(index, separator);
+            internal.children.insert(index + 1, right_page_id);
+            if internal.children.len() <= self.internal_max_size {
+                node.write(&mut guard, self.key_size);
+                return Ok(true);
+            }
+
+            let mid = internal.children.len() / 2;
+            let right = InternalNode {
+                level: internal.level,
+                keys: internal.keys.split_off(mid),
+                children: internal.children.split_off(mid),
+            };
+            // The left half keeps one key too many: it moves up as the separator.
+            separator = internal.keys.pop().unwrap();
+            (left_page_id, left_level) = (guard.page_id(), internal.level);
+            let mut right_guard = self.bpm.new_page_write()?;
+            right_page_id = right_guard.page_id();
+            Node::Internal(right).write(&mut right_guard, self.key_size);
+            node.write(&mut guard, self.key_size);
+        }
+    }
+
+    fn remove_pessimistic(&self, key: &[u8]) -> CrabDbResult<bool> {
+        let mut root_latch = Some(self.root_page_id.write().unwrap());
+        let mut path: Vec<LatchedNode<'_>> = Vec::new();
+        let mut page_id = **root_latch.as_ref().unwrap();
+        let mut left_sibling_page_id = None;
+        loop {
+            let mut left_sibling = match left_sibling_page_id {
+                Some(left_sibling_page_id) => {
+                    let guard = self.bpm.fetch_page_write(left_sibling_page_id)?;
+                    let node = Node::read(&guard, left_sibling_page_id)?;
+                    Some((guard, node))
+                },
+                None => None,
+            };
+            let guard = self.bpm.fetch_page_write(page_id)?;
+            let node = Node::read(&guard, page_id)?;
+            // A node that can lose an entry without underflowing stops merges below it from
+            // reaching further up. The root only has to keep a single entry.
+            let is_root = root_latch.as_ref().is_some_and(|root_page_id| **root_page_id == page_id);
+            let can_spare = match &node {
+                Node::Leaf(_) if is_root => true,
+                Node::Internal(internal) if is_root => internal.children.len() > 2,
+                node => node.size() > self.min_size(node),
+            };
+            if can_spare {
+                root_latch = None;
+                path.clear();
+                left_sibling = None;
+            }
+            let next = match &node {
+                Node::Internal(internal) => {
+                    let index = internal.child_index(key);
+                    Some((internal.children[index], index.checked_sub(1).map(|left| internal.children[left])))
+                },
+                Node::Leaf(_) => None,
+            };
+            path.push(LatchedNode { guard, node, left_sibling });
+            match next {
+                Some((child_page_id, left_page_id)) => (page_id, left_sibling_page_id) = (child_page_id, left_page_id),
+                None => break,
+            }
+        }
+
+        let mut entry = path.pop().unwrap();
+        let Node::Leaf(leaf) = &mut entry.node else {
+            return Err(CrabDBError::Corruption(format!("Page {} is not a B+ tree leaf", entry.guard.page_id())));
+        };
+        let Ok(position) = search(leaf, key) else {
+            return Ok(false);
+        };
+        leaf.keys.remove(position);
+        leaf.rids.remove(position);
+
+        loop {
+            let Some(parent) = path.last_mut() else {
+                return self.finish_remove(entry, root_latch);
+            };
+            if entry.node.size() >= self.min_size(&entry.node) {
+                entry.node.write(&mut entry.guard, self.key_size);
+                return Ok(true);
+            }
+            self.rebalance(parent, entry)?;
+            entry = path.pop().unwrap();
+        }
+    }
+
+    // Writes back the topmost latched node of a delete. If that is an internal root left
+    // with a single child, the child becomes the root instead.
+    fn finish_remove(&self, mut entry: LatchedNode<'_>, root_latch: Option<RwLockWriteGuard<'_, PageId>>) -> CrabDbResult<bool> {
+        match (&entry.node, root_latch) {
+            (Node::Internal(internal), Some(mut root_latch)) if internal.children.len() == 1 => {
+                self.set_root(&mut root_latch, internal.children[0])?;
+                let old_root_page_id = entry.guard.page_id();
+                drop(entry);
+                self.bpm.delete_page(old_root_page_id)?;
+            },
+            _ => entry.node.write(&mut entry.guard, self.key_size),
+        }
+        Ok(true)
+    }
+
+    // Refills `child`, which has dropped below half full, by borrowing an entry from a
+    // sibling that can spare one or else by merging with it. The parent is only updated in
+    // memory; the caller writes it back.
+    fn rebalance(&self, parent: &mut LatchedNode<'_>, mut child: LatchedNode<'_>) -> CrabDbResult<()> {
+        let parent_node = internal_mut(&mut parent.node)?;
+        let index = parent_node.children.iter().position(|&page_id| page_id == child.guard.page_id())
+            .ok_or_else(|| CrabDBError::Corruption(format!("Page {} is missing from its parent", child.guard.page_id())))?;
+        let child_is_left = child.left_sibling.is_none();
+        let (left_index, (mut left_guard, mut left), (mut right_guard, mut right)) = match child.left_sibling.take() {
+            Some(left_sibling) => (index - 1, left_sibling, (child.guard, child.node)),
+            None => {
+                let right_page_id = parent_node.children[index + 1];
+                let right_guard = self.bpm.fetch_page_write(right_page_id)?;
+                let right = Node::read(&right_guard, right_page_id)?;
+                (index, (child.guard, child.node), (right_guard, right))
+            },
+        };
+        let sibling = if child_is_left { &right } else { &left };
+
+        if sibling.size() > self.min_size(sibling) {
+            let separator = &mut parent_node.keys[left_index];
+            match (&mut left, &mut right) {
+                (Node::Leaf(left), Node::Leaf(right)) => {
+                    if child_is_left {
+                        left.keys.push(right.keys.remove(0));
+                        left.rids.push(right.rids.remove(0));
+                    } else {
+                        right.keys.insert(0, left.keys.pop().unwrap());
+                        right.rids.insert(0, left.rids.pop().unwrap());
+                    }
+                    *separator = right.keys[0].clone();
+                },
+                (Node::Internal(left), Node::Internal(right)) => {
+                    if child_is_left {
+                        left.keys.push(std::mem::replace(separator, right.keys.remove(0)));
+                        left.children.push(right.children.remove(0));
+                    } else {
+                        right.keys.insert(0, std::mem::replace(separator, left.keys.pop().unwrap()));
+                        right.children.insert(0, left.children.pop().unwrap());
+                    }
+                },
+                _ => return Err(CrabDBError::Corruption("B+ tree siblings are at different levels".into())),
+            }
+            left.write(&mut left_guard, self.key_size);
+            right.write(&mut right_guard, self.key_size);
+            return Ok(());
+        }
+
+        let separator = parent_node.keys.remove(left_index);
+        parent_node.children.remove(left_index + 1);
+        match (&mut left, right) {
+            (Node::Leaf(left), Node::Leaf(right)) => {
+                left.keys.extend(right.keys);
+                left.rids.extend(right.rids);
+                left.next_page_id = right.next_page_id;
+            },
+            (Node::Internal(left), Node::Internal(right)) => {
+                left.keys.push(separator);
+                left.keys.extend(right.keys);
+                left.children.extend(right.children);
+            },
+            _ => return Err(CrabDBError::Corruption("B+ tree siblings are at different levels".into())),
+        }
+        left.write(&mut left_guard, self.key_size);
+        // Nothing else can reach the right node: its parent and left neighbour are latched.
+        let right_page_id = right_guard.page_id();
+        drop(right_guard);
+        self.bpm.delete_page(right_page_id)
+    }
+
+    fn set_root(&self, root_page_id: &mut PageId, new_root_page_id: PageId) -> CrabDbResult<()> {
+        let header = TreeHeader {
+            root_page_id: new_root_page_id,
+            key_size: self.key_size,
+            leaf_max_size: self.leaf_max_size,
+            internal_max_size: self.internal_max_size,
+        };
+        header.write(&mut *self.bpm.fetch_page_write(self.header_page_id)?);
+        *root_page_id = new_root_page_id;
+        Ok(())
+    }
+}
+
+impl Index for BPlusTree {
+    fn kind(&self) -> IndexKind {
+        IndexKind::BPlusTree
+    }
+
+    fn key_size(&self) -> usize {
+        self.key_size
+    }
+
+    fn get(&self, key: &[u8]) -> CrabDbResult<Option<Rid>> {
+        BPlusTree::get(self, key)
+    }
+
+    fn insert(&self, key: &[u8], rid: Rid) -> CrabDbResult<bool> {
+        BPlusTree::insert(self, key, rid)
+    }
+
+    fn remove(&self, key: &[u8]) -> CrabDbResult<bool> {
+        BPlusTree::remove(self, key)
+    }
+
+    fn range<'a>(&'a self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> CrabDbResult<IndexIterator<'a>> {
+        Ok(Box::new(BPlusTree::range(self, start, end)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    use tempfile::TempDir;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::storage::disk::file_disk_manager::FileDiskManager;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use crate::storage::table::tuple::Rid;
+    use super::BPlusTree;
+
+    fn buffer_pool(dir: &TempDir) -> Arc<BufferPoolManager> {
+        let disk_manager = Arc::new(FileDiskManager::new(dir.path().join("test.db")).unwrap());
+        Arc::new(BufferPoolManager::new(16, disk_manager, Arc::new(LRUKReplacer::new(16, 2))))
+    }
+
+    fn key(i: u64) -> [u8; 8] {
+        i.to_be_bytes()
+    }
+
+    fn rid(i: u64) -> Rid {
+        Rid::new(i, (i % 7) as u16)
+    }
+
+    // Visits 0..n in a scrambled but deterministic order.
+    fn shuffled(n: u64) -> Vec<u64> {
+        (0..n).map(|i| (i * 7919) % n).collect()
+    }
+
+    fn scan(tree: &BPlusTree) -> Vec<u64> {
+        tree.iter().map(|entry| u64::from_be_bytes(entry.unwrap().0.try_into().unwrap())).collect()
+    }
+
+    #[test]
+    pub fn test_bplus_tree_insert_and_get() {
+        let dir = TempDir::new().unwrap();
+        let tree = BPlusTree::with_max_sizes(buffer_pool(&dir), 8, 4, 4).unwrap();
+        for i in shuffled(1000) {
+            assert!(tree.insert(&key(i), rid(i)).unwrap());
+        }
+        assert!(!tree.insert(&key(10), rid(0)).unwrap());
+
+        for i in 0..1000 {
+            assert_eq!(Some(rid(i)), tree.get(&key(i)).unwrap());
+        }
+        assert_eq!(None, tree.get(&key(1000)).unwrap());
+        assert_eq!((0..1000).collect::<Vec<_>>(), scan(&tree));
+        assert_eq!("Key must be 8 bytes, got 2", tree.get(&[1, 2]).unwrap_err().to_string());
+    }
+
+    #[test]
+    pub fn test_bplus_tree_range() {
+        let dir = TempDir::new().unwrap();
+        let tree = BPlusTree::with_max_sizes(buffer_pool(&dir), 8, 3, 3).unwrap();
+        for i in (0..200).map(|i| i * 2) {
+            tree.insert(&key(i), rid(i)).unwrap();
+        }
+        let range = |start: Bound<&[u8]>, end: Bound<&[u8]>| tree.range(start, end)
+            .map(|entry| u64::from_be_bytes(entry.unwrap().0.try_into().unwrap()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(vec![10, 12, 14], range(Bound::Included(&key(10)), Bound::Excluded(&key(16))));
+        assert_eq!(vec![12, 14, 16], range(Bound::Excluded(&key(10)), Bound::Included(&key(16))));
+        assert_eq!(vec![12, 14], range(Bound::Included(&key(11)), Bound::Included(&key(15))));
+        assert_eq!(vec![396, 398], range(Bound::Included(&key(395)), Bound::Unbounded));
+        assert_eq!(vec![0, 2], range(Bound::Unbounded, Bound::Excluded(&key(4))));
+        assert!(range(Bound::Included(&key(500)), Bound::Unbounded).is_empty());
+    }
+
+    #[test]
+    pub fn test_bplus_tree_remove() {
+        let dir = TempDir::new().unwrap();
+        let tree = BPlusTree::with_max_sizes(buffer_pool(&dir), 8, 4, 5).unwrap();
+        for i in shuffled(500) {
+            tree.insert(&key(i), rid(i)).unwrap();
+        }
+        for i in shuffled(500).into_iter().filter(|i| i % 3 != 0) {
+            assert!(tree.remove(&key(i)).unwrap());
+        }
+        assert!(!tree.remove(&key(1)).unwrap());
+        assert_eq!((0..500).filter(|i| i % 3 == 0).collect::<Vec<_>>(), scan(&tree));
+        for i in 0..500 {
+            assert_eq!((i % 3 == 0).then(|| rid(i)), tree.get(&key(i)).unwrap());
+        }
+
+        for i in (0..500).filter(|i| i % 3 == 0) {
+            assert!(tree.remove(&key(i)).unwrap());
+        }
+        assert!(scan(&tree).is_empty());
+        // the emptied tree is still usable
+        tree.insert(&key(42), rid(42)).unwrap();
+        assert_eq!(vec![42], scan(&tree));
+    }
+
+    #[test]
+    pub fn test_bplus_tree_reopen() {
+        let dir = TempDir::new().unwrap();
+        let bpm = buffer_pool(&dir);
+        let header_page_id = {
+            let tree = BPlusTree::with_max_sizes(bpm.clone(), 8, 4, 4).unwrap();
+            for i in 0..100 {
+                tree.insert(&key(i), rid(i)).unwrap();
+            }
+            tree.header_page_id()
+        };
+        bpm.flush_all_pages().unwrap();
+
+        let tree = BPlusTree::open(bpm, header_page_id).unwrap();
+        assert_eq!(8, tree.key_size());
+        assert_eq!(Some(rid(77)), tree.get(&key(77)).unwrap());
+        assert_eq!((0..100).collect::<Vec<_>>(), scan(&tree));
+    }
+
+    #[test]
+    pub fn test_bplus_tree_concurrent_stress() {
+        const THREADS: u64 = 4;
+        const KEYS_PER_THREAD: u64 = 1000;
+        let bpm = Arc::new(BufferPoolManager::new(256, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(256, 2))));
+        let tree = Arc::new(BPlusTree::with_max_sizes(bpm, 8, 5, 5).unwrap());
+        let writers_done = Arc::new(AtomicBool::new(false));
+
+        // scanners check that every scan sees strictly increasing keys while the tree is
+        // being split and merged underneath them
+        let scanners: Vec<_> = (0..2).map(|_| {
+            let tree = tree.clone();
+            let writers_done = writers_done.clone();
+            thread::spawn(move || {
+                while !writers_done.load(Ordering::Acquire) {
+                    let keys = scan(&tree);
+                    assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+                }
+            })
+        }).collect();
+
+        // each writer owns the keys congruent to its id, inserts them all, then deletes
+        // every other one, with lookups interleaved
+        let writers: Vec<_> = (0..THREADS).map(|t| {
+            let tree = tree.clone();
+            thread::spawn(move || {
+                let keys: Vec<u64> = shuffled(KEYS_PER_THREAD).into_iter().map(|i| i * THREADS + t).collect();
+                for &i in &keys {
+                    assert!(tree.insert(&key(i), rid(i)).unwrap());
+                    assert_eq!(Some(rid(i)), tree.get(&key(i)).unwrap());
+                }
+                for &i in keys.iter().filter(|&&i| (i / THREADS) % 2 == 1) {
+                    assert!(tree.remove(&key(i)).unwrap());
+                    assert_eq!(None, tree.get(&key(i)).unwrap());
+                }
+            })
+        }).collect();
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        writers_done.store(true, Ordering::Release);
+        for scanner in scanners {
+            scanner.join().unwrap();
+        }
+
+        let expected: Vec<u64> = (0..THREADS * KEYS_PER_THREAD).filter(|i| (i / THREADS).is_multiple_of(2)).collect();
+        assert_eq!(expected, scan(&tree));
+        for i in 0..THREADS * KEYS_PER_THREAD {
+            assert_eq!(((i / THREADS).is_multiple_of(2)).then(|| rid(i)), tree.get(&key(i)).unwrap());
+        }
+    }
+}
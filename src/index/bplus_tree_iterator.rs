@@ -0,0 +1,65 @@
+use std::ops::Bound;
+
+use crate::storage::table::tuple::Rid;
+use crate::types::CrabDbResult;
+
+use super::bplus_tree::BPlusTree;
+use super::bplus_tree_page::LeafNode;
+
+// Walks the tree in key order, yielding entries within the bounds. One leaf is copied out at
+// a time and no latch is held between calls; each following leaf is found by seeking past
+// the last key returned, so the scan stays correct while other threads split and merge
+// nodes underneath it.
+pub struct BPlusTreeIterator<'a> {
+    tree: &'a BPlusTree,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    leaf: Option<(LeafNode, usize)>,
+    done: bool,
+}
+
+impl<'a> BPlusTreeIterator<'a> {
+    pub(crate) fn new(tree: &'a BPlusTree, start: Bound<Vec<u8>>, end: Bound<Vec<u8>>) -> Self {
+        BPlusTreeIterator { tree, start, end, leaf: None, done: false }
+    }
+
+    fn advance(&mut self) -> CrabDbResult<Option<(Vec<u8>, Rid)>> {
+        if self.leaf.is_none() {
+            self.leaf = self.tree.seek(&self.start)?;
+        }
+        let Some((leaf, position)) = &mut self.leaf else {
+            return Ok(None);
+        };
+        let key = leaf.keys[*position].clone();
+        let in_range = match &self.end {
+            Bound::Included(end) => key <= *end,
+            Bound::Excluded(end) => key < *end,
+            Bound::Unbounded => true,
+        };
+        if !in_range {
+            return Ok(None);
+        }
+        let rid = leaf.rids[*position];
+        *position += 1;
+        if *position == leaf.keys.len() {
+            self.start = Bound::Excluded(key.clone());
+            self.leaf = None;
+        }
+        Ok(Some((key, rid)))
+    }
+}
+
+impl Iterator for BPlusTreeIterator<'_> {
+    type Item = CrabDbResult<(Vec<u8>, Rid)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let entry = self.advance().transpose();
+        if !matches!(entry, Some(Ok(_))) {
+            self.done = true;
+        }
+        entry
+    }
+}
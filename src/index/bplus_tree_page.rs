@@ -0,0 +1,215 @@
+use crate::storage::common::{PageId, PAGE_HEADER_SIZE, PAGE_SIZE};
+use crate::storage::table::tuple::Rid;
+use crate::types::{CrabDBError, CrabDbResult};
+
+// Node page layout, starting at PAGE_HEADER_SIZE, all integers little endian:
+//   page_type u8 | level u8 | size u16 | key_size u16 | reserved u16 | next_page_id u64 | entries
+// A leaf entry is `key | rid page_id u64 | rid slot u16`, in key order, and `next_page_id`
+// links the leaves left to right. An internal entry is `key | child page_id u64`; `size` is
+// the number of children and the key of the first entry is unused, so child i holds keys in
+// [key i, key i+1). Leaves are level 0 and an internal node is one level above its
+// children; a node keeps its level for life, which tells a descent when the next step is a
+// leaf.
+const PAGE_TYPE_OFFSET: usize = PAGE_HEADER_SIZE;
+const LEVEL_OFFSET: usize = PAGE_HEADER_SIZE + 1;
+const SIZE_OFFSET: usize = PAGE_HEADER_SIZE + 2;
+const KEY_SIZE_OFFSET: usize = PAGE_HEADER_SIZE + 4;
+const NEXT_PAGE_ID_OFFSET: usize = PAGE_HEADER_SIZE + 8;
+const ENTRIES_OFFSET: usize = PAGE_HEADER_SIZE + 16;
+
+const LEAF_PAGE: u8 = 1;
+const INTERNAL_PAGE: u8 = 2;
+
+const RID_SIZE: usize = 10;
+const CHILD_SIZE: usize = 8;
+
+// Header page layout: root_page_id u64 | key_size u16 | leaf_max_size u16 | internal_max_size u16
+const ROOT_PAGE_ID_OFFSET: usize = PAGE_HEADER_SIZE;
+const HEADER_KEY_SIZE_OFFSET: usize = PAGE_HEADER_SIZE + 8;
+const LEAF_MAX_SIZE_OFFSET: usize = PAGE_HEADER_SIZE + 10;
+const INTERNAL_MAX_SIZE_OFFSET: usize = PAGE_HEADER_SIZE + 12;
+
+// Entries a leaf page can physically hold.
+pub(crate) fn leaf_capacity(key_size: usize) -> usize {
+    (PAGE_SIZE - ENTRIES_OFFSET) / (key_size + RID_SIZE)
+}
+
+// Children an internal page can physically hold.
+pub(crate) fn internal_capacity(key_size: usize) -> usize {
+    (PAGE_SIZE - ENTRIES_OFFSET) / (key_size + CHILD_SIZE)
+}
+
+fn read_u16(data: &[u8; PAGE_SIZE], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8; PAGE_SIZE], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TreeHeader {
+    pub root_page_id: PageId,
+    pub key_size: usize,
+    pub leaf_max_size: usize,
+    pub internal_max_size: usize,
+}
+
+impl TreeHeader {
+    pub(crate) fn read(data: &[u8; PAGE_SIZE]) -> Self {
+        TreeHeader {
+            root_page_id: read_u64(data, ROOT_PAGE_ID_OFFSET),
+            key_size: read_u16(data, HEADER_KEY_SIZE_OFFSET) as usize,
+            leaf_max_size: read_u16(data, LEAF_MAX_SIZE_OFFSET) as usize,
+            internal_max_size: read_u16(data, INTERNAL_MAX_SIZE_OFFSET) as usize,
+        }
+    }
+
+    pub(crate) fn write(&self, data: &mut [u8; PAGE_SIZE]) {
+        data[ROOT_PAGE_ID_OFFSET..ROOT_PAGE_ID_OFFSET + 8].copy_from_slice(&self.root_page_id.to_le_bytes());
+        data[HEADER_KEY_SIZE_OFFSET..HEADER_KEY_SIZE_OFFSET + 2].copy_from_slice(&(self.key_size as u16).to_le_bytes());
+        data[LEAF_MAX_SIZE_OFFSET..LEAF_MAX_SIZE_OFFSET + 2].copy_from_slice(&(self.leaf_max_size as u16).to_le_bytes());
+        data[INTERNAL_MAX_SIZE_OFFSET..INTERNAL_MAX_SIZE_OFFSET + 2]
+            .copy_from_slice(&(self.internal_max_size as u16).to_le_bytes());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LeafNode {
+    pub keys: Vec<Vec<u8>>,
+    pub rids: Vec<Rid>,
+    pub next_page_id: PageId,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct InternalNode {
+    pub level: u8,
+    // keys[i] separates children[i] and children[i + 1], so there is one key fewer than
+    // there are children.
+    pub keys: Vec<Vec<u8>>,
+    pub children: Vec<PageId>,
+}
+
+impl InternalNode {
+    // Index of the child whose subtree may contain `key`.
+    pub(crate) fn child_index(&self, key: &[u8]) -> usize {
+        self.keys.partition_point(|k| k.as_slice() <= key)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Node {
+    Leaf(LeafNode),
+    Internal(InternalNode),
+}
+
+impl Node {
+    pub(crate) fn size(&self) -> usize {
+        match self {
+            Node::Leaf(leaf) => leaf.keys.len(),
+            Node::Internal(internal) => internal.children.len(),
+        }
+    }
+
+    pub(crate) fn read(data: &[u8; PAGE_SIZE], page_id: PageId) -> CrabDbResult<Self> {
+        let size = read_u16(data, SIZE_OFFSET) as usize;
+        let key_size = read_u16(data, KEY_SIZE_OFFSET) as usize;
+        let key_at = |offset: usize| data[offset..offset + key_size].to_vec();
+        match data[PAGE_TYPE_OFFSET] {
+            LEAF_PAGE if size <= leaf_capacity(key_size) => {
+                let entry_size = key_size + RID_SIZE;
+                let mut leaf = LeafNode {
+                    keys: Vec::with_capacity(size),
+                    rids: Vec::with_capacity(size),
+                    next_page_id: read_u64(data, NEXT_PAGE_ID_OFFSET),
+                };
+                for i in 0..size {
+                    let offset = ENTRIES_OFFSET + i * entry_size;
+                    leaf.keys.push(key_at(offset));
+                    leaf.rids.push(Rid::new(read_u64(data, offset + key_size), read_u16(data, offset + key_size + 8)));
+                }
+                Ok(Node::Leaf(leaf))
+            },
+            INTERNAL_PAGE if size <= internal_capacity(key_size) => {
+                let entry_size = key_size + CHILD_SIZE;
+                let mut internal = InternalNode {
+                    level: data[LEVEL_OFFSET],
+                    keys: Vec::with_capacity(size),
+                    children: Vec::with_capacity(size),
+                };
+                for i in 0..size {
+                    let offset = ENTRIES_OFFSET + i * entry_size;
+                    if i > 0 {
+                        internal.keys.push(key_at(offset));
+                    }
+                    internal.children.push(read_u64(data, offset + key_size));
+                }
+                Ok(Node::Internal(internal))
+            },
+            page_type => Err(CrabDBError::Corruption(format!(
+                "Page {page_id} is not a valid B+ tree node (type {page_type}, size {size})"
+            ))),
+        }
+    }
+
+    pub(crate) fn write(&self, data: &mut [u8; PAGE_SIZE], key_size: usize) {
+        data[PAGE_HEADER_SIZE..].fill(0);
+        data[SIZE_OFFSET..SIZE_OFFSET + 2].copy_from_slice(&(self.size() as u16).to_le_bytes());
+        data[KEY_SIZE_OFFSET..KEY_SIZE_OFFSET + 2].copy_from_slice(&(key_size as u16).to_le_bytes());
+        match self {
+            Node::Leaf(leaf) => {
+                data[PAGE_TYPE_OFFSET] = LEAF_PAGE;
+                data[NEXT_PAGE_ID_OFFSET..NEXT_PAGE_ID_OFFSET + 8].copy_from_slice(&leaf.next_page_id.to_le_bytes());
+                let entry_size = key_size + RID_SIZE;
+                for (i, (key, rid)) in leaf.keys.iter().zip(&leaf.rids).enumerate() {
+                    let offset = ENTRIES_OFFSET + i * entry_size;
+                    data[offset..offset + key_size].copy_from_slice(key);
+                    data[offset + key_size..offset + key_size + 8].copy_from_slice(&rid.page_id().to_le_bytes());
+                    data[offset + key_size + 8..offset + entry_size].copy_from_slice(&rid.slot_id().to_le_bytes());
+                }
+            },
+            Node::Internal(internal) => {
+                data[PAGE_TYPE_OFFSET] = INTERNAL_PAGE;
+                data[LEVEL_OFFSET] = internal.level;
+                let entry_size = key_size + CHILD_SIZE;
+                for (i, child) in internal.children.iter().enumerate() {
+                    let offset = ENTRIES_OFFSET + i * entry_size;
+                    if i > 0 {
+                        data[offset..offset + key_size].copy_from_slice(&internal.keys[i - 1]);
+                    }
+                    data[offset + key_size..offset + entry_size].copy_from_slice(&child.to_le_bytes());
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::common::{INVALID_PAGE_ID, PAGE_SIZE};
+    use crate::storage::table::tuple::Rid;
+    use super::{InternalNode, LeafNode, Node};
+
+    #[test]
+    pub fn test_bplus_tree_node_round_trip() {
+        let mut data = [0; PAGE_SIZE];
+        let leaf = Node::Leaf(LeafNode {
+            keys: vec![vec![1, 1], vec![2, 2]],
+            rids: vec![Rid::new(3, 4), Rid::new(5, 6)],
+            next_page_id: INVALID_PAGE_ID,
+        });
+        leaf.write(&mut data, 2);
+        assert_eq!(leaf, Node::read(&data, 1).unwrap());
+
+        let internal = Node::Internal(InternalNode { level: 1, keys: vec![vec![5, 0], vec![9, 0]], children: vec![10, 11, 12] });
+        internal.write(&mut data, 2);
+        assert_eq!(internal, Node::read(&data, 1).unwrap());
+        if let Node::Internal(internal) = internal {
+            assert_eq!(0, internal.child_index(&[1, 0]));
+            assert_eq!(1, internal.child_index(&[5, 0]));
+            assert_eq!(2, internal.child_index(&[200, 0]));
+        }
+
+        assert!(Node::read(&[0; PAGE_SIZE], 1).is_err());
+    }
+}
@@ -0,0 +1,350 @@
+use std::sync::{Arc, RwLock};
+
+use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+use crate::storage::common::PageId;
+use crate::storage::table::tuple::Rid;
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::bloom_filter::BloomFilter;
+use super::extendible_hash_page::{bucket_capacity, Bucket, Directory, MAX_GLOBAL_DEPTH};
+use super::table_index::{Index, IndexKind};
+
+// CRC32C is stable across builds, unlike std's hasher, which matters since bucket
+// placement is persisted; the finalizer from MurmurHash3 spreads it over the low bits the
+// directory uses.
+fn hash_key(key: &[u8]) -> u32 {
+    let mut hash = crc32c::crc32c(key);
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^ (hash >> 16)
+}
+
+// A disk-backed extendible hash index for equality lookups. A directory page maps the low
+// `global_depth` bits of a key's hash to bucket pages; a full bucket splits in two, doubling
+// the directory when it has to, and buckets that empty out merge back with their split
+// image. The directory page latch orders every operation: lookups share it, modifications
+// hold it exclusively. An index may keep a Bloom filter of every key ever inserted, so that
+// looking up an absent key usually reads no pages at all.
+pub struct ExtendibleHashIndex {
+    bpm: Arc<BufferPoolManager>,
+    directory_page_id: PageId,
+    key_size: usize,
+    bloom_filter: Option<RwLock<BloomFilter>>,
+}
+
+impl ExtendibleHashIndex {
+    pub fn new(bpm: Arc<BufferPoolManager>, key_size: usize) -> CrabDbResult<Self> {
+        Self::with_bucket_max_size(bpm, key_size, bucket_capacity(key_size))
+    }
+
+    pub fn with_bucket_max_size(bpm: Arc<BufferPoolManager>, key_size: usize, bucket_max_size: usize) -> CrabDbResult<Self> {
+        Self::create(bpm, key_size, bucket_max_size, None)
+    }
+
+    // An index with a Bloom filter sized for `expected_keys` at `false_positive_rate`; see
+    // `BloomFilter::new`.
+    pub fn with_bloom_filter(
+        bpm: Arc<BufferPoolManager>,
+        key_size: usize,
+        expected_keys: usize,
+        false_positive_rate: f64,
+    ) -> CrabDbResult<Self> {
+        let filter = BloomFilter::new(expected_keys, false_positive_rate)?;
+        Self::create(bpm, key_size, bucket_capacity(key_size), Some(filter))
+    }
+
+    fn create(
+        bpm: Arc<BufferPoolManager>,
+        key_size: usize,
+        bucket_max_size: usize,
+        mut bloom_filter: Option<BloomFilter>,
+    ) -> CrabDbResult<Self> {
+        if key_size == 0 || bucket_max_size == 0 || bucket_max_size > bucket_capacity(key_size) {
+            return Err(CrabDBError::InvalidInput(format!(
+                "Invalid hash bucket size {bucket_max_size} for {key_size} byte keys"
+            )));
+        }
+        let bloom_filter_page_id = match &mut bloom_filter {
+            Some(filter) => Some(filter.write(&bpm)?),
+            None => None,
+        };
+        let mut directory_guard = bpm.new_page_write()?;
+        let directory_page_id = directory_guard.page_id();
+        let bucket_page_id = {
+            let mut bucket_guard = bpm.new_page_write()?;
+            Bucket::default().write(&mut bucket_guard, key_size);
+            bucket_guard.page_id()
+        };
+        Directory {
+            global_depth: 0,
+            key_size,
+            bucket_max_size,
+            bucket_page_ids: vec![bucket_page_id],
+            local_depths: vec![0],
+            bloom_filter_page_id,
+        }.write(&mut directory_guard);
+        drop(directory_guard);
+        Ok(ExtendibleHashIndex { bpm, directory_page_id, key_size, bloom_filter: bloom_filter.map(RwLock::new) })
+    }
+
+    pub fn open(bpm: Arc<BufferPoolManager>, directory_page_id: PageId) -> CrabDbResult<Self> {
+        let directory = Directory::read(&*bpm.fetch_page_read(directory_page_id)?, directory_page_id)?;
+        let bloom_filter = match directory.bloom_filter_page_id {
+            Some(page_id) => Some(RwLock::new(BloomFilter::open(&bpm, page_id)?)),
+            None => None,
+        };
+        Ok(ExtendibleHashIndex { bpm, directory_page_id, key_size: directory.key_size, bloom_filter })
+    }
+
+    pub fn directory_page_id(&self) -> PageId {
+        self.directory_page_id
+    }
+
+    pub fn global_depth(&self) -> CrabDbResult<u8> {
+        let guard = self.bpm.fetch_page_read(self.directory_page_id)?;
+        Ok(Directory::read(&guard, self.directory_page_id)?.global_depth)
+    }
+
+    fn check_key(&self, key: &[u8]) -> CrabDbResult<()> {
+        if key.len() != self.key_size {
+            return Err(CrabDBError::InvalidInput(format!("Key must be {} bytes, got {}", self.key_size, key.len())));
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: &[u8]) -> CrabDbResult<Option<Rid>> {
+        self.check_key(key)?;
+        if let Some(filter) = &self.bloom_filter {
+            if !filter.read().unwrap().may_contain(key) {
+                return Ok(None);
+            }
+        }
+        let directory_guard = self.bpm.fetch_page_read(self.directory_page_id)?;
+        let directory = Directory::read(&directory_guard, self.directory_page_id)?;
+        let bucket_page_id = directory.bucket_page_ids[directory.slot(hash_key(key))];
+        let bucket = Bucket::read(&*self.bpm.fetch_page_read(bucket_page_id)?, bucket_page_id)?;
+        Ok(bucket.find(key).map(|i| bucket.rids[i]))
+    }
+
+    pub fn insert(&self, key: &[u8], rid: Rid) -> CrabDbResult<bool> {
+        self.check_key(key)?;
+        let hash = hash_key(key);
+        let mut directory_guard = self.bpm.fetch_page_write(self.directory_page_id)?;
+        let mut directory = Directory::read(&directory_guard, self.directory_page_id)?;
+        // Set the key's bits before it becomes visible, so a lookup that finds it in a bucket
+        // would have passed the filter too.
+        if let Some(filter) = &self.bloom_filter {
+            filter.write().unwrap().insert_and_write(&self.bpm, key)?;
+        }
+        loop {
+            let slot = directory.slot(hash);
+            let bucket_page_id = directory.bucket_page_ids[slot];
+            let mut bucket_guard = self.bpm.fetch_page_write(bucket_page_id)?;
+            let mut bucket = Bucket::read(&bucket_guard, bucket_page_id)?;
+            if bucket.find(key).is_some() {
+                return Ok(false);
+            }
+            if bucket.keys.len() < directory.bucket_max_size {
+                bucket.keys.push(key.to_vec());
+                bucket.rids.push(rid);
+                bucket.write(&mut bucket_guard, self.key_size);
+                return Ok(true);
+            }
+
+            // Split the full bucket on the next hash bit and try again.
+            let local_depth = directory.local_depths[slot];
+            if local_depth == directory.global_depth {
+                if directory.global_depth == MAX_GLOBAL_DEPTH {
+                    return Err(CrabDBError::InvalidInput(format!(
+                        "Hash index directory is full: a bucket cannot split past global depth {MAX_GLOBAL_DEPTH}"
+                    )));
+                }
+                directory.grow();
+            }
+            let split_bit = 1u32 << local_depth;
+            let mut image = Bucket::default();
+            let mut kept = Bucket::default();
+            for (key, rid) in bucket.keys.into_iter().zip(bucket.rids) {
+                let target = if hash_key(&key) & split_bit != 0 { &mut image } else { &mut kept };
+                target.keys.push(key);
+                target.rids.push(rid);
+            }
+            let mut image_guard = self.bpm.new_page_write()?;
+            let image_page_id = image_guard.page_id();
+            for slot in 0..directory.bucket_page_ids.len() {
+                if directory.bucket_page_ids[slot] == bucket_page_id {
+                    if slot as u32 & split_bit != 0 {
+                        directory.bucket_page_ids[slot] = image_page_id;
+                    }
+                    directory.local_depths[slot] = local_depth + 1;
+                }
+            }
+            kept.write(&mut bucket_guard, self.key_size);
+            image.write(&mut image_guard, self.key_size);
+            directory.write(&mut directory_guard);
+        }
+    }
+
+    pub fn remove(&self, key: &[u8]) -> CrabDbResult<bool> {
+        self.check_key(key)?;
+        let hash = hash_key(key);
+        let mut directory_guard = self.bpm.fetch_page_write(self.directory_page_id)?;
+        let mut directory = Directory::read(&directory_guard, self.directory_page_id)?;
+        let slot = directory.slot(hash);
+        let bucket_page_id = directory.bucket_page_ids[slot];
+        {
+            let mut bucket_guard = self.bpm.fetch_page_write(bucket_page_id)?;
+            let mut bucket = Bucket::read(&bucket_guard, bucket_page_id)?;
+            let Some(position) = bucket.find(key) else {
+                return Ok(false);
+            };
+            bucket.keys.remove(position);
+            bucket.rids.remove(position);
+            bucket.write(&mut bucket_guard, self.key_size);
+            if !bucket.keys.is_empty() {
+                return Ok(true);
+            }
+        }
+
+        // Fold the empty bucket into its split image for as long as the two have the same
+        // local depth; the surviving bucket may itself be empty and fold further.
+        loop {
+            let slot = directory.slot(hash);
+            let local_depth = directory.local_depths[slot];
+            if local_depth == 0 {
+                break;
+            }
+            let image_slot = slot ^ (1 << (local_depth - 1));
+            if directory.local_depths[image_slot] != local_depth {
+                break;
+            }
+            let empty_page_id = directory.bucket_page_ids[slot];
+            let is_empty = Bucket::read(&*self.bpm.fetch_page_read(empty_page_id)?, empty_page_id)?.keys.is_empty();
+            if !is_empty {
+                break;
+            }
+            let image_page_id = directory.bucket_page_ids[image_slot];
+            for slot in 0..directory.bucket_page_ids.len() {
+                if directory.bucket_page_ids[slot] == empty_page_id || directory.bucket_page_ids[slot] == image_page_id {
+                    directory.bucket_page_ids[slot] = image_page_id;
+                    directory.local_depths[slot] = local_depth - 1;
+                }
+            }
+            // Nobody else can have the page pinned: every access goes through the directory.
+            self.bpm.delete_page(empty_page_id)?;
+        }
+        directory.shrink();
+        directory.write(&mut directory_guard);
+        Ok(true)
+    }
+}
+
+impl Index for ExtendibleHashIndex {
+    fn kind(&self) -> IndexKind {
+        IndexKind::Hash
+    }
+
+    fn key_size(&self) -> usize {
+        self.key_size
+    }
+
+    fn get(&self, key: &[u8]) -> CrabDbResult<Option<Rid>> {
+        ExtendibleHashIndex::get(self, key)
+    }
+
+    fn insert(&self, key: &[u8], rid: Rid) -> CrabDbResult<bool> {
+        ExtendibleHashIndex::insert(self, key, rid)
+    }
+
+    fn remove(&self, key: &[u8]) -> CrabDbResult<bool> {
+        ExtendibleHashIndex::remove(self, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+    use std::sync::Arc;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::index::table_index::Index;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use crate::storage::table::tuple::Rid;
+    use super::ExtendibleHashIndex;
+
+    fn buffer_pool() -> Arc<BufferPoolManager> {
+        Arc::new(BufferPoolManager::new(16, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(16, 2))))
+    }
+
+    fn key(i: u64) -> [u8; 8] {
+        i.to_be_bytes()
+    }
+
+    #[test]
+    pub fn test_extendible_hash_grows_and_shrinks() {
+        let index = ExtendibleHashIndex::with_bucket_max_size(buffer_pool(), 8, 8).unwrap();
+        for i in 0..300 {
+            assert!(index.insert(&key(i), Rid::new(i, 0)).unwrap());
+        }
+        assert!(!index.insert(&key(7), Rid::new(0, 0)).unwrap());
+        assert!(index.global_depth().unwrap() >= 6);
+        for i in 0..300 {
+            assert_eq!(Some(Rid::new(i, 0)), index.get(&key(i)).unwrap());
+        }
+        assert_eq!(None, index.get(&key(300)).unwrap());
+
+        for i in 0..300 {
+            assert!(index.remove(&key(i)).unwrap());
+        }
+        assert!(!index.remove(&key(0)).unwrap());
+        assert_eq!(0, index.global_depth().unwrap());
+        assert_eq!(None, index.get(&key(5)).unwrap());
+    }
+
+    #[test]
+    pub fn test_extendible_hash_bloom_filter_skips_absent_keys() {
+        let bpm = buffer_pool();
+        let index = ExtendibleHashIndex::with_bloom_filter(bpm.clone(), 8, 1000, 0.01).unwrap();
+        for i in 0..200 {
+            assert!(index.insert(&key(i), Rid::new(i, 0)).unwrap());
+        }
+        let fetches = |bpm: &BufferPoolManager| {
+            let metrics = bpm.metrics_snapshot();
+            metrics.hits + metrics.misses
+        };
+        let before = fetches(&bpm);
+        let found = (1000..2000).filter(|&i| index.get(&key(i)).unwrap().is_some()).count();
+        assert_eq!(0, found);
+        // only false positives reach the directory and a bucket
+        assert!(fetches(&bpm) - before < 2 * 30);
+
+        // the filter is persisted with the index and removals leave it alone
+        assert!(index.remove(&key(3)).unwrap());
+        let index = ExtendibleHashIndex::open(bpm.clone(), index.directory_page_id()).unwrap();
+        assert_eq!(Some(Rid::new(7, 0)), index.get(&key(7)).unwrap());
+        assert_eq!(None, index.get(&key(3)).unwrap());
+        assert!(ExtendibleHashIndex::with_bloom_filter(bpm, 8, 1000, 0.0).is_err());
+    }
+
+    #[test]
+    pub fn test_extendible_hash_reopen_and_limits() {
+        let bpm = buffer_pool();
+        let directory_page_id = {
+            let index = ExtendibleHashIndex::with_bucket_max_size(bpm.clone(), 8, 1).unwrap();
+            let error = (0..1000).map(|i| index.insert(&key(i), Rid::new(i, 1))).find_map(Result::err).unwrap();
+            assert_eq!("Hash index directory is full: a bucket cannot split past global depth 8", error.to_string());
+            index.directory_page_id()
+        };
+
+        let index: Box<dyn Index> = Box::new(ExtendibleHashIndex::open(bpm, directory_page_id).unwrap());
+        assert_eq!(8, index.key_size());
+        assert_eq!(Some(Rid::new(0, 1)), index.get(&key(0)).unwrap());
+        assert_eq!(
+            "hash indexes do not support range scans",
+            index.range(Bound::Unbounded, Bound::Unbounded).err().unwrap().to_string()
+        );
+    }
+}
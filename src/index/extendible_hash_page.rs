@@ -0,0 +1,181 @@
+use crate::storage::common::{PageId, PAGE_HEADER_SIZE, PAGE_SIZE};
+use crate::storage::table::tuple::Rid;
+use crate::types::{CrabDBError, CrabDbResult};
+
+// The directory always reserves room for 2^MAX_GLOBAL_DEPTH slots so it never moves.
+pub(crate) const MAX_GLOBAL_DEPTH: u8 = 8;
+const DIRECTORY_SLOTS: usize = 1 << MAX_GLOBAL_DEPTH;
+
+// Directory page layout, starting at PAGE_HEADER_SIZE, all integers little endian:
+//   global_depth u8 | flags u8 | key_size u16 | bucket_max_size u16 | reserved u16
+//   | bucket page ids u64 * DIRECTORY_SLOTS | local depths u8 * DIRECTORY_SLOTS
+//   | bloom filter header page id u64, when HAS_BLOOM_FILTER is set
+// Only the first 2^global_depth slots are in use.
+const GLOBAL_DEPTH_OFFSET: usize = PAGE_HEADER_SIZE;
+const FLAGS_OFFSET: usize = PAGE_HEADER_SIZE + 1;
+const HAS_BLOOM_FILTER: u8 = 1;
+const DIRECTORY_KEY_SIZE_OFFSET: usize = PAGE_HEADER_SIZE + 2;
+const BUCKET_MAX_SIZE_OFFSET: usize = PAGE_HEADER_SIZE + 4;
+const BUCKET_PAGE_IDS_OFFSET: usize = PAGE_HEADER_SIZE + 8;
+const LOCAL_DEPTHS_OFFSET: usize = BUCKET_PAGE_IDS_OFFSET + DIRECTORY_SLOTS * 8;
+const BLOOM_FILTER_PAGE_ID_OFFSET: usize = LOCAL_DEPTHS_OFFSET + DIRECTORY_SLOTS;
+const _: () = assert!(BLOOM_FILTER_PAGE_ID_OFFSET + 8 <= PAGE_SIZE);
+
+// Bucket page layout: size u16 | key_size u16 | reserved u32 | (key | rid page_id u64 | rid slot u16)*
+const BUCKET_SIZE_OFFSET: usize = PAGE_HEADER_SIZE;
+const BUCKET_KEY_SIZE_OFFSET: usize = PAGE_HEADER_SIZE + 2;
+const BUCKET_ENTRIES_OFFSET: usize = PAGE_HEADER_SIZE + 8;
+const RID_SIZE: usize = 10;
+
+// Entries a bucket page can physically hold.
+pub(crate) fn bucket_capacity(key_size: usize) -> usize {
+    (PAGE_SIZE - BUCKET_ENTRIES_OFFSET) / (key_size + RID_SIZE)
+}
+
+fn read_u16(data: &[u8; PAGE_SIZE], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8; PAGE_SIZE], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Directory {
+    pub global_depth: u8,
+    pub key_size: usize,
+    pub bucket_max_size: usize,
+    // One entry per slot in use; slots that share a bucket share its local depth.
+    pub bucket_page_ids: Vec<PageId>,
+    pub local_depths: Vec<u8>,
+    pub bloom_filter_page_id: Option<PageId>,
+}
+
+impl Directory {
+    pub(crate) fn slot(&self, hash: u32) -> usize {
+        (hash as usize) & ((1 << self.global_depth) - 1)
+    }
+
+    // Doubles the directory; each new slot points where its counterpart in the lower half does.
+    pub(crate) fn grow(&mut self) {
+        self.bucket_page_ids.extend_from_within(..);
+        self.local_depths.extend_from_within(..);
+        self.global_depth += 1;
+    }
+
+    // Halves the directory while no bucket needs the top bit of the slot index.
+    pub(crate) fn shrink(&mut self) {
+        while self.global_depth > 0 && self.local_depths.iter().all(|&depth| depth < self.global_depth) {
+            self.global_depth -= 1;
+            self.bucket_page_ids.truncate(1 << self.global_depth);
+            self.local_depths.truncate(1 << self.global_depth);
+        }
+    }
+
+    pub(crate) fn read(data: &[u8; PAGE_SIZE], page_id: PageId) -> CrabDbResult<Self> {
+        let global_depth = data[GLOBAL_DEPTH_OFFSET];
+        if global_depth > MAX_GLOBAL_DEPTH {
+            return Err(CrabDBError::Corruption(format!(
+                "Page {page_id} is not a valid hash directory (global depth {global_depth})"
+            )));
+        }
+        let slots = 1 << global_depth;
+        Ok(Directory {
+            global_depth,
+            key_size: read_u16(data, DIRECTORY_KEY_SIZE_OFFSET) as usize,
+            bucket_max_size: read_u16(data, BUCKET_MAX_SIZE_OFFSET) as usize,
+            bucket_page_ids: (0..slots).map(|slot| read_u64(data, BUCKET_PAGE_IDS_OFFSET + slot * 8)).collect(),
+            local_depths: data[LOCAL_DEPTHS_OFFSET..LOCAL_DEPTHS_OFFSET + slots].to_vec(),
+            bloom_filter_page_id: (data[FLAGS_OFFSET] & HAS_BLOOM_FILTER != 0)
+                .then(|| read_u64(data, BLOOM_FILTER_PAGE_ID_OFFSET)),
+        })
+    }
+
+    pub(crate) fn write(&self, data: &mut [u8; PAGE_SIZE]) {
+        data[PAGE_HEADER_SIZE..].fill(0);
+        data[GLOBAL_DEPTH_OFFSET] = self.global_depth;
+        data[DIRECTORY_KEY_SIZE_OFFSET..DIRECTORY_KEY_SIZE_OFFSET + 2].copy_from_slice(&(self.key_size as u16).to_le_bytes());
+        data[BUCKET_MAX_SIZE_OFFSET..BUCKET_MAX_SIZE_OFFSET + 2].copy_from_slice(&(self.bucket_max_size as u16).to_le_bytes());
+        for (slot, page_id) in self.bucket_page_ids.iter().enumerate() {
+            let offset = BUCKET_PAGE_IDS_OFFSET + slot * 8;
+            data[offset..offset + 8].copy_from_slice(&page_id.to_le_bytes());
+        }
+        data[LOCAL_DEPTHS_OFFSET..LOCAL_DEPTHS_OFFSET + self.local_depths.len()].copy_from_slice(&self.local_depths);
+        if let Some(page_id) = self.bloom_filter_page_id {
+            data[FLAGS_OFFSET] |= HAS_BLOOM_FILTER;
+            data[BLOOM_FILTER_PAGE_ID_OFFSET..BLOOM_FILTER_PAGE_ID_OFFSET + 8].copy_from_slice(&page_id.to_le_bytes());
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Bucket {
+    pub keys: Vec<Vec<u8>>,
+    pub rids: Vec<Rid>,
+}
+
+impl Bucket {
+    pub(crate) fn find(&self, key: &[u8]) -> Option<usize> {
+        self.keys.iter().position(|k| k == key)
+    }
+
+    pub(crate) fn read(data: &[u8; PAGE_SIZE], page_id: PageId) -> CrabDbResult<Self> {
+        let size = read_u16(data, BUCKET_SIZE_OFFSET) as usize;
+        let key_size = read_u16(data, BUCKET_KEY_SIZE_OFFSET) as usize;
+        if key_size == 0 || size > bucket_capacity(key_size) {
+            return Err(CrabDBError::Corruption(format!("Page {page_id} is not a valid hash bucket")));
+        }
+        let mut bucket = Bucket { keys: Vec::with_capacity(size), rids: Vec::with_capacity(size) };
+        for i in 0..size {
+            let offset = BUCKET_ENTRIES_OFFSET + i * (key_size + RID_SIZE);
+            bucket.keys.push(data[offset..offset + key_size].to_vec());
+            bucket.rids.push(Rid::new(read_u64(data, offset + key_size), read_u16(data, offset + key_size + 8)));
+        }
+        Ok(bucket)
+    }
+
+    pub(crate) fn write(&self, data: &mut [u8; PAGE_SIZE], key_size: usize) {
+        data[PAGE_HEADER_SIZE..].fill(0);
+        data[BUCKET_SIZE_OFFSET..BUCKET_SIZE_OFFSET + 2].copy_from_slice(&(self.keys.len() as u16).to_le_bytes());
+        data[BUCKET_KEY_SIZE_OFFSET..BUCKET_KEY_SIZE_OFFSET + 2].copy_from_slice(&(key_size as u16).to_le_bytes());
+        for (i, (key, rid)) in self.keys.iter().zip(&self.rids).enumerate() {
+            let offset = BUCKET_ENTRIES_OFFSET + i * (key_size + RID_SIZE);
+            data[offset..offset + key_size].copy_from_slice(key);
+            data[offset + key_size..offset + key_size + 8].copy_from_slice(&rid.page_id().to_le_bytes());
+            data[offset + key_size + 8..offset + key_size + RID_SIZE].copy_from_slice(&rid.slot_id().to_le_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::common::PAGE_SIZE;
+    use crate::storage::table::tuple::Rid;
+    use super::{Bucket, Directory};
+
+    #[test]
+    pub fn test_extendible_hash_pages_round_trip() {
+        let mut data = [0; PAGE_SIZE];
+        let mut directory = Directory {
+            global_depth: 1,
+            key_size: 4,
+            bucket_max_size: 8,
+            bucket_page_ids: vec![10, 11],
+            local_depths: vec![1, 1],
+            bloom_filter_page_id: Some(12),
+        };
+        directory.write(&mut data);
+        assert_eq!(directory, Directory::read(&data, 1).unwrap());
+
+        directory.grow();
+        assert_eq!(vec![10, 11, 10, 11], directory.bucket_page_ids);
+        assert_eq!(3, directory.slot(7));
+        directory.shrink();
+        assert_eq!(1, directory.global_depth);
+
+        let bucket = Bucket { keys: vec![vec![1, 2, 3, 4]], rids: vec![Rid::new(5, 6)] };
+        bucket.write(&mut data, 4);
+        assert_eq!(bucket, Bucket::read(&data, 1).unwrap());
+        assert_eq!(Some(0), bucket.find(&[1, 2, 3, 4]));
+    }
+}
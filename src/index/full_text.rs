@@ -0,0 +1,306 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Bound;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+use crate::storage::common::PageId;
+use crate::storage::table::tuple::Rid;
+use crate::types::CrabDbResult;
+
+use super::bplus_tree::BPlusTree;
+use super::table_index::{Index, IndexIterator, IndexKind};
+
+// Terms are cut to this many bytes, at a character boundary, to fit a key.
+pub const TERM_SIZE: usize = 32;
+
+// A term, then the row's rid, then how often the term occurs in it.
+const KEY_SIZE: usize = TERM_SIZE + 8 + 2 + 4;
+
+// BM25's term frequency saturation and length normalization, at their usual values.
+const BM25_K1: f64 = 1.2;
+const BM25_B: f64 = 0.75;
+
+// Splits text into terms: runs of letters and digits, lowercased.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut term = word.to_lowercase();
+            let mut end = term.len().min(TERM_SIZE);
+            while !term.is_char_boundary(end) {
+                end -= 1;
+            }
+            term.truncate(end);
+            term
+        })
+        .collect()
+}
+
+type TermBytes = [u8; TERM_SIZE];
+
+fn term_bytes(term: &str) -> TermBytes {
+    let mut bytes = [0; TERM_SIZE];
+    bytes[..term.len()].copy_from_slice(term.as_bytes());
+    bytes
+}
+
+// The all-zero term, which no real term is, keys the length of each row.
+fn key(term: &str, rid: Rid, count: u32) -> Vec<u8> {
+    let mut key = Vec::with_capacity(KEY_SIZE);
+    key.extend_from_slice(&term_bytes(term));
+    key.extend_from_slice(&rid.page_id().to_be_bytes());
+    key.extend_from_slice(&rid.slot_id().to_be_bytes());
+    key.extend_from_slice(&count.to_be_bytes());
+    key
+}
+
+fn term_bounds(term: &str) -> (Vec<u8>, Vec<u8>) {
+    let mut start = term.as_bytes().to_vec();
+    start.resize(KEY_SIZE, 0);
+    let mut end = start[..TERM_SIZE].to_vec();
+    end.resize(KEY_SIZE, 0xff);
+    (start, end)
+}
+
+fn count_of(key: &[u8]) -> u32 {
+    u32::from_be_bytes(key[KEY_SIZE - 4..].try_into().unwrap())
+}
+
+fn is_length(key: &[u8]) -> bool {
+    key[..TERM_SIZE].iter().all(|&b| b == 0)
+}
+
+// What BM25 needs to know of the rows indexed, besides the row being scored: how many there
+// are, how many terms they hold on average, and how many hold each term of the query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bm25Statistics {
+    pub rows: u64,
+    pub average_length: f64,
+    pub row_frequencies: Vec<u64>,
+}
+
+impl Bm25Statistics {
+    // The relevance of `text` to `terms`, which the statistics were gathered for.
+    pub fn score(&self, terms: &[String], text: &str) -> f64 {
+        let tokens = tokenize(text);
+        let length = tokens.len() as f64;
+        let rows = self.rows as f64;
+        terms.iter().zip(&self.row_frequencies).map(|(term, &frequency)| {
+            let occurrences = tokens.iter().filter(|token| *token == term).count() as f64;
+            if occurrences == 0.0 {
+                return 0.0;
+            }
+            let frequency = frequency as f64;
+            let idf = ((rows - frequency + 0.5) / (frequency + 0.5) + 1.0).ln();
+            let normalization = 1.0 - BM25_B + BM25_B * length / self.average_length.max(1.0);
+            idf * occurrences * (BM25_K1 + 1.0) / (occurrences + BM25_K1 * normalization)
+        }).sum()
+    }
+}
+
+// An inverted index over a text column, kept in a B+ tree: a posting per distinct term of
+// each row, keyed by the term, the row's rid and the term's count in it, and an entry keyed
+// by the row's rid and its length. A term's postings are a range of the tree, in rid order.
+//
+// Like other indexes it is not versioned: rows that are deleted or change text leave their
+// entries behind while a snapshot may still read the old text, and lookups only find
+// candidates, which are checked against the rows themselves. Once the change commits with
+// nothing else running its entries go (see `vacuum::prune_rows`), and vacuum prunes the
+// rest later. Until then the statistics BM25 scores with count those rows too, as search
+// engines count deleted documents until they merge them away.
+pub struct FullTextIndex {
+    tree: BPlusTree,
+    // Rows with a length entry, and the sum of their lengths.
+    rows: AtomicU64,
+    total_length: AtomicU64,
+    // Rows with a posting for each term, so neither the planner nor BM25 has to count them.
+    row_frequencies: Mutex<HashMap<TermBytes, u64>>,
+}
+
+impl FullTextIndex {
+    fn with_tree(tree: BPlusTree) -> Self {
+        FullTextIndex { tree, rows: AtomicU64::new(0), total_length: AtomicU64::new(0), row_frequencies: Mutex::default() }
+    }
+
+    pub fn new(bpm: Arc<BufferPoolManager>) -> CrabDbResult<Self> {
+        Ok(Self::with_tree(BPlusTree::new(bpm, KEY_SIZE)?))
+    }
+
+    // Reopens the index, counting its rows and the rows of each term again.
+    pub fn open(bpm: Arc<BufferPoolManager>, header_page_id: PageId) -> CrabDbResult<Self> {
+        let index = Self::with_tree(BPlusTree::open(bpm, header_page_id)?);
+        for entry in index.tree.range(Bound::Unbounded, Bound::Unbounded) {
+            index.count(&entry?.0, 1);
+        }
+        Ok(index)
+    }
+
+    // Counts an entry in or, with a `delta` of -1, out.
+    fn count(&self, key: &[u8], delta: i64) {
+        if is_length(key) {
+            if delta > 0 {
+                self.rows.fetch_add(1, Ordering::Relaxed);
+                self.total_length.fetch_add(count_of(key) as u64, Ordering::Relaxed);
+            } else {
+                self.rows.fetch_sub(1, Ordering::Relaxed);
+                self.total_length.fetch_sub(count_of(key) as u64, Ordering::Relaxed);
+            }
+            return;
+        }
+        let term: TermBytes = key[..TERM_SIZE].try_into().unwrap();
+        let mut row_frequencies = self.row_frequencies.lock().unwrap();
+        let frequency = row_frequencies.entry(term).or_default();
+        *frequency = frequency.saturating_add_signed(delta);
+        if *frequency == 0 {
+            row_frequencies.remove(&term);
+        }
+    }
+
+    pub fn header_page_id(&self) -> PageId {
+        self.tree.header_page_id()
+    }
+
+    // The entries of a row at `rid` whose column holds `text`.
+    pub fn entries(text: &str, rid: Rid) -> Vec<Vec<u8>> {
+        let tokens = tokenize(text);
+        let mut counts = BTreeMap::<&str, u32>::new();
+        for token in &tokens {
+            *counts.entry(token).or_default() += 1;
+        }
+        let mut entries: Vec<_> = counts.into_iter().map(|(term, count)| key(term, rid, count)).collect();
+        entries.push(key("", rid, tokens.len() as u32));
+        entries
+    }
+
+    // The rows with a posting for `term`.
+    fn postings(&self, term: &str) -> CrabDbResult<BTreeSet<Rid>> {
+        let (start, end) = term_bounds(term);
+        self.tree.range(Bound::Included(&start), Bound::Included(&end))
+            .map(|entry| entry.map(|(_, rid)| rid))
+            .collect()
+    }
+
+    // The rows with a posting for every one of `terms`.
+    pub fn search(&self, terms: &[String]) -> CrabDbResult<Vec<Rid>> {
+        let mut found: Option<BTreeSet<Rid>> = None;
+        for term in terms {
+            let postings = self.postings(term)?;
+            found = Some(match found {
+                None => postings,
+                Some(found) => found.intersection(&postings).copied().collect(),
+            });
+            if found.as_ref().is_some_and(BTreeSet::is_empty) {
+                break;
+            }
+        }
+        Ok(found.map_or_else(Vec::new, |found| found.into_iter().collect()))
+    }
+
+    // How many rows have a posting for `term`.
+    pub fn row_frequency(&self, term: &str) -> u64 {
+        if term.is_empty() || term.len() > TERM_SIZE {
+            return 0;
+        }
+        self.row_frequencies.lock().unwrap().get(&term_bytes(term)).copied().unwrap_or(0)
+    }
+
+    pub fn statistics(&self, terms: &[String]) -> Bm25Statistics {
+        let rows = self.rows.load(Ordering::Relaxed);
+        let total_length = self.total_length.load(Ordering::Relaxed);
+        Bm25Statistics {
+            rows,
+            average_length: if rows == 0 { 0.0 } else { total_length as f64 / rows as f64 },
+            row_frequencies: terms.iter().map(|term| self.row_frequency(term)).collect(),
+        }
+    }
+}
+
+impl Index for FullTextIndex {
+    fn kind(&self) -> IndexKind {
+        IndexKind::FullText
+    }
+
+    fn key_size(&self) -> usize {
+        KEY_SIZE
+    }
+
+    fn get(&self, key: &[u8]) -> CrabDbResult<Option<Rid>> {
+        self.tree.get(key)
+    }
+
+    fn insert(&self, key: &[u8], rid: Rid) -> CrabDbResult<bool> {
+        let inserted = self.tree.insert(key, rid)?;
+        if inserted {
+            self.count(key, 1);
+        }
+        Ok(inserted)
+    }
+
+    fn remove(&self, key: &[u8]) -> CrabDbResult<bool> {
+        let removed = self.tree.remove(key)?;
+        if removed {
+            self.count(key, -1);
+        }
+        Ok(removed)
+    }
+
+    fn range<'a>(&'a self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> CrabDbResult<IndexIterator<'a>> {
+        Ok(Box::new(self.tree.range(start, end)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::index::table_index::Index;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use crate::storage::table::tuple::Rid;
+    use super::{tokenize, FullTextIndex, TERM_SIZE};
+
+    #[test]
+    pub fn test_full_text_index_finds_and_scores_rows() {
+        assert_eq!(vec!["crabs", "walk", "sideways", "2", "café"], tokenize("Crabs walk -- SIDEWAYS, 2 Café!"));
+        assert_eq!(TERM_SIZE - 1, tokenize(&format!("a{}", "é".repeat(TERM_SIZE)))[0].len());
+
+        let bpm = Arc::new(BufferPoolManager::new(16, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(16, 2))));
+        let index = FullTextIndex::new(bpm.clone()).unwrap();
+        let texts = [
+            "the crab walks sideways on the beach",
+            "a hermit crab borrows a shell",
+            "the beach is sandy",
+            "crab crab crab",
+        ];
+        for (i, text) in texts.iter().enumerate() {
+            let rid = Rid::new(i as u64, 0);
+            for key in FullTextIndex::entries(text, rid) {
+                assert!(index.insert(&key, rid).unwrap());
+            }
+        }
+        let terms = |query: &str| tokenize(query);
+        assert_eq!(vec![Rid::new(0, 0)], index.search(&terms("crab beach")).unwrap());
+        assert_eq!(3, index.search(&terms("crab")).unwrap().len());
+        assert!(index.search(&terms("lobster crab")).unwrap().is_empty());
+        assert_eq!((3, 0), (index.row_frequency("crab"), index.row_frequency("lobster")));
+
+        let statistics = index.statistics(&terms("crab sandy"));
+        assert_eq!((4, vec![3, 1]), (statistics.rows, statistics.row_frequencies.clone()));
+        assert_eq!(5.0, statistics.average_length);
+        let scores: Vec<_> = texts.iter().map(|text| statistics.score(&terms("crab sandy"), text)).collect();
+        // the rare term outweighs the common one, repeats of a term count for less and less,
+        // and of two rows with a term once the shorter scores higher
+        assert!(scores[2] > scores[3] && scores[3] > scores[1] && scores[1] > scores[0] && scores[0] > 0.0);
+
+        // the counts come back with the index, and follow rows leaving it
+        let reopened = FullTextIndex::open(bpm, index.header_page_id()).unwrap();
+        assert_eq!(statistics, reopened.statistics(&terms("crab sandy")));
+        for key in FullTextIndex::entries(texts[3], Rid::new(3, 0)) {
+            assert!(reopened.remove(&key).unwrap());
+        }
+        let statistics = reopened.statistics(&terms("crab"));
+        assert_eq!((3, vec![2]), (statistics.rows, statistics.row_frequencies));
+    }
+}
@@ -0,0 +1,609 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::ops::{Bound, RangeBounds};
+use std::sync::{Arc, RwLock};
+
+use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+use crate::buffer_pool::page_guard::WritePageGuard;
+use crate::storage::common::{PageId, INVALID_PAGE_ID, PAGE_HEADER_SIZE, PAGE_SIZE};
+use crate::storage::table::tuple::Rid;
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::table_index::{Index, IndexIterator, IndexKind};
+
+// Neighbors a node links to when it joins a layer, and the most it keeps on layer 0, where
+// every node is, and on the layers above.
+const M: usize = 16;
+const MAX_NEIGHBORS_0: usize = 2 * M;
+// Layers above 0 are ever sparser, by a factor of M each; nodes go no higher than this.
+const MAX_LEVEL: usize = 4;
+// Candidates kept while looking for a new node's neighbors, and at least while searching.
+const EF_CONSTRUCTION: usize = 64;
+const EF_SEARCH: usize = 64;
+
+// Header page layout, starting at PAGE_HEADER_SIZE, all integers little endian:
+//   dimensions u16 | has entry u8 | entry level u8 | reserved u32 | entry node u64
+//   | first node page id u64 | last node page id u64 | last vector page id u64
+const DIMENSIONS_OFFSET: usize = PAGE_HEADER_SIZE;
+const HAS_ENTRY_OFFSET: usize = PAGE_HEADER_SIZE + 2;
+const ENTRY_LEVEL_OFFSET: usize = PAGE_HEADER_SIZE + 3;
+const ENTRY_OFFSET: usize = PAGE_HEADER_SIZE + 8;
+const FIRST_PAGE_OFFSET: usize = PAGE_HEADER_SIZE + 16;
+const LAST_PAGE_OFFSET: usize = PAGE_HEADER_SIZE + 24;
+const LAST_VECTOR_PAGE_OFFSET: usize = PAGE_HEADER_SIZE + 32;
+
+// Node page layout: next node page id u64 | bytes of nodes u16 | nodes, each
+//   rid page_id u64 | rid slot u16 | level u8 | deleted u8 | vector id u64
+//   | for each layer from 0 to its level: neighbor count u8 | node ids u64 * the layer's most
+// Nodes are never moved, so a node's id is where it starts: page id * PAGE_SIZE + offset.
+const NEXT_PAGE_OFFSET: usize = PAGE_HEADER_SIZE;
+const USED_OFFSET: usize = PAGE_HEADER_SIZE + 8;
+const NODES_OFFSET: usize = PAGE_HEADER_SIZE + 10;
+const NODE_LEVEL_OFFSET: usize = 10;
+const NODE_DELETED_OFFSET: usize = 11;
+const NODE_VECTOR_OFFSET: usize = 12;
+const NODE_LAYERS_OFFSET: usize = 20;
+const RID_SIZE: usize = 10;
+
+// Vectors live apart from the nodes, so the size of a node does not grow with them. Vector
+// pages have the layout of node pages, with vectors, f32 * dimensions each, for nodes. They
+// are packed one after the other, a vector running on to the next page where a page ends;
+// its id is where it starts, like a node's.
+const VECTORS_OFFSET: usize = NODES_OFFSET;
+
+// The header holds the dimensions in a u16.
+pub const MAX_DIMENSIONS: usize = u16::MAX as usize;
+
+type NodeId = u64;
+type VectorId = u64;
+
+fn read_u16(data: &[u8; PAGE_SIZE], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8; PAGE_SIZE], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+fn write_u64(data: &mut [u8; PAGE_SIZE], offset: usize, value: u64) {
+    data[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+fn set_used(data: &mut [u8; PAGE_SIZE], used: usize) {
+    data[USED_OFFSET..USED_OFFSET + 2].copy_from_slice(&(used as u16).to_le_bytes());
+}
+
+fn locate(id: NodeId) -> (PageId, usize) {
+    (id / PAGE_SIZE as u64, (id % PAGE_SIZE as u64) as usize)
+}
+
+// The `len` bytes of the vector at `id`, gathered from as many vector pages as it runs over.
+fn read_vector_bytes(bpm: &BufferPoolManager, id: VectorId, len: usize) -> CrabDbResult<Vec<u8>> {
+    let (mut page_id, mut offset) = locate(id);
+    let mut bytes = Vec::with_capacity(len);
+    while bytes.len() < len {
+        if page_id == INVALID_PAGE_ID {
+            return Err(CrabDBError::Corruption(format!("HNSW vector {id} ends before its {len} bytes")));
+        }
+        let page = bpm.fetch_page_read(page_id)?;
+        let end = (VECTORS_OFFSET + read_u16(&page, USED_OFFSET) as usize).min(offset + len - bytes.len());
+        if end <= offset {
+            return Err(CrabDBError::Corruption(format!("HNSW vector {id} ends before its {len} bytes")));
+        }
+        bytes.extend_from_slice(&page[offset..end]);
+        page_id = read_u64(&page, NEXT_PAGE_OFFSET);
+        offset = VECTORS_OFFSET;
+    }
+    Ok(bytes)
+}
+
+fn max_neighbors(layer: usize) -> usize {
+    if layer == 0 { MAX_NEIGHBORS_0 } else { M }
+}
+
+// Where the neighbors of `layer` start in a node, from its start.
+fn layer_offset(layer: usize) -> usize {
+    NODE_LAYERS_OFFSET + (0..layer).map(|layer| 1 + 8 * max_neighbors(layer)).sum::<usize>()
+}
+
+fn node_size(level: usize) -> usize {
+    layer_offset(level + 1)
+}
+
+pub fn l2_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum::<f32>().sqrt()
+}
+
+// The entry of a row at `rid` whose column holds `vector`: the rid, then the vector.
+pub fn key(vector: &[f32], rid: Rid) -> Vec<u8> {
+    let mut key = Vec::with_capacity(RID_SIZE + 4 * vector.len());
+    key.extend_from_slice(&rid.page_id().to_be_bytes());
+    key.extend_from_slice(&rid.slot_id().to_be_bytes());
+    for element in vector {
+        key.extend_from_slice(&element.to_le_bytes());
+    }
+    key
+}
+
+fn rid_of(key: &[u8]) -> Rid {
+    Rid::new(u64::from_be_bytes(key[..8].try_into().unwrap()), u16::from_be_bytes(key[8..RID_SIZE].try_into().unwrap()))
+}
+
+fn vector_of(key: &[u8]) -> Vec<f32> {
+    key[RID_SIZE..].chunks_exact(4).map(|element| f32::from_le_bytes(element.try_into().unwrap())).collect()
+}
+
+// The layer a node goes up to, drawn from the usual exponential distribution. The draw comes
+// from a hash of the entry instead of a random number generator, so an index built twice
+// from the same rows comes out the same.
+fn level_of(key: &[u8]) -> usize {
+    let uniform = (crc32c::crc32c(key) as f64 + 1.0) / (u32::MAX as f64 + 2.0);
+    ((-uniform.ln() / (M as f64).ln()) as usize).min(MAX_LEVEL)
+}
+
+// A node as read off its page.
+struct Node {
+    rid: Rid,
+    deleted: bool,
+    vector: Vec<f32>,
+    // Per layer, from 0 up to the node's level.
+    neighbors: Vec<Vec<NodeId>>,
+}
+
+// A node at some distance from a query, ordered by the distance.
+#[derive(Clone, Copy, PartialEq)]
+struct Candidate(f32, NodeId);
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+struct Graph {
+    // The node searches start from, on the top layer, and that layer.
+    entry: Option<(NodeId, usize)>,
+    last_page_id: PageId,
+    // Every node by its entry, and whether it was removed.
+    nodes: HashMap<Vec<u8>, (NodeId, bool)>,
+    last_vector_page_id: PageId,
+}
+
+// An approximate nearest neighbor index over a VECTOR column: a hierarchical navigable
+// small world graph (Malkov and Yashunin) on pages of the buffer pool. Every row is a node
+// on layer 0, linked to rows close to it; each layer above holds a sample of the one below,
+// so a search walks down from the top, closer to the query at every step.
+//
+// Like full-text indexes it is not versioned and holds an entry per version of a row, keyed
+// by the rid and the vector. Removing an entry leaves its node in the graph for searches to
+// pass through, marked so they do not return it; a row that gets the same vector back at the
+// same rid brings its node back. Nodes are only kept in memory by their entries, to find
+// them again, which opening the index reads back.
+pub struct HnswIndex {
+    bpm: Arc<BufferPoolManager>,
+    header_page_id: PageId,
+    first_page_id: PageId,
+    dimensions: usize,
+    graph: RwLock<Graph>,
+}
+
+impl HnswIndex {
+    pub fn new(bpm: Arc<BufferPoolManager>, dimensions: usize) -> CrabDbResult<Self> {
+        if dimensions == 0 || dimensions > MAX_DIMENSIONS {
+            return Err(CrabDBError::InvalidInput(format!(
+                "HNSW indexes hold vectors of 1 to {MAX_DIMENSIONS} dimensions, not {dimensions}"
+            )));
+        }
+        let mut header_guard = bpm.new_page_write()?;
+        let header_page_id = header_guard.page_id();
+        let first_page_id = Self::new_page(&bpm)?.page_id();
+        let first_vector_page_id = Self::new_page(&bpm)?.page_id();
+        let index = HnswIndex {
+            bpm: bpm.clone(),
+            header_page_id,
+            first_page_id,
+            dimensions,
+            graph: RwLock::new(Graph {
+                entry: None,
+                last_page_id: first_page_id,
+                nodes: HashMap::new(),
+                last_vector_page_id: first_vector_page_id,
+            }),
+        };
+        index.write_header(&mut header_guard, &index.graph.read().unwrap());
+        drop(header_guard);
+        Ok(index)
+    }
+
+    // Reopens the index, reading the entries of its nodes back.
+    pub fn open(bpm: Arc<BufferPoolManager>, header_page_id: PageId) -> CrabDbResult<Self> {
+        let header = bpm.fetch_page_read(header_page_id)?;
+        let dimensions = read_u16(&header, DIMENSIONS_OFFSET) as usize;
+        if dimensions == 0 {
+            return Err(CrabDBError::Corruption(format!("Page {header_page_id} is not a valid HNSW header")));
+        }
+        let entry = (header[HAS_ENTRY_OFFSET] != 0).then(|| (read_u64(&header, ENTRY_OFFSET), header[ENTRY_LEVEL_OFFSET] as usize));
+        let first_page_id = read_u64(&header, FIRST_PAGE_OFFSET);
+        let last_page_id = read_u64(&header, LAST_PAGE_OFFSET);
+        let last_vector_page_id = read_u64(&header, LAST_VECTOR_PAGE_OFFSET);
+        drop(header);
+
+        let mut nodes = HashMap::new();
+        let mut page_id = first_page_id;
+        while page_id != INVALID_PAGE_ID {
+            let page = bpm.fetch_page_read(page_id)?;
+            let end = NODES_OFFSET + read_u16(&page, USED_OFFSET) as usize;
+            let mut offset = NODES_OFFSET;
+            while offset < end {
+                let level = page[offset + NODE_LEVEL_OFFSET] as usize;
+                if level > MAX_LEVEL || offset + node_size(level) > end {
+                    return Err(CrabDBError::Corruption(format!("Page {page_id} holds an invalid HNSW node at {offset}")));
+                }
+                let mut key = page[offset..offset + RID_SIZE].to_vec();
+                key.extend(read_vector_bytes(&bpm, read_u64(&page, offset + NODE_VECTOR_OFFSET), 4 * dimensions)?);
+                nodes.insert(key, (page_id * PAGE_SIZE as u64 + offset as u64, page[offset + NODE_DELETED_OFFSET] != 0));
+                offset += node_size(level);
+            }
+            page_id = read_u64(&page, NEXT_PAGE_OFFSET);
+        }
+        let graph = RwLock::new(Graph { entry, last_page_id, nodes, last_vector_page_id });
+        Ok(HnswIndex { bpm, header_page_id, first_page_id, dimensions, graph })
+    }
+
+    // A node or vector page with nothing on it yet.
+    fn new_page(bpm: &BufferPoolManager) -> CrabDbResult<WritePageGuard<'_>> {
+        let mut page = bpm.new_page_write()?;
+        write_u64(&mut page, NEXT_PAGE_OFFSET, INVALID_PAGE_ID);
+        page[USED_OFFSET..USED_OFFSET + 2].fill(0);
+        Ok(page)
+    }
+
+    pub fn header_page_id(&self) -> PageId {
+        self.header_page_id
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn write_header(&self, header: &mut [u8; PAGE_SIZE], graph: &Graph) {
+        header[PAGE_HEADER_SIZE..].fill(0);
+        header[DIMENSIONS_OFFSET..DIMENSIONS_OFFSET + 2].copy_from_slice(&(self.dimensions as u16).to_le_bytes());
+        if let Some((entry, level)) = graph.entry {
+            header[HAS_ENTRY_OFFSET] = 1;
+            header[ENTRY_LEVEL_OFFSET] = level as u8;
+            write_u64(header, ENTRY_OFFSET, entry);
+        }
+        write_u64(header, FIRST_PAGE_OFFSET, self.first_page_id);
+        write_u64(header, LAST_PAGE_OFFSET, graph.last_page_id);
+        write_u64(header, LAST_VECTOR_PAGE_OFFSET, graph.last_vector_page_id);
+    }
+
+    fn read_node(&self, id: NodeId) -> CrabDbResult<Node> {
+        let (page_id, offset) = locate(id);
+        let page = self.bpm.fetch_page_read(page_id)?;
+        let node = &page[offset..];
+        let level = node[NODE_LEVEL_OFFSET] as usize;
+        let neighbors = (0..=level).map(|layer| {
+            let start = layer_offset(layer);
+            (0..node[start] as usize).map(|i| u64::from_le_bytes(node[start + 1 + 8 * i..start + 9 + 8 * i].try_into().unwrap())).collect()
+        }).collect();
+        let rid = rid_of(&node[..RID_SIZE]);
+        let deleted = node[NODE_DELETED_OFFSET] != 0;
+        let vector_id = u64::from_le_bytes(node[NODE_VECTOR_OFFSET..NODE_LAYERS_OFFSET].try_into().unwrap());
+        drop(page);
+        let vector = read_vector_bytes(&self.bpm, vector_id, 4 * self.dimensions)?.chunks_exact(4)
+            .map(|element| f32::from_le_bytes(element.try_into().unwrap()))
+            .collect();
+        Ok(Node { rid, deleted, vector, neighbors })
+    }
+
+    fn write_neighbors(&self, id: NodeId, layer: usize, neighbors: &[NodeId]) -> CrabDbResult<()> {
+        let (page_id, offset) = locate(id);
+        let mut page = self.bpm.fetch_page_write(page_id)?;
+        let start = offset + layer_offset(layer);
+        page[start] = neighbors.len() as u8;
+        for (i, neighbor) in neighbors.iter().enumerate() {
+            write_u64(&mut page, start + 1 + 8 * i, *neighbor);
+        }
+        Ok(())
+    }
+
+    fn set_deleted(&self, id: NodeId, deleted: bool) -> CrabDbResult<()> {
+        let (page_id, offset) = locate(id);
+        self.bpm.fetch_page_write(page_id)?[offset + NODE_DELETED_OFFSET] = deleted as u8;
+        Ok(())
+    }
+
+    // Writes a node without neighbors after the last one, on a new page if it does not fit,
+    // and its vector after the last vector.
+    fn append(&self, graph: &mut Graph, key: &[u8], level: usize) -> CrabDbResult<NodeId> {
+        let vector_id = self.append_vector(graph, &key[RID_SIZE..])?;
+        let size = node_size(level);
+        let mut page = self.bpm.fetch_page_write(graph.last_page_id)?;
+        let mut offset = NODES_OFFSET + read_u16(&page, USED_OFFSET) as usize;
+        if offset + size > PAGE_SIZE {
+            let next = Self::new_page(&self.bpm)?;
+            write_u64(&mut page, NEXT_PAGE_OFFSET, next.page_id());
+            graph.last_page_id = next.page_id();
+            page = next;
+            offset = NODES_OFFSET;
+        }
+        page[offset..offset + size].fill(0);
+        page[offset..offset + RID_SIZE].copy_from_slice(&key[..RID_SIZE]);
+        page[offset + NODE_LEVEL_OFFSET] = level as u8;
+        write_u64(&mut page, offset + NODE_VECTOR_OFFSET, vector_id);
+        set_used(&mut page, offset + size - NODES_OFFSET);
+        Ok(page.page_id() * PAGE_SIZE as u64 + offset as u64)
+    }
+
+    // Writes the bytes of a vector after the last one, going on to new pages as they fill.
+    fn append_vector(&self, graph: &mut Graph, mut bytes: &[u8]) -> CrabDbResult<VectorId> {
+        let mut page = self.bpm.fetch_page_write(graph.last_vector_page_id)?;
+        let mut offset = VECTORS_OFFSET + read_u16(&page, USED_OFFSET) as usize;
+        let mut start = None;
+        loop {
+            if offset == PAGE_SIZE {
+                let next = Self::new_page(&self.bpm)?;
+                write_u64(&mut page, NEXT_PAGE_OFFSET, next.page_id());
+                graph.last_vector_page_id = next.page_id();
+                page = next;
+                offset = VECTORS_OFFSET;
+            }
+            let id = *start.get_or_insert(page.page_id() * PAGE_SIZE as u64 + offset as u64);
+            let len = bytes.len().min(PAGE_SIZE - offset);
+            page[offset..offset + len].copy_from_slice(&bytes[..len]);
+            offset += len;
+            set_used(&mut page, offset - VECTORS_OFFSET);
+            bytes = &bytes[len..];
+            if bytes.is_empty() {
+                return Ok(id);
+            }
+        }
+    }
+
+    // The `ef` nodes of `layer` closest to `query` that a greedy walk from `entries` finds,
+    // closest first.
+    fn search_layer(&self, query: &[f32], entries: &[Candidate], ef: usize, layer: usize) -> CrabDbResult<Vec<Candidate>> {
+        let mut visited: HashSet<NodeId> = entries.iter().map(|entry| entry.1).collect();
+        let mut candidates: BinaryHeap<Reverse<Candidate>> = entries.iter().copied().map(Reverse).collect();
+        let mut found: BinaryHeap<Candidate> = entries.iter().copied().collect();
+        while let Some(Reverse(closest)) = candidates.pop() {
+            if found.len() >= ef && found.peek().is_some_and(|farthest| closest.0 > farthest.0) {
+                break;
+            }
+            let node = self.read_node(closest.1)?;
+            for &neighbor in node.neighbors.get(layer).into_iter().flatten() {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let candidate = Candidate(l2_distance(query, &self.read_node(neighbor)?.vector), neighbor);
+                if found.len() < ef || found.peek().is_some_and(|farthest| candidate.0 < farthest.0) {
+                    candidates.push(Reverse(candidate));
+                    found.push(candidate);
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+        Ok(found.into_sorted_vec())
+    }
+
+    // Links `id` to `neighbor` on `layer`, dropping the farthest of the neighbor's links if
+    // it has too many.
+    fn link(&self, neighbor: NodeId, id: NodeId, layer: usize) -> CrabDbResult<()> {
+        let node = self.read_node(neighbor)?;
+        let mut links = node.neighbors[layer].clone();
+        links.push(id);
+        if links.len() > max_neighbors(layer) {
+            let mut by_distance = links.iter()
+                .map(|&link| Ok(Candidate(l2_distance(&node.vector, &self.read_node(link)?.vector), link)))
+                .collect::<CrabDbResult<Vec<_>>>()?;
+            by_distance.sort();
+            links = by_distance.into_iter().take(max_neighbors(layer)).map(|candidate| candidate.1).collect();
+        }
+        self.write_neighbors(neighbor, layer, &links)
+    }
+
+    // Rows whose vectors are closest to `query`, closest first: at least `k` if the index
+    // holds that many, unless their entries are removed. Being approximate, it may miss some.
+    pub fn search(&self, query: &[f32], k: usize) -> CrabDbResult<Vec<Rid>> {
+        if query.len() != self.dimensions {
+            return Err(CrabDBError::InvalidInput(format!(
+                "The index holds vectors of {} dimensions but the query has {}", self.dimensions, query.len()
+            )));
+        }
+        let graph = self.graph.read().unwrap();
+        let Some((entry, top)) = graph.entry else {
+            return Ok(Vec::new());
+        };
+        let mut nearest = vec![Candidate(l2_distance(query, &self.read_node(entry)?.vector), entry)];
+        for layer in (1..=top).rev() {
+            nearest = self.search_layer(query, &nearest, 1, layer)?;
+        }
+        let mut rids = Vec::new();
+        for candidate in self.search_layer(query, &nearest, EF_SEARCH.max(k), 0)? {
+            let node = self.read_node(candidate.1)?;
+            // A row changed in place has a node per vector it had.
+            if !node.deleted && !rids.contains(&node.rid) {
+                rids.push(node.rid);
+            }
+        }
+        Ok(rids)
+    }
+
+    fn check_key(&self, key: &[u8]) -> CrabDbResult<()> {
+        if key.len() != RID_SIZE + 4 * self.dimensions {
+            return Err(CrabDBError::InvalidInput(format!("Key must be {} bytes, got {}", RID_SIZE + 4 * self.dimensions, key.len())));
+        }
+        Ok(())
+    }
+}
+
+impl Index for HnswIndex {
+    fn kind(&self) -> IndexKind {
+        IndexKind::Hnsw
+    }
+
+    fn key_size(&self) -> usize {
+        RID_SIZE + 4 * self.dimensions
+    }
+
+    fn get(&self, key: &[u8]) -> CrabDbResult<Option<Rid>> {
+        self.check_key(key)?;
+        let graph = self.graph.read().unwrap();
+        Ok(graph.nodes.get(key).filter(|(_, deleted)| !deleted).map(|_| rid_of(key)))
+    }
+
+    fn insert(&self, key: &[u8], _rid: Rid) -> CrabDbResult<bool> {
+        self.check_key(key)?;
+        let mut graph = self.graph.write().unwrap();
+        if let Some((id, deleted)) = graph.nodes.get(key).copied() {
+            if deleted {
+                self.set_deleted(id, false)?;
+                graph.nodes.insert(key.to_vec(), (id, false));
+            }
+            return Ok(deleted);
+        }
+        let vector = vector_of(key);
+        let level = level_of(key);
+        let id = self.append(&mut graph, key, level)?;
+        graph.nodes.insert(key.to_vec(), (id, false));
+        if let Some((entry, top)) = graph.entry {
+            let mut nearest = vec![Candidate(l2_distance(&vector, &self.read_node(entry)?.vector), entry)];
+            for layer in (level + 1..=top).rev() {
+                nearest = self.search_layer(&vector, &nearest, 1, layer)?;
+            }
+            for layer in (0..=level.min(top)).rev() {
+                nearest = self.search_layer(&vector, &nearest, EF_CONSTRUCTION, layer)?;
+                let neighbors: Vec<_> = nearest.iter().take(M).map(|candidate| candidate.1).collect();
+                self.write_neighbors(id, layer, &neighbors)?;
+                for neighbor in neighbors {
+                    self.link(neighbor, id, layer)?;
+                }
+            }
+        }
+        if graph.entry.is_none_or(|(_, top)| level > top) {
+            graph.entry = Some((id, level));
+        }
+        let mut header = self.bpm.fetch_page_write(self.header_page_id)?;
+        self.write_header(&mut header, &graph);
+        Ok(true)
+    }
+
+    fn remove(&self, key: &[u8]) -> CrabDbResult<bool> {
+        self.check_key(key)?;
+        let mut graph = self.graph.write().unwrap();
+        let Some((id, false)) = graph.nodes.get(key).copied() else {
+            return Ok(false);
+        };
+        self.set_deleted(id, true)?;
+        graph.nodes.insert(key.to_vec(), (id, true));
+        Ok(true)
+    }
+
+    // Every entry not removed, which is what vacuum looks through. The graph has no order of
+    // its own, so the entries are sorted here.
+    fn range<'a>(&'a self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> CrabDbResult<IndexIterator<'a>> {
+        let graph = self.graph.read().unwrap();
+        let mut keys: Vec<_> = graph.nodes.iter()
+            .filter(|(key, (_, deleted))| !deleted && RangeBounds::<[u8]>::contains(&(start, end), key.as_slice()))
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.sort();
+        Ok(Box::new(keys.into_iter().map(|key| {
+            let rid = rid_of(&key);
+            Ok((key, rid))
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::index::table_index::Index;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use crate::storage::table::tuple::Rid;
+    use super::{key, l2_distance, HnswIndex, MAX_DIMENSIONS};
+
+    #[test]
+    pub fn test_hnsw_index_finds_nearest_neighbors() {
+        // A small pool, so searches read nodes back from disk.
+        let bpm = Arc::new(BufferPoolManager::new(8, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(8, 2))));
+        assert!(HnswIndex::new(bpm.clone(), 0).is_err());
+        assert!(HnswIndex::new(bpm.clone(), MAX_DIMENSIONS + 1).is_err());
+        let index = HnswIndex::new(bpm.clone(), 2).unwrap();
+        assert!(index.search(&[0.0, 0.0], 3).unwrap().is_empty());
+
+        // Points on a 30 x 30 grid, in an order that is not the grid's.
+        let point = |i: u64| [((i * 7) % 30) as f32, ((i * 7) / 30 % 30) as f32];
+        let rid = |i: u64| Rid::new(i / 100, (i % 100) as u16);
+        for i in 0..900 {
+            assert!(index.insert(&key(&point(i), rid(i)), rid(i)).unwrap());
+        }
+        assert!(!index.insert(&key(&point(0), rid(0)), rid(0)).unwrap());
+        assert!(index.search(&[1.0], 3).is_err());
+
+        let exact = |query: [f32; 2], k: usize| {
+            let mut all: Vec<_> = (0..900).map(|i| (l2_distance(&query, &point(i)), rid(i))).collect();
+            all.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+            all.into_iter().take(k).map(|(_, rid)| rid).collect::<Vec<_>>()
+        };
+        let query = [12.2, 17.1];
+        let found = index.search(&query, 5).unwrap();
+        assert_eq!(exact(query, 1), found[..1]);
+        let recall = exact(query, 5).iter().filter(|rid| found[..5].contains(rid)).count();
+        assert!(recall >= 4, "{found:?}");
+
+        // Removed entries stop showing up and come back when inserted again, and the index
+        // reopens with its nodes.
+        let nearest = found[0];
+        let nearest_key = key(&query.map(f32::round), nearest);
+        assert_eq!(Some(nearest), index.get(&nearest_key).unwrap());
+        assert!(index.remove(&nearest_key).unwrap() && !index.remove(&nearest_key).unwrap());
+        assert!(!index.search(&query, 5).unwrap().contains(&nearest));
+        assert_eq!(899, index.range(std::ops::Bound::Unbounded, std::ops::Bound::Unbounded).unwrap().count());
+        let reopened = HnswIndex::open(bpm, index.header_page_id()).unwrap();
+        assert_eq!(2, reopened.dimensions());
+        assert_eq!(None, reopened.get(&nearest_key).unwrap());
+        assert_eq!(index.search(&query, 5).unwrap(), reopened.search(&query, 5).unwrap());
+        assert!(reopened.insert(&nearest_key, nearest).unwrap());
+        assert_eq!(nearest, reopened.search(&query, 1).unwrap()[0]);
+    }
+
+    #[test]
+    pub fn test_hnsw_index_holds_vectors_bigger_than_a_page() {
+        // 1536 dimensions take 6 KiB, so every vector runs over more than one page.
+        let bpm = Arc::new(BufferPoolManager::new(16, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(16, 2))));
+        let dimensions = 1536;
+        let index = HnswIndex::new(bpm.clone(), dimensions).unwrap();
+        let vector = |i: usize| (0..dimensions).map(|d| ((i * 131 + d * 7) % 1009) as f32 / 1009.0).collect::<Vec<_>>();
+        let rid = |i: usize| Rid::new(1, i as u16);
+        for i in 0..100 {
+            assert!(index.insert(&key(&vector(i), rid(i)), rid(i)).unwrap());
+        }
+        for i in [0, 37, 99] {
+            assert_eq!(rid(i), index.search(&vector(i), 1).unwrap()[0]);
+            assert_eq!(Some(rid(i)), index.get(&key(&vector(i), rid(i))).unwrap());
+        }
+
+        let reopened = HnswIndex::open(bpm, index.header_page_id()).unwrap();
+        assert_eq!(dimensions, reopened.dimensions());
+        assert_eq!(100, reopened.range(std::ops::Bound::Unbounded, std::ops::Bound::Unbounded).unwrap().count());
+        assert_eq!(rid(58), reopened.search(&vector(58), 1).unwrap()[0]);
+        assert!(reopened.insert(&key(&vector(100), rid(100)), rid(100)).unwrap());
+        assert_eq!(rid(100), reopened.search(&vector(100), 1).unwrap()[0]);
+    }
+}
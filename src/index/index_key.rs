@@ -0,0 +1,99 @@
+use crate::types::schema::Schema;
+use crate::types::value::{DataType, Value};
+use crate::types::{CrabDBError, CrabDbResult};
+
+// Varchar key columns are zero padded to this many bytes.
+pub const VARCHAR_KEY_SIZE: usize = 32;
+
+fn column_key_size(data_type: DataType) -> usize {
+    data_type.fixed_size().unwrap_or(VARCHAR_KEY_SIZE)
+}
+
+// Size of the keys built from a key schema: a null marker byte plus a fixed-width value per
+// column.
+pub fn key_size(key_schema: &Schema) -> usize {
+    key_schema.columns().iter().map(|column| 1 + column_key_size(column.data_type())).sum()
+}
+
+// Encodes key values so that comparing the bytes orders keys the way the values compare:
+// integers are big endian with the sign bit flipped, floats have their bits rearranged the
+// usual way, and NULL sorts before any value.
+pub fn encode_key(key_schema: &Schema, values: &[Value]) -> CrabDbResult<Vec<u8>> {
+    if values.len() != key_schema.column_count() {
+        return Err(CrabDBError::InvalidInput(format!(
+            "Expected {} key values but got {}", key_schema.column_count(), values.len()
+        )));
+    }
+    let mut key = Vec::with_capacity(key_size(key_schema));
+    for (column, value) in key_schema.columns().iter().zip(values) {
+        let value = value.cast_to(column.data_type())?;
+        if value.is_null() {
+            key.resize(key.len() + 1 + column_key_size(column.data_type()), 0);
+            continue;
+        }
+        key.push(1);
+        match value {
+            Value::Boolean(b) => key.push(b as u8),
+            Value::Int32(i) => key.extend_from_slice(&((i as u32) ^ (1 << 31)).to_be_bytes()),
+            Value::Int64(i) | Value::Timestamp(i) => key.extend_from_slice(&((i as u64) ^ (1 << 63)).to_be_bytes()),
+            Value::Float64(f) => {
+                let bits = f.to_bits();
+                let bits = if bits >> 63 == 1 { !bits } else { bits ^ (1 << 63) };
+                key.extend_from_slice(&bits.to_be_bytes());
+            },
+            Value::Varchar(s) => {
+                if s.len() > VARCHAR_KEY_SIZE {
+                    return Err(CrabDBError::InvalidInput(format!(
+                        "Varchar key of {} bytes is longer than the {VARCHAR_KEY_SIZE} bytes an index key allows", s.len()
+                    )));
+                }
+                key.extend_from_slice(s.as_bytes());
+                key.resize(key.len() + VARCHAR_KEY_SIZE - s.len(), 0);
+            },
+            Value::Vector(_) => {
+                return Err(CrabDBError::InvalidInput(format!("Column {} is a vector, which cannot be part of a key", column.name())));
+            },
+            Value::Geometry(_) => {
+                return Err(CrabDBError::InvalidInput(format!("Column {} is a geometry, which cannot be part of a key", column.name())));
+            },
+            Value::Null => unreachable!("handled above"),
+        }
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::schema::{Column, Schema};
+    use crate::types::value::{DataType, Value};
+    use super::{encode_key, key_size};
+
+    #[test]
+    pub fn test_index_key_preserves_order() {
+        let schema = Schema::new(vec![Column::new("a", DataType::Int64), Column::new("b", DataType::Float64)]);
+        assert_eq!(18, key_size(&schema));
+        let rows = [
+            [Value::Null, Value::Float64(0.0)],
+            [Value::Int64(-5), Value::Float64(-1.5)],
+            [Value::Int64(-5), Value::Float64(2.0)],
+            [Value::Int32(0), Value::Float64(-3.0)],
+            [Value::Int64(7), Value::Null],
+            [Value::Int64(7), Value::Float64(-0.5)],
+            [Value::Int64(i64::MAX), Value::Int32(1)],
+        ];
+        let keys: Vec<_> = rows.iter().map(|row| encode_key(&schema, row).unwrap()).collect();
+        assert!(keys.iter().all(|key| key.len() == 18));
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    pub fn test_index_key_varchar() {
+        let schema = Schema::new(vec![Column::new("name", DataType::Varchar)]);
+        let a = encode_key(&schema, &[Value::Varchar("apple".into())]).unwrap();
+        let b = encode_key(&schema, &[Value::Varchar("banana".into())]).unwrap();
+        assert!(a < b);
+        assert_eq!(key_size(&schema), a.len());
+        assert!(encode_key(&schema, &[Value::Varchar("x".repeat(33))]).is_err());
+        assert!(encode_key(&schema, &[]).is_err());
+    }
+}
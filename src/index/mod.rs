@@ -0,0 +1,11 @@
+pub mod bloom_filter;
+pub mod bplus_tree;
+pub mod bplus_tree_iterator;
+pub(crate) mod bplus_tree_page;
+pub mod extendible_hash;
+pub(crate) mod extendible_hash_page;
+pub mod full_text;
+pub mod hnsw;
+pub mod index_key;
+pub mod rtree;
+pub mod table_index;
@@ -0,0 +1,700 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::ops::{Bound, RangeBounds};
+use std::sync::{Arc, RwLock};
+
+use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+use crate::storage::common::{PageId, PAGE_HEADER_SIZE, PAGE_SIZE};
+use crate::storage::table::tuple::Rid;
+use crate::types::geometry::Geometry;
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::table_index::{Index, IndexIterator, IndexKind};
+
+// Header page layout, starting at PAGE_HEADER_SIZE, little endian: magic u32 | root page id u64
+const MAGIC: u32 = 0x5254_5245;
+const MAGIC_OFFSET: usize = PAGE_HEADER_SIZE;
+const ROOT_OFFSET: usize = PAGE_HEADER_SIZE + 4;
+
+// Node page layout: level u8 (0 for leaves) | entry count u16 | entries, each
+//   min x f64 | min y f64 | max x f64 | max y f64 | page id u64 | slot u16
+// A leaf entry points at a row by its rid; an entry of a node above at the child node
+// holding everything inside its box, with slot 0.
+const LEVEL_OFFSET: usize = PAGE_HEADER_SIZE;
+const COUNT_OFFSET: usize = PAGE_HEADER_SIZE + 1;
+const ENTRIES_OFFSET: usize = PAGE_HEADER_SIZE + 3;
+const ENTRY_SIZE: usize = 42;
+const MAX_ENTRIES: usize = (PAGE_SIZE - ENTRIES_OFFSET) / ENTRY_SIZE;
+// The fewest entries either half of a split gets.
+const MIN_ENTRIES: usize = MAX_ENTRIES * 2 / 5;
+const RID_SIZE: usize = 10;
+
+// The entry of a row at `rid` whose column holds `geometry`: the rid, then the geometry.
+pub fn key(geometry: &Geometry, rid: Rid) -> Vec<u8> {
+    let mut key = Vec::with_capacity(RID_SIZE + 32);
+    key.extend_from_slice(&rid.page_id().to_be_bytes());
+    key.extend_from_slice(&rid.slot_id().to_be_bytes());
+    for coordinate in geometry.coordinates() {
+        key.extend_from_slice(&coordinate.to_le_bytes());
+    }
+    key
+}
+
+fn entry_of(key: &[u8]) -> Entry {
+    let mut coordinates = [0.0; 4];
+    for (coordinate, bytes) in coordinates.iter_mut().zip(key[RID_SIZE..].chunks_exact(8)) {
+        *coordinate = f64::from_le_bytes(bytes.try_into().unwrap());
+    }
+    Entry {
+        bounds: Geometry::from_coordinates(coordinates),
+        page_id: u64::from_be_bytes(key[..8].try_into().unwrap()),
+        slot: u16::from_be_bytes(key[8..RID_SIZE].try_into().unwrap()),
+    }
+}
+
+// Which rows a spatial search finds, by how their geometry lies against the one given.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpatialQuery {
+    Intersects(Geometry),
+    // Rows whose geometry holds the one given.
+    Contains(Geometry),
+    // Rows whose geometry lies inside the one given.
+    Within(Geometry),
+}
+
+impl SpatialQuery {
+    fn matches(&self, bounds: &Geometry) -> bool {
+        match self {
+            SpatialQuery::Intersects(geometry) => bounds.intersects(geometry),
+            SpatialQuery::Contains(geometry) => bounds.contains(geometry),
+            SpatialQuery::Within(geometry) => geometry.contains(bounds),
+        }
+    }
+
+    // Whether a node whose box is `bounds` may hold a matching entry.
+    fn may_hold(&self, bounds: &Geometry) -> bool {
+        match self {
+            SpatialQuery::Contains(geometry) => bounds.contains(geometry),
+            SpatialQuery::Intersects(geometry) | SpatialQuery::Within(geometry) => bounds.intersects(geometry),
+        }
+    }
+
+    // The share of the entries below a node whose box is `bounds` taken to match, for
+    // estimates: the share of the box the query's geometry covers, but no less than about
+    // one entry of a full node, so points and lines still count.
+    fn overlap(&self, bounds: &Geometry) -> f64 {
+        let (SpatialQuery::Intersects(geometry) | SpatialQuery::Contains(geometry) | SpatialQuery::Within(geometry)) = self;
+        let side = |axis: usize| {
+            let length = bounds.max[axis] - bounds.min[axis];
+            let covered = bounds.max[axis].min(geometry.max[axis]) - bounds.min[axis].max(geometry.min[axis]);
+            if length > 0.0 { (covered / length).clamp(0.0, 1.0) } else { 1.0 }
+        };
+        (side(0) * side(1)).max(1.0 / MAX_ENTRIES as f64)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Entry {
+    bounds: Geometry,
+    page_id: PageId,
+    slot: u16,
+}
+
+impl Entry {
+    fn rid(&self) -> Rid {
+        Rid::new(self.page_id, self.slot)
+    }
+
+    fn key(&self) -> Vec<u8> {
+        key(&self.bounds, self.rid())
+    }
+}
+
+// A node as read off its page.
+struct Node {
+    level: u8,
+    entries: Vec<Entry>,
+}
+
+fn bounds_of(entries: &[Entry]) -> Geometry {
+    entries.iter().skip(1).fold(entries[0].bounds, |bounds, entry| bounds.union(&entry.bounds))
+}
+
+// How much the area of `bounds` grows to take in `other`.
+fn enlargement(bounds: &Geometry, other: &Geometry) -> f64 {
+    bounds.union(other).area() - bounds.area()
+}
+
+// An entry of some node at some distance from a query, ordered by the distance. Leaf entries
+// come before nodes at the same distance, so rows are returned as soon as they are closest.
+#[derive(Clone, Copy, PartialEq)]
+struct Candidate(f64, u8, Entry);
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+            .then(self.1.cmp(&other.1))
+            .then(self.2.page_id.cmp(&other.2.page_id))
+            .then(self.2.slot.cmp(&other.2.slot))
+    }
+}
+
+// A spatial index over a GEOMETRY column: an R-tree (Guttman) on pages of the buffer pool.
+// Each node holds the boxes of the entries below it, so a search only walks down the nodes
+// whose box can hold a match, and a nearest neighbor search the nodes closest to the query.
+// Nodes split in two the quadratic way when full.
+//
+// Like HNSW indexes it is not versioned and holds an entry per version of a row, keyed by
+// the rid and the geometry. Removing an entry shrinks the boxes above it, but nodes left
+// empty stay in the tree until later entries fill them again.
+pub struct RTreeIndex {
+    bpm: Arc<BufferPoolManager>,
+    header_page_id: PageId,
+    // The root page; its lock serializes writers against readers.
+    root: RwLock<PageId>,
+}
+
+impl RTreeIndex {
+    pub fn new(bpm: Arc<BufferPoolManager>) -> CrabDbResult<Self> {
+        let mut header = bpm.new_page_write()?;
+        let header_page_id = header.page_id();
+        let root_page_id = {
+            let mut root = bpm.new_page_write()?;
+            write_node(&mut root, &Node { level: 0, entries: Vec::new() });
+            root.page_id()
+        };
+        header[MAGIC_OFFSET..MAGIC_OFFSET + 4].copy_from_slice(&MAGIC.to_le_bytes());
+        header[ROOT_OFFSET..ROOT_OFFSET + 8].copy_from_slice(&root_page_id.to_le_bytes());
+        drop(header);
+        Ok(RTreeIndex { bpm, header_page_id, root: RwLock::new(root_page_id) })
+    }
+
+    pub fn open(bpm: Arc<BufferPoolManager>, header_page_id: PageId) -> CrabDbResult<Self> {
+        let header = bpm.fetch_page_read(header_page_id)?;
+        if u32::from_le_bytes(header[MAGIC_OFFSET..MAGIC_OFFSET + 4].try_into().unwrap()) != MAGIC {
+            return Err(CrabDBError::Corruption(format!("Page {header_page_id} is not a valid R-tree header")));
+        }
+        let root_page_id = u64::from_le_bytes(header[ROOT_OFFSET..ROOT_OFFSET + 8].try_into().unwrap());
+        drop(header);
+        Ok(RTreeIndex { bpm, header_page_id, root: RwLock::new(root_page_id) })
+    }
+
+    pub fn header_page_id(&self) -> PageId {
+        self.header_page_id
+    }
+
+    fn read_node(&self, page_id: PageId) -> CrabDbResult<Node> {
+        let page = self.bpm.fetch_page_read(page_id)?;
+        let count = u16::from_le_bytes(page[COUNT_OFFSET..COUNT_OFFSET + 2].try_into().unwrap()) as usize;
+        if count > MAX_ENTRIES {
+            return Err(CrabDBError::Corruption(format!("Page {page_id} is not a valid R-tree node")));
+        }
+        let entries = (0..count).map(|i| {
+            let entry = &page[ENTRIES_OFFSET + i * ENTRY_SIZE..ENTRIES_OFFSET + (i + 1) * ENTRY_SIZE];
+            let mut coordinates = [0.0; 4];
+            for (coordinate, bytes) in coordinates.iter_mut().zip(entry[..32].chunks_exact(8)) {
+                *coordinate = f64::from_le_bytes(bytes.try_into().unwrap());
+            }
+            Entry {
+                bounds: Geometry::from_coordinates(coordinates),
+                page_id: u64::from_le_bytes(entry[32..40].try_into().unwrap()),
+                slot: u16::from_le_bytes(entry[40..42].try_into().unwrap()),
+            }
+        }).collect();
+        Ok(Node { level: page[LEVEL_OFFSET], entries })
+    }
+
+    fn write_node(&self, page_id: PageId, node: &Node) -> CrabDbResult<()> {
+        let mut page = self.bpm.fetch_page_write(page_id)?;
+        write_node(&mut page, node);
+        Ok(())
+    }
+
+    fn new_node(&self, node: &Node) -> CrabDbResult<PageId> {
+        let mut page = self.bpm.new_page_write()?;
+        write_node(&mut page, node);
+        Ok(page.page_id())
+    }
+
+    // Adds a leaf entry below the node at `page_id`. Returns the node's new box, and the entry
+    // of the node split off it if it overflowed.
+    fn insert_into(&self, page_id: PageId, entry: Entry) -> CrabDbResult<(Geometry, Option<Entry>)> {
+        let mut node = self.read_node(page_id)?;
+        if node.level == 0 {
+            node.entries.push(entry);
+        } else {
+            // The child whose box grows least, then the smallest.
+            let (i, child) = node.entries.iter().enumerate()
+                .min_by(|(_, a), (_, b)| {
+                    enlargement(&a.bounds, &entry.bounds).total_cmp(&enlargement(&b.bounds, &entry.bounds))
+                        .then(a.bounds.area().total_cmp(&b.bounds.area()))
+                })
+                .map(|(i, child)| (i, child.page_id))
+                .expect("nodes above the leaves are never empty");
+            let (bounds, split) = self.insert_into(child, entry)?;
+            node.entries[i].bounds = bounds;
+            node.entries.extend(split);
+        }
+        if node.entries.len() <= MAX_ENTRIES {
+            self.write_node(page_id, &node)?;
+            return Ok((bounds_of(&node.entries), None));
+        }
+        let (kept, moved) = split(node.entries);
+        let sibling = Node { level: node.level, entries: moved };
+        let sibling = Entry { bounds: bounds_of(&sibling.entries), page_id: self.new_node(&sibling)?, slot: 0 };
+        let bounds = bounds_of(&kept);
+        self.write_node(page_id, &Node { level: node.level, entries: kept })?;
+        Ok((bounds, Some(sibling)))
+    }
+
+    // Takes a leaf entry out from below the node at `page_id`. Returns None if it is not
+    // there, and otherwise the node's new box, if it has any entries left.
+    fn remove_from(&self, page_id: PageId, entry: &Entry) -> CrabDbResult<Option<Option<Geometry>>> {
+        let mut node = self.read_node(page_id)?;
+        if node.level == 0 {
+            let Some(i) = node.entries.iter().position(|candidate| candidate == entry) else {
+                return Ok(None);
+            };
+            node.entries.remove(i);
+        } else {
+            let mut removed = false;
+            for i in 0..node.entries.len() {
+                if !node.entries[i].bounds.contains(&entry.bounds) {
+                    continue;
+                }
+                if let Some(bounds) = self.remove_from(node.entries[i].page_id, entry)? {
+                    if let Some(bounds) = bounds {
+                        node.entries[i].bounds = bounds;
+                    }
+                    removed = true;
+                    break;
+                }
+            }
+            if !removed {
+                return Ok(None);
+            }
+        }
+        self.write_node(page_id, &node)?;
+        Ok(Some((!node.entries.is_empty()).then(|| bounds_of(&node.entries))))
+    }
+
+    fn contains(&self, root: PageId, entry: &Entry) -> CrabDbResult<bool> {
+        let mut pages = vec![root];
+        while let Some(page_id) = pages.pop() {
+            let node = self.read_node(page_id)?;
+            if node.level == 0 {
+                if node.entries.contains(entry) {
+                    return Ok(true);
+                }
+                continue;
+            }
+            pages.extend(node.entries.iter().filter(|child| child.bounds.contains(&entry.bounds)).map(|child| child.page_id));
+        }
+        Ok(false)
+    }
+
+    // Every leaf entry whose box satisfies `query`, visited depth first.
+    fn entries(&self, query: Option<&SpatialQuery>) -> CrabDbResult<Vec<Entry>> {
+        let root = self.root.read().unwrap();
+        let mut pages = vec![*root];
+        let mut entries = Vec::new();
+        while let Some(page_id) = pages.pop() {
+            let node = self.read_node(page_id)?;
+            if node.level == 0 {
+                entries.extend(node.entries.into_iter().filter(|entry| query.is_none_or(|query| query.matches(&entry.bounds))));
+                continue;
+            }
+            pages.extend(node.entries.iter()
+                .filter(|child| query.is_none_or(|query| query.may_hold(&child.bounds)))
+                .map(|child| child.page_id));
+        }
+        Ok(entries)
+    }
+
+    // Rows with an entry satisfying `query`, in rid order and each once.
+    pub fn search(&self, query: &SpatialQuery) -> CrabDbResult<Vec<Rid>> {
+        let mut rids: Vec<_> = self.entries(Some(query))?.iter().map(Entry::rid).collect();
+        rids.sort();
+        rids.dedup();
+        Ok(rids)
+    }
+
+    // The share of entries `query` matches, judged from the root alone so it costs a single
+    // page: a root that is a leaf is counted exactly, and otherwise each child the query may
+    // reach is taken to hold an even share of the entries, of which it matches as much as
+    // the query overlaps the child's box.
+    pub fn selectivity(&self, query: &SpatialQuery) -> CrabDbResult<f64> {
+        let root = self.read_node(*self.root.read().unwrap())?;
+        if root.entries.is_empty() {
+            return Ok(0.0);
+        }
+        let matched: f64 = root.entries.iter()
+            .map(|entry| match root.level {
+                0 => f64::from(u8::from(query.matches(&entry.bounds))),
+                _ if query.may_hold(&entry.bounds) => query.overlap(&entry.bounds),
+                _ => 0.0,
+            })
+            .sum();
+        Ok(matched / root.entries.len() as f64)
+    }
+
+    // The `k` rows whose geometry is closest to `query`, closest first. Unlike HNSW the
+    // search is exact: nodes are visited closest first, and a row is only returned once
+    // nothing left to visit can be closer.
+    pub fn nearest(&self, query: &Geometry, k: usize) -> CrabDbResult<Vec<Rid>> {
+        let root = self.root.read().unwrap();
+        let mut rids = Vec::new();
+        let mut candidates = BinaryHeap::new();
+        let root_node = self.read_node(*root)?;
+        if root_node.entries.is_empty() {
+            return Ok(rids);
+        }
+        candidates.push(Reverse(Candidate(0.0, 1, Entry { bounds: bounds_of(&root_node.entries), page_id: *root, slot: 0 })));
+        while let Some(Reverse(Candidate(_, is_node, entry))) = candidates.pop() {
+            if rids.len() == k {
+                break;
+            }
+            if is_node == 0 {
+                // A row changed in place has an entry per geometry it had.
+                if !rids.contains(&entry.rid()) {
+                    rids.push(entry.rid());
+                }
+                continue;
+            }
+            let node = self.read_node(entry.page_id)?;
+            let is_node = u8::from(node.level > 0);
+            for child in node.entries {
+                candidates.push(Reverse(Candidate(query.distance(&child.bounds), is_node, child)));
+            }
+        }
+        Ok(rids)
+    }
+
+    fn check_key(&self, key: &[u8]) -> CrabDbResult<Entry> {
+        if key.len() != RID_SIZE + 32 {
+            return Err(CrabDBError::InvalidInput(format!("Key must be {} bytes, got {}", RID_SIZE + 32, key.len())));
+        }
+        Ok(entry_of(key))
+    }
+}
+
+impl Index for RTreeIndex {
+    fn kind(&self) -> IndexKind {
+        IndexKind::RTree
+    }
+
+    fn key_size(&self) -> usize {
+        RID_SIZE + 32
+    }
+
+    fn get(&self, key: &[u8]) -> CrabDbResult<Option<Rid>> {
+        let entry = self.check_key(key)?;
+        let root = self.root.read().unwrap();
+        Ok(self.contains(*root, &entry)?.then(|| entry.rid()))
+    }
+
+    fn insert(&self, key: &[u8], _rid: Rid) -> CrabDbResult<bool> {
+        let entry = self.check_key(key)?;
+        let mut root = self.root.write().unwrap();
+        if self.contains(*root, &entry)? {
+            return Ok(false);
+        }
+        let (bounds, split) = self.insert_into(*root, entry)?;
+        if let Some(sibling) = split {
+            // The root split, so the tree grows a level.
+            let level = self.read_node(*root)?.level + 1;
+            let old_root = Entry { bounds, page_id: *root, slot: 0 };
+            *root = self.new_node(&Node { level, entries: vec![old_root, sibling] })?;
+            let mut header = self.bpm.fetch_page_write(self.header_page_id)?;
+            header[ROOT_OFFSET..ROOT_OFFSET + 8].copy_from_slice(&root.to_le_bytes());
+        }
+        Ok(true)
+    }
+
+    // The root stays where it is, but the nodes below change, so writers still go one at a
+    // time.
+    #[allow(clippy::readonly_write_lock)]
+    fn remove(&self, key: &[u8]) -> CrabDbResult<bool> {
+        let entry = self.check_key(key)?;
+        let root = self.root.write().unwrap();
+        Ok(self.remove_from(*root, &entry)?.is_some())
+    }
+
+    // Every entry, which is what vacuum looks through. The tree orders entries by where they
+    // are, not by key, so they are sorted here.
+    fn range<'a>(&'a self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> CrabDbResult<IndexIterator<'a>> {
+        let mut keys: Vec<_> = self.entries(None)?.iter()
+            .map(Entry::key)
+            .filter(|key| RangeBounds::<[u8]>::contains(&(start, end), key.as_slice()))
+            .collect();
+        keys.sort();
+        Ok(Box::new(keys.into_iter().map(|key| {
+            let rid = entry_of(&key).rid();
+            Ok((key, rid))
+        })))
+    }
+}
+
+fn write_node(page: &mut [u8; PAGE_SIZE], node: &Node) {
+    page[LEVEL_OFFSET] = node.level;
+    page[COUNT_OFFSET..COUNT_OFFSET + 2].copy_from_slice(&(node.entries.len() as u16).to_le_bytes());
+    for (i, entry) in node.entries.iter().enumerate() {
+        let start = ENTRIES_OFFSET + i * ENTRY_SIZE;
+        for (j, coordinate) in entry.bounds.coordinates().iter().enumerate() {
+            page[start + 8 * j..start + 8 * (j + 1)].copy_from_slice(&coordinate.to_le_bytes());
+        }
+        page[start + 32..start + 40].copy_from_slice(&entry.page_id.to_le_bytes());
+        page[start + 40..start + 42].copy_from_slice(&entry.slot.to_le_bytes());
+    }
+}
+
+// Guttman's quadratic split: the two entries that would waste the most area together seed
+// the halves, then each entry left goes where it grows the box least, the one with the
+// strongest preference first, until one half needs all the rest to reach MIN_ENTRIES.
+fn split(mut entries: Vec<Entry>) -> (Vec<Entry>, Vec<Entry>) {
+    let mut seeds = (0, 1);
+    let mut worst = f64::NEG_INFINITY;
+    for i in 0..entries.len() {
+        for j in i + 1..entries.len() {
+            let waste = entries[i].bounds.union(&entries[j].bounds).area() - entries[i].bounds.area() - entries[j].bounds.area();
+            if waste > worst {
+                (worst, seeds) = (waste, (i, j));
+            }
+        }
+    }
+    let second = entries.swap_remove(seeds.1);
+    let first = entries.swap_remove(seeds.0);
+    let (mut a, mut b) = (vec![first], vec![second]);
+    let (mut a_bounds, mut b_bounds) = (first.bounds, second.bounds);
+    while !entries.is_empty() {
+        if a.len() + entries.len() == MIN_ENTRIES {
+            a.append(&mut entries);
+            break;
+        }
+        if b.len() + entries.len() == MIN_ENTRIES {
+            b.append(&mut entries);
+            break;
+        }
+        let growth = |entry: &Entry| (enlargement(&a_bounds, &entry.bounds), enlargement(&b_bounds, &entry.bounds));
+        let (i, _) = entries.iter().enumerate()
+            .map(|(i, entry)| {
+                let (to_a, to_b) = growth(entry);
+                (i, (to_a - to_b).abs())
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .expect("entries is not empty");
+        let entry = entries.swap_remove(i);
+        let (to_a, to_b) = growth(&entry);
+        let to_a = match to_a.total_cmp(&to_b) {
+            Ordering::Equal => (a_bounds.area(), a.len()) <= (b_bounds.area(), b.len()),
+            ordering => ordering == Ordering::Less,
+        };
+        if to_a {
+            a_bounds = a_bounds.union(&entry.bounds);
+            a.push(entry);
+        } else {
+            b_bounds = b_bounds.union(&entry.bounds);
+            b.push(entry);
+        }
+    }
+    (a, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+    use std::sync::Arc;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::index::table_index::Index;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use crate::storage::table::tuple::Rid;
+    use crate::types::geometry::Geometry;
+    use super::{bounds_of, key, Entry, RTreeIndex, SpatialQuery, MAX_ENTRIES, MIN_ENTRIES};
+
+    fn buffer_pool() -> Arc<BufferPoolManager> {
+        // A small pool, so searches read nodes back from disk.
+        Arc::new(BufferPoolManager::new(8, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(8, 2))))
+    }
+
+    // Checks that the entry above every node holds the box of what the node holds, that the
+    // levels step down by one to the leaves and, unless entries were removed, that splits
+    // left no node but the root under-full. Returns the root's level and the leaf entries.
+    fn check_tree(index: &RTreeIndex, removed: bool) -> (u8, Vec<Entry>) {
+        let root = *index.root.read().unwrap();
+        let root_level = index.read_node(root).unwrap().level;
+        let mut leaves = Vec::new();
+        let mut nodes = vec![(root, root_level, None)];
+        while let Some((page_id, level, bounds)) = nodes.pop() {
+            let node = index.read_node(page_id).unwrap();
+            assert_eq!(level, node.level);
+            assert!(node.entries.len() <= MAX_ENTRIES);
+            if let Some(bounds) = bounds {
+                assert!(removed || node.entries.len() >= MIN_ENTRIES, "page {page_id} has {} entries", node.entries.len());
+                // Nodes emptied by removals keep their old box above them.
+                if !node.entries.is_empty() {
+                    assert_eq!(bounds, bounds_of(&node.entries));
+                }
+            }
+            match node.level {
+                0 => leaves.extend(node.entries),
+                _ => nodes.extend(node.entries.iter().map(|child| (child.page_id, level - 1, Some(child.bounds)))),
+            }
+        }
+        (root_level, leaves)
+    }
+
+    #[test]
+    pub fn test_rtree_index_splits_nodes_past_their_fan_out() {
+        let bpm = buffer_pool();
+        let index = RTreeIndex::new(bpm.clone()).unwrap();
+        assert!(index.nearest(&Geometry::point(0.0, 0.0), 3).unwrap().is_empty());
+        assert!(index.get(&[0; 4]).is_err());
+
+        // Points on a 60 x 60 grid, in an order that is not the grid's.
+        let point = |i: u64| Geometry::point(((i * 7) % 60) as f64, ((i * 7) / 60 % 60) as f64);
+        let rid = |i: u64| Rid::new(i / 100, (i % 100) as u16);
+        let insert = |i: u64| assert!(index.insert(&key(&point(i), rid(i)), rid(i)).unwrap());
+
+        // A full root is still a leaf; one entry more splits it in two under a new root.
+        (0..MAX_ENTRIES as u64).for_each(insert);
+        assert_eq!(0, check_tree(&index, false).0);
+        insert(MAX_ENTRIES as u64);
+        let (level, leaves) = check_tree(&index, false);
+        assert_eq!((1, MAX_ENTRIES + 1), (level, leaves.len()));
+        assert_eq!(2, index.read_node(*index.root.read().unwrap()).unwrap().entries.len());
+        assert!(!index.insert(&key(&point(0), rid(0)), rid(0)).unwrap());
+
+        // The leaves go on splitting under the root, and every entry is still found.
+        (MAX_ENTRIES as u64 + 1..3600).for_each(insert);
+        let (level, leaves) = check_tree(&index, false);
+        assert_eq!(1, level);
+        assert_eq!(3600, leaves.len());
+        assert!(index.read_node(*index.root.read().unwrap()).unwrap().entries.len() >= 3600 / MAX_ENTRIES);
+        let window = Geometry::bbox(9.5, 9.5, 14.0, 11.0);
+        let mut inside: Vec<_> = (0..3600).filter(|&i| window.contains(&point(i))).map(rid).collect();
+        inside.sort();
+        assert_eq!(inside, index.search(&SpatialQuery::Intersects(window)).unwrap());
+        assert_eq!(5 * 2, inside.len());
+
+        // Nearest neighbors come back exactly, closest first, and the same after reopening.
+        let query = Geometry::point(30.2, 41.4);
+        let mut all: Vec<_> = (0..3600).map(|i| (query.distance(&point(i)), rid(i))).collect();
+        all.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        let nearest = index.nearest(&query, 5).unwrap();
+        assert_eq!(all[..5].iter().map(|(_, rid)| *rid).collect::<Vec<_>>(), nearest);
+        let reopened = RTreeIndex::open(bpm.clone(), index.header_page_id()).unwrap();
+        assert_eq!(nearest, reopened.nearest(&query, 5).unwrap());
+        assert_eq!(3600, reopened.range(Bound::Unbounded, Bound::Unbounded).unwrap().count());
+        assert!(RTreeIndex::open(bpm, index.header_page_id() + 1).is_err());
+    }
+
+    #[test]
+    pub fn test_rtree_index_removes_entries_and_shrinks_boxes() {
+        let index = RTreeIndex::new(buffer_pool()).unwrap();
+        let point = |i: u64| Geometry::point((i % 40) as f64, (i / 40) as f64);
+        let rid = |i: u64| Rid::new(1, i as u16);
+        for i in 0..1600 {
+            index.insert(&key(&point(i), rid(i)), rid(i)).unwrap();
+        }
+        let root_bounds = || bounds_of(&index.read_node(*index.root.read().unwrap()).unwrap().entries);
+        assert_eq!(Geometry::bbox(0.0, 0.0, 39.0, 39.0), root_bounds());
+
+        // Only the very entry goes: the same rid with another geometry is not there.
+        assert!(!index.remove(&key(&Geometry::point(0.5, 0.0), rid(0))).unwrap());
+        assert!(index.remove(&key(&point(0), rid(0))).unwrap());
+        assert!(!index.remove(&key(&point(0), rid(0))).unwrap());
+        assert_eq!(None, index.get(&key(&point(0), rid(0))).unwrap());
+        assert_eq!(Some(rid(1)), index.get(&key(&point(1), rid(1))).unwrap());
+        assert_eq!(vec![rid(1)], index.nearest(&Geometry::point(0.0, 0.0), 1).unwrap());
+
+        // Taking out the top row pulls the boxes of the nodes that held it down with it.
+        for i in 1560..1600 {
+            assert!(index.remove(&key(&point(i), rid(i))).unwrap());
+        }
+        let (_, leaves) = check_tree(&index, true);
+        assert_eq!(1600 - 41, leaves.len());
+        assert!(leaves.iter().all(|leaf| leaf.bounds.max[1] <= 38.0));
+        assert!(index.search(&SpatialQuery::Intersects(Geometry::bbox(0.0, 38.5, 39.0, 40.0))).unwrap().is_empty());
+
+        // A tree emptied out takes entries again.
+        for i in 1..1560 {
+            assert!(index.remove(&key(&point(i), rid(i))).unwrap());
+        }
+        assert!(index.search(&SpatialQuery::Intersects(Geometry::bbox(-1.0, -1.0, 40.0, 40.0))).unwrap().is_empty());
+        assert_eq!(0, index.range(Bound::Unbounded, Bound::Unbounded).unwrap().count());
+        assert!(index.insert(&key(&point(7), rid(7)), rid(7)).unwrap());
+        assert_eq!(vec![rid(7)], index.nearest(&Geometry::point(0.0, 0.0), 3).unwrap());
+    }
+
+    #[test]
+    pub fn test_rtree_index_within_and_intersects_on_shared_boundaries() {
+        let index = RTreeIndex::new(buffer_pool()).unwrap();
+        let window = Geometry::bbox(0.0, 0.0, 10.0, 10.0);
+        let geometries = [
+            // on the window's edge, at its corner, and a box along its edge
+            Geometry::point(10.0, 5.0),
+            Geometry::point(0.0, 0.0),
+            Geometry::bbox(0.0, 2.0, 10.0, 3.0),
+            // sharing only an edge or a corner from outside
+            Geometry::bbox(10.0, 4.0, 12.0, 6.0),
+            Geometry::bbox(-2.0, -2.0, 0.0, 0.0),
+            // crossing the edge, and just clear of it
+            Geometry::bbox(9.0, 9.0, 11.0, 11.0),
+            Geometry::point(10.0 + f64::EPSILON * 16.0, 5.0),
+            // the window itself
+            window,
+        ];
+        for (i, geometry) in geometries.iter().enumerate() {
+            let rid = Rid::new(1, i as u16);
+            index.insert(&key(geometry, rid), rid).unwrap();
+        }
+        let slots = |query: SpatialQuery| index.search(&query).unwrap().iter().map(|rid| rid.slot_id()).collect::<Vec<_>>();
+
+        // The boundary belongs to the window: what lies on it is within, but what only touches
+        // it from outside, or crosses it, merely intersects.
+        assert_eq!(vec![0, 1, 2, 7], slots(SpatialQuery::Within(window)));
+        assert_eq!(vec![0, 1, 2, 3, 4, 5, 7], slots(SpatialQuery::Intersects(window)));
+        // Rows holding a point on the window's edge include the window and what meets it there.
+        assert_eq!(vec![0, 3, 7], slots(SpatialQuery::Contains(Geometry::point(10.0, 5.0))));
+        assert_eq!(vec![1, 4, 7], slots(SpatialQuery::Intersects(Geometry::point(0.0, 0.0))));
+        assert_eq!(vec![1, 4, 7], slots(SpatialQuery::Contains(Geometry::point(0.0, 0.0))));
+        assert_eq!(vec![1, 4], slots(SpatialQuery::Within(Geometry::bbox(-2.0, -2.0, 0.0, 0.0))));
+    }
+
+    #[test]
+    pub fn test_rtree_selectivity_is_judged_from_the_root() {
+        let bpm = Arc::new(BufferPoolManager::new(8, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(8, 2))));
+        let index = RTreeIndex::new(bpm).unwrap();
+        let everything = SpatialQuery::Intersects(Geometry::bbox(-1.0, -1.0, 101.0, 101.0));
+        assert_eq!(0.0, index.selectivity(&everything).unwrap());
+
+        // While the root is a leaf, its entries are counted exactly.
+        let point = |i: u64| Geometry::point((i % 100) as f64, (i / 100) as f64);
+        let rid = |i: u64| Rid::new(i / 100, (i % 100) as u16);
+        for i in 0..50 {
+            index.insert(&key(&point(i), rid(i)), rid(i)).unwrap();
+        }
+        assert_eq!(0.2, index.selectivity(&SpatialQuery::Within(Geometry::bbox(0.0, 0.0, 9.5, 1.0))).unwrap());
+
+        // Above that, the estimate follows how much of the points a box covers.
+        for i in 50..10_000 {
+            index.insert(&key(&point(i), rid(i)), rid(i)).unwrap();
+        }
+        assert!(index.selectivity(&everything).unwrap() > 0.99);
+        let quarter = index.selectivity(&SpatialQuery::Intersects(Geometry::bbox(0.0, 0.0, 49.5, 49.5))).unwrap();
+        assert!((0.15..0.4).contains(&quarter), "{quarter}");
+        let one = index.selectivity(&SpatialQuery::Contains(Geometry::point(12.0, 34.0))).unwrap();
+        assert!(one < 0.01, "{one}");
+        assert_eq!(0.0, index.selectivity(&SpatialQuery::Intersects(Geometry::point(200.0, 200.0))).unwrap());
+    }
+}
@@ -0,0 +1,75 @@
+use std::any::Any;
+use std::fmt::Display;
+use std::ops::Bound;
+
+use crate::storage::table::tuple::Rid;
+use crate::types::{CrabDBError, CrabDbResult};
+
+pub type IndexIterator<'a> = Box<dyn Iterator<Item = CrabDbResult<(Vec<u8>, Rid)>> + 'a>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexKind {
+    BPlusTree,
+    Hash,
+    FullText,
+    Hnsw,
+    RTree,
+}
+
+impl IndexKind {
+    pub(crate) fn tag(&self) -> u8 {
+        match self {
+            IndexKind::BPlusTree => 1,
+            IndexKind::Hash => 2,
+            IndexKind::FullText => 3,
+            IndexKind::Hnsw => 4,
+            IndexKind::RTree => 5,
+        }
+    }
+
+    pub(crate) fn from_tag(tag: u8) -> Option<IndexKind> {
+        match tag {
+            1 => Some(IndexKind::BPlusTree),
+            2 => Some(IndexKind::Hash),
+            3 => Some(IndexKind::FullText),
+            4 => Some(IndexKind::Hnsw),
+            5 => Some(IndexKind::RTree),
+            _ => None,
+        }
+    }
+
+    // Whether each row has a single key, which no other row may share. Full-text, HNSW and
+    // R-tree indexes hold entries keyed by the row instead (see `IndexInfo::entries_for_row`).
+    pub fn is_unique(&self) -> bool {
+        !matches!(self, IndexKind::FullText | IndexKind::Hnsw | IndexKind::RTree)
+    }
+}
+
+impl Display for IndexKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IndexKind::BPlusTree => write!(f, "B+ tree"),
+            IndexKind::Hash => write!(f, "hash"),
+            IndexKind::FullText => write!(f, "full-text"),
+            IndexKind::Hnsw => write!(f, "HNSW"),
+            IndexKind::RTree => write!(f, "R-tree"),
+        }
+    }
+}
+
+// What the catalog and executors need from an index, whatever its structure. Keys are
+// fixed-size byte strings (see `index_key`) and each key maps to a single record. Indexes
+// with more to offer, like full-text, HNSW and R-tree ones, are reached through `Any`.
+pub trait Index: Any + Send + Sync {
+    fn kind(&self) -> IndexKind;
+    fn key_size(&self) -> usize;
+    fn get(&self, key: &[u8]) -> CrabDbResult<Option<Rid>>;
+    // Returns false, leaving the index untouched, if the key is already present.
+    fn insert(&self, key: &[u8], rid: Rid) -> CrabDbResult<bool>;
+    // Returns false if the key was not present.
+    fn remove(&self, key: &[u8]) -> CrabDbResult<bool>;
+    // Entries with keys in the given bounds, in key order. Only ordered indexes support it.
+    fn range<'a>(&'a self, _start: Bound<&[u8]>, _end: Bound<&[u8]>) -> CrabDbResult<IndexIterator<'a>> {
+        Err(CrabDBError::InvalidInput(format!("{} indexes do not support range scans", self.kind())))
+    }
+}
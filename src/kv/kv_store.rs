@@ -0,0 +1,434 @@
+use std::ops::{Bound, RangeBounds};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::catalog::table_info::KvStoreInfo;
+use crate::concurrency::transaction::{IsolationLevel, Transaction, TransactionState, TXN_TS_FLAG};
+use crate::concurrency::transaction_manager::TransactionManager;
+use crate::platform;
+use crate::storage::table::overflow::MAX_OVERFLOW_TUPLE_SIZE;
+use crate::storage::table::tuple::{Rid, Tuple};
+use crate::types::{CrabDBError, CrabDbResult};
+
+// Longest key a store takes.
+pub const KV_MAX_KEY_SIZE: usize = 128;
+// Size of the B+ tree keys: the key zero padded to KV_MAX_KEY_SIZE, then its length as a
+// big endian u16, so the bytes compare the way the keys do.
+pub const KV_TREE_KEY_SIZE: usize = KV_MAX_KEY_SIZE + 2;
+
+const ANCHOR_TAG: u8 = 1;
+const VALUE_TAG: u8 = 2;
+// tag u8 | expires_at u64, milliseconds since the Unix epoch | value
+const EXPIRING_VALUE_TAG: u8 = 3;
+// tag u8 | has_value u8 | value page_id u64 | value slot_id u16
+const ANCHOR_HEADER_SIZE: usize = 12;
+
+pub(crate) fn now_ms() -> u64 {
+    platform::clock().now_micros() / 1_000
+}
+
+fn tree_key(key: &[u8]) -> CrabDbResult<Vec<u8>> {
+    if key.len() > KV_MAX_KEY_SIZE {
+        return Err(CrabDBError::InvalidInput(format!(
+            "Key of {} bytes is longer than the {KV_MAX_KEY_SIZE} bytes a key-value store allows", key.len()
+        )));
+    }
+    let mut tree_key = key.to_vec();
+    tree_key.resize(KV_MAX_KEY_SIZE, 0);
+    tree_key.extend_from_slice(&(key.len() as u16).to_be_bytes());
+    Ok(tree_key)
+}
+
+// Every key has an anchor tuple that the B+ tree points at and that in turn points at the
+// tuple holding the key's current value, or at nothing once the key is deleted. Anchors
+// never change size, so their versions are always updated in place and a key's tree entry
+// stays valid for as long as its anchor lives.
+struct Anchor {
+    key: Vec<u8>,
+    value: Option<Rid>,
+}
+
+impl Anchor {
+    fn serialize(&self) -> Tuple {
+        let mut data = Vec::with_capacity(ANCHOR_HEADER_SIZE + self.key.len());
+        data.push(ANCHOR_TAG);
+        data.push(self.value.is_some() as u8);
+        let value = self.value.unwrap_or(Rid::new(0, 0));
+        data.extend_from_slice(&value.page_id().to_le_bytes());
+        data.extend_from_slice(&value.slot_id().to_le_bytes());
+        data.extend_from_slice(&self.key);
+        Tuple::new(data)
+    }
+
+    // None if the tuple is not an anchor, which happens when the slot a stale tree entry
+    // points at was reused.
+    fn deserialize(tuple: &Tuple) -> Option<Anchor> {
+        let data = tuple.data();
+        if data.len() < ANCHOR_HEADER_SIZE || data[0] != ANCHOR_TAG {
+            return None;
+        }
+        let page_id = u64::from_le_bytes(data[2..10].try_into().unwrap());
+        let slot_id = u16::from_le_bytes(data[10..12].try_into().unwrap());
+        let value = (data[1] != 0).then(|| Rid::new(page_id, slot_id));
+        Some(Anchor { key: data[ANCHOR_HEADER_SIZE..].to_vec(), value })
+    }
+}
+
+// Transactional key-value access to one of the catalog's key-value stores. Keys and values
+// are byte strings; reads see the transaction's snapshot and writes conflict the way row
+// writes do. Deleted keys keep their anchor, so a store never shrinks below one small tuple
+// per key it ever held. A key put with a TTL reads as missing once it expires, whatever
+// the snapshot; vacuum deletes expired keys for good (see `remove_expired`).
+pub struct KvStore {
+    info: Arc<KvStoreInfo>,
+    txn_manager: Arc<TransactionManager>,
+}
+
+impl KvStore {
+    pub fn new(info: Arc<KvStoreInfo>, txn_manager: Arc<TransactionManager>) -> Self {
+        KvStore { info, txn_manager }
+    }
+
+    pub fn name(&self) -> &str {
+        self.info.name()
+    }
+
+    pub fn get(&self, txn: &Transaction, key: &[u8]) -> CrabDbResult<Option<Vec<u8>>> {
+        Ok(self.get_with_expiry(txn, key)?.map(|(value, _)| value))
+    }
+
+    // The value of `key` and when it expires, in milliseconds since the Unix epoch, if it
+    // was put with a TTL.
+    pub fn get_with_expiry(&self, txn: &Transaction, key: &[u8]) -> CrabDbResult<Option<(Vec<u8>, Option<u64>)>> {
+        match self.anchor(txn, key)? {
+            Some((_, Anchor { value: Some(value), .. })) => self.value(txn, value, now_ms()),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn put(&self, txn: &Transaction, key: &[u8], value: &[u8]) -> CrabDbResult<()> {
+        self.put_entry(txn, key, value, None)
+    }
+
+    // Puts `value` under `key` until `ttl` from now, when the key expires.
+    pub fn put_with_ttl(&self, txn: &Transaction, key: &[u8], value: &[u8], ttl: Duration) -> CrabDbResult<()> {
+        if ttl.is_zero() {
+            return Err(CrabDBError::InvalidInput(format!("Key {key:?} needs a TTL above zero")));
+        }
+        let expires_at = now_ms().saturating_add(ttl.as_millis().max(1) as u64);
+        self.put_entry(txn, key, value, Some(expires_at))
+    }
+
+    // Makes `key` expire `ttl` from now, keeping its value. Returns whether the key was there.
+    pub fn expire(&self, txn: &Transaction, key: &[u8], ttl: Duration) -> CrabDbResult<bool> {
+        match self.get_with_expiry(txn, key)? {
+            Some((value, _)) => self.put_with_ttl(txn, key, &value, ttl).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    fn put_entry(&self, txn: &Transaction, key: &[u8], value: &[u8], expires_at: Option<u64>) -> CrabDbResult<()> {
+        let mut data = Vec::with_capacity(9 + value.len());
+        match expires_at {
+            Some(expires_at) => {
+                data.push(EXPIRING_VALUE_TAG);
+                data.extend_from_slice(&expires_at.to_le_bytes());
+            },
+            None => data.push(VALUE_TAG),
+        }
+        data.extend_from_slice(value);
+        if data.len() > MAX_OVERFLOW_TUPLE_SIZE {
+            return Err(CrabDBError::InvalidInput(format!(
+                "Value of {} bytes is too big for a key-value store", value.len()
+            )));
+        }
+        let tree_key = tree_key(key)?;
+        let heap = self.info.heap();
+        match self.anchor(txn, key)? {
+            Some((anchor_rid, anchor)) => {
+                let new_value = self.txn_manager.insert(txn, heap, &Tuple::new(data))?;
+                let new_anchor = Anchor { key: key.to_vec(), value: Some(new_value) };
+                self.txn_manager.update(txn, heap, anchor_rid, &new_anchor.serialize())?;
+                if let Some(old_value) = anchor.value {
+                    self.txn_manager.delete(txn, heap, old_value)?;
+                }
+            },
+            None => {
+                let value = self.txn_manager.insert(txn, heap, &Tuple::new(data))?;
+                let anchor = Anchor { key: key.to_vec(), value: Some(value) };
+                let anchor_rid = self.txn_manager.insert(txn, heap, &anchor.serialize())?;
+                self.point_tree_at(txn, &tree_key, key, anchor_rid)?;
+            },
+        }
+        Ok(())
+    }
+
+    // Returns whether the key was there. An expired key is deleted too, but was not there.
+    pub fn delete(&self, txn: &Transaction, key: &[u8]) -> CrabDbResult<bool> {
+        let Some((anchor_rid, Anchor { value: Some(value), .. })) = self.anchor(txn, key)? else {
+            return Ok(false);
+        };
+        let live = self.value(txn, value, now_ms())?.is_some();
+        let heap = self.info.heap();
+        let tombstone = Anchor { key: key.to_vec(), value: None };
+        self.txn_manager.update(txn, heap, anchor_rid, &tombstone.serialize())?;
+        self.txn_manager.delete(txn, heap, value)?;
+        Ok(live)
+    }
+
+    // Deletes the keys that have expired, each in a transaction of its own so a sweep never
+    // holds up writers for long; a key written meanwhile is left to the next sweep. The
+    // values end up deleted tuples, which vacuum reclaims like any other. Returns how many
+    // keys were deleted.
+    pub fn remove_expired(&self) -> CrabDbResult<usize> {
+        let now = now_ms();
+        let expired = {
+            let txn = self.txn_manager.begin(IsolationLevel::SnapshotIsolation);
+            let expired = self.expired_keys(&txn, now);
+            self.txn_manager.commit(&txn)?;
+            expired?
+        };
+        let mut removed = 0;
+        for key in expired {
+            let txn = self.txn_manager.begin(IsolationLevel::SnapshotIsolation);
+            let remove = || {
+                // The key may have been put again since it was found expired.
+                let still_expired = match self.anchor(&txn, &key)? {
+                    Some((_, Anchor { value: Some(value), .. })) => self.value(&txn, value, now)?.is_none(),
+                    _ => false,
+                };
+                if still_expired {
+                    self.delete(&txn, &key)?;
+                }
+                Ok(still_expired)
+            };
+            match remove().and_then(|removed| self.txn_manager.commit(&txn).map(|_| removed)) {
+                Ok(removed_key) => removed += removed_key as usize,
+                Err(e) => {
+                    let _ = self.txn_manager.abort(&txn);
+                    // A key written meanwhile is left to the next sweep; anything else fails it.
+                    if !e.is_retryable() {
+                        return Err(e);
+                    }
+                },
+            }
+        }
+        Ok(removed)
+    }
+
+    fn expired_keys(&self, txn: &Transaction, now: u64) -> CrabDbResult<Vec<Vec<u8>>> {
+        let mut expired = Vec::new();
+        for entry in self.info.tree().range(Bound::Unbounded, Bound::Unbounded) {
+            let (tree_key, anchor_rid) = entry?;
+            let len = u16::from_be_bytes(tree_key[KV_MAX_KEY_SIZE..].try_into().unwrap()) as usize;
+            if let Some(Anchor { key, value: Some(value) }) = self.visible_anchor(txn, &tree_key[..len], anchor_rid)? {
+                if self.value(txn, value, now)?.is_none() {
+                    expired.push(key);
+                }
+            }
+        }
+        Ok(expired)
+    }
+
+    // The entries with keys in `range`, in key order.
+    pub fn scan<'a, K: AsRef<[u8]>>(
+        &'a self,
+        txn: &'a Transaction,
+        range: impl RangeBounds<K>,
+    ) -> CrabDbResult<impl Iterator<Item = CrabDbResult<(Vec<u8>, Vec<u8>)>> + 'a> {
+        let bound = |bound: Bound<&K>| match bound {
+            Bound::Included(key) => tree_key(key.as_ref()).map(Bound::Included),
+            Bound::Excluded(key) => tree_key(key.as_ref()).map(Bound::Excluded),
+            Bound::Unbounded => Ok(Bound::Unbounded),
+        };
+        let (start, end) = (bound(range.start_bound())?, bound(range.end_bound())?);
+        let entries = self.info.tree().range(start.as_ref().map(Vec::as_slice), end.as_ref().map(Vec::as_slice));
+        let now = now_ms();
+        Ok(entries.filter_map(move |entry| {
+            let read = || {
+                let (tree_key, anchor_rid) = entry?;
+                let len = u16::from_be_bytes(tree_key[KV_MAX_KEY_SIZE..].try_into().unwrap()) as usize;
+                match self.visible_anchor(txn, &tree_key[..len], anchor_rid)? {
+                    Some(Anchor { key, value: Some(value) }) => Ok(self.value(txn, value, now)?.map(|(value, _)| (key, value))),
+                    _ => Ok(None),
+                }
+            };
+            read().transpose()
+        }))
+    }
+
+    // The anchor of `key` visible to `txn`, with its rid.
+    fn anchor(&self, txn: &Transaction, key: &[u8]) -> CrabDbResult<Option<(Rid, Anchor)>> {
+        let Some(anchor_rid) = self.info.tree().get(&tree_key(key)?)? else {
+            return Ok(None);
+        };
+        Ok(self.visible_anchor(txn, key, anchor_rid)?.map(|anchor| (anchor_rid, anchor)))
+    }
+
+    fn visible_anchor(&self, txn: &Transaction, key: &[u8], anchor_rid: Rid) -> CrabDbResult<Option<Anchor>> {
+        let tuple = self.txn_manager.get(txn, self.info.heap(), anchor_rid)?;
+        Ok(tuple.as_ref().and_then(Anchor::deserialize).filter(|anchor| anchor.key == key))
+    }
+
+    // The value at `value_rid` and when it expires, or None if it expired by `now`.
+    fn value(&self, txn: &Transaction, value_rid: Rid, now: u64) -> CrabDbResult<Option<(Vec<u8>, Option<u64>)>> {
+        let tuple = self.txn_manager.get(txn, self.info.heap(), value_rid)?;
+        match tuple.as_ref().map(|tuple| tuple.data()) {
+            Some([VALUE_TAG, value @ ..]) => Ok(Some((value.to_vec(), None))),
+            Some([EXPIRING_VALUE_TAG, expiring @ ..]) if expiring.len() >= 8 => {
+                let (expires_at, value) = expiring.split_at(8);
+                let expires_at = u64::from_le_bytes(expires_at.try_into().unwrap());
+                Ok((expires_at > now).then(|| (value.to_vec(), Some(expires_at))))
+            },
+            _ => Err(CrabDBError::Corruption(format!(
+                "Key-value store {} has an anchor pointing at missing value {value_rid}", self.name()
+            ))),
+        }
+    }
+
+    // Points the tree entry of a key that `txn` sees no anchor for at its new anchor. An
+    // existing entry is stale, and replaced, if its anchor was rolled back; if it belongs to
+    // a transaction that has not committed or committed after `txn` began, the key is being
+    // written concurrently.
+    fn point_tree_at(&self, txn: &Transaction, tree_key: &[u8], key: &[u8], anchor_rid: Rid) -> CrabDbResult<()> {
+        let tree = self.info.tree();
+        if tree.insert(tree_key, anchor_rid)? {
+            return Ok(());
+        }
+        let conflict = || {
+            txn.set_state(TransactionState::Aborted);
+            CrabDBError::Conflict(format!(
+                "Transaction {} conflicts with a concurrent write to key {key:?} of key-value store {}", txn.id(), self.name()
+            ))
+        };
+        let Some(existing) = tree.get(tree_key)? else {
+            return Err(conflict());
+        };
+        let heap = self.info.heap();
+        let live = match heap.tuple_meta(existing) {
+            Ok(meta) if !meta.is_deleted => heap.get_tuple(existing)
+                .map(|tuple| Anchor::deserialize(&tuple).is_some_and(|anchor| anchor.key == key))
+                .map(|is_anchor| is_anchor.then_some(meta.ts))?,
+            _ => None,
+        };
+        if let Some(ts) = live {
+            if ts & TXN_TS_FLAG != 0 || ts > txn.read_ts() {
+                return Err(conflict());
+            }
+            return Err(CrabDBError::new(format!("Key-value store {} lost track of key {key:?}", self.name())));
+        }
+        if !tree.remove(tree_key)? || !tree.insert(tree_key, anchor_rid)? {
+            return Err(conflict());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::catalog::system_catalog::Catalog;
+    use crate::concurrency::lock_manager::LockManager;
+    use crate::concurrency::transaction::IsolationLevel;
+    use crate::concurrency::transaction_manager::TransactionManager;
+    use crate::concurrency::vacuum::Vacuum;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use crate::types::CrabDBError;
+    use super::{KvStore, KV_TREE_KEY_SIZE};
+
+    #[test]
+    pub fn test_kv_store_is_transactional() {
+        let bpm = Arc::new(BufferPoolManager::new(32, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(32, 2))));
+        let catalog = Catalog::open(bpm).unwrap();
+        let txn_manager = Arc::new(TransactionManager::new(Arc::new(LockManager::new())));
+        let kv = KvStore::new(catalog.create_kv_store("crabs", KV_TREE_KEY_SIZE).unwrap(), txn_manager.clone());
+        let begin = || txn_manager.begin(IsolationLevel::SnapshotIsolation);
+
+        let txn = begin();
+        for (key, value) in [("ferris", "rust"), ("sebastian", "sea"), ("larry", "lobster"), ("a", "first")] {
+            kv.put(&txn, key.as_bytes(), value.as_bytes()).unwrap();
+        }
+        kv.put(&txn, b"larry", &[7; 600]).unwrap();
+        assert!(kv.delete(&txn, b"a").unwrap());
+        assert!(!kv.delete(&txn, b"a").unwrap());
+        let reader = begin();
+        txn_manager.commit(&txn).unwrap();
+        // a snapshot taken before the commit sees nothing
+        assert_eq!(None, kv.get(&reader, b"ferris").unwrap());
+        txn_manager.commit(&reader).unwrap();
+
+        let txn = begin();
+        assert_eq!(Some(b"rust".to_vec()), kv.get(&txn, b"ferris").unwrap());
+        let keys: Vec<_> = kv.scan::<&[u8]>(&txn, ..).unwrap().map(|entry| entry.unwrap().0).collect();
+        assert_eq!(vec![b"ferris".to_vec(), b"larry".to_vec(), b"sebastian".to_vec()], keys);
+        let range: Vec<_> = kv.scan(&txn, b"f".as_slice()..b"m".as_slice()).unwrap().map(|entry| entry.unwrap()).collect();
+        assert_eq!(vec![(b"ferris".to_vec(), b"rust".to_vec()), (b"larry".to_vec(), vec![7; 600])], range);
+
+        // rolled back writes leave no trace, including the key a put brought back
+        kv.put(&txn, b"a", b"again").unwrap();
+        kv.put(&txn, b"ferris", b"crab").unwrap();
+        kv.delete(&txn, b"sebastian").unwrap();
+        txn_manager.abort(&txn).unwrap();
+        let txn = begin();
+        assert_eq!(None, kv.get(&txn, b"a").unwrap());
+        assert_eq!(Some(b"rust".to_vec()), kv.get(&txn, b"ferris").unwrap());
+        assert_eq!(Some(b"sea".to_vec()), kv.get(&txn, b"sebastian").unwrap());
+
+        // two transactions writing the same key conflict
+        let other = begin();
+        kv.put(&txn, b"new", b"1").unwrap();
+        let err = kv.put(&other, b"new", b"2").unwrap_err();
+        assert!(matches!(err, CrabDBError::Conflict(_)));
+        txn_manager.abort(&other).unwrap();
+        txn_manager.commit(&txn).unwrap();
+        assert!(kv.put(&begin(), &[0; 129], b"too long").is_err());
+    }
+
+    #[test]
+    pub fn test_kv_store_expires_keys() {
+        let bpm = Arc::new(BufferPoolManager::new(32, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(32, 2))));
+        let catalog = Arc::new(Catalog::open(bpm).unwrap());
+        let txn_manager = Arc::new(TransactionManager::new(Arc::new(LockManager::new())));
+        let kv = KvStore::new(catalog.create_kv_store("cache", KV_TREE_KEY_SIZE).unwrap(), txn_manager.clone());
+        let begin = || txn_manager.begin(IsolationLevel::SnapshotIsolation);
+
+        let txn = begin();
+        kv.put_with_ttl(&txn, b"ferris", b"soon gone", Duration::from_millis(1)).unwrap();
+        kv.put_with_ttl(&txn, b"larry", b"lasting", Duration::from_secs(3600)).unwrap();
+        kv.put(&txn, b"sebastian", b"forever").unwrap();
+        kv.put_with_ttl(&txn, b"sebastian", b"short", Duration::from_millis(1)).unwrap();
+        kv.put(&txn, b"sebastian", b"forever again").unwrap();
+        assert!(kv.put_with_ttl(&txn, b"nobody", b"", Duration::ZERO).is_err());
+        let (value, expires_at) = kv.get_with_expiry(&txn, b"larry").unwrap().unwrap();
+        assert_eq!(b"lasting".to_vec(), value);
+        assert!(expires_at.unwrap() > super::now_ms() + 3_000_000);
+        assert_eq!(Some((b"forever again".to_vec(), None)), kv.get_with_expiry(&txn, b"sebastian").unwrap());
+        assert!(!kv.expire(&txn, b"nobody", Duration::from_secs(1)).unwrap());
+        txn_manager.commit(&txn).unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+
+        // expired keys read as missing before anything reclaims them
+        let txn = begin();
+        assert_eq!(None, kv.get(&txn, b"ferris").unwrap());
+        assert_eq!(Some(b"lasting".to_vec()), kv.get(&txn, b"larry").unwrap());
+        assert_eq!(Some(b"forever again".to_vec()), kv.get(&txn, b"sebastian").unwrap());
+        let keys: Vec<_> = kv.scan::<&[u8]>(&txn, ..).unwrap().map(|entry| entry.unwrap().0).collect();
+        assert_eq!(vec![b"larry".to_vec(), b"sebastian".to_vec()], keys);
+        txn_manager.commit(&txn).unwrap();
+
+        let stats = Vacuum::new(catalog.clone(), txn_manager.clone()).run().unwrap();
+        assert_eq!(1, stats.expired_keys_removed);
+        assert!(stats.tuples_reclaimed >= 1);
+        let txn = begin();
+        assert!(!kv.delete(&txn, b"ferris").unwrap());
+        // a key put again lives on
+        kv.put(&txn, b"ferris", b"back").unwrap();
+        txn_manager.commit(&txn).unwrap();
+        assert_eq!(0, kv.remove_expired().unwrap());
+        assert_eq!(Some(b"back".to_vec()), kv.get(&begin(), b"ferris").unwrap());
+    }
+}
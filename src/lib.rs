@@ -1,2 +1,36 @@
+// A tracing event, or nothing at all without the tracing feature. Fields may only use
+// values the surrounding code uses anyway, or they go unused without the feature.
+#[cfg(feature = "tracing")]
+macro_rules! trace_event {
+    ($($arg:tt)*) => { tracing::event!($($arg)*) };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_event {
+    ($($arg:tt)*) => {};
+}
+
+// The YCSB-style workloads crab-bench runs; they time operations with `Instant` and run
+// them on threads, which wasm builds have neither of.
+#[cfg(not(target_family = "wasm"))]
+pub mod bench;
 pub mod buffer_pool;
-pub mod types;
\ No newline at end of file
+pub mod catalog;
+pub mod concurrency;
+pub mod db;
+pub mod execution;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod index;
+pub mod kv;
+pub mod metrics;
+pub mod platform;
+pub mod server;
+pub mod sql;
+pub mod storage;
+pub mod types;
+pub mod wal;
+
+pub use db::crab_db::CrabDb;
+pub use db::engine::CrabDbEngine;
+pub use db::options::CrabDbOptions;
@@ -0,0 +1,290 @@
+// The shell needs a terminal and files, which wasm builds have neither of; those only build
+// the library and leave this an empty binary.
+#![cfg_attr(target_family = "wasm", allow(dead_code, unused_imports))]
+
+use std::io::IsTerminal;
+
+use crab_db::db::transaction::DbTransaction;
+use crab_db::types::schema::Schema;
+use crab_db::types::value::Value;
+use crab_db::types::CrabDbResult;
+use crab_db::{CrabDb, CrabDbOptions};
+#[cfg(not(target_family = "wasm"))]
+use rustyline::error::ReadlineError;
+#[cfg(not(target_family = "wasm"))]
+use rustyline::DefaultEditor;
+
+const HELP: &str = "\
+Statements end with a semicolon and may span several lines. BEGIN, COMMIT and ROLLBACK
+group statements into one transaction; otherwise each statement commits on its own.
+  \\dt          list tables
+  \\d TABLE     describe a table
+  \\?           show this help
+  \\q           quit
+";
+
+// Renders rows as an aligned table, numbers to the right, followed by the row count.
+fn format_table(schema: &Schema, rows: &[Vec<Value>]) -> String {
+    let cells: Vec<Vec<String>> = rows.iter().map(|row| row.iter().map(Value::to_string).collect()).collect();
+    let widths: Vec<usize> = schema.columns().iter().enumerate()
+        .map(|(i, column)| cells.iter().map(|row| row[i].chars().count()).fold(column.name().chars().count(), usize::max))
+        .collect();
+    let numeric: Vec<bool> = schema.columns().iter().map(|column| column.data_type().is_numeric()).collect();
+    let line = |values: Vec<String>| {
+        let padded: Vec<_> = values.iter().enumerate().map(|(i, value)| {
+            if numeric[i] { format!("{value:>width$}", width = widths[i]) } else { format!("{value:<width$}", width = widths[i]) }
+        }).collect();
+        format!(" {}\n", padded.join(" | ").trim_end())
+    };
+    let mut out = line(schema.columns().iter().map(|column| column.name().to_string()).collect());
+    out.push_str(&widths.iter().map(|width| "-".repeat(width + 2)).collect::<Vec<_>>().join("+"));
+    out.push('\n');
+    for row in cells {
+        out.push_str(&line(row));
+    }
+    out.push_str(&format!("({} row{})\n", rows.len(), if rows.len() == 1 { "" } else { "s" }));
+    out
+}
+
+fn describe(db: &CrabDb, name: &str) -> String {
+    let Some(table) = db.catalog().table(name) else {
+        return format!("Table {name} does not exist\n");
+    };
+    let mut out = format!("Table {}\n", table.name());
+    // The state columns of a materialized view are not its own.
+    let columns = table.schema().columns();
+    let state_columns = db.catalog().view_of(&table).map_or(0, |view| view.state_columns);
+    for column in &columns[..columns.len() - state_columns] {
+        let nullable = if column.is_nullable() { "" } else { " NOT NULL" };
+        out.push_str(&format!("  {} {}{nullable}\n", column.name(), column.data_type()));
+    }
+    for index in db.catalog().table_indexes(table.name()) {
+        let columns: Vec<_> = index.key_columns().iter().map(|&column| table.schema().column(column).name()).collect();
+        out.push_str(&format!("  index {} ({}) using {}\n", index.name(), columns.join(", "), index.kind()));
+    }
+    out
+}
+
+// Holds the open database and, between BEGIN and COMMIT or ROLLBACK, the transaction
+// statements run in.
+struct Shell<'a> {
+    db: &'a CrabDb,
+    txn: Option<DbTransaction<'a>>,
+}
+
+impl Shell<'_> {
+    fn meta_command(&self, command: &str) -> String {
+        let mut words = command.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("\\dt"), None) => {
+                let names = self.db.catalog().table_names();
+                if names.is_empty() { "No tables\n".to_string() } else { names.join("\n") + "\n" }
+            },
+            (Some("\\d"), Some(name)) => describe(self.db, name),
+            (Some("\\?"), None) => HELP.to_string(),
+            _ => format!("Unknown command {command}; \\? lists the commands\n"),
+        }
+    }
+
+    fn statement(&mut self, sql: &str) -> CrabDbResult<String> {
+        let keyword = sql.trim_end_matches(';').trim().to_uppercase();
+        match (keyword.as_str(), self.txn.take()) {
+            ("BEGIN" | "START TRANSACTION", None) => {
+                self.txn = Some(self.db.begin_transaction());
+                Ok("BEGIN\n".to_string())
+            },
+            ("BEGIN" | "START TRANSACTION", Some(txn)) => {
+                self.txn = Some(txn);
+                Ok("Already in a transaction\n".to_string())
+            },
+            ("COMMIT", Some(txn)) => txn.commit().map(|_| "COMMIT\n".to_string()),
+            ("ROLLBACK", Some(txn)) => txn.rollback().map(|_| "ROLLBACK\n".to_string()),
+            ("COMMIT" | "ROLLBACK", None) => Ok("Not in a transaction\n".to_string()),
+            (_, txn) => {
+                self.txn = txn;
+                let rows = match &self.txn {
+                    Some(txn) => txn.query(sql)?,
+                    None => self.db.query(sql)?,
+                };
+                if rows.schema().column_count() == 0 {
+                    return Ok("OK\n".to_string());
+                }
+                let schema = rows.schema().clone();
+                Ok(format_table(&schema, &rows.fetch_all()?))
+            },
+        }
+    }
+}
+
+// Serves the "redis" key-value store over RESP until the process is killed.
+#[cfg(feature = "resp")]
+fn serve_resp(db: std::sync::Arc<CrabDb>, address: &str) -> std::io::Result<()> {
+    use crab_db::server::resp::RespServer;
+
+    let server = RespServer::new(db, "redis").map_err(std::io::Error::other)?;
+    let listener = std::net::TcpListener::bind(address)?;
+    println!("crab-db {} serving RESP on {}", env!("CARGO_PKG_VERSION"), listener.local_addr()?);
+    std::sync::Arc::new(server).serve(listener)
+}
+
+// Serves SQL over gRPC until the process is killed.
+#[cfg(feature = "grpc")]
+fn serve_grpc(db: std::sync::Arc<CrabDb>, address: &str) -> Result<(), Box<dyn std::error::Error>> {
+    use crab_db::server::grpc::GrpcService;
+
+    let address = address.parse()?;
+    println!("crab-db {} serving gRPC on {address}", env!("CARGO_PKG_VERSION"));
+    let service = GrpcService::new(db);
+    tokio::runtime::Runtime::new()?.block_on(tonic::transport::Server::builder().add_service(service.into_server()).serve(address))?;
+    Ok(())
+}
+
+// Serves the Prometheus metrics of `db` on /metrics from a background thread.
+#[cfg(all(feature = "prometheus", any(feature = "resp", feature = "grpc")))]
+fn serve_metrics(db: std::sync::Arc<CrabDb>, address: &str) -> std::io::Result<()> {
+    use crab_db::metrics::prometheus_exporter::{serve_metrics, PrometheusExporter};
+
+    let registry = prometheus::Registry::new();
+    registry.register(Box::new(PrometheusExporter::new(db))).map_err(std::io::Error::other)?;
+    let listener = std::net::TcpListener::bind(address)?;
+    println!("crab-db {} serving metrics on http://{}/metrics", env!("CARGO_PKG_VERSION"), listener.local_addr()?);
+    std::thread::spawn(move || serve_metrics(registry, listener));
+    Ok(())
+}
+
+#[cfg(target_family = "wasm")]
+fn main() {}
+
+#[cfg(not(target_family = "wasm"))]
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let (mut path, mut resp_address, mut grpc_address, mut metrics_address) = ("crab.db".to_string(), None, None, None);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--resp" if cfg!(feature = "resp") => resp_address = args.next(),
+            "--grpc" if cfg!(feature = "grpc") => grpc_address = args.next(),
+            "--metrics" if cfg!(feature = "prometheus") => metrics_address = args.next(),
+            _ => path = arg,
+        }
+    }
+    let db = match CrabDb::open(&path, CrabDbOptions::default()) {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("Cannot open {path}: {e}");
+            std::process::exit(1);
+        },
+    };
+    #[cfg(any(feature = "resp", feature = "grpc"))]
+    if resp_address.is_some() || grpc_address.is_some() {
+        let db = std::sync::Arc::new(db);
+        #[cfg(feature = "prometheus")]
+        if let Some(address) = metrics_address.take() {
+            if let Err(e) = serve_metrics(db.clone(), &address) {
+                eprintln!("Cannot serve metrics on {address}: {e}");
+                std::process::exit(1);
+            }
+        }
+        #[cfg(feature = "resp")]
+        if let Some(address) = resp_address {
+            if let Err(e) = serve_resp(db, &address) {
+                eprintln!("Cannot serve RESP on {address}: {e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        #[cfg(feature = "grpc")]
+        if let Some(address) = grpc_address {
+            if let Err(e) = serve_grpc(db, &address) {
+                eprintln!("Cannot serve gRPC on {address}: {e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    #[cfg(not(feature = "resp"))]
+    let _ = resp_address;
+    #[cfg(not(feature = "grpc"))]
+    let _ = grpc_address;
+    // The shell closes the database on exit, which the metrics thread would keep open.
+    if metrics_address.is_some() {
+        eprintln!("--metrics needs --resp or --grpc");
+        std::process::exit(1);
+    }
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("Cannot read input: {e}");
+            std::process::exit(1);
+        },
+    };
+    if std::io::stdin().is_terminal() {
+        println!("crab-db {} on {path}; \\? for help", env!("CARGO_PKG_VERSION"));
+    }
+    let mut shell = Shell { db: &db, txn: None };
+    let mut pending = String::new();
+    loop {
+        let line = match editor.readline(if pending.is_empty() { "crab-db> " } else { "     ..> " }) {
+            Ok(line) => line,
+            // Ctrl-C abandons the statement being typed.
+            Err(ReadlineError::Interrupted) => {
+                pending.clear();
+                continue;
+            },
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("Cannot read input: {e}");
+                break;
+            },
+        };
+        let trimmed = line.trim();
+        if pending.is_empty() && trimmed.starts_with('\\') {
+            if trimmed == "\\q" {
+                break;
+            }
+            let _ = editor.add_history_entry(trimmed);
+            print!("{}", shell.meta_command(trimmed));
+            continue;
+        }
+        pending.push_str(&line);
+        pending.push('\n');
+        if !trimmed.ends_with(';') {
+            continue;
+        }
+        let _ = editor.add_history_entry(pending.trim_end());
+        match shell.statement(&std::mem::take(&mut pending)) {
+            Ok(output) => print!("{output}"),
+            Err(e) => println!("ERROR: {e}"),
+        }
+    }
+    drop(shell);
+    if let Err(e) = db.close() {
+        eprintln!("Cannot close {path}: {e}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use crab_db::{CrabDb, CrabDbOptions};
+    use super::Shell;
+
+    #[test]
+    pub fn test_shell_prints_tables_and_runs_meta_commands() {
+        let dir = TempDir::new().unwrap();
+        let db = CrabDb::open(dir.path().join("crabs.db"), CrabDbOptions::default()).unwrap();
+        let mut shell = Shell { db: &db, txn: None };
+        assert_eq!("OK\n", shell.statement("CREATE TABLE crabs (id INT NOT NULL, name VARCHAR);").unwrap());
+        assert_eq!("BEGIN\n", shell.statement("begin;").unwrap());
+        shell.statement("INSERT INTO crabs VALUES (1, 'ferris'), (22, NULL);").unwrap();
+        assert_eq!("COMMIT\n", shell.statement("COMMIT;").unwrap());
+        assert_eq!(
+            " id | name\n----+--------\n  1 | ferris\n 22 | NULL\n(2 rows)\n",
+            shell.statement("SELECT * FROM crabs ORDER BY id;").unwrap()
+        );
+        assert_eq!("crabs\n", shell.meta_command("\\dt"));
+        assert_eq!("Table crabs\n  id INT NOT NULL\n  name VARCHAR\n", shell.meta_command("\\d crabs"));
+        assert!(shell.statement("SELECT nope FROM crabs;").is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use std::iter::Sum;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use super::latency_histogram::{LatencyHistogram, LatencyHistogramSnapshot, SlowIoThreshold};
+
+// Counters a BufferPoolManager bumps as it works. They only ever grow; rates come from
+// comparing two snapshots.
+#[derive(Debug, Default)]
+pub struct BufferPoolMetrics {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    new_pages: AtomicU64,
+    evictions: AtomicU64,
+    dirty_writes: AtomicU64,
+    pins: AtomicU64,
+    disk_reads: AtomicU64,
+    disk_read_nanos: AtomicU64,
+    disk_writes: AtomicU64,
+    disk_write_nanos: AtomicU64,
+    coalesced_writes: AtomicU64,
+    disk_read_latency: LatencyHistogram,
+    disk_write_latency: LatencyHistogram,
+    slow_io_threshold: SlowIoThreshold,
+    slow_disk_reads: AtomicU64,
+    slow_disk_writes: AtomicU64,
+}
+
+fn nanos(duration: Duration) -> u64 {
+    duration.as_nanos().min(u64::MAX as u128) as u64
+}
+
+impl BufferPoolMetrics {
+    pub(crate) fn record_hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_new_page(&self) {
+        self.new_pages.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_eviction(&self) {
+        self.evictions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_dirty_write(&self) {
+        self.dirty_writes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_pin(&self) {
+        self.pins.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_slow_io_threshold(&self, threshold: Option<Duration>) {
+        self.slow_io_threshold.set(threshold);
+    }
+
+    // Returns whether the read was slow, for the caller to report.
+    pub(crate) fn record_disk_read(&self, latency: Duration) -> bool {
+        self.disk_reads.fetch_add(1, Ordering::Relaxed);
+        self.disk_read_nanos.fetch_add(nanos(latency), Ordering::Relaxed);
+        self.disk_read_latency.record(latency);
+        let slow = self.slow_io_threshold.exceeded_by(latency);
+        if slow {
+            self.slow_disk_reads.fetch_add(1, Ordering::Relaxed);
+        }
+        slow
+    }
+
+    // One write of `pages` consecutive pages. Returns whether it was slow, for the caller
+    // to report.
+    pub(crate) fn record_disk_write(&self, pages: usize, latency: Duration) -> bool {
+        self.disk_writes.fetch_add(pages as u64, Ordering::Relaxed);
+        if pages > 1 {
+            self.coalesced_writes.fetch_add(1, Ordering::Relaxed);
+        }
+        self.disk_write_nanos.fetch_add(nanos(latency), Ordering::Relaxed);
+        self.disk_write_latency.record(latency);
+        let slow = self.slow_io_threshold.exceeded_by(latency);
+        if slow {
+            self.slow_disk_writes.fetch_add(1, Ordering::Relaxed);
+        }
+        slow
+    }
+
+    // The counters, plus the gauges the caller read off the pool at the same time. Counters
+    // are read one by one, so a snapshot taken under load may be off by the operations that
+    // raced it.
+    pub(crate) fn snapshot(
+        &self,
+        pool_size: usize,
+        free_frames: usize,
+        evictable_frames: usize,
+        permanent_frames: usize,
+    ) -> BufferPoolMetricsSnapshot {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        BufferPoolMetricsSnapshot {
+            pool_size,
+            free_frames,
+            evictable_frames,
+            permanent_frames,
+            hits: load(&self.hits),
+            misses: load(&self.misses),
+            new_pages: load(&self.new_pages),
+            evictions: load(&self.evictions),
+            dirty_writes: load(&self.dirty_writes),
+            pins: load(&self.pins),
+            disk_reads: load(&self.disk_reads),
+            disk_read_nanos: load(&self.disk_read_nanos),
+            disk_writes: load(&self.disk_writes),
+            disk_write_nanos: load(&self.disk_write_nanos),
+            coalesced_writes: load(&self.coalesced_writes),
+            disk_read_latency: self.disk_read_latency.snapshot(),
+            disk_write_latency: self.disk_write_latency.snapshot(),
+            slow_disk_reads: load(&self.slow_disk_reads),
+            slow_disk_writes: load(&self.slow_disk_writes),
+        }
+    }
+}
+
+// What a buffer pool has done since it was created, and how full it is now.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BufferPoolMetricsSnapshot {
+    pub pool_size: usize,
+    // Frames holding no page.
+    pub free_frames: usize,
+    // Frames holding an unpinned page the replacer may evict.
+    pub evictable_frames: usize,
+    // Frames holding a page pinned for good, which are never evicted.
+    pub permanent_frames: usize,
+    // Fetches of a page that was already resident.
+    pub hits: u64,
+    // Fetches that had to read the page from disk.
+    pub misses: u64,
+    pub new_pages: u64,
+    pub evictions: u64,
+    // Dirty pages written back, on eviction or when flushed.
+    pub dirty_writes: u64,
+    pub pins: u64,
+    pub disk_reads: u64,
+    pub disk_read_nanos: u64,
+    // Pages written.
+    pub disk_writes: u64,
+    pub disk_write_nanos: u64,
+    // Writes of several consecutive pages at once, whose pages disk_writes counts each.
+    pub coalesced_writes: u64,
+    pub disk_read_latency: LatencyHistogramSnapshot,
+    // Per write, however many pages it wrote.
+    pub disk_write_latency: LatencyHistogramSnapshot,
+    // Reads and writes that took longer than the slow I/O threshold.
+    pub slow_disk_reads: u64,
+    pub slow_disk_writes: u64,
+}
+
+// Adds up the snapshots of several pools, as if they were one.
+impl Sum for BufferPoolMetricsSnapshot {
+    fn sum<I: Iterator<Item = Self>>(snapshots: I) -> Self {
+        snapshots.fold(Self::default(), |total, snapshot| BufferPoolMetricsSnapshot {
+            pool_size: total.pool_size + snapshot.pool_size,
+            free_frames: total.free_frames + snapshot.free_frames,
+            evictable_frames: total.evictable_frames + snapshot.evictable_frames,
+            permanent_frames: total.permanent_frames + snapshot.permanent_frames,
+            hits: total.hits + snapshot.hits,
+            misses: total.misses + snapshot.misses,
+            new_pages: total.new_pages + snapshot.new_pages,
+            evictions: total.evictions + snapshot.evictions,
+            dirty_writes: total.dirty_writes + snapshot.dirty_writes,
+            pins: total.pins + snapshot.pins,
+            disk_reads: total.disk_reads + snapshot.disk_reads,
+            disk_read_nanos: total.disk_read_nanos + snapshot.disk_read_nanos,
+            disk_writes: total.disk_writes + snapshot.disk_writes,
+            disk_write_nanos: total.disk_write_nanos + snapshot.disk_write_nanos,
+            coalesced_writes: total.coalesced_writes + snapshot.coalesced_writes,
+            disk_read_latency: total.disk_read_latency.merge(&snapshot.disk_read_latency),
+            disk_write_latency: total.disk_write_latency.merge(&snapshot.disk_write_latency),
+            slow_disk_reads: total.slow_disk_reads + snapshot.slow_disk_reads,
+            slow_disk_writes: total.slow_disk_writes + snapshot.slow_disk_writes,
+        })
+    }
+}
+
+impl BufferPoolMetricsSnapshot {
+    // Fraction of fetches served without reading from disk; 0 before the first fetch.
+    pub fn hit_ratio(&self) -> f64 {
+        let fetches = self.hits + self.misses;
+        if fetches == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / fetches as f64
+    }
+
+    pub fn average_read_latency(&self) -> Duration {
+        Duration::from_nanos(self.disk_read_nanos.checked_div(self.disk_reads).unwrap_or(0))
+    }
+
+    pub fn average_write_latency(&self) -> Duration {
+        Duration::from_nanos(self.disk_write_nanos.checked_div(self.disk_writes).unwrap_or(0))
+    }
+}
@@ -0,0 +1,11 @@
+use super::buffer_pool_metrics::BufferPoolMetricsSnapshot;
+use super::wal_metrics::WalMetricsSnapshot;
+
+// The metrics of a whole database, read at one point in time.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CrabDbMetricsSnapshot {
+    pub buffer_pool: BufferPoolMetricsSnapshot,
+    pub wal: WalMetricsSnapshot,
+    pub active_transactions: usize,
+}
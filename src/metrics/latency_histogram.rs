@@ -0,0 +1,181 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Each power of two of nanoseconds is split into this many buckets, so a latency is known to
+// within 1/32 of itself, about 3%, however long it is.
+const SUB_BUCKET_BITS: u32 = 5;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+// Latencies from 2^40ns, about 18 minutes, on are counted in the last bucket.
+const MAX_BITS: u32 = 40;
+const BUCKETS: usize = (MAX_BITS - SUB_BUCKET_BITS + 1) as usize * SUB_BUCKETS;
+
+fn nanos(duration: Duration) -> u64 {
+    duration.as_nanos().min(u64::MAX as u128) as u64
+}
+
+// Latencies below SUB_BUCKETS nanoseconds get a bucket each; above, every power of two gets
+// SUB_BUCKETS of them, telling values apart by their next SUB_BUCKET_BITS bits.
+fn bucket_of(nanos: u64) -> usize {
+    let nanos = nanos.min((1 << MAX_BITS) - 1);
+    if nanos < SUB_BUCKETS as u64 {
+        return nanos as usize;
+    }
+    let magnitude = 63 - nanos.leading_zeros();
+    let shift = magnitude - SUB_BUCKET_BITS;
+    (shift as usize + 1) * SUB_BUCKETS + (nanos >> shift) as usize - SUB_BUCKETS
+}
+
+// The largest latency counted in `bucket`.
+fn highest_in(bucket: usize) -> u64 {
+    if bucket < SUB_BUCKETS {
+        return bucket as u64;
+    }
+    let shift = (bucket / SUB_BUCKETS - 1) as u32;
+    let lowest = ((SUB_BUCKETS + bucket % SUB_BUCKETS) as u64) << shift;
+    lowest + (1 << shift) - 1
+}
+
+// Counts latencies in log-linear buckets, in the manner of an HDR histogram, so percentiles
+// can be read off it with a bounded relative error. Recording is a couple of atomic adds.
+pub struct LatencyHistogram {
+    buckets: Box<[AtomicU64]>,
+    max_nanos: AtomicU64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        LatencyHistogram { buckets: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(), max_nanos: AtomicU64::new(0) }
+    }
+}
+
+impl std::fmt::Debug for LatencyHistogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LatencyHistogram").field("snapshot", &self.snapshot()).finish()
+    }
+}
+
+impl LatencyHistogram {
+    pub(crate) fn record(&self, latency: Duration) {
+        let nanos = nanos(latency);
+        self.buckets[bucket_of(nanos)].fetch_add(1, Ordering::Relaxed);
+        self.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> LatencyHistogramSnapshot {
+        let mut counts: Vec<_> = self.buckets.iter().map(|count| count.load(Ordering::Relaxed)).collect();
+        let used = counts.iter().rposition(|&count| count > 0).map_or(0, |last| last + 1);
+        counts.truncate(used);
+        LatencyHistogramSnapshot { counts, max_nanos: self.max_nanos.load(Ordering::Relaxed) }
+    }
+}
+
+// The latencies a histogram had counted when it was read.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LatencyHistogramSnapshot {
+    // Latencies per bucket, up to the last bucket with any.
+    counts: Vec<u64>,
+    max_nanos: u64,
+}
+
+impl LatencyHistogramSnapshot {
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max_nanos)
+    }
+
+    // The latency `percentile` percent of those counted were at or below, from 0 to 100;
+    // zero if none were counted. Overstates it by at most about 3%, never past the max.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        let count = self.count();
+        if count == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((percentile.clamp(0.0, 100.0) / 100.0 * count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, &bucket_count) in self.counts.iter().enumerate() {
+            seen += bucket_count;
+            if seen >= rank {
+                return Duration::from_nanos(highest_in(bucket).min(self.max_nanos));
+            }
+        }
+        self.max()
+    }
+
+    // The histogram of the latencies counted by both.
+    pub fn merge(&self, other: &Self) -> Self {
+        let (longer, shorter) = if self.counts.len() >= other.counts.len() { (self, other) } else { (other, self) };
+        let mut counts = longer.counts.clone();
+        for (count, other_count) in counts.iter_mut().zip(&shorter.counts) {
+            *count += other_count;
+        }
+        LatencyHistogramSnapshot { counts, max_nanos: self.max_nanos.max(other.max_nanos) }
+    }
+}
+
+// The latency past which a disk operation is reported as slow, if any.
+#[derive(Debug)]
+pub(crate) struct SlowIoThreshold(AtomicU64);
+
+impl Default for SlowIoThreshold {
+    fn default() -> Self {
+        SlowIoThreshold(AtomicU64::new(u64::MAX))
+    }
+}
+
+impl SlowIoThreshold {
+    pub(crate) fn set(&self, threshold: Option<Duration>) {
+        self.0.store(threshold.map_or(u64::MAX, nanos), Ordering::Relaxed);
+    }
+
+    pub(crate) fn exceeded_by(&self, latency: Duration) -> bool {
+        let threshold = self.0.load(Ordering::Relaxed);
+        threshold != u64::MAX && nanos(latency) > threshold
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{bucket_of, highest_in, LatencyHistogram, SlowIoThreshold, BUCKETS};
+
+    #[test]
+    pub fn test_latency_histogram_percentiles() {
+        // Every latency lands in a bucket whose range holds it.
+        for nanos in (0..5000).chain([1 << 20, 123_456_789, u64::MAX >> 30]) {
+            let bucket = bucket_of(nanos);
+            assert!(bucket < BUCKETS && highest_in(bucket) >= nanos, "{nanos}");
+            assert!(bucket == 0 || highest_in(bucket - 1) < nanos, "{nanos}");
+        }
+        assert_eq!(BUCKETS - 1, bucket_of(u64::MAX));
+
+        let histogram = LatencyHistogram::default();
+        assert_eq!(Duration::ZERO, histogram.snapshot().percentile(99.0));
+        for micros in 1..=1000 {
+            histogram.record(Duration::from_micros(micros));
+        }
+        let snapshot = histogram.snapshot();
+        assert_eq!(1000, snapshot.count());
+        assert_eq!(Duration::from_millis(1), snapshot.max());
+        assert_eq!(Duration::from_millis(1), snapshot.percentile(100.0));
+        for (percentile, micros) in [(50.0, 500.0), (90.0, 900.0), (99.0, 990.0), (99.9, 999.0)] {
+            let latency = snapshot.percentile(percentile).as_nanos() as f64 / 1000.0;
+            assert!((micros..=micros * 1.04).contains(&latency), "p{percentile} = {latency}us");
+        }
+
+        let other = LatencyHistogram::default();
+        other.record(Duration::from_secs(2));
+        let merged = snapshot.merge(&other.snapshot());
+        assert_eq!((1001, Duration::from_secs(2)), (merged.count(), merged.max()));
+        assert_eq!(snapshot.percentile(50.0), merged.percentile(50.0));
+
+        let threshold = SlowIoThreshold::default();
+        assert!(!threshold.exceeded_by(Duration::MAX));
+        threshold.set(Some(Duration::from_millis(10)));
+        assert!(threshold.exceeded_by(Duration::from_millis(11)) && !threshold.exceeded_by(Duration::from_millis(10)));
+    }
+}
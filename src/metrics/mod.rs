@@ -0,0 +1,6 @@
+pub mod buffer_pool_metrics;
+pub mod crab_db_metrics;
+pub mod latency_histogram;
+#[cfg(feature = "prometheus")]
+pub mod prometheus_exporter;
+pub mod wal_metrics;
@@ -0,0 +1,244 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::MetricFamily;
+use prometheus::{Counter, Encoder, Gauge, GaugeVec, IntCounter, IntGauge, Opts, Registry, TextEncoder};
+
+use crate::db::crab_db::CrabDb;
+use crate::metrics::latency_histogram::LatencyHistogramSnapshot;
+
+// Exposes the metrics of a database to a prometheus Registry. The values are read off the
+// database each time the registry is gathered, so nothing is counted twice.
+pub struct PrometheusExporter {
+    db: Arc<CrabDb>,
+    hits: IntCounter,
+    misses: IntCounter,
+    hit_ratio: Gauge,
+    evictions: IntCounter,
+    dirty_writes: IntCounter,
+    pool_size: IntGauge,
+    free_frames: IntGauge,
+    disk_reads: IntCounter,
+    disk_read_seconds: Counter,
+    disk_writes: IntCounter,
+    disk_write_seconds: Counter,
+    coalesced_writes: IntCounter,
+    disk_read_latency: GaugeVec,
+    disk_write_latency: GaugeVec,
+    slow_disk_reads: IntCounter,
+    slow_disk_writes: IntCounter,
+    wal_flushes: IntCounter,
+    wal_flush_seconds: Counter,
+    wal_bytes_flushed: IntCounter,
+    wal_sync_latency: GaugeVec,
+    wal_slow_syncs: IntCounter,
+    active_transactions: IntGauge,
+    // Collecting resets and refills the counters, so two scrapes must not interleave.
+    collecting: Mutex<()>,
+}
+
+fn int_counter(name: &str, help: &str) -> IntCounter {
+    IntCounter::with_opts(Opts::new(name, help)).unwrap()
+}
+
+fn counter(name: &str, help: &str) -> Counter {
+    Counter::with_opts(Opts::new(name, help)).unwrap()
+}
+
+fn int_gauge(name: &str, help: &str) -> IntGauge {
+    IntGauge::with_opts(Opts::new(name, help)).unwrap()
+}
+
+fn seconds(nanos: u64) -> f64 {
+    nanos as f64 / 1e9
+}
+
+// The percentiles a latency histogram is exported as, in the "quantile" label.
+const QUANTILES: [(&str, f64); 4] = [("0.5", 50.0), ("0.9", 90.0), ("0.99", 99.0), ("0.999", 99.9)];
+
+fn quantiles(name: &str, help: &str) -> GaugeVec {
+    GaugeVec::new(Opts::new(name, help), &["quantile"]).unwrap()
+}
+
+fn set_quantiles(gauges: &GaugeVec, histogram: &LatencyHistogramSnapshot) {
+    for (label, percentile) in QUANTILES {
+        gauges.with_label_values(&[label]).set(histogram.percentile(percentile).as_secs_f64());
+    }
+}
+
+impl PrometheusExporter {
+    pub fn new(db: Arc<CrabDb>) -> Self {
+        PrometheusExporter {
+            db,
+            hits: int_counter("crab_db_buffer_pool_hits_total", "Page fetches served from the buffer pool"),
+            misses: int_counter("crab_db_buffer_pool_misses_total", "Page fetches that read the page from disk"),
+            hit_ratio: Gauge::with_opts(Opts::new("crab_db_buffer_pool_hit_ratio", "Fraction of page fetches served from the buffer pool")).unwrap(),
+            evictions: int_counter("crab_db_buffer_pool_evictions_total", "Pages evicted from the buffer pool"),
+            dirty_writes: int_counter("crab_db_buffer_pool_dirty_writes_total", "Dirty pages written back to disk"),
+            pool_size: int_gauge("crab_db_buffer_pool_frames", "Frames in the buffer pool"),
+            free_frames: int_gauge("crab_db_buffer_pool_free_frames", "Frames holding no page"),
+            disk_reads: int_counter("crab_db_disk_reads_total", "Pages read from the database file"),
+            disk_read_seconds: counter("crab_db_disk_read_seconds_total", "Time spent reading pages"),
+            disk_writes: int_counter("crab_db_disk_writes_total", "Pages written to the database file"),
+            disk_write_seconds: counter("crab_db_disk_write_seconds_total", "Time spent writing pages"),
+            coalesced_writes: int_counter("crab_db_disk_coalesced_writes_total", "Writes of several consecutive pages at once"),
+            disk_read_latency: quantiles("crab_db_disk_read_latency_seconds", "Latency of page reads"),
+            disk_write_latency: quantiles("crab_db_disk_write_latency_seconds", "Latency of page writes"),
+            slow_disk_reads: int_counter("crab_db_slow_disk_reads_total", "Page reads slower than the slow I/O threshold"),
+            slow_disk_writes: int_counter("crab_db_slow_disk_writes_total", "Page writes slower than the slow I/O threshold"),
+            wal_flushes: int_counter("crab_db_wal_flushes_total", "Writes and syncs of the write-ahead log"),
+            wal_flush_seconds: counter("crab_db_wal_flush_seconds_total", "Time spent writing and syncing the write-ahead log"),
+            wal_bytes_flushed: int_counter("crab_db_wal_bytes_flushed_total", "Bytes written to the write-ahead log"),
+            wal_sync_latency: quantiles("crab_db_wal_sync_latency_seconds", "Latency of syncs of the write-ahead log"),
+            wal_slow_syncs: int_counter("crab_db_wal_slow_syncs_total", "Syncs of the write-ahead log slower than the slow I/O threshold"),
+            active_transactions: int_gauge("crab_db_active_transactions", "Transactions begun and not yet finished"),
+            collecting: Mutex::new(()),
+        }
+    }
+
+    fn collectors(&self) -> [&dyn Collector; 22] {
+        [
+            &self.hits, &self.misses, &self.hit_ratio, &self.evictions, &self.dirty_writes,
+            &self.pool_size, &self.free_frames, &self.disk_reads, &self.disk_read_seconds,
+            &self.disk_writes, &self.disk_write_seconds, &self.coalesced_writes, &self.disk_read_latency, &self.disk_write_latency,
+            &self.slow_disk_reads, &self.slow_disk_writes, &self.wal_flushes, &self.wal_flush_seconds,
+            &self.wal_bytes_flushed, &self.wal_sync_latency, &self.wal_slow_syncs, &self.active_transactions,
+        ]
+    }
+}
+
+impl Collector for PrometheusExporter {
+    fn desc(&self) -> Vec<&Desc> {
+        self.collectors().into_iter().flat_map(|collector| collector.desc()).collect()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let _collecting = self.collecting.lock().unwrap();
+        let metrics = self.db.metrics_snapshot();
+        let set_int = |counter: &IntCounter, value: u64| {
+            counter.reset();
+            counter.inc_by(value);
+        };
+        let set = |counter: &Counter, value: f64| {
+            counter.reset();
+            counter.inc_by(value);
+        };
+        let buffer_pool = &metrics.buffer_pool;
+        set_int(&self.hits, buffer_pool.hits);
+        set_int(&self.misses, buffer_pool.misses);
+        self.hit_ratio.set(buffer_pool.hit_ratio());
+        set_int(&self.evictions, buffer_pool.evictions);
+        set_int(&self.dirty_writes, buffer_pool.dirty_writes);
+        self.pool_size.set(buffer_pool.pool_size as i64);
+        self.free_frames.set(buffer_pool.free_frames as i64);
+        set_int(&self.disk_reads, buffer_pool.disk_reads);
+        set(&self.disk_read_seconds, seconds(buffer_pool.disk_read_nanos));
+        set_int(&self.disk_writes, buffer_pool.disk_writes);
+        set(&self.disk_write_seconds, seconds(buffer_pool.disk_write_nanos));
+        set_int(&self.coalesced_writes, buffer_pool.coalesced_writes);
+        set_quantiles(&self.disk_read_latency, &buffer_pool.disk_read_latency);
+        set_quantiles(&self.disk_write_latency, &buffer_pool.disk_write_latency);
+        set_int(&self.slow_disk_reads, buffer_pool.slow_disk_reads);
+        set_int(&self.slow_disk_writes, buffer_pool.slow_disk_writes);
+        set_int(&self.wal_flushes, metrics.wal.flushes);
+        set(&self.wal_flush_seconds, seconds(metrics.wal.flush_nanos));
+        set_int(&self.wal_bytes_flushed, metrics.wal.bytes_flushed);
+        set_quantiles(&self.wal_sync_latency, &metrics.wal.sync_latency);
+        set_int(&self.wal_slow_syncs, metrics.wal.slow_syncs);
+        self.active_transactions.set(metrics.active_transactions as i64);
+        self.collectors().into_iter().flat_map(|collector| collector.collect()).collect()
+    }
+}
+
+// Answers GET /metrics with everything in `registry`, in the Prometheus text format, one
+// connection at a time until the listener fails.
+pub fn serve_metrics(registry: Registry, listener: TcpListener) -> std::io::Result<()> {
+    for stream in listener.incoming() {
+        // A scraper that hangs up early should not stop the endpoint.
+        let _ = handle_scrape(&registry, stream?);
+    }
+    Ok(())
+}
+
+fn handle_scrape(registry: &Registry, mut stream: TcpStream) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the headers; nothing in them changes the answer.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header.trim_end() != "" {
+        header.clear();
+    }
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+    let (status, content_type, body) = if request_line.starts_with("GET ") && path == "/metrics" {
+        let encoder = TextEncoder::new();
+        let mut body = Vec::new();
+        encoder.encode(&registry.gather(), &mut body).map_err(std::io::Error::other)?;
+        ("200 OK", encoder.format_type().to_string(), body)
+    } else {
+        ("404 Not Found", "text/plain".to_string(), b"Not found\n".to_vec())
+    };
+    write!(stream, "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len())?;
+    stream.write_all(&body)?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Arc;
+
+    use prometheus::Registry;
+    use tempfile::TempDir;
+
+    use crate::db::crab_db::CrabDb;
+    use crate::db::options::CrabDbOptions;
+    use super::{serve_metrics, PrometheusExporter};
+
+    #[test]
+    pub fn test_prometheus_exporter_serves_database_metrics() {
+        let dir = TempDir::new().unwrap();
+        let db = Arc::new(CrabDb::open(dir.path().join("crabs.db"), CrabDbOptions::default()).unwrap());
+        db.execute("CREATE TABLE crabs (id INT NOT NULL, name VARCHAR)").unwrap();
+        db.execute("INSERT INTO crabs VALUES (1, 'ferris')").unwrap();
+        let txn = db.begin_transaction();
+
+        let registry = Registry::new();
+        registry.register(Box::new(PrometheusExporter::new(db.clone()))).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || serve_metrics(registry, listener));
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            write!(stream, "GET {path} HTTP/1.1\r\nHost: crab-db\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        let sample = |name: &str| -> f64 {
+            let line = response.lines().find(|line| line.split(' ').next() == Some(name)).unwrap();
+            line.split(' ').nth(1).unwrap().parse().unwrap()
+        };
+        assert_eq!(1.0, sample("crab_db_active_transactions"));
+        assert_eq!(1024.0, sample("crab_db_buffer_pool_frames"));
+        let metrics = db.metrics_snapshot();
+        assert!(metrics.buffer_pool.hits > 0);
+        assert_eq!(metrics.buffer_pool.hits as f64, sample("crab_db_buffer_pool_hits_total"));
+        assert_eq!(metrics.buffer_pool.hit_ratio(), sample("crab_db_buffer_pool_hit_ratio"));
+        assert_eq!(metrics.wal.flushes as f64, sample("crab_db_wal_flushes_total"));
+        assert!(response.contains("# TYPE crab_db_buffer_pool_hits_total counter"));
+        let p99 = metrics.wal.sync_latency.percentile(99.0).as_secs_f64();
+        assert!(p99 > 0.0);
+        assert_eq!(p99, sample("crab_db_wal_sync_latency_seconds{quantile=\"0.99\"}"));
+
+        txn.rollback().unwrap();
+        assert!(get("/metrics").contains("\ncrab_db_active_transactions 0\n"));
+        assert!(get("/other").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
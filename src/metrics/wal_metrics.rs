@@ -0,0 +1,76 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::wal::common::Lsn;
+
+use super::latency_histogram::{LatencyHistogram, LatencyHistogramSnapshot, SlowIoThreshold};
+
+// Counters a LogManager bumps each time it writes and syncs the log.
+#[derive(Debug, Default)]
+pub struct WalMetrics {
+    flushes: AtomicU64,
+    flush_nanos: AtomicU64,
+    bytes_flushed: AtomicU64,
+    sync_latency: LatencyHistogram,
+    slow_io_threshold: SlowIoThreshold,
+    slow_syncs: AtomicU64,
+}
+
+impl WalMetrics {
+    pub(crate) fn set_slow_io_threshold(&self, threshold: Option<Duration>) {
+        self.slow_io_threshold.set(threshold);
+    }
+
+    pub(crate) fn record_flush(&self, bytes: usize, latency: Duration) {
+        self.flushes.fetch_add(1, Ordering::AcqRel);
+        self.flush_nanos.fetch_add(latency.as_nanos().min(u64::MAX as u128) as u64, Ordering::Relaxed);
+        self.bytes_flushed.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    // Returns whether the sync was slow, for the caller to report.
+    pub(crate) fn record_sync(&self, latency: Duration) -> bool {
+        self.sync_latency.record(latency);
+        let slow = self.slow_io_threshold.exceeded_by(latency);
+        if slow {
+            self.slow_syncs.fetch_add(1, Ordering::Relaxed);
+        }
+        slow
+    }
+
+    pub(crate) fn flushes(&self) -> u64 {
+        self.flushes.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn snapshot(&self, flushed_lsn: Lsn) -> WalMetricsSnapshot {
+        WalMetricsSnapshot {
+            flushed_lsn,
+            flushes: self.flushes(),
+            flush_nanos: self.flush_nanos.load(Ordering::Relaxed),
+            bytes_flushed: self.bytes_flushed.load(Ordering::Relaxed),
+            sync_latency: self.sync_latency.snapshot(),
+            slow_syncs: self.slow_syncs.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// What a log manager has written since it was opened.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WalMetricsSnapshot {
+    pub flushed_lsn: Lsn,
+    // Writes+syncs of the log; with group commit one covers many commits.
+    pub flushes: u64,
+    // Time spent writing and syncing, summed over the flushes.
+    pub flush_nanos: u64,
+    pub bytes_flushed: u64,
+    // How long each sync of the log file took; nothing is synced with SyncMode::Off.
+    pub sync_latency: LatencyHistogramSnapshot,
+    // Syncs that took longer than the slow I/O threshold.
+    pub slow_syncs: u64,
+}
+
+impl WalMetricsSnapshot {
+    pub fn average_flush_latency(&self) -> Duration {
+        Duration::from_nanos(self.flush_nanos.checked_div(self.flushes).unwrap_or(0))
+    }
+}
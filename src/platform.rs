@@ -0,0 +1,132 @@
+// What crab-db needs from the platform it runs on: a clock and threads. Native builds get
+// both from std. wasm32-unknown-unknown has neither, so there disk requests run on the
+// calling thread, nothing runs in the background, and time comes from a clock the embedder
+// installs with `set_clock`, such as one reading `Date.now()` through wasm-bindgen.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use crate::types::{CrabDBError, CrabDbResult};
+
+// Whether the engine can run work on threads of its own.
+pub const HAS_THREADS: bool = cfg!(not(target_family = "wasm"));
+
+pub trait Clock: Send + Sync {
+    // Microseconds since the Unix epoch.
+    fn now_micros(&self) -> u64;
+
+    // Microseconds since some fixed point, for timing things. Never goes backwards.
+    fn monotonic_micros(&self) -> u64;
+}
+
+// The system clock, timed with `Instant`.
+#[cfg(not(target_family = "wasm"))]
+pub struct SystemClock {
+    started: std::time::Instant,
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock { started: std::time::Instant::now() }
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl Clock for SystemClock {
+    fn now_micros(&self) -> u64 {
+        std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |now| now.as_micros() as u64)
+    }
+
+    fn monotonic_micros(&self) -> u64 {
+        self.started.elapsed().as_micros() as u64
+    }
+}
+
+// A clock that only moves when told to: the default where there is no system clock, under
+// which timings read zero and key-value TTLs never run out.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    micros: AtomicU64,
+}
+
+impl ManualClock {
+    pub fn new(now_micros: u64) -> Self {
+        ManualClock { micros: AtomicU64::new(now_micros) }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.micros.fetch_add(by.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    // Moves the clock to `now_micros`, or leaves it if it is already past.
+    pub fn set(&self, now_micros: u64) {
+        self.micros.fetch_max(now_micros, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_micros(&self) -> u64 {
+        self.micros.load(Ordering::Relaxed)
+    }
+
+    fn monotonic_micros(&self) -> u64 {
+        self.now_micros()
+    }
+}
+
+static CLOCK: OnceLock<Arc<dyn Clock>> = OnceLock::new();
+
+// Makes `clock` the one the engine reads. Only possible before anything has read it.
+pub fn set_clock(clock: Arc<dyn Clock>) -> CrabDbResult<()> {
+    CLOCK.set(clock).map_err(|_| CrabDBError::InvalidInput("The clock is already in use and cannot be replaced".to_string()))
+}
+
+pub fn clock() -> &'static dyn Clock {
+    CLOCK.get_or_init(default_clock).as_ref()
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn default_clock() -> Arc<dyn Clock> {
+    Arc::new(SystemClock::default())
+}
+
+#[cfg(target_family = "wasm")]
+fn default_clock() -> Arc<dyn Clock> {
+    Arc::new(ManualClock::default())
+}
+
+// Times something on the engine's clock.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Stopwatch {
+    started: u64,
+}
+
+impl Stopwatch {
+    pub(crate) fn start() -> Self {
+        Stopwatch { started: clock().monotonic_micros() }
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        Duration::from_micros(clock().monotonic_micros().saturating_sub(self.started))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Clock, ManualClock};
+
+    #[test]
+    pub fn test_manual_clock_only_moves_forward() {
+        let clock = ManualClock::new(1_000);
+        clock.advance(Duration::from_millis(2));
+        assert_eq!(3_000, clock.now_micros());
+        clock.set(2_000);
+        assert_eq!(3_000, clock.monotonic_micros());
+        clock.set(5_000);
+        assert_eq!(5_000, clock.now_micros());
+    }
+}
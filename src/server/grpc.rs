@@ -0,0 +1,375 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tonic::{Request, Response, Status};
+
+use crate::concurrency::transaction::Transaction;
+use crate::db::crab_db::{CrabDb, StatementOutput, DEFAULT_SESSION};
+use crate::db::cursor::Cursor;
+use crate::db::engine::CrabDbEngine;
+use crate::types::schema::Column;
+use crate::types::value::{DataType, Value};
+use crate::types::{CrabDBError, ErrorCode};
+
+#[allow(clippy::all)]
+pub mod proto {
+    tonic::include_proto!("crab_db");
+}
+
+use proto::crab_db_server::CrabDbServer;
+use proto::value::Kind;
+
+// Rows per message of a Query stream.
+const ROWS_PER_RESPONSE: usize = 256;
+// Messages of a Query stream made ahead of the client reading them.
+const RESPONSES_AHEAD: usize = 4;
+
+fn status(e: CrabDBError) -> Status {
+    let message = e.to_string();
+    match e.code() {
+        ErrorCode::InvalidInput => Status::invalid_argument(message),
+        ErrorCode::NotFound => Status::not_found(message),
+        ErrorCode::Conflict | ErrorCode::Deadlock | ErrorCode::TxnAborted => Status::aborted(message),
+        ErrorCode::LockTimeout => Status::deadline_exceeded(message),
+        ErrorCode::ResourceExhausted => Status::resource_exhausted(message),
+        ErrorCode::Io => Status::unavailable(message),
+        ErrorCode::Corruption => Status::data_loss(message),
+        ErrorCode::Internal => Status::internal(message),
+    }
+}
+
+fn to_proto_value(value: Value) -> proto::Value {
+    let kind = match value {
+        Value::Null => Kind::Null(true),
+        Value::Boolean(b) => Kind::Boolean(b),
+        Value::Int32(n) => Kind::Int32(n),
+        Value::Int64(n) => Kind::Int64(n),
+        Value::Float64(f) => Kind::Float64(f),
+        Value::Varchar(s) => Kind::Varchar(s),
+        Value::Timestamp(micros) => Kind::Timestamp(micros),
+        Value::Vector(elements) => Kind::Vector(proto::Vector { elements }),
+        Value::Geometry(geometry) => Kind::Geometry(proto::Geometry {
+            min_x: geometry.min[0],
+            min_y: geometry.min[1],
+            max_x: geometry.max[0],
+            max_y: geometry.max[1],
+        }),
+    };
+    proto::Value { kind: Some(kind) }
+}
+
+fn to_proto_column(column: &Column) -> proto::Column {
+    let data_type = match column.data_type() {
+        DataType::Boolean => proto::DataType::Boolean,
+        DataType::Int32 => proto::DataType::Int32,
+        DataType::Int64 => proto::DataType::Int64,
+        DataType::Float64 => proto::DataType::Float64,
+        DataType::Varchar => proto::DataType::Varchar,
+        DataType::Timestamp => proto::DataType::Timestamp,
+        DataType::Vector(_) => proto::DataType::Vector,
+        DataType::Geometry => proto::DataType::Geometry,
+    };
+    let dimensions = match column.data_type() {
+        DataType::Vector(dimensions) => dimensions as u32,
+        _ => 0,
+    };
+    proto::Column { name: column.name().to_string(), data_type: data_type.into(), nullable: column.is_nullable(), dimensions }
+}
+
+// Metadata naming the database a request is for, when the service serves an engine's.
+pub const DATABASE_HEADER: &str = "database";
+
+// A database name, empty when there is only one, and a transaction id.
+type TransactionKey = (String, u64);
+
+// The databases requests go to.
+#[derive(Clone)]
+enum Databases {
+    One(Arc<CrabDb>),
+    // The open databases of an engine, by the name in DATABASE_HEADER.
+    Engine(Arc<CrabDbEngine>),
+}
+
+// The CrabDb gRPC service of proto/crab_db.proto. Statements run on tokio's blocking
+// threads. A query runs as its stream is read, until the stream ends or the client goes away.
+// A transaction runs one statement at a time; a transaction whose client goes away without
+// committing or rolling back stays open.
+#[derive(Clone)]
+pub struct GrpcService {
+    databases: Databases,
+    // The transactions BeginTransaction started, except those running a statement, by
+    // database name and id.
+    transactions: Arc<Mutex<HashMap<TransactionKey, Arc<Transaction>>>>,
+}
+
+impl GrpcService {
+    pub fn new(db: Arc<CrabDb>) -> Self {
+        Self::with_databases(Databases::One(db))
+    }
+
+    // Serves the open databases of `engine`: every request names the one it is for in its
+    // DATABASE_HEADER metadata, which a client sets once for its channel.
+    pub fn for_engine(engine: Arc<CrabDbEngine>) -> Self {
+        Self::with_databases(Databases::Engine(engine))
+    }
+
+    fn with_databases(databases: Databases) -> Self {
+        GrpcService { databases, transactions: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    pub fn into_server(self) -> CrabDbServer<Self> {
+        CrabDbServer::new(self)
+    }
+
+    // The database `request` is for, with its name; unnamed if there is only one.
+    fn route<T>(&self, request: &Request<T>) -> Result<(String, Arc<CrabDb>), Status> {
+        match &self.databases {
+            Databases::One(db) => Ok((String::new(), db.clone())),
+            Databases::Engine(engine) => {
+                let Some(name) = request.metadata().get(DATABASE_HEADER) else {
+                    return Err(Status::invalid_argument(format!("Name the database in the {DATABASE_HEADER} metadata")));
+                };
+                let name = name.to_str().map_err(|_| Status::invalid_argument("The database name is not ASCII"))?;
+                let db = engine.database(name).map_err(|e| Status::not_found(e.to_string()))?;
+                Ok((name.to_string(), db))
+            },
+        }
+    }
+
+    fn take_transaction(&self, database: &str, id: u64) -> Result<Arc<Transaction>, Status> {
+        self.transactions.lock().unwrap().remove(&(database.to_string(), id)).ok_or_else(|| {
+            Status::failed_precondition(format!("Transaction {id} does not exist or is running another statement"))
+        })
+    }
+
+    fn put_transaction(&self, database: String, txn: Arc<Transaction>) {
+        self.transactions.lock().unwrap().insert((database, txn.id()), txn);
+    }
+
+    // Runs `sql` on a blocking thread, in the transaction `transaction_id` if there is one.
+    async fn run(&self, (database, db): (String, Arc<CrabDb>), sql: String, transaction_id: Option<u64>) -> Result<StatementOutput, Status> {
+        let txn = transaction_id.map(|id| self.take_transaction(&database, id)).transpose()?;
+        let (txn, output) = tokio::task::spawn_blocking(move || match txn {
+            Some(txn) => {
+                let output = db.run_in_transaction(DEFAULT_SESSION, &txn, &sql);
+                (Some(txn), output)
+            },
+            None => (None, db.run(&sql)),
+        }).await.map_err(|e| Status::internal(e.to_string()))?;
+        if let Some(txn) = txn {
+            self.put_transaction(database, txn);
+        }
+        output.map_err(status)
+    }
+
+    // Opens a cursor over `sql` on a blocking thread, in the transaction `transaction_id` if
+    // there is one, and streams its rows from there. Until the stream ends, the transaction
+    // counts as running a statement.
+    async fn stream(
+        &self,
+        (database, db): (String, Arc<CrabDb>),
+        sql: String,
+        transaction_id: Option<u64>,
+    ) -> Result<<Self as proto::crab_db_server::CrabDb>::QueryStream, Status> {
+        let txn = transaction_id.map(|id| self.take_transaction(&database, id)).transpose()?;
+        let service = self.clone();
+        let (opened_tx, opened_rx) = tokio::sync::oneshot::channel();
+        let (responses_tx, responses_rx) = tokio::sync::mpsc::channel(RESPONSES_AHEAD);
+        tokio::task::spawn_blocking(move || {
+            let cursor = match &txn {
+                Some(txn) => db.query_in_transaction(DEFAULT_SESSION, txn, &sql),
+                None => db.query(&sql),
+            };
+            match cursor {
+                Ok(cursor) => {
+                    let _ = opened_tx.send(Ok(()));
+                    send_rows(cursor, &responses_tx);
+                },
+                Err(e) => {
+                    let _ = opened_tx.send(Err(status(e)));
+                },
+            }
+            if let Some(txn) = txn {
+                service.put_transaction(database, txn);
+            }
+        });
+        opened_rx.await.map_err(|e| Status::internal(e.to_string()))??;
+        Ok(tokio_stream::wrappers::ReceiverStream::new(responses_rx))
+    }
+}
+
+// Sends the columns of `cursor`, then its rows in batches, until they run out, one fails or
+// the client goes away.
+fn send_rows(mut cursor: Cursor, responses: &tokio::sync::mpsc::Sender<Result<proto::QueryResponse, Status>>) {
+    let columns = cursor.schema().columns().iter().map(to_proto_column).collect();
+    if responses.blocking_send(Ok(proto::QueryResponse { columns, rows: Vec::new() })).is_err() {
+        return;
+    }
+    loop {
+        let response = match cursor.fetch_next(ROWS_PER_RESPONSE) {
+            Ok(rows) if rows.is_empty() => return,
+            Ok(rows) => {
+                let rows = rows.into_iter().map(|row| proto::Row { values: row.into_iter().map(to_proto_value).collect() }).collect();
+                Ok(proto::QueryResponse { columns: Vec::new(), rows })
+            },
+            Err(e) => Err(status(e)),
+        };
+        let failed = response.is_err();
+        if responses.blocking_send(response).is_err() || failed {
+            return;
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl proto::crab_db_server::CrabDb for GrpcService {
+    type QueryStream = tokio_stream::wrappers::ReceiverStream<Result<proto::QueryResponse, Status>>;
+
+    async fn execute(&self, request: Request<proto::ExecuteRequest>) -> Result<Response<proto::ExecuteResponse>, Status> {
+        let target = self.route(&request)?;
+        let request = request.into_inner();
+        let output = self.run(target, request.sql, request.transaction_id).await?;
+        Ok(Response::new(proto::ExecuteResponse { count: output.count }))
+    }
+
+    async fn query(&self, request: Request<proto::QueryRequest>) -> Result<Response<Self::QueryStream>, Status> {
+        let target = self.route(&request)?;
+        let request = request.into_inner();
+        Ok(Response::new(self.stream(target, request.sql, request.transaction_id).await?))
+    }
+
+    async fn begin_transaction(
+        &self,
+        request: Request<proto::BeginTransactionRequest>,
+    ) -> Result<Response<proto::BeginTransactionResponse>, Status> {
+        let (database, db) = self.route(&request)?;
+        let txn = db.begin();
+        let transaction_id = txn.id();
+        self.put_transaction(database, txn);
+        Ok(Response::new(proto::BeginTransactionResponse { transaction_id }))
+    }
+
+    async fn commit(&self, request: Request<proto::CommitRequest>) -> Result<Response<proto::CommitResponse>, Status> {
+        let (database, db) = self.route(&request)?;
+        let txn = self.take_transaction(&database, request.into_inner().transaction_id)?;
+        tokio::task::spawn_blocking(move || db.commit(&txn))
+            .await.map_err(|e| Status::internal(e.to_string()))?
+            .map_err(status)?;
+        Ok(Response::new(proto::CommitResponse {}))
+    }
+
+    async fn rollback(&self, request: Request<proto::RollbackRequest>) -> Result<Response<proto::RollbackResponse>, Status> {
+        let (database, db) = self.route(&request)?;
+        let txn = self.take_transaction(&database, request.into_inner().transaction_id)?;
+        db.txn_manager().abort(&txn).map_err(status)?;
+        Ok(Response::new(proto::RollbackResponse {}))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tempfile::TempDir;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tokio_stream::StreamExt;
+    use tonic::transport::Server;
+
+    use crate::db::crab_db::CrabDb;
+    use crate::db::engine::CrabDbEngine;
+    use crate::db::options::{CrabDbOptions, DatabaseQuota, EngineOptions};
+    use super::proto::crab_db_client::CrabDbClient;
+    use super::proto::value::Kind;
+    use super::proto::{BeginTransactionRequest, CommitRequest, DataType, ExecuteRequest, QueryRequest, RollbackRequest};
+    use super::{GrpcService, DATABASE_HEADER};
+
+    #[test]
+    pub fn test_grpc_service_runs_statements_and_transactions() {
+        let dir = TempDir::new().unwrap();
+        let db = Arc::new(CrabDb::open(dir.path().join("crabs.db"), CrabDbOptions::default()).unwrap());
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            tokio::spawn(Server::builder()
+                .add_service(GrpcService::new(db).into_server())
+                .serve_with_incoming(TcpListenerStream::new(listener)));
+            let mut client = CrabDbClient::connect(format!("http://{address}")).await.unwrap();
+            let execute = |sql: &str, transaction_id| ExecuteRequest { sql: sql.to_string(), transaction_id };
+
+            client.execute(execute("CREATE TABLE crabs (id INT NOT NULL, name VARCHAR)", None)).await.unwrap();
+            let id = client.begin_transaction(BeginTransactionRequest {}).await.unwrap().into_inner().transaction_id;
+            let inserted = client.execute(execute("INSERT INTO crabs VALUES (1, 'ferris'), (2, NULL)", Some(id))).await.unwrap();
+            assert_eq!(2, inserted.into_inner().count);
+            // a failed statement leaves the transaction usable
+            let error = client.execute(execute("INSERT INTO crabs VALUES (NULL, 'nobody')", Some(id))).await.unwrap_err();
+            assert_eq!(tonic::Code::InvalidArgument, error.code());
+            client.commit(CommitRequest { transaction_id: id }).await.unwrap();
+            let error = client.commit(CommitRequest { transaction_id: id }).await.unwrap_err();
+            assert_eq!(tonic::Code::FailedPrecondition, error.code());
+
+            let id = client.begin_transaction(BeginTransactionRequest {}).await.unwrap().into_inner().transaction_id;
+            client.execute(execute("DELETE FROM crabs", Some(id))).await.unwrap();
+            client.rollback(RollbackRequest { transaction_id: id }).await.unwrap();
+
+            let sql = "SELECT id, name FROM crabs ORDER BY id".to_string();
+            let responses: Vec<_> = client.query(QueryRequest { sql, transaction_id: None }).await.unwrap()
+                .into_inner().collect::<Result<_, _>>().await.unwrap();
+            let columns: Vec<_> = responses[0].columns.iter().map(|column| (column.name.as_str(), column.data_type())).collect();
+            assert_eq!(vec![("id", DataType::Int32), ("name", DataType::Varchar)], columns);
+            let rows: Vec<Vec<_>> = responses[1..].iter().flat_map(|response| &response.rows)
+                .map(|row| row.values.iter().map(|value| value.kind.clone().unwrap()).collect())
+                .collect();
+            assert_eq!(vec![
+                vec![Kind::Int32(1), Kind::Varchar("ferris".to_string())],
+                vec![Kind::Int32(2), Kind::Null(true)],
+            ], rows);
+        });
+    }
+
+    #[test]
+    pub fn test_grpc_service_routes_requests_by_database() {
+        let dir = TempDir::new().unwrap();
+        let engine = Arc::new(CrabDbEngine::open(dir.path(), EngineOptions::default().pool_size(64)).unwrap());
+        for (name, crab) in [("reef", "ferris"), ("lagoon", "sebastian")] {
+            let db = engine.open_database(name, DatabaseQuota::default()).unwrap();
+            db.execute(&format!("CREATE TABLE crabs (name VARCHAR); INSERT INTO crabs VALUES ('{crab}')")).unwrap();
+        }
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            tokio::spawn(Server::builder()
+                .add_service(GrpcService::for_engine(engine).into_server())
+                .serve_with_incoming(TcpListenerStream::new(listener)));
+            let mut client = CrabDbClient::connect(format!("http://{address}")).await.unwrap();
+            let request = |database: Option<&str>, sql: &str| {
+                let mut request = tonic::Request::new(ExecuteRequest { sql: sql.to_string(), transaction_id: None });
+                if let Some(database) = database {
+                    request.metadata_mut().insert(DATABASE_HEADER, database.parse().unwrap());
+                }
+                request
+            };
+
+            let error = client.execute(request(None, "SELECT * FROM crabs")).await.unwrap_err();
+            assert_eq!(tonic::Code::InvalidArgument, error.code());
+            let error = client.execute(request(Some("abyss"), "SELECT * FROM crabs")).await.unwrap_err();
+            assert_eq!(tonic::Code::NotFound, error.code());
+            client.execute(request(Some("reef"), "INSERT INTO crabs VALUES ('larry')")).await.unwrap();
+            let count = |response: tonic::Response<super::proto::ExecuteResponse>| response.into_inner().count;
+            assert_eq!(2, count(client.execute(request(Some("reef"), "SELECT * FROM crabs")).await.unwrap()));
+            assert_eq!(1, count(client.execute(request(Some("lagoon"), "SELECT * FROM crabs")).await.unwrap()));
+
+            // a transaction belongs to the database it began in
+            let mut begin = tonic::Request::new(BeginTransactionRequest {});
+            begin.metadata_mut().insert(DATABASE_HEADER, "lagoon".parse().unwrap());
+            let id = client.begin_transaction(begin).await.unwrap().into_inner().transaction_id;
+            let mut commit = tonic::Request::new(CommitRequest { transaction_id: id });
+            commit.metadata_mut().insert(DATABASE_HEADER, "reef".parse().unwrap());
+            assert_eq!(tonic::Code::FailedPrecondition, client.commit(commit).await.unwrap_err().code());
+            let mut commit = tonic::Request::new(CommitRequest { transaction_id: id });
+            commit.metadata_mut().insert(DATABASE_HEADER, "lagoon".parse().unwrap());
+            client.commit(commit).await.unwrap();
+        });
+    }
+}
@@ -0,0 +1,4 @@
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "resp")]
+pub mod resp;
@@ -0,0 +1,562 @@
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::db::crab_db::CrabDb;
+use crate::db::engine::CrabDbEngine;
+use crate::kv::kv_store::{now_ms, KvStore};
+use crate::types::{CrabDBError, CrabDbResult};
+
+// Longest bulk string a client may send; values must fit in a page anyway.
+const MAX_BULK_SIZE: usize = 1 << 20;
+// Most arguments one command may have.
+const MAX_ARGUMENTS: usize = 1 << 16;
+// Keys SCAN returns when the client does not pass COUNT.
+const DEFAULT_SCAN_COUNT: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    fn ok() -> Self {
+        Reply::Simple("OK".to_string())
+    }
+
+    fn write(&self, out: &mut impl Write) -> std::io::Result<()> {
+        match self {
+            Reply::Simple(text) => write!(out, "+{text}\r\n"),
+            Reply::Error(text) => write!(out, "-{}\r\n", text.replace(['\r', '\n'], " ")),
+            Reply::Integer(n) => write!(out, ":{n}\r\n"),
+            Reply::Bulk(None) => write!(out, "$-1\r\n"),
+            Reply::Bulk(Some(bytes)) => {
+                write!(out, "${}\r\n", bytes.len())?;
+                out.write_all(bytes)?;
+                out.write_all(b"\r\n")
+            },
+            Reply::Array(replies) => {
+                write!(out, "*{}\r\n", replies.len())?;
+                replies.iter().try_for_each(|reply| reply.write(out))
+            },
+        }
+    }
+}
+
+fn protocol_error(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Protocol error: {message}"))
+}
+
+fn read_line(reader: &mut impl BufRead) -> std::io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    if reader.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    while matches!(line.last(), Some(b'\n' | b'\r')) {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+fn read_length(line: &[u8], max: usize) -> std::io::Result<usize> {
+    std::str::from_utf8(line).ok()
+        .and_then(|text| text.parse::<usize>().ok())
+        .filter(|&length| length <= max)
+        .ok_or_else(|| protocol_error("invalid length"))
+}
+
+// Reads the next command, either as an array of bulk strings, the way clients send them, or
+// as an inline line of words, the way people type them into telnet. None at end of stream.
+fn read_command(reader: &mut impl BufRead) -> std::io::Result<Option<Vec<Vec<u8>>>> {
+    loop {
+        let Some(line) = read_line(reader)? else {
+            return Ok(None);
+        };
+        let Some(count) = line.strip_prefix(b"*") else {
+            let words: Vec<_> = line.split(u8::is_ascii_whitespace).filter(|word| !word.is_empty()).map(<[u8]>::to_vec).collect();
+            if words.is_empty() {
+                continue;
+            }
+            return Ok(Some(words));
+        };
+        let count = read_length(count, MAX_ARGUMENTS)?;
+        let mut arguments = Vec::with_capacity(count);
+        for _ in 0..count {
+            let line = read_line(reader)?.ok_or_else(|| protocol_error("unexpected end of stream"))?;
+            let length = line.strip_prefix(b"$").ok_or_else(|| protocol_error("expected a bulk string"))?;
+            let mut argument = vec![0; read_length(length, MAX_BULK_SIZE)? + 2];
+            reader.read_exact(&mut argument)?;
+            if !argument.ends_with(b"\r\n") {
+                return Err(protocol_error("bulk string not terminated by CRLF"));
+            }
+            argument.truncate(argument.len() - 2);
+            arguments.push(argument);
+        }
+        if !arguments.is_empty() {
+            return Ok(Some(arguments));
+        }
+    }
+}
+
+// Redis glob matching: `*`, `?`, `[abc]`, `[^a-z]` and `\` escapes. Each element but `*`
+// matches one byte, so on a mismatch only the last `*` needs to take one more byte: earlier
+// ones taking more could not let the rest match where the last one's could not. That keeps
+// it to O(pattern * text) steps, however many `*`s a client sends.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    glob_match_counting(pattern, text).0
+}
+
+// `glob_match`, also returning how many steps it took.
+fn glob_match_counting(pattern: &[u8], text: &[u8]) -> (bool, usize) {
+    let (mut p, mut t) = (0, 0);
+    // The pattern after the last `*` seen and where in the text it matches from so far.
+    let mut star = None;
+    let mut steps = 0;
+    while t < text.len() {
+        steps += 1;
+        if pattern.get(p) == Some(&b'*') {
+            p += 1;
+            star = Some((p, t));
+            continue;
+        }
+        match match_one(&pattern[p..], text[t]) {
+            Some(rest) => {
+                p = pattern.len() - rest.len();
+                t += 1;
+            },
+            None => match star {
+                Some((after_star, from)) => {
+                    star = Some((after_star, from + 1));
+                    p = after_star;
+                    t = from + 1;
+                },
+                None => return (false, steps),
+            },
+        }
+    }
+    (pattern[p..].iter().all(|&c| c == b'*'), steps)
+}
+
+// Matches the first element of `pattern`, which is not `*`, against `c`; returns the rest of
+// the pattern if it matches. A class without its `]` matches nothing.
+fn match_one(pattern: &[u8], c: u8) -> Option<&[u8]> {
+    match pattern {
+        [] => None,
+        [b'?', rest @ ..] => Some(rest),
+        [b'[', rest @ ..] => {
+            let (negate, mut class) = match rest.split_first() {
+                Some((b'^', class)) => (true, class),
+                _ => (false, rest),
+            };
+            let mut matched = false;
+            loop {
+                match class {
+                    [] => return None,
+                    [b']', after @ ..] => {
+                        class = after;
+                        break;
+                    },
+                    [b'\\', escaped, after @ ..] | [escaped, after @ ..] if !matches!(after, [b'-', _, ..]) => {
+                        matched |= c == *escaped;
+                        class = after;
+                    },
+                    [low, b'-', high, after @ ..] => {
+                        matched |= (*low.min(high)..=*low.max(high)).contains(&c);
+                        class = after;
+                    },
+                    _ => return None,
+                }
+            }
+            (matched != negate).then_some(class)
+        },
+        [b'\\', escaped, rest @ ..] | [escaped, rest @ ..] => (c == *escaped).then_some(rest),
+    }
+}
+
+fn parse_integer(argument: &[u8]) -> Result<i64, Reply> {
+    std::str::from_utf8(argument).ok()
+        .and_then(|text| text.parse().ok())
+        .ok_or_else(|| Reply::Error("ERR value is not an integer or out of range".to_string()))
+}
+
+fn wrong_arguments(command: &str) -> Reply {
+    Reply::Error(format!("ERR wrong number of arguments for '{command}' command"))
+}
+
+fn syntax_error() -> Reply {
+    Reply::Error("ERR syntax error".to_string())
+}
+
+fn db_error(e: CrabDBError) -> Reply {
+    Reply::Error(format!("ERR {e}"))
+}
+
+// The databases connections use.
+enum Databases {
+    One(Arc<CrabDb>),
+    // The open databases of an engine; each connection picks one with SELECT.
+    Engine(Arc<CrabDbEngine>),
+}
+
+// Serves one of a CrabDb's key-value stores over RESP2, the Redis protocol, so Redis clients
+// can use it as a persistent cache. Supports PING, GET, SET (with EX, PX, NX and XX), DEL,
+// EXPIRE, TTL, SCAN (with MATCH and COUNT), SELECT and QUIT. Every command runs in a
+// transaction of its own. Expiry is the store's own (see `KvStore::put_with_ttl`), so keys
+// set through RESP and through the API are the same and vacuum reclaims expired ones either
+// way. Serving an engine, the store of the same name in each of its open databases is
+// served, and a connection names the database it uses with SELECT before anything else.
+pub struct RespServer {
+    databases: Databases,
+    store_name: String,
+}
+
+// The database and store a connection uses.
+struct Selected {
+    db: Arc<CrabDb>,
+    store: KvStore,
+}
+
+impl RespServer {
+    pub fn new(db: Arc<CrabDb>, store_name: &str) -> CrabDbResult<Self> {
+        db.kv_store(store_name)?;
+        Ok(RespServer { databases: Databases::One(db), store_name: store_name.to_string() })
+    }
+
+    // Serves the store called `store_name` of whichever open database of `engine` each
+    // connection selects.
+    pub fn for_engine(engine: Arc<CrabDbEngine>, store_name: &str) -> Self {
+        RespServer { databases: Databases::Engine(engine), store_name: store_name.to_string() }
+    }
+
+    // Accepts connections until accepting fails, serving each on a thread of its own.
+    pub fn serve(self: Arc<Self>, listener: TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept()?;
+            let server = self.clone();
+            std::thread::spawn(move || {
+                let _ = server.handle_connection(stream);
+            });
+        }
+    }
+
+    // Runs the commands a client sends until it quits or disconnects.
+    pub fn handle_connection(&self, stream: TcpStream) -> std::io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = BufWriter::new(stream);
+        let mut selected = match &self.databases {
+            Databases::One(db) => Some(self.select(db.clone()).map_err(std::io::Error::other)?),
+            Databases::Engine(_) => None,
+        };
+        loop {
+            let command = match read_command(&mut reader) {
+                Ok(Some(command)) => command,
+                Ok(None) => return Ok(()),
+                Err(e) => {
+                    Reply::Error(format!("ERR {e}")).write(&mut writer)?;
+                    return writer.flush();
+                },
+            };
+            let quit = command[0].eq_ignore_ascii_case(b"QUIT");
+            let reply = if quit { Reply::ok() } else { self.run(&mut selected, &command) };
+            reply.write(&mut writer)?;
+            // Only flush once the client has no pipelined commands left to read.
+            if quit || reader.buffer().is_empty() {
+                writer.flush()?;
+            }
+            if quit {
+                return Ok(());
+            }
+        }
+    }
+
+    fn select(&self, db: Arc<CrabDb>) -> CrabDbResult<Selected> {
+        let store = db.kv_store(&self.store_name)?;
+        Ok(Selected { db, store })
+    }
+
+    fn run(&self, selected: &mut Option<Selected>, command: &[Vec<u8>]) -> Reply {
+        let name = String::from_utf8_lossy(&command[0]).to_ascii_lowercase();
+        let arguments = &command[1..];
+        let result = match (name.as_str(), arguments) {
+            ("ping", []) => Ok(Reply::Simple("PONG".to_string())),
+            ("ping", [message]) => Ok(Reply::Bulk(Some(message.clone()))),
+            ("select", [database]) => self.select_database(selected, database),
+            ("ping" | "select", _) => Err(wrong_arguments(&name)),
+            _ => match selected {
+                Some(selected) => selected.run(&name, arguments),
+                None => Err(Reply::Error("ERR no database selected; SELECT one by name first".to_string())),
+            },
+        };
+        result.unwrap_or_else(|reply| reply)
+    }
+
+    fn select_database(&self, selected: &mut Option<Selected>, database: &[u8]) -> Result<Reply, Reply> {
+        let Databases::Engine(engine) = &self.databases else {
+            return Err(Reply::Error("ERR SELECT is only supported when serving several databases".to_string()));
+        };
+        let db = engine.database(&String::from_utf8_lossy(database)).map_err(db_error)?;
+        *selected = Some(self.select(db).map_err(db_error)?);
+        Ok(Reply::ok())
+    }
+}
+
+impl Selected {
+    fn run(&self, name: &str, arguments: &[Vec<u8>]) -> Result<Reply, Reply> {
+        match (name, arguments) {
+            ("get", [key]) => self.get(key),
+            ("set", [key, value, options @ ..]) => self.set(key, value, options),
+            ("del", keys) if !keys.is_empty() => self.del(keys),
+            ("expire", [key, seconds]) => self.expire(key, seconds),
+            ("ttl", [key]) => self.ttl(key),
+            ("scan", [cursor, options @ ..]) => self.scan(cursor, options),
+            ("get" | "set" | "del" | "expire" | "ttl" | "scan", _) => Err(wrong_arguments(name)),
+            _ => Err(Reply::Error(format!("ERR unknown command '{name}'"))),
+        }
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Reply, Reply> {
+        let txn = self.db.begin_transaction();
+        Ok(Reply::Bulk(self.store.get(txn.txn(), key).map_err(db_error)?))
+    }
+
+    fn set(&self, key: &[u8], value: &[u8], options: &[Vec<u8>]) -> Result<Reply, Reply> {
+        let (mut ttl, mut only_if) = (None, None);
+        let mut options = options.iter();
+        while let Some(option) = options.next() {
+            let option = String::from_utf8_lossy(option).to_ascii_uppercase();
+            match option.as_str() {
+                "EX" | "PX" => {
+                    let amount = parse_integer(options.next().ok_or_else(syntax_error)?)?;
+                    if amount <= 0 {
+                        return Err(Reply::Error("ERR invalid expire time in 'set' command".to_string()));
+                    }
+                    let ms = if option == "EX" { amount.saturating_mul(1000) } else { amount };
+                    ttl = Some(Duration::from_millis(ms as u64));
+                },
+                "NX" | "XX" if only_if.is_none() => only_if = Some(option == "XX"),
+                _ => return Err(syntax_error()),
+            }
+        }
+        let txn = self.db.begin_transaction();
+        if let Some(must_exist) = only_if {
+            if self.store.get(txn.txn(), key).map_err(db_error)?.is_some() != must_exist {
+                return Ok(Reply::Bulk(None));
+            }
+        }
+        match ttl {
+            Some(ttl) => self.store.put_with_ttl(txn.txn(), key, value, ttl),
+            None => self.store.put(txn.txn(), key, value),
+        }.map_err(db_error)?;
+        txn.commit().map_err(db_error)?;
+        Ok(Reply::ok())
+    }
+
+    fn del(&self, keys: &[Vec<u8>]) -> Result<Reply, Reply> {
+        let txn = self.db.begin_transaction();
+        let mut deleted = 0;
+        for key in keys {
+            if self.store.delete(txn.txn(), key).map_err(db_error)? {
+                deleted += 1;
+            }
+        }
+        txn.commit().map_err(db_error)?;
+        Ok(Reply::Integer(deleted))
+    }
+
+    // Like Redis, an expiry that is not in the future deletes the key.
+    fn expire(&self, key: &[u8], seconds: &[u8]) -> Result<Reply, Reply> {
+        let seconds = parse_integer(seconds)?;
+        let txn = self.db.begin_transaction();
+        let existed = if seconds <= 0 {
+            self.store.delete(txn.txn(), key)
+        } else {
+            self.store.expire(txn.txn(), key, Duration::from_secs(seconds as u64))
+        }.map_err(db_error)?;
+        txn.commit().map_err(db_error)?;
+        Ok(Reply::Integer(existed as i64))
+    }
+
+    fn ttl(&self, key: &[u8]) -> Result<Reply, Reply> {
+        let now = now_ms();
+        let txn = self.db.begin_transaction();
+        Ok(Reply::Integer(match self.store.get_with_expiry(txn.txn(), key).map_err(db_error)? {
+            None => -2,
+            Some((_, None)) => -1,
+            Some((_, Some(expires_at))) => expires_at.saturating_sub(now).div_ceil(1000) as i64,
+        }))
+    }
+
+    // The cursor counts the live keys before the next one to return, so keys written or
+    // deleted between calls may shift which keys a later call returns.
+    fn scan(&self, cursor: &[u8], options: &[Vec<u8>]) -> Result<Reply, Reply> {
+        let cursor = usize::try_from(parse_integer(cursor)?).map_err(|_| Reply::Error("ERR invalid cursor".to_string()))?;
+        let (mut pattern, mut count) = (None, DEFAULT_SCAN_COUNT);
+        let mut options = options.iter();
+        while let Some(option) = options.next() {
+            let value = options.next().ok_or_else(syntax_error)?;
+            match String::from_utf8_lossy(option).to_ascii_uppercase().as_str() {
+                "MATCH" => pattern = Some(value),
+                "COUNT" => count = usize::try_from(parse_integer(value)?).ok().filter(|&count| count > 0).ok_or_else(syntax_error)?,
+                _ => return Err(syntax_error()),
+            }
+        }
+        let txn = self.db.begin_transaction();
+        let mut entries = self.store.scan::<&[u8]>(txn.txn(), ..).map_err(db_error)?
+            .map(|entry| entry.map(|(key, _)| key))
+            .skip(cursor);
+        let mut keys = Vec::new();
+        for key in entries.by_ref().take(count) {
+            let key = key.map_err(db_error)?;
+            if pattern.is_none_or(|pattern| glob_match(pattern, &key)) {
+                keys.push(Reply::Bulk(Some(key)));
+            }
+        }
+        let next = if entries.next().is_some() { cursor + count } else { 0 };
+        Ok(Reply::Array(vec![Reply::Bulk(Some(next.to_string().into_bytes())), Reply::Array(keys)]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Arc;
+
+    use tempfile::TempDir;
+
+    use crate::db::crab_db::CrabDb;
+    use crate::db::engine::CrabDbEngine;
+    use crate::db::options::{CrabDbOptions, DatabaseQuota, EngineOptions};
+    use super::{glob_match, glob_match_counting, RespServer};
+
+    fn reply(reader: &mut impl BufRead) -> String {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        match line.as_bytes()[0] {
+            b'$' if !line.starts_with("$-1") => {
+                let mut bulk = vec![0; line[1..].trim().parse::<usize>().unwrap() + 2];
+                reader.read_exact(&mut bulk).unwrap();
+                line + &String::from_utf8(bulk).unwrap()
+            },
+            b'*' => (0..line[1..].trim().parse().unwrap()).fold(line, |line, _| line + &reply(reader)),
+            _ => line,
+        }
+    }
+
+    #[test]
+    pub fn test_resp_server_speaks_redis() {
+        assert!(glob_match(b"crab:*:[a-c]?", b"crab:42:bx"));
+        assert!(!glob_match(b"crab:[^a-c]", b"crab:b"));
+        assert!(glob_match(b"\\*", b"*"));
+        assert!(glob_match(b"*crab*", b"crab") && glob_match(b"a*b*c", b"aXbYbc") && !glob_match(b"a*b", b"aXbY"));
+        assert!(glob_match(b"[a-c]\\", b"b\\") && !glob_match(b"[abc", b"a"));
+        // a pattern of many stars against a long key that just misses is quick, not exponential
+        let pattern = [b"a*".repeat(30), b"b".to_vec()].concat();
+        let (matched, steps) = glob_match_counting(&pattern, &[b'a'; 100]);
+        assert!(!matched);
+        assert!(steps <= pattern.len() * 100, "{steps} steps");
+
+        let dir = TempDir::new().unwrap();
+        let db = Arc::new(CrabDb::open(dir.path().join("cache.db"), CrabDbOptions::default()).unwrap());
+        let server = RespServer::new(db.clone(), "cache").unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            // Sends commands, pipelined if there are several, and reads a reply to each.
+            let mut send = |commands: &[&str]| -> Vec<String> {
+                stream.write_all(commands.concat().as_bytes()).unwrap();
+                commands.iter().map(|_| reply(&mut reader)).collect()
+            };
+            let mut replies = send(&[
+                "*3\r\n$3\r\nSET\r\n$6\r\nferris\r\n$4\r\nrust\r\n",
+                "GET ferris\r\n",
+                "SET ferris other NX\r\n",
+                "TTL ferris\r\n",
+                "EXPIRE ferris 100\r\n",
+                "TTL ferris\r\n",
+                "SET larry lobster PX 1\r\n",
+                "SET gone soon PX 1\r\n",
+            ]);
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            replies.extend(send(&["GET larry\r\n", "SET crab:1 a\r\n", "SET crab:2 b\r\n", "SET sebastian c\r\n"]));
+            for command in ["SCAN 0 COUNT 2\r\n", "SCAN 2 MATCH crab:*\r\n", "DEL crab:1 larry nobody\r\n", "FLY away\r\n", "QUIT\r\n"] {
+                replies.extend(send(&[command]));
+            }
+            replies
+        });
+        let (stream, _) = listener.accept().unwrap();
+        server.handle_connection(stream).unwrap();
+        assert_eq!(vec![
+            "+OK\r\n",
+            "$4\r\nrust\r\n",
+            "$-1\r\n",
+            ":-1\r\n",
+            ":1\r\n",
+            ":100\r\n",
+            "+OK\r\n",
+            "+OK\r\n",
+            "$-1\r\n",
+            "+OK\r\n",
+            "+OK\r\n",
+            "+OK\r\n",
+            "*2\r\n$1\r\n2\r\n*2\r\n$6\r\ncrab:1\r\n$6\r\ncrab:2\r\n",
+            "*2\r\n$1\r\n0\r\n*0\r\n",
+            ":1\r\n",
+            "-ERR unknown command 'fly'\r\n",
+            "+OK\r\n",
+        ], client.join().unwrap());
+
+        // keys set over RESP are the store's, expiry included
+        let store = db.kv_store("cache").unwrap();
+        let txn = db.begin_transaction();
+        let (value, expires_at) = store.get_with_expiry(txn.txn(), b"ferris").unwrap().unwrap();
+        assert_eq!((b"rust".to_vec(), true), (value, expires_at.is_some()));
+        txn.commit().unwrap();
+        assert_eq!(1, db.vacuum().unwrap().expired_keys_removed);
+    }
+
+    #[test]
+    pub fn test_resp_server_routes_connections_by_database() {
+        let dir = TempDir::new().unwrap();
+        let engine = Arc::new(CrabDbEngine::open(dir.path(), EngineOptions::default().pool_size(64)).unwrap());
+        for name in ["reef", "lagoon"] {
+            engine.open_database(name, DatabaseQuota::default()).unwrap();
+        }
+        let server = Arc::new(RespServer::for_engine(engine.clone(), "cache"));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || server.serve(listener));
+        // Sends each command on a connection of its own and reads the replies.
+        let session = |commands: &[&str]| -> Vec<String> {
+            let mut stream = TcpStream::connect(address).unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            commands.iter().map(|command| {
+                stream.write_all(command.as_bytes()).unwrap();
+                reply(&mut reader)
+            }).collect()
+        };
+
+        assert_eq!(vec![
+            "+PONG\r\n",
+            "-ERR no database selected; SELECT one by name first\r\n",
+            "-ERR Database abyss is not open\r\n",
+            "+OK\r\n",
+            "+OK\r\n",
+            "$6\r\nferris\r\n",
+        ], session(&["PING\r\n", "GET crab\r\n", "SELECT abyss\r\n", "SELECT reef\r\n", "SET crab ferris\r\n", "GET crab\r\n"]));
+        assert_eq!(
+            vec!["+OK\r\n", "$-1\r\n", "+OK\r\n", "+OK\r\n", "$6\r\nferris\r\n"],
+            session(&["SELECT lagoon\r\n", "GET crab\r\n", "SET crab sebastian\r\n", "SELECT reef\r\n", "GET crab\r\n"])
+        );
+        let lagoon = engine.database("lagoon").unwrap();
+        let txn = lagoon.begin_transaction();
+        assert_eq!(Some(b"sebastian".to_vec()), lagoon.kv_store("cache").unwrap().get(txn.txn(), b"crab").unwrap());
+    }
+}
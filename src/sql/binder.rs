@@ -0,0 +1,1511 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use sqlparser::ast::{
+    self, BinaryOperator, ColumnOption, CreateIndex, CopyLegacyCsvOption, CopyLegacyOption, CopyOption, CopySource, CopyTarget, Expr, FromTable, FunctionArg, FunctionArgExpr, FunctionArguments, GroupByExpr,
+    Ident, IndexColumn, IndexType, JoinConstraint, JoinOperator, LimitClause, NullsDistinctOption, ObjectName, OrderByKind, PrimaryKeyConstraint, Query, Select,
+    SearchModifier, SelectItem, SelectItemQualifiedWildcardKind, SetExpr, Statement, TableConstraint, TableFactor, TableObject, TableWithJoins, UnaryOperator,
+    UniqueConstraint,
+};
+use sqlparser::dialect::GenericDialect;
+use sqlparser::parser::Parser;
+use sqlparser::tokenizer::{Token, TokenWithSpan, Tokenizer, Whitespace};
+
+use crate::catalog::system_catalog::{Catalog, SessionId};
+use crate::catalog::table_info::{MaterializedView, Oid, TableInfo, Ttl};
+use crate::concurrency::transaction_manager::AsOf;
+use crate::catalog::udf::{coercible, Udf};
+use crate::execution::aggregation_executor::{AggregateExpression, AggregateFunction};
+use crate::execution::copy::CopyFormat;
+use crate::execution::expression::{ArithmeticOp, CompareOp, Expression, SpatialOp};
+use crate::execution::join::JoinType;
+use crate::execution::sort_executor::SortKey;
+use crate::index::full_text::tokenize;
+use crate::index::table_index::IndexKind;
+use crate::types::geometry::Geometry;
+use crate::types::schema::{Column, Schema};
+use crate::types::value::{DataType, Value};
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::logical_plan::{BoundStatement, LogicalPlan, UniqueKey};
+
+pub fn parse(sql: &str) -> CrabDbResult<Vec<Statement>> {
+    parse_as_of(sql)?.into_iter()
+        .map(|(statement, as_of)| match as_of {
+            None => Ok(statement),
+            Some(_) => Err(unsupported("AS OF here")),
+        })
+        .collect()
+}
+
+// The procedure `REFRESH MATERIALIZED VIEW name` is parsed as a call of, with the name of the
+// view as its argument.
+pub(crate) const REFRESH_PROCEDURE: &str = "crab_refresh_materialized_view";
+
+// Parses statements, each with the point in time it reads the database as of if it ends in
+//   AS OF TIMESTAMP <microseconds since the Unix epoch>
+//   AS OF LSN <position in the write-ahead log>
+// which the parser has no syntax for, so the clause is cut off before parsing. Neither has
+// it `<->`, which it reads as `<` then `->`, so the two are joined into an operator of its own,
+// nor REFRESH MATERIALIZED VIEW, which becomes a call of `REFRESH_PROCEDURE`.
+pub fn parse_as_of(sql: &str) -> CrabDbResult<Vec<(Statement, Option<AsOf>)>> {
+    let dialect = GenericDialect {};
+    let error = |e: &dyn std::fmt::Display| CrabDBError::InvalidInput(format!("Cannot parse SQL: {e}"));
+    let mut tokens = Tokenizer::new(&dialect, sql).tokenize_with_location().map_err(|e| error(&e))?;
+    let mut i = 0;
+    while i + 1 < tokens.len() {
+        if tokens[i].token == Token::Lt && tokens[i + 1].token == Token::Arrow {
+            let span = tokens[i].span.union(&tokens[i + 1].span);
+            tokens.splice(i..i + 2, [TokenWithSpan::new(Token::CustomBinaryOperator("<->".into()), span)]);
+        }
+        i += 1;
+    }
+
+    let mut statements = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for end in 0..=tokens.len() {
+        match tokens.get(end).map(|token| &token.token) {
+            Some(Token::LParen) => depth += 1,
+            Some(Token::RParen) => depth = depth.saturating_sub(1),
+            Some(Token::SemiColon) | None if depth == 0 => {
+                let mut statement = tokens[start..end].to_vec();
+                start = end + 1;
+                if statement.iter().all(|token| matches!(token.token, Token::Whitespace(_))) {
+                    continue;
+                }
+                let as_of = as_of_clause(&mut statement)?;
+                refresh_as_call(&mut statement);
+                for parsed in Parser::new(&dialect).with_tokens_with_locations(statement).parse_statements().map_err(|e| error(&e))? {
+                    statements.push((parsed, as_of));
+                }
+            },
+            _ => {},
+        }
+    }
+    Ok(statements)
+}
+
+// Takes the AS OF clause off the end of a statement's tokens.
+fn as_of_clause(tokens: &mut Vec<TokenWithSpan>) -> CrabDbResult<Option<AsOf>> {
+    let words: Vec<usize> = (0..tokens.len()).filter(|&i| !matches!(tokens[i].token, Token::Whitespace(_))).collect();
+    let [.., as_, of, kind, value] = words[..] else {
+        return Ok(None);
+    };
+    let keyword = |i: usize, keyword: &str| {
+        matches!(&tokens[i].token, Token::Word(word) if word.quote_style.is_none() && word.value.eq_ignore_ascii_case(keyword))
+    };
+    if !keyword(as_, "as") || !keyword(of, "of") {
+        return Ok(None);
+    }
+    let Token::Number(digits, _) = &tokens[value].token else {
+        return Err(CrabDBError::InvalidInput(format!("AS OF takes a number, not {}", tokens[value].token)));
+    };
+    let number = digits.parse::<u64>().map_err(|_| CrabDBError::InvalidInput(format!("Invalid AS OF {digits}")))?;
+    let as_of = if keyword(kind, "timestamp") {
+        AsOf::Timestamp(number)
+    } else if keyword(kind, "lsn") {
+        AsOf::Lsn(number)
+    } else {
+        return Err(CrabDBError::InvalidInput(format!("AS OF takes TIMESTAMP or LSN, not {}", tokens[kind].token)));
+    };
+    tokens.truncate(as_);
+    Ok(Some(as_of))
+}
+
+// Rewrites `REFRESH MATERIALIZED VIEW name` into `CALL <REFRESH_PROCEDURE>(name)`.
+fn refresh_as_call(tokens: &mut Vec<TokenWithSpan>) {
+    let words: Vec<usize> = (0..tokens.len()).filter(|&i| !matches!(tokens[i].token, Token::Whitespace(_))).collect();
+    let [refresh, materialized, view, ..] = words[..] else {
+        return;
+    };
+    let keyword = |i: usize, keyword: &str| {
+        matches!(&tokens[i].token, Token::Word(word) if word.quote_style.is_none() && word.value.eq_ignore_ascii_case(keyword))
+    };
+    if !keyword(refresh, "refresh") || !keyword(materialized, "materialized") || !keyword(view, "view") {
+        return;
+    }
+    let call = [Token::make_keyword("CALL"), Token::Whitespace(Whitespace::Space), Token::make_word(REFRESH_PROCEDURE, None), Token::LParen];
+    tokens.splice(..=view, call.map(TokenWithSpan::wrap));
+    tokens.push(TokenWithSpan::wrap(Token::RParen));
+}
+
+fn unsupported(what: impl std::fmt::Display) -> CrabDBError {
+    CrabDBError::InvalidInput(format!("{what} is not supported"))
+}
+
+// The columns expressions can refer to at some point of a query, one per column of the row
+// they are evaluated on: the table the column came from (its alias if it has one) and the
+// column's name. Names are case-insensitive, like in the catalog.
+#[derive(Clone, Default)]
+struct Scope {
+    columns: Vec<(Option<String>, String)>,
+    types: Vec<DataType>,
+    // The table and position each column is read from, for columns of a table read directly.
+    sources: Vec<Option<(Oid, usize)>>,
+}
+
+impl Scope {
+    fn new(qualifier: Option<&str>, schema: &Schema) -> Self {
+        let columns: Vec<_> = schema.columns().iter()
+            .map(|column| (qualifier.map(str::to_string), column.name().to_string()))
+            .collect();
+        let types = schema.columns().iter().map(Column::data_type).collect();
+        Scope { sources: vec![None; columns.len()], columns, types }
+    }
+
+    fn of_table(qualifier: Option<&str>, table: &TableInfo) -> Self {
+        let mut scope = Scope::new(qualifier, table.schema());
+        scope.sources = (0..scope.columns.len()).map(|column| Some((table.oid(), column))).collect();
+        scope
+    }
+
+    fn join(mut self, right: Scope) -> Self {
+        self.columns.extend(right.columns);
+        self.types.extend(right.types);
+        self.sources.extend(right.sources);
+        self
+    }
+
+    // The rows of the scope, for typing expressions over them.
+    fn schema(&self) -> Schema {
+        Schema::new(self.columns.iter().zip(&self.types).map(|((_, name), data_type)| Column::new(name.clone(), *data_type)).collect())
+    }
+
+    fn resolve(&self, qualifier: Option<&str>, name: &str) -> CrabDbResult<usize> {
+        let mut matches = self.columns.iter().enumerate().filter(|(_, (column_qualifier, column_name))| {
+            column_name.eq_ignore_ascii_case(name)
+                && qualifier.is_none_or(|qualifier| column_qualifier.as_ref().is_some_and(|q| q.eq_ignore_ascii_case(qualifier)))
+        });
+        let full_name = qualifier.map_or(name.to_string(), |qualifier| format!("{qualifier}.{name}"));
+        match (matches.next(), matches.next()) {
+            (Some((index, _)), None) => Ok(index),
+            (None, _) => Err(CrabDBError::InvalidInput(format!("Column {full_name} does not exist"))),
+            (Some(_), Some(_)) => Err(CrabDBError::InvalidInput(format!("Column {full_name} is ambiguous"))),
+        }
+    }
+}
+
+// What a query with GROUP BY or aggregates computes below its projection. While binding the
+// expressions above it, group-by expressions and aggregate calls become references to the
+// aggregation's output: the group-by values first, then the aggregates.
+struct Aggregation {
+    group_by: Vec<Expression>,
+    group_by_names: Vec<String>,
+    aggregates: Vec<AggregateExpression>,
+    aggregate_names: Vec<String>,
+}
+
+impl Aggregation {
+    // The output rows of the aggregation over rows of `input`.
+    fn schema(&self, input: &Schema) -> Schema {
+        let mut columns = Vec::new();
+        for (expr, name) in self.group_by.iter().zip(&self.group_by_names) {
+            columns.push(Column::new(name.clone(), expr.data_type(input)));
+        }
+        for (aggregate, name) in self.aggregates.iter().zip(&self.aggregate_names) {
+            let argument_type = aggregate.argument.data_type(input);
+            let data_type = match &aggregate.function {
+                AggregateFunction::CountStar | AggregateFunction::Count => DataType::Int64,
+                AggregateFunction::Sum if argument_type == DataType::Float64 => DataType::Float64,
+                AggregateFunction::Sum => DataType::Int64,
+                AggregateFunction::Min | AggregateFunction::Max => argument_type,
+                AggregateFunction::Avg => DataType::Float64,
+                AggregateFunction::User(function) => function.return_type(),
+            };
+            columns.push(Column::new(name.clone(), data_type));
+        }
+        Schema::new(columns)
+    }
+}
+
+// Checks that an argument of a user-defined function, over rows of `input`, converts to the
+// type the function takes. A bare NULL goes anywhere.
+fn check_argument(function: &ObjectName, argument: &Expression, input: &Schema, data_type: DataType) -> CrabDbResult<()> {
+    let argument_type = argument.data_type(input);
+    if *argument == Expression::Constant(Value::Null) || coercible(argument_type, data_type) {
+        return Ok(());
+    }
+    Err(CrabDBError::InvalidInput(format!("{function} takes {data_type} but got {argument_type}")))
+}
+
+fn spatial_op(name: &ObjectName) -> Option<SpatialOp> {
+    match name.to_string().to_ascii_lowercase().as_str() {
+        "st_contains" => Some(SpatialOp::Contains),
+        "st_intersects" => Some(SpatialOp::Intersects),
+        _ => None,
+    }
+}
+
+// A geometry written as text, like 'POINT(1 2)' or 'BOX(0 0, 3 4)', read as one.
+fn geometry(expression: Expression) -> CrabDbResult<Expression> {
+    match expression {
+        Expression::Constant(Value::Varchar(text)) => Geometry::parse(&text).map(|geometry| Expression::Constant(Value::Geometry(geometry))),
+        expression => Ok(expression),
+    }
+}
+
+// The name a select item's column gets when it has no alias.
+fn column_name(expr: &Expr) -> String {
+    match expr {
+        Expr::Identifier(ident) => ident.value.clone(),
+        Expr::CompoundIdentifier(idents) => idents.last().map_or(String::new(), |ident| ident.value.clone()),
+        other => other.to_string(),
+    }
+}
+
+fn table_name(name: &ObjectName) -> CrabDbResult<String> {
+    match name.0.as_slice() {
+        [part] => part.as_ident().map(|ident| ident.value.clone()).ok_or_else(|| unsupported(format!("Table name {name}"))),
+        _ => Err(unsupported(format!("Qualified table name {name}"))),
+    }
+}
+
+// A PRIMARY KEY or UNIQUE constraint as written: its name, whether it is the primary key,
+// and its columns.
+type KeyConstraint<'a> = (Option<&'a Ident>, bool, Vec<String>);
+
+fn primary_key_constraint(constraint: &PrimaryKeyConstraint) -> CrabDbResult<()> {
+    if constraint.index_name.is_some() || constraint.index_type.is_some() || !constraint.include.is_empty()
+        || !constraint.index_options.is_empty() || constraint.characteristics.is_some()
+    {
+        return Err(unsupported(format!("Constraint option in {constraint}")));
+    }
+    Ok(())
+}
+
+// NULL is a key like any other in an index, so a UNIQUE column holds at most one NULL, as
+// with NULLS NOT DISTINCT.
+fn unique_constraint(constraint: &UniqueConstraint) -> CrabDbResult<()> {
+    if constraint.index_name.is_some() || constraint.index_type.is_some() || !constraint.include.is_empty()
+        || !constraint.index_options.is_empty() || constraint.characteristics.is_some()
+        || constraint.nulls_distinct == NullsDistinctOption::Distinct
+    {
+        return Err(unsupported(format!("Constraint option in {constraint}")));
+    }
+    Ok(())
+}
+
+fn key_columns(columns: &[IndexColumn]) -> CrabDbResult<Vec<String>> {
+    columns.iter().map(|column| match &column.column.expr {
+        Expr::Identifier(ident) if column.column.options.sort.is_none() && column.column.options.nulls_first.is_none() => {
+            Ok(ident.value.clone())
+        },
+        _ => Err(unsupported(format!("Key column {column}"))),
+    }).collect()
+}
+
+// WITH (ttl_column = 'column', ttl_seconds = n) makes rows expire n seconds past their
+// timestamp in the column, or as soon as it passes if ttl_seconds is left out.
+fn table_ttl(name: &str, options: &ast::CreateTableOptions, columns: &[Column]) -> CrabDbResult<Option<Ttl>> {
+    let options = match options {
+        ast::CreateTableOptions::None => return Ok(None),
+        ast::CreateTableOptions::With(options) => options,
+        other => return Err(unsupported(format!("Table options {other}"))),
+    };
+    let (mut column, mut after) = (None, Duration::ZERO);
+    for option in options {
+        let ast::SqlOption::KeyValue { key, value } = option else {
+            return Err(unsupported(format!("Table option {option}")));
+        };
+        let value = match value {
+            Expr::Value(value) => literal(&value.value)?,
+            Expr::Identifier(ident) => Value::Varchar(ident.value.clone()),
+            other => return Err(CrabDBError::InvalidInput(format!("Table option {key} must be a literal, not {other}"))),
+        };
+        match (key.value.to_lowercase().as_str(), value) {
+            ("ttl_column", Value::Varchar(ttl_column)) => column = Some(ttl_column),
+            ("ttl_seconds", value) => match value.as_i64() {
+                Some(seconds) if seconds >= 0 => after = Duration::from_secs(seconds as u64),
+                _ => return Err(CrabDBError::InvalidInput(format!("ttl_seconds of table {name} must be a number of seconds"))),
+            },
+            ("ttl_column", _) => return Err(CrabDBError::InvalidInput(format!("ttl_column of table {name} must name a column"))),
+            _ => return Err(unsupported(format!("Table option {key}"))),
+        }
+    }
+    let Some(column) = column else {
+        return Err(CrabDBError::InvalidInput(format!("The TTL of table {name} needs a ttl_column")));
+    };
+    let column = columns.iter().position(|candidate| candidate.name().eq_ignore_ascii_case(&column))
+        .ok_or_else(|| CrabDBError::InvalidInput(format!("Column {column} does not exist in table {name}")))?;
+    if columns[column].data_type() != DataType::Timestamp {
+        return Err(CrabDBError::InvalidInput(format!("The TTL of table {name} must count from a TIMESTAMP column")));
+    }
+    Ok(Some(Ttl { column, after }))
+}
+
+fn data_type(data_type: &ast::DataType) -> CrabDbResult<DataType> {
+    use ast::DataType as Sql;
+    match data_type {
+        Sql::Bool | Sql::Boolean => Ok(DataType::Boolean),
+        Sql::SmallInt(_) | Sql::Int(_) | Sql::Int4(_) | Sql::Int32 | Sql::Integer(_) => Ok(DataType::Int32),
+        Sql::BigInt(_) | Sql::Int8(_) | Sql::Int64 => Ok(DataType::Int64),
+        Sql::Float(_) | Sql::Float8 | Sql::Float64 | Sql::Real | Sql::Double(_) | Sql::DoublePrecision => Ok(DataType::Float64),
+        Sql::Char(_) | Sql::CharVarying(_) | Sql::Varchar(_) | Sql::Text | Sql::String(_) => Ok(DataType::Varchar),
+        Sql::Timestamp(..) => Ok(DataType::Timestamp),
+        Sql::Custom(name, arguments) if name.to_string().eq_ignore_ascii_case("geometry") && arguments.is_empty() => Ok(DataType::Geometry),
+        Sql::Custom(name, arguments) if name.to_string().eq_ignore_ascii_case("vector") => {
+            match arguments.as_slice() {
+                [dimensions] => match dimensions.parse::<u16>() {
+                    Ok(dimensions) if dimensions > 0 => Ok(DataType::Vector(dimensions)),
+                    _ => Err(CrabDBError::InvalidInput(format!("Invalid number of dimensions in {data_type}"))),
+                },
+                _ => Err(CrabDBError::InvalidInput(format!("{data_type} needs a number of dimensions, as in VECTOR(3)"))),
+            }
+        },
+        other => Err(unsupported(format!("Data type {other}"))),
+    }
+}
+
+fn literal(value: &ast::Value) -> CrabDbResult<Value> {
+    match value {
+        ast::Value::Number(digits, _) => {
+            if let Ok(i) = digits.parse::<i64>() {
+                return Ok(i32::try_from(i).map_or(Value::Int64(i), Value::Int32));
+            }
+            digits.parse::<f64>().map(Value::Float64).map_err(|_| CrabDBError::InvalidInput(format!("Invalid number {digits}")))
+        },
+        ast::Value::SingleQuotedString(s) => Ok(Value::Varchar(s.clone())),
+        ast::Value::Boolean(b) => Ok(Value::Boolean(*b)),
+        ast::Value::Null => Ok(Value::Null),
+        other => Err(unsupported(format!("Literal {other}"))),
+    }
+}
+
+// The terms of a full-text search query, each once.
+fn search_terms(query: &ast::Value) -> CrabDbResult<Vec<String>> {
+    let ast::Value::SingleQuotedString(query) = query else {
+        return Err(CrabDBError::InvalidInput(format!("Search query {query} must be a string")));
+    };
+    let mut terms = tokenize(query);
+    let mut seen = std::collections::HashSet::new();
+    terms.retain(|term| seen.insert(term.clone()));
+    if terms.is_empty() {
+        return Err(CrabDBError::InvalidInput(format!("Search query '{query}' has no terms")));
+    }
+    Ok(terms)
+}
+
+// Resolves parsed statements against the catalog, turning them into bound statements whose
+// queries are logical plans. Placeholders $1, $2, ... stand for the parameters given, in order.
+// In a session, names resolve to its temporary tables first.
+pub struct Binder<'a> {
+    catalog: &'a Catalog,
+    parameters: &'a [Value],
+    session: Option<SessionId>,
+}
+
+impl<'a> Binder<'a> {
+    pub fn new(catalog: &'a Catalog) -> Self {
+        Binder { catalog, parameters: &[], session: None }
+    }
+
+    pub fn with_parameters(mut self, parameters: &'a [Value]) -> Self {
+        self.parameters = parameters;
+        self
+    }
+
+    pub fn in_session(mut self, session: SessionId) -> Self {
+        self.session = Some(session);
+        self
+    }
+
+    pub fn bind(&self, statement: &Statement) -> CrabDbResult<BoundStatement> {
+        match statement {
+            Statement::CreateTable(create) => self.bind_create_table(create),
+            Statement::CreateIndex(create) => self.bind_create_index(create),
+            Statement::CreateView(create) => self.bind_create_view(create),
+            Statement::Call(function) if function.name.to_string().eq_ignore_ascii_case(REFRESH_PROCEDURE) => self.bind_refresh(function),
+            Statement::Insert(insert) => self.bind_insert(insert),
+            Statement::Query(query) => Ok(BoundStatement::Select(self.bind_query(query)?)),
+            Statement::Update(update) => self.bind_update(update),
+            Statement::Delete(delete) => self.bind_delete(delete),
+            Statement::Explain { statement, analyze, .. } => {
+                let statement = self.bind(statement)?;
+                if matches!(
+                    statement,
+                    BoundStatement::CreateTable { .. }
+                        | BoundStatement::CreateIndex { .. }
+                        | BoundStatement::CreateMaterializedView { .. }
+                        | BoundStatement::RefreshMaterializedView { .. }
+                        | BoundStatement::Copy { .. }
+                        | BoundStatement::Explain { .. }
+                ) {
+                    return Err(unsupported("EXPLAIN of this statement"));
+                }
+                Ok(BoundStatement::Explain { statement: Box::new(statement), analyze: *analyze })
+            },
+            Statement::Analyze(analyze) => self.bind_analyze(analyze),
+            Statement::Copy { source, to, target, options, legacy_options, .. } => {
+                self.bind_copy(source, *to, target, options, legacy_options)
+            },
+            other => Err(unsupported(format!("Statement {other}"))),
+        }
+    }
+
+    fn table(&self, name: &ObjectName) -> CrabDbResult<std::sync::Arc<crate::catalog::table_info::TableInfo>> {
+        let name = table_name(name)?;
+        self.temp_table(&name)
+            .or_else(|| self.catalog.table(&name))
+            .ok_or_else(|| CrabDBError::InvalidInput(format!("Table {name} does not exist")))
+    }
+
+    fn temp_table(&self, name: &str) -> Option<std::sync::Arc<TableInfo>> {
+        self.catalog.temp_table(self.session?, name)
+    }
+
+    // A table statements can write rows to: not one a materialized view is kept in, whose
+    // rows only change with the tables the view reads.
+    fn writable_table(&self, name: &ObjectName) -> CrabDbResult<std::sync::Arc<TableInfo>> {
+        let table = self.table(name)?;
+        if self.catalog.view_of(&table).is_some() {
+            return Err(CrabDBError::InvalidInput(format!("Materialized view {} cannot be written to", table.name())));
+        }
+        Ok(table)
+    }
+
+    // PRIMARY KEY and UNIQUE constraints become B+ tree indexes, named after the constraint
+    // or, failing that, the way PostgreSQL names them: table_pkey and table_column_key.
+    // Temporary tables take neither.
+    fn bind_create_table(&self, create: &ast::CreateTable) -> CrabDbResult<BoundStatement> {
+        let name = table_name(&create.name)?;
+        if create.temporary && self.session.is_none() {
+            return Err(CrabDBError::InvalidInput(format!("Temporary table {name} needs a session")));
+        }
+        let mut columns = Vec::with_capacity(create.columns.len());
+        let mut constraints: Vec<KeyConstraint> = Vec::new();
+        for definition in &create.columns {
+            let mut column = Column::new(definition.name.value.clone(), data_type(&definition.data_type)?);
+            for option in &definition.options {
+                let key_columns = || vec![definition.name.value.clone()];
+                match &option.option {
+                    ColumnOption::Null => {},
+                    ColumnOption::NotNull => column = column.not_null(),
+                    ColumnOption::PrimaryKey(constraint) => {
+                        primary_key_constraint(constraint)?;
+                        constraints.push((option.name.as_ref().or(constraint.name.as_ref()), true, key_columns()));
+                    },
+                    ColumnOption::Unique(constraint) => {
+                        unique_constraint(constraint)?;
+                        constraints.push((option.name.as_ref().or(constraint.name.as_ref()), false, key_columns()));
+                    },
+                    other => return Err(unsupported(format!("Column option {other}"))),
+                }
+            }
+            columns.push(column);
+        }
+        for constraint in &create.constraints {
+            match constraint {
+                TableConstraint::PrimaryKey(constraint) => {
+                    primary_key_constraint(constraint)?;
+                    constraints.push((constraint.name.as_ref(), true, key_columns(&constraint.columns)?));
+                },
+                TableConstraint::Unique(constraint) => {
+                    unique_constraint(constraint)?;
+                    constraints.push((constraint.name.as_ref(), false, key_columns(&constraint.columns)?));
+                },
+                other => return Err(unsupported(format!("Table constraint {other}"))),
+            }
+        }
+
+        if create.temporary && !constraints.is_empty() {
+            return Err(CrabDBError::InvalidInput(format!("Temporary table {name} cannot have keys")));
+        }
+
+        // Nothing is created if the table is there already, so its names may be taken.
+        let existing = match create.temporary {
+            true => self.temp_table(&name).is_some(),
+            false => self.catalog.table(&name).is_some(),
+        };
+        let existing = create.if_not_exists && existing;
+        let mut unique_keys: Vec<UniqueKey> = Vec::new();
+        let mut has_primary_key = false;
+        for (constraint_name, primary, key_columns) in constraints {
+            if primary && std::mem::replace(&mut has_primary_key, true) {
+                return Err(CrabDBError::InvalidInput(format!("Table {name} cannot have more than one primary key")));
+            }
+            for key_column in &key_columns {
+                let Some(position) = columns.iter().position(|column| column.name().eq_ignore_ascii_case(key_column)) else {
+                    return Err(CrabDBError::InvalidInput(format!("Column {key_column} does not exist in table {name}")));
+                };
+                if primary {
+                    columns[position] = columns[position].clone().not_null();
+                }
+            }
+            let index_name = match constraint_name {
+                Some(constraint_name) => constraint_name.value.clone(),
+                None if primary => format!("{name}_pkey"),
+                None => format!("{name}_{}_key", key_columns.join("_")),
+            };
+            let taken = unique_keys.iter().any(|key| key.name.eq_ignore_ascii_case(&index_name))
+                || (!existing && self.catalog.index(&index_name).is_some());
+            if taken {
+                return Err(CrabDBError::InvalidInput(format!("Index {index_name} already exists")));
+            }
+            unique_keys.push(UniqueKey { name: index_name, columns: key_columns });
+        }
+        Ok(BoundStatement::CreateTable {
+            ttl: table_ttl(&name, &create.table_options, &columns)?,
+            name,
+            schema: Schema::new(columns),
+            unique_keys,
+            temporary: create.temporary,
+            if_not_exists: create.if_not_exists,
+        })
+    }
+
+    // Indexes on column values enforce uniqueness, so those are CREATE UNIQUE INDEX, with the
+    // default USING BTREE or USING HASH. USING FULLTEXT indexes the terms of a text column
+    // for MATCH ... AGAINST and BM25, USING HNSW a vector column for nearest neighbors, and
+    // USING RTREE a geometry column for ST_CONTAINS, ST_INTERSECTS and nearest neighbors.
+    fn bind_create_index(&self, create: &CreateIndex) -> CrabDbResult<BoundStatement> {
+        if create.concurrently || create.r#async || !create.include.is_empty() || create.nulls_distinct.is_some()
+            || !create.with.is_empty() || create.predicate.is_some() || !create.index_options.is_empty() || !create.alter_options.is_empty()
+        {
+            return Err(unsupported(format!("Index option in {create}")));
+        }
+        let name = create.name.as_ref().map(table_name).transpose()?
+            .ok_or_else(|| CrabDBError::InvalidInput("CREATE INDEX needs an index name".into()))?;
+        let kind = match &create.using {
+            None | Some(IndexType::BTree) => IndexKind::BPlusTree,
+            Some(IndexType::Hash) => IndexKind::Hash,
+            Some(IndexType::Custom(ident)) if ident.value.eq_ignore_ascii_case("fulltext") => IndexKind::FullText,
+            Some(IndexType::Custom(ident)) if ident.value.eq_ignore_ascii_case("hnsw") => IndexKind::Hnsw,
+            Some(IndexType::Custom(ident)) if ident.value.eq_ignore_ascii_case("rtree") => IndexKind::RTree,
+            Some(other) => return Err(unsupported(format!("Index type {other}"))),
+        };
+        if create.unique != kind.is_unique() {
+            return Err(CrabDBError::InvalidInput(match kind.is_unique() {
+                true => format!("{kind} indexes must be UNIQUE"),
+                false => format!("{kind} indexes cannot be UNIQUE"),
+            }));
+        }
+        let table = self.table(&create.table_name)?;
+        if table.is_temporary() {
+            return Err(CrabDBError::InvalidInput(format!("Temporary table {} cannot be indexed", table.name())));
+        }
+        Ok(BoundStatement::CreateIndex {
+            name,
+            table,
+            columns: key_columns(&create.columns)?,
+            kind,
+            if_not_exists: create.if_not_exists,
+        })
+    }
+
+    // A materialized view is a table holding the result of its query, which is bound outside
+    // of any session so it never reads a temporary table. Its columns are those of the query,
+    // renamed if the view lists names for them.
+    fn bind_create_view(&self, create: &ast::CreateView) -> CrabDbResult<BoundStatement> {
+        if !create.materialized {
+            return Err(unsupported("A view that is not materialized"));
+        }
+        if create.or_replace || create.or_alter || create.temporary || create.to.is_some() {
+            return Err(unsupported("CREATE MATERIALIZED VIEW with OR REPLACE, OR ALTER, TEMPORARY or TO"));
+        }
+        let name = table_name(&create.name)?;
+        let query = Binder::new(self.catalog).bind_query(&create.query)?;
+        let mut columns = query.schema().columns().to_vec();
+        if !create.columns.is_empty() {
+            if create.columns.len() != columns.len() {
+                return Err(CrabDBError::InvalidInput(format!(
+                    "Materialized view {name} names {} columns but its query has {}", create.columns.len(), columns.len()
+                )));
+            }
+            for (column, definition) in columns.iter_mut().zip(&create.columns) {
+                if definition.data_type.is_some() || definition.options.is_some() {
+                    return Err(unsupported("Column types or options of a materialized view"));
+                }
+                *column = Column::new(definition.name.value.clone(), column.data_type());
+            }
+        }
+        // NOT NULL comes from the tables the query reads, which a view does not keep up with.
+        let columns = columns.into_iter().map(|column| Column::new(column.name().to_string(), column.data_type())).collect();
+        let mut sources: Vec<Oid> = query.scans().iter().map(|table| table.oid()).collect();
+        sources.sort_unstable();
+        sources.dedup();
+        Ok(BoundStatement::CreateMaterializedView {
+            name,
+            schema: Schema::new(columns),
+            view: MaterializedView { query: create.query.to_string(), sources, state_columns: 0 },
+            if_not_exists: create.if_not_exists,
+        })
+    }
+
+    fn bind_refresh(&self, function: &ast::Function) -> CrabDbResult<BoundStatement> {
+        let FunctionArguments::List(list) = &function.args else {
+            return Err(CrabDBError::InvalidInput("REFRESH MATERIALIZED VIEW needs the name of a view".into()));
+        };
+        let name = match list.args.as_slice() {
+            [FunctionArg::Unnamed(FunctionArgExpr::Expr(Expr::Identifier(ident)))] => ObjectName::from(vec![ident.clone()]),
+            [FunctionArg::Unnamed(FunctionArgExpr::Expr(Expr::CompoundIdentifier(idents)))] => ObjectName::from(idents.clone()),
+            _ => return Err(CrabDBError::InvalidInput("REFRESH MATERIALIZED VIEW needs the name of a view".into())),
+        };
+        let table = self.table(&name)?;
+        let view = self.catalog.view_of(&table)
+            .ok_or_else(|| CrabDBError::InvalidInput(format!("Table {} is not a materialized view", table.name())))?;
+        Ok(BoundStatement::RefreshMaterializedView { table, view })
+    }
+
+    fn bind_insert(&self, insert: &ast::Insert) -> CrabDbResult<BoundStatement> {
+        let TableObject::TableName(name) = &insert.table else {
+            return Err(unsupported("Inserting into a table function"));
+        };
+        if insert.returning.is_some() || insert.on.is_some() {
+            return Err(unsupported("INSERT with RETURNING or ON CONFLICT"));
+        }
+        let table = self.writable_table(name)?;
+        let Some(query) = &insert.source else {
+            return Err(unsupported("INSERT without VALUES or SELECT"));
+        };
+        let source = self.bind_query(query)?;
+        let width = source.schema().column_count();
+        if insert.columns.is_empty() {
+            if width != table.schema().column_count() {
+                return Err(CrabDBError::InvalidInput(format!(
+                    "Table {} has {} columns but {width} values were supplied", table.name(), table.schema().column_count()
+                )));
+            }
+            return Ok(BoundStatement::Insert { table, source });
+        }
+
+        // Lay the supplied columns out like the table, with NULL for the missing ones.
+        let scope = Scope::new(None, table.schema());
+        let mut positions = vec![None; table.schema().column_count()];
+        for (i, column) in insert.columns.iter().enumerate() {
+            let position = scope.resolve(None, &column.to_string())?;
+            if positions[position].replace(i).is_some() {
+                return Err(CrabDBError::InvalidInput(format!("Column {column} is listed more than once")));
+            }
+        }
+        if width != insert.columns.len() {
+            return Err(CrabDBError::InvalidInput(format!("{} columns were listed but {width} values were supplied", insert.columns.len())));
+        }
+        let expressions = positions.iter()
+            .map(|position| position.map_or(Expression::Constant(Value::Null), Expression::Column))
+            .collect();
+        let source = LogicalPlan::Projection { input: Box::new(source), expressions, schema: table.schema().clone() };
+        Ok(BoundStatement::Insert { table, source })
+    }
+
+    fn single_table(&self, from: &TableWithJoins) -> CrabDbResult<(std::sync::Arc<crate::catalog::table_info::TableInfo>, Scope)> {
+        let TableFactor::Table { name, alias, .. } = &from.relation else {
+            return Err(unsupported(format!("Modifying {}", from.relation)));
+        };
+        if !from.joins.is_empty() {
+            return Err(unsupported("Modifying joined tables"));
+        }
+        let table = self.writable_table(name)?;
+        let qualifier = alias.as_ref().map_or(table.name().to_string(), |alias| alias.name.value.clone());
+        let scope = Scope::of_table(Some(&qualifier), &table);
+        Ok((table, scope))
+    }
+
+    fn bind_update(&self, update: &ast::Update) -> CrabDbResult<BoundStatement> {
+        if update.from.is_some() || update.returning.is_some() {
+            return Err(unsupported("UPDATE with FROM or RETURNING"));
+        }
+        let (table, scope) = self.single_table(&update.table)?;
+        let mut assignments = Vec::with_capacity(update.assignments.len());
+        for assignment in &update.assignments {
+            let ast::AssignmentTarget::ColumnName(column) = &assignment.target else {
+                return Err(unsupported("Assigning to a tuple of columns"));
+            };
+            let column = scope.resolve(None, &column.to_string())?;
+            assignments.push((column, self.bind_expr(&assignment.value, &scope)?));
+        }
+        let predicate = update.selection.as_ref().map(|selection| self.bind_expr(selection, &scope)).transpose()?;
+        Ok(BoundStatement::Update { table, assignments, predicate })
+    }
+
+    fn bind_delete(&self, delete: &ast::Delete) -> CrabDbResult<BoundStatement> {
+        let (FromTable::WithFromKeyword(from) | FromTable::WithoutKeyword(from)) = &delete.from;
+        let ([from], None, None) = (from.as_slice(), &delete.using, &delete.returning) else {
+            return Err(unsupported("DELETE from several tables or with USING or RETURNING"));
+        };
+        let (table, scope) = self.single_table(from)?;
+        let predicate = delete.selection.as_ref().map(|selection| self.bind_expr(selection, &scope)).transpose()?;
+        Ok(BoundStatement::Delete { table, predicate })
+    }
+
+    fn bind_analyze(&self, analyze: &ast::Analyze) -> CrabDbResult<BoundStatement> {
+        if !analyze.columns.is_empty() || analyze.partitions.is_some() || analyze.noscan {
+            return Err(unsupported("ANALYZE of some columns or partitions"));
+        }
+        let tables = match &analyze.table_name {
+            Some(name) => vec![self.table(name)?],
+            None => self.catalog.table_names().iter().filter_map(|name| self.catalog.table(name)).collect(),
+        };
+        Ok(BoundStatement::Analyze { tables })
+    }
+
+    // COPY of a whole table from or to a server-side file. FORMAT is csv (the default) or
+    // parquet, in the WITH (...) form or the legacy one.
+    fn bind_copy(
+        &self,
+        source: &CopySource,
+        to: bool,
+        target: &CopyTarget,
+        options: &[CopyOption],
+        legacy_options: &[CopyLegacyOption],
+    ) -> CrabDbResult<BoundStatement> {
+        let CopySource::Table { table_name, columns } = source else {
+            return Err(unsupported("COPY of a query"));
+        };
+        if !columns.is_empty() {
+            return Err(unsupported("COPY of some columns"));
+        }
+        let CopyTarget::File { filename } = target else {
+            return Err(unsupported(format!("COPY {} {target}", if to { "TO" } else { "FROM" })));
+        };
+        let (mut format, mut header) = (None, false);
+        for option in options {
+            match option {
+                CopyOption::Format(name) => format = Some(name.value.to_ascii_lowercase()),
+                CopyOption::Header(on) => header = *on,
+                other => return Err(unsupported(format!("COPY option {other}"))),
+            }
+        }
+        for option in legacy_options {
+            match option {
+                CopyLegacyOption::Csv(csv_options) => {
+                    format = Some("csv".to_string());
+                    for csv_option in csv_options {
+                        match csv_option {
+                            CopyLegacyCsvOption::Header => header = true,
+                            other => return Err(unsupported(format!("COPY option {other}"))),
+                        }
+                    }
+                },
+                CopyLegacyOption::Parquet => format = Some("parquet".to_string()),
+                CopyLegacyOption::Header => header = true,
+                other => return Err(unsupported(format!("COPY option {other}"))),
+            }
+        }
+        let format = match format.as_deref() {
+            None | Some("csv") => CopyFormat::Csv { header },
+            Some("parquet") if !header => CopyFormat::Parquet,
+            Some("parquet") => return Err(unsupported("HEADER with FORMAT parquet")),
+            Some(other) => return Err(unsupported(format!("COPY format {other}"))),
+        };
+        let table = if to { self.table(table_name)? } else { self.writable_table(table_name)? };
+        Ok(BoundStatement::Copy { table, path: PathBuf::from(filename), format, to })
+    }
+
+    fn bind_query(&self, query: &Query) -> CrabDbResult<LogicalPlan> {
+        if query.with.is_some() || query.fetch.is_some() {
+            return Err(unsupported("A query with WITH or FETCH"));
+        }
+        match query.body.as_ref() {
+            SetExpr::Select(select) => self.bind_select(select, query),
+            SetExpr::Query(inner) if query.order_by.is_none() && query.limit_clause.is_none() => self.bind_query(inner),
+            SetExpr::Values(values) if query.order_by.is_none() && query.limit_clause.is_none() => self.bind_values(values),
+            other => Err(unsupported(format!("Query {other}"))),
+        }
+    }
+
+    fn bind_values(&self, values: &ast::Values) -> CrabDbResult<LogicalPlan> {
+        let scope = Scope::default();
+        let rows = values.rows.iter()
+            .map(|row| row.content.iter().map(|expr| self.bind_expr(expr, &scope)).collect::<CrabDbResult<Vec<_>>>())
+            .collect::<CrabDbResult<Vec<_>>>()?;
+        let width = rows.first().map_or(0, Vec::len);
+        if rows.iter().any(|row| row.len() != width) {
+            return Err(CrabDBError::InvalidInput("VALUES rows must all have the same number of values".into()));
+        }
+        // Column types come from the first row; other rows are cast when they are stored.
+        let empty = Schema::new(Vec::new());
+        let schema = Schema::new(rows.first().map_or(Vec::new(), |row| {
+            row.iter().enumerate().map(|(i, expr)| Column::new(format!("column{}", i + 1), expr.data_type(&empty))).collect()
+        }));
+        Ok(LogicalPlan::Values { rows, schema })
+    }
+
+    fn bind_from(&self, from: &[TableWithJoins]) -> CrabDbResult<(LogicalPlan, Scope)> {
+        let mut bound: Option<(LogicalPlan, Scope)> = None;
+        for table in from {
+            let (mut plan, mut scope) = self.bind_table_factor(&table.relation)?;
+            for join in &table.joins {
+                let (right, right_scope) = self.bind_table_factor(&join.relation)?;
+                let (join_type, constraint) = match &join.join_operator {
+                    JoinOperator::Join(constraint) | JoinOperator::Inner(constraint) => (JoinType::Inner, constraint),
+                    JoinOperator::Left(constraint) | JoinOperator::LeftOuter(constraint) => (JoinType::Left, constraint),
+                    JoinOperator::CrossJoin(constraint) => (JoinType::Inner, constraint),
+                    other => return Err(unsupported(format!("Join {other:?}"))),
+                };
+                scope = scope.join(right_scope);
+                let predicate = match constraint {
+                    JoinConstraint::On(on) => Some(self.bind_expr(on, &scope)?),
+                    JoinConstraint::None => None,
+                    JoinConstraint::Using(_) | JoinConstraint::Natural => return Err(unsupported("JOIN with USING or NATURAL")),
+                };
+                plan = LogicalPlan::join(plan, right, join_type, predicate);
+            }
+            // Tables listed with commas are cross joined.
+            bound = Some(match bound {
+                None => (plan, scope),
+                Some((left, left_scope)) => (LogicalPlan::join(left, plan, JoinType::Inner, None), left_scope.join(scope)),
+            });
+        }
+        // Without FROM, a query reads a single row of no columns.
+        Ok(bound.unwrap_or_else(|| {
+            (LogicalPlan::Values { rows: vec![Vec::new()], schema: Schema::new(Vec::new()) }, Scope::default())
+        }))
+    }
+
+    fn bind_table_factor(&self, factor: &TableFactor) -> CrabDbResult<(LogicalPlan, Scope)> {
+        match factor {
+            TableFactor::Table { name, alias, args: None, .. } => {
+                let table = self.table(name)?;
+                let qualifier = alias.as_ref().map_or(table.name().to_string(), |alias| alias.name.value.clone());
+                let mut scope = Scope::of_table(Some(&qualifier), &table);
+                let state_columns = self.catalog.view_of(&table).map_or(0, |view| view.state_columns);
+                if state_columns == 0 {
+                    return Ok((LogicalPlan::Scan { table }, scope));
+                }
+                // The state a view keeps for its maintenance is not part of its rows.
+                let width = table.schema().column_count() - state_columns;
+                scope.columns.truncate(width);
+                scope.types.truncate(width);
+                scope.sources.truncate(width);
+                let schema = Schema::new(table.schema().columns()[..width].to_vec());
+                let expressions = (0..width).map(Expression::Column).collect();
+                Ok((LogicalPlan::Projection { input: Box::new(LogicalPlan::Scan { table }), expressions, schema }, scope))
+            },
+            TableFactor::Derived { lateral: false, subquery, alias, .. } => {
+                let plan = self.bind_query(subquery)?;
+                let scope = Scope::new(alias.as_ref().map(|alias| alias.name.value.as_str()), plan.schema());
+                Ok((plan, scope))
+            },
+            other => Err(unsupported(format!("FROM {other}"))),
+        }
+    }
+
+    fn bind_select(&self, select: &Select, query: &Query) -> CrabDbResult<LogicalPlan> {
+        if select.distinct.is_some() || select.top.is_some() || select.into.is_some() {
+            return Err(unsupported("SELECT with DISTINCT, TOP or INTO"));
+        }
+        let (mut plan, scope) = self.bind_from(&select.from)?;
+        if let Some(selection) = &select.selection {
+            plan = LogicalPlan::Filter { input: Box::new(plan), predicate: self.bind_expr(selection, &scope)? };
+        }
+
+        let group_by = match &select.group_by {
+            GroupByExpr::Expressions(exprs, modifiers) if modifiers.is_empty() => exprs,
+            other => return Err(unsupported(format!("{other}"))),
+        };
+        let items_aggregate = select.projection.iter().any(|item| match item {
+            SelectItem::UnnamedExpr(expr) | SelectItem::ExprWithAlias { expr, .. } => self.contains_aggregate(expr),
+            _ => false,
+        });
+        let mut aggregation = None;
+        if !group_by.is_empty() || items_aggregate || select.having.is_some() {
+            let mut bound = Aggregation { group_by: Vec::new(), group_by_names: Vec::new(), aggregates: Vec::new(), aggregate_names: Vec::new() };
+            for expr in group_by {
+                bound.group_by.push(self.bind_expr(expr, &scope)?);
+                bound.group_by_names.push(column_name(expr));
+            }
+            aggregation = Some(bound);
+        }
+
+        // Select items are bound before the operators below the projection are built, since
+        // binding them is what collects the aggregates.
+        let mut expressions = Vec::new();
+        let mut names = Vec::new();
+        let mut aliases = Vec::new();
+        for item in &select.projection {
+            match item {
+                SelectItem::UnnamedExpr(expr) => {
+                    expressions.push(self.bind_expr_in(expr, &scope, &mut aggregation)?);
+                    names.push(column_name(expr));
+                },
+                SelectItem::ExprWithAlias { expr, alias } => {
+                    expressions.push(self.bind_expr_in(expr, &scope, &mut aggregation)?);
+                    names.push(alias.value.clone());
+                    aliases.push((alias.value.clone(), expressions.len() - 1));
+                },
+                SelectItem::Wildcard(_) | SelectItem::QualifiedWildcard(..) if aggregation.is_some() => {
+                    return Err(CrabDBError::InvalidInput("SELECT * cannot be used with GROUP BY or aggregates".into()));
+                },
+                SelectItem::Wildcard(_) => {
+                    for (i, (_, name)) in scope.columns.iter().enumerate() {
+                        expressions.push(Expression::Column(i));
+                        names.push(name.clone());
+                    }
+                },
+                SelectItem::QualifiedWildcard(SelectItemQualifiedWildcardKind::ObjectName(qualifier), _) => {
+                    let qualifier = table_name(qualifier)?;
+                    let before = expressions.len();
+                    for (i, (column_qualifier, name)) in scope.columns.iter().enumerate() {
+                        if column_qualifier.as_ref().is_some_and(|q| q.eq_ignore_ascii_case(&qualifier)) {
+                            expressions.push(Expression::Column(i));
+                            names.push(name.clone());
+                        }
+                    }
+                    if expressions.len() == before {
+                        return Err(CrabDBError::InvalidInput(format!("Table {qualifier} is not in the FROM clause")));
+                    }
+                },
+                other => return Err(unsupported(format!("Select item {other}"))),
+            }
+        }
+        let having = select.having.as_ref().map(|having| self.bind_expr_in(having, &scope, &mut aggregation)).transpose()?;
+
+        let mut keys = Vec::new();
+        if let Some(order_by) = &query.order_by {
+            let OrderByKind::Expressions(order_by) = &order_by.kind else {
+                return Err(unsupported("ORDER BY ALL"));
+            };
+            for item in order_by {
+                if item.options.nulls_first.is_some() || item.with_fill.is_some() {
+                    return Err(unsupported("ORDER BY with NULLS FIRST/LAST or WITH FILL"));
+                }
+                let expression = match &item.expr {
+                    // ORDER BY 2 sorts by the second select item.
+                    Expr::Value(value) if matches!(value.value, ast::Value::Number(..)) => {
+                        let position = literal(&value.value)?.as_i64().unwrap_or(0);
+                        usize::try_from(position - 1).ok().and_then(|i| expressions.get(i)).cloned()
+                            .ok_or_else(|| CrabDBError::InvalidInput(format!("ORDER BY position {position} is not in the select list")))?
+                    },
+                    Expr::Identifier(ident) if aliases.iter().any(|(alias, _)| alias.eq_ignore_ascii_case(&ident.value)) => {
+                        let (_, i) = aliases.iter().find(|(alias, _)| alias.eq_ignore_ascii_case(&ident.value)).unwrap();
+                        expressions[*i].clone()
+                    },
+                    expr => self.bind_expr_in(expr, &scope, &mut aggregation)?,
+                };
+                let descending = matches!(item.options.sort, Some(ast::OrderBySort::Desc));
+                keys.push(SortKey { expression, descending });
+            }
+        }
+
+        if let Some(aggregation) = aggregation {
+            let schema = aggregation.schema(plan.schema());
+            plan = LogicalPlan::Aggregate {
+                input: Box::new(plan),
+                group_by: aggregation.group_by,
+                aggregates: aggregation.aggregates,
+                schema,
+            };
+            if let Some(having) = having {
+                plan = LogicalPlan::Filter { input: Box::new(plan), predicate: having };
+            }
+        }
+        if !keys.is_empty() {
+            plan = LogicalPlan::Sort { input: Box::new(plan), keys };
+        }
+        if let Some(limit_clause) = &query.limit_clause {
+            let (limit, offset) = match limit_clause {
+                LimitClause::LimitOffset { limit, offset, limit_by } if limit_by.is_empty() => {
+                    (limit.as_ref(), offset.as_ref().map(|offset| &offset.value))
+                },
+                LimitClause::OffsetCommaLimit { offset, limit } => (Some(limit), Some(offset)),
+                other => return Err(unsupported(format!("{other}"))),
+            };
+            let limit = limit.map(|limit| self.bind_count(limit, "LIMIT")).transpose()?;
+            let offset = offset.map(|offset| self.bind_count(offset, "OFFSET")).transpose()?.unwrap_or(0);
+            plan = LogicalPlan::Limit { input: Box::new(plan), limit, offset };
+        }
+
+        let input_schema = plan.schema().clone();
+        let schema = Schema::new(expressions.iter().zip(names)
+            .map(|(expression, name)| Column::new(name, expression.data_type(&input_schema)))
+            .collect());
+        Ok(LogicalPlan::Projection { input: Box::new(plan), expressions, schema })
+    }
+
+    // LIMIT and OFFSET take a constant row count.
+    fn bind_count(&self, expr: &Expr, clause: &str) -> CrabDbResult<usize> {
+        self.bind_expr(expr, &Scope::default())?.evaluate(&[])?.as_i64()
+            .and_then(|count| usize::try_from(count).ok())
+            .ok_or_else(|| CrabDBError::InvalidInput(format!("{clause} must be a non-negative integer")))
+    }
+
+    fn bind_expr(&self, expr: &Expr, scope: &Scope) -> CrabDbResult<Expression> {
+        self.bind_expr_in(expr, scope, &mut None)
+    }
+
+    // Binds an expression over rows of `scope`, or, if `aggregation` is given, over the
+    // output rows of that aggregation of them.
+    fn bind_expr_in(&self, expr: &Expr, scope: &Scope, aggregation: &mut Option<Aggregation>) -> CrabDbResult<Expression> {
+        if let Some(aggregation) = aggregation {
+            if let Ok(bound) = self.bind_expr(expr, scope) {
+                if let Some(i) = aggregation.group_by.iter().position(|group_by| *group_by == bound) {
+                    return Ok(Expression::Column(i));
+                }
+            }
+        }
+        match expr {
+            Expr::Identifier(_) | Expr::CompoundIdentifier(_) if aggregation.is_some() => Err(CrabDBError::InvalidInput(format!(
+                "Column {expr} must appear in the GROUP BY clause or be used in an aggregate function"
+            ))),
+            Expr::Identifier(ident) => Ok(Expression::Column(scope.resolve(None, &ident.value)?)),
+            Expr::CompoundIdentifier(idents) => match idents.as_slice() {
+                [qualifier, name] => Ok(Expression::Column(scope.resolve(Some(&qualifier.value), &name.value)?)),
+                _ => Err(unsupported(format!("Column reference {expr}"))),
+            },
+            Expr::Value(value) => match &value.value {
+                ast::Value::Placeholder(placeholder) => placeholder.strip_prefix('$')
+                    .and_then(|position| position.parse::<usize>().ok())
+                    .and_then(|position| self.parameters.get(position.checked_sub(1)?))
+                    .map(|parameter| Expression::Constant(parameter.clone()))
+                    .ok_or_else(|| CrabDBError::InvalidInput(format!("No parameter for {placeholder}"))),
+                value => Ok(Expression::Constant(literal(value)?)),
+            },
+            Expr::Nested(inner) => self.bind_expr_in(inner, scope, aggregation),
+            Expr::BinaryOp { left, op, right } => {
+                let (left, right) = (self.bind_expr_in(left, scope, aggregation)?, self.bind_expr_in(right, scope, aggregation)?);
+                let compare = |op| Ok(Expression::compare(op, left.clone(), right.clone()));
+                let arithmetic = |op| Ok(Expression::arithmetic(op, left.clone(), right.clone()));
+                match op {
+                    BinaryOperator::Eq => compare(CompareOp::Eq),
+                    BinaryOperator::NotEq => compare(CompareOp::NotEq),
+                    BinaryOperator::Lt => compare(CompareOp::Lt),
+                    BinaryOperator::LtEq => compare(CompareOp::LtEq),
+                    BinaryOperator::Gt => compare(CompareOp::Gt),
+                    BinaryOperator::GtEq => compare(CompareOp::GtEq),
+                    BinaryOperator::Plus => arithmetic(ArithmeticOp::Add),
+                    BinaryOperator::Minus => arithmetic(ArithmeticOp::Subtract),
+                    BinaryOperator::Multiply => arithmetic(ArithmeticOp::Multiply),
+                    BinaryOperator::Divide => arithmetic(ArithmeticOp::Divide),
+                    BinaryOperator::And => Ok(Expression::and(left, right)),
+                    BinaryOperator::Or => Ok(Expression::or(left, right)),
+                    // Vectors are written as text, like '[1, 2.5]', and geometries like 'POINT(1 2)'.
+                    BinaryOperator::Custom(op) if op == "<->" => {
+                        let operand = |expression: Expression| match expression {
+                            Expression::Constant(Value::Varchar(text)) if text.trim_start().starts_with('[') => {
+                                Value::parse_vector(&text).map(Expression::Constant)
+                            },
+                            expression => geometry(expression),
+                        };
+                        Ok(Expression::Distance(Box::new(operand(left)?), Box::new(operand(right)?)))
+                    },
+                    other => Err(unsupported(format!("Operator {other}"))),
+                }
+            },
+            Expr::UnaryOp { op, expr } => {
+                let operand = self.bind_expr_in(expr, scope, aggregation)?;
+                match op {
+                    UnaryOperator::Not => Ok(Expression::Not(Box::new(operand))),
+                    UnaryOperator::Plus => Ok(operand),
+                    // Negative literals are folded so they stay constants.
+                    UnaryOperator::Minus => match operand {
+                        Expression::Constant(value) => {
+                            Ok(Expression::Constant(crate::execution::expression::arithmetic(ArithmeticOp::Subtract, Value::Int32(0), value)?))
+                        },
+                        operand => Ok(Expression::arithmetic(ArithmeticOp::Subtract, Expression::Constant(Value::Int32(0)), operand)),
+                    },
+                    other => Err(unsupported(format!("Operator {other}"))),
+                }
+            },
+            Expr::IsNull(inner) => Ok(Expression::IsNull(Box::new(self.bind_expr_in(inner, scope, aggregation)?))),
+            Expr::IsNotNull(inner) => Ok(Expression::Not(Box::new(Expression::IsNull(Box::new(self.bind_expr_in(inner, scope, aggregation)?))))),
+            Expr::Between { expr, negated, low, high } => {
+                let (value, low, high) = (self.bind_expr_in(expr, scope, aggregation)?, self.bind_expr_in(low, scope, aggregation)?, self.bind_expr_in(high, scope, aggregation)?);
+                let between = Expression::and(
+                    Expression::compare(CompareOp::GtEq, value.clone(), low),
+                    Expression::compare(CompareOp::LtEq, value, high),
+                );
+                Ok(if *negated { Expression::Not(Box::new(between)) } else { between })
+            },
+            Expr::InList { expr, list, negated } => {
+                let value = self.bind_expr_in(expr, scope, aggregation)?;
+                let mut any = None;
+                for item in list {
+                    let equal = Expression::compare(CompareOp::Eq, value.clone(), self.bind_expr_in(item, scope, aggregation)?);
+                    any = Some(match any {
+                        None => equal,
+                        Some(any) => Expression::or(any, equal),
+                    });
+                }
+                let any = any.ok_or_else(|| CrabDBError::InvalidInput("IN needs at least one value".into()))?;
+                Ok(if *negated { Expression::Not(Box::new(any)) } else { any })
+            },
+            Expr::MatchAgainst { .. } if aggregation.is_some() => Err(unsupported(format!("{expr} with GROUP BY or aggregates"))),
+            Expr::Function(function) if aggregation.is_some() && function.name.to_string().eq_ignore_ascii_case("bm25") => {
+                Err(unsupported(format!("{expr} with GROUP BY or aggregates")))
+            },
+            Expr::MatchAgainst { columns, match_value, opt_search_modifier } => {
+                if !matches!(opt_search_modifier, None | Some(SearchModifier::InNaturalLanguageMode)) {
+                    return Err(unsupported(format!("Search modifier in {expr}")));
+                }
+                let [column] = columns.as_slice() else {
+                    return Err(unsupported(format!("MATCH over several columns in {expr}")));
+                };
+                let column = match column.0.iter().map(|part| part.as_ident()).collect::<Option<Vec<_>>>().as_deref() {
+                    Some([name]) => Expression::Column(scope.resolve(None, &name.value)?),
+                    Some([qualifier, name]) => Expression::Column(scope.resolve(Some(&qualifier.value), &name.value)?),
+                    _ => return Err(unsupported(format!("Column reference {column}"))),
+                };
+                Ok(Expression::Match(Box::new(column), search_terms(&match_value.value)?))
+            },
+            Expr::Function(function) if function.name.to_string().eq_ignore_ascii_case("bm25") => self.bind_bm25(function, scope),
+            Expr::Function(function) if spatial_op(&function.name).is_some() => self.bind_spatial(function, scope, aggregation),
+            Expr::Function(function) => {
+                let Some(function_kind) = self.aggregate_function(&function.name) else {
+                    return self.bind_call(function, scope, aggregation);
+                };
+                let Some(aggregation) = aggregation else {
+                    return Err(CrabDBError::InvalidInput(format!("Aggregate function {} is not allowed here", function.name)));
+                };
+                let FunctionArguments::List(list) = &function.args else {
+                    return Err(CrabDBError::InvalidInput(format!("{} takes one argument", function.name)));
+                };
+                if list.duplicate_treatment.is_some() || !list.clauses.is_empty() || function.filter.is_some() || function.over.is_some() {
+                    return Err(unsupported(format!("Aggregate {expr}")));
+                }
+                let aggregate = match (function_kind, list.args.as_slice()) {
+                    (AggregateFunction::Count, [FunctionArg::Unnamed(FunctionArgExpr::Wildcard)]) => AggregateExpression::count_star(),
+                    // Aggregates cannot nest, so the argument is bound over the input rows.
+                    (AggregateFunction::User(udf), [FunctionArg::Unnamed(FunctionArgExpr::Expr(argument))]) => {
+                        let argument = self.bind_expr(argument, scope)?;
+                        check_argument(&function.name, &argument, &scope.schema(), udf.argument())?;
+                        AggregateExpression::new(AggregateFunction::User(udf), argument)
+                    },
+                    (function_kind, [FunctionArg::Unnamed(FunctionArgExpr::Expr(argument))]) => {
+                        AggregateExpression::new(function_kind, self.bind_expr(argument, scope)?)
+                    },
+                    _ => return Err(CrabDBError::InvalidInput(format!("{} takes one argument", function.name))),
+                };
+                let position = match aggregation.aggregates.iter().position(|existing| *existing == aggregate) {
+                    Some(position) => position,
+                    None => {
+                        aggregation.aggregates.push(aggregate);
+                        aggregation.aggregate_names.push(expr.to_string());
+                        aggregation.aggregates.len() - 1
+                    },
+                };
+                Ok(Expression::Column(aggregation.group_by.len() + position))
+            },
+            other => Err(unsupported(format!("Expression {other}"))),
+        }
+    }
+
+    // The built-in aggregate or user-defined one called `name`.
+    fn aggregate_function(&self, name: &ObjectName) -> Option<AggregateFunction> {
+        match name.to_string().to_lowercase().as_str() {
+            "count" => Some(AggregateFunction::Count),
+            "sum" => Some(AggregateFunction::Sum),
+            "min" => Some(AggregateFunction::Min),
+            "max" => Some(AggregateFunction::Max),
+            "avg" => Some(AggregateFunction::Avg),
+            name => match self.catalog.function(name) {
+                Some(Udf::Aggregate(function)) => Some(AggregateFunction::User(function)),
+                _ => None,
+            },
+        }
+    }
+
+    fn contains_aggregate(&self, expr: &Expr) -> bool {
+        let contains = |expr: &Expr| self.contains_aggregate(expr);
+        match expr {
+            Expr::Function(function) => {
+                self.aggregate_function(&function.name).is_some() || match &function.args {
+                    FunctionArguments::List(list) => list.args.iter().any(|argument| {
+                        matches!(argument, FunctionArg::Unnamed(FunctionArgExpr::Expr(argument)) if contains(argument))
+                    }),
+                    _ => false,
+                }
+            },
+            Expr::BinaryOp { left, right, .. } => contains(left) || contains(right),
+            Expr::UnaryOp { expr, .. } | Expr::Nested(expr) | Expr::IsNull(expr) | Expr::IsNotNull(expr) => contains(expr),
+            Expr::Between { expr, low, high, .. } => contains(expr) || contains(low) || contains(high),
+            Expr::InList { expr, list, .. } => contains(expr) || list.iter().any(contains),
+            _ => false,
+        }
+    }
+
+    // A call of a user-defined scalar function. Its arguments may hold aggregates.
+    fn bind_call(&self, function: &ast::Function, scope: &Scope, aggregation: &mut Option<Aggregation>) -> CrabDbResult<Expression> {
+        let Some(Udf::Scalar(udf)) = self.catalog.function(&function.name.to_string()) else {
+            return Err(CrabDBError::InvalidInput(format!("Unknown function {}", function.name)));
+        };
+        let arguments = match &function.args {
+            FunctionArguments::None => &[][..],
+            FunctionArguments::List(list) if list.duplicate_treatment.is_none() && list.clauses.is_empty() => list.args.as_slice(),
+            _ => return Err(unsupported(format!("{function}"))),
+        };
+        if function.filter.is_some() || function.over.is_some() {
+            return Err(unsupported(format!("{function}")));
+        }
+        if arguments.len() != udf.arguments().len() {
+            return Err(CrabDBError::InvalidInput(format!("{} takes {} arguments but got {}", function.name, udf.arguments().len(), arguments.len())));
+        }
+        let mut bound = Vec::with_capacity(arguments.len());
+        for (argument, data_type) in arguments.iter().zip(udf.arguments()) {
+            let FunctionArg::Unnamed(FunctionArgExpr::Expr(argument)) = argument else {
+                return Err(unsupported(format!("Argument {argument} of {}", function.name)));
+            };
+            let argument = self.bind_expr_in(argument, scope, aggregation)?;
+            let input = match aggregation {
+                Some(aggregation) => aggregation.schema(&scope.schema()),
+                None => scope.schema(),
+            };
+            check_argument(&function.name, &argument, &input, *data_type)?;
+            bound.push(argument);
+        }
+        Ok(Expression::Call(udf, bound))
+    }
+
+    // ST_CONTAINS(a, b) or ST_INTERSECTS(a, b) over two geometries, either of which may be
+    // written as text. Its arguments may hold aggregates.
+    fn bind_spatial(&self, function: &ast::Function, scope: &Scope, aggregation: &mut Option<Aggregation>) -> CrabDbResult<Expression> {
+        let op = spatial_op(&function.name).expect("checked by the caller");
+        let arguments = match &function.args {
+            FunctionArguments::List(list) if list.duplicate_treatment.is_none() && list.clauses.is_empty() => list.args.as_slice(),
+            _ => &[],
+        };
+        let [FunctionArg::Unnamed(FunctionArgExpr::Expr(left)), FunctionArg::Unnamed(FunctionArgExpr::Expr(right))] = arguments else {
+            return Err(CrabDBError::InvalidInput(format!("{} takes two geometries", function.name)));
+        };
+        if function.filter.is_some() || function.over.is_some() {
+            return Err(unsupported(format!("{function}")));
+        }
+        let (left, right) = (geometry(self.bind_expr_in(left, scope, aggregation)?)?, geometry(self.bind_expr_in(right, scope, aggregation)?)?);
+        let input = match aggregation {
+            Some(aggregation) => aggregation.schema(&scope.schema()),
+            None => scope.schema(),
+        };
+        for argument in [&left, &right] {
+            let argument_type = argument.data_type(&input);
+            if *argument != Expression::Constant(Value::Null) && argument_type != DataType::Geometry {
+                return Err(CrabDBError::InvalidInput(format!("{} takes GEOMETRY but got {argument_type}", function.name)));
+            }
+        }
+        Ok(Expression::Spatial(op, Box::new(left), Box::new(right)))
+    }
+
+    // BM25(column, 'query') scores rows by how relevant their text is to the query, with the
+    // statistics of the full-text index on the column.
+    fn bind_bm25(&self, function: &ast::Function, scope: &Scope) -> CrabDbResult<Expression> {
+        let arguments = match &function.args {
+            FunctionArguments::List(list) if list.duplicate_treatment.is_none() && list.clauses.is_empty() => list.args.as_slice(),
+            _ => &[],
+        };
+        let [FunctionArg::Unnamed(FunctionArgExpr::Expr(column)), FunctionArg::Unnamed(FunctionArgExpr::Expr(Expr::Value(query)))] = arguments else {
+            return Err(CrabDBError::InvalidInput(format!("{} takes a column and a search query", function.name)));
+        };
+        if function.filter.is_some() || function.over.is_some() {
+            return Err(unsupported(format!("{function}")));
+        }
+        let Expression::Column(position) = self.bind_expr(column, scope)? else {
+            return Err(CrabDBError::InvalidInput(format!("{} takes a column and a search query", function.name)));
+        };
+        let terms = search_terms(&query.value)?;
+        let index = scope.sources[position]
+            .and_then(|(table_oid, table_column)| {
+                let table = self.catalog.table_by_oid(table_oid)?;
+                self.catalog.indexes_of(&table).into_iter()
+                    .find(|index| index.kind() == IndexKind::FullText && index.key_columns() == [table_column])
+            })
+            .ok_or_else(|| CrabDBError::InvalidInput(format!("{} needs a full-text index on column {column}", function.name)))?;
+        let statistics = index.full_text().expect("full-text indexes are FullTextIndex").statistics(&terms);
+        Ok(Expression::Bm25(Box::new(Expression::Column(position)), terms, statistics))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::catalog::system_catalog::Catalog;
+    use crate::catalog::udf::{AggregateUdf, ScalarUdf};
+    use crate::execution::aggregation_executor::{AggregateExpression, AggregateFunction};
+    use crate::execution::expression::{CompareOp, Expression};
+    use crate::execution::join::JoinType;
+    use crate::sql::logical_plan::{BoundStatement, LogicalPlan, UniqueKey};
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use crate::types::schema::{Column, Schema};
+    use crate::types::value::{DataType, Value};
+    use crate::types::ErrorCode;
+    use super::{parse, Binder};
+
+    fn catalog() -> Catalog {
+        let bpm = Arc::new(BufferPoolManager::new(8, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(8, 2))));
+        let catalog = Catalog::open(bpm).unwrap();
+        catalog.create_table("crabs", Schema::new(vec![
+            Column::new("id", DataType::Int64).not_null(), Column::new("name", DataType::Varchar), Column::new("beach_id", DataType::Int32),
+        ])).unwrap();
+        catalog.create_table("beaches", Schema::new(vec![Column::new("id", DataType::Int32), Column::new("name", DataType::Varchar)])).unwrap();
+        catalog
+    }
+
+    fn bind(catalog: &Catalog, sql: &str) -> BoundStatement {
+        Binder::new(catalog).bind(&parse(sql).unwrap()[0]).unwrap()
+    }
+
+    fn bind_err(catalog: &Catalog, sql: &str) -> String {
+        let error = Binder::new(catalog).bind(&parse(sql).unwrap()[0]).err().unwrap();
+        assert_eq!(ErrorCode::InvalidInput, error.code());
+        assert!(!error.is_retryable());
+        error.to_string()
+    }
+
+    #[test]
+    pub fn test_bind_select() {
+        let catalog = catalog();
+        let BoundStatement::Select(plan) = bind(
+            &catalog,
+            "SELECT c.name, b.name AS beach FROM crabs c LEFT JOIN beaches b ON c.beach_id = b.id WHERE c.id > 10 ORDER BY beach DESC LIMIT 5 OFFSET 2",
+        ) else {
+            panic!("expected a select");
+        };
+        let LogicalPlan::Projection { input, expressions, schema } = plan else {
+            panic!("expected a projection");
+        };
+        assert_eq!(vec![Expression::Column(1), Expression::Column(4)], expressions);
+        assert_eq!(vec!["name", "beach"], schema.columns().iter().map(|column| column.name()).collect::<Vec<_>>());
+        let LogicalPlan::Limit { input, limit: Some(5), offset: 2 } = *input else {
+            panic!("expected a limit");
+        };
+        let LogicalPlan::Sort { input, keys } = *input else {
+            panic!("expected a sort");
+        };
+        assert!(keys[0].descending && keys[0].expression == Expression::Column(4));
+        let LogicalPlan::Filter { input, predicate } = *input else {
+            panic!("expected a filter");
+        };
+        assert_eq!(Expression::compare(CompareOp::Gt, Expression::Column(0), Expression::Constant(Value::Int32(10))), predicate);
+        let LogicalPlan::Join { join_type: JoinType::Left, predicate: Some(on), schema, .. } = *input else {
+            panic!("expected a join");
+        };
+        assert_eq!(Expression::compare(CompareOp::Eq, Expression::Column(2), Expression::Column(3)), on);
+        assert_eq!(5, schema.column_count());
+
+        assert_eq!("Column name is ambiguous", bind_err(&catalog, "SELECT name FROM crabs, beaches"));
+        assert_eq!("Column crabs.size does not exist", bind_err(&catalog, "SELECT crabs.size FROM crabs"));
+        assert_eq!("Table reefs does not exist", bind_err(&catalog, "SELECT * FROM reefs"));
+    }
+
+    #[test]
+    pub fn test_bind_aggregates_and_modifications() {
+        let catalog = catalog();
+        let BoundStatement::Select(LogicalPlan::Projection { input, expressions, schema }) = bind(
+            &catalog,
+            "SELECT beach_id, COUNT(*), MAX(id) + 1 FROM crabs GROUP BY beach_id HAVING COUNT(*) > 2",
+        ) else {
+            panic!("expected a projection");
+        };
+        assert_eq!(Expression::Column(0), expressions[0]);
+        assert_eq!(Expression::Column(1), expressions[1]);
+        assert_eq!(DataType::Int64, schema.column(1).data_type());
+        let LogicalPlan::Filter { input, predicate } = *input else {
+            panic!("expected HAVING");
+        };
+        assert_eq!(Expression::compare(CompareOp::Gt, Expression::Column(1), Expression::Constant(Value::Int32(2))), predicate);
+        let LogicalPlan::Aggregate { group_by, aggregates, .. } = *input else {
+            panic!("expected an aggregate");
+        };
+        assert_eq!(vec![Expression::Column(2)], group_by);
+        assert_eq!(
+            vec![AggregateExpression::count_star(), AggregateExpression::new(AggregateFunction::Max, Expression::Column(0))],
+            aggregates
+        );
+        assert_eq!(
+            "Column name must appear in the GROUP BY clause or be used in an aggregate function",
+            bind_err(&catalog, "SELECT name, COUNT(*) FROM crabs GROUP BY beach_id")
+        );
+        assert_eq!("Aggregate function SUM is not allowed here", bind_err(&catalog, "SELECT * FROM crabs WHERE SUM(id) > 1"));
+
+        let BoundStatement::Insert { source: LogicalPlan::Projection { input, expressions, .. }, .. } = bind(
+            &catalog, "INSERT INTO crabs (name, id) VALUES ('ferris', 1), ('crabby', -2)",
+        ) else {
+            panic!("expected an insert of reordered columns");
+        };
+        assert_eq!(vec![Expression::Column(1), Expression::Column(0), Expression::Constant(Value::Null)], expressions);
+        let LogicalPlan::Values { rows, .. } = *input else {
+            panic!("expected values");
+        };
+        assert_eq!(Expression::Constant(Value::Int32(-2)), rows[1][1]);
+        assert_eq!("Table crabs has 3 columns but 2 values were supplied", bind_err(&catalog, "INSERT INTO crabs VALUES (1, 'x')"));
+
+        let BoundStatement::Update { assignments, predicate: Some(_), .. } = bind(&catalog, "UPDATE crabs SET beach_id = beach_id + 1 WHERE id = 1") else {
+            panic!("expected an update");
+        };
+        assert_eq!(2, assignments[0].0);
+        let BoundStatement::Delete { predicate: None, .. } = bind(&catalog, "DELETE FROM crabs") else {
+            panic!("expected a delete");
+        };
+        let BoundStatement::CreateTable { name, schema, unique_keys, ttl: None, temporary: false, if_not_exists: true } = bind(
+            &catalog, "CREATE TABLE IF NOT EXISTS reefs (id BIGINT NOT NULL, depth DOUBLE)",
+        ) else {
+            panic!("expected a create table");
+        };
+        assert_eq!("reefs", name);
+        assert_eq!(Schema::new(vec![Column::new("id", DataType::Int64).not_null(), Column::new("depth", DataType::Float64)]), schema);
+        assert!(unique_keys.is_empty());
+    }
+
+    #[test]
+    pub fn test_bind_user_defined_functions() {
+        let catalog = catalog();
+        let shout = catalog.create_scalar_function(ScalarUdf::new("shout", vec![DataType::Varchar], DataType::Varchar, |arguments| {
+            Ok(arguments[0].as_str().map_or(Value::Null, |text| Value::Varchar(text.to_uppercase())))
+        })).unwrap();
+        let longest = catalog.create_aggregate_function(AggregateUdf::new("longest", DataType::Varchar, DataType::Int64, |values| {
+            Ok(Value::Int64(values.iter().filter_map(Value::as_str).map(str::len).max().unwrap_or(0) as i64))
+        })).unwrap();
+        assert!(catalog.create_scalar_function(ScalarUdf::new("SHOUT", vec![], DataType::Int32, |_| Ok(Value::Null))).is_err());
+        assert!(catalog.create_aggregate_function(AggregateUdf::new("sum", DataType::Int32, DataType::Int32, |_| Ok(Value::Null))).is_err());
+
+        let BoundStatement::Select(LogicalPlan::Projection { expressions, schema, .. }) = bind(&catalog, "SELECT Shout(name) FROM crabs") else {
+            panic!("expected a projection");
+        };
+        assert_eq!(vec![Expression::Call(shout.clone(), vec![Expression::Column(1)])], expressions);
+        assert_eq!(DataType::Varchar, schema.column(0).data_type());
+        let BoundStatement::Select(LogicalPlan::Projection { input, expressions, schema }) = bind(
+            &catalog, "SELECT beach_id, shout(MIN(name)), longest(name) + 1 FROM crabs GROUP BY beach_id",
+        ) else {
+            panic!("expected a projection");
+        };
+        assert_eq!(Expression::Call(shout, vec![Expression::Column(1)]), expressions[1]);
+        assert_eq!(DataType::Int64, schema.column(2).data_type());
+        let LogicalPlan::Aggregate { aggregates, .. } = *input else {
+            panic!("expected an aggregate");
+        };
+        assert_eq!(AggregateExpression::new(AggregateFunction::User(longest), Expression::Column(1)), aggregates[1]);
+
+        assert_eq!("Unknown function whisper", bind_err(&catalog, "SELECT whisper(name) FROM crabs"));
+        assert_eq!("shout takes 1 arguments but got 2", bind_err(&catalog, "SELECT shout(name, name) FROM crabs"));
+        assert_eq!("shout takes VARCHAR but got BIGINT", bind_err(&catalog, "SELECT shout(id) FROM crabs"));
+        assert_eq!("longest takes VARCHAR but got INT", bind_err(&catalog, "SELECT longest(beach_id) FROM crabs"));
+        assert_eq!("Aggregate function longest is not allowed here", bind_err(&catalog, "SELECT * FROM crabs WHERE longest(name) > 1"));
+        catalog.drop_function("shout").unwrap();
+        assert_eq!("Unknown function shout", bind_err(&catalog, "SELECT shout(name) FROM crabs"));
+    }
+
+    #[test]
+    pub fn test_binder_turns_key_constraints_into_unique_keys() {
+        let catalog = catalog();
+        let BoundStatement::CreateTable { schema, unique_keys, .. } = bind(
+            &catalog,
+            "CREATE TABLE reefs (id BIGINT PRIMARY KEY, name VARCHAR UNIQUE, x INT, y INT, CONSTRAINT reefs_spot UNIQUE (x, y))",
+        ) else {
+            panic!("expected a create table");
+        };
+        // primary key columns cannot be NULL
+        assert_eq!(Schema::new(vec![
+            Column::new("id", DataType::Int64).not_null(),
+            Column::new("name", DataType::Varchar),
+            Column::new("x", DataType::Int32),
+            Column::new("y", DataType::Int32),
+        ]), schema);
+        assert_eq!(vec![
+            UniqueKey { name: "reefs_pkey".into(), columns: vec!["id".into()] },
+            UniqueKey { name: "reefs_name_key".into(), columns: vec!["name".into()] },
+            UniqueKey { name: "reefs_spot".into(), columns: vec!["x".into(), "y".into()] },
+        ], unique_keys);
+
+        assert_eq!(
+            "Table reefs cannot have more than one primary key",
+            bind_err(&catalog, "CREATE TABLE reefs (id BIGINT PRIMARY KEY, x INT, PRIMARY KEY (x))"),
+        );
+        assert_eq!("Column z does not exist in table reefs", bind_err(&catalog, "CREATE TABLE reefs (id BIGINT, UNIQUE (z))"));
+        assert_eq!(
+            "Index reefs_id_key already exists",
+            bind_err(&catalog, "CREATE TABLE reefs (id BIGINT UNIQUE, CONSTRAINT reefs_id_key UNIQUE (id))"),
+        );
+        assert!(bind_err(&catalog, "CREATE TABLE reefs (id BIGINT, UNIQUE NULLS DISTINCT (id))").ends_with("is not supported"));
+    }
+}
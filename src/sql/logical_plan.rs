@@ -0,0 +1,168 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::catalog::table_info::{MaterializedView, TableInfo, Ttl};
+use crate::execution::aggregation_executor::AggregateExpression;
+use crate::execution::copy::CopyFormat;
+use crate::execution::expression::Expression;
+use crate::execution::join::{join_schema, JoinType};
+use crate::execution::sort_executor::SortKey;
+use crate::index::table_index::IndexKind;
+use crate::types::schema::Schema;
+
+// A query as relational operators over bound expressions: every name has been resolved
+// against the catalog and every column reference is a position in the operator's input row.
+// Nothing about how to execute it is decided yet; that is the planner's job.
+pub enum LogicalPlan {
+    Scan {
+        table: Arc<TableInfo>,
+    },
+    // Rows of constant expressions, such as the rows of INSERT ... VALUES.
+    Values {
+        rows: Vec<Vec<Expression>>,
+        schema: Schema,
+    },
+    Filter {
+        input: Box<LogicalPlan>,
+        predicate: Expression,
+    },
+    Projection {
+        input: Box<LogicalPlan>,
+        expressions: Vec<Expression>,
+        schema: Schema,
+    },
+    // Joined rows are the left row followed by the right row, and the predicate reads them.
+    Join {
+        left: Box<LogicalPlan>,
+        right: Box<LogicalPlan>,
+        join_type: JoinType,
+        predicate: Option<Expression>,
+        schema: Schema,
+    },
+    // Output rows are the group-by values followed by the aggregates.
+    Aggregate {
+        input: Box<LogicalPlan>,
+        group_by: Vec<Expression>,
+        aggregates: Vec<AggregateExpression>,
+        schema: Schema,
+    },
+    Sort {
+        input: Box<LogicalPlan>,
+        keys: Vec<SortKey>,
+    },
+    Limit {
+        input: Box<LogicalPlan>,
+        limit: Option<usize>,
+        offset: usize,
+    },
+}
+
+impl LogicalPlan {
+    pub fn schema(&self) -> &Schema {
+        match self {
+            LogicalPlan::Scan { table } => table.schema(),
+            LogicalPlan::Values { schema, .. }
+            | LogicalPlan::Projection { schema, .. }
+            | LogicalPlan::Join { schema, .. }
+            | LogicalPlan::Aggregate { schema, .. } => schema,
+            LogicalPlan::Filter { input, .. } | LogicalPlan::Sort { input, .. } | LogicalPlan::Limit { input, .. } => {
+                input.schema()
+            },
+        }
+    }
+
+    // The tables the plan scans, once per scan, left to right.
+    pub(crate) fn scans(&self) -> Vec<&Arc<TableInfo>> {
+        match self {
+            LogicalPlan::Scan { table } => vec![table],
+            LogicalPlan::Values { .. } => Vec::new(),
+            LogicalPlan::Join { left, right, .. } => {
+                let mut scans = left.scans();
+                scans.extend(right.scans());
+                scans
+            },
+            LogicalPlan::Filter { input, .. }
+            | LogicalPlan::Projection { input, .. }
+            | LogicalPlan::Aggregate { input, .. }
+            | LogicalPlan::Sort { input, .. }
+            | LogicalPlan::Limit { input, .. } => input.scans(),
+        }
+    }
+
+    pub(crate) fn join(left: LogicalPlan, right: LogicalPlan, join_type: JoinType, predicate: Option<Expression>) -> Self {
+        let schema = join_schema(left.schema(), right.schema(), join_type);
+        LogicalPlan::Join { left: Box::new(left), right: Box::new(right), join_type, predicate, schema }
+    }
+}
+
+// A PRIMARY KEY or UNIQUE constraint of a table being created, enforced by the B+ tree
+// index `name` over `columns`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UniqueKey {
+    pub name: String,
+    pub columns: Vec<String>,
+}
+
+// A statement with its names resolved, ready to be planned.
+pub enum BoundStatement {
+    CreateTable {
+        name: String,
+        schema: Schema,
+        unique_keys: Vec<UniqueKey>,
+        ttl: Option<Ttl>,
+        // A temporary table of the session the statement runs in.
+        temporary: bool,
+        if_not_exists: bool,
+    },
+    CreateIndex {
+        name: String,
+        table: Arc<TableInfo>,
+        columns: Vec<String>,
+        kind: IndexKind,
+        if_not_exists: bool,
+    },
+    // A table holding the result of the view's query.
+    CreateMaterializedView {
+        name: String,
+        schema: Schema,
+        view: MaterializedView,
+        if_not_exists: bool,
+    },
+    // Runs the query of the view kept in `table` again, replacing the rows the table holds.
+    RefreshMaterializedView {
+        table: Arc<TableInfo>,
+        view: Arc<MaterializedView>,
+    },
+    // `source` produces rows laid out like the table's.
+    Insert {
+        table: Arc<TableInfo>,
+        source: LogicalPlan,
+    },
+    Select(LogicalPlan),
+    // Assignments are (column, new value) and read the old row, as does the predicate.
+    Update {
+        table: Arc<TableInfo>,
+        assignments: Vec<(usize, Expression)>,
+        predicate: Option<Expression>,
+    },
+    Delete {
+        table: Arc<TableInfo>,
+        predicate: Option<Expression>,
+    },
+    // Collects the statistics of the tables.
+    Analyze {
+        tables: Vec<Arc<TableInfo>>,
+    },
+    // COPY table FROM (import) or TO (export) a file.
+    Copy {
+        table: Arc<TableInfo>,
+        path: PathBuf,
+        format: CopyFormat,
+        to: bool,
+    },
+    // EXPLAIN [ANALYZE] of a statement that runs a plan.
+    Explain {
+        statement: Box<BoundStatement>,
+        analyze: bool,
+    },
+}
@@ -0,0 +1,4 @@
+pub mod binder;
+pub mod logical_plan;
+pub mod physical_plan;
+pub mod planner;
@@ -0,0 +1,357 @@
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::ops::Bound;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::catalog::table_info::{IndexInfo, TableInfo};
+use crate::execution::aggregation_executor::{AggregateExpression, AggregateFunction, AggregationExecutor};
+use crate::execution::analyze_executor::{AnalyzeExecutor, OperatorStats};
+use crate::execution::delete_executor::DeleteExecutor;
+use crate::execution::executor::{execute, Executor, ExecutorContext};
+use crate::execution::expression::Expression;
+use crate::execution::filter_executor::FilterExecutor;
+use crate::execution::hash_join_executor::HashJoinExecutor;
+use crate::execution::index_scan_executor::{IndexLookup, IndexScanExecutor};
+use crate::execution::insert_executor::InsertExecutor;
+use crate::execution::join::{EquiJoinKeys, JoinType};
+use crate::execution::limit_executor::LimitExecutor;
+use crate::execution::nested_loop_join_executor::NestedLoopJoinExecutor;
+use crate::execution::projection_executor::ProjectionExecutor;
+use crate::execution::seq_scan_executor::SeqScanExecutor;
+use crate::execution::sort_executor::{SortExecutor, SortKey};
+use crate::execution::statistics_executor::StatisticsExecutor;
+use crate::execution::top_n_executor::TopNExecutor;
+use crate::execution::update_executor::UpdateExecutor;
+use crate::execution::values_executor::ValuesExecutor;
+use crate::index::rtree::SpatialQuery;
+use crate::types::schema::Schema;
+use crate::types::value::Value;
+use crate::types::CrabDbResult;
+
+// A query with every decision about how to run it made: each node maps to one executor.
+pub enum PhysicalPlan {
+    SeqScan {
+        table: Arc<TableInfo>,
+        predicate: Option<Expression>,
+    },
+    IndexScan {
+        table: Arc<TableInfo>,
+        index: Arc<IndexInfo>,
+        lookup: IndexLookup,
+        predicate: Option<Expression>,
+    },
+    Values {
+        rows: Vec<Vec<Expression>>,
+        schema: Schema,
+    },
+    Filter {
+        input: Box<PhysicalPlan>,
+        predicate: Expression,
+    },
+    Projection {
+        input: Box<PhysicalPlan>,
+        expressions: Vec<Expression>,
+        schema: Schema,
+    },
+    NestedLoopJoin {
+        left: Box<PhysicalPlan>,
+        right: Box<PhysicalPlan>,
+        join_type: JoinType,
+        predicate: Option<Expression>,
+    },
+    HashJoin {
+        left: Box<PhysicalPlan>,
+        right: Box<PhysicalPlan>,
+        join_type: JoinType,
+        keys: EquiJoinKeys,
+    },
+    Aggregate {
+        input: Box<PhysicalPlan>,
+        group_by: Vec<Expression>,
+        aggregates: Vec<AggregateExpression>,
+        having: Option<Expression>,
+        schema: Schema,
+    },
+    Sort {
+        input: Box<PhysicalPlan>,
+        keys: Vec<SortKey>,
+    },
+    Limit {
+        input: Box<PhysicalPlan>,
+        limit: Option<usize>,
+        offset: usize,
+    },
+    TopN {
+        input: Box<PhysicalPlan>,
+        keys: Vec<SortKey>,
+        n: usize,
+    },
+    Insert {
+        table: Arc<TableInfo>,
+        input: Box<PhysicalPlan>,
+    },
+    Update {
+        table: Arc<TableInfo>,
+        input: Box<PhysicalPlan>,
+        assignments: Vec<(usize, Expression)>,
+    },
+    Delete {
+        table: Arc<TableInfo>,
+        input: Box<PhysicalPlan>,
+    },
+    Analyze {
+        tables: Vec<Arc<TableInfo>>,
+    },
+}
+
+impl PhysicalPlan {
+    // Builds the executor tree that runs this plan in `ctx`.
+    pub fn build<'a>(&'a self, ctx: &'a ExecutorContext) -> Box<dyn Executor + 'a> {
+        self.build_with(ctx, &mut None)
+    }
+
+    // With `stats`, every executor is wrapped to record its OperatorStats, pushed in the
+    // order `render` visits the nodes.
+    fn build_with<'a>(
+        &'a self,
+        ctx: &'a ExecutorContext,
+        stats: &mut Option<Vec<Rc<RefCell<OperatorStats>>>>,
+    ) -> Box<dyn Executor + 'a> {
+        let own = stats.as_mut().map(|stats| {
+            let own = Rc::new(RefCell::new(OperatorStats::default()));
+            stats.push(own.clone());
+            own
+        });
+        let mut build = |plan: &'a PhysicalPlan| plan.build_with(ctx, stats);
+        let executor: Box<dyn Executor + 'a> = match self {
+            PhysicalPlan::SeqScan { table, predicate } => Box::new(SeqScanExecutor::new(ctx, table, predicate.clone())),
+            PhysicalPlan::IndexScan { table, index, lookup, predicate } => {
+                Box::new(IndexScanExecutor::new(ctx, table, index, lookup.clone(), predicate.clone()))
+            },
+            PhysicalPlan::Values { rows, schema } => Box::new(ValuesExecutor::new(rows.clone(), schema.clone())),
+            PhysicalPlan::Filter { input, predicate } => Box::new(FilterExecutor::new(build(input), predicate.clone())),
+            PhysicalPlan::Projection { input, expressions, schema } => {
+                Box::new(ProjectionExecutor::new(build(input), expressions.clone(), schema.clone()))
+            },
+            PhysicalPlan::NestedLoopJoin { left, right, join_type, predicate } => {
+                Box::new(NestedLoopJoinExecutor::new(build(left), build(right), *join_type, predicate.clone()))
+            },
+            PhysicalPlan::HashJoin { left, right, join_type, keys } => {
+                Box::new(HashJoinExecutor::new(ctx, build(left), build(right), *join_type, keys.clone()))
+            },
+            PhysicalPlan::Aggregate { input, group_by, aggregates, having, schema } => Box::new(AggregationExecutor::new(
+                build(input), group_by.clone(), aggregates.clone(), having.clone(), schema.clone(),
+            )),
+            PhysicalPlan::Sort { input, keys } => Box::new(SortExecutor::new(ctx, build(input), keys.clone())),
+            PhysicalPlan::Limit { input, limit, offset } => {
+                Box::new(LimitExecutor::with_offset(build(input), limit.unwrap_or(usize::MAX), *offset))
+            },
+            PhysicalPlan::TopN { input, keys, n } => Box::new(TopNExecutor::new(build(input), keys.clone(), *n)),
+            PhysicalPlan::Insert { table, input } => Box::new(InsertExecutor::new(ctx, table, build(input))),
+            PhysicalPlan::Update { table, input, assignments } => {
+                Box::new(UpdateExecutor::new(ctx, table, build(input), assignments.clone()))
+            },
+            PhysicalPlan::Delete { table, input } => Box::new(DeleteExecutor::new(ctx, table, build(input))),
+            PhysicalPlan::Analyze { tables } => Box::new(StatisticsExecutor::new(ctx, tables)),
+        };
+        match own {
+            Some(own) => Box::new(AnalyzeExecutor::new(executor, own)),
+            None => executor,
+        }
+    }
+
+    // The plan as an indented tree, one operator per line, for EXPLAIN.
+    pub fn explain(&self) -> String {
+        let mut out = String::new();
+        self.render(0, &mut None, &mut out);
+        out
+    }
+
+    // Runs the plan to completion in `ctx`, then renders it like `explain` with what each
+    // operator actually did. Statements that modify tables do modify them.
+    pub fn explain_analyze(&self, ctx: &ExecutorContext) -> CrabDbResult<String> {
+        let mut stats = Some(Vec::new());
+        execute(self.build_with(ctx, &mut stats).as_mut())?;
+        let stats: Vec<_> = stats.unwrap_or_default().iter().map(|stats| stats.borrow().clone()).collect();
+        let mut out = String::new();
+        self.render(0, &mut Some(stats.iter()), &mut out);
+        Ok(out)
+    }
+
+    fn children(&self) -> Vec<&PhysicalPlan> {
+        match self {
+            PhysicalPlan::SeqScan { .. }
+            | PhysicalPlan::IndexScan { .. }
+            | PhysicalPlan::Values { .. }
+            | PhysicalPlan::Analyze { .. } => Vec::new(),
+            PhysicalPlan::NestedLoopJoin { left, right, .. } | PhysicalPlan::HashJoin { left, right, .. } => vec![left, right],
+            PhysicalPlan::Filter { input, .. }
+            | PhysicalPlan::Projection { input, .. }
+            | PhysicalPlan::Aggregate { input, .. }
+            | PhysicalPlan::Sort { input, .. }
+            | PhysicalPlan::Limit { input, .. }
+            | PhysicalPlan::TopN { input, .. }
+            | PhysicalPlan::Insert { input, .. }
+            | PhysicalPlan::Update { input, .. }
+            | PhysicalPlan::Delete { input, .. } => vec![input],
+        }
+    }
+
+    fn render(&self, depth: usize, stats: &mut Option<std::slice::Iter<OperatorStats>>, out: &mut String) {
+        let _ = write!(out, "{}{}", "  ".repeat(depth), self.describe());
+        if let Some(own) = stats.as_mut().and_then(|stats| stats.next()) {
+            let _ = write!(
+                out, " (actual rows={} loops={} time={:.3}ms)", own.rows, own.loops, own.elapsed.as_secs_f64() * 1000.0
+            );
+        }
+        out.push('\n');
+        for child in self.children() {
+            child.render(depth + 1, stats, out);
+        }
+    }
+
+    fn describe(&self) -> String {
+        let list = |expressions: &mut dyn Iterator<Item = String>| expressions.collect::<Vec<_>>().join(", ");
+        let sort_keys = |keys: &[SortKey]| list(&mut keys.iter().map(|key| {
+            if key.descending { format!("{} DESC", key.expression) } else { key.expression.to_string() }
+        }));
+        let filter = |predicate: &Option<Expression>| predicate.as_ref().map_or(String::new(), |predicate| format!(" filter: {predicate}"));
+        match self {
+            PhysicalPlan::SeqScan { table, predicate } => format!("SeqScan {}{}", table.name(), filter(predicate)),
+            PhysicalPlan::IndexScan { table, index, lookup, predicate } => {
+                let lookup = match lookup {
+                    IndexLookup::Point(values) => format!("= ({})", list(&mut values.iter().map(|value| value.to_string()))),
+                    IndexLookup::Range { start, end } => {
+                        let start = match start {
+                            Bound::Included(value) => format!("[{value}"),
+                            Bound::Excluded(value) => format!("({value}"),
+                            Bound::Unbounded => "(-inf".to_string(),
+                        };
+                        let end = match end {
+                            Bound::Included(value) => format!("{value}]"),
+                            Bound::Excluded(value) => format!("{value})"),
+                            Bound::Unbounded => "+inf)".to_string(),
+                        };
+                        format!("in {start}, {end}")
+                    },
+                    IndexLookup::Terms(terms) => format!("match '{}'", terms.join(" ")),
+                    IndexLookup::Nearest { vector, k } => format!("nearest {k} to {}", Value::Vector(vector.clone())),
+                    IndexLookup::Spatial(SpatialQuery::Intersects(geometry)) => format!("intersecting {geometry}"),
+                    IndexLookup::Spatial(SpatialQuery::Contains(geometry)) => format!("containing {geometry}"),
+                    IndexLookup::Spatial(SpatialQuery::Within(geometry)) => format!("within {geometry}"),
+                    IndexLookup::NearestGeometry { geometry, k } => format!("nearest {k} to {geometry}"),
+                };
+                format!("IndexScan {} using {} {lookup}{}", table.name(), index.name(), filter(predicate))
+            },
+            PhysicalPlan::Values { rows, .. } => format!("Values ({} rows)", rows.len()),
+            PhysicalPlan::Filter { predicate, .. } => format!("Filter {predicate}"),
+            PhysicalPlan::Projection { expressions, .. } => {
+                format!("Projection [{}]", list(&mut expressions.iter().map(Expression::to_string)))
+            },
+            PhysicalPlan::NestedLoopJoin { join_type, predicate, .. } => match predicate {
+                Some(predicate) => format!("NestedLoopJoin {} on {predicate}", join_name(*join_type)),
+                None => format!("NestedLoopJoin {}", join_name(*join_type)),
+            },
+            PhysicalPlan::HashJoin { join_type, keys, .. } => {
+                let left = list(&mut keys.left.iter().map(Expression::to_string));
+                let right = list(&mut keys.right.iter().map(Expression::to_string));
+                let residual = keys.residual.as_ref().map_or(String::new(), |residual| format!(" residual: {residual}"));
+                format!("HashJoin {} on left [{left}] = right [{right}]{residual}", join_name(*join_type))
+            },
+            PhysicalPlan::Aggregate { group_by, aggregates, having, .. } => {
+                let aggregates = list(&mut aggregates.iter().map(|aggregate| match &aggregate.function {
+                    AggregateFunction::CountStar => "COUNT(*)".to_string(),
+                    function => format!("{function}({})", aggregate.argument),
+                }));
+                let mut text = format!("Aggregate [{aggregates}]");
+                if !group_by.is_empty() {
+                    let _ = write!(text, " group by [{}]", list(&mut group_by.iter().map(Expression::to_string)));
+                }
+                if let Some(having) = having {
+                    let _ = write!(text, " having: {having}");
+                }
+                text
+            },
+            PhysicalPlan::Sort { keys, .. } => format!("Sort [{}]", sort_keys(keys)),
+            PhysicalPlan::Limit { limit, offset, .. } => {
+                let limit = limit.map_or("all".to_string(), |limit| limit.to_string());
+                if *offset == 0 { format!("Limit {limit}") } else { format!("Limit {limit} offset {offset}") }
+            },
+            PhysicalPlan::TopN { keys, n, .. } => format!("TopN {n} [{}]", sort_keys(keys)),
+            PhysicalPlan::Insert { table, .. } => format!("Insert into {}", table.name()),
+            PhysicalPlan::Update { table, assignments, .. } => {
+                let assignments = list(&mut assignments.iter().map(|(column, value)| format!("#{column} = {value}")));
+                format!("Update {} set {assignments}", table.name())
+            },
+            PhysicalPlan::Delete { table, .. } => format!("Delete from {}", table.name()),
+            PhysicalPlan::Analyze { tables } => {
+                format!("Analyze [{}]", list(&mut tables.iter().map(|table| table.name().to_string())))
+            },
+        }
+    }
+}
+
+fn join_name(join_type: JoinType) -> &'static str {
+    match join_type {
+        JoinType::Inner => "inner",
+        JoinType::Left => "left",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::catalog::system_catalog::Catalog;
+    use crate::concurrency::lock_manager::LockManager;
+    use crate::concurrency::transaction::IsolationLevel;
+    use crate::concurrency::transaction_manager::TransactionManager;
+    use crate::execution::executor::ExecutorContext;
+    use crate::index::table_index::IndexKind;
+    use crate::sql::binder::{parse, Binder};
+    use crate::sql::planner::{Plan, Planner};
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use crate::types::schema::{Column, Schema};
+    use crate::types::value::DataType;
+
+    #[test]
+    pub fn test_explain_and_explain_analyze() {
+        let bpm = Arc::new(BufferPoolManager::new(16, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(16, 2))));
+        let catalog = Arc::new(Catalog::open(bpm).unwrap());
+        let txn_manager = Arc::new(TransactionManager::new(Arc::new(LockManager::new())));
+        let ctx = ExecutorContext::new(catalog.clone(), txn_manager.clone(), txn_manager.begin(IsolationLevel::SnapshotIsolation));
+        catalog.create_table("crabs", Schema::new(vec![
+            Column::new("id", DataType::Int64).not_null(), Column::new("name", DataType::Varchar), Column::new("size", DataType::Int32),
+        ])).unwrap();
+        catalog.create_index("crabs_id", "crabs", &["id"], IndexKind::BPlusTree).unwrap();
+        let plan = |sql: &str| Planner::new(&catalog).plan(Binder::new(&catalog).bind(&parse(sql).unwrap()[0]).unwrap());
+
+        let Plan::Explain { plan: insert, analyze: true } = plan("EXPLAIN ANALYZE INSERT INTO crabs VALUES (1, 'ferris', 3), (2, 'crabby', 5), (3, 'pinchy', 4)") else {
+            panic!("expected EXPLAIN ANALYZE");
+        };
+        assert_eq!(
+            "Insert into crabs\n  Values (3 rows)\n",
+            insert.explain()
+        );
+        let analyzed = insert.explain_analyze(&ctx).unwrap();
+        assert!(analyzed.starts_with("Insert into crabs (actual rows=1 loops=1 time="), "{analyzed}");
+        assert!(analyzed.contains("\n  Values (3 rows) (actual rows=3 loops=1 time="), "{analyzed}");
+
+        let Plan::Explain { plan: select, analyze: false } = plan("EXPLAIN SELECT name, size * 2 FROM crabs WHERE id >= 2 AND name <> 'x' ORDER BY size DESC LIMIT 1") else {
+            panic!("expected EXPLAIN");
+        };
+        assert_eq!(
+            "Projection [#0, (#1 * 2)]\n  TopN 1 [#1 DESC]\n    Projection [#1, #2]\n      \
+             IndexScan crabs using crabs_id in [2, +inf) filter: #0 >= 2 AND #1 <> 'x'\n",
+            select.explain()
+        );
+        let analyzed = select.explain_analyze(&ctx).unwrap();
+        let rows: Vec<_> = analyzed.lines().map(|line| line.split("actual rows=").nth(1).unwrap().split(' ').next().unwrap()).collect();
+        assert_eq!(vec!["1", "1", "2", "2"], rows);
+
+        assert!(Binder::new(&catalog).bind(&parse("EXPLAIN CREATE TABLE t (a INT)").unwrap()[0]).is_err());
+    }
+}
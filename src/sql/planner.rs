@@ -0,0 +1,732 @@
+use std::collections::BTreeSet;
+use std::ops::Bound;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::catalog::system_catalog::Catalog;
+use crate::catalog::table_info::{IndexInfo, MaterializedView, TableInfo, Ttl};
+use crate::execution::copy::CopyFormat;
+use crate::execution::expression::{CompareOp, Expression, SpatialOp};
+use crate::execution::index_scan_executor::IndexLookup;
+use crate::execution::join::{EquiJoinKeys, JoinType};
+use crate::execution::sort_executor::SortKey;
+use crate::index::index_key::encode_key;
+use crate::index::rtree::SpatialQuery;
+use crate::index::table_index::IndexKind;
+use crate::types::schema::{Column, Schema};
+use crate::types::value::Value;
+
+use super::logical_plan::{BoundStatement, LogicalPlan, UniqueKey};
+use super::physical_plan::PhysicalPlan;
+
+// The most rows ORDER BY ... LIMIT keeps in a TopN heap. Beyond that a sort, which can
+// spill, is cheaper on memory.
+pub const TOP_N_MAX_ROWS: usize = 10_000;
+
+// How many rows of a sequential scan cost as much as fetching one row through an index,
+// which reads a random page of the table for every row.
+pub const INDEX_ROW_COST: f64 = 4.0;
+
+// A bound statement ready to run: DDL and COPY are carried out directly, everything else
+// through its physical plan.
+pub enum Plan {
+    CreateTable {
+        name: String,
+        schema: Schema,
+        unique_keys: Vec<UniqueKey>,
+        ttl: Option<Ttl>,
+        // A temporary table of the session the statement runs in.
+        temporary: bool,
+        if_not_exists: bool,
+    },
+    CreateIndex {
+        name: String,
+        table: Arc<TableInfo>,
+        columns: Vec<String>,
+        kind: IndexKind,
+        if_not_exists: bool,
+    },
+    CreateMaterializedView {
+        name: String,
+        schema: Schema,
+        view: MaterializedView,
+        if_not_exists: bool,
+    },
+    RefreshMaterializedView {
+        table: Arc<TableInfo>,
+        view: Arc<MaterializedView>,
+    },
+    Execute(PhysicalPlan),
+    // Bulk loads a table from a file, or writes it to one.
+    Copy {
+        table: Arc<TableInfo>,
+        path: PathBuf,
+        format: CopyFormat,
+        to: bool,
+    },
+    // Shows the plan instead of running it, or with ANALYZE runs it and shows what each
+    // operator did.
+    Explain {
+        plan: PhysicalPlan,
+        analyze: bool,
+    },
+}
+
+// Turns logical plans into physical ones with a few rewrite rules: predicates are pushed
+// down to the scans and join inputs they read, columns nothing above needs are dropped
+// before joins, sorts and aggregations, scans use an index when the predicate pins or bounds
+// its key (and, once ANALYZE has run, statistics say the index is cheaper), equi-joins
+// become hash joins and ORDER BY ... LIMIT becomes a TopN, fed by an HNSW index when it
+// orders a table by the distance of a column to a vector.
+pub struct Planner<'a> {
+    catalog: &'a Catalog,
+    use_indexes: bool,
+}
+
+impl<'a> Planner<'a> {
+    pub fn new(catalog: &'a Catalog) -> Self {
+        Planner { catalog, use_indexes: true }
+    }
+
+    // Plans scans of whole tables only, for queries reading the past: indexes are not
+    // versioned, and an entry only points at the newest row with its key.
+    pub fn without_indexes(mut self) -> Self {
+        self.use_indexes = false;
+        self
+    }
+
+    pub fn plan(&self, statement: BoundStatement) -> Plan {
+        match statement {
+            BoundStatement::CreateTable { name, schema, unique_keys, ttl, temporary, if_not_exists } => {
+                Plan::CreateTable { name, schema, unique_keys, ttl, temporary, if_not_exists }
+            },
+            BoundStatement::CreateIndex { name, table, columns, kind, if_not_exists } => {
+                Plan::CreateIndex { name, table, columns, kind, if_not_exists }
+            },
+            BoundStatement::CreateMaterializedView { name, schema, view, if_not_exists } => {
+                Plan::CreateMaterializedView { name, schema, view, if_not_exists }
+            },
+            BoundStatement::RefreshMaterializedView { table, view } => Plan::RefreshMaterializedView { table, view },
+            BoundStatement::Insert { table, source } => {
+                Plan::Execute(PhysicalPlan::Insert { table, input: Box::new(self.plan_query(source)) })
+            },
+            BoundStatement::Select(plan) => Plan::Execute(self.plan_query(plan)),
+            BoundStatement::Update { table, assignments, predicate } => {
+                let input = Box::new(self.plan_scan(table.clone(), predicate));
+                Plan::Execute(PhysicalPlan::Update { table, input, assignments })
+            },
+            BoundStatement::Delete { table, predicate } => {
+                let input = Box::new(self.plan_scan(table.clone(), predicate));
+                Plan::Execute(PhysicalPlan::Delete { table, input })
+            },
+            BoundStatement::Analyze { tables } => Plan::Execute(PhysicalPlan::Analyze { tables }),
+            BoundStatement::Copy { table, path, format, to } => Plan::Copy { table, path, format, to },
+            BoundStatement::Explain { statement, analyze } => match self.plan(*statement) {
+                Plan::Execute(plan) => Plan::Explain { plan, analyze },
+                _ => unreachable!("the binder only explains statements that run a plan"),
+            },
+        }
+    }
+
+    pub fn plan_query(&self, plan: LogicalPlan) -> PhysicalPlan {
+        let plan = push_down(plan, Vec::new());
+        let required = (0..plan.schema().column_count()).collect();
+        let (plan, _) = prune(plan, &required);
+        self.physical(plan)
+    }
+
+    fn physical(&self, plan: LogicalPlan) -> PhysicalPlan {
+        match plan {
+            LogicalPlan::Scan { table } => self.plan_scan(table, None),
+            LogicalPlan::Filter { input, predicate } => match *input {
+                LogicalPlan::Scan { table } => self.plan_scan(table, Some(predicate)),
+                // What is left above an aggregation after pushdown is its HAVING.
+                LogicalPlan::Aggregate { input, group_by, aggregates, schema } => PhysicalPlan::Aggregate {
+                    input: Box::new(self.physical(*input)),
+                    group_by,
+                    aggregates,
+                    having: Some(predicate),
+                    schema,
+                },
+                input => PhysicalPlan::Filter { input: Box::new(self.physical(input)), predicate },
+            },
+            LogicalPlan::Values { rows, schema } => PhysicalPlan::Values { rows, schema },
+            LogicalPlan::Projection { input, expressions, schema } => {
+                PhysicalPlan::Projection { input: Box::new(self.physical(*input)), expressions, schema }
+            },
+            LogicalPlan::Join { left, right, join_type, predicate, .. } => {
+                let left_width = left.schema().column_count();
+                let (left, right) = (Box::new(self.physical(*left)), Box::new(self.physical(*right)));
+                match predicate.as_ref().and_then(|predicate| EquiJoinKeys::extract(predicate, left_width)) {
+                    Some(keys) => PhysicalPlan::HashJoin { left, right, join_type, keys },
+                    None => PhysicalPlan::NestedLoopJoin { left, right, join_type, predicate },
+                }
+            },
+            LogicalPlan::Aggregate { input, group_by, aggregates, schema } => PhysicalPlan::Aggregate {
+                input: Box::new(self.physical(*input)),
+                group_by,
+                aggregates,
+                having: None,
+                schema,
+            },
+            LogicalPlan::Sort { input, keys } => PhysicalPlan::Sort { input: Box::new(self.physical(*input)), keys },
+            LogicalPlan::Limit { input, limit, offset } => match (*input, limit) {
+                (LogicalPlan::Sort { input, keys }, Some(limit)) if limit.saturating_add(offset) <= TOP_N_MAX_ROWS => {
+                    let input = self.plan_nearest(&input, &keys, limit + offset).unwrap_or_else(|| self.physical(*input));
+                    let top_n = PhysicalPlan::TopN { input: Box::new(input), keys, n: limit + offset };
+                    if offset == 0 {
+                        return top_n;
+                    }
+                    PhysicalPlan::Limit { input: Box::new(top_n), limit: Some(limit), offset }
+                },
+                (input, limit) => PhysicalPlan::Limit { input: Box::new(self.physical(input)), limit, offset },
+            },
+        }
+    }
+
+    // The rows of a table about the `k` closest to a vector by a column with an HNSW index,
+    // or to a geometry by a column with an R-tree index, if `keys` order the table,
+    // unfiltered, by that distance. The TopN above orders them by their actual distance, as
+    // the index may return rows whose value has since changed.
+    fn plan_nearest(&self, input: &LogicalPlan, keys: &[SortKey], k: usize) -> Option<PhysicalPlan> {
+        if !self.use_indexes {
+            return None;
+        }
+        let [SortKey { expression: Expression::Distance(left, right), descending: false }] = keys else {
+            return None;
+        };
+        let (column, constant) = match (left.as_ref(), right.as_ref()) {
+            (Expression::Column(column), Expression::Constant(constant))
+            | (Expression::Constant(constant), Expression::Column(column)) => (*column, constant),
+            _ => return None,
+        };
+        let kind = match constant {
+            Value::Vector(_) => IndexKind::Hnsw,
+            Value::Geometry(_) => IndexKind::RTree,
+            _ => return None,
+        };
+        let (table, column) = match input {
+            LogicalPlan::Scan { table } => (table, column),
+            LogicalPlan::Projection { input, expressions, .. } => match (input.as_ref(), expressions.get(column)?) {
+                (LogicalPlan::Scan { table }, Expression::Column(column)) => (table, *column),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        let index = self.catalog.indexes_of(table).into_iter()
+            .find(|index| index.kind() == kind && index.key_columns() == [column])?;
+        let lookup = match constant {
+            Value::Geometry(geometry) => IndexLookup::NearestGeometry { geometry: *geometry, k },
+            Value::Vector(vector) => IndexLookup::Nearest { vector: vector.clone(), k },
+            _ => unreachable!("checked above"),
+        };
+        let scan = PhysicalPlan::IndexScan { table: table.clone(), index, lookup, predicate: None };
+        Some(match input {
+            LogicalPlan::Projection { expressions, schema, .. } => {
+                PhysicalPlan::Projection { input: Box::new(scan), expressions: expressions.clone(), schema: schema.clone() }
+            },
+            _ => scan,
+        })
+    }
+
+    // Reads the rows of a table matching `predicate`, through an index if one helps. Index
+    // entries may be stale, so the index scan still checks the whole predicate.
+    fn plan_scan(&self, table: Arc<TableInfo>, predicate: Option<Expression>) -> PhysicalPlan {
+        if let Some((index, lookup)) = predicate.as_ref().and_then(|predicate| self.choose_index(&table, predicate)) {
+            return PhysicalPlan::IndexScan { table, index, lookup, predicate };
+        }
+        PhysicalPlan::SeqScan { table, predicate }
+    }
+
+    // Without statistics, prefers an index whose whole key the predicate pins with
+    // equalities, then a single-column B+ tree whose key it bounds, then a full-text or
+    // R-tree index whose column it matches. With statistics from
+    // ANALYZE, picks the index expected to return the fewest rows, and none at all when
+    // fetching those rows one by one costs more than reading the whole table.
+    fn choose_index(&self, table: &TableInfo, predicate: &Expression) -> Option<(Arc<IndexInfo>, IndexLookup)> {
+        if !self.use_indexes {
+            return None;
+        }
+        let candidates = self.index_candidates(table, predicate);
+        let Some(statistics) = self.catalog.statistics_of(table) else {
+            return candidates.into_iter().next();
+        };
+        let row_count = statistics.row_count;
+        let estimate = |(index, lookup): &(Arc<IndexInfo>, IndexLookup)| {
+            let fraction = match lookup {
+                IndexLookup::Point(_) => index.key_columns().iter()
+                    .map(|&column| statistics.columns[column].equal_fraction(row_count))
+                    .product::<f64>(),
+                IndexLookup::Range { start, end } => statistics.columns[index.key_columns()[0]]
+                    .range_fraction(row_count, start.as_ref(), end.as_ref()),
+                // No more rows than hold the rarest term, which the index keeps count of.
+                IndexLookup::Terms(terms) => {
+                    let rarest = index.full_text().and_then(|full_text| terms.iter().map(|term| full_text.row_frequency(term)).min());
+                    return rarest.map_or(row_count as f64, |rows| rows as f64);
+                },
+                // The index judges the share from the boxes of its root, without searching.
+                IndexLookup::Spatial(query) => match index.rtree().and_then(|rtree| rtree.selectivity(query).ok()) {
+                    Some(fraction) => fraction,
+                    None => return row_count as f64,
+                },
+                IndexLookup::Nearest { k, .. } | IndexLookup::NearestGeometry { k, .. } => return *k as f64,
+            };
+            fraction * row_count as f64
+        };
+        candidates.into_iter()
+            .map(|candidate| (estimate(&candidate), candidate))
+            .filter(|(rows, _)| rows * INDEX_ROW_COST < row_count as f64)
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, candidate)| candidate)
+    }
+
+    // Every index the predicate can look rows up in: first those whose whole key it pins,
+    // then the single-column B+ trees whose key it bounds, then the full-text indexes on a
+    // column it matches and the R-tree indexes on a column it tests against a geometry.
+    fn index_candidates(&self, table: &TableInfo, predicate: &Expression) -> Vec<(Arc<IndexInfo>, IndexLookup)> {
+        let conjuncts = predicate.clone().into_conjuncts();
+        let comparisons: Vec<_> = conjuncts.iter().filter_map(column_comparison).collect();
+        let (mut points, mut ranges, mut matches) = (Vec::new(), Vec::new(), Vec::new());
+        for index in self.catalog.indexes_of(table) {
+            if index.kind() == IndexKind::FullText {
+                let terms = conjuncts.iter().find_map(|conjunct| match conjunct {
+                    Expression::Match(column, terms) if **column == Expression::Column(index.key_columns()[0]) => Some(terms.clone()),
+                    _ => None,
+                });
+                if let Some(terms) = terms {
+                    matches.push((index, IndexLookup::Terms(terms)));
+                }
+                continue;
+            }
+            if index.kind() == IndexKind::RTree {
+                let key = Expression::Column(index.key_columns()[0]);
+                let query = conjuncts.iter().find_map(|conjunct| match conjunct {
+                    Expression::Spatial(op, left, right) => match (op, left.as_ref(), right.as_ref()) {
+                        (SpatialOp::Intersects, column, Expression::Constant(Value::Geometry(geometry)))
+                        | (SpatialOp::Intersects, Expression::Constant(Value::Geometry(geometry)), column) if *column == key => {
+                            Some(SpatialQuery::Intersects(*geometry))
+                        },
+                        (SpatialOp::Contains, column, Expression::Constant(Value::Geometry(geometry))) if *column == key => {
+                            Some(SpatialQuery::Contains(*geometry))
+                        },
+                        (SpatialOp::Contains, Expression::Constant(Value::Geometry(geometry)), column) if *column == key => {
+                            Some(SpatialQuery::Within(*geometry))
+                        },
+                        _ => None,
+                    },
+                    _ => None,
+                });
+                if let Some(query) = query {
+                    matches.push((index, IndexLookup::Spatial(query)));
+                }
+                continue;
+            }
+            let point = index.key_columns().iter().zip(index.key_schema().columns())
+                .map(|(&column, key_column)| comparisons.iter().find_map(|(compared, op, value)| {
+                    (*compared == column && *op == CompareOp::Eq).then(|| key_value(value, key_column)).flatten()
+                }))
+                .collect::<Option<Vec<_>>>();
+            if let Some(values) = point {
+                points.push((index, IndexLookup::Point(values)));
+                continue;
+            }
+            if index.kind() != IndexKind::BPlusTree || index.key_columns().len() != 1 {
+                continue;
+            }
+            let (mut start, mut end) = (Bound::Unbounded, Bound::Unbounded);
+            for (compared, op, value) in &comparisons {
+                let Some(value) = (*compared == index.key_columns()[0]).then(|| key_value(value, index.key_schema().column(0))).flatten() else {
+                    continue;
+                };
+                match op {
+                    CompareOp::Gt if start == Bound::Unbounded => start = Bound::Excluded(value),
+                    CompareOp::GtEq if start == Bound::Unbounded => start = Bound::Included(value),
+                    CompareOp::Lt if end == Bound::Unbounded => end = Bound::Excluded(value),
+                    CompareOp::LtEq if end == Bound::Unbounded => end = Bound::Included(value),
+                    _ => {},
+                }
+            }
+            if start != Bound::Unbounded || end != Bound::Unbounded {
+                ranges.push((index, IndexLookup::Range { start, end }));
+            }
+        }
+        points.extend(ranges);
+        points.extend(matches);
+        points
+    }
+}
+
+// A conjunct comparing a column with a constant, as (column, operator, constant) with the
+// column on the left.
+fn column_comparison(conjunct: &Expression) -> Option<(usize, CompareOp, Value)> {
+    let Expression::Compare(op, left, right) = conjunct else {
+        return None;
+    };
+    match (left.as_ref(), right.as_ref()) {
+        (Expression::Column(column), Expression::Constant(value)) => Some((*column, *op, value.clone())),
+        (Expression::Constant(value), Expression::Column(column)) => {
+            let flipped = match op {
+                CompareOp::Lt => CompareOp::Gt,
+                CompareOp::LtEq => CompareOp::GtEq,
+                CompareOp::Gt => CompareOp::Lt,
+                CompareOp::GtEq => CompareOp::LtEq,
+                op => *op,
+            };
+            Some((*column, flipped, value.clone()))
+        },
+        _ => None,
+    }
+}
+
+// The constant as a value of a key column, if it can be looked up in the index at all:
+// NULL never compares equal, and a constant the column's type cannot hold exactly or that is
+// too long for a key is left to the predicate.
+fn key_value(value: &Value, key_column: &Column) -> Option<Value> {
+    if value.is_null() {
+        return None;
+    }
+    let value = value.cast_to(key_column.data_type()).ok()?;
+    encode_key(&Schema::new(vec![key_column.clone()]), std::slice::from_ref(&value)).ok()?;
+    Some(value)
+}
+
+fn filter(plan: LogicalPlan, predicates: Vec<Expression>) -> LogicalPlan {
+    match Expression::conjunction(predicates) {
+        Some(predicate) => LogicalPlan::Filter { input: Box::new(plan), predicate },
+        None => plan,
+    }
+}
+
+// Moves the conjuncts of `predicates`, which read the output of `plan`, and of the filters
+// inside it as far down as they can go.
+fn push_down(plan: LogicalPlan, mut predicates: Vec<Expression>) -> LogicalPlan {
+    match plan {
+        LogicalPlan::Filter { input, predicate } => {
+            predicates.extend(predicate.into_conjuncts());
+            push_down(*input, predicates)
+        },
+        LogicalPlan::Projection { input, expressions, schema } => {
+            let below = predicates.iter().map(|predicate| predicate.substitute(&expressions)).collect();
+            LogicalPlan::Projection { input: Box::new(push_down(*input, below)), expressions, schema }
+        },
+        LogicalPlan::Sort { input, keys } => LogicalPlan::Sort { input: Box::new(push_down(*input, predicates)), keys },
+        LogicalPlan::Join { left, right, join_type, predicate, .. } => {
+            let left_width = left.schema().column_count();
+            let (mut left_predicates, mut right_predicates, mut join_predicates, mut above) = (vec![], vec![], vec![], vec![]);
+            let on = predicate.map_or(Vec::new(), Expression::into_conjuncts);
+            match join_type {
+                JoinType::Inner => {
+                    for conjunct in predicates.into_iter().chain(on) {
+                        if conjunct.reads_only(0..left_width) {
+                            left_predicates.push(conjunct);
+                        } else if conjunct.reads_only(left_width..usize::MAX) {
+                            right_predicates.push(conjunct.remap_columns(&|column| column - left_width));
+                        } else {
+                            join_predicates.push(conjunct);
+                        }
+                    }
+                },
+                // Every left row comes out of a left join, matched or not, so WHERE can only
+                // filter its left input early and ON only its right input.
+                JoinType::Left => {
+                    for conjunct in predicates {
+                        if conjunct.reads_only(0..left_width) {
+                            left_predicates.push(conjunct);
+                        } else {
+                            above.push(conjunct);
+                        }
+                    }
+                    for conjunct in on {
+                        if conjunct.reads_only(left_width..usize::MAX) && !conjunct.reads_only(0..0) {
+                            right_predicates.push(conjunct.remap_columns(&|column| column - left_width));
+                        } else {
+                            join_predicates.push(conjunct);
+                        }
+                    }
+                },
+            }
+            let left = push_down(*left, left_predicates);
+            let right = push_down(*right, right_predicates);
+            filter(LogicalPlan::join(left, right, join_type, Expression::conjunction(join_predicates)), above)
+        },
+        // Conjuncts on the group-by values alone can filter the input rows instead of the
+        // groups. Without GROUP BY there is a single group even over no rows, so nothing moves.
+        LogicalPlan::Aggregate { input, group_by, aggregates, schema } => {
+            let (below, above): (Vec<_>, Vec<_>) = predicates.into_iter()
+                .partition(|predicate| !group_by.is_empty() && predicate.reads_only(0..group_by.len()));
+            let below = below.iter().map(|predicate| predicate.substitute(&group_by)).collect();
+            let aggregate = LogicalPlan::Aggregate { input: Box::new(push_down(*input, below)), group_by, aggregates, schema };
+            filter(aggregate, above)
+        },
+        LogicalPlan::Limit { input, limit, offset } => {
+            filter(LogicalPlan::Limit { input: Box::new(push_down(*input, Vec::new())), limit, offset }, predicates)
+        },
+        plan @ (LogicalPlan::Scan { .. } | LogicalPlan::Values { .. }) => filter(plan, predicates),
+    }
+}
+
+// Where each column of a plan's output ended up in the output of the pruned plan, if it was
+// kept.
+type ColumnMap = Vec<Option<usize>>;
+
+fn identity(width: usize) -> ColumnMap {
+    (0..width).map(Some).collect()
+}
+
+fn remap(expression: &Expression, map: &ColumnMap) -> Expression {
+    expression.remap_columns(&|column| map[column].expect("columns read above are kept"))
+}
+
+fn columns_read<'e>(expressions: impl IntoIterator<Item = &'e Expression>, columns: &mut BTreeSet<usize>) {
+    for expression in expressions {
+        expression.collect_columns(columns);
+    }
+}
+
+// Projects the output of `plan` down to the `required` columns, if that drops any.
+fn narrow(plan: LogicalPlan, required: &BTreeSet<usize>) -> (LogicalPlan, ColumnMap) {
+    let width = plan.schema().column_count();
+    if required.len() == width {
+        return (plan, identity(width));
+    }
+    let mut map = vec![None; width];
+    for (position, &column) in required.iter().enumerate() {
+        map[column] = Some(position);
+    }
+    let schema = Schema::new(required.iter().map(|&column| plan.schema().column(column).clone()).collect());
+    let expressions = required.iter().map(|&column| Expression::Column(column)).collect();
+    (LogicalPlan::Projection { input: Box::new(plan), expressions, schema }, map)
+}
+
+// Drops the columns of `plan`'s output outside `required`, and those its operators do not
+// need from their inputs, so fewer bytes flow through joins and get buffered by sorts,
+// aggregations and hash tables. Scans are narrowed right after their filter, which keeps a
+// filtered scan in one piece for index selection.
+fn prune(plan: LogicalPlan, required: &BTreeSet<usize>) -> (LogicalPlan, ColumnMap) {
+    match plan {
+        LogicalPlan::Scan { .. } => narrow(plan, required),
+        LogicalPlan::Filter { input, predicate } => {
+            if let LogicalPlan::Scan { .. } = *input {
+                return narrow(LogicalPlan::Filter { input, predicate }, required);
+            }
+            let mut needed = required.clone();
+            predicate.collect_columns(&mut needed);
+            let (input, map) = prune(*input, &needed);
+            let predicate = remap(&predicate, &map);
+            (LogicalPlan::Filter { input: Box::new(input), predicate }, map)
+        },
+        LogicalPlan::Values { .. } => {
+            let width = plan.schema().column_count();
+            (plan, identity(width))
+        },
+        LogicalPlan::Projection { input, expressions, schema } => {
+            let width = expressions.len();
+            let kept: Vec<usize> = required.iter().copied().collect();
+            let mut needed = BTreeSet::new();
+            columns_read(kept.iter().map(|&column| &expressions[column]), &mut needed);
+            let (input, input_map) = prune(*input, &needed);
+            let mut expressions: Vec<_> = kept.iter().map(|&column| remap(&expressions[column], &input_map)).collect();
+            let schema = Schema::new(kept.iter().map(|&column| schema.column(column).clone()).collect());
+            let mut map = vec![None; width];
+            for (position, &column) in kept.iter().enumerate() {
+                map[column] = Some(position);
+            }
+            // A projection of a projection is one projection.
+            let input = match input {
+                LogicalPlan::Projection { input, expressions: inner, .. } => {
+                    expressions = expressions.iter().map(|expression| expression.substitute(&inner)).collect();
+                    *input
+                },
+                input => input,
+            };
+            (LogicalPlan::Projection { input: Box::new(input), expressions, schema }, map)
+        },
+        LogicalPlan::Join { left, right, join_type, predicate, .. } => {
+            let left_width = left.schema().column_count();
+            let mut needed = required.clone();
+            columns_read(&predicate, &mut needed);
+            let left_needed = needed.range(..left_width).copied().collect();
+            let right_needed = needed.range(left_width..).map(|column| column - left_width).collect();
+            let (left, left_map) = prune(*left, &left_needed);
+            let (right, right_map) = prune(*right, &right_needed);
+            let new_left_width = left.schema().column_count();
+            let map: ColumnMap = left_map.into_iter()
+                .chain(right_map.into_iter().map(|column| column.map(|column| column + new_left_width)))
+                .collect();
+            let predicate = predicate.map(|predicate| remap(&predicate, &map));
+            (LogicalPlan::join(left, right, join_type, predicate), map)
+        },
+        LogicalPlan::Aggregate { input, group_by, aggregates, schema } => {
+            let mut needed = BTreeSet::new();
+            columns_read(group_by.iter().chain(aggregates.iter().map(|aggregate| &aggregate.argument)), &mut needed);
+            let (input, input_map) = prune(*input, &needed);
+            let group_by = group_by.iter().map(|expression| remap(expression, &input_map)).collect();
+            let aggregates = aggregates.into_iter()
+                .map(|mut aggregate| {
+                    aggregate.argument = remap(&aggregate.argument, &input_map);
+                    aggregate
+                })
+                .collect();
+            let width = schema.column_count();
+            (LogicalPlan::Aggregate { input: Box::new(input), group_by, aggregates, schema }, identity(width))
+        },
+        LogicalPlan::Sort { input, keys } => {
+            let mut needed = required.clone();
+            columns_read(keys.iter().map(|key| &key.expression), &mut needed);
+            let (input, map) = prune(*input, &needed);
+            let keys = keys.into_iter()
+                .map(|mut key| {
+                    key.expression = remap(&key.expression, &map);
+                    key
+                })
+                .collect();
+            (LogicalPlan::Sort { input: Box::new(input), keys }, map)
+        },
+        LogicalPlan::Limit { input, limit, offset } => {
+            let (input, map) = prune(*input, required);
+            (LogicalPlan::Limit { input: Box::new(input), limit, offset }, map)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::catalog::system_catalog::Catalog;
+    use crate::concurrency::lock_manager::LockManager;
+    use crate::concurrency::transaction::IsolationLevel;
+    use crate::concurrency::transaction_manager::TransactionManager;
+    use crate::execution::executor::{execute, ExecutorContext};
+    use crate::execution::index_scan_executor::IndexLookup;
+    use crate::index::table_index::IndexKind;
+    use crate::sql::binder::{parse, Binder};
+    use crate::sql::physical_plan::PhysicalPlan;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use crate::types::value::Value;
+    use crate::types::CrabDbResult;
+    use super::{Plan, Planner};
+
+    fn plan(catalog: &Catalog, sql: &str) -> PhysicalPlan {
+        let statement = Binder::new(catalog).bind(&parse(sql).unwrap()[0]).unwrap();
+        match Planner::new(catalog).plan(statement) {
+            Plan::Execute(plan) => plan,
+            _ => panic!("expected a plan to execute"),
+        }
+    }
+
+    fn run(ctx: &ExecutorContext, sql: &str) -> CrabDbResult<Vec<Vec<Value>>> {
+        let plan = plan(ctx.catalog(), sql);
+        let mut executor = plan.build(ctx);
+        Ok(execute(executor.as_mut())?.into_iter().map(|row| row.values).collect())
+    }
+
+    #[test]
+    pub fn test_planner_pushes_down_predicates_and_uses_indexes() {
+        let bpm = Arc::new(BufferPoolManager::new(16, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(16, 2))));
+        let catalog = Arc::new(Catalog::open(bpm).unwrap());
+        let txn_manager = Arc::new(TransactionManager::new(Arc::new(LockManager::new())));
+        let ctx = ExecutorContext::new(catalog.clone(), txn_manager.clone(), txn_manager.begin(IsolationLevel::SnapshotIsolation));
+        let create = |sql: &str| match Planner::new(&catalog).plan(Binder::new(&catalog).bind(&parse(sql).unwrap()[0]).unwrap()) {
+            Plan::CreateTable { name, schema, .. } => catalog.create_table(&name, schema).unwrap(),
+            _ => panic!("expected CREATE TABLE"),
+        };
+        create("CREATE TABLE crabs (id BIGINT NOT NULL, name VARCHAR, beach_id INT)");
+        create("CREATE TABLE beaches (id INT, name VARCHAR)");
+        catalog.create_index("crabs_id", "crabs", &["id"], IndexKind::BPlusTree).unwrap();
+        catalog.create_index("beaches_id", "beaches", &["id"], IndexKind::Hash).unwrap();
+        let crabs: Vec<_> = (0..50).map(|id| format!("({id}, 'crab{id}', {})", id % 5)).collect();
+        assert_eq!(vec![vec![Value::Int64(50)]], run(&ctx, &format!("INSERT INTO crabs VALUES {}", crabs.join(", "))).unwrap());
+        run(&ctx, "INSERT INTO beaches VALUES (0, 'sandy'), (1, 'rocky'), (2, 'pebbly')").unwrap();
+
+        let query = "SELECT name FROM crabs WHERE id = 7";
+        let PhysicalPlan::Projection { input, .. } = plan(&catalog, query) else {
+            panic!("expected a projection");
+        };
+        assert!(matches!(*input, PhysicalPlan::IndexScan { lookup: IndexLookup::Point(_), .. }));
+        assert_eq!(vec![vec![Value::Varchar("crab7".into())]], run(&ctx, query).unwrap());
+        assert_eq!(3, run(&ctx, "SELECT id FROM crabs WHERE 13 > id AND id >= 10").unwrap().len());
+
+        // Both WHERE conjuncts reach the scans below the hash join, and only the join's columns
+        // are kept from each side.
+        let query = "SELECT c.name, b.name FROM crabs c JOIN beaches b ON c.beach_id = b.id WHERE b.id = 2 AND c.id > 30";
+        let PhysicalPlan::Projection { input, .. } = plan(&catalog, query) else {
+            panic!("expected a projection");
+        };
+        let PhysicalPlan::HashJoin { left, right, .. } = *input else {
+            panic!("expected a hash join");
+        };
+        let PhysicalPlan::Projection { input: left, expressions, .. } = *left else {
+            panic!("expected the left side to be narrowed");
+        };
+        assert_eq!(2, expressions.len());
+        assert!(matches!(*left, PhysicalPlan::IndexScan { lookup: IndexLookup::Range { .. }, .. }));
+        assert!(matches!(*right, PhysicalPlan::IndexScan { lookup: IndexLookup::Point(_), .. }));
+        let rows = run(&ctx, query).unwrap();
+        assert_eq!(4, rows.len());
+        assert!(rows.iter().all(|row| row[1] == Value::Varchar("pebbly".into())));
+
+        let query = "SELECT id FROM crabs ORDER BY id DESC LIMIT 2 OFFSET 1";
+        let PhysicalPlan::Projection { input, .. } = plan(&catalog, query) else {
+            panic!("expected a projection");
+        };
+        assert!(matches!(*input, PhysicalPlan::Limit { input, .. } if matches!(*input, PhysicalPlan::TopN { n: 3, .. })));
+        assert_eq!(vec![vec![Value::Int64(48)], vec![Value::Int64(47)]], run(&ctx, query).unwrap());
+        let query = "SELECT beach_id, COUNT(*) AS n FROM crabs WHERE id < 20 GROUP BY beach_id HAVING COUNT(*) > 3 AND beach_id < 2";
+        assert_eq!(vec![vec![Value::Int32(0), Value::Int64(4)], vec![Value::Int32(1), Value::Int64(4)]], run(&ctx, query).unwrap());
+
+        // Index entries left behind by updates and deletes are skipped and replaced.
+        assert_eq!(vec![vec![Value::Int64(1)]], run(&ctx, "UPDATE crabs SET id = id + 1000 WHERE id = 3").unwrap());
+        assert!(run(&ctx, "SELECT * FROM crabs WHERE id = 3").unwrap().is_empty());
+        assert_eq!(vec![vec![Value::Varchar("crab3".into())]], run(&ctx, "SELECT name FROM crabs WHERE id = 1003").unwrap());
+        assert_eq!(vec![vec![Value::Int64(1)]], run(&ctx, "DELETE FROM crabs WHERE id = 5").unwrap());
+        run(&ctx, "INSERT INTO crabs VALUES (5, 'crab5 again', 0), (3, 'crab3 again', 0)").unwrap();
+        assert_eq!(vec![vec![Value::Varchar("crab5 again".into())]], run(&ctx, "SELECT name FROM crabs WHERE id = 5").unwrap());
+        let duplicate = run(&ctx, "INSERT INTO crabs VALUES (7, 'impostor', 0)").err().unwrap();
+        assert_eq!("Duplicate key in index crabs_id of table crabs", duplicate.to_string());
+    }
+
+    #[test]
+    pub fn test_planner_uses_statistics_to_choose_indexes() {
+        let bpm = Arc::new(BufferPoolManager::new(32, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(32, 2))));
+        let catalog = Arc::new(Catalog::open(bpm).unwrap());
+        let txn_manager = Arc::new(TransactionManager::new(Arc::new(LockManager::new())));
+        let ctx = ExecutorContext::new(catalog.clone(), txn_manager.clone(), txn_manager.begin(IsolationLevel::SnapshotIsolation));
+        let Plan::CreateTable { name, schema, .. } = Planner::new(&catalog).plan(
+            Binder::new(&catalog).bind(&parse("CREATE TABLE crabs (id BIGINT NOT NULL, size INT)").unwrap()[0]).unwrap()
+        ) else {
+            panic!("expected CREATE TABLE");
+        };
+        catalog.create_table(&name, schema).unwrap();
+        catalog.create_index("crabs_id", "crabs", &["id"], IndexKind::BPlusTree).unwrap();
+        let crabs: Vec<_> = (0..200).map(|id| format!("({id}, {})", id % 10)).collect();
+        run(&ctx, &format!("INSERT INTO crabs VALUES {}", crabs.join(", "))).unwrap();
+
+        let is_index_scan = |sql: &str| match plan(&catalog, sql) {
+            PhysicalPlan::Projection { input, .. } => matches!(*input, PhysicalPlan::IndexScan { .. }),
+            _ => panic!("expected a projection"),
+        };
+        // Without statistics any bound on the key is worth an index scan.
+        assert!(is_index_scan("SELECT size FROM crabs WHERE id > 5"));
+        assert!(catalog.table_statistics("crabs").is_none());
+
+        assert_eq!(vec![vec![Value::Int64(200)]], run(&ctx, "ANALYZE crabs").unwrap());
+        let statistics = catalog.table_statistics("crabs").unwrap();
+        assert_eq!(200, statistics.row_count);
+        assert_eq!(10, statistics.columns[1].distinct_count);
+        assert_eq!(Some(&Value::Int64(199)), statistics.columns[0].max());
+
+        // Almost every row matches, so reading the table is cheaper than the index.
+        assert!(!is_index_scan("SELECT size FROM crabs WHERE id > 5"));
+        assert!(is_index_scan("SELECT size FROM crabs WHERE id > 190"));
+        assert!(is_index_scan("SELECT size FROM crabs WHERE id = 7 AND size = 7"));
+        assert_eq!(195, run(&ctx, "SELECT size FROM crabs WHERE id >= 5").unwrap().len());
+
+        // A bare ANALYZE covers every table, and newer statistics replace older ones.
+        run(&ctx, "DELETE FROM crabs WHERE id >= 100").unwrap();
+        assert_eq!(vec![vec![Value::Int64(100)]], run(&ctx, "ANALYZE").unwrap());
+        assert_eq!(100, catalog.table_statistics("crabs").unwrap().row_count);
+    }
+}
@@ -0,0 +1,12 @@
+pub type PageId = u64;
+
+// Bytes per page. Fixed when crab-db is built: page layouts and database files depend on it.
+pub const PAGE_SIZE: usize = 4096;
+pub const INVALID_PAGE_ID: PageId = PageId::MAX;
+
+// Every page starts with a fixed header: first the disk layer's checksum, compression and
+// encryption metadata (see storage::disk::page_codec), then the page LSN the buffer pool
+// maintains for the WAL. Page layouts must start at PAGE_HEADER_SIZE.
+pub const PAGE_DISK_HEADER_SIZE: usize = 40;
+pub const PAGE_LSN_OFFSET: usize = PAGE_DISK_HEADER_SIZE;
+pub const PAGE_HEADER_SIZE: usize = PAGE_LSN_OFFSET + 8;
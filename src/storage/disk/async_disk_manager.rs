@@ -0,0 +1,19 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::storage::common::PageId;
+use crate::types::CrabDbResult;
+
+use super::disk_scheduler::PageBuffer;
+
+pub type DiskFuture<T> = Pin<Box<dyn Future<Output = CrabDbResult<T>> + Send>>;
+
+// Async counterpart of DiskManager. The returned futures own everything they need, so a
+// caller can start many reads and await them together or hand them to spawned tasks.
+pub trait AsyncDiskManager: Send + Sync {
+    fn read_page(&self, page_id: PageId) -> DiskFuture<PageBuffer>;
+    fn write_page(&self, page_id: PageId, data: PageBuffer) -> DiskFuture<()>;
+    fn allocate_page(&self) -> DiskFuture<PageId>;
+    fn deallocate_page(&self, page_id: PageId) -> DiskFuture<()>;
+    fn num_pages(&self) -> u64;
+}
@@ -0,0 +1,230 @@
+use std::fs::File;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::storage::common::{PageId, PAGE_SIZE};
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::async_disk_manager::{AsyncDiskManager, DiskFuture};
+use super::disk_manager::{check_page_buffer, DiskManager};
+use super::file_disk_manager::{open_db_file, AlignedPage, FileDiskManagerOptions};
+use super::page_codec::PageCodec;
+use super::disk_scheduler::PageBuffer;
+
+// File backend for async callers. Every request runs positional I/O (pread/pwrite) on tokio's
+// blocking pool, so unlike FileDiskManager no lock is held while the disk works and any number
+// of page reads can be in flight at once. The sync DiskManager impl uses the same positional
+// I/O, so it also lets a multi-worker DiskScheduler overlap reads.
+pub struct AsyncFileDiskManager {
+    inner: Arc<AsyncFileDiskManagerInner>,
+}
+
+struct AsyncFileDiskManagerInner {
+    db_file: File,
+    codec: PageCodec,
+    num_pages: Mutex<u64>,
+}
+
+impl AsyncFileDiskManager {
+    pub fn new(db_path: impl AsRef<Path>) -> CrabDbResult<Self> {
+        Self::with_options(db_path, FileDiskManagerOptions::default())
+    }
+
+    pub fn with_options(db_path: impl AsRef<Path>, options: FileDiskManagerOptions) -> CrabDbResult<Self> {
+        let opened = open_db_file(db_path.as_ref(), options.direct_io)?;
+        Ok(AsyncFileDiskManager {
+            inner: Arc::new(AsyncFileDiskManagerInner {
+                db_file: opened.db_file,
+                codec: PageCodec::with_encryption(options.compression, options.encryption),
+                num_pages: Mutex::new(opened.num_pages),
+            }),
+        })
+    }
+
+    fn spawn<T, F>(&self, op: F) -> DiskFuture<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&AsyncFileDiskManagerInner) -> CrabDbResult<T> + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || op(&inner))
+                .await
+                .map_err(|e| CrabDBError::new(format!("Disk I/O task failed: {e}")))?
+        })
+    }
+}
+
+impl AsyncFileDiskManagerInner {
+    fn check_page_id(&self, page_id: PageId) -> CrabDbResult<()> {
+        if page_id >= *self.num_pages.lock().unwrap() {
+            return Err(CrabDBError::NotFound(format!("Page {page_id} has not been allocated")));
+        }
+        Ok(())
+    }
+
+    fn read_page(&self, page_id: PageId, buf: &mut [u8]) -> CrabDbResult<()> {
+        check_page_buffer(buf.len())?;
+        self.check_page_id(page_id)?;
+        let mut raw = AlignedPage::zeroed();
+        read_exact_at(&self.db_file, &mut raw.0, page_id * PAGE_SIZE as u64)
+            .map_err(|e| CrabDBError::io(format!("Failed to read page {page_id}"), e))?;
+        self.codec.decode(page_id, &raw.0, buf)
+    }
+
+    fn write_page(&self, page_id: PageId, buf: &[u8]) -> CrabDbResult<()> {
+        check_page_buffer(buf.len())?;
+        self.check_page_id(page_id)?;
+        let mut raw = AlignedPage::zeroed();
+        self.codec.encode(page_id, buf, &mut raw.0);
+        write_all_at(&self.db_file, &raw.0, page_id * PAGE_SIZE as u64)
+            .map_err(|e| CrabDBError::io(format!("Failed to write page {page_id}"), e))?;
+        self.db_file.sync_data()
+            .map_err(|e| CrabDBError::io(format!("Failed to sync page {page_id}"), e))
+    }
+
+    fn allocate_page(&self) -> CrabDbResult<PageId> {
+        let mut num_pages = self.num_pages.lock().unwrap();
+        let page_id = *num_pages;
+        self.db_file.set_len((page_id + 1) * PAGE_SIZE as u64)
+            .map_err(|e| CrabDBError::io(format!("Failed to extend database file for page {page_id}"), e))?;
+        *num_pages += 1;
+        Ok(page_id)
+    }
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(unix)]
+fn write_all_at(file: &File, buf: &[u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::write_all_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset)? {
+            0 => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            n => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            },
+        }
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn write_all_at(file: &File, mut buf: &[u8], mut offset: u64) -> std::io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        let n = file.seek_write(buf, offset)?;
+        buf = &buf[n..];
+        offset += n as u64;
+    }
+    Ok(())
+}
+
+impl AsyncDiskManager for AsyncFileDiskManager {
+
+    fn read_page(&self, page_id: PageId) -> DiskFuture<PageBuffer> {
+        self.spawn(move |inner| {
+            let mut buf: PageBuffer = Box::new([0; PAGE_SIZE]);
+            inner.read_page(page_id, buf.as_mut_slice()).map(|_| buf)
+        })
+    }
+
+    fn write_page(&self, page_id: PageId, data: PageBuffer) -> DiskFuture<()> {
+        self.spawn(move |inner| inner.write_page(page_id, data.as_slice()))
+    }
+
+    fn allocate_page(&self) -> DiskFuture<PageId> {
+        self.spawn(|inner| inner.allocate_page())
+    }
+
+    // Deallocated pages are not reused yet; the file only grows.
+    fn deallocate_page(&self, page_id: PageId) -> DiskFuture<()> {
+        self.spawn(move |inner| inner.check_page_id(page_id))
+    }
+
+    fn num_pages(&self) -> u64 {
+        *self.inner.num_pages.lock().unwrap()
+    }
+}
+
+impl DiskManager for AsyncFileDiskManager {
+
+    fn read_page(&self, page_id: PageId, buf: &mut [u8]) -> CrabDbResult<()> {
+        self.inner.read_page(page_id, buf)
+    }
+
+    fn write_page(&self, page_id: PageId, buf: &[u8]) -> CrabDbResult<()> {
+        self.inner.write_page(page_id, buf)
+    }
+
+    fn allocate_page(&self) -> CrabDbResult<PageId> {
+        self.inner.allocate_page()
+    }
+
+    fn deallocate_page(&self, page_id: PageId) -> CrabDbResult<()> {
+        self.inner.check_page_id(page_id)
+    }
+
+    fn num_pages(&self) -> u64 {
+        *self.inner.num_pages.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use crate::storage::common::{PAGE_HEADER_SIZE, PAGE_SIZE};
+    use crate::storage::disk::async_disk_manager::AsyncDiskManager;
+    use super::AsyncFileDiskManager;
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_multi_thread().worker_threads(2).build().unwrap()
+    }
+
+    #[test]
+    pub fn test_async_disk_manager_concurrent_reads() {
+        let dir = TempDir::new().unwrap();
+        let disk_manager = AsyncFileDiskManager::new(dir.path().join("test.db")).unwrap();
+
+        runtime().block_on(async {
+            for page_id in 0..16u64 {
+                assert_eq!(page_id, AsyncDiskManager::allocate_page(&disk_manager).await.unwrap());
+                AsyncDiskManager::write_page(&disk_manager, page_id, Box::new([page_id as u8; PAGE_SIZE])).await.unwrap();
+            }
+            // all sixteen reads are started before the first one is awaited
+            let reads: Vec<_> = (0..16u64)
+                .map(|page_id| tokio::spawn(AsyncDiskManager::read_page(&disk_manager, page_id)))
+                .collect();
+            for (page_id, read) in reads.into_iter().enumerate() {
+                assert!(read.await.unwrap().unwrap()[PAGE_HEADER_SIZE..].iter().all(|b| *b == page_id as u8));
+            }
+        });
+    }
+
+    #[test]
+    pub fn test_async_disk_manager_errors() {
+        let dir = TempDir::new().unwrap();
+        let disk_manager = AsyncFileDiskManager::new(dir.path().join("test.db")).unwrap();
+
+        runtime().block_on(async {
+            assert_eq!(
+                "Page 2 has not been allocated",
+                AsyncDiskManager::read_page(&disk_manager, 2).await.unwrap_err().to_string()
+            );
+            assert_eq!(
+                "Page 2 has not been allocated",
+                AsyncDiskManager::deallocate_page(&disk_manager, 2).await.unwrap_err().to_string()
+            );
+        });
+    }
+}
@@ -0,0 +1,25 @@
+use crate::storage::common::{PageId, PAGE_SIZE};
+use crate::types::{CrabDBError, CrabDbResult};
+
+pub trait DiskManager: Send + Sync {
+    fn read_page(&self, page_id: PageId, buf: &mut [u8]) -> CrabDbResult<()>;
+    fn write_page(&self, page_id: PageId, buf: &[u8]) -> CrabDbResult<()>;
+    // Writes `bufs` to consecutive pages, the first to `first_page_id`. Managers that can
+    // write them in one go, and sync them once, do; the rest write them one at a time.
+    fn write_pages(&self, first_page_id: PageId, bufs: &[&[u8]]) -> CrabDbResult<()> {
+        for (page_id, buf) in (first_page_id..).zip(bufs) {
+            self.write_page(page_id, buf)?;
+        }
+        Ok(())
+    }
+    fn allocate_page(&self) -> CrabDbResult<PageId>;
+    fn deallocate_page(&self, page_id: PageId) -> CrabDbResult<()>;
+    fn num_pages(&self) -> u64;
+}
+
+pub(crate) fn check_page_buffer(len: usize) -> CrabDbResult<()> {
+    if len != PAGE_SIZE {
+        return Err(CrabDBError::InvalidInput(format!("Page buffer must be {PAGE_SIZE} bytes, got {len}")));
+    }
+    Ok(())
+}
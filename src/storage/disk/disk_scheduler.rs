@@ -0,0 +1,400 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread::JoinHandle;
+
+use crate::platform;
+use crate::storage::common::{PageId, PAGE_SIZE};
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::disk_manager::DiskManager;
+
+pub type PageBuffer = Box<[u8; PAGE_SIZE]>;
+
+pub enum DiskRequest {
+    ReadPage {
+        page_id: PageId,
+        callback: DiskPromise<PageBuffer>,
+    },
+    WritePage {
+        page_id: PageId,
+        data: PageBuffer,
+        callback: DiskPromise<()>,
+    },
+    // Consecutive pages, the first being `first_page_id`, written in one go.
+    WritePages {
+        first_page_id: PageId,
+        data: Vec<PageBuffer>,
+        callback: DiskPromise<()>,
+    },
+}
+
+struct CompletionSlot<T> {
+    result: Option<CrabDbResult<T>>,
+    waker: Option<Waker>,
+}
+
+struct SharedCompletion<T> {
+    slot: Mutex<CompletionSlot<T>>,
+    ready: Condvar,
+}
+
+// The worker's half of a request: exactly one result is handed over, either through
+// `complete` or, if the request is dropped unserviced, as a shutdown error.
+pub struct DiskPromise<T> {
+    shared: Option<Arc<SharedCompletion<T>>>,
+}
+
+impl<T> DiskPromise<T> {
+    pub fn complete(mut self, result: CrabDbResult<T>) {
+        if let Some(shared) = self.shared.take() {
+            Self::fulfil(&shared, result);
+        }
+    }
+
+    fn fulfil(shared: &SharedCompletion<T>, result: CrabDbResult<T>) {
+        let mut slot = shared.slot.lock().unwrap();
+        slot.result = Some(result);
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+        shared.ready.notify_all();
+    }
+}
+
+impl<T> Drop for DiskPromise<T> {
+    fn drop(&mut self) {
+        if let Some(shared) = self.shared.take() {
+            Self::fulfil(&shared, Err(CrabDBError::new("Disk scheduler shut down before completing the request".into())));
+        }
+    }
+}
+
+// Handle to an in-flight request. Threads block on it with `wait`; async callers simply
+// `.await` it, so many requests can be outstanding without parking a thread per request.
+pub struct DiskCompletion<T> {
+    shared: Arc<SharedCompletion<T>>,
+}
+
+pub fn disk_promise<T>() -> (DiskPromise<T>, DiskCompletion<T>) {
+    let shared = Arc::new(SharedCompletion {
+        slot: Mutex::new(CompletionSlot { result: None, waker: None }),
+        ready: Condvar::new(),
+    });
+    (DiskPromise { shared: Some(shared.clone()) }, DiskCompletion { shared })
+}
+
+impl<T> DiskCompletion<T> {
+    pub fn wait(self) -> CrabDbResult<T> {
+        let mut slot = self.shared.slot.lock().unwrap();
+        loop {
+            if let Some(result) = slot.result.take() {
+                return result;
+            }
+            slot = self.shared.ready.wait(slot).unwrap();
+        }
+    }
+
+    pub fn try_result(&self) -> Option<CrabDbResult<T>> {
+        self.shared.slot.lock().unwrap().result.take()
+    }
+}
+
+impl<T> Future for DiskCompletion<T> {
+    type Output = CrabDbResult<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.shared.slot.lock().unwrap();
+        match slot.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            },
+        }
+    }
+}
+
+// A request with the disk manager it is for, as workers receive it.
+type QueuedRequest = (Arc<dyn DiskManager>, DiskRequest);
+
+// Queues page reads and writes and runs them on background worker threads so callers only
+// block when they actually need the result. Schedulers made with `share` send their requests
+// to the same workers, which stop once every one of them is dropped.
+pub struct DiskScheduler {
+    disk_manager: Arc<dyn DiskManager>,
+    sender: Option<Sender<QueuedRequest>>,
+    workers: Option<Arc<Vec<JoinHandle<()>>>>,
+    // Requests run on the thread that schedules them; there are no workers.
+    inline: bool,
+}
+
+impl DiskScheduler {
+    // Inline where the platform has no threads, whatever `num_workers` says.
+    pub fn new(disk_manager: Arc<dyn DiskManager>, num_workers: usize) -> Self {
+        if !platform::HAS_THREADS {
+            return Self::inline(disk_manager);
+        }
+        let (sender, receiver) = mpsc::channel::<QueuedRequest>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..num_workers.max(1)).map(|worker| {
+            let receiver = receiver.clone();
+            std::thread::Builder::new()
+                .name(format!("crab-db-disk-{worker}"))
+                .spawn(move || Self::run_worker(receiver))
+                .expect("failed to spawn disk scheduler worker")
+        }).collect();
+        DiskScheduler {
+            disk_manager,
+            sender: Some(sender),
+            workers: Some(Arc::new(workers)),
+            inline: false,
+        }
+    }
+
+    // A scheduler for `disk_manager` that runs its requests on this one's workers, so
+    // several databases can share them.
+    pub fn share(&self, disk_manager: Arc<dyn DiskManager>) -> Self {
+        DiskScheduler {
+            disk_manager,
+            sender: self.sender.clone(),
+            workers: self.workers.clone(),
+            inline: self.inline,
+        }
+    }
+
+    // Runs each request before `schedule` returns, so its completion is already done.
+    pub fn inline(disk_manager: Arc<dyn DiskManager>) -> Self {
+        DiskScheduler {
+            disk_manager,
+            sender: None,
+            workers: None,
+            inline: true,
+        }
+    }
+
+    pub fn disk_manager(&self) -> &Arc<dyn DiskManager> {
+        &self.disk_manager
+    }
+
+    pub fn schedule(&self, request: DiskRequest) -> CrabDbResult<()> {
+        if self.inline {
+            Self::run(self.disk_manager.as_ref(), request);
+            return Ok(());
+        }
+        match &self.sender {
+            Some(sender) => sender.send((self.disk_manager.clone(), request))
+                .map_err(|_| CrabDBError::new("Disk scheduler is shut down".into())),
+            None => Err(CrabDBError::new("Disk scheduler is shut down".into())),
+        }
+    }
+
+    pub fn schedule_read(&self, page_id: PageId) -> CrabDbResult<DiskCompletion<PageBuffer>> {
+        let (callback, completion) = disk_promise();
+        self.schedule(DiskRequest::ReadPage { page_id, callback })?;
+        Ok(completion)
+    }
+
+    pub fn schedule_write(&self, page_id: PageId, data: PageBuffer) -> CrabDbResult<DiskCompletion<()>> {
+        let (callback, completion) = disk_promise();
+        self.schedule(DiskRequest::WritePage { page_id, data, callback })?;
+        Ok(completion)
+    }
+
+    pub fn schedule_writes(&self, first_page_id: PageId, data: Vec<PageBuffer>) -> CrabDbResult<DiskCompletion<()>> {
+        let (callback, completion) = disk_promise();
+        self.schedule(DiskRequest::WritePages { first_page_id, data, callback })?;
+        Ok(completion)
+    }
+
+    pub async fn read_page_async(&self, page_id: PageId) -> CrabDbResult<PageBuffer> {
+        self.schedule_read(page_id)?.await
+    }
+
+    pub async fn write_page_async(&self, page_id: PageId, data: PageBuffer) -> CrabDbResult<()> {
+        self.schedule_write(page_id, data)?.await
+    }
+
+    fn run_worker(receiver: Arc<Mutex<Receiver<QueuedRequest>>>) {
+        loop {
+            let (disk_manager, request) = match receiver.lock().unwrap().recv() {
+                Ok(request) => request,
+                // Every sender is gone: the scheduler is shutting down.
+                Err(_) => return,
+            };
+            Self::run(disk_manager.as_ref(), request);
+        }
+    }
+
+    fn run(disk_manager: &dyn DiskManager, request: DiskRequest) {
+        match request {
+            DiskRequest::ReadPage { page_id, callback } => callback.complete(Self::read(disk_manager, page_id)),
+            DiskRequest::WritePage { page_id, data, callback } => {
+                callback.complete(Self::write(disk_manager, page_id, &data));
+            },
+            DiskRequest::WritePages { first_page_id, data, callback } => {
+                callback.complete(Self::write_run(disk_manager, first_page_id, &data));
+            },
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "disk_read", level = "trace", skip(disk_manager), err))]
+    fn read(disk_manager: &dyn DiskManager, page_id: PageId) -> CrabDbResult<PageBuffer> {
+        let mut buf: PageBuffer = Box::new([0; PAGE_SIZE]);
+        disk_manager.read_page(page_id, buf.as_mut_slice())?;
+        Ok(buf)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "disk_write", level = "trace", skip(disk_manager, data), err))]
+    fn write(disk_manager: &dyn DiskManager, page_id: PageId, data: &PageBuffer) -> CrabDbResult<()> {
+        disk_manager.write_page(page_id, data.as_slice())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "disk_write_run", level = "trace", skip(disk_manager, data), fields(pages = data.len()), err))]
+    fn write_run(disk_manager: &dyn DiskManager, first_page_id: PageId, data: &[PageBuffer]) -> CrabDbResult<()> {
+        let bufs: Vec<&[u8]> = data.iter().map(|page| page.as_slice()).collect();
+        disk_manager.write_pages(first_page_id, &bufs)
+    }
+}
+
+impl Drop for DiskScheduler {
+    fn drop(&mut self) {
+        self.sender.take();
+        // Whichever scheduler lets go of the workers last has dropped its sender after every
+        // other one, so the workers are on their way out.
+        if let Some(workers) = self.workers.take().and_then(Arc::into_inner) {
+            for worker in workers {
+                let _ = worker.join();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    use crate::storage::common::PAGE_SIZE;
+    use crate::storage::disk::disk_manager::DiskManager as _;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use super::{disk_promise, DiskScheduler};
+
+    #[test]
+    pub fn test_disk_scheduler_write_then_read() {
+        let disk_manager = Arc::new(MemoryDiskManager::new());
+        let page_id = disk_manager.allocate_page().unwrap();
+        let scheduler = DiskScheduler::new(disk_manager, 1);
+
+        let write = scheduler.schedule_write(page_id, Box::new([7; PAGE_SIZE])).unwrap();
+        let read = scheduler.schedule_read(page_id).unwrap();
+        // a single worker runs requests in submission order
+        assert!(write.wait().is_ok());
+        assert_eq!([7; PAGE_SIZE], *read.wait().unwrap());
+    }
+
+    #[test]
+    pub fn test_inline_disk_scheduler_completes_before_returning() {
+        let disk_manager = Arc::new(MemoryDiskManager::new());
+        let page_id = disk_manager.allocate_page().unwrap();
+        let scheduler = DiskScheduler::inline(disk_manager);
+
+        let write = scheduler.schedule_write(page_id, Box::new([3; PAGE_SIZE])).unwrap();
+        assert!(write.try_result().unwrap().is_ok());
+        let read = scheduler.schedule_read(page_id).unwrap();
+        assert_eq!([3; PAGE_SIZE], *read.try_result().unwrap().unwrap());
+    }
+
+    #[test]
+    pub fn test_disk_scheduler_reports_errors() {
+        let scheduler = DiskScheduler::new(Arc::new(MemoryDiskManager::new()), 2);
+        assert_eq!(
+            "Page 5 has not been allocated",
+            scheduler.schedule_read(5).unwrap().wait().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    pub fn test_disk_scheduler_many_requests_many_workers() {
+        let disk_manager = Arc::new(MemoryDiskManager::new());
+        for _ in 0..32 {
+            disk_manager.allocate_page().unwrap();
+        }
+        let scheduler = DiskScheduler::new(disk_manager, 4);
+
+        let writes: Vec<_> = (0..32u64)
+            .map(|page_id| scheduler.schedule_write(page_id, Box::new([page_id as u8; PAGE_SIZE])).unwrap())
+            .collect();
+        for write in writes {
+            assert!(write.wait().is_ok());
+        }
+        let reads: Vec<_> = (0..32u64).map(|page_id| scheduler.schedule_read(page_id).unwrap()).collect();
+        for (page_id, read) in reads.into_iter().enumerate() {
+            assert_eq!(page_id as u8, read.wait().unwrap()[PAGE_SIZE - 1]);
+        }
+    }
+
+    #[test]
+    pub fn test_shared_disk_scheduler_keeps_disk_managers_apart() {
+        let (first, second) = (Arc::new(MemoryDiskManager::new()), Arc::new(MemoryDiskManager::new()));
+        let page_id = first.allocate_page().unwrap();
+        assert_eq!(page_id, second.allocate_page().unwrap());
+        let scheduler = DiskScheduler::new(first, 2);
+        let shared = scheduler.share(second.clone());
+
+        scheduler.schedule_write(page_id, Box::new([1; PAGE_SIZE])).unwrap().wait().unwrap();
+        shared.schedule_write(page_id, Box::new([2; PAGE_SIZE])).unwrap().wait().unwrap();
+        assert_eq!([1; PAGE_SIZE], *scheduler.schedule_read(page_id).unwrap().wait().unwrap());
+        // the workers outlive the scheduler that started them
+        drop(scheduler);
+        assert_eq!([2; PAGE_SIZE], *shared.schedule_read(page_id).unwrap().wait().unwrap());
+    }
+
+    #[test]
+    pub fn test_disk_scheduler_async_reads_in_flight() {
+        let disk_manager = Arc::new(MemoryDiskManager::new());
+        for page_id in 0..8u64 {
+            disk_manager.allocate_page().unwrap();
+            disk_manager.write_page(page_id, &[page_id as u8; PAGE_SIZE]).unwrap();
+        }
+        let scheduler = DiskScheduler::new(disk_manager, 4);
+
+        // issue every read before awaiting any of them
+        let reads: Vec<_> = (0..8u64).map(|page_id| scheduler.read_page_async(page_id)).collect();
+        for (page_id, read) in reads.into_iter().enumerate() {
+            assert_eq!(page_id as u8, block_on(read).unwrap()[0]);
+        }
+    }
+
+    #[test]
+    pub fn test_disk_promise_dropped_reports_shutdown() {
+        let (promise, completion) = disk_promise::<()>();
+        drop(promise);
+        assert_eq!(
+            "Disk scheduler shut down before completing the request",
+            completion.wait().unwrap_err().to_string()
+        );
+    }
+
+    // Minimal executor so the async path can be tested without pulling in a runtime.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        struct ThreadWaker(std::thread::Thread);
+        impl Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        let mut context = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+            std::thread::park();
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::{Mutex, MutexGuard};
+
+use crate::storage::common::{PageId, PAGE_SIZE};
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::disk_manager::{check_page_buffer, DiskManager};
+
+#[derive(Default)]
+pub struct MemoryDiskManager {
+    pages: Mutex<Vec<Box<[u8; PAGE_SIZE]>>>,
+}
+
+impl MemoryDiskManager {
+    pub fn new() -> Self {
+        MemoryDiskManager {
+            pages: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+fn check_page_id(pages: &[Box<[u8; PAGE_SIZE]>], page_id: PageId) -> CrabDbResult<usize> {
+    if page_id >= pages.len() as u64 {
+        return Err(CrabDBError::NotFound(format!("Page {page_id} has not been allocated")));
+    }
+    Ok(page_id as usize)
+}
+
+impl DiskManager for MemoryDiskManager {
+
+    fn read_page(&self, page_id: PageId, buf: &mut [u8]) -> CrabDbResult<()> {
+        check_page_buffer(buf.len())?;
+        let pages: MutexGuard<Vec<Box<[u8; PAGE_SIZE]>>> = self.pages.lock().unwrap();
+        let index = check_page_id(&pages, page_id)?;
+        buf.copy_from_slice(pages[index].as_slice());
+        Ok(())
+    }
+
+    fn write_page(&self, page_id: PageId, buf: &[u8]) -> CrabDbResult<()> {
+        check_page_buffer(buf.len())?;
+        let mut pages: MutexGuard<Vec<Box<[u8; PAGE_SIZE]>>> = self.pages.lock().unwrap();
+        let index = check_page_id(&pages, page_id)?;
+        pages[index].copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn allocate_page(&self) -> CrabDbResult<PageId> {
+        let mut pages: MutexGuard<Vec<Box<[u8; PAGE_SIZE]>>> = self.pages.lock().unwrap();
+        pages.push(Box::new([0; PAGE_SIZE]));
+        Ok(pages.len() as u64 - 1)
+    }
+
+    fn deallocate_page(&self, page_id: PageId) -> CrabDbResult<()> {
+        let mut pages: MutexGuard<Vec<Box<[u8; PAGE_SIZE]>>> = self.pages.lock().unwrap();
+        let index = check_page_id(&pages, page_id)?;
+        pages[index].fill(0);
+        Ok(())
+    }
+
+    fn num_pages(&self) -> u64 {
+        self.pages.lock().unwrap().len() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::common::PAGE_SIZE;
+    use crate::storage::disk::disk_manager::DiskManager as _;
+    use super::MemoryDiskManager;
+
+    #[test]
+    pub fn test_memory_disk_manager_read_write_roundtrip() {
+        let disk_manager = MemoryDiskManager::new();
+
+        assert_eq!(0, disk_manager.allocate_page().unwrap());
+        assert_eq!(1, disk_manager.allocate_page().unwrap());
+        disk_manager.write_page(1, &[3u8; PAGE_SIZE]).unwrap();
+
+        let mut buf = [0u8; PAGE_SIZE];
+        disk_manager.read_page(1, &mut buf).unwrap();
+        assert_eq!([3u8; PAGE_SIZE], buf);
+        disk_manager.read_page(0, &mut buf).unwrap();
+        assert_eq!([0u8; PAGE_SIZE], buf);
+        assert_eq!(2, disk_manager.num_pages());
+    }
+
+    #[test]
+    pub fn test_memory_disk_manager_rejects_unallocated_page() {
+        let disk_manager = MemoryDiskManager::new();
+
+        assert_eq!(
+            "Page 0 has not been allocated",
+            disk_manager.write_page(0, &[0u8; PAGE_SIZE]).unwrap_err().to_string()
+        );
+        assert_eq!(
+            "Page buffer must be 4096 bytes, got 1",
+            disk_manager.read_page(0, &mut [0u8; 1]).unwrap_err().to_string()
+        );
+    }
+}
@@ -0,0 +1,12 @@
+pub mod async_disk_manager;
+#[cfg(feature = "async-io")]
+pub mod async_file_disk_manager;
+pub mod disk_manager;
+pub mod disk_scheduler;
+pub mod faulty_disk_manager;
+pub mod file_disk_manager;
+pub mod memory_disk_manager;
+#[cfg(feature = "object-store")]
+pub mod object_store_disk_manager;
+pub mod page_codec;
+pub mod temp_disk_manager;
@@ -0,0 +1,322 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectStore, ObjectStoreExt, PutPayload};
+
+use crate::storage::common::{PageId, PAGE_SIZE};
+use crate::storage::encryption::KeyRing;
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::disk_manager::{check_page_buffer, DiskManager};
+use super::page_codec::{PageCodec, PageCompression};
+
+#[derive(Debug, Clone)]
+pub struct ObjectStoreDiskManagerOptions {
+    // Pages kept in the local cache file.
+    pub cache_pages: usize,
+    pub compression: PageCompression,
+    pub encryption: Option<Arc<KeyRing>>,
+}
+
+impl Default for ObjectStoreDiskManagerOptions {
+    fn default() -> Self {
+        ObjectStoreDiskManagerOptions { cache_pages: 1024, compression: PageCompression::None, encryption: None }
+    }
+}
+
+// Keeps every page as an object under a prefix of an object store, so the database is only
+// as big as the bucket, with a local file caching the pages read or written most recently.
+// Writes go through to the store before they return; the cache only saves the round trip on
+// reads. Pages are encoded as on a local disk, but the zeroed tail of a compressed page is
+// not uploaded. The cache is not trusted across restarts: opening starts it empty.
+//
+// Calls block on a runtime of their own, so they must not come from inside an async task;
+// the DiskScheduler's workers are plain threads.
+pub struct ObjectStoreDiskManager {
+    store: Arc<dyn ObjectStore>,
+    prefix: ObjectPath,
+    codec: PageCodec,
+    runtime: tokio::runtime::Runtime,
+    num_pages: Mutex<u64>,
+    cache: Mutex<PageCache>,
+}
+
+// Encoded page images in slots of the cache file, evicted oldest first.
+struct PageCache {
+    file: File,
+    capacity: usize,
+    slots: HashMap<PageId, u64>,
+    order: VecDeque<PageId>,
+    free_slots: Vec<u64>,
+    // Bumped by every write, so a read that fetched a page before the write finished does
+    // not cache what it fetched over what was written.
+    generation: u64,
+}
+
+impl PageCache {
+    fn read(&mut self, page_id: PageId, raw: &mut [u8]) -> CrabDbResult<bool> {
+        let Some(&slot) = self.slots.get(&page_id) else {
+            return Ok(false);
+        };
+        self.file.seek(SeekFrom::Start(slot * PAGE_SIZE as u64))
+            .and_then(|_| self.file.read_exact(raw))
+            .map_err(|e| CrabDBError::io(format!("Failed to read page {page_id} from the cache"), e))?;
+        Ok(true)
+    }
+
+    fn insert(&mut self, page_id: PageId, raw: &[u8]) -> CrabDbResult<()> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+        let slot = match self.slots.get(&page_id) {
+            Some(&slot) => slot,
+            None => {
+                if self.slots.len() == self.capacity {
+                    let evicted = self.order.pop_front().expect("a full cache has pages");
+                    let slot = self.slots.remove(&evicted).unwrap();
+                    self.free_slots.push(slot);
+                }
+                let slot = self.free_slots.pop().unwrap_or(self.slots.len() as u64);
+                self.slots.insert(page_id, slot);
+                self.order.push_back(page_id);
+                slot
+            },
+        };
+        let written = self.file.seek(SeekFrom::Start(slot * PAGE_SIZE as u64))
+            .and_then(|_| self.file.write_all(raw));
+        if let Err(e) = written {
+            self.remove(page_id);
+            return Err(CrabDBError::io(format!("Failed to write page {page_id} to the cache"), e));
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, page_id: PageId) {
+        if let Some(slot) = self.slots.remove(&page_id) {
+            self.order.retain(|cached| *cached != page_id);
+            self.free_slots.push(slot);
+        }
+    }
+}
+
+fn store_error(context: String, e: object_store::Error) -> CrabDBError {
+    CrabDBError::io(context, std::io::Error::other(e))
+}
+
+impl ObjectStoreDiskManager {
+    pub fn new(store: Arc<dyn ObjectStore>, prefix: &str, cache_path: impl AsRef<Path>) -> CrabDbResult<Self> {
+        Self::with_options(store, prefix, cache_path, ObjectStoreDiskManagerOptions::default())
+    }
+
+    // Counts the pages already under `prefix`, so a database written by another machine
+    // opens with all of them.
+    pub fn with_options(
+        store: Arc<dyn ObjectStore>,
+        prefix: &str,
+        cache_path: impl AsRef<Path>,
+        options: ObjectStoreDiskManagerOptions,
+    ) -> CrabDbResult<Self> {
+        let cache_path = cache_path.as_ref();
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(cache_path)
+            .map_err(|e| CrabDBError::io(format!("Failed to open page cache {}", cache_path.display()), e))?;
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()
+            .map_err(|e| CrabDBError::io("Failed to start the object store runtime".to_string(), e))?;
+        let prefix = ObjectPath::from(prefix);
+        let listed = runtime.block_on(store.list_with_delimiter(Some(&prefix)))
+            .map_err(|e| store_error(format!("Failed to list pages under {prefix}"), e))?;
+        let mut num_pages = 0;
+        for object in listed.objects {
+            let page_id = object.location.filename()
+                .and_then(|name| name.parse::<PageId>().ok())
+                .ok_or_else(|| CrabDBError::Corruption(format!("Object {} is not a page", object.location)))?;
+            num_pages = num_pages.max(page_id + 1);
+        }
+        Ok(ObjectStoreDiskManager {
+            store,
+            prefix,
+            codec: PageCodec::with_encryption(options.compression, options.encryption),
+            runtime,
+            num_pages: Mutex::new(num_pages),
+            cache: Mutex::new(PageCache {
+                file,
+                capacity: options.cache_pages,
+                slots: HashMap::new(),
+                order: VecDeque::new(),
+                free_slots: Vec::new(),
+                generation: 0,
+            }),
+        })
+    }
+
+    // Page ids are zero-padded so that listing returns pages in order.
+    fn page_path(&self, page_id: PageId) -> ObjectPath {
+        self.prefix.clone().join(format!("{page_id:020}"))
+    }
+
+    fn check_page_id(&self, page_id: PageId) -> CrabDbResult<()> {
+        if page_id >= *self.num_pages.lock().unwrap() {
+            return Err(CrabDBError::NotFound(format!("Page {page_id} has not been allocated")));
+        }
+        Ok(())
+    }
+
+    fn put(&self, page_id: PageId, raw: &[u8]) -> CrabDbResult<()> {
+        let len = raw.iter().rposition(|b| *b != 0).map_or(0, |last| last + 1);
+        let payload = PutPayload::from(raw[..len].to_vec());
+        self.runtime.block_on(self.store.put(&self.page_path(page_id), payload))
+            .map_err(|e| store_error(format!("Failed to write page {page_id}"), e))?;
+        Ok(())
+    }
+
+    fn get(&self, page_id: PageId, raw: &mut [u8]) -> CrabDbResult<()> {
+        let bytes = self.runtime.block_on(async {
+            self.store.get(&self.page_path(page_id)).await?.bytes().await
+        }).map_err(|e| store_error(format!("Failed to read page {page_id}"), e))?;
+        if bytes.len() > PAGE_SIZE {
+            return Err(CrabDBError::Corruption(format!("Page {page_id} is {} bytes in the object store", bytes.len())));
+        }
+        raw[..bytes.len()].copy_from_slice(&bytes);
+        raw[bytes.len()..].fill(0);
+        Ok(())
+    }
+
+    pub fn cached_pages(&self) -> usize {
+        self.cache.lock().unwrap().slots.len()
+    }
+}
+
+impl DiskManager for ObjectStoreDiskManager {
+
+    fn read_page(&self, page_id: PageId, buf: &mut [u8]) -> CrabDbResult<()> {
+        check_page_buffer(buf.len())?;
+        self.check_page_id(page_id)?;
+        let mut raw = [0; PAGE_SIZE];
+        let generation = {
+            let mut cache = self.cache.lock().unwrap();
+            if cache.read(page_id, &mut raw)? {
+                drop(cache);
+                return self.codec.decode(page_id, &raw, buf);
+            }
+            cache.generation
+        };
+        self.get(page_id, &mut raw)?;
+        self.codec.decode(page_id, &raw, buf)?;
+        let mut cache = self.cache.lock().unwrap();
+        if cache.generation == generation {
+            cache.insert(page_id, &raw)?;
+        }
+        Ok(())
+    }
+
+    fn write_page(&self, page_id: PageId, buf: &[u8]) -> CrabDbResult<()> {
+        check_page_buffer(buf.len())?;
+        self.check_page_id(page_id)?;
+        let mut raw = [0; PAGE_SIZE];
+        self.codec.encode(page_id, buf, &mut raw);
+        {
+            // whatever the cache holds may be about to go stale
+            let mut cache = self.cache.lock().unwrap();
+            cache.generation += 1;
+            cache.remove(page_id);
+        }
+        self.put(page_id, &raw)?;
+        let mut cache = self.cache.lock().unwrap();
+        cache.generation += 1;
+        cache.insert(page_id, &raw)
+    }
+
+    // Uploads an empty object, which reads back as a zeroed page, so the allocation survives
+    // a reopen even if the page is never written.
+    fn allocate_page(&self) -> CrabDbResult<PageId> {
+        let mut num_pages = self.num_pages.lock().unwrap();
+        let page_id = *num_pages;
+        self.put(page_id, &[])?;
+        *num_pages += 1;
+        Ok(page_id)
+    }
+
+    // The page's object stays in the store until its id is reused.
+    fn deallocate_page(&self, page_id: PageId) -> CrabDbResult<()> {
+        self.check_page_id(page_id)
+    }
+
+    fn num_pages(&self) -> u64 {
+        *self.num_pages.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use object_store::memory::InMemory;
+    use object_store::ObjectStore;
+    use tempfile::TempDir;
+
+    use crate::db::crab_db::CrabDb;
+    use crate::db::options::CrabDbOptions;
+    use crate::storage::common::{PAGE_HEADER_SIZE, PAGE_SIZE};
+    use crate::storage::disk::disk_manager::DiskManager as _;
+    use crate::storage::disk::page_codec::PageCompression;
+    use super::{ObjectStoreDiskManager, ObjectStoreDiskManagerOptions};
+
+    #[test]
+    pub fn test_object_store_disk_manager_reads_past_the_cache() {
+        let dir = TempDir::new().unwrap();
+        let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let options = ObjectStoreDiskManagerOptions { cache_pages: 2, compression: PageCompression::Lz4, ..Default::default() };
+        let disk_manager = ObjectStoreDiskManager::with_options(store.clone(), "crabs", dir.path().join("cache"), options).unwrap();
+
+        for page_id in 0..5u64 {
+            assert_eq!(page_id, disk_manager.allocate_page().unwrap());
+            let mut data = [0u8; PAGE_SIZE];
+            data[PAGE_HEADER_SIZE..].fill(page_id as u8 + 1);
+            disk_manager.write_page(page_id, &data).unwrap();
+        }
+        assert_eq!(2, disk_manager.cached_pages());
+        let mut buf = [0u8; PAGE_SIZE];
+        for page_id in (0..5u64).rev() {
+            disk_manager.read_page(page_id, &mut buf).unwrap();
+            assert!(buf[PAGE_HEADER_SIZE..].iter().all(|b| *b == page_id as u8 + 1));
+        }
+        assert_eq!("Page 5 has not been allocated", disk_manager.read_page(5, &mut buf).unwrap_err().to_string());
+
+        // another machine with a cold cache sees the same pages
+        let reopened = ObjectStoreDiskManager::new(store, "crabs", dir.path().join("other-cache")).unwrap();
+        assert_eq!(5, reopened.num_pages());
+        assert_eq!(0, reopened.cached_pages());
+        reopened.read_page(3, &mut buf).unwrap();
+        assert!(buf[PAGE_HEADER_SIZE..].iter().all(|b| *b == 4));
+        assert_eq!(1, reopened.cached_pages());
+    }
+
+    #[test]
+    pub fn test_database_on_object_store_reopens() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("crabs.db");
+        let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let options = CrabDbOptions::default().pool_size(8);
+        let open = |cache: &str| {
+            let disk_manager = ObjectStoreDiskManager::new(store.clone(), "db", dir.path().join(cache)).unwrap();
+            CrabDb::open_with_disk_manager(&path, Arc::new(disk_manager), options.clone()).unwrap()
+        };
+        {
+            let db = open("cache");
+            db.execute("CREATE TABLE crabs (id BIGINT NOT NULL, name VARCHAR)").unwrap();
+            for id in 0..100 {
+                db.execute(&format!("INSERT INTO crabs VALUES ({id}, 'crab {id} {}')", "x".repeat(100))).unwrap();
+            }
+        }
+
+        let db = open("cache");
+        let rows = db.query("SELECT id FROM crabs").unwrap().fetch_all().unwrap();
+        assert_eq!(100, rows.len());
+    }
+}
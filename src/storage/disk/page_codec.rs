@@ -0,0 +1,275 @@
+use std::sync::Arc;
+
+use crate::storage::common::{PageId, PAGE_DISK_HEADER_SIZE, PAGE_SIZE};
+use crate::storage::encryption::{KeyId, KeyRing, SealInfo, NONCE_SIZE, TAG_SIZE};
+use crate::types::{CrabDBError, CrabDbResult};
+
+// On-disk page header, filled in by `encode` and consumed by `decode`:
+//   0..4    CRC32C of bytes 4..PAGE_SIZE
+//   4       compression algorithm the payload was written with
+//   5       flags
+//   6..8    compressed payload length
+//   8..12   id of the key the payload is encrypted with
+//   12..24  AES-GCM nonce
+//   24..40  AES-GCM tag
+const CHECKSUM_SIZE: usize = 4;
+const ALGORITHM_OFFSET: usize = 4;
+const FLAGS_OFFSET: usize = 5;
+const LENGTH_OFFSET: usize = 6;
+const KEY_ID_OFFSET: usize = 8;
+const NONCE_OFFSET: usize = 12;
+const TAG_OFFSET: usize = NONCE_OFFSET + NONCE_SIZE;
+const PAYLOAD_SIZE: usize = PAGE_SIZE - PAGE_DISK_HEADER_SIZE;
+const _: () = assert!(TAG_OFFSET + TAG_SIZE == PAGE_DISK_HEADER_SIZE);
+
+const FLAG_ENCRYPTED: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PageCompression {
+    #[default]
+    None,
+    Lz4,
+    // zstd compression level
+    Zstd(i32),
+}
+
+impl PageCompression {
+    fn tag(&self) -> u8 {
+        match self {
+            PageCompression::None => 0,
+            PageCompression::Lz4 => 1,
+            PageCompression::Zstd(_) => 2,
+        }
+    }
+}
+
+// Turns a buffer pool page into its on-disk image and back: compress, then encrypt, then
+// checksum the result. The algorithm and key id are recorded per page, so a database can
+// change its compression setting or rotate keys and still read older pages; a page that does
+// not compress into the payload area is simply stored as is. Pages still occupy a
+// full slot in the file; the zeroed tail of a compressed page is what a sparse file or a
+// larger page size can reclaim.
+#[derive(Debug, Clone, Default)]
+pub struct PageCodec {
+    compression: PageCompression,
+    encryption: Option<Arc<KeyRing>>,
+}
+
+impl PageCodec {
+    pub fn new(compression: PageCompression) -> Self {
+        PageCodec { compression, encryption: None }
+    }
+
+    pub fn with_encryption(compression: PageCompression, encryption: Option<Arc<KeyRing>>) -> Self {
+        PageCodec { compression, encryption }
+    }
+
+    pub fn compression(&self) -> PageCompression {
+        self.compression
+    }
+
+    // The page id and the header fields that describe the payload are authenticated along
+    // with it, so a page copied to another slot or relabelled fails to decrypt.
+    fn associated_data(page_id: PageId, raw: &[u8]) -> [u8; 16] {
+        let mut associated_data = [0u8; 16];
+        associated_data[..8].copy_from_slice(&page_id.to_le_bytes());
+        associated_data[8..].copy_from_slice(&raw[ALGORITHM_OFFSET..NONCE_OFFSET]);
+        associated_data
+    }
+
+    pub(crate) fn encode(&self, page_id: PageId, page: &[u8], out: &mut [u8]) {
+        out.fill(0);
+        let payload = &page[PAGE_DISK_HEADER_SIZE..];
+        let compressed_len = match self.compression {
+            PageCompression::None => None,
+            PageCompression::Lz4 => lz4_flex::block::compress_into(payload, &mut out[PAGE_DISK_HEADER_SIZE..]).ok(),
+            PageCompression::Zstd(level) => zstd_compress(payload, &mut out[PAGE_DISK_HEADER_SIZE..], level),
+        };
+        match compressed_len {
+            Some(len) => {
+                out[ALGORITHM_OFFSET] = self.compression.tag();
+                out[LENGTH_OFFSET..KEY_ID_OFFSET].copy_from_slice(&(len as u16).to_le_bytes());
+            },
+            None => out[PAGE_DISK_HEADER_SIZE..].copy_from_slice(payload),
+        }
+        if let Some(key_ring) = &self.encryption {
+            out[FLAGS_OFFSET] |= FLAG_ENCRYPTED;
+            out[KEY_ID_OFFSET..NONCE_OFFSET].copy_from_slice(&key_ring.active_key_id().to_le_bytes());
+            let associated_data = Self::associated_data(page_id, out);
+            let seal = key_ring.seal_in_place(&associated_data, &mut out[PAGE_DISK_HEADER_SIZE..]);
+            out[NONCE_OFFSET..TAG_OFFSET].copy_from_slice(&seal.nonce);
+            out[TAG_OFFSET..PAGE_DISK_HEADER_SIZE].copy_from_slice(&seal.tag);
+        }
+        let checksum = crc32c::crc32c(&out[CHECKSUM_SIZE..]);
+        out[..CHECKSUM_SIZE].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    // The header bytes of `out` are zeroed; they carry nothing for the layers above.
+    pub(crate) fn decode(&self, page_id: PageId, raw: &[u8], out: &mut [u8]) -> CrabDbResult<()> {
+        Self::verify_checksum(page_id, raw)?;
+        out[..PAGE_DISK_HEADER_SIZE].fill(0);
+
+        let mut decrypted;
+        let mut payload = &raw[PAGE_DISK_HEADER_SIZE..];
+        if raw[FLAGS_OFFSET] & FLAG_ENCRYPTED != 0 {
+            let key_ring = self.encryption.as_ref().ok_or_else(|| {
+                CrabDBError::InvalidInput(format!("Page {page_id} is encrypted but no encryption key is configured"))
+            })?;
+            let seal = SealInfo {
+                key_id: KeyId::from_le_bytes(raw[KEY_ID_OFFSET..NONCE_OFFSET].try_into().unwrap()),
+                nonce: raw[NONCE_OFFSET..TAG_OFFSET].try_into().unwrap(),
+                tag: raw[TAG_OFFSET..PAGE_DISK_HEADER_SIZE].try_into().unwrap(),
+            };
+            decrypted = [0u8; PAYLOAD_SIZE];
+            decrypted.copy_from_slice(payload);
+            key_ring.open_in_place(&seal, &Self::associated_data(page_id, raw), &mut decrypted)
+                .map_err(|e| match e {
+                    // sealed with a key the key ring was not given
+                    CrabDBError::NotFound(_) => CrabDBError::InvalidInput(format!("Failed to decrypt page {page_id}: {e}")),
+                    _ => CrabDBError::Corruption(format!("Failed to decrypt page {page_id}: {e}")),
+                })?;
+            payload = &decrypted;
+        }
+
+        let len = u16::from_le_bytes([raw[LENGTH_OFFSET], raw[LENGTH_OFFSET + 1]]) as usize;
+        let decompressed = match raw[ALGORITHM_OFFSET] {
+            0 => {
+                out[PAGE_DISK_HEADER_SIZE..].copy_from_slice(payload);
+                return Ok(());
+            },
+            1 => lz4_flex::block::decompress_into(&payload[..len], &mut out[PAGE_DISK_HEADER_SIZE..])
+                .map_err(|e| e.to_string()),
+            2 => zstd_decompress(&payload[..len], &mut out[PAGE_DISK_HEADER_SIZE..]),
+            tag => Err(format!("unknown compression algorithm {tag}")),
+        };
+        match decompressed {
+            Ok(PAYLOAD_SIZE) => Ok(()),
+            Ok(size) => Err(CrabDBError::Corruption(format!("Page {page_id} decompressed to {size} bytes, expected {PAYLOAD_SIZE}"))),
+            Err(e) => Err(CrabDBError::Corruption(format!("Failed to decompress page {page_id}: {e}"))),
+        }
+    }
+
+    // A page that was allocated but never written is all zeroes and carries no checksum yet.
+    fn verify_checksum(page_id: PageId, raw: &[u8]) -> CrabDbResult<()> {
+        let stored = u32::from_le_bytes(raw[..CHECKSUM_SIZE].try_into().unwrap());
+        let computed = crc32c::crc32c(&raw[CHECKSUM_SIZE..]);
+        if stored != computed && !(stored == 0 && raw.iter().all(|b| *b == 0)) {
+            return Err(CrabDBError::Corruption(format!(
+                "Checksum mismatch on page {page_id}: stored {stored:#010x}, computed {computed:#010x}"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn zstd_compress(payload: &[u8], out: &mut [u8], level: i32) -> Option<usize> {
+    zstd::bulk::compress_to_buffer(payload, out, level).ok()
+}
+
+// wasm builds leave out zstd, so pages are written uncompressed there instead.
+#[cfg(target_family = "wasm")]
+fn zstd_compress(_payload: &[u8], _out: &mut [u8], _level: i32) -> Option<usize> {
+    None
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn zstd_decompress(payload: &[u8], out: &mut [u8]) -> Result<usize, String> {
+    zstd::bulk::decompress_to_buffer(payload, out).map_err(|e| e.to_string())
+}
+
+#[cfg(target_family = "wasm")]
+fn zstd_decompress(_payload: &[u8], _out: &mut [u8]) -> Result<usize, String> {
+    Err("zstd is not available in wasm builds".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::storage::common::{PAGE_HEADER_SIZE, PAGE_SIZE};
+    use crate::storage::encryption::KeyRing;
+    use crate::types::CrabDBError;
+    use super::{PageCodec, PageCompression};
+
+    fn sample_page() -> [u8; PAGE_SIZE] {
+        let mut page = [0u8; PAGE_SIZE];
+        for (i, b) in page[PAGE_HEADER_SIZE..].iter_mut().enumerate() {
+            *b = (i / 64) as u8;
+        }
+        page
+    }
+
+    #[test]
+    pub fn test_page_codec_roundtrip_every_algorithm() {
+        let page = sample_page();
+        for compression in [PageCompression::None, PageCompression::Lz4, PageCompression::Zstd(3)] {
+            let codec = PageCodec::new(compression);
+            let mut raw = [0u8; PAGE_SIZE];
+            codec.encode(0, &page, &mut raw);
+            if compression != PageCompression::None {
+                assert!(u16::from_le_bytes([raw[6], raw[7]]) < 1024, "{compression:?} should shrink the page");
+            }
+            // decoding does not depend on the reader's own setting
+            let mut out = [1u8; PAGE_SIZE];
+            PageCodec::default().decode(0, &raw, &mut out).unwrap();
+            assert_eq!(page, out);
+        }
+    }
+
+    #[test]
+    pub fn test_page_codec_stores_incompressible_page_raw() {
+        let mut page = [0u8; PAGE_SIZE];
+        let mut x: u32 = 12345;
+        for b in page[PAGE_HEADER_SIZE..].iter_mut() {
+            x = x.wrapping_mul(1103515245).wrapping_add(12345);
+            *b = (x >> 16) as u8;
+        }
+        let mut raw = [0u8; PAGE_SIZE];
+        PageCodec::new(PageCompression::Lz4).encode(0, &page, &mut raw);
+        assert_eq!(0, raw[4]);
+        let mut out = [0u8; PAGE_SIZE];
+        PageCodec::default().decode(0, &raw, &mut out).unwrap();
+        assert_eq!(page, out);
+    }
+
+    #[test]
+    pub fn test_page_codec_rejects_corrupt_page() {
+        let mut raw = [0u8; PAGE_SIZE];
+        PageCodec::new(PageCompression::Zstd(1)).encode(9, &sample_page(), &mut raw);
+        raw[PAGE_HEADER_SIZE + 3] ^= 0xff;
+        let mut out = [0u8; PAGE_SIZE];
+        let err = PageCodec::default().decode(9, &raw, &mut out).unwrap_err();
+        assert!(matches!(err, CrabDBError::Corruption(_)));
+        assert!(err.to_string().starts_with("Checksum mismatch on page 9"));
+    }
+
+    #[test]
+    pub fn test_page_codec_encryption_and_key_rotation() {
+        let page = sample_page();
+        let mut key_ring = KeyRing::new(1, [1; 32]);
+        let old_codec = PageCodec::with_encryption(PageCompression::Lz4, Some(Arc::new(key_ring.clone())));
+        let mut old_raw = [0u8; PAGE_SIZE];
+        old_codec.encode(3, &page, &mut old_raw);
+        assert!(!old_raw.windows(64).any(|w| w.iter().all(|b| *b == 1)), "payload must not be readable");
+
+        key_ring.add_key(2, [2; 32]).unwrap();
+        key_ring.set_active_key(2).unwrap();
+        let codec = PageCodec::with_encryption(PageCompression::None, Some(Arc::new(key_ring)));
+        let mut raw = [0u8; PAGE_SIZE];
+        codec.encode(3, &page, &mut raw);
+
+        let mut out = [0u8; PAGE_SIZE];
+        codec.decode(3, &old_raw, &mut out).unwrap();
+        assert_eq!(page, out);
+        codec.decode(3, &raw, &mut out).unwrap();
+        assert_eq!(page, out);
+
+        // a page moved to another slot no longer authenticates
+        assert!(matches!(codec.decode(4, &raw, &mut out).unwrap_err(), CrabDBError::Corruption(_)));
+        assert_eq!(
+            "Page 3 is encrypted but no encryption key is configured",
+            PageCodec::default().decode(3, &raw, &mut out).unwrap_err().to_string()
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use std::sync::{Arc, Mutex};
+
+use crate::storage::common::PageId;
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::disk_manager::DiskManager;
+
+// Temporary pages are numbered from here up, so none shares an id with a page of the
+// database: rids, undo versions and locks are keyed by page id whichever pool a page is in.
+pub const TEMP_PAGE_ID_BASE: PageId = 1 << 62;
+
+// Pages of temporary tables, kept apart from the database's in storage of their own: a file
+// that is emptied whenever the database opens, or memory. Nothing logs them and nothing
+// reopens them, so a crash leaves nothing in the database to clean up. `inner` holds the
+// pages at their id less TEMP_PAGE_ID_BASE, and deallocated ones are handed out again.
+pub struct TempDiskManager {
+    inner: Arc<dyn DiskManager>,
+    free_pages: Mutex<Vec<PageId>>,
+}
+
+impl TempDiskManager {
+    pub fn new(inner: Arc<dyn DiskManager>) -> Self {
+        TempDiskManager { inner, free_pages: Mutex::new(Vec::new()) }
+    }
+
+    fn inner_page_id(page_id: PageId) -> CrabDbResult<PageId> {
+        page_id.checked_sub(TEMP_PAGE_ID_BASE)
+            .ok_or_else(|| CrabDBError::InvalidInput(format!("Page {page_id} is not a temporary page")))
+    }
+}
+
+impl DiskManager for TempDiskManager {
+    fn read_page(&self, page_id: PageId, buf: &mut [u8]) -> CrabDbResult<()> {
+        self.inner.read_page(Self::inner_page_id(page_id)?, buf)
+    }
+
+    fn write_page(&self, page_id: PageId, buf: &[u8]) -> CrabDbResult<()> {
+        self.inner.write_page(Self::inner_page_id(page_id)?, buf)
+    }
+
+    fn write_pages(&self, first_page_id: PageId, bufs: &[&[u8]]) -> CrabDbResult<()> {
+        self.inner.write_pages(Self::inner_page_id(first_page_id)?, bufs)
+    }
+
+    fn allocate_page(&self) -> CrabDbResult<PageId> {
+        if let Some(page_id) = self.free_pages.lock().unwrap().pop() {
+            return Ok(page_id);
+        }
+        Ok(TEMP_PAGE_ID_BASE + self.inner.allocate_page()?)
+    }
+
+    fn deallocate_page(&self, page_id: PageId) -> CrabDbResult<()> {
+        self.inner.deallocate_page(Self::inner_page_id(page_id)?)?;
+        self.free_pages.lock().unwrap().push(page_id);
+        Ok(())
+    }
+
+    fn num_pages(&self) -> u64 {
+        self.inner.num_pages()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::storage::common::PAGE_SIZE;
+    use crate::storage::disk::disk_manager::DiskManager as _;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use super::{TempDiskManager, TEMP_PAGE_ID_BASE};
+
+    #[test]
+    pub fn test_temp_disk_manager_numbers_pages_apart_and_reuses_them() {
+        let disk_manager = TempDiskManager::new(Arc::new(MemoryDiskManager::new()));
+        assert_eq!(TEMP_PAGE_ID_BASE, disk_manager.allocate_page().unwrap());
+        let page_id = disk_manager.allocate_page().unwrap();
+        assert_eq!(TEMP_PAGE_ID_BASE + 1, page_id);
+        disk_manager.write_page(page_id, &[7u8; PAGE_SIZE]).unwrap();
+        let mut buf = [0u8; PAGE_SIZE];
+        disk_manager.read_page(page_id, &mut buf).unwrap();
+        assert_eq!([7u8; PAGE_SIZE], buf);
+        assert!(disk_manager.read_page(1, &mut buf).is_err());
+
+        disk_manager.deallocate_page(page_id).unwrap();
+        assert_eq!(page_id, disk_manager.allocate_page().unwrap());
+        assert_eq!(2, disk_manager.num_pages());
+    }
+}
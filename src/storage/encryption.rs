@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use aes_gcm::aead::{AeadInOut, Generate, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce, Tag};
+
+use crate::types::{CrabDBError, CrabDbResult};
+
+pub type KeyId = u32;
+
+pub const NONCE_SIZE: usize = 12;
+pub const TAG_SIZE: usize = 16;
+// How much longer `KeyRing::seal` makes a buffer.
+pub const SEAL_OVERHEAD: usize = 4 + NONCE_SIZE + TAG_SIZE;
+
+// Everything that lets a sealed buffer be opened again, stored next to the ciphertext.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SealInfo {
+    pub key_id: KeyId,
+    pub nonce: [u8; NONCE_SIZE],
+    pub tag: [u8; TAG_SIZE],
+}
+
+// AES-256-GCM master keys by id. New data is always sealed with the active key; every key
+// stays available for opening, so rotating is: add the new key, make it active, and let
+// pages pick it up as they are rewritten.
+#[derive(Clone)]
+pub struct KeyRing {
+    active_key_id: KeyId,
+    ciphers: HashMap<KeyId, Aes256Gcm>,
+}
+
+impl Debug for KeyRing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut key_ids: Vec<_> = self.ciphers.keys().collect();
+        key_ids.sort();
+        f.debug_struct("KeyRing")
+            .field("active_key_id", &self.active_key_id)
+            .field("key_ids", &key_ids)
+            .finish()
+    }
+}
+
+impl KeyRing {
+    pub fn new(key_id: KeyId, master_key: [u8; 32]) -> Self {
+        let mut ciphers = HashMap::new();
+        ciphers.insert(key_id, Aes256Gcm::new(&Key::<Aes256Gcm>::from(master_key)));
+        KeyRing { active_key_id: key_id, ciphers }
+    }
+
+    pub fn add_key(&mut self, key_id: KeyId, master_key: [u8; 32]) -> CrabDbResult<()> {
+        if self.ciphers.contains_key(&key_id) {
+            return Err(CrabDBError::InvalidInput(format!("Encryption key {key_id} already exists")));
+        }
+        self.ciphers.insert(key_id, Aes256Gcm::new(&Key::<Aes256Gcm>::from(master_key)));
+        Ok(())
+    }
+
+    pub fn set_active_key(&mut self, key_id: KeyId) -> CrabDbResult<()> {
+        if !self.ciphers.contains_key(&key_id) {
+            return Err(CrabDBError::NotFound(format!("Unknown encryption key {key_id}")));
+        }
+        self.active_key_id = key_id;
+        Ok(())
+    }
+
+    pub fn active_key_id(&self) -> KeyId {
+        self.active_key_id
+    }
+
+    // Encrypts `buf` in place with a fresh random nonce. `associated_data` is authenticated
+    // but not encrypted; it binds the ciphertext to where it lives (e.g. its page id).
+    pub fn seal_in_place(&self, associated_data: &[u8], buf: &mut [u8]) -> SealInfo {
+        let nonce = Nonce::generate();
+        let tag = self.ciphers[&self.active_key_id]
+            .encrypt_inout_detached(&nonce, associated_data, buf.into())
+            .expect("buffer is within the AES-GCM size limit");
+        SealInfo { key_id: self.active_key_id, nonce: nonce.into(), tag: tag.into() }
+    }
+
+    pub fn open_in_place(&self, seal: &SealInfo, associated_data: &[u8], buf: &mut [u8]) -> CrabDbResult<()> {
+        let cipher = self.ciphers.get(&seal.key_id)
+            .ok_or_else(|| CrabDBError::NotFound(format!("Unknown encryption key {}", seal.key_id)))?;
+        cipher.decrypt_inout_detached(&Nonce::from(seal.nonce), associated_data, buf.into(), &Tag::from(seal.tag))
+            .map_err(|_| CrabDBError::Corruption("Decryption failed: wrong key or tampered data".into()))
+    }
+
+    // Self-contained form for variable-length records such as log entries:
+    // key id | nonce | tag | ciphertext.
+    pub fn seal(&self, associated_data: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let mut ciphertext = plaintext.to_vec();
+        let seal = self.seal_in_place(associated_data, &mut ciphertext);
+        let mut sealed = Vec::with_capacity(SEAL_OVERHEAD + ciphertext.len());
+        sealed.extend_from_slice(&seal.key_id.to_le_bytes());
+        sealed.extend_from_slice(&seal.nonce);
+        sealed.extend_from_slice(&seal.tag);
+        sealed.extend_from_slice(&ciphertext);
+        sealed
+    }
+
+    pub fn open(&self, associated_data: &[u8], sealed: &[u8]) -> CrabDbResult<Vec<u8>> {
+        if sealed.len() < SEAL_OVERHEAD {
+            return Err(CrabDBError::Corruption(format!("Sealed record is {} bytes, shorter than its header", sealed.len())));
+        }
+        let seal = SealInfo {
+            key_id: KeyId::from_le_bytes(sealed[..4].try_into().unwrap()),
+            nonce: sealed[4..4 + NONCE_SIZE].try_into().unwrap(),
+            tag: sealed[4 + NONCE_SIZE..SEAL_OVERHEAD].try_into().unwrap(),
+        };
+        let mut plaintext = sealed[SEAL_OVERHEAD..].to_vec();
+        self.open_in_place(&seal, associated_data, &mut plaintext)?;
+        Ok(plaintext)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::CrabDBError;
+    use super::KeyRing;
+
+    #[test]
+    pub fn test_key_ring_seal_open_and_rotate() {
+        let mut key_ring = KeyRing::new(1, [1; 32]);
+        let old = key_ring.seal(b"log", b"insert 42");
+        assert_eq!(b"insert 42".to_vec(), key_ring.open(b"log", &old).unwrap());
+
+        key_ring.add_key(2, [2; 32]).unwrap();
+        key_ring.set_active_key(2).unwrap();
+        let new = key_ring.seal(b"log", b"insert 43");
+        assert_eq!(2, u32::from_le_bytes(new[..4].try_into().unwrap()));
+        // records sealed under the retired key still open
+        assert_eq!(b"insert 42".to_vec(), key_ring.open(b"log", &old).unwrap());
+        assert_eq!(b"insert 43".to_vec(), key_ring.open(b"log", &new).unwrap());
+
+        assert_eq!("Encryption key 2 already exists", key_ring.add_key(2, [3; 32]).unwrap_err().to_string());
+        assert_eq!("Unknown encryption key 9", key_ring.set_active_key(9).unwrap_err().to_string());
+    }
+
+    #[test]
+    pub fn test_key_ring_rejects_tampering() {
+        let key_ring = KeyRing::new(1, [7; 32]);
+        let mut sealed = key_ring.seal(b"page 3", b"secret");
+        assert!(matches!(key_ring.open(b"page 4", &sealed).unwrap_err(), CrabDBError::Corruption(_)));
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert!(matches!(key_ring.open(b"page 3", &sealed).unwrap_err(), CrabDBError::Corruption(_)));
+        assert_eq!(
+            "Unknown encryption key 1",
+            KeyRing::new(5, [7; 32]).open(b"page 3", &key_ring.seal(b"page 3", b"x")).unwrap_err().to_string()
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use std::sync::Mutex;
+
+use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+use crate::storage::common::{PageId, INVALID_PAGE_ID, PAGE_HEADER_SIZE, PAGE_SIZE};
+use crate::types::{CrabDBError, CrabDbResult};
+use crate::wal::common::{INVALID_LSN, SYSTEM_TXN_ID};
+use crate::wal::log_record::LogRecordBody;
+
+const NEXT_PAGE_ID_OFFSET: usize = PAGE_HEADER_SIZE;
+const ENTRIES_OFFSET: usize = NEXT_PAGE_ID_OFFSET + 8;
+// Pages one map page keeps an entry for.
+pub const PAGES_PER_MAP_PAGE: usize = PAGE_SIZE - ENTRIES_OFFSET;
+
+// Entry of a deallocated page, ready to be handed out again.
+const FREE_PAGE: u8 = u8::MAX;
+// Other entries hold the free bytes of a page in units of CATEGORY_SIZE, rounded down. A zero
+// entry, which is what a page that was never recorded has, promises nothing.
+const CATEGORY_SIZE: usize = 32;
+
+// One byte per page of the database, kept in a chain of map pages: the first map page covers
+// pages 0..PAGES_PER_MAP_PAGE, the next one the pages after that, and so on. The buffer pool
+// takes deallocated pages from here before it grows the file, and table heaps look for pages
+// with room in it instead of walking their chain.
+//
+// Whether a page is free is logged as an update of its entry under SYSTEM_TXN_ID, committed
+// right away, so recovery redoes it. Free space entries are only hints that inserts check
+// against the page, so they are not logged.
+pub struct FreeSpaceMap {
+    state: Mutex<FreeSpaceMapState>,
+}
+
+struct FreeSpaceMapState {
+    // Map pages in chain order.
+    map_page_ids: Vec<PageId>,
+    num_free_pages: usize,
+}
+
+impl FreeSpaceMap {
+    // Starts an empty map, on a new page of `bpm`.
+    pub fn create(bpm: &BufferPoolManager) -> CrabDbResult<Self> {
+        let page = bpm.new_page()?;
+        let first_page_id = page.page_id();
+        bpm.unpin_page(first_page_id, false)?;
+        Self::write_logged(bpm, first_page_id, NEXT_PAGE_ID_OFFSET, &INVALID_PAGE_ID.to_le_bytes())?;
+        Ok(FreeSpaceMap { state: Mutex::new(FreeSpaceMapState { map_page_ids: vec![first_page_id], num_free_pages: 0 }) })
+    }
+
+    // Reopens a map created earlier, given its first page.
+    pub fn open(bpm: &BufferPoolManager, first_page_id: PageId) -> CrabDbResult<Self> {
+        let mut map_page_ids = Vec::new();
+        let mut num_free_pages = 0;
+        let mut page_id = first_page_id;
+        while page_id != INVALID_PAGE_ID {
+            let page = bpm.fetch_page_read(page_id)?;
+            num_free_pages += page[ENTRIES_OFFSET..].iter().filter(|&&entry| entry == FREE_PAGE).count();
+            map_page_ids.push(page_id);
+            page_id = u64::from_le_bytes(page[NEXT_PAGE_ID_OFFSET..ENTRIES_OFFSET].try_into().unwrap());
+        }
+        Ok(FreeSpaceMap { state: Mutex::new(FreeSpaceMapState { map_page_ids, num_free_pages }) })
+    }
+
+    pub fn first_page_id(&self) -> PageId {
+        self.state.lock().unwrap().map_page_ids[0]
+    }
+
+    pub fn num_free_pages(&self) -> usize {
+        self.state.lock().unwrap().num_free_pages
+    }
+
+    // Takes a deallocated page off the map, if there is one.
+    pub fn take_free_page(&self, bpm: &BufferPoolManager) -> CrabDbResult<Option<PageId>> {
+        let mut state = self.state.lock().unwrap();
+        if state.num_free_pages == 0 {
+            return Ok(None);
+        }
+        for (i, &map_page_id) in state.map_page_ids.iter().enumerate() {
+            let position = bpm.fetch_page_read(map_page_id)?[ENTRIES_OFFSET..]
+                .iter()
+                .position(|&entry| entry == FREE_PAGE);
+            if let Some(position) = position {
+                Self::write_logged(bpm, map_page_id, ENTRIES_OFFSET + position, &[0])?;
+                state.num_free_pages -= 1;
+                return Ok(Some((i * PAGES_PER_MAP_PAGE + position) as PageId));
+            }
+        }
+        Err(CrabDBError::Corruption("Free space map counts free pages it does not hold".into()))
+    }
+
+    // Puts a deallocated page on the map, to be handed out again.
+    pub fn mark_free(&self, bpm: &BufferPoolManager, page_id: PageId) -> CrabDbResult<()> {
+        let mut state = self.state.lock().unwrap();
+        let (map_page_id, offset) = Self::locate(bpm, &mut state, page_id)?;
+        if bpm.fetch_page_read(map_page_id)?[offset] == FREE_PAGE {
+            return Err(CrabDBError::InvalidInput(format!("Page {page_id} is already free")));
+        }
+        Self::write_logged(bpm, map_page_id, offset, &[FREE_PAGE])?;
+        state.num_free_pages += 1;
+        Ok(())
+    }
+
+    // Records that `page_id` has `free_space` bytes to spare. Ignored for free pages.
+    pub fn record_free_space(&self, bpm: &BufferPoolManager, page_id: PageId, free_space: usize) -> CrabDbResult<()> {
+        let mut state = self.state.lock().unwrap();
+        let (map_page_id, offset) = Self::locate(bpm, &mut state, page_id)?;
+        let category = (free_space / CATEGORY_SIZE).min(FREE_PAGE as usize - 1) as u8;
+        let mut page = bpm.fetch_page_write(map_page_id)?;
+        if page[offset] != FREE_PAGE && page[offset] != category {
+            page[offset] = category;
+        }
+        Ok(())
+    }
+
+    // Finds a page with at least `needed` bytes to spare among those `accept` takes, lowest
+    // page id first.
+    pub fn find_page(&self, bpm: &BufferPoolManager, needed: usize, accept: impl Fn(PageId) -> bool) -> CrabDbResult<Option<PageId>> {
+        let state = self.state.lock().unwrap();
+        let category = needed.div_ceil(CATEGORY_SIZE);
+        for (i, &map_page_id) in state.map_page_ids.iter().enumerate() {
+            let page = bpm.fetch_page_read(map_page_id)?;
+            let found = page[ENTRIES_OFFSET..].iter()
+                .enumerate()
+                .filter(|&(_, &entry)| entry != FREE_PAGE && entry as usize >= category)
+                .map(|(position, _)| (i * PAGES_PER_MAP_PAGE + position) as PageId)
+                .find(|&page_id| accept(page_id));
+            if found.is_some() {
+                return Ok(found);
+            }
+        }
+        Ok(None)
+    }
+
+    // The map page and offset of `page_id`'s entry, chaining on map pages until one covers it.
+    fn locate(bpm: &BufferPoolManager, state: &mut FreeSpaceMapState, page_id: PageId) -> CrabDbResult<(PageId, usize)> {
+        let index = page_id as usize / PAGES_PER_MAP_PAGE;
+        while state.map_page_ids.len() <= index {
+            // Map pages come straight from the disk manager: going through `new_page` would
+            // come back here for a free page.
+            let new_page_id = bpm.disk_scheduler().disk_manager().allocate_page()?;
+            bpm.new_page_with_id(new_page_id)?;
+            bpm.unpin_page(new_page_id, false)?;
+            Self::write_logged(bpm, new_page_id, NEXT_PAGE_ID_OFFSET, &INVALID_PAGE_ID.to_le_bytes())?;
+            let last_page_id = *state.map_page_ids.last().unwrap();
+            Self::write_logged(bpm, last_page_id, NEXT_PAGE_ID_OFFSET, &new_page_id.to_le_bytes())?;
+            state.map_page_ids.push(new_page_id);
+        }
+        Ok((state.map_page_ids[index], ENTRIES_OFFSET + page_id as usize % PAGES_PER_MAP_PAGE))
+    }
+
+    // Writes `bytes` at `offset`, logging the change first when the buffer pool has a log.
+    fn write_logged(bpm: &BufferPoolManager, page_id: PageId, offset: usize, bytes: &[u8]) -> CrabDbResult<()> {
+        let mut page = bpm.fetch_page_write(page_id)?;
+        if let Some(log_manager) = bpm.log_manager() {
+            let lsn = log_manager.append(SYSTEM_TXN_ID, INVALID_LSN, LogRecordBody::Update {
+                page_id,
+                offset: offset as u16,
+                before: page[offset..offset + bytes.len()].to_vec(),
+                after: bytes.to_vec(),
+            })?;
+            log_manager.append(SYSTEM_TXN_ID, lsn, LogRecordBody::Commit { commit_ts: 0 })?;
+            page.page().set_page_lsn(lsn);
+        }
+        page[offset..offset + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tempfile::TempDir;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::storage::disk::disk_scheduler::DiskScheduler;
+    use crate::storage::disk::file_disk_manager::FileDiskManager;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use crate::wal::log_manager::LogManager;
+    use crate::wal::recovery_manager::RecoveryManager;
+    use super::{FreeSpaceMap, PAGES_PER_MAP_PAGE};
+
+    #[test]
+    pub fn test_free_space_map_reuses_pages_and_finds_room() {
+        let bpm = BufferPoolManager::new(4, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(4, 2)));
+        bpm.set_free_space_map(FreeSpaceMap::create(&bpm).unwrap()).unwrap();
+        assert!(bpm.set_free_space_map(FreeSpaceMap::open(&bpm, 0).unwrap()).is_err());
+        for page_id in 1..4 {
+            assert_eq!(page_id, bpm.new_page().unwrap().page_id());
+            assert!(bpm.unpin_page(page_id, false).is_ok());
+        }
+
+        // deleted pages are handed out again, lowest first, before the file grows
+        let free_space_map = bpm.free_space_map().unwrap();
+        assert!(bpm.delete_page(3).is_ok());
+        assert!(bpm.delete_page(2).is_ok());
+        assert!(free_space_map.mark_free(&bpm, 2).is_err());
+        assert_eq!(2, free_space_map.num_free_pages());
+        assert_eq!(2, bpm.new_page().unwrap().page_id());
+        assert_eq!(3, bpm.new_page_write().unwrap().page_id());
+        assert_eq!(4, bpm.new_page().unwrap().page_id());
+
+        // free space is kept in 32 byte steps; free pages never match
+        free_space_map.record_free_space(&bpm, 1, 100).unwrap();
+        free_space_map.record_free_space(&bpm, 2, 1000).unwrap();
+        assert_eq!(Some(1), free_space_map.find_page(&bpm, 96, |_| true).unwrap());
+        assert_eq!(Some(2), free_space_map.find_page(&bpm, 97, |_| true).unwrap());
+        assert_eq!(None, free_space_map.find_page(&bpm, 96, |page_id| page_id > 2).unwrap());
+        assert!(bpm.unpin_page(2, false).is_ok());
+        assert!(bpm.delete_page(2).is_ok());
+        assert_eq!(None, free_space_map.find_page(&bpm, 97, |_| true).unwrap());
+
+        // a page past the first map page chains on a second one, which reopening finds
+        let far_page_id = (PAGES_PER_MAP_PAGE + 3) as u64;
+        free_space_map.record_free_space(&bpm, far_page_id, 4000).unwrap();
+        let reopened = FreeSpaceMap::open(&bpm, free_space_map.first_page_id()).unwrap();
+        assert_eq!(1, reopened.num_free_pages());
+        assert_eq!(Some(far_page_id), reopened.find_page(&bpm, 2000, |_| true).unwrap());
+    }
+
+    #[test]
+    pub fn test_free_space_map_is_recovered_from_the_log() {
+        let dir = TempDir::new().unwrap();
+        let open = || {
+            let disk_manager = Arc::new(FileDiskManager::new(dir.path().join("test.db")).unwrap());
+            let log_manager = Arc::new(LogManager::new(dir.path().join("test.log")).unwrap());
+            let bpm = Arc::new(BufferPoolManager::with_log_manager(
+                4,
+                Arc::new(DiskScheduler::new(disk_manager, 1)),
+                Arc::new(LRUKReplacer::new(4, 2)),
+                Some(log_manager.clone()),
+            ));
+            (bpm, log_manager)
+        };
+        {
+            let (bpm, _) = open();
+            bpm.set_free_space_map(FreeSpaceMap::create(&bpm).unwrap()).unwrap();
+            for page_id in 1..4 {
+                bpm.new_page().unwrap();
+                assert!(bpm.unpin_page(page_id, false).is_ok());
+            }
+            assert!(bpm.delete_page(2).is_ok());
+            assert!(bpm.delete_page(3).is_ok());
+            assert_eq!(2, bpm.new_page().unwrap().page_id());
+            // crash: the map page never reaches disk
+        }
+
+        let (bpm, log_manager) = open();
+        RecoveryManager::new(bpm.clone(), log_manager).recover().unwrap();
+        let free_space_map = FreeSpaceMap::open(&bpm, 0).unwrap();
+        assert_eq!(1, free_space_map.num_free_pages());
+        assert_eq!(Some(3), free_space_map.take_free_page(&bpm).unwrap());
+        assert_eq!(None, free_space_map.take_free_page(&bpm).unwrap());
+    }
+}
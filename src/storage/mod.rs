@@ -0,0 +1,6 @@
+pub mod common;
+pub mod disk;
+pub mod encryption;
+pub mod free_space_map;
+pub mod page;
+pub mod table;
@@ -0,0 +1,380 @@
+use std::cmp::Reverse;
+use std::ops::{Deref, DerefMut};
+
+use crate::storage::common::{PageId, INVALID_PAGE_ID, PAGE_HEADER_SIZE, PAGE_SIZE};
+use crate::storage::table::tuple::{SlotId, Timestamp, TupleMeta};
+use crate::types::{CrabDBError, CrabDbResult};
+
+// Slotted page layout, after the common page header:
+//   next_page_id u64 | num_slots u16 | free_space_pointer u16 | slot directory ... free ... tuples
+// The slot directory grows forward, tuple bytes grow backwards from the end of the page.
+// A slot is (offset u16, size u16, ts u64); size 0 marks an empty slot that inserts can reuse
+// and the top bit of size marks a tuple as deleted but not yet reclaimed. The next bit marks
+// a tuple too big for the page, whose bytes are a stub pointing to its overflow pages (see
+// storage::table::overflow). `ts` is the commit timestamp of the version in the slot, used
+// for MVCC visibility.
+const NEXT_PAGE_ID_OFFSET: usize = PAGE_HEADER_SIZE;
+const NUM_SLOTS_OFFSET: usize = NEXT_PAGE_ID_OFFSET + 8;
+const FREE_SPACE_POINTER_OFFSET: usize = NUM_SLOTS_OFFSET + 2;
+const SLOTS_OFFSET: usize = FREE_SPACE_POINTER_OFFSET + 2;
+pub(crate) const SLOT_SIZE: usize = 12;
+const DELETED_FLAG: u16 = 1 << 15;
+const OVERFLOW_FLAG: u16 = 1 << 14;
+const SIZE_MASK: u16 = !(DELETED_FLAG | OVERFLOW_FLAG);
+
+pub const MAX_TUPLE_SIZE: usize = PAGE_SIZE - SLOTS_OFFSET - SLOT_SIZE;
+
+// A view over page bytes. Wrap a read guard for lookups or a write guard to modify.
+pub struct TablePage<T> {
+    data: T,
+}
+
+impl<T: Deref<Target = [u8; PAGE_SIZE]>> TablePage<T> {
+    pub fn new(data: T) -> Self {
+        TablePage { data }
+    }
+
+    fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_le_bytes([self.data[offset], self.data[offset + 1]])
+    }
+
+    pub fn next_page_id(&self) -> PageId {
+        PageId::from_le_bytes(self.data[NEXT_PAGE_ID_OFFSET..NUM_SLOTS_OFFSET].try_into().unwrap())
+    }
+
+    pub fn num_slots(&self) -> SlotId {
+        self.read_u16(NUM_SLOTS_OFFSET)
+    }
+
+    fn free_space_pointer(&self) -> usize {
+        self.read_u16(FREE_SPACE_POINTER_OFFSET) as usize
+    }
+
+    fn slot(&self, slot_id: SlotId) -> (usize, u16) {
+        let slot_offset = SLOTS_OFFSET + slot_id as usize * SLOT_SIZE;
+        (self.read_u16(slot_offset) as usize, self.read_u16(slot_offset + 2))
+    }
+
+    fn slot_ts(&self, slot_id: SlotId) -> Timestamp {
+        let offset = SLOTS_OFFSET + slot_id as usize * SLOT_SIZE + 4;
+        Timestamp::from_le_bytes(self.data[offset..offset + 8].try_into().unwrap())
+    }
+
+    fn slots_end(&self) -> usize {
+        SLOTS_OFFSET + self.num_slots() as usize * SLOT_SIZE
+    }
+
+    // Contiguous bytes between the slot directory and the tuple data.
+    pub fn free_space(&self) -> usize {
+        self.free_space_pointer() - self.slots_end()
+    }
+
+    // Free space once deleted tuples and holes left by shrinking updates are reclaimed.
+    pub fn reclaimable_space(&self) -> usize {
+        let live: usize = (0..self.num_slots())
+            .map(|slot_id| (self.slot(slot_id).1 & SIZE_MASK) as usize)
+            .sum();
+        PAGE_SIZE - self.slots_end() - live
+    }
+
+    fn check_slot(&self, slot_id: SlotId) -> CrabDbResult<(usize, u16)> {
+        if slot_id >= self.num_slots() {
+            return Err(CrabDBError::NotFound(format!("Slot {slot_id} is out of range")));
+        }
+        match self.slot(slot_id) {
+            (_, 0) => Err(CrabDBError::NotFound(format!("Slot {slot_id} is empty"))),
+            slot => Ok(slot),
+        }
+    }
+
+    pub fn is_deleted(&self, slot_id: SlotId) -> CrabDbResult<bool> {
+        Ok(self.check_slot(slot_id)?.1 & DELETED_FLAG != 0)
+    }
+
+    pub fn tuple_meta(&self, slot_id: SlotId) -> CrabDbResult<TupleMeta> {
+        let (_, size) = self.check_slot(slot_id)?;
+        Ok(TupleMeta { ts: self.slot_ts(slot_id), is_deleted: size & DELETED_FLAG != 0 })
+    }
+
+    // Like `get_tuple`, but also returns tuples that are marked deleted.
+    pub fn get_tuple_with_meta(&self, slot_id: SlotId) -> CrabDbResult<(TupleMeta, &[u8])> {
+        let (offset, size) = self.check_slot(slot_id)?;
+        let meta = TupleMeta { ts: self.slot_ts(slot_id), is_deleted: size & DELETED_FLAG != 0 };
+        Ok((meta, &self.data[offset..offset + (size & SIZE_MASK) as usize]))
+    }
+
+    pub fn get_tuple(&self, slot_id: SlotId) -> CrabDbResult<&[u8]> {
+        let (offset, size) = self.check_slot(slot_id)?;
+        if size & DELETED_FLAG != 0 {
+            return Err(CrabDBError::NotFound(format!("Slot {slot_id} has been deleted")));
+        }
+        Ok(&self.data[offset..offset + (size & SIZE_MASK) as usize])
+    }
+
+    // Whether the slot holds an overflow stub rather than the tuple itself.
+    pub fn is_overflow(&self, slot_id: SlotId) -> CrabDbResult<bool> {
+        Ok(self.check_slot(slot_id)?.1 & OVERFLOW_FLAG != 0)
+    }
+}
+
+impl<T: DerefMut<Target = [u8; PAGE_SIZE]>> TablePage<T> {
+    pub fn init(&mut self, next_page_id: PageId) {
+        self.set_next_page_id(next_page_id);
+        self.write_u16(NUM_SLOTS_OFFSET, 0);
+        self.write_u16(FREE_SPACE_POINTER_OFFSET, PAGE_SIZE as u16);
+    }
+
+    fn write_u16(&mut self, offset: usize, value: u16) {
+        self.data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn set_slot(&mut self, slot_id: SlotId, offset: usize, size: u16) {
+        let slot_offset = SLOTS_OFFSET + slot_id as usize * SLOT_SIZE;
+        self.write_u16(slot_offset, offset as u16);
+        self.write_u16(slot_offset + 2, size);
+    }
+
+    pub fn set_tuple_ts(&mut self, slot_id: SlotId, ts: Timestamp) -> CrabDbResult<()> {
+        self.check_slot(slot_id)?;
+        let offset = SLOTS_OFFSET + slot_id as usize * SLOT_SIZE + 4;
+        self.data[offset..offset + 8].copy_from_slice(&ts.to_le_bytes());
+        Ok(())
+    }
+
+    pub fn set_next_page_id(&mut self, next_page_id: PageId) {
+        self.data[NEXT_PAGE_ID_OFFSET..NUM_SLOTS_OFFSET].copy_from_slice(&next_page_id.to_le_bytes());
+    }
+
+    // Returns None when the tuple does not fit, even after compacting the page.
+    pub fn insert_tuple(&mut self, tuple: &[u8]) -> Option<SlotId> {
+        if tuple.is_empty() || tuple.len() > MAX_TUPLE_SIZE {
+            return None;
+        }
+        let reused_slot = (0..self.num_slots()).find(|&slot_id| self.slot(slot_id).1 == 0);
+        let needed = tuple.len() + if reused_slot.is_some() { 0 } else { SLOT_SIZE };
+        if !self.reserve(needed) {
+            return None;
+        }
+
+        let slot_id = match reused_slot {
+            Some(slot_id) => slot_id,
+            None => {
+                let slot_id = self.num_slots();
+                self.write_u16(NUM_SLOTS_OFFSET, slot_id + 1);
+                slot_id
+            },
+        };
+        let offset = self.free_space_pointer() - tuple.len();
+        self.data[offset..offset + tuple.len()].copy_from_slice(tuple);
+        self.write_u16(FREE_SPACE_POINTER_OFFSET, offset as u16);
+        self.set_slot(slot_id, offset, tuple.len() as u16);
+        self.set_tuple_ts(slot_id, 0).expect("slot was just filled");
+        Some(slot_id)
+    }
+
+    // Like `insert_tuple`, for the stub of a tuple stored in overflow pages.
+    pub fn insert_overflow_stub(&mut self, stub: &[u8]) -> Option<SlotId> {
+        let slot_id = self.insert_tuple(stub)?;
+        let (offset, size) = self.slot(slot_id);
+        self.set_slot(slot_id, offset, size | OVERFLOW_FLAG);
+        Some(slot_id)
+    }
+
+    // Rewrites the tuple in place when it shrinks, otherwise moves it to fresh space.
+    // Returns false, leaving the old tuple untouched, if the new version does not fit. An
+    // overflow stub is never updated in place, as its overflow pages may still be read.
+    pub fn update_tuple(&mut self, slot_id: SlotId, tuple: &[u8]) -> CrabDbResult<bool> {
+        let old = self.get_tuple(slot_id)?.len();
+        if tuple.is_empty() || tuple.len() > MAX_TUPLE_SIZE || self.is_overflow(slot_id)? {
+            return Ok(false);
+        }
+        if tuple.len() <= old {
+            let (offset, _) = self.slot(slot_id);
+            self.data[offset..offset + tuple.len()].copy_from_slice(tuple);
+            self.set_slot(slot_id, offset, tuple.len() as u16);
+            return Ok(true);
+        }
+        // The old copy counts as reclaimable while we make room for the new one.
+        let (old_offset, old_size) = self.slot(slot_id);
+        self.set_slot(slot_id, old_offset, 0);
+        if !self.reserve(tuple.len()) {
+            self.set_slot(slot_id, old_offset, old_size);
+            return Ok(false);
+        }
+        let offset = self.free_space_pointer() - tuple.len();
+        self.data[offset..offset + tuple.len()].copy_from_slice(tuple);
+        self.write_u16(FREE_SPACE_POINTER_OFFSET, offset as u16);
+        self.set_slot(slot_id, offset, tuple.len() as u16);
+        Ok(true)
+    }
+
+    // Hides the tuple but keeps its bytes so the delete can still be rolled back.
+    pub fn mark_delete(&mut self, slot_id: SlotId) -> CrabDbResult<()> {
+        let (offset, size) = self.check_slot(slot_id)?;
+        if size & DELETED_FLAG != 0 {
+            return Err(CrabDBError::NotFound(format!("Slot {slot_id} has been deleted")));
+        }
+        self.set_slot(slot_id, offset, size | DELETED_FLAG);
+        Ok(())
+    }
+
+    pub fn rollback_delete(&mut self, slot_id: SlotId) -> CrabDbResult<()> {
+        let (offset, size) = self.check_slot(slot_id)?;
+        self.set_slot(slot_id, offset, size & !DELETED_FLAG);
+        Ok(())
+    }
+
+    // Undoes a write to the tuple in the slot: puts back the version it replaced, bytes and
+    // timestamp, or for a tuple the write inserted, leaves a dead tuple nobody sees. Returns
+    // false, changing nothing, if the old version no longer fits.
+    pub fn restore_tuple(&mut self, slot_id: SlotId, prior: Option<(Timestamp, &[u8])>) -> CrabDbResult<bool> {
+        match prior {
+            Some((ts, tuple)) => {
+                let deleted = self.is_deleted(slot_id)?;
+                self.rollback_delete(slot_id)?;
+                // A delete leaves the old bytes in place, overflow stubs included, so there is
+                // nothing to write back then.
+                if self.get_tuple(slot_id)? != tuple && !self.update_tuple(slot_id, tuple)? {
+                    if deleted {
+                        self.mark_delete(slot_id)?;
+                    }
+                    return Ok(false);
+                }
+                self.set_tuple_ts(slot_id, ts)?;
+            },
+            None => {
+                if !self.is_deleted(slot_id)? {
+                    self.mark_delete(slot_id)?;
+                }
+                self.set_tuple_ts(slot_id, 0)?;
+            },
+        }
+        Ok(true)
+    }
+
+    // Frees the slot for good; its space is reclaimed by the next compaction.
+    pub fn apply_delete(&mut self, slot_id: SlotId) -> CrabDbResult<()> {
+        self.check_slot(slot_id)?;
+        self.set_slot(slot_id, 0, 0);
+        Ok(())
+    }
+
+    fn reserve(&mut self, needed: usize) -> bool {
+        if self.free_space() >= needed {
+            return true;
+        }
+        if self.reclaimable_space() < needed {
+            return false;
+        }
+        self.compact();
+        self.free_space() >= needed
+    }
+
+    // Packs every slotted tuple (deleted ones included) against the end of the page.
+    pub fn compact(&mut self) {
+        let mut tuples: Vec<(SlotId, usize, u16)> = (0..self.num_slots())
+            .map(|slot_id| (slot_id, self.slot(slot_id)))
+            .filter(|(_, (_, size))| *size != 0)
+            .map(|(slot_id, (offset, size))| (slot_id, offset, size))
+            .collect();
+        // Moving the highest tuples first never overwrites one that has not moved yet.
+        tuples.sort_by_key(|(_, offset, _)| Reverse(*offset));
+        let mut free_space_pointer = PAGE_SIZE;
+        for (slot_id, offset, size) in tuples {
+            let len = (size & SIZE_MASK) as usize;
+            free_space_pointer -= len;
+            self.data.copy_within(offset..offset + len, free_space_pointer);
+            self.set_slot(slot_id, free_space_pointer, size);
+        }
+        self.write_u16(FREE_SPACE_POINTER_OFFSET, free_space_pointer as u16);
+    }
+}
+
+impl Default for TablePage<Box<[u8; PAGE_SIZE]>> {
+    fn default() -> Self {
+        let mut page = TablePage::new(Box::new([0; PAGE_SIZE]));
+        page.init(INVALID_PAGE_ID);
+        page
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::common::{INVALID_PAGE_ID, PAGE_SIZE};
+    use crate::storage::table::tuple::TupleMeta;
+    use super::{TablePage, MAX_TUPLE_SIZE};
+
+    #[test]
+    pub fn test_table_page_insert_get_update() {
+        let mut page = TablePage::default();
+        assert_eq!(INVALID_PAGE_ID, page.next_page_id());
+        let free = page.free_space();
+
+        let a = page.insert_tuple(b"alpha").unwrap();
+        let b = page.insert_tuple(b"bravo!").unwrap();
+        assert_eq!((0, 1), (a, b));
+        assert_eq!(free - 5 - 6 - 24, page.free_space());
+        assert_eq!(b"alpha", page.get_tuple(a).unwrap());
+
+        assert!(page.update_tuple(a, b"al").unwrap());
+        assert_eq!(b"al", page.get_tuple(a).unwrap());
+        assert!(page.update_tuple(a, b"a much longer alpha").unwrap());
+        assert_eq!(b"a much longer alpha", page.get_tuple(a).unwrap());
+        assert_eq!(b"bravo!", page.get_tuple(b).unwrap());
+        assert_eq!("Slot 2 is out of range", page.get_tuple(2).unwrap_err().to_string());
+    }
+
+    #[test]
+    pub fn test_table_page_delete_lifecycle() {
+        let mut page = TablePage::default();
+        let slot_id = page.insert_tuple(b"tuple").unwrap();
+        page.set_tuple_ts(slot_id, 42).unwrap();
+        page.mark_delete(slot_id).unwrap();
+        assert!(page.is_deleted(slot_id).unwrap());
+        assert_eq!(TupleMeta { ts: 42, is_deleted: true }, page.tuple_meta(slot_id).unwrap());
+        assert_eq!(&b"tuple"[..], page.get_tuple_with_meta(slot_id).unwrap().1);
+        assert_eq!("Slot 0 has been deleted", page.get_tuple(slot_id).unwrap_err().to_string());
+
+        page.rollback_delete(slot_id).unwrap();
+        assert_eq!(b"tuple", page.get_tuple(slot_id).unwrap());
+
+        page.apply_delete(slot_id).unwrap();
+        assert_eq!("Slot 0 is empty", page.get_tuple(slot_id).unwrap_err().to_string());
+        // the empty slot is handed out again
+        assert_eq!(Some(0), page.insert_tuple(b"again"));
+    }
+
+    #[test]
+    pub fn test_table_page_fills_up_and_compacts() {
+        let mut page = TablePage::default();
+        let tuple = [7u8; 100];
+        let mut slots = Vec::new();
+        while let Some(slot_id) = page.insert_tuple(&tuple) {
+            slots.push(slot_id);
+        }
+        assert!(page.free_space() < tuple.len() + 12);
+        assert_eq!(None, page.insert_tuple(&[0; MAX_TUPLE_SIZE + 1]));
+
+        // deleting every other tuple leaves holes that only compaction can use
+        for slot_id in slots.iter().step_by(2) {
+            page.apply_delete(*slot_id).unwrap();
+        }
+        let big = [9u8; 300];
+        let big_slot = page.insert_tuple(&big).unwrap();
+        assert_eq!(&big[..], page.get_tuple(big_slot).unwrap());
+        for slot_id in slots.iter().skip(1).step_by(2) {
+            assert_eq!(&tuple[..], page.get_tuple(*slot_id).unwrap());
+        }
+        assert_eq!(page.free_space(), page.reclaimable_space());
+    }
+
+    #[test]
+    pub fn test_table_page_over_borrowed_bytes() {
+        let mut data = [0u8; PAGE_SIZE];
+        TablePage::new(&mut data).init(5);
+        TablePage::new(&mut data).insert_tuple(b"x").unwrap();
+        let page = TablePage::new(&data);
+        assert_eq!(5, page.next_page_id());
+        assert_eq!(b"x", page.get_tuple(0).unwrap());
+    }
+}
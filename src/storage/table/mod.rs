@@ -0,0 +1,4 @@
+pub mod overflow;
+pub mod table_heap;
+pub mod table_iterator;
+pub mod tuple;
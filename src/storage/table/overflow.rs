@@ -0,0 +1,196 @@
+use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+use crate::storage::common::{PageId, INVALID_PAGE_ID, PAGE_HEADER_SIZE, PAGE_SIZE};
+use crate::types::{CrabDBError, CrabDbResult};
+
+// Tuples too big for a slotted page are split over a chain of overflow pages, and the slot
+// keeps a stub instead: the tuple's length (u64) and the first page of the chain (u64).
+// Overflow page layout, after the common page header:
+//   next_page_id u64 | len u16 | bytes
+const NEXT_PAGE_ID_OFFSET: usize = PAGE_HEADER_SIZE;
+const LEN_OFFSET: usize = NEXT_PAGE_ID_OFFSET + 8;
+const DATA_OFFSET: usize = LEN_OFFSET + 2;
+pub const OVERFLOW_CHUNK_SIZE: usize = PAGE_SIZE - DATA_OFFSET;
+pub const STUB_SIZE: usize = 16;
+
+// Largest tuple a table heap stores.
+pub const MAX_OVERFLOW_TUPLE_SIZE: usize = 1 << 30;
+
+// Writes `data` to a new overflow chain and returns the stub that points to it. The chain is
+// written back to front, so every page can point to the one after it as soon as it is
+// written.
+pub(crate) fn write_chain(bpm: &BufferPoolManager, data: &[u8]) -> CrabDbResult<Vec<u8>> {
+    let mut next_page_id = INVALID_PAGE_ID;
+    for chunk in data.chunks(OVERFLOW_CHUNK_SIZE).rev() {
+        let mut page = match bpm.new_page_write() {
+            Ok(page) => page,
+            Err(e) => {
+                // Hand back what was written so far; the error is the one worth reporting.
+                let _ = free_chain(bpm, next_page_id);
+                return Err(e);
+            },
+        };
+        let page_id = page.page_id();
+        page[NEXT_PAGE_ID_OFFSET..LEN_OFFSET].copy_from_slice(&next_page_id.to_le_bytes());
+        page[LEN_OFFSET..DATA_OFFSET].copy_from_slice(&(chunk.len() as u16).to_le_bytes());
+        page[DATA_OFFSET..DATA_OFFSET + chunk.len()].copy_from_slice(chunk);
+        next_page_id = page_id;
+    }
+    let mut stub = Vec::with_capacity(STUB_SIZE);
+    stub.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    stub.extend_from_slice(&next_page_id.to_le_bytes());
+    Ok(stub)
+}
+
+// The tuple's length and the first page of its chain.
+pub(crate) fn parse_stub(stub: &[u8]) -> CrabDbResult<(usize, PageId)> {
+    if stub.len() != STUB_SIZE {
+        return Err(CrabDBError::Corruption(format!("Overflow stub of {} bytes, expected {STUB_SIZE}", stub.len())));
+    }
+    Ok((
+        u64::from_le_bytes(stub[..8].try_into().unwrap()) as usize,
+        PageId::from_le_bytes(stub[8..].try_into().unwrap()),
+    ))
+}
+
+pub(crate) fn read_chain(bpm: &BufferPoolManager, stub: &[u8]) -> CrabDbResult<Vec<u8>> {
+    let (len, first_page_id) = parse_stub(stub)?;
+    let mut data = Vec::with_capacity(len);
+    for chunk in TupleChunks::new(bpm, len, first_page_id) {
+        data.extend_from_slice(&chunk?);
+    }
+    Ok(data)
+}
+
+// Deletes every page of the chain starting at `page_id`.
+pub(crate) fn free_chain(bpm: &BufferPoolManager, mut page_id: PageId) -> CrabDbResult<()> {
+    while page_id != INVALID_PAGE_ID {
+        let next_page_id = {
+            let page = bpm.fetch_page_read(page_id)?;
+            PageId::from_le_bytes(page[NEXT_PAGE_ID_OFFSET..LEN_OFFSET].try_into().unwrap())
+        };
+        bpm.delete_page(page_id)?;
+        page_id = next_page_id;
+    }
+    Ok(())
+}
+
+// Streams a tuple back one page at a time, so a large value never has to be held in memory
+// whole. A tuple stored in its slot comes back as a single chunk.
+pub struct TupleChunks<'a> {
+    bpm: &'a BufferPoolManager,
+    inline: Option<Vec<u8>>,
+    page_id: PageId,
+    remaining: usize,
+}
+
+impl<'a> TupleChunks<'a> {
+    pub(crate) fn new(bpm: &'a BufferPoolManager, len: usize, first_page_id: PageId) -> Self {
+        TupleChunks { bpm, inline: None, page_id: first_page_id, remaining: len }
+    }
+
+    pub(crate) fn inline(bpm: &'a BufferPoolManager, data: Vec<u8>) -> Self {
+        TupleChunks { bpm, remaining: data.len(), inline: Some(data), page_id: INVALID_PAGE_ID }
+    }
+
+    // Bytes not returned yet.
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    fn advance(&mut self) -> CrabDbResult<Option<Vec<u8>>> {
+        if let Some(data) = self.inline.take() {
+            self.remaining = 0;
+            return Ok(Some(data));
+        }
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        if self.page_id == INVALID_PAGE_ID {
+            return Err(CrabDBError::Corruption(format!("Overflow chain ends {} bytes short", self.remaining)));
+        }
+        let page = self.bpm.fetch_page_read(self.page_id)?;
+        let len = u16::from_le_bytes(page[LEN_OFFSET..DATA_OFFSET].try_into().unwrap()) as usize;
+        if len > OVERFLOW_CHUNK_SIZE || len > self.remaining {
+            return Err(CrabDBError::Corruption(format!("Overflow page {} holds {len} bytes", self.page_id)));
+        }
+        self.page_id = PageId::from_le_bytes(page[NEXT_PAGE_ID_OFFSET..LEN_OFFSET].try_into().unwrap());
+        self.remaining -= len;
+        Ok(Some(page[DATA_OFFSET..DATA_OFFSET + len].to_vec()))
+    }
+}
+
+impl Iterator for TupleChunks<'_> {
+    type Item = CrabDbResult<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.advance() {
+            Ok(chunk) => chunk.map(Ok),
+            Err(e) => {
+                self.remaining = 0;
+                self.inline = None;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::concurrency::lock_manager::LockManager;
+    use crate::concurrency::transaction::IsolationLevel;
+    use crate::concurrency::transaction_manager::TransactionManager;
+    use crate::storage::disk::memory_disk_manager::MemoryDiskManager;
+    use crate::storage::free_space_map::FreeSpaceMap;
+    use crate::storage::table::table_heap::TableHeap;
+    use crate::storage::table::tuple::Tuple;
+    use super::OVERFLOW_CHUNK_SIZE;
+
+    fn large(len: usize) -> Tuple {
+        Tuple::new((0..len).map(|i| (i % 251) as u8).collect())
+    }
+
+    #[test]
+    pub fn test_overflow_pages_hold_tuples_bigger_than_a_page() {
+        let bpm = Arc::new(BufferPoolManager::new(4, Arc::new(MemoryDiskManager::new()), Arc::new(LRUKReplacer::new(4, 2))));
+        bpm.set_free_space_map(FreeSpaceMap::create(&bpm).unwrap()).unwrap();
+        let heap = TableHeap::new(bpm.clone()).unwrap();
+
+        let small = heap.insert_tuple(&Tuple::new(b"small".to_vec())).unwrap();
+        let rid = heap.insert_tuple(&large(5 * OVERFLOW_CHUNK_SIZE + 10)).unwrap();
+        // only the stub lives in the heap page
+        assert_eq!(small.page_id(), rid.page_id());
+        assert_eq!(large(5 * OVERFLOW_CHUNK_SIZE + 10), heap.get_tuple(rid).unwrap());
+        assert_eq!(vec![Tuple::new(b"small".to_vec()), large(5 * OVERFLOW_CHUNK_SIZE + 10)],
+            heap.iter().map(|entry| entry.unwrap().1).collect::<Vec<_>>());
+
+        // streamed back a page at a time
+        let chunks = heap.tuple_chunks(rid).unwrap();
+        assert_eq!(5 * OVERFLOW_CHUNK_SIZE + 10, chunks.remaining());
+        let lens: Vec<_> = chunks.map(|chunk| chunk.unwrap().len()).collect();
+        assert_eq!(vec![OVERFLOW_CHUNK_SIZE, OVERFLOW_CHUNK_SIZE, OVERFLOW_CHUNK_SIZE, OVERFLOW_CHUNK_SIZE, OVERFLOW_CHUNK_SIZE, 10], lens);
+        assert_eq!(vec![5], heap.tuple_chunks(small).unwrap().map(|chunk| chunk.unwrap().len()).collect::<Vec<_>>());
+
+        // a stub is never updated in place, and reclaiming it frees its pages
+        assert!(!heap.update_tuple(rid, &Tuple::new(b"shorter".to_vec())).unwrap());
+        heap.mark_delete(rid).unwrap();
+        heap.apply_delete(rid).unwrap();
+        assert_eq!(6, bpm.free_space_map().unwrap().num_free_pages());
+
+        // snapshots read through the stub, and an aborted delete keeps the pages in place
+        let txn_manager = TransactionManager::new(Arc::new(LockManager::new()));
+        let writer = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        let rid = txn_manager.insert(&writer, &heap, &large(2 * OVERFLOW_CHUNK_SIZE)).unwrap();
+        txn_manager.commit(&writer).unwrap();
+        assert_eq!(4, bpm.free_space_map().unwrap().num_free_pages());
+        let deleter = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        txn_manager.delete(&deleter, &heap, rid).unwrap();
+        txn_manager.abort(&deleter).unwrap();
+        let reader = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        assert_eq!(Some(large(2 * OVERFLOW_CHUNK_SIZE)), txn_manager.get(&reader, &heap, rid).unwrap());
+        assert_eq!(2, txn_manager.scan(&reader, &heap).count());
+    }
+}
@@ -0,0 +1,549 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, RwLockReadGuard};
+
+use crate::buffer_pool::access_strategy::BufferAccessStrategy;
+use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+use crate::buffer_pool::eviction::replacer::AccessType;
+use crate::buffer_pool::page_guard::WritePageGuard;
+use crate::catalog::table_info::Oid;
+use crate::storage::common::{PageId, INVALID_PAGE_ID, PAGE_HEADER_SIZE, PAGE_SIZE};
+use crate::storage::page::table_page::{TablePage, MAX_TUPLE_SIZE, SLOT_SIZE};
+use crate::types::{CrabDBError, CrabDbResult};
+use crate::wal::common::{Lsn, INVALID_LSN, SYSTEM_TXN_ID};
+use crate::wal::log_manager::LogManager;
+use crate::wal::log_record::LogRecordBody;
+
+use super::overflow::{self, TupleChunks, MAX_OVERFLOW_TUPLE_SIZE};
+use super::table_iterator::TableIterator;
+use super::tuple::{Rid, SlotId, Timestamp, Tuple, TupleMeta};
+
+// Logs the insert of the tuple at `rid`, given its page before and after, and returns the
+// LSN of the record.
+pub(crate) type InsertLogger<'a> = &'a dyn Fn(&Arc<LogManager>, Rid, &[u8; PAGE_SIZE], &[u8; PAGE_SIZE]) -> CrabDbResult<Lsn>;
+
+// A table stored as a singly linked list of TablePages. New tuples go to the last page; once
+// it is full they go to an earlier page the buffer pool's free space map has room on, and
+// only when there is none is a new page chained on. Tuples bigger than a page are written to
+// overflow pages and leave a stub in their slot.
+pub struct TableHeap {
+    bpm: Arc<BufferPoolManager>,
+    first_page_id: PageId,
+    pages: Mutex<HeapPages>,
+    // The table or key-value store whose rows the heap holds, which transactions lock them
+    // under; None for heaps of the catalog and of tests.
+    owner: Option<Oid>,
+}
+
+struct HeapPages {
+    last_page_id: PageId,
+    // Every page of the heap, to tell its pages apart from others in the free space map.
+    page_ids: HashSet<PageId>,
+}
+
+impl HeapPages {
+    fn new(first_page_id: PageId) -> Self {
+        HeapPages { last_page_id: first_page_id, page_ids: HashSet::from([first_page_id]) }
+    }
+}
+
+impl TableHeap {
+    pub fn new(bpm: Arc<BufferPoolManager>) -> CrabDbResult<Self> {
+        let page = bpm.new_page()?;
+        let first_page_id = page.page_id();
+        TablePage::new(page.write()).init(INVALID_PAGE_ID);
+        bpm.unpin_page(first_page_id, true)?;
+        Ok(TableHeap { bpm, first_page_id, pages: Mutex::new(HeapPages::new(first_page_id)), owner: None })
+    }
+
+    // Reopens a heap created earlier, given its first page.
+    pub fn open(bpm: Arc<BufferPoolManager>, first_page_id: PageId) -> CrabDbResult<Self> {
+        let heap = TableHeap { bpm, first_page_id, pages: Mutex::new(HeapPages::new(first_page_id)), owner: None };
+        let mut pages = HeapPages::new(first_page_id);
+        loop {
+            let next_page_id = heap.read_page(pages.last_page_id, |page| Ok(page.next_page_id()))?;
+            if next_page_id == INVALID_PAGE_ID {
+                break;
+            }
+            pages.last_page_id = next_page_id;
+            pages.page_ids.insert(next_page_id);
+        }
+        *heap.pages.lock().unwrap() = pages;
+        Ok(heap)
+    }
+
+    pub(crate) fn owned_by(mut self, oid: Oid) -> Self {
+        self.owner = Some(oid);
+        self
+    }
+
+    pub fn first_page_id(&self) -> PageId {
+        self.first_page_id
+    }
+
+    pub fn owner(&self) -> Option<Oid> {
+        self.owner
+    }
+
+    // How many pages the heap is chained over, overflow pages aside.
+    pub fn num_pages(&self) -> usize {
+        self.pages.lock().unwrap().page_ids.len()
+    }
+
+    // The pages the heap is chained over, overflow pages aside.
+    pub(crate) fn page_ids(&self) -> HashSet<PageId> {
+        self.pages.lock().unwrap().page_ids.clone()
+    }
+
+    pub(crate) fn bpm(&self) -> &Arc<BufferPoolManager> {
+        &self.bpm
+    }
+
+    pub fn insert_tuple(&self, tuple: &Tuple) -> CrabDbResult<Rid> {
+        self.insert_tuple_with_ts(tuple, 0, None).map(|(rid, _)| rid)
+    }
+
+    // Inserts the tuple with timestamp `ts`. With `log`, and a log in the buffer pool, the
+    // write to its slot is logged through `log`, whose LSN is returned along with the rid.
+    pub(crate) fn insert_tuple_with_ts(&self, tuple: &Tuple, ts: Timestamp, log: Option<InsertLogger>) -> CrabDbResult<(Rid, Lsn)> {
+        if tuple.is_empty() || tuple.len() > MAX_OVERFLOW_TUPLE_SIZE {
+            return Err(CrabDBError::InvalidInput(format!(
+                "Tuple of {} bytes cannot be stored (max {MAX_OVERFLOW_TUPLE_SIZE})", tuple.len()
+            )));
+        }
+        let is_overflow = tuple.len() > MAX_TUPLE_SIZE;
+        let stub;
+        let data = if is_overflow {
+            stub = overflow::write_chain(&self.bpm, tuple.data())?;
+            &stub
+        } else {
+            tuple.data()
+        };
+        let inserted = self.insert_data(data, is_overflow, ts, log);
+        if let (Err(_), true) = (&inserted, is_overflow) {
+            let _ = overflow::free_chain(&self.bpm, overflow::parse_stub(data)?.1);
+        }
+        inserted
+    }
+
+    fn insert_data(&self, data: &[u8], is_overflow: bool, ts: Timestamp, log: Option<InsertLogger>) -> CrabDbResult<(Rid, Lsn)> {
+        let mut pages = self.pages.lock().unwrap();
+        let last_page_id = pages.last_page_id;
+        let (inserted, free_space, lsn) = self.insert_into(last_page_id, data, is_overflow, ts, log)?;
+        if let Some(slot_id) = inserted {
+            return Ok((Rid::new(last_page_id, slot_id), lsn));
+        }
+        if let Some(free_space_map) = self.bpm.free_space_map() {
+            free_space_map.record_free_space(&self.bpm, last_page_id, free_space)?;
+            let needed = data.len() + SLOT_SIZE;
+            while let Some(page_id) = free_space_map.find_page(&self.bpm, needed, |page_id| pages.page_ids.contains(&page_id))? {
+                // The entry is only a hint; a page that turns out to be too full is recorded
+                // with what it really has, so it is not suggested again.
+                let (inserted, free_space, lsn) = self.insert_into(page_id, data, is_overflow, ts, log)?;
+                free_space_map.record_free_space(&self.bpm, page_id, free_space)?;
+                if let Some(slot_id) = inserted {
+                    return Ok((Rid::new(page_id, slot_id), lsn));
+                }
+            }
+        }
+
+        let page = self.bpm.new_page()?;
+        let new_page_id = page.page_id();
+        TablePage::new(page.write()).init(INVALID_PAGE_ID);
+        self.bpm.unpin_page(new_page_id, true)?;
+        let (inserted, _, lsn) = self.insert_into(new_page_id, data, is_overflow, ts, log)?;
+        let slot_id = inserted.expect("tuple fits in an empty page");
+        // On disk the new page is all zeroes until written, which reads as a page linking to
+        // page 0, so it has to get there before the link to it does.
+        self.bpm.flush_page(new_page_id)?;
+        self.write_page(last_page_id, |page| {
+            page.set_next_page_id(new_page_id);
+            Ok(())
+        })?;
+        pages.last_page_id = new_page_id;
+        pages.page_ids.insert(new_page_id);
+        Ok((Rid::new(new_page_id, slot_id), lsn))
+    }
+
+    // Returns the slot the tuple went to, if it fit, the space the page has left, and the
+    // LSN of the insert if it was logged.
+    fn insert_into(
+        &self,
+        page_id: PageId,
+        data: &[u8],
+        is_overflow: bool,
+        ts: Timestamp,
+        log: Option<InsertLogger>,
+    ) -> CrabDbResult<(Option<SlotId>, usize, Lsn)> {
+        let insert = |page: &mut TablePage<&mut [u8; PAGE_SIZE]>| {
+            let slot_id = if is_overflow { page.insert_overflow_stub(data) } else { page.insert_tuple(data) };
+            if let Some(slot_id) = slot_id {
+                page.set_tuple_ts(slot_id, ts)?;
+            }
+            Ok((slot_id, page.reclaimable_space()))
+        };
+        let Some(log) = log else {
+            let (slot_id, free_space) = self.write_page(page_id, insert)?;
+            return Ok((slot_id, free_space, INVALID_LSN));
+        };
+        let ((slot_id, free_space), lsn) = self.bpm.write_page_logged(
+            page_id,
+            |data| insert(&mut TablePage::new(data)),
+            |log_manager, &(slot_id, _), before, after| match slot_id {
+                Some(slot_id) => log(log_manager, Rid::new(page_id, slot_id), before, after).map(Some),
+                None => Ok(None),
+            },
+        )?;
+        Ok((slot_id, free_space, lsn))
+    }
+
+    pub fn get_tuple(&self, rid: Rid) -> CrabDbResult<Tuple> {
+        self.read_page(rid.page_id(), |page| {
+            let data = page.get_tuple(rid.slot_id())?;
+            self.resolve_overflow(page.is_overflow(rid.slot_id())?, Tuple::new(data.to_vec()))
+        })
+    }
+
+    // Reads the tuple at `rid` back in chunks of at most a page, without assembling it in
+    // memory. The chunks stay readable until the tuple is reclaimed by vacuum.
+    pub fn tuple_chunks(&self, rid: Rid) -> CrabDbResult<TupleChunks<'_>> {
+        let (is_overflow, data) = self.read_page(rid.page_id(), |page| {
+            Ok((page.is_overflow(rid.slot_id())?, page.get_tuple(rid.slot_id())?.to_vec()))
+        })?;
+        if !is_overflow {
+            return Ok(TupleChunks::inline(&self.bpm, data));
+        }
+        let (len, first_page_id) = overflow::parse_stub(&data)?;
+        Ok(TupleChunks::new(&self.bpm, len, first_page_id))
+    }
+
+    // Turns the bytes of a slot into the tuple they stand for: the bytes themselves, or for
+    // an overflow stub the tuple read back from its overflow pages.
+    pub(crate) fn resolve_overflow(&self, is_overflow: bool, tuple: Tuple) -> CrabDbResult<Tuple> {
+        if !is_overflow {
+            return Ok(tuple);
+        }
+        Ok(Tuple::new(overflow::read_chain(&self.bpm, tuple.data())?))
+    }
+
+    pub fn tuple_meta(&self, rid: Rid) -> CrabDbResult<TupleMeta> {
+        self.read_page(rid.page_id(), |page| page.tuple_meta(rid.slot_id()))
+    }
+
+    // Returns false if the new version no longer fits in the tuple's page; the caller can
+    // then delete and reinsert it elsewhere.
+    pub fn update_tuple(&self, rid: Rid, tuple: &Tuple) -> CrabDbResult<bool> {
+        self.write_page(rid.page_id(), |page| page.update_tuple(rid.slot_id(), tuple.data()))
+    }
+
+    pub fn mark_delete(&self, rid: Rid) -> CrabDbResult<()> {
+        self.write_page(rid.page_id(), |page| page.mark_delete(rid.slot_id()))
+    }
+
+    pub fn rollback_delete(&self, rid: Rid) -> CrabDbResult<()> {
+        self.write_page(rid.page_id(), |page| page.rollback_delete(rid.slot_id()))
+    }
+
+    pub fn apply_delete(&self, rid: Rid) -> CrabDbResult<()> {
+        let (stub, free_space) = self.write_page(rid.page_id(), |page| {
+            let stub = Self::take_overflow_stub(page, rid.slot_id())?;
+            page.apply_delete(rid.slot_id())?;
+            Ok((stub, page.reclaimable_space()))
+        })?;
+        self.free_overflow(stub.as_slice())?;
+        self.record_free_space(rid.page_id(), free_space)
+    }
+
+    // The stub of a slot about to be reclaimed, if it has overflow pages to free along with it.
+    pub(crate) fn take_overflow_stub(page: &TablePage<&mut [u8; PAGE_SIZE]>, slot_id: SlotId) -> CrabDbResult<Option<Vec<u8>>> {
+        Ok(match page.is_overflow(slot_id)? {
+            true => Some(page.get_tuple_with_meta(slot_id)?.1.to_vec()),
+            false => None,
+        })
+    }
+
+    // Frees the overflow pages of reclaimed slots, once the page holding them is released.
+    pub(crate) fn free_overflow<S: AsRef<[u8]>>(&self, stubs: &[S]) -> CrabDbResult<()> {
+        for stub in stubs {
+            overflow::free_chain(&self.bpm, overflow::parse_stub(stub.as_ref())?.1)?;
+        }
+        Ok(())
+    }
+
+    // Lets inserts find the room `page_id` has, through the buffer pool's free space map.
+    pub(crate) fn record_free_space(&self, page_id: PageId, free_space: usize) -> CrabDbResult<()> {
+        match self.bpm.free_space_map() {
+            Some(free_space_map) => free_space_map.record_free_space(&self.bpm, page_id, free_space),
+            None => Ok(()),
+        }
+    }
+
+    // Moves the tuple at `rid` to `target_page_id`, with its timestamp and overflow pages, and
+    // returns where it went; None, leaving it be, if it does not fit there. Both pages are
+    // logged whole as one committed change, so a crash cannot lose the tuple or keep both
+    // copies. The caller makes sure nobody is reading the tuple meanwhile.
+    pub(crate) fn move_tuple(&self, rid: Rid, target_page_id: PageId) -> CrabDbResult<Option<Rid>> {
+        if rid.page_id() == target_page_id {
+            return Ok(None);
+        }
+        let mut source = self.bpm.fetch_page_write(rid.page_id())?;
+        let mut target = self.bpm.fetch_page_write(target_page_id)?;
+        let (meta, data, is_overflow) = {
+            let page = TablePage::new(&*source);
+            let (meta, data) = page.get_tuple_with_meta(rid.slot_id())?;
+            (meta, data.to_vec(), page.is_overflow(rid.slot_id())?)
+        };
+        let slot_id = {
+            let mut page = TablePage::new(&mut *target);
+            let slot_id = if is_overflow { page.insert_overflow_stub(&data) } else { page.insert_tuple(&data) };
+            let Some(slot_id) = slot_id else {
+                return Ok(None);
+            };
+            page.set_tuple_ts(slot_id, meta.ts)?;
+            slot_id
+        };
+        let mut page = TablePage::new(&mut *source);
+        page.apply_delete(rid.slot_id())?;
+        let source_space = page.reclaimable_space();
+        let target_space = TablePage::new(&*target).reclaimable_space();
+        self.log_pages(&[&source, &target])?;
+        drop((source, target));
+        self.record_free_space(rid.page_id(), source_space)?;
+        self.record_free_space(target_page_id, target_space)?;
+        Ok(Some(Rid::new(target_page_id, slot_id)))
+    }
+
+    // Takes `page_id` out of the heap's chain and hands it back to the buffer pool, to be
+    // given out again. Returns false, keeping it, if it is the first page or still has
+    // tuples, deleted ones included.
+    pub(crate) fn free_page(&self, page_id: PageId) -> CrabDbResult<bool> {
+        let mut pages = self.pages.lock().unwrap();
+        if page_id == self.first_page_id || !pages.page_ids.contains(&page_id) {
+            return Ok(false);
+        }
+        let (empty, next_page_id) = self.read_page(page_id, |page| {
+            let empty = (0..page.num_slots()).all(|slot_id| page.tuple_meta(slot_id).is_err());
+            Ok((empty, page.next_page_id()))
+        })?;
+        if !empty {
+            return Ok(false);
+        }
+        let mut prev_page_id = self.first_page_id;
+        loop {
+            let next = self.read_page(prev_page_id, |page| Ok(page.next_page_id()))?;
+            if next == page_id {
+                break;
+            }
+            if next == INVALID_PAGE_ID {
+                return Err(CrabDBError::Corruption(format!("Page {page_id} is not in the chain of its heap")));
+            }
+            prev_page_id = next;
+        }
+        // The page is only given out again once the link to it is gone for good.
+        {
+            let mut prev = self.bpm.fetch_page_write(prev_page_id)?;
+            TablePage::new(&mut *prev).set_next_page_id(next_page_id);
+            self.log_pages(&[&prev])?;
+        }
+        if pages.last_page_id == page_id {
+            pages.last_page_id = prev_page_id;
+        }
+        pages.page_ids.remove(&page_id);
+        self.bpm.delete_page(page_id)?;
+        Ok(true)
+    }
+
+    // Logs the pages whole, as one change committed right away, when the buffer pool has a
+    // log; recovery redoes it.
+    fn log_pages(&self, pages: &[&WritePageGuard<'_>]) -> CrabDbResult<()> {
+        let Some(log_manager) = self.bpm.log_manager() else {
+            return Ok(());
+        };
+        let mut prev_lsn = INVALID_LSN;
+        for page in pages {
+            // A compensation record is redone and never undone, which is what a page image
+            // needs.
+            prev_lsn = log_manager.append(SYSTEM_TXN_ID, prev_lsn, LogRecordBody::Compensation {
+                page_id: page.page_id(),
+                offset: PAGE_HEADER_SIZE as u16,
+                after: page[PAGE_HEADER_SIZE..].to_vec(),
+                undo_next_lsn: INVALID_LSN,
+            })?;
+            page.page().set_page_lsn(prev_lsn);
+        }
+        log_manager.append(SYSTEM_TXN_ID, prev_lsn, LogRecordBody::Commit { commit_ts: 0 })?;
+        Ok(())
+    }
+
+    // Hands every page of the heap back to the buffer pool. Used for heaps that only live as
+    // long as a query or a session, such as the spill space of executors and temporary tables.
+    pub(crate) fn delete_pages(&self) -> CrabDbResult<()> {
+        let mut page_id = self.first_page_id;
+        while page_id != INVALID_PAGE_ID {
+            let (next_page_id, stubs) = self.read_page(page_id, |page| {
+                let stubs: Vec<_> = (0..page.num_slots())
+                    .filter(|&slot_id| page.is_overflow(slot_id).unwrap_or(false))
+                    .map(|slot_id| Ok(page.get_tuple_with_meta(slot_id)?.1.to_vec()))
+                    .collect::<CrabDbResult<_>>()?;
+                Ok((page.next_page_id(), stubs))
+            })?;
+            self.free_overflow(&stubs)?;
+            self.bpm.delete_page(page_id)?;
+            page_id = next_page_id;
+        }
+        Ok(())
+    }
+
+    pub fn iter(&self) -> TableIterator<'_> {
+        TableIterator::new(self)
+    }
+
+    pub(crate) fn read_page<R>(
+        &self,
+        page_id: PageId,
+        f: impl FnOnce(&TablePage<RwLockReadGuard<'_, [u8; PAGE_SIZE]>>) -> CrabDbResult<R>,
+    ) -> CrabDbResult<R> {
+        self.read_page_with_access(page_id, AccessType::Lookup, f)
+    }
+
+    pub(crate) fn read_page_with_access<R>(
+        &self,
+        page_id: PageId,
+        access_type: AccessType,
+        f: impl FnOnce(&TablePage<RwLockReadGuard<'_, [u8; PAGE_SIZE]>>) -> CrabDbResult<R>,
+    ) -> CrabDbResult<R> {
+        self.read_page_with_strategy(page_id, access_type, None, f)
+    }
+
+    // With a strategy, the page is read as a scan through the strategy's ring of frames.
+    pub(crate) fn read_page_with_strategy<R>(
+        &self,
+        page_id: PageId,
+        access_type: AccessType,
+        strategy: Option<&BufferAccessStrategy>,
+        f: impl FnOnce(&TablePage<RwLockReadGuard<'_, [u8; PAGE_SIZE]>>) -> CrabDbResult<R>,
+    ) -> CrabDbResult<R> {
+        let page = match strategy {
+            Some(strategy) => self.bpm.fetch_page_with_strategy(page_id, strategy)?,
+            None => self.bpm.fetch_page_with_access(page_id, access_type)?,
+        };
+        let result = f(&TablePage::new(page.read()));
+        self.bpm.unpin_page(page_id, false)?;
+        result
+    }
+
+    // Changes made here are not logged; commits log the page whole.
+    pub(crate) fn write_page<R>(
+        &self,
+        page_id: PageId,
+        f: impl FnOnce(&mut TablePage<&mut [u8; PAGE_SIZE]>) -> CrabDbResult<R>,
+    ) -> CrabDbResult<R> {
+        let mut page = self.bpm.fetch_page_write(page_id)?;
+        f(&mut TablePage::new(&mut *page))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tempfile::TempDir;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::storage::disk::file_disk_manager::FileDiskManager;
+    use crate::storage::free_space_map::FreeSpaceMap;
+    use crate::storage::table::tuple::Tuple;
+    use super::TableHeap;
+
+    fn buffer_pool(dir: &TempDir, pool_size: usize) -> Arc<BufferPoolManager> {
+        let disk_manager = Arc::new(FileDiskManager::new(dir.path().join("test.db")).unwrap());
+        Arc::new(BufferPoolManager::new(pool_size, disk_manager, Arc::new(LRUKReplacer::new(pool_size, 2))))
+    }
+
+    fn tuple(i: usize) -> Tuple {
+        Tuple::new(format!("tuple number {i:04} {}", "x".repeat(i % 50)).into_bytes())
+    }
+
+    #[test]
+    pub fn test_table_heap_spans_pages() {
+        let dir = TempDir::new().unwrap();
+        let heap = TableHeap::new(buffer_pool(&dir, 4)).unwrap();
+
+        let rids: Vec<_> = (0..500).map(|i| heap.insert_tuple(&tuple(i)).unwrap()).collect();
+        assert!(rids.last().unwrap().page_id() > heap.first_page_id());
+        for (i, rid) in rids.iter().enumerate() {
+            assert_eq!(tuple(i), heap.get_tuple(*rid).unwrap());
+        }
+
+        let scanned: Vec<_> = heap.iter().map(|entry| entry.unwrap()).collect();
+        assert_eq!(500, scanned.len());
+        assert_eq!(rids, scanned.iter().map(|(rid, _)| *rid).collect::<Vec<_>>());
+        assert_eq!(tuple(499), scanned[499].1);
+    }
+
+    #[test]
+    pub fn test_table_heap_delete_and_update() {
+        let dir = TempDir::new().unwrap();
+        let heap = TableHeap::new(buffer_pool(&dir, 4)).unwrap();
+        let rids: Vec<_> = (0..3).map(|i| heap.insert_tuple(&tuple(i)).unwrap()).collect();
+
+        heap.mark_delete(rids[1]).unwrap();
+        assert_eq!(2, heap.iter().count());
+        heap.rollback_delete(rids[1]).unwrap();
+        assert_eq!(3, heap.iter().count());
+        heap.mark_delete(rids[1]).unwrap();
+        heap.apply_delete(rids[1]).unwrap();
+        assert!(heap.get_tuple(rids[1]).is_err());
+
+        assert!(heap.update_tuple(rids[2], &Tuple::new(b"updated".to_vec())).unwrap());
+        let scanned: Vec<_> = heap.iter().map(|entry| entry.unwrap().1).collect();
+        assert_eq!(vec![tuple(0), Tuple::new(b"updated".to_vec())], scanned);
+        assert_eq!(
+            "Tuple of 0 bytes cannot be stored (max 1073741824)",
+            heap.insert_tuple(&Tuple::new(Vec::new())).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    pub fn test_table_heap_reopen() {
+        let dir = TempDir::new().unwrap();
+        let bpm = buffer_pool(&dir, 4);
+        let first_page_id = {
+            let heap = TableHeap::new(bpm.clone()).unwrap();
+            for i in 0..200 {
+                heap.insert_tuple(&tuple(i)).unwrap();
+            }
+            heap.first_page_id()
+        };
+
+        let heap = TableHeap::open(bpm, first_page_id).unwrap();
+        let rid = heap.insert_tuple(&tuple(200)).unwrap();
+        assert_eq!(tuple(200), heap.get_tuple(rid).unwrap());
+        assert_eq!(201, heap.iter().count());
+    }
+
+    #[test]
+    pub fn test_table_heap_fills_pages_the_free_space_map_has_room_on() {
+        let dir = TempDir::new().unwrap();
+        let bpm = buffer_pool(&dir, 8);
+        bpm.set_free_space_map(FreeSpaceMap::create(&bpm).unwrap()).unwrap();
+        // two of these fit on a page
+        let big = Tuple::new(vec![7; 1800]);
+
+        let heap = TableHeap::new(bpm.clone()).unwrap();
+        let rids: Vec<_> = (0..6).map(|_| heap.insert_tuple(&big).unwrap()).collect();
+        heap.apply_delete(rids[0]).unwrap();
+        heap.apply_delete(rids[1]).unwrap();
+
+        // another heap never gets the room on the first heap's page
+        let other = TableHeap::new(bpm.clone()).unwrap();
+        let other_rids: Vec<_> = (0..3).map(|_| other.insert_tuple(&big).unwrap()).collect();
+        assert!(other_rids.iter().all(|rid| rid.page_id() != rids[0].page_id()));
+
+        // the first heap fills its emptied page before chaining on a new one
+        let page_ids: Vec<_> = (0..3).map(|_| heap.insert_tuple(&big).unwrap().page_id()).collect();
+        assert_eq!(vec![rids[0].page_id(), rids[0].page_id()], page_ids[..2]);
+        assert!(page_ids[2] > other_rids[2].page_id());
+        assert_eq!(7, heap.iter().count());
+    }
+}
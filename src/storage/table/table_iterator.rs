@@ -0,0 +1,71 @@
+use crate::buffer_pool::access_strategy::BufferAccessStrategy;
+use crate::buffer_pool::eviction::replacer::AccessType;
+use crate::storage::common::{PageId, INVALID_PAGE_ID};
+use crate::types::CrabDbResult;
+
+use super::table_heap::TableHeap;
+use super::tuple::{Rid, SlotId, Tuple};
+
+// Walks every live tuple of a heap in page order. Each step pins the current page only for
+// as long as it takes to copy the next tuple out, so a scan never holds frames hostage.
+pub struct TableIterator<'a> {
+    heap: &'a TableHeap,
+    page_id: PageId,
+    slot_id: SlotId,
+    strategy: Option<BufferAccessStrategy>,
+}
+
+impl<'a> TableIterator<'a> {
+    pub(crate) fn new(heap: &'a TableHeap) -> Self {
+        TableIterator { heap, page_id: heap.first_page_id(), slot_id: 0, strategy: None }
+    }
+
+    // Reads the heap's pages through the strategy's ring of frames.
+    pub fn with_strategy(mut self, strategy: BufferAccessStrategy) -> Self {
+        self.strategy = Some(strategy);
+        self
+    }
+
+    fn advance(&mut self) -> CrabDbResult<Option<(Rid, Tuple)>> {
+        while self.page_id != INVALID_PAGE_ID {
+            let entering_page = self.slot_id == 0;
+            let (found, next_page_id) = self.heap.read_page_with_strategy(self.page_id, AccessType::Scan, self.strategy.as_ref(), |page| {
+                while self.slot_id < page.num_slots() {
+                    let slot_id = self.slot_id;
+                    self.slot_id += 1;
+                    if let Ok(tuple) = page.get_tuple(slot_id) {
+                        let tuple = self.heap.resolve_overflow(page.is_overflow(slot_id)?, Tuple::new(tuple.to_vec()))?;
+                        return Ok((Some((Rid::new(self.page_id, slot_id), tuple)), page.next_page_id()));
+                    }
+                }
+                Ok((None, page.next_page_id()))
+            })?;
+            // Start reading the next page while this one is scanned. If the read cannot be
+            // started, the page is simply read when the scan gets there.
+            if entering_page {
+                let _ = self.heap.bpm().prefetch(&[next_page_id]);
+            }
+            if found.is_some() {
+                return Ok(found);
+            }
+            self.page_id = next_page_id;
+            self.slot_id = 0;
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for TableIterator<'_> {
+    type Item = CrabDbResult<(Rid, Tuple)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.advance() {
+            Ok(entry) => entry.map(Ok),
+            Err(e) => {
+                // Stop after reporting the error instead of retrying the same page forever.
+                self.page_id = INVALID_PAGE_ID;
+                Some(Err(e))
+            },
+        }
+    }
+}
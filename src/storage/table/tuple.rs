@@ -0,0 +1,76 @@
+use std::fmt::Display;
+
+use crate::storage::common::PageId;
+use crate::types::schema::Schema;
+use crate::types::value::Value;
+use crate::types::CrabDbResult;
+
+pub type SlotId = u16;
+
+// Commit timestamp of a tuple version. Tuples written outside of MVCC carry 0, which every
+// snapshot sees.
+pub type Timestamp = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TupleMeta {
+    pub ts: Timestamp,
+    pub is_deleted: bool,
+}
+
+// Record id: where a tuple lives in a table heap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Rid {
+    page_id: PageId,
+    slot_id: SlotId,
+}
+
+impl Rid {
+    pub fn new(page_id: PageId, slot_id: SlotId) -> Self {
+        Rid { page_id, slot_id }
+    }
+
+    pub fn page_id(&self) -> PageId {
+        self.page_id
+    }
+
+    pub fn slot_id(&self) -> SlotId {
+        self.slot_id
+    }
+}
+
+impl Display for Rid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}, {})", self.page_id, self.slot_id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tuple {
+    data: Vec<u8>,
+}
+
+impl Tuple {
+    pub fn new(data: Vec<u8>) -> Self {
+        Tuple { data }
+    }
+
+    pub fn from_values(schema: &Schema, values: &[Value]) -> CrabDbResult<Self> {
+        Ok(Tuple { data: schema.serialize_row(values)? })
+    }
+
+    pub fn values(&self, schema: &Schema) -> CrabDbResult<Vec<Value>> {
+        schema.deserialize_row(&self.data)
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
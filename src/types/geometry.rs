@@ -0,0 +1,117 @@
+use std::fmt::Display;
+
+use super::{CrabDBError, CrabDbResult};
+
+// A GEOMETRY value: an axis-aligned box, min corner first. A point is a box with both
+// corners the same. Coordinates are finite, so boxes compare and order like tuples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Geometry {
+    pub min: [f64; 2],
+    pub max: [f64; 2],
+}
+
+impl Geometry {
+    pub fn point(x: f64, y: f64) -> Self {
+        Geometry { min: [x, y], max: [x, y] }
+    }
+
+    // The box with corners at the two points, in either order.
+    pub fn bbox(x1: f64, y1: f64, x2: f64, y2: f64) -> Self {
+        Geometry { min: [x1.min(x2), y1.min(y2)], max: [x1.max(x2), y1.max(y2)] }
+    }
+
+    pub fn is_point(&self) -> bool {
+        self.min == self.max
+    }
+
+    pub fn intersects(&self, other: &Geometry) -> bool {
+        (0..2).all(|axis| self.min[axis] <= other.max[axis] && other.min[axis] <= self.max[axis])
+    }
+
+    // Whether `other` lies inside this geometry, its boundary included.
+    pub fn contains(&self, other: &Geometry) -> bool {
+        (0..2).all(|axis| self.min[axis] <= other.min[axis] && other.max[axis] <= self.max[axis])
+    }
+
+    // The Euclidean distance between the closest points of the two, 0 if they intersect.
+    pub fn distance(&self, other: &Geometry) -> f64 {
+        let gap = |axis: usize| (self.min[axis] - other.max[axis]).max(other.min[axis] - self.max[axis]).max(0.0);
+        gap(0).hypot(gap(1))
+    }
+
+    // The smallest box holding both.
+    pub fn union(&self, other: &Geometry) -> Geometry {
+        Geometry {
+            min: [self.min[0].min(other.min[0]), self.min[1].min(other.min[1])],
+            max: [self.max[0].max(other.max[0]), self.max[1].max(other.max[1])],
+        }
+    }
+
+    pub fn area(&self) -> f64 {
+        (self.max[0] - self.min[0]) * (self.max[1] - self.min[1])
+    }
+
+    pub(crate) fn coordinates(&self) -> [f64; 4] {
+        [self.min[0], self.min[1], self.max[0], self.max[1]]
+    }
+
+    pub(crate) fn from_coordinates(coordinates: [f64; 4]) -> Self {
+        Geometry { min: [coordinates[0], coordinates[1]], max: [coordinates[2], coordinates[3]] }
+    }
+
+    // Reads a geometry written the way it prints, e.g. "POINT(1 2)" or "BOX(0 0, 3 4)".
+    pub fn parse(text: &str) -> CrabDbResult<Geometry> {
+        let invalid = || CrabDBError::InvalidInput(format!("Invalid geometry {text}"));
+        let text = text.trim();
+        let open = text.find('(').ok_or_else(invalid)?;
+        let inner = text[open + 1..].strip_suffix(')').ok_or_else(invalid)?;
+        let numbers = inner.split(',')
+            .flat_map(str::split_whitespace)
+            .map(|number| number.parse::<f64>().ok().filter(|number| number.is_finite()).ok_or_else(invalid))
+            .collect::<CrabDbResult<Vec<_>>>()?;
+        match (text[..open].trim().to_ascii_uppercase().as_str(), numbers.as_slice(), inner.matches(',').count()) {
+            ("POINT", &[x, y], 0) => Ok(Geometry::point(x, y)),
+            ("BOX", &[x1, y1, x2, y2], 1) => Ok(Geometry::bbox(x1, y1, x2, y2)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Display for Geometry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_point() {
+            return write!(f, "POINT({} {})", self.min[0], self.min[1]);
+        }
+        write!(f, "BOX({} {}, {} {})", self.min[0], self.min[1], self.max[0], self.max[1])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Geometry;
+
+    #[test]
+    pub fn test_geometry_predicates_and_text() {
+        let unit = Geometry::bbox(1.0, 1.0, 0.0, 0.0);
+        assert_eq!(Geometry { min: [0.0, 0.0], max: [1.0, 1.0] }, unit);
+        let inside = Geometry::point(0.5, 1.0);
+        let outside = Geometry::point(4.0, 5.0);
+        assert!(unit.contains(&inside) && unit.intersects(&inside) && !inside.contains(&unit));
+        assert!(!unit.intersects(&outside) && !unit.contains(&outside));
+        assert!(unit.intersects(&Geometry::bbox(1.0, 1.0, 2.0, 2.0)));
+        assert_eq!(0.0, unit.distance(&inside));
+        assert_eq!(5.0, unit.distance(&outside));
+        assert_eq!(Geometry::bbox(0.0, 0.0, 4.0, 5.0), unit.union(&outside));
+        assert_eq!(1.0, unit.area());
+
+        assert_eq!("POINT(0.5 1)", inside.to_string());
+        assert_eq!("BOX(0 0, 1 1)", unit.to_string());
+        for geometry in [inside, unit] {
+            assert_eq!(geometry, Geometry::parse(&geometry.to_string()).unwrap());
+        }
+        assert_eq!(Geometry::point(-1.5, 2.0), Geometry::parse(" point( -1.5  2 ) ").unwrap());
+        for invalid in ["POINT(1)", "POINT(1, 2)", "BOX(0 0 1 1)", "BOX(0 0, 1)", "CIRCLE(0 0, 1)", "POINT(1 inf)", "POINT 1 2"] {
+            assert!(Geometry::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use std::fmt::Display;
+
+use crate::buffer_pool::common::FrameId;
+use crate::storage::common::PageId;
+use crate::wal::common::TxnId;
+
+pub mod geometry;
+pub mod schema;
+pub mod value;
+
+#[derive(Debug)]
+pub enum CrabDBError {
+    // Every frame of the buffer pool is pinned, so no page can be brought in.
+    BufferPoolFull,
+    // A frame id at or beyond the number of frames a replacer tracks.
+    FrameOutOfRange(FrameId),
+    // A frame the replacer has no access history for.
+    FrameNotFound(FrameId),
+    // A frame that cannot be removed from the replacer because it is pinned.
+    NotEvictable(FrameId),
+    // A page that cannot be deleted, or whose frame cannot be retired, because it is pinned.
+    PagePinned(PageId),
+    Io { context: String, source: std::io::Error },
+    // A page read back from disk failed its checksum: a torn write or media corruption.
+    Corruption(String),
+    // The transaction was aborted to break a deadlock; retrying it may succeed.
+    Deadlock(String),
+    // The transaction waited longer than the lock timeout for a lock and was aborted; no
+    // deadlock was detected, the lock was just held for too long. Retrying it may succeed.
+    LockTimeout(String),
+    // Another transaction wrote the same row after this one's snapshot, or is upgrading its
+    // lock on it; retrying may succeed.
+    Conflict(String),
+    // The transaction was already aborted, so it can do nothing but roll back.
+    TxnAborted(TxnId),
+    // The request itself is wrong: SQL that does not parse or bind, values of the wrong type,
+    // a violated constraint, a transaction used after it ended. Retrying cannot help.
+    InvalidInput(String),
+    // A row would give an index a key it already holds: every index is unique, including
+    // those backing PRIMARY KEY and UNIQUE constraints, which are named after them.
+    ConstraintViolation { table: String, constraint: String },
+    // What the operation refers to is not there: a page that was never allocated or is not in
+    // the buffer pool, an empty or deleted slot, a row, an encryption key.
+    NotFound(String),
+    // The database stopped taking writes and commits after a failure it cannot recover from
+    // while open, such as a log flush that failed: it has to be reopened.
+    Offline(String),
+    General(String),
+}
+
+// A stable classification of errors for callers that cannot match on the error itself,
+// such as clients of a server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    InvalidInput,
+    NotFound,
+    Conflict,
+    Deadlock,
+    LockTimeout,
+    TxnAborted,
+    ResourceExhausted,
+    Io,
+    Corruption,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidInput => "invalid_input",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::Conflict => "conflict",
+            ErrorCode::Deadlock => "deadlock",
+            ErrorCode::LockTimeout => "lock_timeout",
+            ErrorCode::TxnAborted => "txn_aborted",
+            ErrorCode::ResourceExhausted => "resource_exhausted",
+            ErrorCode::Io => "io",
+            ErrorCode::Corruption => "corruption",
+            ErrorCode::Internal => "internal",
+        }
+    }
+
+    // Whether the caller is at fault, like an HTTP 4xx, rather than the database, like a 5xx.
+    pub fn is_client_error(&self) -> bool {
+        matches!(
+            self,
+            ErrorCode::InvalidInput | ErrorCode::Conflict | ErrorCode::Deadlock | ErrorCode::LockTimeout | ErrorCode::TxnAborted
+        )
+    }
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Display for CrabDBError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CrabDBError::BufferPoolFull => write!(f, "Buffer pool is full; every frame is pinned"),
+            CrabDBError::FrameOutOfRange(frame_id) => write!(f, "Frame {frame_id} exceeds the replacer size"),
+            CrabDBError::FrameNotFound(frame_id) => write!(f, "Frame {frame_id} does not exist in the replacer"),
+            CrabDBError::NotEvictable(frame_id) => write!(f, "Frame {frame_id} is marked as not evictable"),
+            CrabDBError::PagePinned(page_id) => write!(f, "Page {page_id} is pinned"),
+            CrabDBError::Io { context, source } => write!(f, "{context}: {source}"),
+            CrabDBError::Corruption(message)
+            | CrabDBError::Deadlock(message)
+            | CrabDBError::LockTimeout(message)
+            | CrabDBError::Conflict(message)
+            | CrabDBError::InvalidInput(message)
+            | CrabDBError::NotFound(message)
+            | CrabDBError::General(message) => write!(f, "{message}"),
+            CrabDBError::Offline(reason) => write!(f, "The database is offline until it is reopened: {reason}"),
+            CrabDBError::TxnAborted(txn_id) => write!(f, "Transaction {txn_id} is aborted"),
+            CrabDBError::ConstraintViolation { table, constraint } => {
+                write!(f, "Duplicate key in index {constraint} of table {table}")
+            },
+        }
+    }
+}
+
+impl std::error::Error for CrabDBError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CrabDBError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl CrabDBError {
+    pub fn new(message: String) -> Self {
+        CrabDBError::General(message)
+    }
+
+    // An I/O failure, described by what was being done when it happened.
+    pub fn io(context: String, source: std::io::Error) -> Self {
+        CrabDBError::Io { context, source }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            CrabDBError::BufferPoolFull => ErrorCode::ResourceExhausted,
+            CrabDBError::Io { .. } | CrabDBError::Offline(_) => ErrorCode::Io,
+            CrabDBError::Corruption(_) => ErrorCode::Corruption,
+            CrabDBError::Deadlock(_) => ErrorCode::Deadlock,
+            CrabDBError::LockTimeout(_) => ErrorCode::LockTimeout,
+            CrabDBError::Conflict(_) => ErrorCode::Conflict,
+            CrabDBError::TxnAborted(_) => ErrorCode::TxnAborted,
+            CrabDBError::InvalidInput(_) | CrabDBError::ConstraintViolation { .. } => ErrorCode::InvalidInput,
+            CrabDBError::NotFound(_) => ErrorCode::NotFound,
+            CrabDBError::FrameOutOfRange(_)
+            | CrabDBError::FrameNotFound(_)
+            | CrabDBError::NotEvictable(_)
+            | CrabDBError::PagePinned(_)
+            | CrabDBError::General(_) => ErrorCode::Internal,
+        }
+    }
+
+    // Whether running the whole transaction again may succeed: it lost a race with another
+    // transaction rather than doing anything wrong.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self.code(), ErrorCode::Conflict | ErrorCode::Deadlock | ErrorCode::LockTimeout | ErrorCode::TxnAborted)
+    }
+
+    // Whether the condition may clear up on its own, so that the same operation may succeed
+    // if tried again later: retryable errors, a buffer pool whose pages are all pinned for
+    // the moment and I/O that was interrupted or timed out.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            CrabDBError::BufferPoolFull => true,
+            CrabDBError::Io { source, .. } => matches!(
+                source.kind(),
+                std::io::ErrorKind::Interrupted | std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+            ),
+            _ => self.is_retryable(),
+        }
+    }
+}
+
+pub type CrabDbResult<T> = Result<T, CrabDBError>;
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Error, ErrorKind};
+
+    use super::{CrabDBError, ErrorCode};
+
+    #[test]
+    pub fn test_errors_map_to_codes_and_retryability() {
+        let io = |kind: ErrorKind| CrabDBError::io("Failed to read page 3".to_string(), Error::from(kind));
+        let text = || "message".to_string();
+        // each error with its code, whether retrying the transaction may succeed and whether
+        // trying the same operation again later may
+        let cases = [
+            (CrabDBError::BufferPoolFull, ErrorCode::ResourceExhausted, false, true),
+            (CrabDBError::FrameOutOfRange(7), ErrorCode::Internal, false, false),
+            (CrabDBError::FrameNotFound(7), ErrorCode::Internal, false, false),
+            (CrabDBError::NotEvictable(7), ErrorCode::Internal, false, false),
+            (CrabDBError::PagePinned(7), ErrorCode::Internal, false, false),
+            (io(ErrorKind::NotFound), ErrorCode::Io, false, false),
+            (io(ErrorKind::Interrupted), ErrorCode::Io, false, true),
+            (io(ErrorKind::TimedOut), ErrorCode::Io, false, true),
+            (io(ErrorKind::WouldBlock), ErrorCode::Io, false, true),
+            (CrabDBError::Corruption(text()), ErrorCode::Corruption, false, false),
+            (CrabDBError::Deadlock(text()), ErrorCode::Deadlock, true, true),
+            (CrabDBError::LockTimeout(text()), ErrorCode::LockTimeout, true, true),
+            (CrabDBError::Conflict(text()), ErrorCode::Conflict, true, true),
+            (CrabDBError::TxnAborted(7), ErrorCode::TxnAborted, true, true),
+            (CrabDBError::InvalidInput(text()), ErrorCode::InvalidInput, false, false),
+            (CrabDBError::ConstraintViolation { table: "crabs".to_string(), constraint: "crabs_pkey".to_string() }, ErrorCode::InvalidInput, false, false),
+            (CrabDBError::NotFound(text()), ErrorCode::NotFound, false, false),
+            (CrabDBError::Offline(text()), ErrorCode::Io, false, false),
+            (CrabDBError::General(text()), ErrorCode::Internal, false, false),
+        ];
+        for (error, code, retryable, transient) in cases {
+            assert_eq!((code, retryable, transient), (error.code(), error.is_retryable(), error.is_transient()), "{error:?}");
+        }
+
+        let client_errors = [ErrorCode::InvalidInput, ErrorCode::Conflict, ErrorCode::Deadlock, ErrorCode::LockTimeout, ErrorCode::TxnAborted];
+        for code in client_errors {
+            assert!(code.is_client_error(), "{code}");
+        }
+        for code in [ErrorCode::NotFound, ErrorCode::ResourceExhausted, ErrorCode::Io, ErrorCode::Corruption, ErrorCode::Internal] {
+            assert!(!code.is_client_error(), "{code}");
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use super::value::{DataType, Value};
+use super::{CrabDBError, CrabDbResult};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    name: String,
+    data_type: DataType,
+    nullable: bool,
+}
+
+impl Column {
+    pub fn new(name: impl Into<String>, data_type: DataType) -> Self {
+        Column { name: name.into(), data_type, nullable: true }
+    }
+
+    pub fn not_null(mut self) -> Self {
+        self.nullable = false;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn data_type(&self) -> DataType {
+        self.data_type
+    }
+
+    pub fn is_nullable(&self) -> bool {
+        self.nullable
+    }
+}
+
+// Describes the columns of a row and how a row is laid out as tuple bytes:
+//   null bitmap (one bit per column) | non-null values in column order
+// Fixed-size values are stored inline and varchars as a u32 length followed by the bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schema {
+    columns: Vec<Column>,
+}
+
+impl Schema {
+    pub fn new(columns: Vec<Column>) -> Self {
+        Schema { columns }
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    pub fn column(&self, index: usize) -> &Column {
+        &self.columns[index]
+    }
+
+    pub fn column_count(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column.name.eq_ignore_ascii_case(name))
+    }
+
+    fn bitmap_size(&self) -> usize {
+        self.columns.len().div_ceil(8)
+    }
+
+    pub fn serialize_row(&self, values: &[Value]) -> CrabDbResult<Vec<u8>> {
+        if values.len() != self.columns.len() {
+            return Err(CrabDBError::InvalidInput(format!(
+                "Expected {} values but got {}", self.columns.len(), values.len()
+            )));
+        }
+        let mut out = vec![0; self.bitmap_size()];
+        for (i, (column, value)) in self.columns.iter().zip(values).enumerate() {
+            if value.is_null() {
+                if !column.nullable {
+                    return Err(CrabDBError::InvalidInput(format!("Column {} does not allow NULL", column.name)));
+                }
+                out[i / 8] |= 1 << (i % 8);
+                continue;
+            }
+            value.cast_to(column.data_type)
+                .map_err(|e| CrabDBError::InvalidInput(format!("Column {}: {e}", column.name)))?
+                .serialize(&mut out);
+        }
+        Ok(out)
+    }
+
+    pub fn deserialize_row(&self, bytes: &[u8]) -> CrabDbResult<Vec<Value>> {
+        let bitmap = bytes.get(..self.bitmap_size())
+            .ok_or_else(|| CrabDBError::Corruption("Tuple is too short for its null bitmap".into()))?;
+        let mut position = bitmap.len();
+        let mut values = Vec::with_capacity(self.columns.len());
+        for (i, column) in self.columns.iter().enumerate() {
+            if bitmap[i / 8] & (1 << (i % 8)) != 0 {
+                values.push(Value::Null);
+                continue;
+            }
+            let (value, len) = Value::deserialize(column.data_type, &bytes[position..])?;
+            values.push(value);
+            position += len;
+        }
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::value::{DataType, Value};
+    use super::{Column, Schema};
+
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Column::new("id", DataType::Int64).not_null(),
+            Column::new("name", DataType::Varchar),
+            Column::new("score", DataType::Float64),
+            Column::new("active", DataType::Boolean),
+        ])
+    }
+
+    #[test]
+    pub fn test_schema_row_round_trip() {
+        let schema = schema();
+        // Int32 literals are widened to the column type
+        let row = vec![Value::Int32(1), Value::Varchar("ferris".into()), Value::Null, Value::Boolean(true)];
+        let bytes = schema.serialize_row(&row).unwrap();
+        assert_eq!(
+            vec![Value::Int64(1), Value::Varchar("ferris".into()), Value::Null, Value::Boolean(true)],
+            schema.deserialize_row(&bytes).unwrap()
+        );
+        assert_eq!(Some(2), schema.index_of("SCORE"));
+        assert_eq!(None, schema.index_of("missing"));
+    }
+
+    #[test]
+    pub fn test_schema_rejects_bad_rows() {
+        let schema = schema();
+        assert_eq!(
+            "Expected 4 values but got 1",
+            schema.serialize_row(&[Value::Int64(1)]).unwrap_err().to_string()
+        );
+        assert_eq!(
+            "Column id does not allow NULL",
+            schema.serialize_row(&[Value::Null, Value::Null, Value::Null, Value::Null]).unwrap_err().to_string()
+        );
+        assert_eq!(
+            "Column name: Cannot convert 5 to VARCHAR",
+            schema.serialize_row(&[Value::Int64(1), Value::Int32(5), Value::Null, Value::Null]).unwrap_err().to_string()
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use std::cmp::Ordering;
+use std::fmt::Display;
+
+use super::geometry::Geometry;
+use super::{CrabDBError, CrabDbResult};
+
+const VECTOR_TAG: u8 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DataType {
+    Boolean,
+    Int32,
+    Int64,
+    Float64,
+    Varchar,
+    // Microseconds since the Unix epoch.
+    Timestamp,
+    // A fixed number of 32-bit floats, such as an embedding.
+    Vector(u16),
+    // A point or an axis-aligned box in the plane.
+    Geometry,
+}
+
+impl DataType {
+    // Serialized size of a value of this type, or None for variable-length types.
+    pub fn fixed_size(&self) -> Option<usize> {
+        match self {
+            DataType::Boolean => Some(1),
+            DataType::Int32 => Some(4),
+            DataType::Int64 | DataType::Float64 | DataType::Timestamp => Some(8),
+            DataType::Varchar => None,
+            DataType::Vector(dimensions) => Some(4 * *dimensions as usize),
+            DataType::Geometry => Some(32),
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, DataType::Int32 | DataType::Int64 | DataType::Float64)
+    }
+
+    // Stable on-disk tag, used wherever schemas are persisted. A vector's dimensions are
+    // persisted after the tag.
+    pub(crate) fn tag(&self) -> u8 {
+        match self {
+            DataType::Boolean => 1,
+            DataType::Int32 => 2,
+            DataType::Int64 => 3,
+            DataType::Float64 => 4,
+            DataType::Varchar => 5,
+            DataType::Timestamp => 6,
+            DataType::Vector(_) => VECTOR_TAG,
+            DataType::Geometry => 8,
+        }
+    }
+
+    pub(crate) fn from_tag(tag: u8) -> Option<DataType> {
+        match tag {
+            1 => Some(DataType::Boolean),
+            2 => Some(DataType::Int32),
+            3 => Some(DataType::Int64),
+            4 => Some(DataType::Float64),
+            5 => Some(DataType::Varchar),
+            6 => Some(DataType::Timestamp),
+            // With no dimensions, for the reader to fill in.
+            VECTOR_TAG => Some(DataType::Vector(0)),
+            8 => Some(DataType::Geometry),
+            _ => None,
+        }
+    }
+}
+
+impl Display for DataType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let DataType::Vector(dimensions) = self {
+            return write!(f, "VECTOR({dimensions})");
+        }
+        let name = match self {
+            DataType::Boolean => "BOOLEAN",
+            DataType::Int32 => "INT",
+            DataType::Int64 => "BIGINT",
+            DataType::Float64 => "DOUBLE",
+            DataType::Varchar => "VARCHAR",
+            DataType::Timestamp => "TIMESTAMP",
+            DataType::Geometry => "GEOMETRY",
+            DataType::Vector(_) => unreachable!("written above"),
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Boolean(bool),
+    Int32(i32),
+    Int64(i64),
+    Float64(f64),
+    Varchar(String),
+    Timestamp(i64),
+    Vector(Vec<f32>),
+    Geometry(Geometry),
+}
+
+impl Value {
+    // None for Null, which fits a column of any type.
+    pub fn data_type(&self) -> Option<DataType> {
+        match self {
+            Value::Null => None,
+            Value::Boolean(_) => Some(DataType::Boolean),
+            Value::Int32(_) => Some(DataType::Int32),
+            Value::Int64(_) => Some(DataType::Int64),
+            Value::Float64(_) => Some(DataType::Float64),
+            Value::Varchar(_) => Some(DataType::Varchar),
+            Value::Timestamp(_) => Some(DataType::Timestamp),
+            Value::Vector(vector) => Some(DataType::Vector(vector.len() as u16)),
+            Value::Geometry(_) => Some(DataType::Geometry),
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Int32(i) => Some(*i as i64),
+            Value::Int64(i) | Value::Timestamp(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Int32(i) => Some(*i as f64),
+            Value::Int64(i) => Some(*i as f64),
+            Value::Float64(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Varchar(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_vector(&self) -> Option<&[f32]> {
+        match self {
+            Value::Vector(vector) => Some(vector),
+            _ => None,
+        }
+    }
+
+    pub fn as_geometry(&self) -> Option<&Geometry> {
+        match self {
+            Value::Geometry(geometry) => Some(geometry),
+            _ => None,
+        }
+    }
+
+    // Reads a vector written the way it prints, e.g. "[1, 2.5, -3]".
+    pub fn parse_vector(text: &str) -> CrabDbResult<Value> {
+        let invalid = || CrabDBError::InvalidInput(format!("Invalid vector {text}"));
+        let inner = text.trim().strip_prefix('[').and_then(|rest| rest.strip_suffix(']')).ok_or_else(invalid)?;
+        if inner.trim().is_empty() {
+            return Ok(Value::Vector(Vec::new()));
+        }
+        inner.split(',')
+            .map(|element| element.trim().parse::<f32>().ok().filter(|element| element.is_finite()).ok_or_else(invalid))
+            .collect::<CrabDbResult<_>>()
+            .map(Value::Vector)
+    }
+
+    // SQL comparison: numbers compare across widths, and anything compared with Null (or
+    // with a value of an unrelated type) is unknown.
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Null, _) | (_, Value::Null) => None,
+            (Value::Boolean(a), Value::Boolean(b)) => Some(a.cmp(b)),
+            (Value::Varchar(a), Value::Varchar(b)) => Some(a.cmp(b)),
+            (Value::Timestamp(a), Value::Timestamp(b)) => Some(a.cmp(b)),
+            (Value::Vector(a), Value::Vector(b)) => a.partial_cmp(b),
+            (Value::Vector(_), _) | (_, Value::Vector(_)) => None,
+            (Value::Geometry(a), Value::Geometry(b)) => a.coordinates().partial_cmp(&b.coordinates()),
+            (Value::Geometry(_), _) | (_, Value::Geometry(_)) => None,
+            (Value::Float64(_), _) | (_, Value::Float64(_)) => self.as_f64()?.partial_cmp(&other.as_f64()?),
+            _ => Some(self.as_i64()?.cmp(&other.as_i64()?)),
+        }
+    }
+
+    // Converts the value for storage in a column of `data_type`. Only lossless conversions
+    // are allowed: integers widen, and narrow only when the value fits. Vectors and
+    // geometries may be written as strings, and vectors must have the column's dimensions.
+    pub fn cast_to(&self, data_type: DataType) -> CrabDbResult<Value> {
+        if self.is_null() || self.data_type() == Some(data_type) {
+            return Ok(self.clone());
+        }
+        let cast = match (self, data_type) {
+            (Value::Int32(i), DataType::Int64) => Some(Value::Int64(*i as i64)),
+            (Value::Int32(i), DataType::Float64) => Some(Value::Float64(*i as f64)),
+            (Value::Int64(i), DataType::Int32) => i32::try_from(*i).ok().map(Value::Int32),
+            (Value::Int64(i), DataType::Timestamp) => Some(Value::Timestamp(*i)),
+            (Value::Varchar(s), DataType::Vector(_)) => return Value::parse_vector(s)?.cast_to(data_type),
+            (Value::Varchar(s), DataType::Geometry) => return Geometry::parse(s).map(Value::Geometry),
+            _ => None,
+        };
+        cast.ok_or_else(|| CrabDBError::InvalidInput(format!("Cannot convert {self} to {data_type}")))
+    }
+
+    // Appends the value's bytes; Null has no representation of its own and is tracked by
+    // the tuple's null bitmap instead.
+    pub(crate) fn serialize(&self, out: &mut Vec<u8>) {
+        match self {
+            Value::Null => (),
+            Value::Boolean(b) => out.push(*b as u8),
+            Value::Int32(i) => out.extend_from_slice(&i.to_le_bytes()),
+            Value::Int64(i) | Value::Timestamp(i) => out.extend_from_slice(&i.to_le_bytes()),
+            Value::Float64(f) => out.extend_from_slice(&f.to_le_bytes()),
+            Value::Varchar(s) => {
+                out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                out.extend_from_slice(s.as_bytes());
+            },
+            Value::Vector(vector) => {
+                for element in vector {
+                    out.extend_from_slice(&element.to_le_bytes());
+                }
+            },
+            Value::Geometry(geometry) => {
+                for coordinate in geometry.coordinates() {
+                    out.extend_from_slice(&coordinate.to_le_bytes());
+                }
+            },
+        }
+    }
+
+    // Reads a value of `data_type` from the start of `bytes`, returning it with the number
+    // of bytes consumed.
+    pub(crate) fn deserialize(data_type: DataType, bytes: &[u8]) -> CrabDbResult<(Value, usize)> {
+        let take = |len: usize| bytes.get(..len)
+            .ok_or_else(|| CrabDBError::Corruption(format!("Tuple is truncated while reading a {data_type} value")));
+        let value = match data_type {
+            DataType::Boolean => (Value::Boolean(take(1)?[0] != 0), 1),
+            DataType::Int32 => (Value::Int32(i32::from_le_bytes(take(4)?.try_into().unwrap())), 4),
+            DataType::Int64 => (Value::Int64(i64::from_le_bytes(take(8)?.try_into().unwrap())), 8),
+            DataType::Float64 => (Value::Float64(f64::from_le_bytes(take(8)?.try_into().unwrap())), 8),
+            DataType::Timestamp => (Value::Timestamp(i64::from_le_bytes(take(8)?.try_into().unwrap())), 8),
+            DataType::Varchar => {
+                let len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+                let s = String::from_utf8(take(4 + len)?[4..].to_vec())
+                    .map_err(|_| CrabDBError::Corruption("Varchar value is not valid UTF-8".into()))?;
+                (Value::Varchar(s), 4 + len)
+            },
+            DataType::Vector(dimensions) => {
+                let len = 4 * dimensions as usize;
+                let vector = take(len)?.chunks_exact(4).map(|element| f32::from_le_bytes(element.try_into().unwrap())).collect();
+                (Value::Vector(vector), len)
+            },
+            DataType::Geometry => {
+                let mut coordinates = [0.0; 4];
+                for (coordinate, bytes) in coordinates.iter_mut().zip(take(32)?.chunks_exact(8)) {
+                    *coordinate = f64::from_le_bytes(bytes.try_into().unwrap());
+                }
+                (Value::Geometry(Geometry::from_coordinates(coordinates)), 32)
+            },
+        };
+        Ok(value)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Null => write!(f, "NULL"),
+            Value::Boolean(b) => write!(f, "{b}"),
+            Value::Int32(i) => write!(f, "{i}"),
+            Value::Int64(i) => write!(f, "{i}"),
+            Value::Float64(x) => write!(f, "{x}"),
+            Value::Varchar(s) => write!(f, "{s}"),
+            Value::Timestamp(t) => write!(f, "{t}"),
+            Value::Vector(vector) => {
+                let elements: Vec<_> = vector.iter().map(f32::to_string).collect();
+                write!(f, "[{}]", elements.join(", "))
+            },
+            Value::Geometry(geometry) => write!(f, "{geometry}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use super::{DataType, Geometry, Value};
+
+    #[test]
+    pub fn test_value_round_trip() {
+        let values = [
+            Value::Boolean(true),
+            Value::Int32(-7),
+            Value::Int64(1 << 40),
+            Value::Float64(2.5),
+            Value::Varchar("crab".into()),
+            Value::Timestamp(1_700_000_000_000_000),
+            Value::Vector(vec![1.0, -0.5, 3.25]),
+            Value::Geometry(Geometry::bbox(-1.0, 2.0, 3.5, 4.0)),
+        ];
+        let mut bytes = Vec::new();
+        for value in &values {
+            value.serialize(&mut bytes);
+        }
+        let mut position = 0;
+        for value in &values {
+            let (read, len) = Value::deserialize(value.data_type().unwrap(), &bytes[position..]).unwrap();
+            assert_eq!(*value, read);
+            position += len;
+        }
+        assert_eq!(bytes.len(), position);
+        assert!(Value::deserialize(DataType::Varchar, &bytes[..6]).is_err());
+    }
+
+    #[test]
+    pub fn test_value_compare_and_cast() {
+        assert_eq!(Some(Ordering::Less), Value::Int32(1).compare(&Value::Int64(2)));
+        assert_eq!(Some(Ordering::Greater), Value::Float64(2.5).compare(&Value::Int32(2)));
+        assert_eq!(Some(Ordering::Equal), Value::Varchar("a".into()).compare(&Value::Varchar("a".into())));
+        assert_eq!(None, Value::Null.compare(&Value::Int32(1)));
+        assert_eq!(None, Value::Boolean(true).compare(&Value::Int32(1)));
+
+        assert_eq!(Value::Int64(3), Value::Int32(3).cast_to(DataType::Int64).unwrap());
+        assert_eq!(Value::Int32(3), Value::Int64(3).cast_to(DataType::Int32).unwrap());
+        assert_eq!(Value::Null, Value::Null.cast_to(DataType::Varchar).unwrap());
+        assert_eq!(
+            "Cannot convert 4294967296 to INT",
+            Value::Int64(1 << 32).cast_to(DataType::Int32).unwrap_err().to_string()
+        );
+
+        assert_eq!(Value::Vector(vec![1.0, 2.5]), Value::Varchar(" [1, 2.5] ".into()).cast_to(DataType::Vector(2)).unwrap());
+        assert_eq!("[1, 2.5]", Value::Vector(vec![1.0, 2.5]).to_string());
+        assert!(Value::Varchar("[1, 2.5]".into()).cast_to(DataType::Vector(3)).is_err());
+        assert!(Value::parse_vector("[1, x]").is_err());
+        assert!(Value::parse_vector("1, 2").is_err());
+
+        let point = Value::Geometry(Geometry::point(1.0, 2.0));
+        assert_eq!(point, Value::Varchar("POINT(1 2)".into()).cast_to(DataType::Geometry).unwrap());
+        assert_eq!("POINT(1 2)", point.to_string());
+        assert_eq!(Some(Ordering::Less), point.compare(&Value::Geometry(Geometry::point(1.0, 3.0))));
+        assert_eq!(None, point.compare(&Value::Int32(1)));
+        assert!(Value::Varchar("[1, 2]".into()).cast_to(DataType::Geometry).is_err());
+    }
+}
@@ -0,0 +1,296 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::storage::encryption::KeyRing;
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::common::{Lsn, INVALID_LSN};
+use super::log_manager::LogManager;
+use super::log_record::{LogRecord, LogRecordBody};
+
+// Where a restore from archived log stops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryTarget {
+    // Everything in the archive.
+    Latest,
+    // The record with this LSN, and everything before it.
+    Lsn(Lsn),
+    // The last clock record at or before this time, which follows the commits made by then.
+    Time(SystemTime),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalSegment {
+    pub first_lsn: Lsn,
+    pub last_lsn: Lsn,
+    pub path: PathBuf,
+}
+
+pub(crate) fn unix_micros(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_micros() as u64)
+}
+
+// A directory of log segments copied out of a database's write-ahead log, oldest first,
+// without gaps. Each segment is named after the first and last LSN it holds, zero padded so
+// the names sort, and is written under a temporary name and renamed, so a segment that
+// exists is whole. The log is only recycled once the archive has copied it.
+pub struct WalArchive {
+    dir: PathBuf,
+    // Last LSN copied into the archive, and the log offset archiving reads on from;
+    // archiving runs one at a time under this lock.
+    archived: Mutex<(Lsn, u64)>,
+    // Seals the records of segments, as in the log they come from.
+    encryption: Option<Arc<KeyRing>>,
+}
+
+impl WalArchive {
+    pub fn open(dir: impl Into<PathBuf>) -> CrabDbResult<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| CrabDBError::io(format!("Failed to create WAL archive {}", dir.display()), e))?;
+        let archive = WalArchive { dir, archived: Mutex::new((INVALID_LSN, 0)), encryption: None };
+        let archived_lsn = archive.segments()?.last().map_or(INVALID_LSN, |segment| segment.last_lsn);
+        archive.archived.lock().unwrap().0 = archived_lsn;
+        Ok(archive)
+    }
+
+    pub fn with_encryption(mut self, encryption: Option<Arc<KeyRing>>) -> Self {
+        self.encryption = encryption;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // Last LSN copied into the archive.
+    pub fn archived_lsn(&self) -> Lsn {
+        self.archived.lock().unwrap().0
+    }
+
+    pub fn segments(&self) -> CrabDbResult<Vec<WalSegment>> {
+        let read_error = |e| CrabDBError::io(format!("Failed to list WAL archive {}", self.dir.display()), e);
+        let mut segments = Vec::new();
+        for entry in std::fs::read_dir(&self.dir).map_err(read_error)? {
+            let path = entry.map_err(read_error)?.path();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()).and_then(|name| name.strip_suffix(".wal")) else {
+                continue;
+            };
+            let Some((first_lsn, last_lsn)) = name.split_once('-') else {
+                continue;
+            };
+            if let (Ok(first_lsn), Ok(last_lsn)) = (first_lsn.parse(), last_lsn.parse()) {
+                segments.push(WalSegment { first_lsn, last_lsn, path });
+            }
+        }
+        segments.sort_by_key(|segment| segment.first_lsn);
+        Ok(segments)
+    }
+
+    // Copies the records of `log_manager` past the last archived one into a new segment and
+    // returns it, or None if there was nothing new. The log is read on from where the last
+    // call stopped, and what the segment covers is recycled, as far as the log's last
+    // checkpoint allows.
+    pub fn archive(&self, log_manager: &LogManager) -> CrabDbResult<Option<WalSegment>> {
+        let mut archived = self.archived.lock().unwrap();
+        let (archived_lsn, offset) = *archived;
+        log_manager.flush(log_manager.next_lsn() - 1)?;
+        let (records, end) = log_manager.records_from(offset)?;
+        let mut bytes = Vec::new();
+        let mut lsns = None;
+        for record in records.iter().filter(|record| record.lsn > archived_lsn) {
+            if lsns.is_none() && archived_lsn != INVALID_LSN && record.lsn != archived_lsn + 1 {
+                return Err(CrabDBError::InvalidInput(format!(
+                    "WAL archive {} ends at LSN {archived_lsn}, but the log from there to LSN {} has been recycled",
+                    self.dir.display(), record.lsn - 1,
+                )));
+            }
+            record.serialize_sealed(&mut bytes, self.encryption.as_deref());
+            let first_lsn = lsns.map_or(record.lsn, |(first_lsn, _)| first_lsn);
+            lsns = Some((first_lsn, record.lsn));
+        }
+        let Some((first_lsn, last_lsn)) = lsns else {
+            archived.1 = end;
+            return Ok(None);
+        };
+        let path = self.dir.join(format!("{first_lsn:020}-{last_lsn:020}.wal"));
+        let temp_path = path.with_extension("tmp");
+        let write_error = |e| CrabDBError::io(format!("Failed to write WAL segment {}", path.display()), e);
+        std::fs::write(&temp_path, &bytes).map_err(write_error)?;
+        std::fs::File::open(&temp_path).and_then(|file| file.sync_all()).map_err(write_error)?;
+        std::fs::rename(&temp_path, &path).map_err(write_error)?;
+        *archived = (last_lsn, end);
+        log_manager.recycle(last_lsn + 1)?;
+        Ok(Some(WalSegment { first_lsn, last_lsn, path }))
+    }
+
+    // The archived records after `after_lsn` up to `target`, in order. `after_lsn` is where
+    // a backup ends and `after_time` when it was taken, if known; a target before the
+    // backup, or one the archive does not reach, is an error, as is a gap in the archive.
+    pub(crate) fn records(&self, after_lsn: Lsn, after_time: Option<u64>, target: RecoveryTarget) -> CrabDbResult<Vec<LogRecord>> {
+        let before_backup = || CrabDBError::InvalidInput(format!("Recovery target {target:?} is before the backup was taken"));
+        match target {
+            RecoveryTarget::Lsn(lsn) if lsn < after_lsn => return Err(before_backup()),
+            RecoveryTarget::Time(time) if after_time.is_some_and(|after_time| unix_micros(time) < after_time) => {
+                return Err(before_backup());
+            },
+            _ => (),
+        }
+
+        let mut records = Vec::new();
+        let mut next_lsn = after_lsn + 1;
+        'segments: for segment in self.segments()?.into_iter().filter(|segment| segment.last_lsn > after_lsn) {
+            let bytes = std::fs::read(&segment.path)
+                .map_err(|e| CrabDBError::io(format!("Failed to read WAL segment {}", segment.path.display()), e))?;
+            let mut position = 0;
+            while let Some((record, size)) = LogRecord::deserialize_sealed(&bytes[position..], self.encryption.as_deref())? {
+                position += size;
+                if record.lsn < next_lsn {
+                    continue;
+                }
+                if record.lsn != next_lsn {
+                    return Err(CrabDBError::Corruption(format!(
+                        "WAL archive {} is missing records {next_lsn} to {}", self.dir.display(), record.lsn - 1
+                    )));
+                }
+                let past_target = match (target, &record.body) {
+                    (RecoveryTarget::Lsn(lsn), _) => record.lsn > lsn,
+                    (RecoveryTarget::Time(time), LogRecordBody::Clock { unix_micros: micros }) => *micros > unix_micros(time),
+                    _ => false,
+                };
+                if past_target {
+                    break 'segments;
+                }
+                next_lsn += 1;
+                records.push(record);
+            }
+            if position != bytes.len() {
+                return Err(CrabDBError::Corruption(format!("WAL segment {} is damaged", segment.path.display())));
+            }
+        }
+        match target {
+            RecoveryTarget::Lsn(lsn) if next_lsn <= lsn => Err(CrabDBError::InvalidInput(format!(
+                "WAL archive {} ends at LSN {}, before the recovery target {lsn}", self.dir.display(), next_lsn - 1
+            ))),
+            RecoveryTarget::Time(_) => {
+                // Stop at the last clock record: what follows it may belong to a commit made
+                // after the target.
+                let end = records.iter().rposition(|record| matches!(record.body, LogRecordBody::Clock { .. }));
+                records.truncate(end.map_or(0, |end| end + 1));
+                Ok(records)
+            },
+            _ => Ok(records),
+        }
+    }
+}
+
+// Archives the log on a background thread every `interval`.
+pub struct WalArchiver {
+    worker: Option<(Sender<()>, JoinHandle<()>)>,
+}
+
+impl WalArchiver {
+    pub fn new(archive: Arc<WalArchive>, log_manager: Arc<LogManager>, interval: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let worker = std::thread::Builder::new()
+            .name("crab-db-wal-archiver".into())
+            .spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    // A failed copy is retried on the next tick.
+                    let _ = archive.archive(&log_manager);
+                }
+            })
+            .expect("failed to spawn WAL archiver");
+        WalArchiver { worker: Some((stop, worker)) }
+    }
+}
+
+impl Drop for WalArchiver {
+    fn drop(&mut self) {
+        if let Some((stop, worker)) = self.worker.take() {
+            drop(stop);
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use tempfile::TempDir;
+
+    use crate::db::crab_db::CrabDb;
+    use crate::db::options::CrabDbOptions;
+    use crate::types::CrabDBError;
+    use crate::wal::common::INVALID_LSN;
+    use crate::wal::log_manager::LogManager;
+    use crate::wal::log_record::LogRecordBody;
+    use super::{RecoveryTarget, WalArchive};
+
+    fn options() -> CrabDbOptions {
+        CrabDbOptions::default().pool_size(16).vacuum_interval(None).flush_interval(None).wal_archive_interval(None)
+    }
+
+    #[test]
+    pub fn test_point_in_time_recovery_from_archived_log() {
+        let dir = TempDir::new().unwrap();
+        let archive_dir = dir.path().join("archive");
+        let restore = |name: &str, target: RecoveryTarget| {
+            CrabDb::restore_to(dir.path().join("base.bak"), &archive_dir, dir.path().join(name), options(), target)
+        };
+        let count = |db: &CrabDb| db.execute("SELECT * FROM crabs").unwrap();
+
+        let db = CrabDb::open(dir.path().join("crabs.db"), options().wal_archive_dir(&archive_dir)).unwrap();
+        db.execute("CREATE TABLE crabs (id BIGINT NOT NULL)").unwrap();
+        db.execute("INSERT INTO crabs VALUES (1)").unwrap();
+        db.backup_to(dir.path().join("base.bak")).unwrap();
+        let before_backup = SystemTime::now() - Duration::from_secs(60);
+        db.execute("INSERT INTO crabs VALUES (2), (3)").unwrap();
+        let first_segment = db.archive_wal().unwrap().unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        let after_three = SystemTime::now();
+        std::thread::sleep(Duration::from_millis(10));
+        db.execute("INSERT INTO crabs VALUES (4)").unwrap();
+        let after_four = db.metrics_snapshot().wal.flushed_lsn;
+        db.execute("DELETE FROM crabs").unwrap();
+        db.close().unwrap();
+
+        assert_eq!(3, count(&restore("time.db", RecoveryTarget::Time(after_three)).unwrap()));
+        assert_eq!(4, count(&restore("lsn.db", RecoveryTarget::Lsn(after_four)).unwrap()));
+        assert_eq!(0, count(&restore("latest.db", RecoveryTarget::Latest).unwrap()));
+        assert!(matches!(restore("early.db", RecoveryTarget::Time(before_backup)), Err(CrabDBError::InvalidInput(_))));
+        assert!(matches!(restore("far.db", RecoveryTarget::Lsn(after_four + 1000)), Err(CrabDBError::InvalidInput(_))));
+        assert!(!dir.path().join("far.db").exists());
+
+        // a restore cannot skip over a missing segment
+        std::fs::remove_file(first_segment.path).unwrap();
+        assert!(matches!(restore("gap.db", RecoveryTarget::Latest), Err(CrabDBError::Corruption(_))));
+    }
+
+    #[test]
+    pub fn test_archive_reads_on_from_its_offset_and_recycles_what_it_copied() {
+        let dir = TempDir::new().unwrap();
+        let archive = WalArchive::open(dir.path().join("archive")).unwrap();
+        let log_manager = LogManager::new(dir.path().join("test.log")).unwrap();
+        let begin = log_manager.append(1, INVALID_LSN, LogRecordBody::Begin).unwrap();
+        log_manager.append(1, begin, LogRecordBody::Commit { commit_ts: 1 }).unwrap();
+        let (checkpoint, _) = log_manager.append_checkpoint(1, log_manager.next_lsn(), Vec::new()).unwrap();
+
+        let segment = archive.archive(&log_manager).unwrap().unwrap();
+        assert_eq!((begin, checkpoint), (segment.first_lsn, segment.last_lsn));
+        // the checkpoint stays, for the log to carry on numbering from
+        assert_eq!(vec![checkpoint], log_manager.records().unwrap().iter().map(|record| record.lsn).collect::<Vec<_>>());
+        assert!(archive.archive(&log_manager).unwrap().is_none());
+
+        let begin = log_manager.append(2, INVALID_LSN, LogRecordBody::Begin).unwrap();
+        log_manager.append(2, begin, LogRecordBody::Abort).unwrap();
+        let segment = archive.archive(&log_manager).unwrap().unwrap();
+        assert_eq!((checkpoint + 1, checkpoint + 2), (segment.first_lsn, segment.last_lsn));
+        assert_eq!(checkpoint, log_manager.records().unwrap()[0].lsn);
+        assert_eq!(2, archive.segments().unwrap().len());
+    }
+}
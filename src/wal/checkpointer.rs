@@ -0,0 +1,54 @@
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+use crate::concurrency::transaction_manager::TransactionManager;
+use crate::types::CrabDbResult;
+
+use super::archive::WalArchive;
+use super::common::Lsn;
+
+// Logs a fuzzy checkpoint of `bpm` and recycles the log before the first record recovery
+// from it reads, keeping what `archive` has yet to copy. Returns how many records were
+// recycled. Pages are not written back for it: the log shrinks as the background writer
+// and eviction clean the pages that were dirty.
+pub(crate) fn checkpoint(bpm: &BufferPoolManager, txn_manager: &TransactionManager, archive: Option<&WalArchive>) -> CrabDbResult<usize> {
+    txn_manager.log_checkpoint(bpm)?;
+    let archived = archive.map_or(Lsn::MAX, |archive| archive.archived_lsn() + 1);
+    match bpm.log_manager() {
+        Some(log_manager) => log_manager.recycle(archived),
+        None => Ok(0),
+    }
+}
+
+// Takes a checkpoint on a background thread every `interval`.
+pub struct Checkpointer {
+    worker: Option<(Sender<()>, JoinHandle<()>)>,
+}
+
+impl Checkpointer {
+    pub fn new(bpm: Arc<BufferPoolManager>, txn_manager: Arc<TransactionManager>, archive: Option<Arc<WalArchive>>, interval: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let worker = std::thread::Builder::new()
+            .name("crab-db-checkpointer".into())
+            .spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    // A failed checkpoint is retried on the next tick.
+                    let _ = checkpoint(&bpm, &txn_manager, archive.as_deref());
+                }
+            })
+            .expect("failed to spawn checkpointer");
+        Checkpointer { worker: Some((stop, worker)) }
+    }
+}
+
+impl Drop for Checkpointer {
+    fn drop(&mut self) {
+        if let Some((stop, worker)) = self.worker.take() {
+            drop(stop);
+            let _ = worker.join();
+        }
+    }
+}
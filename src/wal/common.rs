@@ -0,0 +1,9 @@
+pub type Lsn = u64;
+pub type TxnId = u64;
+
+// LSNs start at 1; a page that has never been logged carries INVALID_LSN.
+pub const INVALID_LSN: Lsn = 0;
+pub const INVALID_TXN_ID: TxnId = TxnId::MAX;
+// Log records the database writes on its own behalf, such as free space map updates, each
+// committed right away.
+pub const SYSTEM_TXN_ID: TxnId = INVALID_TXN_ID - 1;
@@ -0,0 +1,716 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::time::Duration;
+
+use crate::metrics::wal_metrics::{WalMetrics, WalMetricsSnapshot};
+use crate::platform::Stopwatch;
+use crate::storage::common::PageId;
+use crate::storage::encryption::{KeyRing, SEAL_OVERHEAD};
+use crate::storage::table::tuple::Timestamp;
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::common::{Lsn, TxnId, INVALID_LSN, SYSTEM_TXN_ID};
+use super::log_record::{LogRecord, LogRecordBody};
+
+pub const DEFAULT_LOG_BUFFER_SIZE: usize = 64 * 1024;
+
+// Whether a flush waits for the log to reach the disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncMode {
+    // Every flush syncs the log, so a commit survives a power failure once it returns.
+    #[default]
+    Full,
+    // Flushes hand the log to the OS without syncing it. A crash of the process loses
+    // nothing, but a crash of the machine can lose the latest commits.
+    Off,
+}
+
+#[derive(Debug, Clone)]
+pub struct LogManagerOptions {
+    pub buffer_size: usize,
+    // How long a flush waits for more commits to join it before writing. Commits arriving
+    // while a flush is already running are batched into the next one regardless.
+    pub group_commit_delay: Duration,
+    pub sync_mode: SyncMode,
+    // Syncs of the log slower than this are counted, and reported as tracing events.
+    pub slow_io_threshold: Option<Duration>,
+    // Seals the body of every record written with the active key, so page images and rows
+    // never reach the log file in the clear, and opens sealed records read back. Records
+    // are written in the clear if None.
+    pub encryption: Option<Arc<KeyRing>>,
+}
+
+impl Default for LogManagerOptions {
+    fn default() -> Self {
+        LogManagerOptions {
+            buffer_size: DEFAULT_LOG_BUFFER_SIZE,
+            group_commit_delay: Duration::ZERO,
+            sync_mode: SyncMode::Full,
+            slow_io_threshold: None,
+            encryption: None,
+        }
+    }
+}
+
+// Appends log records to an in-memory buffer and writes the buffer out when a transaction
+// commits, when it fills up, or when the buffer pool needs a page's changes on disk before
+// the page itself (the WAL rule). Flushes use group commit: one thread at a time writes and
+// syncs everything buffered so far while the others keep appending or wait for it.
+//
+// The front of the log is recycled once a checkpoint makes it unneeded for recovery; see
+// `recycle`. Byte offsets into the log, as `records_from` takes and returns them, count the
+// bytes recycled too, so an offset stays good across recycling.
+pub struct LogManager {
+    options: LogManagerOptions,
+    flushed_lsn: AtomicU64,
+    metrics: WalMetrics,
+    state: Mutex<LogManagerState>,
+    flushed: Condvar,
+    log_file: Mutex<LogFile>,
+    // Bytes recycled from the front of the log file since it was opened; changed with the
+    // file locked.
+    recycled_bytes: AtomicU64,
+    // The first LSN each live `LogHold` keeps.
+    holds: Mutex<Vec<Weak<AtomicU64>>>,
+    // Makes every write of the log fail, for tests of what happens when it does.
+    #[cfg(test)]
+    failing: std::sync::atomic::AtomicBool,
+}
+
+// Where the log is written: a file, or memory for a database that does not outlive the
+// process.
+enum LogFile {
+    Disk(File, PathBuf),
+    Memory(Cursor<Vec<u8>>),
+}
+
+impl LogFile {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        match self {
+            LogFile::Disk(file, _) => file.set_len(len),
+            LogFile::Memory(cursor) => {
+                cursor.get_mut().truncate(len as usize);
+                Ok(())
+            },
+        }
+    }
+
+    fn sync_data(&mut self) -> io::Result<()> {
+        match self {
+            LogFile::Disk(file, _) => file.sync_data(),
+            LogFile::Memory(_) => Ok(()),
+        }
+    }
+
+    // Replaces the contents with `tail`. A file is written beside and renamed over the old
+    // one, so a crash midway leaves one or the other whole.
+    fn replace(&mut self, tail: &[u8]) -> io::Result<()> {
+        match self {
+            LogFile::Disk(file, path) => {
+                let mut tmp_path = OsString::from(path.as_os_str());
+                tmp_path.push(".tmp");
+                let mut tmp = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&tmp_path)?;
+                tmp.write_all(tail)?;
+                tmp.sync_all()?;
+                std::fs::rename(&tmp_path, &*path)?;
+                *file = tmp;
+                Ok(())
+            },
+            LogFile::Memory(cursor) => {
+                *cursor.get_mut() = tail.to_vec();
+                Ok(())
+            },
+        }
+    }
+}
+
+impl Read for LogFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            LogFile::Disk(file, _) => file.read(buf),
+            LogFile::Memory(cursor) => cursor.read(buf),
+        }
+    }
+}
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            LogFile::Disk(file, _) => file.write(buf),
+            LogFile::Memory(cursor) => cursor.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            LogFile::Disk(file, _) => file.flush(),
+            LogFile::Memory(_) => Ok(()),
+        }
+    }
+}
+
+impl Seek for LogFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            LogFile::Disk(file, _) => file.seek(pos),
+            LogFile::Memory(cursor) => cursor.seek(pos),
+        }
+    }
+}
+
+struct LogManagerState {
+    buffer: Vec<u8>,
+    next_lsn: Lsn,
+    last_buffered_lsn: Lsn,
+    flush_in_progress: bool,
+    // txn id -> LSNs of its first and last record, for every transaction that has logged a
+    // record but not its end: the active transaction table a checkpoint records.
+    active_txns: HashMap<TxnId, (Lsn, Lsn)>,
+    // LSN of the last checkpoint appended, and the first record recovery from it reads.
+    checkpoint: Option<(Lsn, Lsn)>,
+}
+
+// Keeps the records of a log from an LSN on from being recycled, for as long as it lives;
+// see `LogManager::hold`. Clones share the hold.
+#[derive(Clone)]
+pub struct LogHold {
+    lsn: Arc<AtomicU64>,
+}
+
+impl LogHold {
+    pub fn lsn(&self) -> Lsn {
+        self.lsn.load(Ordering::Acquire)
+    }
+
+    // Lets go of the records before `lsn`. A hold only ever moves forward.
+    pub fn advance(&self, lsn: Lsn) {
+        self.lsn.fetch_max(lsn, Ordering::AcqRel);
+    }
+}
+
+impl LogManager {
+    pub fn new(log_path: impl AsRef<Path>) -> CrabDbResult<Self> {
+        Self::with_options(log_path, LogManagerOptions::default())
+    }
+
+    pub fn with_buffer_size(log_path: impl AsRef<Path>, buffer_size: usize) -> CrabDbResult<Self> {
+        Self::with_options(log_path, LogManagerOptions { buffer_size, ..Default::default() })
+    }
+
+    // Reopening an existing log continues after its last intact record; a torn record left
+    // at the tail by a crash is cut off.
+    pub fn with_options(log_path: impl AsRef<Path>, options: LogManagerOptions) -> CrabDbResult<Self> {
+        let log_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(log_path.as_ref())
+            .map_err(|e| CrabDBError::io(format!("Failed to open log file {}", log_path.as_ref().display()), e))?;
+        Self::with_log_file(LogFile::Disk(log_file, log_path.as_ref().to_path_buf()), options)
+    }
+
+    // A log kept in memory, gone with the log manager, for databases that are too.
+    pub fn in_memory(options: LogManagerOptions) -> CrabDbResult<Self> {
+        Self::with_log_file(LogFile::Memory(Cursor::new(Vec::new())), options)
+    }
+
+    fn with_log_file(mut log_file: LogFile, options: LogManagerOptions) -> CrabDbResult<Self> {
+        let (records, valid_len) = Self::read_records(&mut log_file, 0, options.encryption.as_deref())?;
+        log_file.set_len(valid_len)
+            .map_err(|e| CrabDBError::io("Failed to truncate torn log tail".to_string(), e))?;
+        let last_lsn = records.last().map_or(INVALID_LSN, |record| record.lsn);
+        let mut active_txns = HashMap::new();
+        for record in &records {
+            track_active_txn(&mut active_txns, record);
+        }
+
+        let metrics = WalMetrics::default();
+        metrics.set_slow_io_threshold(options.slow_io_threshold);
+        let buffer = Vec::with_capacity(options.buffer_size);
+        Ok(LogManager {
+            options,
+            flushed_lsn: AtomicU64::new(last_lsn),
+            metrics,
+            state: Mutex::new(LogManagerState {
+                buffer,
+                next_lsn: last_lsn + 1,
+                last_buffered_lsn: last_lsn,
+                flush_in_progress: false,
+                active_txns,
+                checkpoint: None,
+            }),
+            flushed: Condvar::new(),
+            log_file: Mutex::new(log_file),
+            recycled_bytes: AtomicU64::new(0),
+            holds: Mutex::new(Vec::new()),
+            #[cfg(test)]
+            failing: std::sync::atomic::AtomicBool::new(false),
+        })
+    }
+
+    // Commit records are durable by the time this returns.
+    pub fn append(&self, txn_id: TxnId, prev_lsn: Lsn, body: LogRecordBody) -> CrabDbResult<Lsn> {
+        let is_commit = matches!(body, LogRecordBody::Commit { .. });
+        let lsn = self.append_buffered(txn_id, prev_lsn, body)?;
+        if is_commit {
+            self.flush(lsn)?;
+        }
+        Ok(lsn)
+    }
+
+    // Like `append`, but leaves flushing a commit to the caller, who can then do it without
+    // holding up others, as group commit needs.
+    pub fn append_buffered(&self, txn_id: TxnId, prev_lsn: Lsn, body: LogRecordBody) -> CrabDbResult<Lsn> {
+        self.append_record(LogRecord::new(INVALID_LSN, prev_lsn, txn_id, body))
+    }
+
+    // Appends a fuzzy checkpoint with the dirty page table `dirty_pages`, taken after
+    // `begin_lsn` was handed out, and the active transaction table as of the checkpoint
+    // itself. Returns its LSN and that of the first record recovery from it reads, before
+    // which `recycle` may drop the log once the checkpoint is flushed.
+    pub fn append_checkpoint(&self, last_commit_ts: Timestamp, begin_lsn: Lsn, dirty_pages: Vec<(PageId, Lsn)>) -> CrabDbResult<(Lsn, Lsn)> {
+        let lsn = self.append_record(LogRecord::new(INVALID_LSN, INVALID_LSN, SYSTEM_TXN_ID, LogRecordBody::Checkpoint {
+            last_commit_ts,
+            begin_lsn,
+            dirty_pages,
+            active_txns: Vec::new(),
+        }))?;
+        let checkpoint = self.state.lock().unwrap().checkpoint;
+        // If another checkpoint was appended since, recovery from that one reads no less.
+        Ok((lsn, checkpoint.map_or(lsn, |(_, redo_lsn)| redo_lsn)))
+    }
+
+    fn append_record(&self, mut record: LogRecord) -> CrabDbResult<Lsn> {
+        let seal_overhead = if self.options.encryption.is_some() { SEAL_OVERHEAD } else { 0 };
+        let mut size = record.serialized_size() + seal_overhead;
+        loop {
+            let mut state: MutexGuard<LogManagerState> = self.state.lock().unwrap();
+            // The active transactions are taken with the LSN, so they are exactly those
+            // the checkpoint follows.
+            if let LogRecordBody::Checkpoint { active_txns, .. } = &mut record.body {
+                *active_txns = state.active_txns.iter().map(|(&txn_id, &(_, last_lsn))| (txn_id, last_lsn)).collect();
+                active_txns.sort_unstable();
+                size = record.serialized_size() + seal_overhead;
+            }
+            if !state.buffer.is_empty() && state.buffer.len() + size > self.options.buffer_size {
+                let last_buffered_lsn = state.last_buffered_lsn;
+                drop(state);
+                self.flush(last_buffered_lsn)?;
+                continue;
+            }
+            record.lsn = state.next_lsn;
+            record.serialize_sealed(&mut state.buffer, self.options.encryption.as_deref());
+            state.next_lsn += 1;
+            state.last_buffered_lsn = record.lsn;
+            if let LogRecordBody::Checkpoint { begin_lsn, dirty_pages, .. } = &record.body {
+                let redo_lsn = dirty_pages.iter().map(|&(_, rec_lsn)| rec_lsn)
+                    .chain(state.active_txns.values().map(|&(first_lsn, _)| first_lsn))
+                    .fold(*begin_lsn, Lsn::min);
+                state.checkpoint = Some((record.lsn, redo_lsn));
+            }
+            track_active_txn(&mut state.active_txns, &record);
+            return Ok(record.lsn);
+        }
+    }
+
+    // Appends records copied from another log, such as a primary's, keeping their LSNs, and
+    // flushes them. The first must follow the last record of this log.
+    pub fn append_copied(&self, records: &[LogRecord]) -> CrabDbResult<()> {
+        let last_lsn = {
+            let mut state: MutexGuard<LogManagerState> = self.state.lock().unwrap();
+            if let Some((i, record)) = records.iter().enumerate().find(|(i, record)| record.lsn != state.next_lsn + *i as Lsn) {
+                return Err(CrabDBError::InvalidInput(format!(
+                    "Log record {} does not follow the last record of the log, {}", record.lsn, state.next_lsn + i as Lsn - 1,
+                )));
+            }
+            for record in records {
+                record.serialize_sealed(&mut state.buffer, self.options.encryption.as_deref());
+                state.next_lsn += 1;
+                state.last_buffered_lsn = record.lsn;
+                track_active_txn(&mut state.active_txns, record);
+            }
+            state.last_buffered_lsn
+        };
+        self.flush(last_lsn)
+    }
+
+    // Makes every record up to and including `lsn` durable. If another thread is already
+    // flushing, waits for it and only flushes again if that did not cover `lsn`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "wal_flush", level = "debug", skip(self), err))]
+    pub fn flush(&self, lsn: Lsn) -> CrabDbResult<()> {
+        let mut state: MutexGuard<LogManagerState> = self.state.lock().unwrap();
+        // Nothing past the last appended record can be made durable.
+        let lsn = lsn.min(state.last_buffered_lsn);
+        while self.flushed_lsn() < lsn {
+            if state.flush_in_progress {
+                state = self.flushed.wait(state).unwrap();
+                continue;
+            }
+            state.flush_in_progress = true;
+            if !self.options.group_commit_delay.is_zero() {
+                drop(state);
+                std::thread::sleep(self.options.group_commit_delay);
+                state = self.state.lock().unwrap();
+            }
+            let buffer = std::mem::take(&mut state.buffer);
+            let flush_lsn = state.last_buffered_lsn;
+            drop(state);
+
+            let written = self.write_out(&buffer, flush_lsn);
+            state = self.state.lock().unwrap();
+            state.flush_in_progress = false;
+            match written {
+                Ok(()) => self.flushed_lsn.store(flush_lsn, Ordering::Release),
+                // Keep the records so a later flush can retry them in order.
+                Err(_) => {
+                    state.buffer.splice(0..0, buffer);
+                },
+            }
+            self.flushed.notify_all();
+            written?;
+        }
+        Ok(())
+    }
+
+    pub fn flushed_lsn(&self) -> Lsn {
+        self.flushed_lsn.load(Ordering::Acquire)
+    }
+
+    pub fn next_lsn(&self) -> Lsn {
+        self.state.lock().unwrap().next_lsn
+    }
+
+    // Number of writes+syncs of the log so far; with group commit this grows slower than
+    // the number of commits.
+    pub fn num_flushes(&self) -> u64 {
+        self.metrics.flushes()
+    }
+
+    pub fn metrics_snapshot(&self) -> WalMetricsSnapshot {
+        self.metrics.snapshot(self.flushed_lsn())
+    }
+
+    // Every record in the log not recycled yet, oldest first. Buffered records are flushed
+    // first.
+    pub fn records(&self) -> CrabDbResult<Vec<LogRecord>> {
+        self.flush(self.next_lsn() - 1)?;
+        Ok(Self::read_records(&mut self.log_file.lock().unwrap(), 0, self.options.encryption.as_deref())?.0)
+    }
+
+    // The records written to the log file from byte `offset` on, and the offset just past
+    // them. Records still buffered are not included, and neither are those recycled: an
+    // offset before the front of the log reads from the front, so a reader that needs every
+    // record finds out by their LSNs.
+    pub fn records_from(&self, offset: u64) -> CrabDbResult<(Vec<LogRecord>, u64)> {
+        let mut log_file = self.log_file.lock().unwrap();
+        let recycled_bytes = self.recycled_bytes.load(Ordering::Acquire);
+        let (records, end) = Self::read_records(&mut log_file, offset.saturating_sub(recycled_bytes), self.options.encryption.as_deref())?;
+        Ok((records, end + recycled_bytes))
+    }
+
+    // Keeps the records from `lsn` on, and any appended later, from being recycled until
+    // the hold is dropped, for readers that go through the log at their own pace. Records
+    // already recycled are gone for good.
+    pub fn hold(&self, lsn: Lsn) -> LogHold {
+        let lsn = Arc::new(AtomicU64::new(lsn));
+        let mut holds = self.holds.lock().unwrap();
+        holds.retain(|hold| hold.strong_count() > 0);
+        holds.push(Arc::downgrade(&lsn));
+        LogHold { lsn }
+    }
+
+    // Drops the records before `before_lsn` from the front of the log, as far as the last
+    // checkpoint and the holds let it: records recovery from the checkpoint reads are kept,
+    // as are those a hold is on, and so is the checkpoint itself, so the log never runs
+    // out of records to number the next one after. Returns how many records it dropped.
+    pub fn recycle(&self, before_lsn: Lsn) -> CrabDbResult<usize> {
+        let Some((checkpoint_lsn, redo_lsn)) = self.state.lock().unwrap().checkpoint else {
+            return Ok(0);
+        };
+        // Recovery starts from the last checkpoint on disk, so this one has to be there.
+        self.flush(checkpoint_lsn)?;
+        // Holds are not taken meanwhile, so none is placed on records about to go.
+        let holds = self.holds.lock().unwrap();
+        let keep_lsn = holds.iter()
+            .filter_map(Weak::upgrade)
+            .map(|lsn| lsn.load(Ordering::Acquire))
+            .fold(before_lsn.min(redo_lsn), Lsn::min);
+        let mut log_file = self.log_file.lock().unwrap();
+        let mut bytes = Vec::new();
+        log_file.seek(SeekFrom::Start(0))
+            .and_then(|_| log_file.read_to_end(&mut bytes))
+            .map_err(|e| CrabDBError::io("Failed to read log file".to_string(), e))?;
+        let (mut position, mut dropped) = (0, 0);
+        while let Some((lsn, size)) = LogRecord::peek(&bytes[position..]) {
+            if lsn >= keep_lsn {
+                break;
+            }
+            position += size;
+            dropped += 1;
+        }
+        if dropped == 0 {
+            return Ok(0);
+        }
+        log_file.replace(&bytes[position..])
+            .map_err(|e| CrabDBError::io("Failed to recycle log file".to_string(), e))?;
+        self.recycled_bytes.fetch_add(position as u64, Ordering::AcqRel);
+        drop(holds);
+        trace_event!(tracing::Level::DEBUG, keep_lsn, records = dropped, bytes = position, "recycled log");
+        Ok(dropped)
+    }
+
+    // Blocks until the log is flushed past `lsn`, or for at most `timeout`, and returns the
+    // flushed LSN.
+    pub fn wait_for_flush(&self, lsn: Lsn, timeout: Duration) -> Lsn {
+        let state = self.state.lock().unwrap();
+        let _state = self.flushed.wait_timeout_while(state, timeout, |_| self.flushed_lsn() <= lsn).unwrap();
+        self.flushed_lsn()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn fail_writes(&self, failing: bool) {
+        self.failing.store(failing, Ordering::SeqCst);
+    }
+
+    fn write_out(&self, buffer: &[u8], flush_lsn: Lsn) -> CrabDbResult<()> {
+        if buffer.is_empty() {
+            return Ok(());
+        }
+        #[cfg(test)]
+        if self.failing.load(Ordering::SeqCst) {
+            return Err(CrabDBError::io(format!("Failed to flush log up to LSN {flush_lsn}"), io::ErrorKind::Other.into()));
+        }
+        let mut log_file = self.log_file.lock().unwrap();
+        let started = Stopwatch::start();
+        let sync_latency = log_file.seek(SeekFrom::End(0))
+            .and_then(|_| log_file.write_all(buffer))
+            .and_then(|_| match self.options.sync_mode {
+                SyncMode::Full => {
+                    let syncing = Stopwatch::start();
+                    log_file.sync_data().map(|_| Some(syncing.elapsed()))
+                },
+                SyncMode::Off => Ok(None),
+            })
+            .map_err(|e| CrabDBError::io(format!("Failed to flush log up to LSN {flush_lsn}"), e))?;
+        self.metrics.record_flush(buffer.len(), started.elapsed());
+        if let Some(latency) = sync_latency {
+            if self.metrics.record_sync(latency) {
+                trace_event!(tracing::Level::WARN, flush_lsn, micros = latency.as_micros() as u64, "slow log sync");
+            }
+        }
+        trace_event!(tracing::Level::DEBUG, flush_lsn, bytes = buffer.len(), "wrote log");
+        Ok(())
+    }
+
+    // The intact records from byte `offset` of the log file on, and the offset just past
+    // them.
+    fn read_records(log_file: &mut LogFile, offset: u64, encryption: Option<&KeyRing>) -> CrabDbResult<(Vec<LogRecord>, u64)> {
+        let mut bytes = Vec::new();
+        log_file.seek(SeekFrom::Start(offset))
+            .and_then(|_| log_file.read_to_end(&mut bytes))
+            .map_err(|e| CrabDBError::io("Failed to read log file".to_string(), e))?;
+        let mut records = Vec::new();
+        let mut position = 0;
+        while let Some((record, size)) = LogRecord::deserialize_sealed(&bytes[position..], encryption)? {
+            records.push(record);
+            position += size;
+        }
+        Ok((records, offset + position as u64))
+    }
+}
+
+// Keeps `active_txns` up to date with `record`, the way analysis builds its table: a record
+// of a transaction other than its end makes it active, its Commit or Abort ends it.
+fn track_active_txn(active_txns: &mut HashMap<TxnId, (Lsn, Lsn)>, record: &LogRecord) {
+    match record.body {
+        LogRecordBody::Commit { .. } | LogRecordBody::Abort => {
+            active_txns.remove(&record.txn_id);
+        },
+        LogRecordBody::Clock { .. } | LogRecordBody::Checkpoint { .. } => (),
+        _ => active_txns.entry(record.txn_id).or_insert((record.lsn, record.lsn)).1 = record.lsn,
+    }
+}
+
+impl Drop for LogManager {
+    fn drop(&mut self) {
+        let state = self.state.get_mut().unwrap();
+        if !state.buffer.is_empty() {
+            let log_file = self.log_file.get_mut().unwrap();
+            let _ = log_file.seek(SeekFrom::End(0)).and_then(|_| log_file.write_all(&state.buffer));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::sync::{Arc, Barrier};
+    use std::time::Duration;
+
+    use tempfile::TempDir;
+
+    use crate::storage::encryption::KeyRing;
+    use crate::types::CrabDBError;
+    use crate::wal::common::{Lsn, INVALID_LSN};
+    use crate::wal::log_record::LogRecordBody;
+    use super::{LogManager, LogManagerOptions};
+
+    fn update(page_id: u64) -> LogRecordBody {
+        LogRecordBody::Update { page_id, offset: 64, before: vec![0; 8], after: vec![1; 8] }
+    }
+
+    #[test]
+    pub fn test_log_manager_commit_flushes() {
+        let dir = TempDir::new().unwrap();
+        let log_manager = LogManager::new(dir.path().join("test.log")).unwrap();
+
+        let begin = log_manager.append(1, INVALID_LSN, LogRecordBody::Begin).unwrap();
+        let update = log_manager.append(1, begin, update(0)).unwrap();
+        assert_eq!((1, 2), (begin, update));
+        assert_eq!(INVALID_LSN, log_manager.flushed_lsn());
+
+        let commit = log_manager.append(1, update, LogRecordBody::Commit { commit_ts: 0 }).unwrap();
+        assert_eq!(commit, log_manager.flushed_lsn());
+        let records = log_manager.records().unwrap();
+        assert_eq!(vec![1, 2, 3], records.iter().map(|record| record.lsn).collect::<Vec<_>>());
+        assert_eq!(update, records[2].prev_lsn);
+    }
+
+    #[test]
+    pub fn test_log_manager_flushes_when_buffer_is_full() {
+        let dir = TempDir::new().unwrap();
+        let log_manager = LogManager::with_buffer_size(dir.path().join("test.log"), 128).unwrap();
+
+        let mut last = INVALID_LSN;
+        for page_id in 0..10 {
+            last = log_manager.append(1, last, update(page_id)).unwrap();
+        }
+        // everything but the records still sitting in the buffer is on disk
+        assert!(log_manager.flushed_lsn() > INVALID_LSN);
+        assert!(log_manager.flushed_lsn() < last);
+        log_manager.flush(last).unwrap();
+        assert_eq!(last, log_manager.flushed_lsn());
+    }
+
+    #[test]
+    pub fn test_log_manager_reopen_drops_torn_tail() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.log");
+        {
+            let log_manager = LogManager::new(&path).unwrap();
+            log_manager.append(1, INVALID_LSN, LogRecordBody::Begin).unwrap();
+            log_manager.append(1, 1, LogRecordBody::Commit { commit_ts: 0 }).unwrap();
+        }
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(&[40, 0, 0, 0, 9]).unwrap();
+
+        let log_manager = LogManager::new(&path).unwrap();
+        assert_eq!(2, log_manager.flushed_lsn());
+        assert_eq!(3, log_manager.append(2, INVALID_LSN, LogRecordBody::Begin).unwrap());
+        assert_eq!(3, log_manager.records().unwrap().len());
+    }
+
+    #[test]
+    pub fn test_log_manager_group_commit_batches_flushes() {
+        let dir = TempDir::new().unwrap();
+        let options = LogManagerOptions { group_commit_delay: Duration::from_millis(20), ..Default::default() };
+        let log_manager = Arc::new(LogManager::with_options(dir.path().join("test.log"), options).unwrap());
+
+        let barrier = Arc::new(Barrier::new(8));
+        let handles: Vec<_> = (0..8u64).map(|txn_id| {
+            let log_manager = log_manager.clone();
+            let barrier = barrier.clone();
+            std::thread::spawn(move || {
+                let begin = log_manager.append(txn_id, INVALID_LSN, LogRecordBody::Begin).unwrap();
+                barrier.wait();
+                let commit = log_manager.append(txn_id, begin, LogRecordBody::Commit { commit_ts: 0 }).unwrap();
+                assert!(log_manager.flushed_lsn() >= commit);
+            })
+        }).collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert!(log_manager.num_flushes() < 8, "{} flushes for 8 commits", log_manager.num_flushes());
+        let metrics = log_manager.metrics_snapshot();
+        assert_eq!((log_manager.num_flushes(), 16), (metrics.flushes, metrics.flushed_lsn));
+        assert!(metrics.bytes_flushed > 0 && metrics.flush_nanos > 0);
+        assert_eq!((metrics.flushes, 0), (metrics.sync_latency.count(), metrics.slow_syncs));
+        assert_eq!(16, log_manager.records().unwrap().len());
+    }
+
+    #[test]
+    pub fn test_log_manager_seals_records() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.log");
+        let secret = b"ferris lives under the third rock".to_vec();
+        let body = LogRecordBody::Update { page_id: 3, offset: 64, before: vec![0; secret.len()], after: secret.clone() };
+        let options = LogManagerOptions { encryption: Some(Arc::new(KeyRing::new(1, [7; 32]))), ..Default::default() };
+        {
+            let log_manager = LogManager::with_options(&path, options.clone()).unwrap();
+            let begin = log_manager.append(1, INVALID_LSN, LogRecordBody::Begin).unwrap();
+            let update = log_manager.append(1, begin, body.clone()).unwrap();
+            log_manager.append(1, update, LogRecordBody::Commit { commit_ts: 0 }).unwrap();
+        }
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(!bytes.windows(secret.len()).any(|window| window == secret.as_slice()));
+
+        let log_manager = LogManager::with_options(&path, options).unwrap();
+        assert_eq!(3, log_manager.flushed_lsn());
+        assert_eq!(body, log_manager.records().unwrap()[1].body);
+        drop(log_manager);
+        assert_eq!(
+            "Log record 1 is encrypted but no encryption key is configured",
+            LogManager::new(&path).err().unwrap().to_string()
+        );
+        let wrong_key = LogManagerOptions { encryption: Some(Arc::new(KeyRing::new(1, [8; 32]))), ..Default::default() };
+        assert!(matches!(LogManager::with_options(&path, wrong_key).err().unwrap(), CrabDBError::Corruption(_)));
+    }
+
+    #[test]
+    pub fn test_log_manager_recycles_before_the_checkpoint_and_holds() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.log");
+        let log_manager = LogManager::new(&path).unwrap();
+        let begin_1 = log_manager.append(1, INVALID_LSN, LogRecordBody::Begin).unwrap();
+        let update_1 = log_manager.append(1, begin_1, update(0)).unwrap();
+        log_manager.append(1, update_1, LogRecordBody::Commit { commit_ts: 1 }).unwrap();
+        let begin_2 = log_manager.append(2, INVALID_LSN, LogRecordBody::Begin).unwrap();
+        let update_2 = log_manager.append(2, begin_2, update(1)).unwrap();
+        log_manager.flush(update_2).unwrap();
+        let (_, end) = log_manager.records_from(0).unwrap();
+        assert_eq!(0, log_manager.recycle(Lsn::MAX).unwrap());
+
+        // Recovery from the checkpoint reads from page 0's recLSN on, and txn 2 is active.
+        let hold = log_manager.hold(begin_1);
+        let (checkpoint, redo_lsn) = log_manager.append_checkpoint(1, log_manager.next_lsn(), vec![(0, update_1)]).unwrap();
+        assert_eq!(update_1, redo_lsn);
+        let records = log_manager.records().unwrap();
+        assert!(matches!(&records[5].body, LogRecordBody::Checkpoint { active_txns, .. } if *active_txns == vec![(2, update_2)]));
+        assert_eq!(0, log_manager.recycle(Lsn::MAX).unwrap());
+        hold.advance(update_2);
+        assert_eq!(1, log_manager.recycle(Lsn::MAX).unwrap());
+        assert_eq!(update_1, log_manager.records().unwrap()[0].lsn);
+        // Offsets stay valid across recycling.
+        let (records, _) = log_manager.records_from(end).unwrap();
+        assert_eq!(vec![checkpoint], records.iter().map(|record| record.lsn).collect::<Vec<_>>());
+
+        let commit_2 = log_manager.append(2, update_2, LogRecordBody::Commit { commit_ts: 2 }).unwrap();
+        let (checkpoint, redo_lsn) = log_manager.append_checkpoint(2, log_manager.next_lsn(), Vec::new()).unwrap();
+        assert_eq!(checkpoint, redo_lsn);
+        assert_eq!(3, log_manager.recycle(commit_2).unwrap());
+        drop(hold);
+        assert_eq!(3, log_manager.recycle(Lsn::MAX).unwrap());
+        assert_eq!(vec![checkpoint], log_manager.records().unwrap().iter().map(|record| record.lsn).collect::<Vec<_>>());
+        drop(log_manager);
+
+        let log_manager = LogManager::new(&path).unwrap();
+        assert_eq!(checkpoint + 1, log_manager.next_lsn());
+    }
+}
@@ -0,0 +1,519 @@
+use std::borrow::Cow;
+
+use crate::catalog::table_info::Oid;
+use crate::storage::common::{PageId, PAGE_SIZE};
+use crate::storage::encryption::KeyRing;
+use crate::storage::table::tuple::{Rid, Timestamp};
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::common::{Lsn, TxnId};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogRecordBody {
+    Begin,
+    // `commit_ts` stamps the versions the transaction wrote; 0 for system transactions,
+    // which write no versions.
+    Commit {
+        commit_ts: Timestamp,
+    },
+    Abort,
+    // Physical change of `before.len()` bytes at `offset` within the page.
+    Update {
+        page_id: PageId,
+        offset: u16,
+        before: Vec<u8>,
+        after: Vec<u8>,
+    },
+    NewPage {
+        page_id: PageId,
+    },
+    // Compensation record written while undoing an update: redo-only, and `undo_next_lsn`
+    // skips past the update it compensates so an interrupted rollback never repeats work.
+    Compensation {
+        page_id: PageId,
+        offset: u16,
+        after: Vec<u8>,
+        undo_next_lsn: Lsn,
+    },
+    // Wall-clock time, in microseconds since the Unix epoch, logged after commits while the
+    // log is archived so a restore can stop at a point in time.
+    Clock {
+        unix_micros: u64,
+    },
+    // A row of table `table` that a transaction changed, logged as it commits for change
+    // data capture; the transaction's Commit record follows. `after` is the row as the
+    // transaction left it, empty for a delete. Never redone or undone.
+    RowChange {
+        table: Oid,
+        rid: Rid,
+        kind: RowChangeKind,
+        after: Vec<u8>,
+    },
+    // A transaction's write to the tuple at `rid`: the runs of bytes of its page the write
+    // changed, by offset, as it left them, and what undoing it restores, the tuple's bytes
+    // and timestamp from before, or None if the write inserted it. Redo is physical, undo
+    // logical, since other writes may have moved the tuple within its page since.
+    TupleWrite {
+        rid: Rid,
+        changes: Vec<(u16, Vec<u8>)>,
+        prior: Option<(Timestamp, Vec<u8>)>,
+    },
+    // A fuzzy checkpoint, where recovery's analysis starts. It carries the newest commit
+    // timestamp handed out when it was logged, for a log that starts here, such as a
+    // backup's, to carry the timestamps of commits from before it; the pages the buffer
+    // pool held dirty, with their recLSNs; and the transactions that had logged records
+    // but not their end, with their last LSNs. The tables were taken while the log went on,
+    // so analysis reads them together with the records from `begin_lsn` on.
+    Checkpoint {
+        last_commit_ts: Timestamp,
+        begin_lsn: Lsn,
+        dirty_pages: Vec<(PageId, Lsn)>,
+        active_txns: Vec<(TxnId, Lsn)>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RowChangeKind {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl LogRecordBody {
+    fn tag(&self) -> u8 {
+        match self {
+            LogRecordBody::Begin => 1,
+            LogRecordBody::Commit { .. } => 2,
+            LogRecordBody::Abort => 3,
+            LogRecordBody::Update { .. } => 4,
+            LogRecordBody::NewPage { .. } => 5,
+            LogRecordBody::Compensation { .. } => 6,
+            LogRecordBody::Clock { .. } => 7,
+            LogRecordBody::RowChange { .. } => 8,
+            LogRecordBody::TupleWrite { .. } => 9,
+            LogRecordBody::Checkpoint { .. } => 10,
+        }
+    }
+
+    // An update of page `page_id` from `before` to `after`, over the bytes from the first to
+    // the last that differ. None if none do.
+    pub fn update(page_id: PageId, before: &[u8; PAGE_SIZE], after: &[u8; PAGE_SIZE]) -> Option<Self> {
+        let (start, end) = changed_range(before, after)?;
+        Some(LogRecordBody::Update {
+            page_id,
+            offset: start as u16,
+            before: before[start..end].to_vec(),
+            after: after[start..end].to_vec(),
+        })
+    }
+
+    // A compensation record taking page `page_id` from `before` to `after`, over the bytes
+    // from the first to the last that differ. None if none do.
+    pub fn compensation(page_id: PageId, before: &[u8; PAGE_SIZE], after: &[u8; PAGE_SIZE], undo_next_lsn: Lsn) -> Option<Self> {
+        let (start, end) = changed_range(before, after)?;
+        Some(LogRecordBody::Compensation { page_id, offset: start as u16, after: after[start..end].to_vec(), undo_next_lsn })
+    }
+
+    // A write to the tuple at `rid` that took its page from `before` to `after`.
+    pub fn tuple_write(rid: Rid, before: &[u8; PAGE_SIZE], after: &[u8; PAGE_SIZE], prior: Option<(Timestamp, Vec<u8>)>) -> Self {
+        LogRecordBody::TupleWrite { rid, changes: changed_runs(before, after), prior }
+    }
+}
+
+// Runs of changed bytes closer than this are logged as one.
+const MAX_RUN_GAP: usize = 16;
+
+fn changed_range(before: &[u8], after: &[u8]) -> Option<(usize, usize)> {
+    let start = before.iter().zip(after).position(|(a, b)| a != b)?;
+    let end = before.len() - before.iter().rev().zip(after.iter().rev()).position(|(a, b)| a != b).unwrap();
+    Some((start, end))
+}
+
+// The runs of bytes that differ between `before` and `after`, by offset, as in `after`.
+fn changed_runs(before: &[u8], after: &[u8]) -> Vec<(u16, Vec<u8>)> {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for (i, _) in before.iter().zip(after).enumerate().filter(|(_, (a, b))| a != b) {
+        match runs.last_mut() {
+            Some((_, end)) if i - *end < MAX_RUN_GAP => *end = i + 1,
+            _ => runs.push((i, i + 1)),
+        }
+    }
+    runs.into_iter().map(|(start, end)| (start as u16, after[start..end].to_vec())).collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    pub lsn: Lsn,
+    // Previous record of the same transaction, so it can be undone by walking backwards.
+    pub prev_lsn: Lsn,
+    pub txn_id: TxnId,
+    pub body: LogRecordBody,
+}
+
+// Serialized layout, all integers little endian:
+//   size u32 | lsn u64 | prev_lsn u64 | txn_id u64 | tag u8 | body | crc32c u32
+// `size` covers the whole record and the CRC covers everything before it, so a torn
+// record at the tail of the log is recognised and ignored. In an encrypted log the body is
+// sealed (see `KeyRing::seal`) with the header from the LSN to the tag as associated data,
+// and the tag has the SEALED bit set.
+const FIXED_HEADER_SIZE: usize = 4 + 8 + 8 + 8 + 1;
+const CRC_SIZE: usize = 4;
+const SEALED: u8 = 0x80;
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> CrabDbResult<&'a [u8]> {
+        let bytes = self.bytes.get(self.position..self.position + len)
+            .ok_or_else(|| CrabDBError::Corruption("Log record body is truncated".into()))?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> CrabDbResult<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> CrabDbResult<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> CrabDbResult<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+impl LogRecord {
+    pub fn new(lsn: Lsn, prev_lsn: Lsn, txn_id: TxnId, body: LogRecordBody) -> Self {
+        LogRecord { lsn, prev_lsn, txn_id, body }
+    }
+
+    pub fn serialize(&self, out: &mut Vec<u8>) {
+        self.serialize_sealed(out, None);
+    }
+
+    // Serializes the record with its body sealed by `encryption`, if given.
+    pub fn serialize_sealed(&self, out: &mut Vec<u8>, encryption: Option<&KeyRing>) {
+        let start = out.len();
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&self.lsn.to_le_bytes());
+        out.extend_from_slice(&self.prev_lsn.to_le_bytes());
+        out.extend_from_slice(&self.txn_id.to_le_bytes());
+        out.push(self.body.tag());
+        match &self.body {
+            LogRecordBody::Begin | LogRecordBody::Abort => (),
+            LogRecordBody::Commit { commit_ts } => out.extend_from_slice(&commit_ts.to_le_bytes()),
+            LogRecordBody::Update { page_id, offset, before, after } => {
+                out.extend_from_slice(&page_id.to_le_bytes());
+                out.extend_from_slice(&offset.to_le_bytes());
+                out.extend_from_slice(&(before.len() as u16).to_le_bytes());
+                out.extend_from_slice(before);
+                out.extend_from_slice(after);
+            },
+            LogRecordBody::NewPage { page_id } => out.extend_from_slice(&page_id.to_le_bytes()),
+            LogRecordBody::Compensation { page_id, offset, after, undo_next_lsn } => {
+                out.extend_from_slice(&page_id.to_le_bytes());
+                out.extend_from_slice(&offset.to_le_bytes());
+                out.extend_from_slice(&(after.len() as u16).to_le_bytes());
+                out.extend_from_slice(after);
+                out.extend_from_slice(&undo_next_lsn.to_le_bytes());
+            },
+            LogRecordBody::Clock { unix_micros } => out.extend_from_slice(&unix_micros.to_le_bytes()),
+            LogRecordBody::RowChange { table, rid, kind, after } => {
+                out.extend_from_slice(&table.to_le_bytes());
+                out.extend_from_slice(&rid.page_id().to_le_bytes());
+                out.extend_from_slice(&rid.slot_id().to_le_bytes());
+                out.push(match kind {
+                    RowChangeKind::Insert => 1,
+                    RowChangeKind::Update => 2,
+                    RowChangeKind::Delete => 3,
+                });
+                out.extend_from_slice(&(after.len() as u32).to_le_bytes());
+                out.extend_from_slice(after);
+            },
+            LogRecordBody::TupleWrite { rid, changes, prior } => {
+                out.extend_from_slice(&rid.page_id().to_le_bytes());
+                out.extend_from_slice(&rid.slot_id().to_le_bytes());
+                out.extend_from_slice(&(changes.len() as u16).to_le_bytes());
+                for (offset, bytes) in changes {
+                    out.extend_from_slice(&offset.to_le_bytes());
+                    out.extend_from_slice(&(bytes.len() as u16).to_le_bytes());
+                    out.extend_from_slice(bytes);
+                }
+                match prior {
+                    Some((ts, tuple)) => {
+                        out.push(1);
+                        out.extend_from_slice(&ts.to_le_bytes());
+                        out.extend_from_slice(&(tuple.len() as u16).to_le_bytes());
+                        out.extend_from_slice(tuple);
+                    },
+                    None => out.push(0),
+                }
+            },
+            LogRecordBody::Checkpoint { last_commit_ts, begin_lsn, dirty_pages, active_txns } => {
+                out.extend_from_slice(&last_commit_ts.to_le_bytes());
+                out.extend_from_slice(&begin_lsn.to_le_bytes());
+                for table in [dirty_pages, active_txns] {
+                    out.extend_from_slice(&(table.len() as u32).to_le_bytes());
+                    for (id, lsn) in table {
+                        out.extend_from_slice(&id.to_le_bytes());
+                        out.extend_from_slice(&lsn.to_le_bytes());
+                    }
+                }
+            },
+        }
+        if let Some(key_ring) = encryption {
+            let body_start = start + FIXED_HEADER_SIZE;
+            out[body_start - 1] |= SEALED;
+            let sealed = key_ring.seal(&out[start + 4..body_start], &out[body_start..]);
+            out.truncate(body_start);
+            out.extend_from_slice(&sealed);
+        }
+        let size = (out.len() - start + CRC_SIZE) as u32;
+        out[start..start + 4].copy_from_slice(&size.to_le_bytes());
+        let crc = crc32c::crc32c(&out[start..]);
+        out.extend_from_slice(&crc.to_le_bytes());
+    }
+
+    pub fn serialized_size(&self) -> usize {
+        let body = match &self.body {
+            LogRecordBody::Begin | LogRecordBody::Abort => 0,
+            LogRecordBody::Commit { .. } => 8,
+            LogRecordBody::Checkpoint { dirty_pages, active_txns, .. } => 8 + 8 + 4 + 16 * dirty_pages.len() + 4 + 16 * active_txns.len(),
+            LogRecordBody::Update { before, after, .. } => 8 + 2 + 2 + before.len() + after.len(),
+            LogRecordBody::NewPage { .. } => 8,
+            LogRecordBody::Compensation { after, .. } => 8 + 2 + 2 + after.len() + 8,
+            LogRecordBody::Clock { .. } => 8,
+            LogRecordBody::RowChange { after, .. } => 4 + 8 + 2 + 1 + 4 + after.len(),
+            LogRecordBody::TupleWrite { changes, prior, .. } => {
+                8 + 2 + 2 + changes.iter().map(|(_, bytes)| 2 + 2 + bytes.len()).sum::<usize>()
+                    + 1 + prior.as_ref().map_or(0, |(_, tuple)| 8 + 2 + tuple.len())
+            },
+        };
+        FIXED_HEADER_SIZE + body + CRC_SIZE
+    }
+
+    // Returns the record at the start of `bytes` and how many bytes it used, or None if
+    // `bytes` ends before a complete, intact record does.
+    pub fn deserialize(bytes: &[u8]) -> CrabDbResult<Option<(LogRecord, usize)>> {
+        Self::deserialize_sealed(bytes, None)
+    }
+
+    // The LSN and size of the record at the start of `bytes`, from its header alone, for
+    // stepping over records already known to be intact without opening them.
+    pub(crate) fn peek(bytes: &[u8]) -> Option<(Lsn, usize)> {
+        if bytes.len() < FIXED_HEADER_SIZE {
+            return None;
+        }
+        let size = u32::from_le_bytes(bytes[..4].try_into().unwrap()) as usize;
+        if size < FIXED_HEADER_SIZE + CRC_SIZE || bytes.len() < size {
+            return None;
+        }
+        Some((Lsn::from_le_bytes(bytes[4..12].try_into().unwrap()), size))
+    }
+
+    // Like `deserialize`, opening a sealed body with `encryption`; a sealed record is an
+    // error without it.
+    pub fn deserialize_sealed(bytes: &[u8], encryption: Option<&KeyRing>) -> CrabDbResult<Option<(LogRecord, usize)>> {
+        if bytes.len() < 4 {
+            return Ok(None);
+        }
+        let size = u32::from_le_bytes(bytes[..4].try_into().unwrap()) as usize;
+        if size < FIXED_HEADER_SIZE + CRC_SIZE || bytes.len() < size {
+            return Ok(None);
+        }
+        let stored_crc = u32::from_le_bytes(bytes[size - CRC_SIZE..size].try_into().unwrap());
+        if crc32c::crc32c(&bytes[..size - CRC_SIZE]) != stored_crc {
+            return Ok(None);
+        }
+
+        let mut reader = Reader { bytes: &bytes[..size - CRC_SIZE], position: 4 };
+        let lsn = reader.u64()?;
+        let prev_lsn = reader.u64()?;
+        let txn_id = reader.u64()?;
+        let tag = reader.take(1)?[0];
+        let body = match tag & SEALED {
+            0 => Cow::Borrowed(&bytes[FIXED_HEADER_SIZE..size - CRC_SIZE]),
+            _ => {
+                let key_ring = encryption.ok_or_else(|| {
+                    CrabDBError::InvalidInput(format!("Log record {lsn} is encrypted but no encryption key is configured"))
+                })?;
+                Cow::Owned(key_ring.open(&bytes[4..FIXED_HEADER_SIZE], &bytes[FIXED_HEADER_SIZE..size - CRC_SIZE])?)
+            },
+        };
+        let mut reader = Reader { bytes: &body, position: 0 };
+        let body = match tag & !SEALED {
+            1 => LogRecordBody::Begin,
+            2 => LogRecordBody::Commit { commit_ts: reader.u64()? },
+            3 => LogRecordBody::Abort,
+            4 => {
+                let page_id = reader.u64()?;
+                let offset = reader.u16()?;
+                let len = reader.u16()? as usize;
+                let before = reader.take(len)?.to_vec();
+                let after = reader.take(len)?.to_vec();
+                LogRecordBody::Update { page_id, offset, before, after }
+            },
+            5 => LogRecordBody::NewPage { page_id: reader.u64()? },
+            6 => {
+                let page_id = reader.u64()?;
+                let offset = reader.u16()?;
+                let len = reader.u16()? as usize;
+                let after = reader.take(len)?.to_vec();
+                let undo_next_lsn = reader.u64()?;
+                LogRecordBody::Compensation { page_id, offset, after, undo_next_lsn }
+            },
+            7 => LogRecordBody::Clock { unix_micros: reader.u64()? },
+            8 => {
+                let table = reader.u32()?;
+                let rid = Rid::new(reader.u64()?, reader.u16()?);
+                let kind = match reader.take(1)?[0] {
+                    1 => RowChangeKind::Insert,
+                    2 => RowChangeKind::Update,
+                    3 => RowChangeKind::Delete,
+                    kind => return Err(CrabDBError::Corruption(format!("Unknown row change type {kind} at LSN {lsn}"))),
+                };
+                let len = reader.u32()? as usize;
+                let after = reader.take(len)?.to_vec();
+                LogRecordBody::RowChange { table, rid, kind, after }
+            },
+            9 => {
+                let rid = Rid::new(reader.u64()?, reader.u16()?);
+                let changes = (0..reader.u16()?)
+                    .map(|_| {
+                        let offset = reader.u16()?;
+                        let len = reader.u16()? as usize;
+                        Ok((offset, reader.take(len)?.to_vec()))
+                    })
+                    .collect::<CrabDbResult<_>>()?;
+                let prior = match reader.take(1)?[0] {
+                    0 => None,
+                    _ => {
+                        let ts = reader.u64()?;
+                        let len = reader.u16()? as usize;
+                        Some((ts, reader.take(len)?.to_vec()))
+                    },
+                };
+                LogRecordBody::TupleWrite { rid, changes, prior }
+            },
+            10 => {
+                let last_commit_ts = reader.u64()?;
+                let begin_lsn = reader.u64()?;
+                let mut table = || (0..reader.u32()?).map(|_| Ok((reader.u64()?, reader.u64()?))).collect::<CrabDbResult<Vec<_>>>();
+                let dirty_pages = table()?;
+                let active_txns = table()?;
+                LogRecordBody::Checkpoint { last_commit_ts, begin_lsn, dirty_pages, active_txns }
+            },
+            tag => return Err(CrabDBError::Corruption(format!("Unknown log record type {tag} at LSN {lsn}"))),
+        };
+        Ok(Some((LogRecord { lsn, prev_lsn, txn_id, body }, size)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::common::PAGE_SIZE;
+    use crate::storage::encryption::KeyRing;
+    use crate::storage::table::tuple::Rid;
+    use super::{LogRecord, LogRecordBody, RowChangeKind};
+
+    #[test]
+    pub fn test_log_record_roundtrip() {
+        let records = vec![
+            LogRecord::new(1, 0, 7, LogRecordBody::Begin),
+            LogRecord::new(2, 1, 7, LogRecordBody::NewPage { page_id: 3 }),
+            LogRecord::new(3, 2, 7, LogRecordBody::Update {
+                page_id: 3,
+                offset: 100,
+                before: vec![0, 0, 0],
+                after: vec![1, 2, 3],
+            }),
+            LogRecord::new(4, 3, 7, LogRecordBody::Compensation {
+                page_id: 3,
+                offset: 100,
+                after: vec![0, 0, 0],
+                undo_next_lsn: 2,
+            }),
+            LogRecord::new(5, 4, 7, LogRecordBody::Abort),
+            LogRecord::new(6, 0, 8, LogRecordBody::Clock { unix_micros: 1_700_000_000_000_000 }),
+            LogRecord::new(7, 0, 9, LogRecordBody::RowChange {
+                table: 2,
+                rid: Rid::new(3, 4),
+                kind: RowChangeKind::Update,
+                after: vec![5, 6],
+            }),
+            LogRecord::new(8, 0, 9, LogRecordBody::TupleWrite {
+                rid: Rid::new(3, 5),
+                changes: vec![(40, vec![1, 2]), (4000, vec![3])],
+                prior: Some((12, vec![4, 5, 6])),
+            }),
+            LogRecord::new(9, 8, 9, LogRecordBody::TupleWrite { rid: Rid::new(3, 6), changes: Vec::new(), prior: None }),
+            LogRecord::new(10, 9, 9, LogRecordBody::Commit { commit_ts: 42 }),
+            LogRecord::new(11, 0, 0, LogRecordBody::Checkpoint {
+                last_commit_ts: 42,
+                begin_lsn: 9,
+                dirty_pages: vec![(3, 2), (5, 8)],
+                active_txns: vec![(9, 10)],
+            }),
+        ];
+        let mut bytes = Vec::new();
+        for record in &records {
+            let before = bytes.len();
+            record.serialize(&mut bytes);
+            assert_eq!(record.serialized_size(), bytes.len() - before);
+        }
+
+        let mut position = 0;
+        for record in &records {
+            let (decoded, size) = LogRecord::deserialize(&bytes[position..]).unwrap().unwrap();
+            assert_eq!(*record, decoded);
+            position += size;
+        }
+        assert!(LogRecord::deserialize(&bytes[position..]).unwrap().is_none());
+
+        let key_ring = KeyRing::new(1, [3; 32]);
+        let mut bytes = Vec::new();
+        for record in &records {
+            record.serialize_sealed(&mut bytes, Some(&key_ring));
+        }
+        let mut position = 0;
+        for record in &records {
+            let (decoded, size) = LogRecord::deserialize_sealed(&bytes[position..], Some(&key_ring)).unwrap().unwrap();
+            assert_eq!(*record, decoded);
+            position += size;
+        }
+        assert_eq!(bytes.len(), position);
+    }
+
+    #[test]
+    pub fn test_log_record_bodies_from_page_changes() {
+        let before = [0u8; PAGE_SIZE];
+        let mut after = before;
+        after[100] = 1;
+        after[110] = 2;
+        after[4000] = 3;
+        assert_eq!(None, LogRecordBody::update(7, &before, &before));
+        assert_eq!(
+            Some(LogRecordBody::Update { page_id: 7, offset: 100, before: vec![0; 3901], after: after[100..4001].to_vec() }),
+            LogRecordBody::update(7, &before, &after),
+        );
+        // runs a few bytes apart are logged as one
+        let LogRecordBody::TupleWrite { changes, .. } = LogRecordBody::tuple_write(Rid::new(7, 0), &before, &after, None) else {
+            unreachable!();
+        };
+        assert_eq!(vec![(100, after[100..111].to_vec()), (4000, vec![3])], changes);
+    }
+
+    #[test]
+    pub fn test_log_record_torn_tail_is_ignored() {
+        let mut bytes = Vec::new();
+        LogRecord::new(1, 0, 1, LogRecordBody::Abort).serialize(&mut bytes);
+        assert!(LogRecord::deserialize(&bytes[..bytes.len() - 1]).unwrap().is_none());
+        let last = bytes.len() - 5;
+        bytes[last] ^= 0xff;
+        assert!(LogRecord::deserialize(&bytes).unwrap().is_none());
+    }
+}
@@ -0,0 +1,6 @@
+pub mod archive;
+pub mod checkpointer;
+pub mod common;
+pub mod log_manager;
+pub mod log_record;
+pub mod recovery_manager;
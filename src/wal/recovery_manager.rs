@@ -0,0 +1,463 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+
+use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+use crate::concurrency::mvcc::{UndoVersion, VersionStore};
+use crate::concurrency::transaction::TXN_TS_FLAG;
+use crate::storage::common::{PageId, PAGE_SIZE};
+use crate::storage::page::table_page::TablePage;
+use crate::storage::table::tuple::{Rid, Timestamp, Tuple};
+use crate::types::{CrabDBError, CrabDbResult};
+
+use super::common::{Lsn, TxnId, INVALID_LSN};
+use super::log_manager::LogManager;
+use super::log_record::{LogRecord, LogRecordBody};
+
+#[derive(Debug, Default)]
+pub struct RecoverySummary {
+    redone_records: usize,
+    rolled_back_txns: Vec<TxnId>,
+    last_commit_ts: Timestamp,
+}
+
+impl RecoverySummary {
+    pub fn redone_records(&self) -> usize {
+        self.redone_records
+    }
+
+    pub fn rolled_back_txns(&self) -> &[TxnId] {
+        &self.rolled_back_txns
+    }
+
+    // The newest commit timestamp the log records, in a commit or a checkpoint; every
+    // version left in the database is stamped no later.
+    pub fn last_commit_ts(&self) -> Timestamp {
+        self.last_commit_ts
+    }
+}
+
+// ARIES restart recovery: analysis rebuilds the active transaction table and the dirty page
+// table from the last checkpoint on, redo repeats history from the oldest recLSN, and undo
+// rolls back every transaction that never committed or finished aborting, logging a
+// compensation record per undone update. Tuple writes are undone logically, by putting back the version they
+// replaced, the rest physically. Running it again after a crash mid-recovery is safe.
+pub struct RecoveryManager {
+    bpm: Arc<BufferPoolManager>,
+    log_manager: Arc<LogManager>,
+}
+
+struct AnalysisResult {
+    // txn id -> LSN of its last record
+    active_txns: HashMap<TxnId, Lsn>,
+    // page id -> recLSN, the first record that may have dirtied the page since it was flushed
+    dirty_pages: HashMap<PageId, Lsn>,
+    last_commit_ts: Timestamp,
+}
+
+impl RecoveryManager {
+    pub fn new(bpm: Arc<BufferPoolManager>, log_manager: Arc<LogManager>) -> Self {
+        RecoveryManager { bpm, log_manager }
+    }
+
+    pub fn recover(&self) -> CrabDbResult<RecoverySummary> {
+        let records = self.log_manager.records()?;
+        let analysis = Self::analyze(&records, true);
+        let redone_records = self.redo(&records, &analysis.dirty_pages)?;
+
+        let by_lsn: HashMap<Lsn, &LogRecord> = records.iter().map(|record| (record.lsn, record)).collect();
+        let mut rolled_back_txns = self.undo(&by_lsn, analysis.active_txns)?;
+        rolled_back_txns.sort();
+
+        self.log_manager.flush(self.log_manager.next_lsn() - 1)?;
+        self.bpm.flush_all_pages()?;
+        Ok(RecoverySummary { redone_records, rolled_back_txns, last_commit_ts: analysis.last_commit_ts })
+    }
+
+    // Redoes `records`, log a primary shipped to this replica, on top of pages that already
+    // reflect the log before them. Nothing is undone: transactions still running on the
+    // primary are not losers, and a replica never logs anything of its own. The committed
+    // versions their writes replaced go into `versions`, for snapshots to read past the
+    // writes, and `open_writes` remembers them by transaction, across calls, until the
+    // transaction's commit or abort is replayed.
+    pub(crate) fn replay(&self, records: &[LogRecord], versions: &VersionStore, open_writes: &mut HashMap<TxnId, Vec<Rid>>) -> CrabDbResult<usize> {
+        for record in records {
+            match &record.body {
+                LogRecordBody::TupleWrite { rid, prior: Some((ts, tuple)), .. } if ts & TXN_TS_FLAG == 0 => {
+                    versions.push(*rid, UndoVersion { ts: *ts, tuple: Tuple::new(tuple.clone()) });
+                    open_writes.entry(record.txn_id).or_default().push(*rid);
+                },
+                // Once stamped or rolled back the head is committed, and the only version a
+                // replica's snapshots read.
+                LogRecordBody::Commit { .. } | LogRecordBody::Abort => {
+                    for rid in open_writes.remove(&record.txn_id).unwrap_or_default() {
+                        versions.discard(rid);
+                    }
+                },
+                _ => (),
+            }
+        }
+        // The primary's checkpoints describe its buffer pool, not this one.
+        let analysis = Self::analyze(records, false);
+        self.redo(records, &analysis.dirty_pages)
+    }
+
+    // Rewrites the checkpoints in `records` from `from` on, log archived after a backup, to
+    // describe the restored pages rather than the pool they were taken in: none of those
+    // pages has been written back since the backup, so everything analysis finds dirty is.
+    pub(crate) fn restate_checkpoints(records: &mut [LogRecord], from: usize) {
+        for i in from..records.len() {
+            if !matches!(records[i].body, LogRecordBody::Checkpoint { .. }) {
+                continue;
+            }
+            let analysis = Self::analyze(&records[..i], true);
+            let lsn = records[i].lsn;
+            if let LogRecordBody::Checkpoint { begin_lsn, dirty_pages, active_txns, .. } = &mut records[i].body {
+                *begin_lsn = lsn;
+                *dirty_pages = analysis.dirty_pages.into_iter().collect();
+                dirty_pages.sort_unstable();
+                *active_txns = analysis.active_txns.into_iter().collect();
+                active_txns.sort_unstable();
+            }
+        }
+    }
+
+    // With `from_checkpoint` the tables start out as the last checkpoint in `records` left
+    // them: the transaction table is exact as of the checkpoint, the dirty page table misses
+    // pages dirtied while it was taken, so records from its `begin_lsn` on are read into it.
+    fn analyze(records: &[LogRecord], from_checkpoint: bool) -> AnalysisResult {
+        let mut active_txns = HashMap::new();
+        let mut dirty_pages = HashMap::new();
+        let (mut checkpoint_lsn, mut begin_lsn) = (INVALID_LSN, INVALID_LSN);
+        let checkpoint = records.iter().rev().filter(|_| from_checkpoint).find(|record| matches!(record.body, LogRecordBody::Checkpoint { .. }));
+        if let Some(LogRecord { lsn, body: LogRecordBody::Checkpoint { begin_lsn: begin, dirty_pages: pages, active_txns: txns, .. }, .. }) = checkpoint {
+            active_txns.extend(txns.iter().copied());
+            dirty_pages.extend(pages.iter().copied());
+            (checkpoint_lsn, begin_lsn) = (*lsn, *begin);
+        }
+        let mut last_commit_ts = 0;
+        for record in records {
+            match &record.body {
+                LogRecordBody::Commit { commit_ts } => last_commit_ts = last_commit_ts.max(*commit_ts),
+                LogRecordBody::Checkpoint { last_commit_ts: checkpoint_ts, .. } => last_commit_ts = last_commit_ts.max(*checkpoint_ts),
+                _ => (),
+            }
+            if record.lsn >= begin_lsn {
+                match &record.body {
+                    LogRecordBody::Update { page_id, .. }
+                    | LogRecordBody::Compensation { page_id, .. }
+                    | LogRecordBody::NewPage { page_id } => {
+                        dirty_pages.entry(*page_id).or_insert(record.lsn);
+                    },
+                    LogRecordBody::TupleWrite { rid, .. } => {
+                        dirty_pages.entry(rid.page_id()).or_insert(record.lsn);
+                    },
+                    _ => (),
+                }
+            }
+            if record.lsn > checkpoint_lsn {
+                match &record.body {
+                    LogRecordBody::Commit { .. } | LogRecordBody::Abort => {
+                        active_txns.remove(&record.txn_id);
+                    },
+                    LogRecordBody::Clock { .. } | LogRecordBody::Checkpoint { .. } => (),
+                    // A transaction that logged its changes but not its commit is rolled back.
+                    _ => {
+                        active_txns.insert(record.txn_id, record.lsn);
+                    },
+                }
+            }
+        }
+        AnalysisResult { active_txns, dirty_pages, last_commit_ts }
+    }
+
+    fn redo(&self, records: &[LogRecord], dirty_pages: &HashMap<PageId, Lsn>) -> CrabDbResult<usize> {
+        let start_lsn = match dirty_pages.values().min() {
+            Some(&lsn) => lsn,
+            None => return Ok(0),
+        };
+        let mut redone = 0;
+        for record in records.iter().filter(|record| record.lsn >= start_lsn) {
+            let (page_id, changes) = match &record.body {
+                LogRecordBody::Update { page_id, offset, after, .. }
+                | LogRecordBody::Compensation { page_id, offset, after, .. } => (*page_id, vec![(*offset, after.as_slice())]),
+                LogRecordBody::TupleWrite { rid, changes, .. } => {
+                    (rid.page_id(), changes.iter().map(|(offset, bytes)| (*offset, bytes.as_slice())).collect())
+                },
+                LogRecordBody::NewPage { page_id } => {
+                    self.ensure_allocated(*page_id)?;
+                    continue;
+                },
+                _ => continue,
+            };
+            if dirty_pages.get(&page_id).is_none_or(|&rec_lsn| record.lsn < rec_lsn) {
+                continue;
+            }
+            self.ensure_allocated(page_id)?;
+            let applied = self.apply(page_id, record.lsn, true, |data| {
+                for (offset, bytes) in changes {
+                    let offset = offset as usize;
+                    data[offset..offset + bytes.len()].copy_from_slice(bytes);
+                }
+                Ok(())
+            })?;
+            if applied {
+                redone += 1;
+            }
+        }
+        Ok(redone)
+    }
+
+    fn undo(&self, by_lsn: &HashMap<Lsn, &LogRecord>, mut last_lsns: HashMap<TxnId, Lsn>) -> CrabDbResult<Vec<TxnId>> {
+        let losers: Vec<TxnId> = last_lsns.keys().copied().collect();
+        let mut to_undo: BTreeSet<(Lsn, TxnId)> = last_lsns.iter().map(|(&txn_id, &lsn)| (lsn, txn_id)).collect();
+
+        // Always undo the newest outstanding record first, across all losers.
+        while let Some((lsn, txn_id)) = to_undo.pop_last() {
+            let record = by_lsn[&lsn];
+            let next_lsn = match &record.body {
+                LogRecordBody::Update { page_id, offset, before, .. } => {
+                    let clr_lsn = self.log_manager.append(txn_id, last_lsns[&txn_id], LogRecordBody::Compensation {
+                        page_id: *page_id,
+                        offset: *offset,
+                        after: before.clone(),
+                        undo_next_lsn: record.prev_lsn,
+                    })?;
+                    last_lsns.insert(txn_id, clr_lsn);
+                    self.apply(*page_id, clr_lsn, false, |data| {
+                        let offset = *offset as usize;
+                        data[offset..offset + before.len()].copy_from_slice(before);
+                        Ok(())
+                    })?;
+                    record.prev_lsn
+                },
+                LogRecordBody::TupleWrite { rid, prior, .. } => {
+                    let mut data = Box::new(self.read(rid.page_id())?);
+                    let before = data.clone();
+                    let prior = prior.as_ref().map(|(ts, tuple)| (*ts, tuple.as_slice()));
+                    if !TablePage::new(&mut *data).restore_tuple(rid.slot_id(), prior)? {
+                        return Err(CrabDBError::Corruption(format!("Cannot roll back row {rid}: its page is full")));
+                    }
+                    if let Some(clr) = LogRecordBody::compensation(rid.page_id(), &before, &data, record.prev_lsn) {
+                        let clr_lsn = self.log_manager.append(txn_id, last_lsns[&txn_id], clr)?;
+                        last_lsns.insert(txn_id, clr_lsn);
+                        self.apply(rid.page_id(), clr_lsn, false, |page| {
+                            *page = *data;
+                            Ok(())
+                        })?;
+                    }
+                    record.prev_lsn
+                },
+                LogRecordBody::Compensation { undo_next_lsn, .. } => *undo_next_lsn,
+                _ => record.prev_lsn,
+            };
+            if next_lsn == INVALID_LSN {
+                self.log_manager.append(txn_id, last_lsns[&txn_id], LogRecordBody::Abort)?;
+            } else {
+                to_undo.insert((next_lsn, txn_id));
+            }
+        }
+        Ok(losers)
+    }
+
+    // Changes the page with `change` and stamps it with `lsn`. During redo a page that
+    // already reflects the record (page LSN at or past it) is left alone.
+    fn apply(&self, page_id: PageId, lsn: Lsn, is_redo: bool, change: impl FnOnce(&mut [u8; PAGE_SIZE]) -> CrabDbResult<()>) -> CrabDbResult<bool> {
+        let mut page = self.bpm.fetch_page_write(page_id)?;
+        let applied = !is_redo || page.page().page_lsn() < lsn;
+        if applied {
+            change(&mut page)?;
+            page.page().set_page_lsn(lsn);
+        }
+        Ok(applied)
+    }
+
+    fn read(&self, page_id: PageId) -> CrabDbResult<[u8; PAGE_SIZE]> {
+        Ok(*self.bpm.fetch_page_read(page_id)?)
+    }
+
+    fn ensure_allocated(&self, page_id: PageId) -> CrabDbResult<()> {
+        let disk_manager = self.bpm.disk_scheduler().disk_manager();
+        while disk_manager.num_pages() <= page_id {
+            disk_manager.allocate_page()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tempfile::TempDir;
+
+    use crate::buffer_pool::buffer_pool_manager::BufferPoolManager;
+    use crate::buffer_pool::eviction::lru_k::lru_k_replacer::LRUKReplacer;
+    use crate::concurrency::lock_manager::LockManager;
+    use crate::concurrency::transaction::IsolationLevel;
+    use crate::concurrency::transaction_manager::TransactionManager;
+    use crate::storage::common::PAGE_HEADER_SIZE;
+    use crate::storage::disk::disk_scheduler::DiskScheduler;
+    use crate::storage::disk::file_disk_manager::FileDiskManager;
+    use crate::storage::table::table_heap::TableHeap;
+    use crate::storage::table::tuple::Tuple;
+    use crate::wal::common::{Lsn, TxnId, INVALID_LSN};
+    use crate::wal::log_manager::LogManager;
+    use crate::wal::log_record::LogRecordBody;
+    use super::RecoveryManager;
+
+    const OFFSET: usize = PAGE_HEADER_SIZE;
+
+    fn open(dir: &TempDir) -> (Arc<BufferPoolManager>, Arc<LogManager>) {
+        let disk_manager = Arc::new(FileDiskManager::new(dir.path().join("test.db")).unwrap());
+        let log_manager = Arc::new(LogManager::new(dir.path().join("test.log")).unwrap());
+        let bpm = Arc::new(BufferPoolManager::with_log_manager(
+            4,
+            Arc::new(DiskScheduler::new(disk_manager, 1)),
+            Arc::new(LRUKReplacer::new(4, 2)),
+            Some(log_manager.clone()),
+        ));
+        (bpm, log_manager)
+    }
+
+    // Logs and applies a one-byte change the way a transaction would.
+    fn update(bpm: &BufferPoolManager, log_manager: &LogManager, txn_id: TxnId, prev_lsn: Lsn, offset: usize, value: u8) -> Lsn {
+        let page = bpm.fetch_page(0).unwrap();
+        let before = page.read()[offset];
+        let lsn = log_manager.append(txn_id, prev_lsn, LogRecordBody::Update {
+            page_id: 0,
+            offset: offset as u16,
+            before: vec![before],
+            after: vec![value],
+        }).unwrap();
+        page.write()[offset] = value;
+        page.set_page_lsn(lsn);
+        bpm.unpin_page(0, true).unwrap();
+        lsn
+    }
+
+    fn read(bpm: &BufferPoolManager, offset: usize) -> u8 {
+        let value = bpm.fetch_page(0).unwrap().read()[offset];
+        bpm.unpin_page(0, false).unwrap();
+        value
+    }
+
+    #[test]
+    pub fn test_recovery_redoes_winners_and_undoes_losers() {
+        let dir = TempDir::new().unwrap();
+        {
+            let (bpm, log_manager) = open(&dir);
+            bpm.new_page().unwrap();
+            bpm.unpin_page(0, false).unwrap();
+            let begin_1 = log_manager.append(1, INVALID_LSN, LogRecordBody::Begin).unwrap();
+            let begin_2 = log_manager.append(2, INVALID_LSN, LogRecordBody::Begin).unwrap();
+            let lsn_1 = update(&bpm, &log_manager, 1, begin_1, OFFSET, 11);
+            let lsn_2 = update(&bpm, &log_manager, 2, begin_2, OFFSET + 1, 22);
+            update(&bpm, &log_manager, 2, lsn_2, OFFSET + 2, 23);
+            log_manager.append(1, lsn_1, LogRecordBody::Commit { commit_ts: 0 }).unwrap();
+            // crash: the buffer pool goes away without flushing page 0
+        }
+
+        let (bpm, log_manager) = open(&dir);
+        assert_eq!(0, read(&bpm, OFFSET));
+        let summary = RecoveryManager::new(bpm.clone(), log_manager.clone()).recover().unwrap();
+        assert_eq!(3, summary.redone_records());
+        assert_eq!(&[2], summary.rolled_back_txns());
+        assert_eq!((11, 0, 0), (read(&bpm, OFFSET), read(&bpm, OFFSET + 1), read(&bpm, OFFSET + 2)));
+
+        // two compensation records and the final abort were logged for txn 2
+        let tail: Vec<_> = log_manager.records().unwrap().into_iter().skip(6).collect();
+        assert_eq!(3, tail.len());
+        assert!(matches!(tail[0].body, LogRecordBody::Compensation { offset, .. } if offset as usize == OFFSET + 2));
+        assert!(matches!(tail[1].body, LogRecordBody::Compensation { undo_next_lsn, .. } if undo_next_lsn == 2));
+        assert_eq!(LogRecordBody::Abort, tail[2].body);
+    }
+
+    #[test]
+    pub fn test_recovery_undoes_stolen_pages_and_is_idempotent() {
+        let dir = TempDir::new().unwrap();
+        {
+            let (bpm, log_manager) = open(&dir);
+            bpm.new_page().unwrap();
+            bpm.unpin_page(0, false).unwrap();
+            let begin = log_manager.append(7, INVALID_LSN, LogRecordBody::Begin).unwrap();
+            update(&bpm, &log_manager, 7, begin, OFFSET, 70);
+            // the uncommitted change reaches disk before the crash
+            bpm.flush_all_pages().unwrap();
+        }
+
+        {
+            let (bpm, log_manager) = open(&dir);
+            assert_eq!(70, read(&bpm, OFFSET));
+            let summary = RecoveryManager::new(bpm.clone(), log_manager).recover().unwrap();
+            assert_eq!(0, summary.redone_records());
+            assert_eq!(&[7], summary.rolled_back_txns());
+            assert_eq!(0, read(&bpm, OFFSET));
+        }
+
+        // txn 7 finished aborting, so a second restart has nothing left to undo
+        let (bpm, log_manager) = open(&dir);
+        let summary = RecoveryManager::new(bpm.clone(), log_manager).recover().unwrap();
+        assert!(summary.rolled_back_txns().is_empty());
+        assert_eq!(0, read(&bpm, OFFSET));
+    }
+
+    #[test]
+    pub fn test_recovery_starts_from_the_checkpoint_after_recycling() {
+        let dir = TempDir::new().unwrap();
+        let begin_2 = {
+            let (bpm, log_manager) = open(&dir);
+            bpm.new_page().unwrap();
+            bpm.unpin_page(0, false).unwrap();
+            let begin_1 = log_manager.append(1, INVALID_LSN, LogRecordBody::Begin).unwrap();
+            let lsn_1 = update(&bpm, &log_manager, 1, begin_1, OFFSET, 11);
+            log_manager.append(1, lsn_1, LogRecordBody::Commit { commit_ts: 1 }).unwrap();
+            bpm.flush_all_pages().unwrap();
+            let begin_2 = log_manager.append(2, INVALID_LSN, LogRecordBody::Begin).unwrap();
+            update(&bpm, &log_manager, 2, begin_2, OFFSET + 1, 22);
+            let begin_3 = log_manager.append(3, INVALID_LSN, LogRecordBody::Begin).unwrap();
+            let lsn_3 = update(&bpm, &log_manager, 3, begin_3, OFFSET + 2, 33);
+            log_manager.append(3, lsn_3, LogRecordBody::Commit { commit_ts: 3 }).unwrap();
+            let (_, redo_lsn) = log_manager.append_checkpoint(3, log_manager.next_lsn(), bpm.dirty_page_table()).unwrap();
+            // txn 2, still running, began before page 0 was dirtied again
+            assert_eq!(begin_2, redo_lsn);
+            assert!(log_manager.recycle(Lsn::MAX).unwrap() > 0);
+            // crash: page 0 holds txn 1's change only
+            begin_2
+        };
+
+        let (bpm, log_manager) = open(&dir);
+        assert_eq!(begin_2, log_manager.records().unwrap()[0].lsn);
+        let summary = RecoveryManager::new(bpm.clone(), log_manager).recover().unwrap();
+        assert_eq!(2, summary.redone_records());
+        assert_eq!(&[2], summary.rolled_back_txns());
+        assert_eq!(3, summary.last_commit_ts());
+        assert_eq!((11, 0, 33), (read(&bpm, OFFSET), read(&bpm, OFFSET + 1), read(&bpm, OFFSET + 2)));
+    }
+
+    #[test]
+    pub fn test_recovery_redoes_mvcc_writes() {
+        let dir = TempDir::new().unwrap();
+        let (first_page_id, rid, updated) = {
+            let (bpm, log_manager) = open(&dir);
+            let txn_manager = TransactionManager::new(Arc::new(LockManager::new()));
+            let heap = TableHeap::new(bpm.clone()).unwrap();
+            let txn = txn_manager.begin(IsolationLevel::SnapshotIsolation);
+            let rid = txn_manager.insert(&txn, &heap, &Tuple::new(b"first".to_vec())).unwrap();
+            let updated = txn_manager.insert(&txn, &heap, &Tuple::new(b"second".to_vec())).unwrap();
+            txn_manager.update(&txn, &heap, updated, &Tuple::new(b"second, updated".to_vec())).unwrap();
+            txn_manager.commit(&txn).unwrap();
+            // the commit flushed the log up to the page's last change, but not the page
+            let page_lsn = bpm.fetch_page(rid.page_id()).unwrap().page_lsn();
+            bpm.unpin_page(rid.page_id(), false).unwrap();
+            assert!(page_lsn != INVALID_LSN && page_lsn <= log_manager.flushed_lsn());
+            (heap.first_page_id(), rid, updated)
+        };
+
+        let (bpm, log_manager) = open(&dir);
+        RecoveryManager::new(bpm.clone(), log_manager).recover().unwrap();
+        let heap = TableHeap::open(bpm, first_page_id).unwrap();
+        assert_eq!(b"first", heap.get_tuple(rid).unwrap().data());
+        assert_eq!(b"second, updated", heap.get_tuple(updated).unwrap().data());
+        assert_eq!(1, heap.tuple_meta(rid).unwrap().ts);
+    }
+}